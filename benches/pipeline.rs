@@ -0,0 +1,72 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Benchmarks of the kvasir `parse` and `document` commands over generated corpora.
+//!
+//! kvasir is a binary crate, so the commands are run as processes.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const KVASIR: &str = env!("CARGO_BIN_EXE_kvasir");
+const SIZES: [usize; 3] = [10, 100, 500];
+
+/// Generate corpora of each size using the hidden `bench` command, returning their
+/// directory.
+fn corpora() -> PathBuf {
+    let dir = std::env::temp_dir().join("kvasir-criterion");
+    let sizes = SIZES.map(|s| s.to_string()).join(",");
+    let status = Command::new(KVASIR)
+        .args(["bench", "--iterations", "1", "--sizes", &sizes, "--dir"])
+        .arg(&dir)
+        .output()
+        .expect("Could not run kvasir");
+    assert!(status.status.success(), "Could not generate corpora");
+    dir
+}
+
+fn run(args: &[&str], sources: &Path) {
+    let output = Command::new(KVASIR)
+        .args(args)
+        .arg("--sources")
+        .arg(sources.join("*.*"))
+        .env("KVASIR_LOG", "off")
+        .output()
+        .expect("Could not run kvasir");
+    assert!(output.status.success());
+}
+
+fn pipeline(c: &mut Criterion) {
+    let dir = corpora();
+    let templates = dir.join("templates").join("*").display().to_string();
+
+    let mut group = c.benchmark_group("pipeline");
+    group.sample_size(10);
+    for size in SIZES {
+        let corpus = dir.join(format!("corpus-{}", size));
+        group.bench_with_input(BenchmarkId::new("parse", size), &corpus, |b, corpus| {
+            b.iter(|| run(&["parse"], corpus))
+        });
+        group.bench_with_input(BenchmarkId::new("document", size), &corpus, |b, corpus| {
+            b.iter(|| run(&["document", "--templates", &templates], corpus))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, pipeline);
+criterion_main!(benches);
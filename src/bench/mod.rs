@@ -0,0 +1,151 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Benchmarks of the glob, parse and render pipeline over generated corpora.
+
+use crate::config::{Config, Source};
+use crate::context::TemplateContext;
+use crate::errors::*;
+use crate::parsers::{self, ParserOptions};
+use crate::report::OutputSchema;
+use crate::templates::engine::{Engine, Templates};
+use crate::templates::limits::RenderLimits;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// The template rendered by benchmarks, listing every key of every parsed file.
+const TEMPLATE: &str = r#"{% for file in files %}
+## {{ file.path | filename }} ({{ file.parser }})
+{% for key, value in file.contents %}* {{ key }}
+{% endfor %}{% endfor %}"#;
+
+/// The time taken by each stage of the pipeline for a corpus, averaged over iterations.
+#[derive(Debug)]
+pub struct Timing {
+    pub files: usize,
+    pub list: Duration,
+    pub parse: Duration,
+    pub render: Duration,
+}
+
+/// Generate a corpus of source files of several formats in a directory.
+pub fn generate_corpus(dir: &Path, files: usize) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    for i in 0..files {
+        let keys = (0..20).map(|k| (format!("key_{}", k), format!("value {} {}", i, k)));
+        let (extension, contents) = match i % 3 {
+            0 => (
+                "json",
+                serde_json::to_string_pretty(&keys.collect::<std::collections::BTreeMap<_, _>>())?,
+            ),
+            1 => (
+                "yaml",
+                keys.map(|(k, v)| format!("{}: {}\n", k, v)).collect(),
+            ),
+            _ => (
+                "properties",
+                keys.map(|(k, v)| format!("{}={}\n", k, v)).collect(),
+            ),
+        };
+        fs::write(dir.join(format!("file-{}.{}", i, extension)), contents)?;
+    }
+    Ok(())
+}
+
+/// Run the pipeline over a generated corpus of each size, returning the time taken by
+/// each stage.
+pub fn run(dir: &Path, sizes: &[usize], iterations: u32) -> Result<Vec<Timing>> {
+    let templates_dir = dir.join("templates");
+    fs::create_dir_all(&templates_dir)?;
+    fs::write(templates_dir.join("bench.tpl"), TEMPLATE)?;
+    let templates = Templates::load(
+        Engine::Tera,
+        &templates_dir.join("*").display().to_string(),
+        &Default::default(),
+    )?;
+    let options = ParserOptions::default();
+    let available_parsers = parsers::parsers_with_options(&options);
+    let config = Config::default();
+
+    sizes
+        .iter()
+        .map(|&size| {
+            let corpus = dir.join(format!("corpus-{}", size));
+            generate_corpus(&corpus, size)?;
+            let glob = corpus.join("*.*").display().to_string();
+
+            let mut timing = Timing {
+                files: size,
+                list: Duration::ZERO,
+                parse: Duration::ZERO,
+                render: Duration::ZERO,
+            };
+            for _ in 0..iterations.max(1) {
+                let start = Instant::now();
+                let (files, _) = crate::list_files(vec![Source::from(glob.as_str())]);
+                timing.list += start.elapsed();
+                debug_assert_eq!(files.len(), size);
+
+                let start = Instant::now();
+                let (successes, failures) = crate::parse_files(
+                    vec![Source::from(glob.as_str())],
+                    &available_parsers,
+                    &options,
+                    false,
+                    None,
+                )
+                .unwrap_or_default();
+                timing.parse += start.elapsed();
+
+                let start = Instant::now();
+                templates.render(
+                    "bench.tpl",
+                    TemplateContext::new(successes, &failures, &config, OutputSchema::DEFAULT),
+                    &RenderLimits::default(),
+                )?;
+                timing.render += start.elapsed();
+            }
+            timing.list /= iterations.max(1);
+            timing.parse /= iterations.max(1);
+            timing.render /= iterations.max(1);
+            Ok(timing)
+        })
+        .collect()
+}
+
+/// Return the directory in which benchmark corpora are generated by default.
+pub fn default_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("kvasir-bench-{}", std::process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::bench::{generate_corpus, run};
+
+    #[test]
+    fn bench() {
+        let dir = std::env::temp_dir().join(format!("kvasir-bench-test-{}", std::process::id()));
+        generate_corpus(&dir.join("corpus"), 6).unwrap();
+        assert_eq!(std::fs::read_dir(dir.join("corpus")).unwrap().count(), 6);
+
+        let timings = run(&dir, &[3], 1).unwrap();
+        assert_eq!(timings.len(), 1);
+        assert_eq!(timings[0].files, 3);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
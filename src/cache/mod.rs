@@ -0,0 +1,222 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! A cache of the results of parsing source files, so that files whose contents and parser
+//! options are unchanged are not parsed again, such as by the daemon between requests.
+//!
+//! A cache can also be recorded in a file, one JSON object per line, to which results are
+//! appended as they are added, so that they survive the process being killed part way
+//! through, as when a `kvasir document --resume` run is interrupted.
+
+use crate::errors::*;
+use crate::parsers::{ParseFailure, ParseSuccess};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// Identifies the results of parsing a source file: its path, the hash of its contents and
+/// the sorted parser options with which it was parsed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+struct Key {
+    path: PathBuf,
+    sha256: String,
+    options: Vec<String>,
+}
+
+/// The results of parsing a source file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Cached {
+    successes: Vec<ParseSuccess>,
+    /// The parsers which failed, with their error messages.
+    failures: Vec<(String, String)>,
+}
+
+/// A line of the file in which a cache is recorded, which holds a key and its results, or
+/// references to them when written.
+#[derive(Serialize, Deserialize)]
+struct Recorded<K, C> {
+    /// The version of kvasir, and any other settings which affect the results, with which
+    /// the file was parsed.
+    version: String,
+    #[serde(flatten)]
+    key: K,
+    #[serde(flatten)]
+    cached: C,
+}
+
+/// The file in which a cache is recorded.
+#[derive(Debug)]
+struct Journal {
+    file: File,
+    version: String,
+}
+
+impl Journal {
+    /// Append the results of a parsed file, logging rather than failing if they cannot be
+    /// written.
+    fn record(&mut self, key: &Key, cached: &Cached) {
+        let recorded = Recorded {
+            version: self.version.to_owned(),
+            key,
+            cached,
+        };
+        let result = serde_json::to_string(&recorded)
+            .map_err(Error::from)
+            .and_then(|line| Ok(writeln!(self.file, "{}", line)?));
+        if let Err(e) = result {
+            warn!("Could not record parsed file: {}", e);
+        }
+    }
+}
+
+/// The results of parsing source files.
+#[derive(Debug, Default)]
+pub struct ParseCache {
+    files: HashMap<Key, Cached>,
+    journal: Option<Journal>,
+}
+
+impl ParseCache {
+    /// Open a cache recorded in a file, which results added to the cache are appended to.
+    /// Results recorded with another version, such as by another version of kvasir, are
+    /// ignored, as is a final line left incomplete by an interrupted process.
+    pub fn open(path: &Path, version: &str) -> Result<ParseCache> {
+        let files = match File::open(path) {
+            Ok(file) => BufReader::new(file)
+                .lines()
+                .map_while(|line| line.ok())
+                .filter_map(|line| serde_json::from_str::<Recorded<Key, Cached>>(&line).ok())
+                .filter(|r| r.version == version)
+                .map(|r| (r.key, r.cached))
+                .collect(),
+            Err(_) => HashMap::new(),
+        };
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .chain_err(|| format!("Could not open cache file {}", path.display()))?;
+        Ok(ParseCache {
+            files,
+            journal: Some(Journal {
+                file,
+                version: version.to_string(),
+            }),
+        })
+    }
+
+    /// Return the results of a source file parsed with the same contents and parser options.
+    /// The errors of parsers which failed are restored as messages.
+    pub fn get(
+        &self,
+        path: &Path,
+        sha256: &str,
+        options: &[String],
+    ) -> Option<(Vec<ParseSuccess>, Vec<ParseFailure>)> {
+        let key = Key {
+            path: path.to_owned(),
+            sha256: sha256.to_string(),
+            options: options.to_vec(),
+        };
+        let cached = self.files.get(&key)?;
+        let failures = cached
+            .failures
+            .iter()
+            .map(|(parser, error)| ParseFailure {
+                path: path.to_owned(),
+                parser: parser.to_owned(),
+                error: error.as_str().into(),
+            })
+            .collect();
+        Some((cached.successes.to_owned(), failures))
+    }
+
+    /// Record the results of parsing a source file with the given contents and parser options,
+    /// appending them to the file in which the cache is recorded, if any.
+    pub fn insert(
+        &mut self,
+        path: &Path,
+        sha256: &str,
+        options: &[String],
+        successes: &[ParseSuccess],
+        failures: &[ParseFailure],
+    ) {
+        let key = Key {
+            path: path.to_owned(),
+            sha256: sha256.to_string(),
+            options: options.to_vec(),
+        };
+        let cached = Cached {
+            successes: successes.to_vec(),
+            failures: failures
+                .iter()
+                .map(|f| (f.parser.to_owned(), f.error.to_string()))
+                .collect(),
+        };
+        if let Some(journal) = &mut self.journal {
+            journal.record(&key, &cached);
+        }
+        self.files.insert(key, cached);
+    }
+
+    /// Return the number of source files whose results are cached.
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Return whether no results are cached.
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::cache::ParseCache;
+    use crate::parsers::{ParseFailure, ParseSuccess};
+    use std::path::Path;
+
+    #[test]
+    fn cache() {
+        let path = Path::new("orders.yaml");
+        let success: ParseSuccess = serde_json::from_value(serde_json::json!({
+            "path": path,
+            "parser": "yaml",
+            "contents": {"name": "orders"},
+        }))
+        .unwrap();
+        let failure = ParseFailure {
+            path: path.to_owned(),
+            parser: "json".to_string(),
+            error: "expected value".into(),
+        };
+        let options = vec!["*.strict=true".to_string()];
+        let mut cache = ParseCache::default();
+        cache.insert(path, "abc", &options, &[success], &[failure]);
+
+        let (successes, failures) = cache.get(path, "abc", &options).unwrap();
+        assert_eq!(successes[0].contents["name"], "orders");
+        assert_eq!(failures[0].parser, "json");
+        assert_eq!(failures[0].error.to_string(), "expected value");
+        // Results are only reused with the same contents and parser options
+        assert!(cache.get(path, "def", &options).is_none());
+        assert!(cache.get(path, "abc", &[]).is_none());
+    }
+}
@@ -0,0 +1,51 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Graceful cancellation on Ctrl-C or `SIGTERM`.
+//!
+//! The first signal requests cancellation, after which no further files are parsed or
+//! written, whilst a second signal exits immediately.
+
+use crate::errors::*;
+use crate::exit;
+use log::warn;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// Install the signal handler.
+pub fn install() -> Result<()> {
+    ctrlc::set_handler(|| {
+        if CANCELLED.swap(true, Ordering::SeqCst) {
+            std::process::exit(exit::CANCELLED);
+        }
+        warn!("Cancelling after the current file. Interrupt again to exit immediately.");
+    })
+    .map_err(|e| format!("Could not install signal handler: {}", e).into())
+}
+
+/// Return whether cancellation has been requested.
+pub fn cancelled() -> bool {
+    CANCELLED.load(Ordering::SeqCst)
+}
+
+/// Exit with a summary of the work completed if cancellation has been requested.
+pub fn exit_if_cancelled(summary: &str) {
+    if cancelled() {
+        warn!("Cancelled: {}", summary);
+        std::process::exit(exit::CANCELLED);
+    }
+}
@@ -0,0 +1,125 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Canonical JSON output, written by `kvasir parse` and `kvasir context` with `--canonical`,
+//! so that semantically identical files produce identical output.
+//!
+//! Object keys are sorted, numbers with no fractional part are written as integers, e.g.
+//! `8080.0` as `8080`, and the arrays at keys declared as `unordered` in the `[canonical]`
+//! section of the configuration file are sorted. Keys are dotted paths within the parsed
+//! contents of each file, with `[]` standing for any element of an array, as in
+//! [`Stats`](crate::stats::Stats), e.g. `spec.template.spec.containers[].env`. Other arrays
+//! keep their order, which is significant in most files.
+
+use serde_json::{Number, Value};
+
+/// The largest integer which every `f64` below it in magnitude represents exactly.
+const MAX_EXACT: f64 = 9_007_199_254_740_992.0;
+
+/// Return a number with no fractional part as an integer.
+fn normalise(number: &Number) -> Option<Number> {
+    let float = number.as_f64().filter(|_| number.is_f64())?;
+    if float.fract() != 0.0 || float.abs() > MAX_EXACT {
+        return None;
+    }
+    match float < 0.0 {
+        true => Some(Number::from(float as i64)),
+        false => Some(Number::from(float as u64)),
+    }
+}
+
+/// Put a value in canonical form, sorting the arrays at the given unordered keys.
+pub fn canonicalize(value: &mut Value, unordered: &[String]) {
+    canonicalize_key(value, "", unordered)
+}
+
+/// Put a value found at a dotted key in canonical form.
+fn canonicalize_key(value: &mut Value, key: &str, unordered: &[String]) {
+    match value {
+        Value::Object(map) => {
+            for (name, child) in map.iter_mut() {
+                let path = match key.is_empty() {
+                    true => name.to_owned(),
+                    false => format!("{}.{}", key, name),
+                };
+                canonicalize_key(child, &path, unordered);
+            }
+            let mut entries: Vec<(String, Value)> = std::mem::take(map).into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            *map = entries.into_iter().collect();
+        }
+        Value::Array(items) => {
+            let path = format!("{}[]", key);
+            items
+                .iter_mut()
+                .for_each(|i| canonicalize_key(i, &path, unordered));
+            if unordered.iter().any(|u| u == key) {
+                items.sort_by_cached_key(|i| i.to_string());
+            }
+        }
+        Value::Number(number) => {
+            if let Some(normalised) = normalise(number) {
+                *number = normalised;
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::canonical::canonicalize;
+    use serde_json::json;
+
+    #[test]
+    fn canonical_values() {
+        let mut first = json!({
+            "spec": {
+                "replicas": 3.0,
+                "containers": [
+                    {"name": "app", "env": [{"name": "B", "value": "2"}, {"name": "A"}]},
+                    {"name": "sidecar", "ports": [8080, 80]}
+                ]
+            },
+            "weight": 0.5,
+            "offset": -2.0
+        });
+        let mut second = json!({
+            "offset": -2,
+            "weight": 0.5,
+            "spec": {
+                "containers": [
+                    {"env": [{"name": "A"}, {"value": "2", "name": "B"}], "name": "app"},
+                    {"ports": [8080, 80], "name": "sidecar"}
+                ],
+                "replicas": 3
+            }
+        });
+        let unordered = ["spec.containers[].env".to_string()];
+        canonicalize(&mut first, &unordered);
+        canonicalize(&mut second, &unordered);
+
+        assert_eq!(first.to_string(), second.to_string());
+        assert_eq!(
+            first.to_string(),
+            concat!(
+                r#"{"offset":-2,"spec":{"containers":[{"env":[{"name":"A"},{"name":"B","value":"2"}],"name":"app"},"#,
+                r#"{"name":"sidecar","ports":[8080,80]}],"replicas":3},"weight":0.5}"#
+            )
+        );
+    }
+}
@@ -0,0 +1,350 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! A description of what this version of kvasir can do, for `kvasir capabilities`.
+//!
+//! The description lists the file and directory parsers with the extensions, marker files
+//! and options they accept, the custom template filters and functions with their named
+//! arguments, and the commands and arguments of the command line, so that tools can check
+//! what a given kvasir supports before running it.
+
+use crate::parsers::{self, ParserOptions};
+use crate::templates::engine::Engine;
+use crate::templates::filters;
+use crate::templates::functions::{self, FunctionOptions};
+use clap::ValueEnum;
+use serde::Serialize;
+
+/// The version of the capabilities JSON schema.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The format in which capabilities are written.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Format {
+    /// A list of parsers, filters and functions for reading.
+    Text,
+    /// A JSON document, including the command line schema.
+    Json,
+}
+
+/// What this version of kvasir can do.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Capabilities {
+    pub schema_version: u32,
+    pub version: &'static str,
+    pub parsers: Vec<Parser>,
+    pub directory_parsers: Vec<DirectoryParser>,
+    pub engines: Vec<String>,
+    pub filters: Vec<Helper>,
+    pub functions: Vec<Helper>,
+    pub cli: Command,
+}
+
+/// A file parser and what it accepts.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Parser {
+    pub name: &'static str,
+    pub extensions: Vec<&'static str>,
+    pub options: Vec<&'static str>,
+    pub binary: bool,
+    pub descriptions: bool,
+    pub documents: bool,
+}
+
+/// A directory parser and the files marking the directories it parses.
+#[derive(Debug, Serialize)]
+pub struct DirectoryParser {
+    pub name: &'static str,
+    pub markers: Vec<&'static str>,
+}
+
+/// A custom template filter or function and its named arguments.
+#[derive(Debug, Serialize)]
+pub struct Helper {
+    pub name: &'static str,
+    pub arguments: Vec<&'static str>,
+    pub signature: String,
+}
+
+/// A command of the command line, with its arguments and subcommands.
+#[derive(Debug, Serialize)]
+pub struct Command {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub about: Option<String>,
+    pub arguments: Vec<Argument>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub subcommands: Vec<Command>,
+}
+
+/// An argument of a command.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Argument {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub long: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub short: Option<char>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub help: Option<String>,
+    pub required: bool,
+    pub takes_value: bool,
+    pub global: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub possible_values: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub default_values: Vec<String>,
+}
+
+impl Capabilities {
+    /// Describe the parsers, template helpers and the provided command line.
+    pub fn new(cli: &clap::Command) -> Capabilities {
+        let options = ParserOptions::default();
+        Capabilities {
+            schema_version: SCHEMA_VERSION,
+            version: env!("CARGO_PKG_VERSION"),
+            parsers: parsers::parsers()
+                .iter()
+                .map(|p| {
+                    let capabilities = p.capabilities();
+                    Parser {
+                        name: p.name(),
+                        extensions: capabilities.extensions.to_vec(),
+                        options: capabilities
+                            .descriptions
+                            .then_some("comments")
+                            .into_iter()
+                            .chain(capabilities.options.iter().copied())
+                            .collect(),
+                        binary: capabilities.binary,
+                        descriptions: capabilities.descriptions,
+                        documents: capabilities.documents,
+                    }
+                })
+                .collect(),
+            directory_parsers: parsers::directory_parsers(&options)
+                .iter()
+                .map(|p| DirectoryParser {
+                    name: p.name(),
+                    markers: p.markers().to_vec(),
+                })
+                .collect(),
+            engines: [Engine::Tera, Engine::Handlebars]
+                .iter()
+                .map(|e| e.to_string())
+                .collect(),
+            filters: filters(),
+            functions: functions(),
+            cli: command(cli),
+        }
+    }
+
+    /// Return the parsers, filters and functions as lines of text.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("kvasir {}", self.version),
+            "".into(),
+            "Parsers:".into(),
+        ];
+        lines.extend(self.parsers.iter().map(|p| {
+            let mut line = format!("  {}", p.name);
+            if !p.extensions.is_empty() {
+                line += &format!(" (.{})", p.extensions.join(", ."));
+            }
+            if !p.options.is_empty() {
+                line += &format!(" options: {}", p.options.join(", "));
+            }
+            line
+        }));
+        lines.extend(["".into(), "Directory parsers:".into()]);
+        lines.extend(
+            self.directory_parsers
+                .iter()
+                .map(|p| format!("  {} ({})", p.name, p.markers.join(", "))),
+        );
+        lines.extend(["".into(), "Filters:".into()]);
+        lines.extend(self.filters.iter().map(|f| format!("  {}", f.signature)));
+        lines.extend(["".into(), "Functions:".into()]);
+        lines.extend(self.functions.iter().map(|f| format!("  {}", f.signature)));
+        lines
+    }
+}
+
+/// Return the custom template filters.
+pub fn filters() -> Vec<Helper> {
+    helpers(
+        filters::filters().into_iter().map(|(name, _)| name),
+        filters::ARGUMENTS,
+        true,
+    )
+}
+
+/// Return the custom template functions.
+pub fn functions() -> Vec<Helper> {
+    helpers(
+        functions::functions(&FunctionOptions::default())
+            .into_iter()
+            .map(|(name, _)| name),
+        functions::ARGUMENTS,
+        false,
+    )
+}
+
+/// Describe the registered helpers with their arguments, in order of registration. Filters
+/// without arguments are written without parentheses, as they are used in templates.
+fn helpers(
+    names: impl Iterator<Item = &'static str>,
+    arguments: &[(&'static str, &'static [&'static str])],
+    filter: bool,
+) -> Vec<Helper> {
+    names
+        .map(|name| {
+            let arguments = arguments
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, a)| a.to_vec())
+                .unwrap_or_default();
+            let signature = match arguments.is_empty() {
+                true if filter => name.to_string(),
+                _ => format!(
+                    "{}({})",
+                    name,
+                    arguments
+                        .iter()
+                        .map(|a| format!("{}=...", a))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            };
+            Helper {
+                name,
+                arguments,
+                signature,
+            }
+        })
+        .collect()
+}
+
+/// Describe a command and its visible arguments and subcommands.
+fn command(command: &clap::Command) -> Command {
+    Command {
+        name: command.get_name().to_string(),
+        about: command.get_about().map(|a| a.to_string()),
+        arguments: command
+            .get_arguments()
+            .filter(|a| !a.is_hide_set())
+            .map(|a| {
+                let takes_value = a.get_action().takes_values();
+                Argument {
+                    name: a.get_id().to_string(),
+                    long: a.get_long().map(String::from),
+                    short: a.get_short(),
+                    env: a.get_env().map(|e| e.to_string_lossy().to_string()),
+                    help: a.get_help().map(|h| h.to_string()),
+                    required: a.is_required_set(),
+                    takes_value,
+                    global: a.is_global_set(),
+                    possible_values: a
+                        .get_possible_values()
+                        .iter()
+                        .filter(|v| takes_value && !v.is_hide_set())
+                        .map(|v| v.get_name().to_string())
+                        .collect(),
+                    default_values: a
+                        .get_default_values()
+                        .iter()
+                        .map(|v| v.to_string_lossy().to_string())
+                        .collect(),
+                }
+            })
+            .collect(),
+        subcommands: command
+            .get_subcommands()
+            .filter(|c| !c.is_hide_set())
+            .map(self::command)
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::capabilities::Capabilities;
+    use crate::templates::{filters, functions};
+    use clap::CommandFactory;
+
+    #[test]
+    fn capabilities() {
+        let capabilities = Capabilities::new(&crate::CLOptions::command());
+        let yaml = capabilities
+            .parsers
+            .iter()
+            .find(|p| p.name == "yaml")
+            .unwrap();
+        assert!(yaml.extensions.contains(&"yaml") && yaml.options.contains(&"comments"));
+        assert!(capabilities
+            .directory_parsers
+            .iter()
+            .any(|p| p.name == "helm-chart" && p.markers == ["Chart.yaml"]));
+        assert_eq!(capabilities.engines, ["tera", "handlebars"]);
+
+        // Every filter and function is described with its arguments
+        assert_eq!(capabilities.filters.len(), filters::ARGUMENTS.len());
+        assert_eq!(capabilities.functions.len(), functions::ARGUMENTS.len());
+        let jsonpath = &capabilities.filters[0];
+        assert_eq!(
+            (jsonpath.name, jsonpath.signature.as_str()),
+            ("jsonpath", "jsonpath(path=...)")
+        );
+        assert!(capabilities
+            .filters
+            .iter()
+            .chain(&capabilities.functions)
+            .all(|h| filters::ARGUMENTS
+                .iter()
+                .chain(functions::ARGUMENTS)
+                .any(|(name, _)| *name == h.name)));
+
+        let cli = serde_json::to_value(&capabilities.cli).unwrap();
+        let document = cli["subcommands"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|c| c["name"] == "document")
+            .unwrap();
+        let engine = document["arguments"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|a| a["long"] == "engine")
+            .unwrap();
+        assert_eq!(engine["env"], "KVASIR_ENGINE");
+        assert_eq!(engine["defaultValues"], serde_json::json!(["tera"]));
+        assert!(cli["subcommands"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|c| c["name"] != "bench"));
+        assert!(capabilities
+            .lines()
+            .contains(&"  glob(glob=...)".to_string()));
+    }
+}
@@ -0,0 +1,177 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Checking of the external `http` and `https` URLs linked from generated pages.
+//!
+//! Each URL is requested once, however many pages link to it, with a `HEAD` request,
+//! falling back to `GET` for servers which reject `HEAD` requests.
+
+use crate::check::{links, sources, Markup, Problem};
+use crate::output::OutputFile;
+use crate::paths;
+use log::debug;
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+/// The default timeout of each request.
+pub const DEFAULT_TIMEOUT: f64 = 10.0;
+
+/// A link to an external URL: the path of the page relative to the output directory, the
+/// line and the page itself.
+type Occurrence<'a> = (String, usize, &'a OutputFile);
+
+/// Return the `http` and `https` URLs linked from pages, with where they occur.
+fn urls<'a>(files: &'a [OutputFile], output_dir: &Path) -> BTreeMap<String, Vec<Occurrence<'a>>> {
+    let mut urls: BTreeMap<String, Vec<Occurrence>> = BTreeMap::new();
+    for file in files {
+        let Some(markup) = Markup::of(&file.path) else {
+            continue;
+        };
+        let page = paths::portable(
+            &file
+                .path
+                .strip_prefix(output_dir)
+                .unwrap_or(&file.path)
+                .to_string_lossy(),
+        );
+        for link in links(&file.content, markup) {
+            let lower = link.target.to_lowercase();
+            if lower.starts_with("http://") || lower.starts_with("https://") {
+                urls.entry(link.target)
+                    .or_default()
+                    .push((page.to_owned(), link.line, file));
+            }
+        }
+    }
+    urls
+}
+
+/// Request a URL, returning a description of the failure if it does not respond
+/// successfully.
+fn request(url: &str, timeout: Duration) -> Option<String> {
+    debug!("Checking {}", url);
+    let result = match ureq::head(url).timeout(timeout).call() {
+        Err(ureq::Error::Status(_, _)) => ureq::get(url).timeout(timeout).call(),
+        result => result,
+    };
+    match result {
+        Ok(_) => None,
+        Err(ureq::Error::Status(status, response)) => {
+            Some(format!("{} {}", status, response.status_text()))
+        }
+        Err(ureq::Error::Transport(transport)) => Some(match transport.message() {
+            Some(message) => format!("{}: {}", transport.kind(), message),
+            None => transport.kind().to_string(),
+        }),
+    }
+}
+
+/// Check the external URLs linked from pages concurrently, returning a `dead-url` problem
+/// for each link to a URL which does not respond successfully within the timeout.
+pub fn check_urls(
+    files: &[OutputFile],
+    output_dir: &Path,
+    template: &str,
+    timeout: Duration,
+) -> Vec<Problem> {
+    let urls = urls(files, output_dir);
+    let failures: Vec<(&String, String)> = urls
+        .keys()
+        .collect::<Vec<_>>()
+        .par_iter()
+        .filter_map(|url| request(url, timeout).map(|failure| (*url, failure)))
+        .collect();
+    failures
+        .into_iter()
+        .flat_map(|(url, failure)| {
+            urls[url].iter().map(move |(page, line, file)| Problem {
+                page: page.to_owned(),
+                line: *line,
+                kind: "dead-url",
+                message: format!("Link to {} failed: {}", url, failure),
+                link: Some(url.to_owned()),
+                sources: sources(file),
+                template: template.to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::check::external::{check_urls, urls};
+    use crate::output::OutputFile;
+    use crate::testing::{Response, Server};
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    fn page(path: &str, content: &str) -> OutputFile {
+        OutputFile {
+            path: PathBuf::from("/docs").join(path),
+            content: content.to_string(),
+            attributes: [("source".to_string(), "api.yaml".to_string())].into(),
+            options: Default::default(),
+        }
+    }
+
+    #[test]
+    fn collect_urls() {
+        let pages = vec![
+            page(
+                "a.md",
+                "[Docs](https://docs.example.com) [Local](b.md)\n<a href=\"HTTP://example.com\">x</a>",
+            ),
+            page("b.md", "[Docs](https://docs.example.com) [Mail](mailto:a@b.c)"),
+        ];
+        let urls = urls(&pages, Path::new("/docs"));
+        assert_eq!(
+            urls.keys().collect::<Vec<_>>(),
+            ["HTTP://example.com", "https://docs.example.com"]
+        );
+        assert_eq!(
+            urls["https://docs.example.com"]
+                .iter()
+                .map(|(page, line, _)| (page.as_str(), *line))
+                .collect::<Vec<_>>(),
+            [("a.md", 1), ("b.md", 1)]
+        );
+    }
+
+    #[test]
+    fn dead_urls() {
+        let server = Server::new(|_| Response::new("404 Not Found"));
+
+        let pages = vec![page("a.md", &format!("\n[Gone]({}/gone)", server.url))];
+        let problems = check_urls(
+            &pages,
+            Path::new("/docs"),
+            "docs.tpl",
+            Duration::from_secs(5),
+        );
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].page, "a.md");
+        assert_eq!(problems[0].line, 2);
+        assert_eq!(problems[0].kind, "dead-url");
+        assert_eq!(
+            problems[0].message,
+            format!("Link to {}/gone failed: 404 Not Found", server.url)
+        );
+        assert_eq!(problems[0].sources, ["api.yaml"]);
+    }
+}
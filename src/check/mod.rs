@@ -0,0 +1,676 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Quality checks of generated Markdown and HTML pages.
+//!
+//! Pages are checked for:
+//!
+//! * `broken-link`: relative links and images whose target is neither a generated page nor
+//!   an existing file.
+//! * `missing-anchor`: links to a `#fragment` which is not a heading or `id` of the target
+//!   page.
+//! * `duplicate-anchor`: headings or `id` attributes with the same anchor as an earlier
+//!   one on the page, which links cannot distinguish.
+//! * `missing-alt`: images without alternative text.
+//!
+//! Links with a scheme, such as `https:` or `mailto:`, are not checked here, but URLs may be
+//! checked with the `external` module.
+
+pub mod external;
+
+use crate::errors::*;
+use crate::manifest::Manifest;
+use crate::output::OutputFile;
+use crate::paths;
+use log::{error, warn};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The markup of a page, which determines how its links and anchors are found.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Markup {
+    Markdown,
+    Html,
+}
+
+impl Markup {
+    /// Return the markup of a file from its extension, if it is checked.
+    pub fn of(path: &Path) -> Option<Markup> {
+        match path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .as_deref()
+        {
+            Some("md") | Some("markdown") => Some(Markup::Markdown),
+            Some("html") | Some("htm") => Some(Markup::Html),
+            _ => None,
+        }
+    }
+}
+
+/// A link or image on a page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    /// The line of the page on which the link appears, from 1.
+    pub line: usize,
+    /// The target of the link, as written.
+    pub target: String,
+    /// Whether the link is an image.
+    pub image: bool,
+    /// The alternative text of an image, if any.
+    pub alt: Option<String>,
+}
+
+/// A problem found on a generated page.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Problem {
+    /// The path of the page, relative to the output directory.
+    pub page: String,
+    pub line: usize,
+    pub kind: &'static str,
+    pub message: String,
+    /// The target of the link with the problem, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+    /// The source files which produced the page, from its `source` header attribute.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<String>,
+    /// The root template which produced the page.
+    pub template: String,
+}
+
+/// Return the source files which produced a page, from its `source` header attribute.
+fn sources(file: &OutputFile) -> Vec<String> {
+    file.attributes
+        .get("source")
+        .into_iter()
+        .flat_map(|s| s.split(','))
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Return the lines of a page which are not within Markdown code fences, with their
+/// numbers and with inline code spans removed.
+pub fn prose_lines(content: &str, markup: Markup) -> Vec<(usize, String)> {
+    let mut fenced = false;
+    let mut lines = vec![];
+    for (i, line) in content.lines().enumerate() {
+        if markup == Markup::Markdown {
+            let trimmed = line.trim_start();
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                fenced = !fenced;
+                continue;
+            }
+            if fenced {
+                continue;
+            }
+            let text: String = line
+                .split('`')
+                .enumerate()
+                .filter(|(n, _)| n % 2 == 0)
+                .map(|(_, part)| part)
+                .collect::<Vec<_>>()
+                .join("");
+            lines.push((i + 1, text));
+        } else {
+            lines.push((i + 1, line.to_string()));
+        }
+    }
+    lines
+}
+
+/// Return the links and images on a page, including HTML `<a>` and `<img>` tags within
+/// Markdown.
+pub fn links(content: &str, markup: Markup) -> Vec<Link> {
+    let mut links = vec![];
+    for (line, text) in prose_lines(content, markup) {
+        if markup == Markup::Markdown {
+            links.extend(
+                markdown_links(&text)
+                    .into_iter()
+                    .map(|(image, label, target)| Link {
+                        line,
+                        target,
+                        image,
+                        alt: image.then_some(label),
+                    }),
+            );
+        }
+        for (tag, attributes) in tags(&text) {
+            match tag.as_str() {
+                "a" => {
+                    if let Some(href) = attributes.get("href") {
+                        links.push(Link {
+                            line,
+                            target: href.to_owned(),
+                            image: false,
+                            alt: None,
+                        })
+                    }
+                }
+                "img" => links.push(Link {
+                    line,
+                    target: attributes.get("src").cloned().unwrap_or_default(),
+                    image: true,
+                    alt: attributes.get("alt").cloned(),
+                }),
+                _ => {}
+            }
+        }
+    }
+    links
+}
+
+/// Return the `[text](target)` and `![alt](target)` links within a line of Markdown.
+fn markdown_links(line: &str) -> Vec<(bool, String, String)> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut links = vec![];
+    for start in (0..chars.len()).filter(|&i| chars[i] == '[') {
+        let Some(close) = matching(&chars, start, '[', ']') else {
+            continue;
+        };
+        if chars.get(close + 1) != Some(&'(') {
+            continue;
+        }
+        let Some(end) = matching(&chars, close + 1, '(', ')') else {
+            continue;
+        };
+        let inner: String = chars[close + 2..end].iter().collect();
+        let target = inner
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .trim_start_matches('<')
+            .trim_end_matches('>')
+            .to_string();
+        let image = start > 0 && chars[start - 1] == '!';
+        links.push((image, chars[start + 1..close].iter().collect(), target));
+    }
+    links
+}
+
+/// Return the index of the bracket closing the one at `start`, allowing nesting.
+fn matching(chars: &[char], start: usize, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in chars.iter().enumerate().skip(start) {
+        if *c == open {
+            depth += 1;
+        } else if *c == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+/// Return the lower-cased name and attributes of each HTML start tag within a line.
+fn tags(line: &str) -> Vec<(String, BTreeMap<String, String>)> {
+    let mut tags = vec![];
+    let mut rest = line;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let end = rest.find('>').unwrap_or(rest.len());
+        let tag = &rest[..end];
+        rest = &rest[end..];
+        let name: String = tag
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_lowercase();
+        if name.is_empty() {
+            continue;
+        }
+        tags.push((name.to_owned(), attributes(&tag[name.len()..])));
+    }
+    tags
+}
+
+/// Parse the `name="value"`, `name='value'` and `name=value` attributes of an HTML tag.
+fn attributes(text: &str) -> BTreeMap<String, String> {
+    let mut attributes = BTreeMap::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        let name_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace() || c == '/')
+            .unwrap_or(rest.len());
+        let name = rest[..name_end].to_lowercase();
+        rest = rest[name_end..].trim_start();
+        let value = if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (value, remainder) = match after.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let body = &after[1..];
+                    let end = body.find(quote).unwrap_or(body.len());
+                    (&body[..end], body.get(end + 1..).unwrap_or_default())
+                }
+                _ => {
+                    let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                    (&after[..end], &after[end..])
+                }
+            };
+            rest = remainder;
+            value.to_string()
+        } else {
+            if name.is_empty() {
+                rest = rest.get(1..).unwrap_or_default();
+            }
+            String::new()
+        };
+        if !name.is_empty() {
+            attributes.insert(name, value);
+        }
+        rest = rest.trim_start();
+    }
+    attributes
+}
+
+/// Return the anchor of a Markdown heading, as generated by GitHub: lower-cased, with
+/// spaces replaced by hyphens and punctuation removed.
+pub fn slug(heading: &str) -> String {
+    heading
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Return the anchors of a page with the lines on which they are defined, in order and
+/// including duplicates: the slugs of Markdown headings and the `id` attributes of HTML
+/// elements, as well as `name` attributes of `<a>` tags.
+pub fn anchors(content: &str, markup: Markup) -> Vec<(usize, String)> {
+    let mut anchors = vec![];
+    for (line, text) in prose_lines(content, markup) {
+        if markup == Markup::Markdown {
+            let trimmed = text.trim_start();
+            let level = trimmed.chars().take_while(|c| *c == '#').count();
+            if (1..=6).contains(&level) && trimmed[level..].starts_with(' ') {
+                anchors.push((line, slug(trimmed[level..].trim_end_matches('#'))));
+            }
+        }
+        for (tag, attributes) in tags(&text) {
+            if let Some(id) = attributes.get("id") {
+                anchors.push((line, id.to_owned()));
+            } else if let (true, Some(name)) = (tag == "a", attributes.get("name")) {
+                anchors.push((line, name.to_owned()));
+            }
+        }
+    }
+    anchors
+}
+
+/// Return the targets which links may reference on a page: each anchor, with duplicates
+/// numbered from 1 as on GitHub, e.g. `usage`, `usage-1`.
+pub fn link_targets(content: &str, markup: Markup) -> HashSet<String> {
+    let mut targets = HashSet::new();
+    for (_, anchor) in anchors(content, markup) {
+        let mut candidate = anchor.to_owned();
+        let mut n = 0;
+        while !targets.insert(candidate.to_owned()) {
+            n += 1;
+            candidate = format!("{}-{}", anchor, n);
+        }
+    }
+    targets
+}
+
+/// Resolve a relative link on the page at a path relative to the output directory,
+/// returning the path of its target relative to the output directory, empty for the page
+/// itself, and its fragment, if any. `None` is returned for links with a scheme.
+pub fn resolve(page: &str, target: &str) -> Option<(String, Option<String>)> {
+    if target.starts_with("//")
+        || target
+            .split_once(':')
+            .is_some_and(|(scheme, _)| !scheme.is_empty() && !scheme.contains(['/', '#', '?']))
+    {
+        return None;
+    }
+    let (path, fragment) = match target.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment.to_string())),
+        None => (target, None),
+    };
+    let path = path.split('?').next().unwrap_or_default();
+    let path = percent_decode(path);
+    let resolved = if path.is_empty() {
+        String::new()
+    } else if let Some(absolute) = path.strip_prefix('/') {
+        paths::portable(absolute)
+    } else {
+        paths::join(&paths::join(page, ".."), &path)
+    };
+    Some((resolved, fragment))
+}
+
+/// Decode percent-encoded bytes in a link, such as `%20`.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], text.get(i + 1..i + 3)) {
+            (b'%', Some(hex)) if u8::from_str_radix(hex, 16).is_ok() => {
+                decoded.push(u8::from_str_radix(hex, 16).unwrap_or_default());
+                i += 3;
+            }
+            (b, _) => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Check generated pages, with paths under the output directory, for broken links,
+/// missing and duplicate anchors and images without alternative text.
+///
+/// Links to files which are not generated pages are checked against the output directory,
+/// so pages should be checked once they have been written.
+pub fn check_pages(files: &[OutputFile], output_dir: &Path, template: &str) -> Vec<Problem> {
+    check(files, output_dir, template, true)
+}
+
+/// Check generated pages, with paths under the output directory, for broken relative links
+/// and links to missing anchors only.
+pub fn check_links(files: &[OutputFile], output_dir: &Path, template: &str) -> Vec<Problem> {
+    check(files, output_dir, template, false)
+}
+
+/// Check generated pages for broken links and, if `quality` is set, duplicate anchors and
+/// images without alternative text.
+fn check(files: &[OutputFile], output_dir: &Path, template: &str, quality: bool) -> Vec<Problem> {
+    let relative = |path: &Path| {
+        paths::portable(
+            &path
+                .strip_prefix(output_dir)
+                .unwrap_or(path)
+                .to_string_lossy(),
+        )
+    };
+    let pages: BTreeMap<String, (&OutputFile, Markup)> = files
+        .iter()
+        .filter_map(|f| Markup::of(&f.path).map(|m| (relative(&f.path), (f, m))))
+        .collect();
+
+    let mut problems = vec![];
+    for (page, (file, markup)) in &pages {
+        let mut problem = |line: usize, kind: &'static str, message: String, link: Option<&str>| {
+            problems.push(Problem {
+                page: page.to_owned(),
+                line,
+                kind,
+                message,
+                link: link.map(|l| l.to_string()),
+                sources: sources(file),
+                template: template.to_string(),
+            })
+        };
+
+        let mut seen = HashSet::new();
+        for (line, anchor) in anchors(&file.content, *markup) {
+            if quality && !seen.insert(anchor.to_owned()) {
+                problem(
+                    line,
+                    "duplicate-anchor",
+                    format!("Duplicate anchor #{}", anchor),
+                    None,
+                );
+            }
+        }
+
+        for link in links(&file.content, *markup) {
+            if quality && link.image && link.alt.as_deref().is_none_or(|a| a.trim().is_empty()) {
+                problem(
+                    link.line,
+                    "missing-alt",
+                    format!("Image {} has no alternative text", link.target),
+                    Some(&link.target),
+                );
+            }
+            let Some((path, fragment)) = resolve(page, &link.target) else {
+                continue;
+            };
+            let target_page = if path.is_empty() {
+                pages.get(page)
+            } else {
+                pages.get(&path)
+            };
+            match (target_page, fragment) {
+                (Some((target, markup)), Some(fragment)) if !fragment.is_empty() => {
+                    if !link_targets(&target.content, *markup).contains(&fragment) {
+                        problem(
+                            link.line,
+                            "missing-anchor",
+                            format!("Link to {} has no anchor #{}", link.target, fragment),
+                            Some(&link.target),
+                        );
+                    }
+                }
+                (Some(_), _) => {}
+                (None, _) if output_dir.join(&path).exists() => {}
+                (None, _) => problem(
+                    link.line,
+                    "broken-link",
+                    match link.image {
+                        true => format!("Image {} does not exist", link.target),
+                        false => format!("Link to {} does not exist", link.target),
+                    },
+                    Some(&link.target),
+                ),
+            }
+        }
+    }
+    problems
+}
+
+/// Read the Markdown and HTML pages listed in a manifest from the output directory, with
+/// their contributing sources as the `source` attribute. Pages which no longer exist are
+/// skipped.
+pub fn manifest_pages(manifest: &Manifest, output_dir: &Path) -> Vec<OutputFile> {
+    manifest
+        .files
+        .iter()
+        .filter(|f| Markup::of(&f.path).is_some())
+        .filter_map(|f| {
+            let path = output_dir.join(&f.path);
+            match fs::read_to_string(&path) {
+                Ok(content) => Some(OutputFile {
+                    path,
+                    content,
+                    attributes: [(
+                        "source".to_string(),
+                        f.sources
+                            .iter()
+                            .map(|s| s.to_string_lossy())
+                            .collect::<Vec<_>>()
+                            .join(","),
+                    )]
+                    .into(),
+                    options: Default::default(),
+                }),
+                Err(e) => {
+                    warn!("Could not read {}: {}", path.display(), e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Replace the template of each problem with a link by the first line of the template
+/// files containing the link, e.g. `templates/service.tpl:12`, where it is written
+/// literally rather than built from variables.
+pub fn locate(problems: &mut [Problem], templates: &[PathBuf]) {
+    let contents: Vec<(&PathBuf, String)> = templates
+        .iter()
+        .filter_map(|t| fs::read_to_string(t).ok().map(|c| (t, c)))
+        .collect();
+    for problem in problems.iter_mut() {
+        let Some(link) = &problem.link else {
+            continue;
+        };
+        let found = contents.iter().find_map(|(path, content)| {
+            content
+                .lines()
+                .position(|l| l.contains(link.as_str()))
+                .map(|i| format!("{}:{}", path.display(), i + 1))
+        });
+        if let Some(location) = found {
+            problem.template = location;
+        }
+    }
+}
+
+/// Log each problem with the source files and template which produced its page, with a
+/// summary, returning an error if there are any problems.
+pub fn report(problems: &[Problem]) -> Result<()> {
+    for p in problems {
+        let origin = match p.sources.is_empty() {
+            true => format!("template {}", p.template),
+            false => format!("source {}, template {}", p.sources.join(", "), p.template),
+        };
+        error!(
+            "{}:{}: {} [{}] ({})",
+            p.page, p.line, p.message, p.kind, origin
+        );
+    }
+    crate::logging::summary(&format!("{} problems in generated pages", problems.len()));
+    if !problems.is_empty() {
+        bail!(ErrorKind::ValidationFailure(format!(
+            "{} problems in generated pages",
+            problems.len()
+        )))
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::check::{anchors, check_links, check_pages, links, locate, resolve, slug, Markup};
+    use crate::output::OutputFile;
+    use std::path::{Path, PathBuf};
+
+    fn page(path: &str, content: &str) -> OutputFile {
+        OutputFile {
+            path: PathBuf::from("/docs").join(path),
+            content: content.to_string(),
+            attributes: [("source".to_string(), "k8s/web.yaml".to_string())].into(),
+            options: Default::default(),
+        }
+    }
+
+    #[test]
+    fn markdown() {
+        let content = "# Web Service\nSee [the API](api.md#get-pets) and ![](logo.png).\n```\n[not](a-link.md)\n```\n[![Badge](badge.svg)](https://ci.example.com) `[code](x.md)`\n<img src=\"diagram.png\" alt=\"Diagram\">\n## Usage\n## Usage\n";
+        assert_eq!(
+            links(content, Markup::Markdown)
+                .iter()
+                .map(|l| (l.line, l.target.as_str(), l.alt.as_deref()))
+                .collect::<Vec<_>>(),
+            [
+                (2, "api.md#get-pets", None),
+                (2, "logo.png", Some("")),
+                (6, "https://ci.example.com", None),
+                (6, "badge.svg", Some("Badge")),
+                (7, "diagram.png", Some("Diagram"))
+            ]
+        );
+        assert_eq!(
+            anchors(content, Markup::Markdown),
+            [
+                (1, "web-service".to_string()),
+                (8, "usage".to_string()),
+                (9, "usage".to_string())
+            ]
+        );
+        assert_eq!(slug("GET /pets: List `pets`"), "get-pets-list-pets");
+    }
+
+    #[test]
+    fn resolve_links() {
+        assert_eq!(
+            resolve("services/web.md", "../api%20docs.md#top"),
+            Some(("api docs.md".to_string(), Some("top".to_string())))
+        );
+        assert_eq!(
+            resolve("services/web.md", "#usage"),
+            Some((String::new(), Some("usage".to_string())))
+        );
+        assert_eq!(
+            resolve("services/web.md", "/index.html"),
+            Some(("index.html".to_string(), None))
+        );
+        assert_eq!(resolve("web.md", "mailto:team@example.com"), None);
+        assert_eq!(resolve("web.md", "//cdn.example.com/a.js"), None);
+    }
+
+    #[test]
+    fn pages() {
+        let pages = vec![
+            page(
+                "services/web.md",
+                "# Web\n[API](../api.html#list-pets) [Missing](../api.html#create)\n[Gone](db.md)\n![](logo.png)\n# Web\n[Self](#web-1)\n",
+            ),
+            page(
+                "api.html",
+                "<h2 id=\"list-pets\">List</h2>\n<a href=\"services/web.md#web\">Web</a>",
+            ),
+        ];
+        let problems = check_pages(&pages, Path::new("/docs"), "docs.tpl");
+        assert_eq!(
+            problems
+                .iter()
+                .map(|p| (p.page.as_str(), p.line, p.kind))
+                .collect::<Vec<_>>(),
+            [
+                ("services/web.md", 5, "duplicate-anchor"),
+                ("services/web.md", 2, "missing-anchor"),
+                ("services/web.md", 3, "broken-link"),
+                ("services/web.md", 4, "missing-alt"),
+                ("services/web.md", 4, "broken-link")
+            ]
+        );
+        assert_eq!(problems[0].sources, ["k8s/web.yaml"]);
+        assert_eq!(problems[0].template, "docs.tpl");
+    }
+
+    #[test]
+    fn locate_links() {
+        let template =
+            std::env::temp_dir().join(format!("kvasir-check-{}.tpl", std::process::id()));
+        std::fs::write(&template, "# {{ name }}\n[Database](db.md)\n").unwrap();
+        let pages = vec![page("web.md", "# Web\n[Database](db.md) [Web](#web)\n")];
+
+        let mut problems = check_links(&pages, Path::new("/docs"), "docs.tpl");
+        locate(&mut problems, &[template.to_owned()]);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].link.as_deref(), Some("db.md"));
+        assert_eq!(problems[0].template, format!("{}:2", template.display()));
+
+        std::fs::remove_file(&template).unwrap();
+    }
+}
@@ -0,0 +1,216 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Validation of command-line argument values.
+
+use crate::parsers::ParserOptions;
+use chrono::{DateTime, Utc};
+use clap::Command;
+use itertools::Itertools;
+use std::path::PathBuf;
+
+/// The environment variables of arguments taking several values, which hold a
+/// comma-separated list of the values.
+const LISTS: &[&str] = &[
+    "KVASIR_SOURCES",
+    "KVASIR_TAGS",
+    "KVASIR_PARSER_OPTIONS",
+    "KVASIR_VAR_FILES",
+    "KVASIR_METRICS_LABELS",
+];
+
+/// Split the comma-separated lists of values held by environment variables, looked up with
+/// `var`, into the values of the arguments of a command and its subcommands.
+///
+/// Values given on the command line are not split, so that they may contain commas, e.g.
+/// `--parser-option 'jsonnet.ext_code.ports=[80,443]'`, and still override the environment.
+pub fn split_env_lists(mut command: Command, var: &impl Fn(&str) -> Option<String>) -> Command {
+    let subcommands = command
+        .get_subcommands()
+        .map(|c| c.get_name().to_string())
+        .collect_vec();
+    for name in subcommands {
+        command = command.mut_subcommand(name, |c| split_env_lists(c, var));
+    }
+    let lists = command
+        .get_arguments()
+        .filter_map(|arg| {
+            let env = arg.get_env()?.to_str()?;
+            let value = var(env).filter(|_| LISTS.contains(&env))?;
+            Some((arg.get_id().to_string(), value))
+        })
+        .collect_vec();
+    for (id, value) in lists {
+        let values = value.split(',').map(str::to_string).collect_vec();
+        command = command.mut_arg(id, |arg| arg.env(None).default_values(values));
+    }
+    command
+}
+
+/// Parse a path which must be an existing directory.
+pub fn existing_dir(value: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(value);
+    if path.is_dir() {
+        Ok(path)
+    } else {
+        Err(format!("'{}' is not an existing directory", value))
+    }
+}
+
+/// Parse a non-empty glob expression, or `-` for `stdin`.
+pub fn glob_expression(value: &str) -> Result<String, String> {
+    if value.trim().is_empty() {
+        return Err("glob expression must not be empty".to_string());
+    }
+    glob::Pattern::new(value)
+        .map(|_| value.to_string())
+        .map_err(|e| format!("invalid glob expression: {}", e))
+}
+
+/// Parse a parser option in the form `parser.option=value`.
+pub fn parser_option(value: &str) -> Result<String, String> {
+    ParserOptions::from_args(&[value.to_string()])
+        .map(|_| value.to_string())
+        .map_err(|e| e.to_string())
+}
+
+/// Parse a positive number of seconds.
+pub fn seconds(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(seconds) if seconds.is_finite() && seconds > 0.0 => Ok(seconds),
+        _ => Err(format!("'{}' is not a positive number of seconds", value)),
+    }
+}
+
+/// Parse a count of at least one.
+pub fn count(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(format!("'{}' is not a positive whole number", value)),
+    }
+}
+
+/// Parse a percentage from 0 to 100.
+pub fn percentage(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(percentage) if (0.0..=100.0).contains(&percentage) => Ok(percentage),
+        _ => Err(format!("'{}' is not a percentage from 0 to 100", value)),
+    }
+}
+
+/// Parse a positive size in bytes, optionally followed by `K`, `KB`, `M`, `MB`, `G` or `GB`
+/// for kibibytes, mebibytes or gibibytes, e.g. `512K`.
+pub fn size(value: &str) -> Result<usize, String> {
+    let upper = value.trim().to_uppercase();
+    let (number, multiplier) = match upper.trim_end_matches('B') {
+        n if n.ends_with('K') => (n.trim_end_matches('K'), 1024),
+        n if n.ends_with('M') => (n.trim_end_matches('M'), 1024 * 1024),
+        n if n.ends_with('G') => (n.trim_end_matches('G'), 1024 * 1024 * 1024),
+        n => (n, 1),
+    };
+    match number.trim().parse::<usize>() {
+        Ok(size) if size > 0 => Ok(size * multiplier),
+        _ => Err(format!(
+            "'{}' is not a positive size, e.g. 500000 or 512K",
+            value
+        )),
+    }
+}
+
+/// Parse a metric label in the form `name=value`, whose name must be a valid Prometheus
+/// label name.
+pub fn label(value: &str) -> Result<(String, String), String> {
+    let (name, label) = value
+        .split_once('=')
+        .ok_or_else(|| format!("'{}' is not in the form name=value", value))?;
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.starts_with("__");
+    match valid {
+        true => Ok((name.to_string(), label.to_string())),
+        false => Err(format!(
+            "'{}' is not a valid label name: use letters, digits and underscores",
+            name
+        )),
+    }
+}
+
+/// Parse a time from seconds since the Unix epoch, an RFC 3339 timestamp or a
+/// `YYYY-MM-DD` date.
+pub fn time(value: &str) -> Result<DateTime<Utc>, String> {
+    crate::clock::parse(value).ok_or_else(|| {
+        format!(
+            "'{}' is not a number of seconds since the Unix epoch, RFC 3339 timestamp or date",
+            value
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::cli::{
+        count, existing_dir, glob_expression, label, parser_option, percentage, seconds, size, time,
+    };
+
+    #[test]
+    fn values() {
+        assert!(existing_dir("test/resources").is_ok());
+        assert!(existing_dir("test/resources/test.json").is_err());
+        assert!(existing_dir("missing").is_err());
+
+        assert_eq!(glob_expression("src/**/*.rs").unwrap(), "src/**/*.rs");
+        assert!(glob_expression("-").is_ok());
+        assert!(glob_expression(" ").is_err());
+        assert!(glob_expression("src/***").is_err());
+
+        assert!(parser_option("kubernetes.redact_secrets=true").is_ok());
+        assert!(parser_option("redact_secrets").is_err());
+
+        assert_eq!(seconds("0.5").unwrap(), 0.5);
+        assert!(seconds("0").is_err());
+        assert!(seconds("inf").is_err());
+
+        assert_eq!(count("4").unwrap(), 4);
+        assert!(count("0").is_err());
+        assert!(count("-1").is_err());
+
+        assert_eq!(percentage("80").unwrap(), 80.0);
+        assert!(percentage("100.5").is_err());
+        assert!(percentage("-1").is_err());
+
+        assert_eq!(size("1000").unwrap(), 1000);
+        assert_eq!(size("512K").unwrap(), 524288);
+        assert_eq!(size("1mb").unwrap(), 1048576);
+        assert_eq!(size("2G").unwrap(), 2147483648);
+        assert!(size("0").is_err());
+        assert!(size("big").is_err());
+
+        assert_eq!(
+            label("repo=payments=api").unwrap(),
+            ("repo".to_string(), "payments=api".to_string())
+        );
+        assert!(label("repo").is_err());
+        assert!(label("1repo=payments").is_err());
+        assert!(label("__name__=payments").is_err());
+
+        assert_eq!(time("0").unwrap().timestamp(), 0);
+        assert!(time("now").is_err());
+    }
+}
@@ -0,0 +1,135 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! The time at which output is generated, which can be frozen for reproducible builds.
+//!
+//! The time is frozen with `--frozen-time` or, following the
+//! [reproducible builds](https://reproducible-builds.org/specs/source-date-epoch/)
+//! convention, with the `SOURCE_DATE_EPOCH` environment variable. Front matter, manifests
+//! and the `now` template function then all use the frozen time, so that generated output
+//! is identical across runs.
+
+use crate::errors::*;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, NaiveDate, Utc};
+use std::sync::OnceLock;
+
+/// The environment variable holding the frozen time, in seconds since the Unix epoch.
+pub const SOURCE_DATE_EPOCH: &str = "SOURCE_DATE_EPOCH";
+
+static FROZEN: OnceLock<DateTime<Utc>> = OnceLock::new();
+
+/// Freeze the time at the provided time or, if none is provided, at the time in the
+/// `SOURCE_DATE_EPOCH` environment variable, if set.
+pub fn freeze(time: Option<DateTime<Utc>>) -> Result<()> {
+    let time = match time {
+        Some(time) => Some(time),
+        None => match std::env::var(SOURCE_DATE_EPOCH) {
+            Ok(epoch) if !epoch.trim().is_empty() => Some(
+                parse_epoch(&epoch)
+                    .ok_or_else(|| format!("Invalid {}: '{}'", SOURCE_DATE_EPOCH, epoch))?,
+            ),
+            _ => None,
+        },
+    };
+    if let Some(time) = time {
+        FROZEN
+            .set(time)
+            .map_err(|_| "The time has already been frozen")?;
+    }
+    Ok(())
+}
+
+/// Return whether the time has been frozen.
+pub fn is_frozen() -> bool {
+    FROZEN.get().is_some()
+}
+
+/// Return the current time, or the frozen time if the time has been frozen.
+pub fn now() -> DateTime<Utc> {
+    FROZEN.get().copied().unwrap_or_else(Utc::now)
+}
+
+/// Return the current time, or the frozen time, as an RFC 3339 timestamp to the second.
+pub fn timestamp() -> String {
+    now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+/// Format a time with a `strftime` format string, returning `None` if the format is invalid.
+pub fn format(time: &DateTime<Utc>, format: &str) -> Option<String> {
+    let items: Vec<Item> = StrftimeItems::new(format).collect();
+    if items.contains(&Item::Error) {
+        None
+    } else {
+        Some(time.format_with_items(items.into_iter()).to_string())
+    }
+}
+
+/// Parse a time from seconds since the Unix epoch, an RFC 3339 timestamp or a
+/// `YYYY-MM-DD` date, taken as midnight UTC.
+pub fn parse(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    parse_epoch(value)
+        .or_else(|| {
+            DateTime::parse_from_rfc3339(value)
+                .ok()
+                .map(|t| t.with_timezone(&Utc))
+        })
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+                .map(|t| t.and_utc())
+        })
+}
+
+/// Parse a time from seconds since the Unix epoch.
+fn parse_epoch(value: &str) -> Option<DateTime<Utc>> {
+    value
+        .trim()
+        .parse::<i64>()
+        .ok()
+        .and_then(|s| DateTime::from_timestamp(s, 0))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::clock::{format, parse};
+
+    #[test]
+    fn parse_times() {
+        let expected = "2024-03-01T12:30:00Z";
+        assert_eq!(
+            parse("1709296200")
+                .unwrap()
+                .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            expected
+        );
+        assert_eq!(
+            parse("2024-03-01T13:30:00+01:00")
+                .unwrap()
+                .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            expected
+        );
+        assert_eq!(parse("2024-03-01").unwrap().timestamp(), 1709251200);
+        assert!(parse("yesterday").is_none());
+
+        let time = parse(expected).unwrap();
+        assert_eq!(format(&time, "%d %B %Y").unwrap(), "01 March 2024");
+        assert!(format(&time, "%Q").is_none());
+    }
+}
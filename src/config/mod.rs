@@ -0,0 +1,712 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Project configuration, read from a `kvasir.toml` file.
+//!
+//! ```toml
+//! sources = [
+//!     "config/**/*.properties",
+//!     { glob = "k8s/**/*.yaml", tags = ["infra", "prod"] },
+//! ]
+//! codeowners = ".github/CODEOWNERS"
+//!
+//! [var_schema]
+//! required = ["environment"]
+//! properties.environment = { type = "string", enum = ["Production", "Staging"] }
+//!
+//! [vars]
+//! site = "Platform documentation"
+//!
+//! [profiles.prod]
+//! sources = [{ glob = "envs/prod/**/*.yaml", tags = ["prod"] }]
+//! vars = { environment = "Production" }
+//!
+//! [[hooks]]
+//! pattern = "**/*.md"
+//! command = ["markdownlint", "--fix"]
+//!
+//! [exec]
+//! allow = ["git", "terraform"]
+//!
+//! [pages]
+//! pattern = "services/{stem}.md"
+//!
+//! [i18n]
+//! dir = "locales"
+//! default_locale = "en"
+//!
+//! [publish]
+//! bucket = "docs-site"
+//! region = "eu-west-2"
+//!
+//! [notify]
+//! webhook = "https://hooks.slack.com/services/T000/B000/XXXX"
+//! when = "changes"
+//!
+//! [stats]
+//! distinct = ["$.spec.template.spec.containers[*].image"]
+//!
+//! [canonical]
+//! unordered = ["spec.template.spec.containers[].env"]
+//!
+//! [remote]
+//! retries = 5
+//! on_failure = "warn"
+//!
+//! [[rules]]
+//! name = "operation-description"
+//! parser = "openapi"
+//! select = "$.paths.*.*"
+//! require = ["description"]
+//! severity = "warning"
+//! message = "Every OpenAPI operation must have a description"
+//! ```
+
+use crate::errors::*;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The name of the configuration file read from the current directory by default.
+pub const DEFAULT_CONFIG_FILE: &str = "kvasir.toml";
+
+/// Project configuration.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Config {
+    /// Source files to parse, in addition to any provided on the command line.
+    #[serde(default)]
+    pub sources: Vec<Source>,
+    /// The CODEOWNERS file from which the owners of parsed files are taken. By default,
+    /// the file is looked for in `.github`, the current directory, `docs` and `.gitlab`.
+    #[serde(default)]
+    pub codeowners: Option<PathBuf>,
+    /// Variables made available to templates.
+    #[serde(default)]
+    pub vars: Map<String, Value>,
+    /// A JSON Schema which template variables must match, or the path to a JSON or YAML
+    /// file containing the schema.
+    #[serde(default)]
+    pub var_schema: Option<Value>,
+    /// Named profiles, each adding sources and variables to the configuration.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    /// Commands run on output files after they have been written.
+    #[serde(default)]
+    pub hooks: Vec<Hook>,
+    /// Commands that templates may run with `exec`.
+    #[serde(default)]
+    pub exec: ExecConfig,
+    /// How generated pages are named, for links to the page for a source file.
+    #[serde(default)]
+    pub pages: PagesConfig,
+    /// The object storage bucket to which output is published with `--publish`.
+    #[serde(default)]
+    pub publish: PublishConfig,
+    /// Where translations used by the `t` template function are read from.
+    #[serde(default)]
+    pub i18n: I18nConfig,
+    /// The webhook notified with a summary of each `kvasir document` run.
+    #[serde(default)]
+    pub notify: NotifyConfig,
+    /// Word lists used to spell-check output with `--spell-check`.
+    #[serde(default)]
+    pub spelling: SpellingConfig,
+    /// Options for summary statistics of the parsed files.
+    #[serde(default)]
+    pub stats: StatsConfig,
+    /// Options for canonical output with `--canonical`.
+    #[serde(default)]
+    pub canonical: CanonicalConfig,
+    /// How remote sources are fetched.
+    #[serde(default)]
+    pub remote: RemoteConfig,
+    /// Rules checked against parsed files by `kvasir lint`.
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    /// The name of the active profile, if any.
+    #[serde(skip)]
+    pub profile: Option<String>,
+}
+
+/// A command run on each written output file matching a glob pattern.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Hook {
+    /// A glob pattern matched against the path of each output file, relative to the
+    /// output directory.
+    pub pattern: String,
+    /// The command and its arguments. The path of the output file is appended as the
+    /// final argument.
+    pub command: Vec<String>,
+    /// Whether to run the command once with the paths of all matching files, rather than
+    /// once per file.
+    #[serde(default)]
+    pub batch: bool,
+}
+
+/// A convention checked against the contents of parsed files.
+///
+/// Every value selected by the `select` JSONPath expression is a violation of the rule,
+/// unless `require` is set, in which case only selected values without one of the required
+/// keys are violations.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Rule {
+    /// The name of the rule, used when reporting violations.
+    pub name: String,
+    /// A JSONPath expression selecting values from the contents of each parsed file.
+    pub select: String,
+    /// Keys which each selected value must have.
+    #[serde(default)]
+    pub require: Vec<String>,
+    /// The name of the parser whose files are checked. Files parsed by any parser are
+    /// checked if not set.
+    pub parser: Option<String>,
+    /// The severity of violations of the rule.
+    #[serde(default)]
+    pub severity: Severity,
+    /// The message reported for violations of the rule.
+    pub message: String,
+}
+
+/// The severity of a rule violation. Only violations of rules with `error` severity cause
+/// linting to fail.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    #[default]
+    Error,
+}
+
+/// Commands that templates may run with the `exec` function, if allowed with `--allow-exec`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ExecConfig {
+    /// The names of the commands that may be run.
+    #[serde(default)]
+    pub allow: Vec<String>,
+}
+
+/// How generated pages are named, used by the `page_for` template function.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PagesConfig {
+    /// The path of the page for each source file, relative to the output directory, with
+    /// `{path}`, `{directory}`, `{name}`, `{stem}` and `{extension}` replaced by those of the
+    /// source file.
+    pub pattern: Option<String>,
+    /// The URL prepended to page paths.
+    pub base_url: Option<String>,
+}
+
+/// Where translation catalogues are read from, used by the `t` template function.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct I18nConfig {
+    /// The directory containing a YAML catalogue for each locale, e.g. `de.yaml`,
+    /// defaulting to `locales`.
+    pub dir: Option<String>,
+    /// The locale whose catalogue is used when no locale is selected, and for messages
+    /// missing from the catalogue of the selected locale.
+    pub default_locale: Option<String>,
+}
+
+/// The S3-compatible bucket to which output files are published with `--publish`.
+/// Credentials are read from the `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and
+/// optional `AWS_SESSION_TOKEN` environment variables.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PublishConfig {
+    /// The name of the bucket.
+    pub bucket: Option<String>,
+    /// A prefix prepended to the key of each object, e.g. `docs/`.
+    #[serde(default)]
+    pub prefix: String,
+    /// The region of the bucket, defaulting to `us-east-1`.
+    pub region: Option<String>,
+    /// The URL of an S3-compatible service, defaulting to AWS.
+    pub endpoint: Option<String>,
+    /// Whether the bucket is addressed in the path of each URL rather than its host name,
+    /// as required by some S3-compatible services.
+    #[serde(default)]
+    pub path_style: bool,
+}
+
+/// The word lists used to spell-check generated pages with `--spell-check`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SpellingConfig {
+    /// Files listing known words, one per line, such as `/usr/share/dict/words` or a
+    /// Hunspell `.dic` file, whose affix flags are ignored.
+    #[serde(default)]
+    pub dictionaries: Vec<PathBuf>,
+    /// Project words, such as product and team names, in addition to the dictionaries.
+    #[serde(default)]
+    pub words: Vec<String>,
+    /// The severity of misspellings, defaulting to `warning`. Misspellings with `error`
+    /// severity fail the run.
+    pub severity: Option<Severity>,
+}
+
+/// A Slack or Microsoft Teams incoming webhook notified with a summary of each
+/// `kvasir document` run.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct NotifyConfig {
+    /// The webhook URL, which may instead be provided in the `KVASIR_NOTIFY_WEBHOOK`
+    /// environment variable.
+    pub webhook: Option<String>,
+    /// The service receiving notifications.
+    #[serde(default)]
+    pub format: NotifyFormat,
+    /// When notifications are sent.
+    #[serde(default)]
+    pub when: NotifyWhen,
+    /// The URL of the published documentation, defaulting to the `[pages]` base URL.
+    pub site_url: Option<String>,
+}
+
+/// The service receiving notifications, which determines the format of the payload.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyFormat {
+    #[default]
+    Slack,
+    Teams,
+}
+
+/// When notifications are sent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyWhen {
+    /// After every run.
+    #[default]
+    Always,
+    /// Only after runs which fail or write output files.
+    Changes,
+    /// Only after runs which fail, or in which files cannot be fetched, parsed or rendered.
+    Failure,
+}
+
+/// Options for summary statistics of the parsed files.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StatsConfig {
+    /// JSONPath expressions whose distinct values are counted, in addition to any provided
+    /// on the command line.
+    #[serde(default)]
+    pub distinct: Vec<String>,
+}
+
+/// Options for canonical output of the parsed files.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CanonicalConfig {
+    /// Dotted keys within the parsed contents of files whose arrays may be sorted, as the
+    /// order of their elements is not significant.
+    #[serde(default)]
+    pub unordered: Vec<String>,
+}
+
+/// How sources given as `http` or `https` URLs are fetched.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RemoteConfig {
+    /// The number of times a failed request is retried, defaulting to 3.
+    pub retries: Option<u32>,
+    /// The number of seconds to wait before the first retry, doubling before each further
+    /// retry, defaulting to 1.
+    pub backoff: Option<f64>,
+    /// The maximum number of seconds taken by each request, defaulting to 30.
+    pub timeout: Option<f64>,
+    /// What to do when a remote source cannot be fetched.
+    #[serde(default)]
+    pub on_failure: RemoteFailure,
+}
+
+/// What to do when a remote source cannot be fetched after retrying.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RemoteFailure {
+    /// Fail the run.
+    #[default]
+    Fail,
+    /// Warn, and continue without the source, reporting it in the summary of the run.
+    Warn,
+}
+
+/// A named set of sources and variables, such as those for a single environment.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Profile {
+    /// Source files to parse when the profile is active.
+    #[serde(default)]
+    pub sources: Vec<Source>,
+    /// Variables made available to templates when the profile is active, overriding
+    /// variables of the same name in the main configuration.
+    #[serde(default)]
+    pub vars: Map<String, Value>,
+}
+
+impl Config {
+    /// Load the configuration from the specified file or, if no file is specified, from
+    /// `kvasir.toml` in the current directory if it exists.
+    pub fn load(path: Option<&str>) -> Result<Config> {
+        match path {
+            Some(p) => Config::from_file(Path::new(p)),
+            None if Path::new(DEFAULT_CONFIG_FILE).is_file() => {
+                Config::from_file(Path::new(DEFAULT_CONFIG_FILE))
+            }
+            None => Ok(Config::default()),
+        }
+    }
+
+    /// Activate a profile, adding its sources and variables to the configuration.
+    pub fn activate(&mut self, profile: Option<&str>) -> Result<()> {
+        if let Some(name) = profile {
+            let active = self.profiles.remove(name).ok_or_else(|| {
+                format!(
+                    "Unknown profile '{}'. Available profiles: {}",
+                    name,
+                    self.profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+                )
+            })?;
+            debug!("Activating profile {}", name);
+            self.sources.extend(active.sources);
+            self.vars.extend(active.vars);
+            self.profile = Some(name.to_string());
+        }
+        Ok(())
+    }
+
+    /// Add the variables in JSON, YAML or TOML files to the configuration, overriding
+    /// variables of the same name. Files are read in order, so later files take precedence.
+    pub fn add_var_files(&mut self, paths: &[PathBuf]) -> Result<()> {
+        for path in paths {
+            debug!("Reading variables from {}", path.display());
+            let contents = fs::read_to_string(path)
+                .chain_err(|| format!("Could not read variables file {}", path.display()))?;
+            let vars: Map<String, Value> = match path.extension().and_then(|e| e.to_str()) {
+                Some("toml") => toml::from_str(&contents).map_err(|e| e.to_string()),
+                Some("yaml") | Some("yml") => {
+                    serde_yaml::from_str(&contents).map_err(|e| e.to_string())
+                }
+                _ => serde_json::from_str(&contents).map_err(|e| e.to_string()),
+            }
+            .map_err(|e| {
+                format!(
+                    "Could not parse variables file {} as an object: {}",
+                    path.display(),
+                    e
+                )
+            })?;
+            self.vars.extend(vars);
+        }
+        Ok(())
+    }
+
+    /// Check the template variables against the variable schema, if any, failing with a
+    /// message for each missing or invalid variable.
+    pub fn check_vars(&self) -> Result<()> {
+        let schema = match &self.var_schema {
+            Some(Value::String(path)) => {
+                let contents = fs::read_to_string(path)
+                    .chain_err(|| format!("Could not read variable schema {}", path))?;
+                serde_yaml::from_str(&contents)
+                    .chain_err(|| format!("Could not parse variable schema {}", path))?
+            }
+            Some(schema) => schema.to_owned(),
+            None => return Ok(()),
+        };
+        let errors = crate::schema::validate(&schema, &Value::Object(self.vars.to_owned()), "vars");
+        if !errors.is_empty() {
+            bail!(ErrorKind::ValidationFailure(format!(
+                "Template variables do not match the variable schema:\n  {}",
+                errors.join("\n  ")
+            )))
+        }
+        Ok(())
+    }
+
+    /// Load the configuration from a file.
+    fn from_file(path: &Path) -> Result<Config> {
+        debug!("Reading configuration from {}", path.display());
+        let contents = fs::read_to_string(path)
+            .chain_err(|| format!("Could not read configuration file {}", path.display()))?;
+        toml::from_str(&contents)
+            .chain_err(|| format!("Could not parse configuration file {}", path.display()))
+    }
+}
+
+/// A glob expression for source files, with tags applied to every matching file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(from = "SourceDefinition")]
+pub struct Source {
+    pub glob: String,
+    pub tags: Vec<String>,
+}
+
+impl Source {
+    /// Create a source from a glob expression and tags.
+    pub fn new(glob: &str, tags: &[String]) -> Source {
+        Source {
+            glob: glob.to_string(),
+            tags: tags.to_vec(),
+        }
+    }
+}
+
+impl From<&str> for Source {
+    fn from(glob: &str) -> Self {
+        Source::new(glob, &[])
+    }
+}
+
+/// Sources may be defined as either a bare glob expression or a table with tags.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SourceDefinition {
+    Glob(String),
+    Tagged {
+        glob: String,
+        #[serde(default)]
+        tags: Vec<String>,
+    },
+}
+
+impl From<SourceDefinition> for Source {
+    fn from(definition: SourceDefinition) -> Self {
+        match definition {
+            SourceDefinition::Glob(glob) => Source::from(glob.as_str()),
+            SourceDefinition::Tagged { glob, tags } => Source::new(&glob, &tags),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{Config, NotifyFormat, NotifyWhen, RemoteFailure, Severity, Source};
+    use serde_json::json;
+    use std::fs;
+
+    #[test]
+    fn sources() {
+        let config: Config = toml::from_str(
+            r#"
+            sources = [
+                "config/*.properties",
+                { glob = "k8s/**/*.yaml", tags = ["infra", "prod"] },
+            ]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.sources,
+            vec![
+                Source::from("config/*.properties"),
+                Source::new("k8s/**/*.yaml", &["infra".to_string(), "prod".to_string()])
+            ]
+        );
+    }
+
+    #[test]
+    fn hooks() {
+        let config: Config = toml::from_str(
+            r#"
+            [[hooks]]
+            pattern = "**/*.md"
+            command = ["prettier", "--write"]
+            batch = true
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.hooks[0].pattern, "**/*.md");
+        assert_eq!(config.hooks[0].command, vec!["prettier", "--write"]);
+        assert!(config.hooks[0].batch);
+    }
+
+    #[test]
+    fn exec() {
+        let config: Config = toml::from_str(
+            r#"
+            [exec]
+            allow = ["git", "terraform"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.exec.allow, vec!["git", "terraform"]);
+        assert!(Config::default().exec.allow.is_empty());
+    }
+
+    #[test]
+    fn publish() {
+        let config: Config = toml::from_str(
+            r#"
+            [publish]
+            bucket = "docs-site"
+            prefix = "docs/"
+            endpoint = "http://localhost:9000"
+            path_style = true
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.publish.bucket.as_deref(), Some("docs-site"));
+        assert_eq!(config.publish.prefix, "docs/");
+        assert!(config.publish.region.is_none());
+        assert!(config.publish.path_style);
+        assert!(Config::default().publish.bucket.is_none());
+    }
+
+    #[test]
+    fn notify() {
+        let config: Config = toml::from_str(
+            r#"
+            [notify]
+            webhook = "https://example.webhook.office.com/webhookb2/abc"
+            format = "teams"
+            when = "failure"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.notify.format, NotifyFormat::Teams);
+        assert_eq!(config.notify.when, NotifyWhen::Failure);
+        assert_eq!(Config::default().notify.format, NotifyFormat::Slack);
+        assert_eq!(Config::default().notify.when, NotifyWhen::Always);
+    }
+
+    #[test]
+    fn spelling() {
+        let config: Config = toml::from_str(
+            r#"
+            [spelling]
+            dictionaries = ["/usr/share/dict/words", "docs/words.txt"]
+            words = ["kvasir", "Kubernetes"]
+            severity = "error"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.spelling.dictionaries.len(), 2);
+        assert_eq!(config.spelling.words, ["kvasir", "Kubernetes"]);
+        assert_eq!(config.spelling.severity, Some(Severity::Error));
+        assert_eq!(Config::default().spelling.severity, None);
+    }
+
+    #[test]
+    fn var_schema() {
+        let mut config: Config = toml::from_str(
+            r#"
+            [var_schema]
+            required = ["environment", "team"]
+            properties.environment = { type = "string", enum = ["dev", "prod"] }
+
+            [vars]
+            team = "platform"
+            "#,
+        )
+        .unwrap();
+        let error = config.check_vars().unwrap_err().to_string();
+        assert!(error.contains("vars: missing required variable 'environment'"));
+
+        let var_file =
+            std::env::temp_dir().join(format!("kvasir-vars-{}.yaml", std::process::id()));
+        fs::write(&var_file, "environment: prod\n").unwrap();
+        config.add_var_files(&[var_file.to_owned()]).unwrap();
+        fs::remove_file(&var_file).unwrap();
+        assert!(config.check_vars().is_ok());
+        assert!(Config::default().check_vars().is_ok());
+    }
+
+    #[test]
+    fn rules() {
+        let config: Config = toml::from_str(
+            r#"
+            [[rules]]
+            name = "no-root"
+            select = "$..securityContext[?(@.runAsUser == 0)]"
+            message = "Containers must not run as root"
+
+            [[rules]]
+            name = "description"
+            parser = "openapi"
+            select = "$.paths.*.*"
+            require = ["description"]
+            severity = "warning"
+            message = "Operations must have a description"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.rules[0].severity, Severity::Error);
+        assert!(config.rules[0].require.is_empty());
+        assert_eq!(config.rules[1].parser.as_deref(), Some("openapi"));
+        assert_eq!(config.rules[1].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn stats() {
+        let config: Config = toml::from_str("[stats]\ndistinct = [\"$.name\"]").unwrap();
+        assert_eq!(config.stats.distinct, vec!["$.name"]);
+        assert!(Config::default().stats.distinct.is_empty());
+    }
+
+    #[test]
+    fn remote() {
+        let config: Config =
+            toml::from_str("[remote]\nretries = 5\non_failure = \"warn\"").unwrap();
+        assert_eq!(config.remote.retries, Some(5));
+        assert_eq!(config.remote.on_failure, RemoteFailure::Warn);
+        assert_eq!(Config::default().remote.on_failure, RemoteFailure::Fail);
+    }
+
+    #[test]
+    fn profiles() {
+        let load = || -> Config {
+            toml::from_str(
+                r#"
+                sources = ["common/*.yaml"]
+
+                [vars]
+                site = "Docs"
+                environment = "Development"
+
+                [profiles.prod]
+                sources = [{ glob = "prod/*.yaml", tags = ["prod"] }]
+                vars = { environment = "Production" }
+                "#,
+            )
+            .unwrap()
+        };
+
+        let mut config = load();
+        config.activate(Some("prod")).unwrap();
+        assert_eq!(config.profile, Some("prod".to_string()));
+        assert_eq!(
+            config.sources,
+            vec![
+                Source::from("common/*.yaml"),
+                Source::new("prod/*.yaml", &["prod".to_string()])
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(&config.vars).unwrap(),
+            json!({ "site": "Docs", "environment": "Production" })
+        );
+
+        let mut config = load();
+        config.activate(None).unwrap();
+        assert_eq!(config.sources.len(), 1);
+        assert!(load().activate(Some("test")).is_err());
+    }
+}
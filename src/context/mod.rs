@@ -0,0 +1,448 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! The context provided to templates.
+//!
+//! The context has a stable, versioned schema (see [`OutputSchema`]), with the following
+//! top-level keys:
+//!
+//! * `schemaVersion`: the version of the context schema, from schema version 2.
+//! * `files`: the successfully parsed source files, each with a `path`, `parser`,
+//!   `index` of the document within the file, `contents` and optional `name`,
+//!   `descriptions`, `metadata`, `warnings`, `tags`, `owners`, `duplicates` and
+//!   `external_refs`.
+//! * `failures`: the source files that a parser accepted but could not parse, each with a
+//!   `path`, `parser` and `error`.
+//! * `vars`: the template variables from the configuration file and active profile.
+//! * `env`: the environment variables of the kvasir process.
+//! * `kvasir`: details of the kvasir run, i.e. the kvasir `version` and active `profile`.
+//! * `file`: the file being rendered, only when rendering each file separately.
+//! * `dir`: the directory being rendered, only when rendering each directory separately.
+//! * `stats`: summary statistics of all parsed files (see [`Stats`]), only with `--stats`.
+//! * `drift`: the keys missing from some of the files matched by each source glob (see
+//!   [`Drift`]), only with `--drift`.
+//!
+//! In schema version 1, the active profile is also available as the top-level `profile` key.
+//!
+//! Parsed files can be very large, so the context takes ownership of them rather than
+//! copying them, and can be partitioned into chunks of files, or the files of each directory,
+//! which are rendered separately.
+
+use crate::config::Config;
+use crate::drift::Drift;
+use crate::errors::*;
+use crate::parsers::{ParseFailure, ParseSuccess};
+use crate::paths;
+use crate::report::OutputSchema;
+use crate::stats::Stats;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// The context provided to templates.
+#[derive(Debug, Serialize)]
+pub struct TemplateContext {
+    #[serde(rename = "schemaVersion", skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<u32>,
+    pub files: Vec<Value>,
+    pub failures: Vec<Failure>,
+    pub vars: Map<String, Value>,
+    pub env: BTreeMap<String, String>,
+    pub kvasir: Kvasir,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<Stats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub drift: Option<Vec<Drift>>,
+    /// The directory whose files are in the context, when the files of each directory are
+    /// rendered separately.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+    /// Keys which are only present in schema version 1.
+    #[serde(flatten)]
+    pub legacy: Option<Legacy>,
+}
+
+/// Top-level context keys which are only present in schema version 1.
+#[derive(Debug, Clone, Serialize)]
+pub struct Legacy {
+    pub profile: Option<String>,
+}
+
+/// A file that could not be parsed by a parser which accepted it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Failure {
+    pub path: PathBuf,
+    pub parser: String,
+    pub error: String,
+}
+
+impl From<&ParseFailure> for Failure {
+    fn from(failure: &ParseFailure) -> Failure {
+        Failure {
+            path: failure.path.to_owned(),
+            parser: failure.parser.to_owned(),
+            error: failure.error.to_string(),
+        }
+    }
+}
+
+/// Details of the kvasir run.
+#[derive(Debug, Clone, Serialize)]
+pub struct Kvasir {
+    pub version: &'static str,
+    pub profile: Option<String>,
+}
+
+/// The context for rendering a single file, which is available as `file` alongside the
+/// shared context, without copying it.
+#[derive(Debug, Serialize)]
+pub struct FileContext<'a> {
+    #[serde(flatten)]
+    pub context: &'a TemplateContext,
+    pub file: &'a Value,
+}
+
+/// Convert a parsed file into a JSON value, moving rather than copying its contents.
+pub fn file_value(file: ParseSuccess) -> Value {
+    let mut value = Map::new();
+    value.insert(
+        "path".to_string(),
+        Value::String(file.path.display().to_string()),
+    );
+    value.insert("parser".to_string(), Value::String(file.parser));
+    if let Some(index) = file.index {
+        value.insert("index".to_string(), Value::from(index));
+    }
+    if let Some(name) = file.name {
+        value.insert("name".to_string(), Value::String(name));
+    }
+    value.insert("contents".to_string(), file.contents);
+    if !file.descriptions.is_empty() {
+        value.insert(
+            "descriptions".to_string(),
+            Value::Object(
+                file.descriptions
+                    .into_iter()
+                    .map(|(k, v)| (k, Value::String(v)))
+                    .collect(),
+            ),
+        );
+    }
+    if !file.metadata.is_empty() {
+        value.insert("metadata".to_string(), Value::Object(file.metadata));
+    }
+    if !file.warnings.is_empty() {
+        value.insert(
+            "warnings".to_string(),
+            Value::Array(file.warnings.into_iter().map(Value::String).collect()),
+        );
+    }
+    if !file.tags.is_empty() {
+        value.insert(
+            "tags".to_string(),
+            Value::Array(file.tags.into_iter().map(Value::String).collect()),
+        );
+    }
+    if !file.owners.is_empty() {
+        value.insert(
+            "owners".to_string(),
+            Value::Array(file.owners.into_iter().map(Value::String).collect()),
+        );
+    }
+    if !file.duplicates.is_empty() {
+        value.insert(
+            "duplicates".to_string(),
+            Value::Array(
+                file.duplicates
+                    .iter()
+                    .map(|d| Value::String(d.display().to_string()))
+                    .collect(),
+            ),
+        );
+    }
+    if !file.external_refs.is_empty() {
+        value.insert(
+            "external_refs".to_string(),
+            serde_json::to_value(file.external_refs).unwrap_or_default(),
+        );
+    }
+    Value::Object(value)
+}
+
+impl TemplateContext {
+    /// Create the template context with the given schema from parsing results and the
+    /// active configuration, taking ownership of the parsed files.
+    pub fn new(
+        mut successes: Vec<ParseSuccess>,
+        failures: &[ParseFailure],
+        config: &Config,
+        schema: OutputSchema,
+    ) -> TemplateContext {
+        schema.conform(&mut successes);
+        TemplateContext {
+            schema_version: (schema != OutputSchema::V1).then(|| schema.version()),
+            files: successes.into_iter().map(file_value).collect(),
+            failures: failures.iter().map(Failure::from).collect(),
+            vars: config.vars.to_owned(),
+            env: std::env::vars().collect(),
+            kvasir: Kvasir {
+                version: env!("CARGO_PKG_VERSION"),
+                profile: config.profile.to_owned(),
+            },
+            stats: None,
+            drift: None,
+            dir: None,
+            legacy: (schema == OutputSchema::V1).then(|| Legacy {
+                profile: config.profile.to_owned(),
+            }),
+        }
+    }
+
+    /// Add summary statistics of the parsed files to the context, selecting distinct values
+    /// with each of the `distinct` JSONPath expressions.
+    pub fn with_stats(mut self, distinct: &[String]) -> Result<TemplateContext> {
+        self.stats = Some(Stats::new(&self.files, self.failures.len(), distinct)?);
+        Ok(self)
+    }
+
+    /// Add a report of the drift between the parsed files matched by each source glob,
+    /// given with the paths it matched, to the context.
+    pub fn with_drift(mut self, globs: &[(String, Vec<PathBuf>)]) -> TemplateContext {
+        self.drift = Some(crate::drift::drift(globs, &self.files));
+        self
+    }
+
+    /// Partition the context into contexts each containing at most `size` files, so that
+    /// each chunk of files can be rendered separately. Statistics remain those of all files.
+    pub fn chunks(mut self, size: usize) -> Vec<TemplateContext> {
+        let mut files = std::mem::take(&mut self.files).into_iter().peekable();
+        let mut chunks = vec![];
+        while files.peek().is_some() {
+            chunks.push(self.partition(files.by_ref().take(size.max(1)).collect(), None));
+        }
+        chunks
+    }
+
+    /// Partition the context into a context for each directory containing parsed files, in
+    /// order of directory, with `dir` set to the directory and `files` containing only the
+    /// files directly within it. A directory parsed as a whole belongs to itself rather than
+    /// to its parent. Statistics remain those of all files.
+    pub fn directories(mut self) -> Vec<TemplateContext> {
+        let mut directories: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+        for file in std::mem::take(&mut self.files) {
+            let path = Path::new(file["path"].as_str().unwrap_or_default());
+            let dir = match path.is_dir() {
+                true => path,
+                false => path.parent().unwrap_or(Path::new("")),
+            };
+            directories
+                .entry(paths::portable(&dir.to_string_lossy()))
+                .or_default()
+                .push(file);
+        }
+        directories
+            .into_iter()
+            .map(|(dir, files)| self.partition(files, Some(dir)))
+            .collect()
+    }
+
+    /// Return a context for some of the files of this context.
+    fn partition(&self, files: Vec<Value>, dir: Option<String>) -> TemplateContext {
+        TemplateContext {
+            schema_version: self.schema_version,
+            files,
+            failures: self.failures.to_owned(),
+            vars: self.vars.to_owned(),
+            env: self.env.to_owned(),
+            kvasir: self.kvasir.to_owned(),
+            stats: self.stats.to_owned(),
+            drift: self.drift.to_owned(),
+            dir,
+            legacy: self.legacy.to_owned(),
+        }
+    }
+
+    /// Convert the context into a Tera context, without copying the parsed files.
+    pub fn into_tera(self) -> tera::Result<tera::Context> {
+        let mut context = Map::new();
+        if let Some(version) = self.schema_version {
+            context.insert("schemaVersion".to_string(), Value::from(version));
+        }
+        context.insert("files".to_string(), Value::Array(self.files));
+        context.insert("failures".to_string(), serde_json::to_value(self.failures)?);
+        context.insert("vars".to_string(), Value::Object(self.vars));
+        context.insert("env".to_string(), serde_json::to_value(self.env)?);
+        context.insert("kvasir".to_string(), serde_json::to_value(self.kvasir)?);
+        if let Some(stats) = self.stats {
+            context.insert("stats".to_string(), serde_json::to_value(stats)?);
+        }
+        if let Some(drift) = self.drift {
+            context.insert("drift".to_string(), serde_json::to_value(drift)?);
+        }
+        if let Some(dir) = self.dir {
+            context.insert("dir".to_string(), Value::String(dir));
+        }
+        if let Some(legacy) = self.legacy {
+            context.insert("profile".to_string(), serde_json::to_value(legacy.profile)?);
+        }
+        tera::Context::from_value(Value::Object(context))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::config::Config;
+    use crate::context::{FileContext, TemplateContext};
+    use crate::parsers::{ParseFailure, ParseSuccess};
+    use crate::report::OutputSchema;
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    fn successes() -> Vec<ParseSuccess> {
+        ["a.json", "b.json", "c.json"]
+            .iter()
+            .map(|path| ParseSuccess {
+                path: PathBuf::from(path),
+                parser: "json".to_string(),
+                index: None,
+                name: None,
+                contents: json!({"a": 1}),
+                descriptions: Default::default(),
+                metadata: Default::default(),
+                warnings: vec![],
+                tags: vec![],
+                owners: vec![],
+                duplicates: vec![],
+                external_refs: vec![],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn schema() {
+        let mut config: Config = toml::from_str("[vars]\nteam = \"platform\"").unwrap();
+        config.profile = Some("prod".to_string());
+        let mut successes = successes();
+        successes.truncate(1);
+        successes[0].tags = vec!["infra".to_string()];
+        successes[0].owners = vec!["@platform".to_string()];
+        let mut expected_files = successes.to_owned();
+        OutputSchema::V2.conform(&mut expected_files);
+        let expected_files = serde_json::to_value(&expected_files).unwrap();
+        let failures = vec![ParseFailure {
+            path: PathBuf::from("b.json"),
+            parser: "json".to_string(),
+            error: "invalid".into(),
+        }];
+
+        let context = TemplateContext::new(successes, &failures, &config, OutputSchema::V2);
+        let value = serde_json::to_value(&context).unwrap();
+
+        assert_eq!(value["files"], expected_files);
+        assert_eq!(
+            value["files"],
+            json!([{"path": "a.json", "parser": "json", "index": 0, "contents": {"a": 1}, "tags": ["infra"], "owners": ["@platform"]}])
+        );
+        assert_eq!(
+            value["failures"],
+            json!([{"path": "b.json", "parser": "json", "error": "invalid"}])
+        );
+        assert_eq!(value["vars"], json!({"team": "platform"}));
+        assert!(value["env"].is_object());
+        assert_eq!(value["kvasir"]["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(value["kvasir"]["profile"], "prod");
+        assert_eq!(value["schemaVersion"], 2);
+        assert!(value.get("profile").is_none());
+        assert!(value.get("file").is_none());
+
+        let file_value = serde_json::to_value(FileContext {
+            context: &context,
+            file: &context.files[0],
+        })
+        .unwrap();
+        assert_eq!(file_value["file"]["path"], "a.json");
+        assert_eq!(file_value["files"], expected_files);
+
+        assert_eq!(context.into_tera().unwrap().into_json(), value);
+
+        let legacy = TemplateContext::new(self::successes(), &[], &config, OutputSchema::V1);
+        let value = serde_json::to_value(&legacy).unwrap();
+        assert_eq!(
+            value["files"][0],
+            json!({"path": "a.json", "parser": "json", "contents": {"a": 1}})
+        );
+        assert_eq!(
+            value.as_object().unwrap().keys().collect::<Vec<_>>(),
+            ["files", "failures", "vars", "env", "kvasir", "profile"]
+        );
+        assert_eq!(value["profile"], "prod");
+        assert_eq!(legacy.into_tera().unwrap().into_json(), value);
+    }
+
+    #[test]
+    fn chunks() {
+        let context = TemplateContext::new(successes(), &[], &Config::default(), OutputSchema::V2);
+        let chunks = context.chunks(2);
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].files.len(), 2);
+        assert_eq!(chunks[1].files.len(), 1);
+        assert_eq!(chunks[1].files[0]["path"], "c.json");
+    }
+
+    #[test]
+    fn directories() {
+        let mut successes = successes();
+        successes[0].path = PathBuf::from("services/orders/a.json");
+        successes[2].path = PathBuf::from("services/orders/c.json");
+        let context = TemplateContext::new(successes, &[], &Config::default(), OutputSchema::V2);
+        let directories = context.directories();
+
+        assert_eq!(directories.len(), 2);
+        assert_eq!(directories[0].dir.as_deref(), Some("."));
+        assert_eq!(directories[0].files.len(), 1);
+        assert_eq!(directories[0].files[0]["path"], "b.json");
+        let value = directories
+            .into_iter()
+            .last()
+            .unwrap()
+            .into_tera()
+            .unwrap()
+            .into_json();
+        assert_eq!(value["dir"], "services/orders");
+        assert_eq!(value["files"][0]["path"], "services/orders/a.json");
+        assert_eq!(value["files"][1]["path"], "services/orders/c.json");
+    }
+
+    #[test]
+    fn stats() {
+        let context = TemplateContext::new(successes(), &[], &Config::default(), OutputSchema::V2);
+        assert!(serde_json::to_value(&context)
+            .unwrap()
+            .get("stats")
+            .is_none());
+
+        let chunks = context.with_stats(&["$.a".to_string()]).unwrap().chunks(2);
+        let value = serde_json::to_value(&chunks[1]).unwrap();
+        assert_eq!(value["stats"]["files"], 3);
+        assert_eq!(
+            value["stats"]["distinct"]["$.a"],
+            json!([{"value": 1, "count": 3}])
+        );
+    }
+}
@@ -0,0 +1,143 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Documentation coverage of parsed source files by split output files.
+//!
+//! A source file is documented if it is named in the `source` attribute of the header line
+//! of at least one split output file. Output files without a `source` attribute document
+//! no particular source file.
+
+use crate::errors::*;
+use crate::output::OutputFile;
+use crate::paths;
+use log::warn;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The name of the coverage report written to the output directory.
+pub const COVERAGE_FILE: &str = "kvasir-coverage.json";
+
+/// The documentation coverage of parsed source files.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Coverage {
+    pub sources: usize,
+    pub documented: usize,
+    pub percentage: f64,
+    pub undocumented: Vec<PathBuf>,
+}
+
+impl Coverage {
+    /// Calculate the coverage of parsed source files by split output files.
+    pub fn new(sources: &[PathBuf], outputs: &[OutputFile]) -> Coverage {
+        let referenced: BTreeSet<PathBuf> = outputs
+            .iter()
+            .filter_map(|o| o.attributes.get("source"))
+            .flat_map(|s| s.split(','))
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| paths::clean(Path::new(s)))
+            .collect();
+        let sources: BTreeSet<&PathBuf> = sources.iter().collect();
+        let undocumented: Vec<PathBuf> = sources
+            .iter()
+            .filter(|s| !referenced.contains(&paths::clean(s)))
+            .map(|s| s.to_path_buf())
+            .collect();
+        let documented = sources.len() - undocumented.len();
+        Coverage {
+            sources: sources.len(),
+            documented,
+            percentage: if sources.is_empty() {
+                100.0
+            } else {
+                documented as f64 * 100.0 / sources.len() as f64
+            },
+            undocumented,
+        }
+    }
+
+    /// Log the undocumented source files and a summary, returning an error if the coverage
+    /// is below the minimum percentage, if any.
+    pub fn report(&self, minimum: Option<f64>) -> Result<()> {
+        self.undocumented
+            .iter()
+            .for_each(|s| warn!("{} is not documented by any output file", s.display()));
+        crate::logging::summary(&format!(
+            "{} of {} source files documented ({:.1}%)",
+            self.documented, self.sources, self.percentage
+        ));
+        match minimum {
+            Some(minimum) if self.percentage < minimum => {
+                bail!(ErrorKind::ValidationFailure(format!(
+                    "Documentation coverage of {:.1}% is below the minimum of {}%",
+                    self.percentage, minimum
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Write the coverage as JSON to the output directory.
+    pub fn write(&self, output_dir: &Path) -> Result<PathBuf> {
+        let path = output_dir.join(COVERAGE_FILE);
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::coverage::Coverage;
+    use std::path::PathBuf;
+
+    #[test]
+    fn coverage() {
+        let outputs = crate::output::split_template_content(
+            "8<--",
+            "8<-- a.md source=./config/a.yaml,config/b.yaml\na\n8<-- index.md\nindex\n",
+            std::env::temp_dir(),
+        )
+        .unwrap();
+        let sources = [
+            "config/a.yaml",
+            "config/b.yaml",
+            "config/c.yaml",
+            "config/d.yaml",
+        ]
+        .map(PathBuf::from);
+
+        let coverage = Coverage::new(&sources, &outputs);
+        assert_eq!(coverage.sources, 4);
+        assert_eq!(coverage.documented, 2);
+        assert_eq!(coverage.percentage, 50.0);
+        assert_eq!(
+            coverage.undocumented,
+            [
+                PathBuf::from("config/c.yaml"),
+                PathBuf::from("config/d.yaml")
+            ]
+        );
+
+        assert!(coverage.report(None).is_ok());
+        assert!(coverage.report(Some(50.0)).is_ok());
+        assert!(coverage.report(Some(75.0)).is_err());
+        assert_eq!(Coverage::new(&[], &outputs).percentage, 100.0);
+    }
+}
@@ -0,0 +1,785 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! A long-running `kvasir daemon`, which parses source files and renders templates on
+//! request, so that editor plugins and other tools can query parsed structures without
+//! starting kvasir and parsing every source file for each query.
+//!
+//! Requests and responses are JSON-RPC 2.0 messages, one per line, sent over a Unix domain
+//! socket which only the daemon's user can connect to or, with a token, a TCP connection.
+//! Requests can read any file the daemon's user can read, so on TCP connections, which any
+//! local user can open, no other request is answered until an `authenticate` request has
+//! given the token. Each connection is served by its own thread, but requests are handled
+//! one at a time. The results of parsing each source file are cached for the life of the
+//! daemon, and are reused until the file or the parser options change.
+
+use crate::cache::ParseCache;
+use crate::config::Config;
+use crate::context::TemplateContext;
+use crate::errors::*;
+use crate::exit;
+use crate::parsers::{self, ParseFailure, ParseSuccess, ParserOptions};
+use crate::report::{OutputSchema, ParseReport};
+use crate::templates::engine::{Engine, Templates};
+use crate::templates::functions::FunctionOptions;
+use crate::templates::i18n::Translations;
+use crate::templates::limits::RenderLimits;
+use crate::templates::pages::PageOptions;
+use crate::{cancel, cli, logging, remote};
+use log::{debug, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::io::{BufRead, BufReader, ErrorKind as IoErrorKind, Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The TCP address on which the daemon listens by default on platforms without Unix domain
+/// sockets.
+#[cfg(not(unix))]
+const DEFAULT_ADDRESS: &str = "127.0.0.1:7979";
+
+/// How often the daemon checks whether it has been asked to stop while waiting for a
+/// connection.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The longest request the daemon reads, in bytes, so that a connection which has not
+/// authenticated cannot exhaust its memory.
+const MAX_REQUEST_BYTES: u64 = 16 * 1024 * 1024;
+
+/// The JSON-RPC error code for a request which is not valid JSON.
+pub const PARSE_ERROR: i64 = -32700;
+/// The JSON-RPC error code for a request which is not a JSON-RPC 2.0 request.
+const INVALID_REQUEST: i64 = -32600;
+/// The JSON-RPC error code for an unknown method.
+pub const METHOD_NOT_FOUND: i64 = -32601;
+/// The JSON-RPC error code for invalid parameters.
+const INVALID_PARAMS: i64 = -32602;
+/// The JSON-RPC error code for a request which kvasir could not complete, whose data holds
+/// the exit code that kvasir would have exited with.
+const SERVER_ERROR: i64 = -32000;
+/// The JSON-RPC error code for a request on a connection which has not authenticated, or an
+/// `authenticate` request with the wrong token.
+const UNAUTHORIZED: i64 = -32001;
+
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+/// Options of the daemon.
+#[derive(Debug, Default)]
+pub struct DaemonOptions {
+    /// The TCP address on which to listen, instead of a Unix domain socket.
+    pub listen: Option<String>,
+    /// The Unix domain socket on which to listen, instead of the default socket.
+    pub socket: Option<PathBuf>,
+    /// The token which connections must give before any other request, required on TCP.
+    pub token: Option<String>,
+    /// The maximum depth of templates referenced from the root template of a render.
+    pub max_include_depth: Option<usize>,
+    /// The maximum size in bytes of the output of a render.
+    pub max_output_size: Option<usize>,
+    /// The maximum time taken by a render.
+    pub render_timeout: Option<Duration>,
+}
+
+impl DaemonOptions {
+    /// Return the limits on rendering the templates of a request.
+    fn limits(&self) -> RenderLimits {
+        RenderLimits::new(
+            self.max_include_depth,
+            self.max_output_size,
+            self.render_timeout,
+        )
+    }
+}
+
+/// Return the Unix domain socket on which the daemon listens by default: `kvasir.sock` in
+/// the user's runtime directory, or a socket named after the user in the temporary
+/// directory.
+#[cfg(unix)]
+pub fn default_socket() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("kvasir.sock"),
+        None => std::env::temp_dir().join(format!(
+            "kvasir-{}.sock",
+            std::env::var("USER").unwrap_or_default()
+        )),
+    }
+}
+
+/// Read the daemon's token from a file, ignoring surrounding whitespace.
+pub fn read_token(path: &Path) -> Result<String> {
+    Ok(std::fs::read_to_string(path)
+        .chain_err(|| format!("Could not read the token file {}", path.display()))?
+        .trim()
+        .to_string())
+}
+
+/// The parameters of a request. Each method uses those it needs.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Params {
+    /// Glob expressions for source files, in addition to those in the configuration file.
+    sources: Vec<String>,
+    /// Tags applied to the source files matched by `sources`.
+    tags: Vec<String>,
+    /// A profile in the configuration file whose sources and variables are used.
+    profile: Option<String>,
+    /// Parser options in the form `parser.option=value`.
+    parser_options: Vec<String>,
+    /// Whether to parse each set of source files with the same content only once.
+    dedupe: bool,
+    /// A glob expression for the templates to render.
+    templates: Option<String>,
+    /// The template engine: `tera`, by default, or `handlebars`.
+    engine: Option<String>,
+    /// The root template, if more than one template is not referenced by another.
+    root_template: Option<String>,
+    /// The daemon's token, for `authenticate` requests.
+    token: Option<String>,
+}
+
+/// An error answering a request.
+#[derive(Debug)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+}
+
+impl From<Error> for RpcError {
+    fn from(error: Error) -> Self {
+        RpcError {
+            code: SERVER_ERROR,
+            message: error.to_string(),
+            data: Some(json!({"exit_code": exit::code(&error)})),
+        }
+    }
+}
+
+/// A connection to the daemon.
+trait Stream: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Stream for T {}
+
+/// Parse the source files of a request, returning the configuration with the requested
+/// profile active, and the results.
+fn parse(
+    config: &Config,
+    cache: &mut ParseCache,
+    params: &Params,
+) -> Result<(Config, Vec<ParseSuccess>, Vec<ParseFailure>)> {
+    let mut config = config.to_owned();
+    config.activate(params.profile.as_deref())?;
+    let options = ParserOptions::from_args(&params.parser_options)?;
+    let (mut successes, failures) = crate::parse_files(
+        crate::all_sources(&config, &params.sources, &params.tags)?,
+        &parsers::parsers_with_options(&options),
+        &options,
+        params.dedupe,
+        Some(cache),
+    )?;
+    crate::assign_owners(&config, &mut successes)?;
+    Ok((config, successes, failures))
+}
+
+/// Render the templates of a request with the context of its source files, returning the
+/// name of the root template and the output.
+fn render(
+    config: &Config,
+    options: &DaemonOptions,
+    cache: &mut ParseCache,
+    params: &Params,
+) -> Result<(String, String)> {
+    let Some(templates) = params.templates.as_deref() else {
+        bail!("The templates to render are required")
+    };
+    let engine = match params.engine.as_deref() {
+        Some(engine) => engine.parse::<Engine>()?,
+        None => Engine::Tera,
+    };
+    let (config, successes, failures) = parse(config, cache, params)?;
+    // Network access and commands are not allowed, as requests are not trusted
+    let function_options = FunctionOptions {
+        parser_options: ParserOptions::from_args(&params.parser_options)?,
+        http_options: Default::default(),
+        exec_options: Default::default(),
+        translations: Translations::load(&config.i18n, None)?,
+        page_options: PageOptions::new(&config.pages, None),
+    };
+    let loaded = Templates::load(engine, templates, &function_options)?;
+    let template = crate::get_base_template(
+        templates,
+        &loaded.dependencies(),
+        params.root_template.to_owned(),
+    )?;
+    let limits = options.limits();
+    limits.check_depth(&template, &loaded.dependencies())?;
+    let output = loaded.render(
+        &template,
+        TemplateContext::new(successes, &failures, &config, OutputSchema::DEFAULT),
+        &limits,
+    )?;
+    Ok((template, output))
+}
+
+/// Return whether a token is the daemon's token, comparing their hashes so that the time
+/// taken does not reveal how much of the token is correct.
+fn is_token(token: &str, expected: &str) -> bool {
+    Sha256::digest(token.as_bytes()) == Sha256::digest(expected.as_bytes())
+}
+
+/// Handle a request on a connection, returning its result. Requests other than
+/// `authenticate` are refused until the connection has authenticated, if the daemon has a
+/// token.
+fn handle(
+    config: &Config,
+    options: &DaemonOptions,
+    cache: &mut ParseCache,
+    authenticated: &mut bool,
+    method: &str,
+    params: Value,
+) -> std::result::Result<Value, RpcError> {
+    let params: Params = match params {
+        Value::Null => Params::default(),
+        params => serde_json::from_value(params)
+            .map_err(|e| RpcError::new(INVALID_PARAMS, format!("Invalid params: {}", e)))?,
+    };
+    if method == "authenticate" {
+        *authenticated = match (&options.token, &params.token) {
+            (None, _) => true,
+            (Some(expected), Some(token)) => is_token(token, expected),
+            (Some(_), None) => false,
+        };
+        return match authenticated {
+            true => Ok(Value::Bool(true)),
+            false => Err(RpcError::new(UNAUTHORIZED, "Invalid token")),
+        };
+    }
+    if !*authenticated {
+        return Err(RpcError::new(
+            UNAUTHORIZED,
+            "The connection must authenticate with the daemon's token first",
+        ));
+    }
+    for source in &params.sources {
+        cli::glob_expression(source).map_err(|e| RpcError::new(INVALID_PARAMS, e))?;
+    }
+    match method {
+        "parsers" => Ok(parsers::parsers()
+            .iter()
+            .map(|p| p.name())
+            .chain(
+                parsers::directory_parsers(&ParserOptions::default())
+                    .iter()
+                    .map(|p| p.name()),
+            )
+            .collect()),
+        "parse" => {
+            let started = Instant::now();
+            let remote_failures = remote::failures();
+            let (_, mut successes, failures) = parse(config, cache, &params)?;
+            OutputSchema::LATEST.conform(&mut successes);
+            let report = ParseReport::new(
+                &successes,
+                &failures,
+                remote::failures() - remote_failures,
+                started.elapsed(),
+                OutputSchema::LATEST,
+            );
+            Ok(serde_json::to_value(report).map_err(Error::from)?)
+        }
+        "context" => {
+            let (config, successes, failures) = parse(config, cache, &params)?;
+            let context = TemplateContext::new(successes, &failures, &config, OutputSchema::LATEST);
+            Ok(serde_json::to_value(context).map_err(Error::from)?)
+        }
+        "render" => {
+            let (template, output) = render(config, options, cache, &params)?;
+            Ok(json!({"template": template, "output": output}))
+        }
+        "shutdown" => {
+            SHUTDOWN.store(true, Ordering::SeqCst);
+            Ok(Value::Null)
+        }
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("Unknown method {}", method),
+        )),
+    }
+}
+
+/// Return a JSON-RPC error response.
+pub fn error_response(id: Value, error: RpcError) -> Value {
+    let mut response = json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {"code": error.code, "message": error.message},
+    });
+    if let Some(data) = error.data {
+        response["error"]["data"] = data;
+    }
+    response
+}
+
+/// Answer a line holding a request on a connection, returning the response, or nothing if
+/// the request is a notification, which has no `id`.
+fn answer(
+    config: &Config,
+    options: &DaemonOptions,
+    cache: &Mutex<ParseCache>,
+    authenticated: &mut bool,
+    line: &str,
+) -> Option<Value> {
+    let mut request: Value = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => {
+            let error = RpcError::new(PARSE_ERROR, format!("Invalid JSON: {}", e));
+            return Some(error_response(Value::Null, error));
+        }
+    };
+    let id = request.get("id").cloned();
+    let method = match (&request["jsonrpc"], request["method"].as_str()) {
+        (Value::String(version), Some(method)) if version == "2.0" => method.to_string(),
+        _ => {
+            let error = RpcError::new(INVALID_REQUEST, "Not a JSON-RPC 2.0 request");
+            return Some(error_response(id.unwrap_or_default(), error));
+        }
+    };
+    debug!("Handling {} request", method);
+    let result = {
+        // Requests are handled one at a time, each holding the cache of parsed files
+        let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+        handle(
+            config,
+            options,
+            &mut cache,
+            authenticated,
+            &method,
+            request["params"].take(),
+        )
+    };
+    let id = id?;
+    Some(match result {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(error) => error_response(id, error),
+    })
+}
+
+/// Answer the requests on a connection until it is closed.
+fn serve_connection(
+    config: &Config,
+    options: &DaemonOptions,
+    cache: &Mutex<ParseCache>,
+    stream: Box<dyn Stream>,
+) {
+    let mut authenticated = options.token.is_none();
+    let mut reader = BufReader::new(stream);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match (&mut reader)
+            .take(MAX_REQUEST_BYTES + 1)
+            .read_until(b'\n', &mut line)
+        {
+            Ok(0) => return,
+            Ok(_) => {}
+            Err(e) => return warn!("Could not read request: {}", e),
+        }
+        let too_long = line.len() as u64 > MAX_REQUEST_BYTES;
+        let response = match std::str::from_utf8(&line) {
+            // The rest of the request is not read, so the connection is closed once answered
+            _ if too_long => Some(error_response(
+                Value::Null,
+                RpcError::new(
+                    INVALID_REQUEST,
+                    format!(
+                        "Requests must not be longer than {} bytes",
+                        MAX_REQUEST_BYTES
+                    ),
+                ),
+            )),
+            Ok(line) if line.trim().is_empty() => continue,
+            Ok(line) => answer(config, options, cache, &mut authenticated, line.trim()),
+            Err(e) => Some(error_response(
+                Value::Null,
+                RpcError::new(PARSE_ERROR, format!("Invalid JSON: {}", e)),
+            )),
+        };
+        if let Some(response) = response {
+            let stream = reader.get_mut();
+            if let Err(e) = writeln!(stream, "{}", response).and_then(|_| stream.flush()) {
+                return warn!("Could not write response: {}", e);
+            }
+        }
+        if too_long {
+            return;
+        }
+    }
+}
+
+/// A socket on which the daemon listens for connections.
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixListener, PathBuf),
+}
+
+/// Bind a Unix domain socket which only its owner can connect to.
+///
+/// The socket is bound in a new directory which only its owner can reach, and moved into
+/// place once its permissions are restricted, so that no other user can connect first.
+#[cfg(unix)]
+fn bind_private(path: &Path) -> std::io::Result<std::os::unix::net::UnixListener> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let dir = path.with_file_name(format!(".{}.{}", name, std::process::id()));
+    std::fs::DirBuilder::new().mode(0o700).create(&dir)?;
+    let bound = dir.join("socket");
+    let listener = std::os::unix::net::UnixListener::bind(&bound).and_then(|listener| {
+        std::fs::set_permissions(&bound, std::fs::Permissions::from_mode(0o600))?;
+        std::fs::rename(&bound, path)?;
+        Ok(listener)
+    });
+    let _ = std::fs::remove_dir_all(&dir);
+    listener
+}
+
+impl Listener {
+    /// Listen on the TCP address or Unix domain socket of the options. A TCP address
+    /// requires a token, as any local user could otherwise make requests.
+    fn bind(options: &DaemonOptions) -> Result<Listener> {
+        if options.token.as_deref().is_some_and(str::is_empty) {
+            bail!("The daemon's token must not be empty")
+        }
+        #[cfg(not(unix))]
+        if options.socket.is_some() {
+            bail!("Unix domain sockets are not supported on this platform")
+        }
+        #[cfg(unix)]
+        if options.listen.is_none() {
+            let path = options.socket.to_owned().unwrap_or_else(default_socket);
+            // A socket left by a daemon which did not stop cleanly is replaced, but no other file
+            if let Ok(metadata) = std::fs::symlink_metadata(&path) {
+                use std::os::unix::fs::FileTypeExt;
+                if !metadata.file_type().is_socket() {
+                    bail!(
+                        "Could not listen on {}: path exists and is not a socket",
+                        path.display()
+                    )
+                }
+                if std::os::unix::net::UnixStream::connect(&path).is_err() {
+                    std::fs::remove_file(&path)?;
+                }
+            }
+            let listener = bind_private(&path)
+                .chain_err(|| format!("Could not listen on {}", path.display()))?;
+            listener.set_nonblocking(true)?;
+            return Ok(Listener::Unix(listener, path));
+        }
+        #[cfg(unix)]
+        let address = options.listen.as_deref().unwrap_or_default();
+        #[cfg(not(unix))]
+        let address = options.listen.as_deref().unwrap_or(DEFAULT_ADDRESS);
+        if options.token.is_none() {
+            bail!(
+                "Listening on a TCP address requires a token, with --token-file or KVASIR_DAEMON_TOKEN"
+            )
+        }
+        let listener =
+            TcpListener::bind(address).chain_err(|| format!("Could not listen on {}", address))?;
+        if !listener.local_addr()?.ip().is_loopback() {
+            warn!(
+                "Listening on {}, which is not a loopback address. Connections are not encrypted.",
+                address
+            );
+        }
+        listener.set_nonblocking(true)?;
+        Ok(Listener::Tcp(listener))
+    }
+
+    /// Return where the daemon is listening.
+    fn address(&self) -> String {
+        match self {
+            Listener::Tcp(listener) => listener
+                .local_addr()
+                .map(|a| a.to_string())
+                .unwrap_or_default(),
+            #[cfg(unix)]
+            Listener::Unix(_, path) => path.display().to_string(),
+        }
+    }
+
+    /// Accept a connection, if one is waiting.
+    fn accept(&self) -> std::io::Result<Box<dyn Stream>> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, _) = listener.accept()?;
+                stream.set_nonblocking(false)?;
+                Ok(Box::new(stream))
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener, _) => {
+                let (stream, _) = listener.accept()?;
+                stream.set_nonblocking(false)?;
+                Ok(Box::new(stream))
+            }
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Listener::Unix(_, path) = self {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Serve requests on the TCP address or Unix domain socket of the options, until a
+/// `shutdown` request is received or the daemon is interrupted.
+pub fn serve(config: Config, options: DaemonOptions) -> Result<()> {
+    let listener = Listener::bind(&options)?;
+    logging::summary(&format!(
+        "kvasir daemon listening on {}",
+        listener.address()
+    ));
+    let daemon = Arc::new((config, options, Mutex::new(ParseCache::default())));
+    while !SHUTDOWN.load(Ordering::SeqCst) && !cancel::cancelled() {
+        match listener.accept() {
+            Ok(stream) => {
+                let daemon = Arc::clone(&daemon);
+                std::thread::spawn(move || {
+                    serve_connection(&daemon.0, &daemon.1, &daemon.2, stream)
+                });
+            }
+            Err(e) if e.kind() == IoErrorKind::WouldBlock => std::thread::sleep(POLL_INTERVAL),
+            Err(e) => warn!("Could not accept connection: {}", e),
+        }
+    }
+    logging::summary("kvasir daemon stopped");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::cache::ParseCache;
+    use crate::config::Config;
+    use crate::daemon::{
+        answer, DaemonOptions, METHOD_NOT_FOUND, PARSE_ERROR, SERVER_ERROR, UNAUTHORIZED,
+    };
+    use crate::manifest;
+    use serde_json::{json, Value};
+    use std::fs;
+    use std::sync::Mutex;
+
+    #[test]
+    fn requests() {
+        let dir = std::env::temp_dir().join(format!("kvasir-daemon-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("orders.json");
+        fs::write(&source, r#"{"name": "orders"}"#).unwrap();
+        fs::write(dir.join("base.tpl"), "{{ files[0].contents.name }}").unwrap();
+        let cache = Mutex::new(ParseCache::default());
+        let config = Config::default();
+        let options = DaemonOptions::default();
+        let request = |method: &str, params: Value| {
+            let request = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+            answer(&config, &options, &cache, &mut true, &request.to_string()).unwrap()
+        };
+        let sources = json!([source]);
+
+        let parsed = request("parse", json!({"sources": sources}));
+        assert_eq!(parsed["id"], 1);
+        assert_eq!(parsed["result"]["files"][0]["contents"]["name"], "orders");
+        let hash = manifest::file_sha256(&source).unwrap();
+        assert!(cache.lock().unwrap().get(&source, &hash, &[]).is_some());
+        // Cached results are used until the file changes
+        fs::write(&source, r#"{"name": "payments"}"#).unwrap();
+        let rendered = request(
+            "render",
+            json!({"sources": sources, "templates": dir.join("*.tpl")}),
+        );
+        assert_eq!(
+            rendered["result"],
+            json!({"template": "base.tpl", "output": "payments"})
+        );
+        let context = request("context", json!({"sources": sources}));
+        assert_eq!(
+            context["result"]["files"][0]["contents"]["name"],
+            "payments"
+        );
+
+        let missing = request("parse", json!({"sources": [dir.join("*.yaml")]}));
+        assert_eq!(missing["error"]["code"], SERVER_ERROR);
+        assert_eq!(missing["error"]["data"]["exit_code"], 3);
+        assert_eq!(
+            request("lint", Value::Null)["error"]["code"],
+            METHOD_NOT_FOUND
+        );
+        assert_eq!(
+            request("parse", json!({"source": []}))["error"]["code"],
+            -32602
+        );
+        assert!(request("parsers", Value::Null)["result"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("gitconfig")));
+        assert_eq!(
+            answer(&config, &options, &cache, &mut true, "{").unwrap()["error"]["code"],
+            PARSE_ERROR
+        );
+        // Notifications are not answered
+        let notification = r#"{"jsonrpc": "2.0", "method": "parsers"}"#;
+        assert!(answer(&config, &options, &cache, &mut true, notification).is_none());
+
+        // Render requests are limited
+        let limited = DaemonOptions {
+            max_output_size: Some(4),
+            ..Default::default()
+        };
+        let request = json!({"jsonrpc": "2.0", "id": 1, "method": "render", "params": {"sources": sources, "templates": dir.join("*.tpl")}});
+        let rendered = answer(&config, &limited, &cache, &mut true, &request.to_string()).unwrap();
+        assert_eq!(rendered["error"]["code"], SERVER_ERROR);
+        assert!(rendered["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("maximum output size"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn authentication() {
+        let config = Config::default();
+        let options = DaemonOptions {
+            token: Some("s3cret".to_string()),
+            ..Default::default()
+        };
+        let cache = Mutex::new(ParseCache::default());
+        let mut authenticated = false;
+        let mut request = |method: &str, params: Value| {
+            let request = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+            answer(
+                &config,
+                &options,
+                &cache,
+                &mut authenticated,
+                &request.to_string(),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            request("parsers", Value::Null)["error"]["code"],
+            UNAUTHORIZED
+        );
+        assert_eq!(
+            request("authenticate", json!({"token": "secret"}))["error"]["code"],
+            UNAUTHORIZED
+        );
+        assert_eq!(
+            request("parsers", Value::Null)["error"]["code"],
+            UNAUTHORIZED
+        );
+        assert_eq!(
+            request("authenticate", json!({"token": "s3cret"}))["result"],
+            true
+        );
+        assert!(request("parsers", Value::Null)["result"].is_array());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn long_requests() {
+        use crate::daemon::{serve_connection, INVALID_REQUEST, MAX_REQUEST_BYTES};
+        use std::io::{BufRead, BufReader, Write};
+
+        let (mut client, server) = std::os::unix::net::UnixStream::pair().unwrap();
+        std::thread::spawn(move || {
+            let options = DaemonOptions {
+                token: Some("s3cret".to_string()),
+                ..Default::default()
+            };
+            let cache = Mutex::new(ParseCache::default());
+            serve_connection(&Config::default(), &options, &cache, Box::new(server))
+        });
+        // A request is rejected before the connection has authenticated
+        client
+            .write_all(&vec![b' '; MAX_REQUEST_BYTES as usize + 1])
+            .unwrap();
+        let mut reader = BufReader::new(&client);
+        let mut response = String::new();
+        reader.read_line(&mut response).unwrap();
+        let response: Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(response["error"]["code"], INVALID_REQUEST);
+        // The rest of the request is not read, so the connection is closed
+        assert_eq!(reader.read_line(&mut String::new()).unwrap(), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn listeners() {
+        use crate::daemon::Listener;
+        use std::os::unix::fs::PermissionsExt;
+
+        let socket = std::env::temp_dir().join(format!("kvasir-{}.sock", std::process::id()));
+        let options = DaemonOptions {
+            socket: Some(socket.to_owned()),
+            ..Default::default()
+        };
+        let listener = Listener::bind(&options).unwrap();
+        drop(listener);
+        // A socket left by a daemon which did not stop cleanly is replaced
+        std::os::unix::net::UnixListener::bind(&socket).unwrap();
+        let listener = Listener::bind(&options).unwrap();
+        let mode = fs::metadata(&socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        drop(listener);
+        assert!(!socket.exists());
+        // Only a socket is replaced, so that a mistyped path cannot delete a file
+        fs::write(&socket, "notes").unwrap();
+        assert!(Listener::bind(&options).is_err());
+        assert_eq!(fs::read_to_string(&socket).unwrap(), "notes");
+        fs::remove_file(&socket).unwrap();
+
+        // TCP connections can be made by any local user, so require a token
+        let options = DaemonOptions {
+            listen: Some("127.0.0.1:0".to_string()),
+            ..Default::default()
+        };
+        assert!(Listener::bind(&options).is_err());
+        let options = DaemonOptions {
+            token: Some("s3cret".to_string()),
+            ..options
+        };
+        assert!(Listener::bind(&options).is_ok());
+    }
+}
@@ -0,0 +1,199 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Drift between similar parsed files, written by `kvasir drift` and available to templates
+//! as `drift` with `--drift`.
+//!
+//! The files matched by each source glob, such as `envs/*/config.yaml`, are expected to have
+//! the same keys. For each glob and parser with at least two parsed documents, the report
+//! has the `glob`, the `parser`, the `files` compared and the `keys` which are present in
+//! some of the files but missing from others, each with the files it is `present` in and
+//! `missing` from. Keys are dotted paths, with `[]` standing for any element of an array, as
+//! in [`Stats`](crate::stats::Stats). A key is not reported when its parent is missing from
+//! the same files, so a missing section is reported once rather than for each of its keys.
+//!
+//! Documents after the first in a multi-document file are named with their index, e.g.
+//! `k8s/app.yaml#1`.
+
+use crate::stats::collect_keys;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+/// The drift between the files matched by a source glob and parsed by a parser.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Drift {
+    pub glob: String,
+    pub parser: String,
+    pub files: Vec<String>,
+    pub keys: Vec<DriftedKey>,
+}
+
+/// A key which is present in some of the compared files but missing from others.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DriftedKey {
+    pub key: String,
+    pub present: Vec<String>,
+    pub missing: Vec<String>,
+}
+
+/// Return the parent of a dotted key, if it has one.
+fn parent(key: &str) -> Option<&str> {
+    key.rsplit_once('.')
+        .map(|(parent, _)| parent.trim_end_matches("[]"))
+}
+
+/// Compare the keys of parsed files, as they appear in the template context, matched by
+/// each source glob, given with the paths it matched.
+pub fn drift(globs: &[(String, Vec<PathBuf>)], files: &[Value]) -> Vec<Drift> {
+    let mut reports = vec![];
+    for (glob, paths) in globs {
+        let paths: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+        // The keys of each document, by parser
+        let mut documents: BTreeMap<&str, Vec<(String, BTreeSet<String>)>> = BTreeMap::new();
+        for file in files {
+            let field = |name: &str| file.get(name).and_then(|v| v.as_str()).unwrap_or_default();
+            if !paths.iter().any(|p| p == field("path")) {
+                continue;
+            }
+            let name = match file.get("index").and_then(|i| i.as_u64()).unwrap_or(0) {
+                0 => field("path").to_string(),
+                index => format!("{}#{}", field("path"), index),
+            };
+            let mut keys = BTreeSet::new();
+            collect_keys(file.get("contents").unwrap_or(&Value::Null), "", &mut keys);
+            documents
+                .entry(field("parser"))
+                .or_default()
+                .push((name, keys));
+        }
+
+        for (parser, documents) in documents.into_iter().filter(|(_, d)| d.len() > 1) {
+            let all: BTreeSet<&String> = documents.iter().flat_map(|(_, keys)| keys).collect();
+            let missing_from = |key: &str| {
+                documents
+                    .iter()
+                    .filter(|(_, keys)| !keys.contains(key))
+                    .map(|(name, _)| name.to_owned())
+                    .collect::<Vec<_>>()
+            };
+            let keys = all
+                .into_iter()
+                .filter_map(|key| {
+                    let missing = missing_from(key);
+                    let reported = !missing.is_empty()
+                        && parent(key).is_none_or(|parent| missing_from(parent) != missing);
+                    reported.then(|| DriftedKey {
+                        key: key.to_owned(),
+                        present: documents
+                            .iter()
+                            .filter(|(name, _)| !missing.contains(name))
+                            .map(|(name, _)| name.to_owned())
+                            .collect(),
+                        missing,
+                    })
+                })
+                .collect();
+            reports.push(Drift {
+                glob: glob.to_owned(),
+                parser: parser.to_string(),
+                files: documents.into_iter().map(|(name, _)| name).collect(),
+                keys,
+            });
+        }
+    }
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::drift::{drift, Drift, DriftedKey};
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    #[test]
+    fn drift_between_files() {
+        let files = [
+            json!({
+                "path": "envs/dev/config.yaml",
+                "parser": "yaml",
+                "index": 0,
+                "contents": {"replicas": 1, "debug": true, "db": {"host": "localhost"}}
+            }),
+            json!({
+                "path": "envs/prod/config.yaml",
+                "parser": "yaml",
+                "index": 0,
+                "contents": {"replicas": 3, "alerts": [{"channel": "ops"}]}
+            }),
+            json!({
+                "path": "envs/test/config.yaml",
+                "parser": "yaml",
+                "index": 0,
+                "contents": {"replicas": 2, "db": {"host": "db", "port": 5432}}
+            }),
+            json!({"path": "app.yaml", "parser": "yaml", "index": 0, "contents": {"other": 1}}),
+        ];
+        let globs = [(
+            "envs/*/config.yaml".to_string(),
+            ["dev", "prod", "test"]
+                .iter()
+                .map(|e| PathBuf::from(format!("envs/{}/config.yaml", e)))
+                .collect(),
+        )];
+
+        let key = |key: &str, present: &[&str], missing: &[&str]| {
+            let names = |envs: &[&str]| {
+                envs.iter()
+                    .map(|e| format!("envs/{}/config.yaml", e))
+                    .collect()
+            };
+            DriftedKey {
+                key: key.to_string(),
+                present: names(present),
+                missing: names(missing),
+            }
+        };
+        assert_eq!(
+            drift(&globs, &files),
+            [Drift {
+                glob: "envs/*/config.yaml".to_string(),
+                parser: "yaml".to_string(),
+                files: vec![
+                    "envs/dev/config.yaml".to_string(),
+                    "envs/prod/config.yaml".to_string(),
+                    "envs/test/config.yaml".to_string(),
+                ],
+                keys: vec![
+                    key("alerts", &["prod"], &["dev", "test"]),
+                    key("db", &["dev", "test"], &["prod"]),
+                    key("db.port", &["test"], &["dev", "prod"]),
+                    key("debug", &["dev"], &["prod", "test"]),
+                ],
+            }]
+        );
+        assert_eq!(
+            serde_json::to_value(drift(&globs, &files)).unwrap()[0]["keys"][0],
+            json!({
+                "key": "alerts",
+                "present": ["envs/prod/config.yaml"],
+                "missing": ["envs/dev/config.yaml", "envs/test/config.yaml"]
+            })
+        );
+    }
+}
@@ -16,14 +16,40 @@
 
 //! Error chain providing a wrapper around several error types.
 error_chain! {
+    // Failures with their own exit code, see the `exit` module
+    errors {
+        NoSources(message: String) {
+            description("no source files matched")
+            display("{}", message)
+        }
+        ParseFailures(count: usize) {
+            description("source files could not be parsed")
+            display("{} source files could not be parsed", count)
+        }
+        TemplateFailure(message: String) {
+            description("templates could not be loaded or rendered")
+            display("{}", message)
+        }
+        OutputFailure(message: String) {
+            description("output files could not be written")
+            display("{}", message)
+        }
+        ValidationFailure(message: String) {
+            description("validation failed")
+            display("{}", message)
+        }
+    }
+
     foreign_links {
         Io(std::io::Error);
         JsonParse(serde_json::Error);
         YamlParse(serde_yaml::Error);
         TomlParse(toml::de::Error);
-        IniParse(serde_ini::de::Error);
         XmlParse(serde_xml_rs::Error);
         HoconParse(hocon::Error);
+        HclParse(hcl::Error);
         SqlError(sqlparser::parser::ParserError);
+        Base64(base64::DecodeError);
+        Template(tera::Error);
     }
 }
@@ -0,0 +1,92 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! The exit codes of kvasir, which distinguish why a run failed so that pipelines can
+//! branch on the cause.
+//!
+//! The codes are part of the command-line interface: a code is never reused for a different
+//! class of failure, and new classes of failure are given new codes.
+
+use crate::errors::*;
+
+/// The run succeeded.
+pub const SUCCESS: i32 = 0;
+/// The run failed for any other reason, e.g. an invalid configuration file or an I/O error.
+pub const ERROR: i32 = 1;
+/// The command-line arguments were invalid.
+pub const USAGE: i32 = 2;
+/// No source files matched the source globs.
+pub const NO_SOURCES: i32 = 3;
+/// Source files could not be parsed, with `--fail-on-parse-failures`.
+pub const PARSE_FAILURES: i32 = 4;
+/// Templates could not be loaded or rendered.
+pub const TEMPLATE_FAILURE: i32 = 5;
+/// Output files could not be written.
+pub const OUTPUT_FAILURE: i32 = 6;
+/// Validation failed: lint rules, output checks, spell-checking, coverage, secrets, template
+/// tests, signatures or the variable schema.
+pub const VALIDATION_FAILURE: i32 = 7;
+/// The run was cancelled with Ctrl-C or `SIGTERM`, as for a process interrupted by `SIGINT`.
+pub const CANCELLED: i32 = 130;
+
+/// Return the exit code for an error which stopped a run.
+pub fn code(error: &Error) -> i32 {
+    match error.kind() {
+        ErrorKind::NoSources(_) => NO_SOURCES,
+        ErrorKind::ParseFailures(_) => PARSE_FAILURES,
+        ErrorKind::TemplateFailure(_) | ErrorKind::Template(_) => TEMPLATE_FAILURE,
+        ErrorKind::OutputFailure(_) => OUTPUT_FAILURE,
+        ErrorKind::ValidationFailure(_) => VALIDATION_FAILURE,
+        _ => ERROR,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::errors::{Error, ErrorKind};
+    use crate::exit::{
+        code, CANCELLED, ERROR, NO_SOURCES, OUTPUT_FAILURE, PARSE_FAILURES, SUCCESS,
+        TEMPLATE_FAILURE, USAGE, VALIDATION_FAILURE,
+    };
+
+    #[test]
+    fn codes() {
+        // The codes are documented in the README and must not change
+        assert_eq!(
+            [
+                SUCCESS,
+                ERROR,
+                USAGE,
+                NO_SOURCES,
+                PARSE_FAILURES,
+                TEMPLATE_FAILURE,
+                OUTPUT_FAILURE,
+                VALIDATION_FAILURE,
+                CANCELLED
+            ],
+            [0, 1, 2, 3, 4, 5, 6, 7, 130]
+        );
+        assert_eq!(code(&Error::from("Invalid configuration")), ERROR);
+        assert_eq!(code(&ErrorKind::ParseFailures(2).into()), PARSE_FAILURES);
+        assert_eq!(
+            code(&ErrorKind::ValidationFailure("3 unknown words".into()).into()),
+            VALIDATION_FAILURE
+        );
+        let tera = tera::Tera::one_off("{{ missing }}", &tera::Context::new(), false);
+        assert_eq!(code(&tera.unwrap_err().into()), TEMPLATE_FAILURE);
+    }
+}
@@ -0,0 +1,150 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Versions of the split output kept side by side with `--output-version`.
+//!
+//! Each run writes its output into a subdirectory of the output directory named after a
+//! version, e.g. `v1.4.2`, or the time of generation, e.g. `2021-06-01T120000`, so that
+//! earlier versions of the generated documentation are kept. Each version directory holds a
+//! `.kvasir-version` file recording when it was written, which marks it as a version so
+//! that `--keep-versions` only ever removes directories written by kvasir, oldest first.
+
+use crate::clock;
+use crate::errors::*;
+use chrono::{DateTime, Utc};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// The name of the file marking a directory as a version of the output.
+pub const VERSION_FILE: &str = ".kvasir-version";
+
+/// A version directory within the output directory.
+#[derive(Debug, PartialEq)]
+pub struct Version {
+    pub path: PathBuf,
+    /// The time at which the version was last written.
+    pub written: Option<DateTime<Utc>>,
+}
+
+/// Return the name of a version directory, formatting any `strftime` specifiers in the
+/// name, e.g. `%Y-%m-%d`, with the time of generation.
+pub fn version_name(name: &str, time: &DateTime<Utc>) -> Result<String> {
+    let formatted = clock::format(time, name)
+        .ok_or_else(|| format!("Invalid output version '{}': invalid time format", name))?;
+    match Path::new(&formatted).components().collect::<Vec<_>>()[..] {
+        [Component::Normal(_)] if !formatted.contains(['/', '\\']) => Ok(formatted),
+        _ => bail!(format!(
+            "Invalid output version '{}': '{}' is not a directory name",
+            name, formatted
+        )),
+    }
+}
+
+/// Create the directory of a version within the output directory, marking it as a version
+/// written now, and return its path.
+pub fn create(output_dir: &Path, name: &str) -> Result<PathBuf> {
+    let path = output_dir.join(version_name(name, &clock::now())?);
+    fs::create_dir_all(&path)
+        .chain_err(|| format!("Could not create version directory {}", path.display()))?;
+    fs::write(path.join(VERSION_FILE), clock::timestamp())?;
+    Ok(path)
+}
+
+/// List the versions within the output directory, oldest first.
+pub fn versions(output_dir: &Path) -> Result<Vec<Version>> {
+    let mut versions = vec![];
+    for entry in fs::read_dir(output_dir)? {
+        let path = entry?.path();
+        if let Ok(written) = fs::read_to_string(path.join(VERSION_FILE)) {
+            versions.push(Version {
+                written: clock::parse(&written),
+                path,
+            });
+        }
+    }
+    versions.sort_by(|a, b| (a.written, &a.path).cmp(&(b.written, &b.path)));
+    Ok(versions)
+}
+
+/// Remove the oldest versions within the output directory, other than the current version,
+/// keeping the specified number of versions, and return the paths of those removed.
+pub fn prune(output_dir: &Path, keep: usize, current: &Path) -> Result<Vec<PathBuf>> {
+    let mut versions = versions(output_dir)?;
+    versions.retain(|v| v.path != current);
+    let count = versions.len().saturating_sub(keep.saturating_sub(1));
+    let mut removed = vec![];
+    for version in versions.into_iter().take(count) {
+        fs::remove_dir_all(&version.path)
+            .chain_err(|| format!("Could not remove version {}", version.path.display()))?;
+        removed.push(version.path);
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::history::{create, prune, version_name, versions, VERSION_FILE};
+    use chrono::{TimeZone, Utc};
+    use std::fs;
+
+    #[test]
+    fn names() {
+        let time = Utc.with_ymd_and_hms(2021, 6, 1, 12, 30, 0).unwrap();
+        assert_eq!(version_name("v1.4.2", &time).unwrap(), "v1.4.2");
+        assert_eq!(
+            version_name("%Y-%m-%dT%H%M%S", &time).unwrap(),
+            "2021-06-01T123000"
+        );
+        for invalid in ["..", "a/b", "%Y/%m", "", "%Q"] {
+            assert!(version_name(invalid, &time).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn retention() {
+        let dir = std::env::temp_dir().join(format!("kvasir-history-{}", std::process::id()));
+        fs::create_dir_all(dir.join("assets")).unwrap();
+        for (name, written) in [
+            ("v1", "2021-06-01T00:00:00Z"),
+            ("v3", "2021-06-03T00:00:00Z"),
+            ("v2", "2021-06-02T00:00:00Z"),
+        ] {
+            fs::create_dir_all(dir.join(name)).unwrap();
+            fs::write(dir.join(name).join(VERSION_FILE), written).unwrap();
+        }
+        let current = create(&dir, "v4").unwrap();
+        assert_eq!(current, dir.join("v4"));
+        assert_eq!(
+            versions(&dir)
+                .unwrap()
+                .iter()
+                .map(|v| v.path.to_owned())
+                .collect::<Vec<_>>(),
+            ["v1", "v2", "v3", "v4"].map(|v| dir.join(v))
+        );
+
+        // The current version is kept, and directories which are not versions are ignored
+        assert_eq!(
+            prune(&dir, 2, &current).unwrap(),
+            [dir.join("v1"), dir.join("v2")]
+        );
+        assert!(dir.join("assets").is_dir() && dir.join("v3").is_dir() && current.is_dir());
+        assert!(prune(&dir, 2, &current).unwrap().is_empty());
+        assert_eq!(prune(&dir, 1, &current).unwrap(), [dir.join("v3")]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,200 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Checking of parsed files against the rules defined in the configuration file.
+//!
+//! ```toml
+//! [[rules]]
+//! name = "no-root"
+//! select = "$..securityContext[?(@.runAsUser == 0)]"
+//! message = "Containers must not run as root"
+//! ```
+
+use crate::config::{Rule, Severity};
+use crate::errors::*;
+use crate::parsers::ParseSuccess;
+use log::{error, info, warn};
+use serde::Serialize;
+use serde_json::Value;
+use std::path::PathBuf;
+
+/// A value within a parsed file which violates a rule.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Violation {
+    pub rule: String,
+    pub severity: Severity,
+    pub message: String,
+    pub path: PathBuf,
+    pub parser: String,
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub value: Value,
+}
+
+/// Check parsed files against rules, returning every violation in the order of the files
+/// and then of the rules.
+pub fn check(rules: &[Rule], files: &[ParseSuccess]) -> Result<Vec<Violation>> {
+    let mut violations = vec![];
+    for file in files {
+        for rule in rules
+            .iter()
+            .filter(|r| r.parser.as_ref().is_none_or(|p| p == &file.parser))
+        {
+            let selected = jsonpath_lib::select(&file.contents, &rule.select).map_err(|e| {
+                format!(
+                    "Invalid JSONPath expression {} in rule {}: {}",
+                    rule.select, rule.name, e
+                )
+            })?;
+            violations.extend(
+                selected
+                    .into_iter()
+                    .filter(|v| {
+                        rule.require.is_empty()
+                            || rule
+                                .require
+                                .iter()
+                                .any(|k| v.get(k).is_none_or(|v| v.is_null()))
+                    })
+                    .map(|v| Violation {
+                        rule: rule.name.to_owned(),
+                        severity: rule.severity,
+                        message: rule.message.to_owned(),
+                        path: file.path.to_owned(),
+                        parser: file.parser.to_owned(),
+                        index: file.index.unwrap_or_default(),
+                        name: file.name.to_owned(),
+                        value: v.to_owned(),
+                    }),
+            );
+        }
+    }
+    Ok(violations)
+}
+
+/// Log each violation at the level of its severity, with a summary, returning an error if
+/// any violation has `error` severity.
+pub fn report(violations: &[Violation]) -> Result<()> {
+    for v in violations {
+        let file = match &v.name {
+            Some(name) => format!("{} ({})", v.path.display(), name),
+            None => v.path.display().to_string(),
+        };
+        match v.severity {
+            Severity::Error => error!("{}: {} [{}]", file, v.message, v.rule),
+            Severity::Warning => warn!("{}: {} [{}]", file, v.message, v.rule),
+            Severity::Info => info!("{}: {} [{}]", file, v.message, v.rule),
+        }
+    }
+
+    let errors = violations
+        .iter()
+        .filter(|v| v.severity == Severity::Error)
+        .count();
+    crate::logging::summary(&format!(
+        "{} violations, {} errors",
+        violations.len(),
+        errors
+    ));
+    if errors > 0 {
+        bail!(ErrorKind::ValidationFailure(format!(
+            "{} rule violations with error severity",
+            errors
+        )))
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::config::{Config, Severity};
+    use crate::lint::{check, report};
+    use crate::parsers::ParseSuccess;
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    fn file(parser: &str, contents: serde_json::Value) -> ParseSuccess {
+        ParseSuccess {
+            path: PathBuf::from(format!("{}.yaml", parser)),
+            parser: parser.to_string(),
+            index: None,
+            name: None,
+            contents,
+            descriptions: Default::default(),
+            metadata: Default::default(),
+            warnings: vec![],
+            tags: vec![],
+            owners: vec![],
+            duplicates: vec![],
+            external_refs: vec![],
+        }
+    }
+
+    #[test]
+    fn rules() {
+        let config: Config = toml::from_str(
+            r#"
+            [[rules]]
+            name = "no-root"
+            select = "$..securityContext[?(@.runAsUser == 0)]"
+            message = "Containers must not run as root"
+
+            [[rules]]
+            name = "description"
+            parser = "openapi"
+            select = "$.paths.*.*"
+            require = ["description"]
+            severity = "warning"
+            message = "Operations must have a description"
+            "#,
+        )
+        .unwrap();
+        let files = [
+            file(
+                "yaml",
+                json!({"containers": [
+                    {"securityContext": {"runAsUser": 0}},
+                    {"securityContext": {"runAsUser": 1000}}
+                ]}),
+            ),
+            file(
+                "openapi",
+                json!({"paths": {"/pets": {
+                    "get": {"description": "List pets"},
+                    "post": {"summary": "Add a pet"}
+                }}}),
+            ),
+        ];
+
+        let violations = check(&config.rules, &files).unwrap();
+        assert_eq!(
+            violations
+                .iter()
+                .map(|v| (v.rule.as_str(), v.severity, v.path.to_str().unwrap()))
+                .collect::<Vec<_>>(),
+            [
+                ("no-root", Severity::Error, "yaml.yaml"),
+                ("description", Severity::Warning, "openapi.yaml")
+            ]
+        );
+        assert_eq!(violations[1].value, json!({"summary": "Add a pet"}));
+
+        assert!(report(&violations).is_err());
+        assert!(report(&violations[1..]).is_ok());
+    }
+}
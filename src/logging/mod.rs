@@ -0,0 +1,102 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Logging and diagnostic output, which is always written to `stderr` so that `stdout`
+//! only contains the output of a command.
+
+use clap::ValueEnum;
+use env_logger::{Env, Target};
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// When to use colour in diagnostic output.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ColorChoice {
+    /// Use colour when `stderr` is a terminal, unless `NO_COLOR` is set, so that logs
+    /// collected from containers and CI jobs are plain text.
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Return the equivalent `env_logger` write style.
+    fn write_style(self) -> &'static str {
+        match self {
+            ColorChoice::Auto
+                if std::env::var_os("NO_COLOR").is_some() || !std::io::stderr().is_terminal() =>
+            {
+                "never"
+            }
+            ColorChoice::Auto => "auto",
+            ColorChoice::Always => "always",
+            ColorChoice::Never => "never",
+        }
+    }
+}
+
+/// Return the log level for the requested verbosity: only errors when quiet, otherwise
+/// warnings, increasing with each `-v`.
+fn level(quiet: bool, verbose: u8) -> &'static str {
+    match (quiet, verbose) {
+        (true, _) => "error",
+        (false, 0) => "warn",
+        (false, 1) => "info",
+        (false, 2) => "debug",
+        (false, _) => "trace",
+    }
+}
+
+/// Return the logging environment. `KVASIR_LOG` and `KVASIR_LOG_STYLE` override the
+/// verbosity and colour options.
+fn environment(quiet: bool, verbose: u8, color: ColorChoice) -> Env<'static> {
+    Env::new()
+        .filter_or("KVASIR_LOG", format!("kvasir={}", level(quiet, verbose)))
+        .write_style_or("KVASIR_LOG_STYLE", color.write_style())
+}
+
+/// Initialise the logger, writing to `stderr`.
+pub fn init(quiet: bool, verbose: u8, color: ColorChoice) {
+    QUIET.store(quiet, Ordering::SeqCst);
+    env_logger::Builder::from_env(environment(quiet, verbose, color))
+        .target(Target::Stderr)
+        .init();
+}
+
+/// Write a summary line to `stderr`, unless `--quiet` was given.
+pub fn summary(message: &str) {
+    if !QUIET.load(Ordering::SeqCst) {
+        eprintln!("{}", message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::logging::{level, ColorChoice};
+
+    #[test]
+    fn levels() {
+        assert_eq!(level(true, 2), "error");
+        assert_eq!(level(false, 0), "warn");
+        assert_eq!(level(false, 1), "info");
+        assert_eq!(level(false, 2), "debug");
+        assert_eq!(level(false, 5), "trace");
+        assert_eq!(ColorChoice::Never.write_style(), "never");
+    }
+}
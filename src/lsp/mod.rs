@@ -0,0 +1,779 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! A Language Server Protocol server for kvasir templates, started by an editor with
+//! `kvasir lsp`, which completes the fields of the template context and the filters and
+//! functions available to templates, and reports errors loading or rendering the templates
+//! being edited.
+//!
+//! The server communicates over `stdin` and `stdout` with JSON-RPC messages framed by
+//! `Content-Length` headers. The source files are parsed once, when the server starts, and
+//! each open template is rendered with their context whenever it changes, including its
+//! unsaved contents, so that errors are reported as the template is written.
+
+use crate::capabilities;
+use crate::config::Config;
+use crate::context::TemplateContext;
+use crate::daemon::{self, RpcError, METHOD_NOT_FOUND, PARSE_ERROR};
+use crate::errors::*;
+use crate::parsers::{ParseFailure, ParseSuccess, ParserOptions};
+use crate::report::OutputSchema;
+use crate::templates::engine::{self, Engine, Templates};
+use crate::templates::functions::FunctionOptions;
+use crate::templates::i18n::Translations;
+use crate::templates::limits::RenderLimits;
+use crate::templates::pages::PageOptions;
+use log::{debug, warn};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// The filters built into Tera.
+const TERA_FILTERS: &[&str] = &[
+    "abs",
+    "addslashes",
+    "as_str",
+    "capitalize",
+    "concat",
+    "date",
+    "default",
+    "escape",
+    "escape_xml",
+    "filesizeformat",
+    "filter",
+    "first",
+    "float",
+    "get",
+    "group_by",
+    "indent",
+    "int",
+    "join",
+    "json_encode",
+    "last",
+    "length",
+    "linebreaksbr",
+    "lower",
+    "map",
+    "nth",
+    "pluralize",
+    "replace",
+    "reverse",
+    "round",
+    "safe",
+    "slice",
+    "slugify",
+    "sort",
+    "spaceless",
+    "split",
+    "striptags",
+    "title",
+    "trim",
+    "trim_end",
+    "trim_end_matches",
+    "trim_start",
+    "trim_start_matches",
+    "truncate",
+    "unique",
+    "upper",
+    "urlencode",
+    "urlencode_strict",
+    "wordcount",
+];
+
+/// The functions built into Tera, other than `now`, which kvasir replaces.
+const TERA_FUNCTIONS: &[&str] = &["get_env", "get_random", "range", "throw"];
+
+/// The LSP completion item kind of functions and filters.
+const FUNCTION: u8 = 3;
+/// The LSP completion item kind of fields of the context.
+const FIELD: u8 = 5;
+/// The LSP completion item kind of top-level context keys and template variables.
+const VARIABLE: u8 = 6;
+
+/// The LSP diagnostic severity of errors.
+const SEVERITY_ERROR: u8 = 1;
+
+/// The templates served, and how they are rendered.
+#[derive(Debug, Clone)]
+pub struct LspOptions {
+    /// A glob expression for the templates.
+    pub templates: String,
+    pub engine: Engine,
+    /// Whether templates are rendered once for each parsed file, with the file as `file`.
+    pub per_file: bool,
+    pub parser_options: ParserOptions,
+}
+
+/// The state of the server.
+pub struct Server {
+    options: LspOptions,
+    config: Config,
+    successes: Vec<ParseSuccess>,
+    failures: Vec<ParseFailure>,
+    function_options: FunctionOptions,
+    /// The template context, as JSON, from which fields are completed.
+    context: Value,
+    /// The contents of the open templates, keyed by URI.
+    documents: BTreeMap<String, String>,
+    shutdown: bool,
+    exited: bool,
+}
+
+/// A variable set in a template, such as a loop variable, and the expression it is set from.
+struct Variable {
+    expression: String,
+    /// Whether the variable holds each element of the expression in turn.
+    each: bool,
+}
+
+/// Return the path of a `file` URI.
+fn uri_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let mut bytes = vec![];
+    let mut chars = path.bytes();
+    while let Some(b) = chars.next() {
+        match b {
+            b'%' => {
+                let hex = [chars.next()?, chars.next()?];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            b => bytes.push(b),
+        }
+    }
+    let path = String::from_utf8(bytes).ok()?;
+    // Windows paths such as `/C:/templates/base.tpl`
+    let path = match path.get(2..3) {
+        Some(":") => path.trim_start_matches('/').to_string(),
+        _ => path,
+    };
+    Some(PathBuf::from(path))
+}
+
+/// Return the element of a value that a loop over it sets its variable to, taking the
+/// first element of an array and the first value of an object.
+fn element(value: &Value) -> Option<&Value> {
+    match value {
+        Value::Array(values) => values.first(),
+        Value::Object(map) => map.values().next(),
+        _ => None,
+    }
+}
+
+/// Describe the type of a value.
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(_) => "boolean".to_string(),
+        Value::Number(_) => "number".to_string(),
+        Value::String(_) => "string".to_string(),
+        Value::Array(values) => format!("array of {}", values.len()),
+        Value::Object(map) => format!("object with {} keys", map.len()),
+    }
+}
+
+/// Return the variables set in a Tera template by `for` loops and `set` tags, keyed by name.
+/// The `file` rendered with `--per-file` is always available.
+fn variables(text: &str) -> HashMap<String, Variable> {
+    let mut variables = HashMap::from([(
+        "file".to_string(),
+        Variable {
+            expression: "files".to_string(),
+            each: true,
+        },
+    )]);
+    for tag in text.split("{%").skip(1) {
+        let tag = tag.split("%}").next().unwrap_or_default();
+        let tag = tag.trim_matches(|c: char| c == '-' || c.is_whitespace());
+        let (name, expression, each) = if let Some(rest) = tag.strip_prefix("for ") {
+            let Some((names, expression)) = rest.split_once(" in ") else {
+                continue;
+            };
+            // The value of `for key, value in object`
+            (names.rsplit(',').next().unwrap_or(names), expression, true)
+        } else if let Some(rest) = tag
+            .strip_prefix("set ")
+            .or_else(|| tag.strip_prefix("set_global "))
+        {
+            let Some((name, expression)) = rest.split_once('=') else {
+                continue;
+            };
+            (name, expression, false)
+        } else {
+            continue;
+        };
+        let expression = expression.split('|').next().unwrap_or_default().trim();
+        variables.insert(
+            name.trim().to_string(),
+            Variable {
+                expression: expression.to_string(),
+                each,
+            },
+        );
+    }
+    variables
+}
+
+/// Convert a number of characters from the start of a line to UTF-16 code units, in which
+/// LSP positions are measured.
+fn utf16_offset(line: &str, characters: usize) -> usize {
+    line.chars().take(characters).map(char::len_utf16).sum()
+}
+
+/// Convert a number of UTF-16 code units from the start of a line to characters.
+fn char_offset(line: &str, units: usize) -> usize {
+    let mut total = 0;
+    line.chars()
+        .take_while(|c| {
+            total += c.len_utf16();
+            total <= units
+        })
+        .count()
+}
+
+/// Return the zero-based line and UTF-16 character of an error in a template: the position
+/// given by Tera or Handlebars, such as `--> 3:5`, or that of the first name quoted in the
+/// error, such as a variable which is not in the context.
+fn position(message: &str, text: &str) -> (usize, usize) {
+    let located = message.split_once("-->").and_then(|(_, rest)| {
+        let location = rest.lines().next().unwrap_or_default().trim();
+        let mut parts = location.rsplit(':').map(|p| p.trim().parse::<usize>().ok());
+        match (parts.next(), parts.next()) {
+            (Some(Some(character)), Some(Some(line))) => {
+                Some((line.saturating_sub(1), character.saturating_sub(1)))
+            }
+            _ => None,
+        }
+    });
+    let (line, character) = located
+        .or_else(|| {
+            let name = message.split('`').nth(1).filter(|name| !name.is_empty())?;
+            text.lines().enumerate().find_map(|(number, line)| {
+                line.find(name).map(|i| (number, line[..i].chars().count()))
+            })
+        })
+        .unwrap_or((0, 0));
+    let character = text
+        .lines()
+        .nth(line)
+        .map_or(character, |text| utf16_offset(text, character));
+    (line, character)
+}
+
+/// Read the body of a message, returning nothing at the end of the input.
+fn read_message(reader: &mut impl BufRead) -> Result<Option<Vec<u8>>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = Some(
+                    value
+                        .trim()
+                        .parse::<usize>()
+                        .chain_err(|| format!("Invalid Content-Length {}", value.trim()))?,
+                );
+            }
+        }
+    }
+    let length = length.ok_or("Message without a Content-Length header")?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+/// Write a message.
+fn write_message(writer: &mut impl Write, message: &Value) -> Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()?;
+    Ok(())
+}
+
+impl Server {
+    /// Create a server for templates rendered with the context of the given parsed files.
+    pub fn new(
+        options: LspOptions,
+        config: Config,
+        successes: Vec<ParseSuccess>,
+        failures: Vec<ParseFailure>,
+    ) -> Result<Server> {
+        // Network access and commands are not allowed, as templates are rendered as they
+        // are typed
+        let function_options = FunctionOptions {
+            parser_options: options.parser_options.to_owned(),
+            http_options: Default::default(),
+            exec_options: Default::default(),
+            translations: Translations::load(&config.i18n, None)?,
+            page_options: PageOptions::new(&config.pages, None),
+        };
+        let context = serde_json::to_value(TemplateContext::new(
+            successes.to_owned(),
+            &failures,
+            &config,
+            OutputSchema::DEFAULT,
+        ))?;
+        Ok(Server {
+            options,
+            config,
+            successes,
+            failures,
+            function_options,
+            context,
+            documents: BTreeMap::new(),
+            shutdown: false,
+            exited: false,
+        })
+    }
+
+    /// Return the name of the template at a URI, if it is matched by the templates glob.
+    fn template_name(&self, uri: &str) -> Option<String> {
+        let path = uri_path(uri)?;
+        let dir = engine::template_dir(&self.options.templates);
+        let pattern = glob::Pattern::new(&self.options.templates[dir.len()..]).ok()?;
+        let dir = match dir {
+            "" => Path::new("."),
+            dir => Path::new(dir),
+        };
+        let dir = dir.canonicalize().ok()?;
+        let path = path.canonicalize().unwrap_or(path);
+        let name = path
+            .strip_prefix(&dir)
+            .ok()?
+            .to_string_lossy()
+            .replace('\\', "/");
+        pattern.matches(&name).then_some(name)
+    }
+
+    /// Load the templates, with the contents of those which are open, and render a template.
+    fn render(&self, name: &str) -> Result<()> {
+        let mut sources = engine::read_templates(&self.options.templates)?;
+        for (uri, text) in &self.documents {
+            if let Some(name) = self.template_name(uri) {
+                sources.insert(name, text.to_owned());
+            }
+        }
+        let templates =
+            Templates::from_sources(self.options.engine, sources, &self.function_options)?;
+        let context = TemplateContext::new(
+            self.successes.to_owned(),
+            &self.failures,
+            &self.config,
+            OutputSchema::DEFAULT,
+        );
+        let limits = RenderLimits::default();
+        match self.options.per_file {
+            true => templates
+                .render_each_file(name, context, &limits)
+                .into_iter()
+                .find_map(|r| r.err())
+                .map_or(Ok(()), Err),
+            false => templates.render(name, context, &limits).map(|_| ()),
+        }
+    }
+
+    /// Return the notification publishing the diagnostics of the template at a URI, if it
+    /// is one of the templates.
+    fn diagnostics(&self, uri: &str) -> Option<Value> {
+        let name = self.template_name(uri)?;
+        let text = self.documents.get(uri).map_or("", String::as_str);
+        let diagnostics = match self.render(&name) {
+            Ok(_) => vec![],
+            Err(e) => {
+                let message = e
+                    .iter()
+                    .map(|e| e.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                // Errors in other templates, such as a partial, are reported at the start
+                let (line, character) = match message.contains(&name) {
+                    true => position(&message, text),
+                    false => (0, 0),
+                };
+                vec![json!({
+                    "range": {
+                        "start": {"line": line, "character": character},
+                        "end": {"line": line, "character": character + 1},
+                    },
+                    "severity": SEVERITY_ERROR,
+                    "source": "kvasir",
+                    "message": message,
+                })]
+            }
+        };
+        Some(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": {"uri": uri, "diagnostics": diagnostics},
+        }))
+    }
+
+    /// Return the value of a path in the context, such as `file.contents.spec`, where the
+    /// path may start with a variable set in the template, and arrays are indexed with
+    /// `[0]` or `.0`.
+    fn resolve(
+        &self,
+        path: &str,
+        variables: &HashMap<String, Variable>,
+        depth: usize,
+    ) -> Option<Value> {
+        let mut segments = path.split('.');
+        let first = segments.next()?;
+        let (name, indexes) = first.split_once('[').unwrap_or((first, ""));
+        let mut value = match (self.context.get(name), variables.get(name)) {
+            (Some(value), _) => value.to_owned(),
+            // Variables may be set from other variables, but not from themselves
+            (None, Some(variable)) if depth < 10 => {
+                let value = self.resolve(&variable.expression, variables, depth + 1)?;
+                match variable.each {
+                    true => element(&value)?.to_owned(),
+                    false => value,
+                }
+            }
+            _ => return None,
+        };
+        for index in indexes.split('[').filter(|i| !i.is_empty()) {
+            value = value
+                .get(index.trim_end_matches(']').parse::<usize>().ok()?)?
+                .to_owned();
+        }
+        for segment in segments {
+            let (key, indexes) = segment.split_once('[').unwrap_or((segment, ""));
+            value = match key.parse::<usize>() {
+                Ok(i) => value.get(i)?.to_owned(),
+                Err(_) => value.get(key)?.to_owned(),
+            };
+            for index in indexes.split('[').filter(|i| !i.is_empty()) {
+                value = value
+                    .get(index.trim_end_matches(']').parse::<usize>().ok()?)?
+                    .to_owned();
+            }
+        }
+        Some(value)
+    }
+
+    /// Return the completion items at a position in the template at a URI, where the
+    /// character is measured in UTF-16 code units.
+    fn completions(&self, uri: &str, line: usize, character: usize) -> Vec<Value> {
+        let text = self.documents.get(uri).map_or("", String::as_str);
+        let line = text.lines().nth(line).unwrap_or_default();
+        let prefix: String = line.chars().take(char_offset(line, character)).collect();
+        let start = prefix
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !(c.is_alphanumeric() || matches!(c, '_' | '.' | '[' | ']')))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let word = &prefix[start..];
+        let item = |label: &str, kind: u8, detail: String| json!({"label": label, "kind": kind, "detail": detail});
+        let tera = self.options.engine == Engine::Tera;
+
+        let items: Vec<Value> = if prefix[..start].trim_end().ends_with('|') {
+            let builtin = TERA_FILTERS.iter().filter(|_| tera);
+            capabilities::filters()
+                .into_iter()
+                .map(|f| item(f.name, FUNCTION, f.signature))
+                .chain(builtin.map(|f| item(f, FUNCTION, "Tera filter".to_string())))
+                .collect()
+        } else if let Some((path, _)) = word.rsplit_once('.') {
+            let variables = variables(text);
+            match self.resolve(path, &variables, 0) {
+                Some(Value::Object(map)) => map
+                    .iter()
+                    .map(|(key, value)| item(key, FIELD, describe(value)))
+                    .collect(),
+                _ => vec![],
+            }
+        } else {
+            let variables = variables(text);
+            let context = self.context.as_object().into_iter().flatten();
+            let builtin = TERA_FUNCTIONS.iter().filter(|_| tera);
+            let helpers = capabilities::functions()
+                .into_iter()
+                .chain(capabilities::filters().into_iter().filter(|_| !tera));
+            context
+                .map(|(key, value)| item(key, VARIABLE, describe(value)))
+                .chain(
+                    variables
+                        .iter()
+                        .filter(|(name, _)| self.context.get(name.as_str()).is_none())
+                        .map(|(name, v)| item(name, VARIABLE, v.expression.to_owned())),
+                )
+                .chain(helpers.map(|f| item(f.name, FUNCTION, f.signature)))
+                .chain(builtin.map(|f| item(f, FUNCTION, "Tera function".to_string())))
+                .collect()
+        };
+        let partial = word.rsplit('.').next().unwrap_or_default();
+        items
+            .into_iter()
+            .filter(|i| i["label"].as_str().is_some_and(|l| l.starts_with(partial)))
+            .collect()
+    }
+
+    /// Handle the body of a message, answering a body which is not JSON with an error.
+    fn handle_body(&mut self, body: &[u8]) -> Vec<Value> {
+        match serde_json::from_slice(body) {
+            Ok(message) => self.handle(message),
+            Err(e) => {
+                warn!("Ignoring a message which is not valid JSON: {}", e);
+                let error = RpcError::new(PARSE_ERROR, format!("Invalid JSON: {}", e));
+                vec![daemon::error_response(Value::Null, error)]
+            }
+        }
+    }
+
+    /// Handle a request or notification, returning the response, if it is a request, and
+    /// any notifications to send.
+    pub fn handle(&mut self, message: Value) -> Vec<Value> {
+        let id = message.get("id").cloned();
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        debug!("Handling {}", method);
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": {"openClose": true, "change": 1, "save": {"includeText": true}},
+                    "completionProvider": {"triggerCharacters": [".", "|"]},
+                },
+                "serverInfo": {"name": "kvasir", "version": env!("CARGO_PKG_VERSION")},
+            }),
+            "shutdown" => {
+                self.shutdown = true;
+                Value::Null
+            }
+            "exit" => {
+                self.exited = true;
+                return vec![];
+            }
+            "textDocument/didOpen" | "textDocument/didChange" | "textDocument/didSave" => {
+                let text = match method {
+                    "textDocument/didChange" => params["contentChanges"]
+                        .as_array()
+                        .and_then(|changes| changes.last())
+                        .map(|change| &change["text"]),
+                    "textDocument/didOpen" => Some(&params["textDocument"]["text"]),
+                    _ => Some(&params["text"]),
+                };
+                if let Some(text) = text.and_then(Value::as_str) {
+                    self.documents.insert(uri.to_string(), text.to_string());
+                }
+                return self.diagnostics(uri).into_iter().collect();
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return vec![json!({
+                    "jsonrpc": "2.0",
+                    "method": "textDocument/publishDiagnostics",
+                    "params": {"uri": uri, "diagnostics": []},
+                })];
+            }
+            "textDocument/completion" => {
+                let position = &params["position"];
+                Value::Array(self.completions(
+                    uri,
+                    position["line"].as_u64().unwrap_or_default() as usize,
+                    position["character"].as_u64().unwrap_or_default() as usize,
+                ))
+            }
+            _ => {
+                return id
+                    .map(|id| {
+                        let error =
+                            RpcError::new(METHOD_NOT_FOUND, format!("Unknown method {}", method));
+                        daemon::error_response(id, error)
+                    })
+                    .into_iter()
+                    .collect()
+            }
+        };
+        id.map(|id| json!({"jsonrpc": "2.0", "id": id, "result": result}))
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Serve an editor over `stdin` and `stdout` until it asks the server to exit.
+pub fn serve(mut server: Server) -> Result<()> {
+    let (stdin, stdout) = (std::io::stdin(), std::io::stdout());
+    let (mut reader, mut writer) = (stdin.lock(), stdout.lock());
+    while let Some(body) = read_message(&mut reader)? {
+        for message in server.handle_body(&body) {
+            write_message(&mut writer, &message)?;
+        }
+        if server.exited {
+            break;
+        }
+    }
+    if !server.shutdown {
+        bail!("The editor exited without shutting down the language server")
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::config::Config;
+    use crate::daemon::PARSE_ERROR;
+    use crate::lsp::{position, read_message, uri_path, write_message, LspOptions, Server};
+    use crate::parsers::{ParseSuccess, ParserOptions};
+    use crate::templates::engine::Engine;
+    use serde_json::json;
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn templates() {
+        let dir = std::env::temp_dir().join(format!("kvasir-lsp-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("base.tpl"), "{{ files | length }}").unwrap();
+        let success: ParseSuccess = serde_json::from_value(json!({
+            "path": "orders.yaml",
+            "parser": "yaml",
+            "contents": {"name": "orders", "spec": {"replicas": 2}},
+        }))
+        .unwrap();
+        let options = LspOptions {
+            templates: format!("{}/*.tpl", dir.display()),
+            engine: Engine::Tera,
+            per_file: false,
+            parser_options: ParserOptions::default(),
+        };
+        let mut server = Server::new(options, Config::default(), vec![success], vec![]).unwrap();
+        let uri = format!("file://{}", dir.join("base.tpl").display());
+        let open = |server: &mut Server, text: &str| {
+            server.handle(json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didOpen",
+                "params": {"textDocument": {"uri": uri, "languageId": "tera", "version": 1, "text": text}},
+            }))
+        };
+
+        let published = open(
+            &mut server,
+            "{% for f in files %}\n{{ f.contents.name | upper }}\n{% endfor %}",
+        );
+        assert_eq!(published[0]["params"]["diagnostics"], json!([]));
+        let published = open(
+            &mut server,
+            "{% for f in files %}\n  {{ f.contents.nmae }}\n{% endfor %}",
+        );
+        let diagnostic = &published[0]["params"]["diagnostics"][0];
+        assert_eq!(
+            diagnostic["range"]["start"],
+            json!({"line": 1, "character": 5})
+        );
+        assert!(diagnostic["message"]
+            .as_str()
+            .unwrap()
+            .contains("f.contents.nmae"));
+        let published = open(&mut server, "{{ files | }}");
+        let diagnostic = &published[0]["params"]["diagnostics"][0];
+        assert_eq!(
+            diagnostic["range"]["start"],
+            json!({"line": 0, "character": 11})
+        );
+
+        // Completes at the `$` marking the cursor, or at the end of the text
+        let mut complete = |text: &str| {
+            let (before, after) = text.split_once('$').unwrap_or((text, ""));
+            open(&mut server, &format!("{}{}", before, after));
+            let line = before.lines().count() - 1;
+            let character = before.lines().last().unwrap().encode_utf16().count();
+            let response = server.handle(json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "method": "textDocument/completion",
+                "params": {"textDocument": {"uri": uri}, "position": {"line": line, "character": character}},
+            }));
+            response[0]["result"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|i| i["label"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            complete("{% for f in files %}\n{{ f.contents."),
+            ["name", "spec"]
+        );
+        assert_eq!(complete("{{ files[0].contents.spec.r"), ["replicas"]);
+        assert_eq!(
+            complete("{{ \"😀\" ~ files[0].contents.spec.r$ }}"),
+            ["replicas"]
+        );
+        assert_eq!(complete("{{ fi"), ["files", "file"]);
+        assert!(complete("{{ vars | json").contains(&"jsonpath".to_string()));
+        assert!(complete("{{ ").contains(&"glob".to_string()));
+
+        assert_eq!(
+            server.handle(json!({"jsonrpc": "2.0", "id": 3, "method": "textDocument/hover"}))[0]
+                ["error"]["code"],
+            -32601
+        );
+        // Messages which are not JSON are answered with an error rather than ending the session
+        assert_eq!(
+            server.handle_body(b"{\"jsonrpc\": ")[0]["error"]["code"],
+            PARSE_ERROR
+        );
+        assert_eq!(
+            server.handle(json!({"jsonrpc": "2.0", "id": 4, "method": "shutdown"}))[0],
+            json!({"jsonrpc": "2.0", "id": 4, "result": null})
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn messages() {
+        let mut buffer = vec![];
+        let message = json!({"jsonrpc": "2.0", "method": "initialized", "params": {}});
+        write_message(&mut buffer, &message).unwrap();
+        assert!(buffer.starts_with(b"Content-Length: 52\r\n\r\n{"));
+        let mut reader = std::io::BufReader::new(buffer.as_slice());
+        assert_eq!(
+            read_message(&mut reader).unwrap(),
+            Some(message.to_string().into_bytes())
+        );
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+
+        assert_eq!(
+            uri_path("file:///home/jo/my%20templates/base.tpl"),
+            Some(PathBuf::from("/home/jo/my templates/base.tpl"))
+        );
+        assert_eq!(
+            uri_path("file:///C:/templates/base.tpl"),
+            Some(PathBuf::from("C:/templates/base.tpl"))
+        );
+        assert_eq!(uri_path("untitled:Untitled-1"), None);
+        assert_eq!(position("Failed to parse\n --> 3:7\n  |", ""), (2, 6));
+        assert_eq!(
+            position("Variable `spec.name` not found", "a\n  {{ spec.name }}"),
+            (1, 5)
+        );
+        assert_eq!(
+            position("Variable `spec.name` not found", "a\n😀 {{ spec.name }}"),
+            (1, 6)
+        );
+        assert_eq!(position("Failed to parse\n --> 1:3\n  |", "é😀x"), (0, 3));
+    }
+}
@@ -37,35 +37,85 @@
 //!     kvasir document --globs /path/to/**/*.yaml --templates templates/base.tpl
 //!```
 
+mod bench;
+mod cache;
+mod cancel;
+mod canonical;
+mod capabilities;
+mod check;
+mod cli;
+mod clock;
+mod config;
+mod context;
+mod coverage;
+mod daemon;
+mod drift;
 mod errors;
+mod exit;
+mod history;
+mod lint;
+mod logging;
+mod lsp;
+mod manifest;
+mod metrics;
+mod notify;
+mod output;
+mod pagination;
 mod parsers;
+mod paths;
+mod progress;
+mod publish;
+mod references;
+mod remote;
+mod report;
+mod schema;
+mod search;
+mod secrets;
+mod signing;
+mod sitemap;
+mod snapshots;
+mod spelling;
+mod stats;
 mod templates;
+#[cfg(test)]
+mod testing;
+mod trace;
+mod update;
 
 #[macro_use]
 extern crate error_chain;
 
-use env_logger::Env;
-use errors::Error;
+use cache::ParseCache;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use config::{Config, Severity, Source};
+use context::TemplateContext;
+use errors::{Error, ErrorKind};
 use glob::GlobError;
 use itertools::{Either, Itertools};
 use log::{debug, error, info, warn};
-use once_cell::unsync::OnceCell;
+use manifest::Manifest;
 use parsers::FileParser;
-use parsers::{ParseFailure, ParseSuccess};
-use path_clean::PathClean;
+use parsers::{CodeOwners, ParseContext, ParseFailure, ParseSuccess, ParserOptions};
+use rayon::prelude::*;
+use report::{OutputSchema, ParseReport};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs;
+use std::time::{Duration, Instant};
 use std::{path::Path, path::PathBuf, str};
-use structopt::StructOpt;
-use tera::Context;
-
-/// OS-specific line endings
-#[cfg(windows)]
-const LINE_ENDING: &str = "\r\n";
-#[cfg(not(windows))]
-const LINE_ENDING: &str = "\n";
+use templates::engine::{Engine, Templates};
+use templates::exec::ExecOptions;
+use templates::functions::FunctionOptions;
+use templates::http::HttpOptions;
+use templates::i18n::Translations;
+use templates::limits::RenderLimits;
+use templates::pages::PageOptions;
 
-#[derive(Debug, StructOpt)]
-#[structopt(name = "kvasir", version = "0.3.4")]
+#[derive(Debug, Parser)]
+#[command(
+    name = "kvasir",
+    version,
+    about = "Source file parser and template generator"
+)]
 /// kvasir - source file parser and template generator
 ///
 /// kvasir is a tool for parsing structured text files into JSON format, either
@@ -81,41 +131,160 @@ const LINE_ENDING: &str = "\n";
 /// It can be run directly or within CI/CD pipelines to generate and embed
 /// documentation into markdown files, READMEs or other documentation tools.
 ///
-/// EXAMPLES:
-///     kvasir parse --globs /path/to/**/*.yaml /path/to/**/*.xml
-///     kvasir document --globs /path/to/**/*.yaml --templates templates/base.tpl
+/// Every option can also be set with the environment variable shown in its help, e.g.
+/// `KVASIR_SOURCES`. Options that take several values accept a comma-separated list.
+#[command(after_help = "EXAMPLES:
+    kvasir parse --sources '/path/to/**/*.yaml' '/path/to/**/*.xml'
+    kvasir document --sources '/path/to/**/*.yaml' --templates 'templates/*.tpl'")]
 struct CLOptions {
-    #[structopt(short, long)]
-    /// Enable debug application output.
+    #[arg(short, long, global = true, env = "KVASIR_DEBUG")]
+    /// Enable debug application output, equivalent to `-vv`.
     debug: bool,
-    #[structopt(subcommand)]
+    #[arg(short, long, global = true, env = "KVASIR_VERBOSE", action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    /// Increase the detail of diagnostic output: `-v` for progress, `-vv` for debug output
+    /// and `-vvv` for trace output. The environment variable takes the number of `v`s.
+    verbose: u8,
+    #[arg(
+        short,
+        long,
+        global = true,
+        env = "KVASIR_QUIET",
+        conflicts_with = "debug"
+    )]
+    /// Only output errors, and no summaries, to `stderr`.
+    quiet: bool,
+    #[arg(
+        long,
+        global = true,
+        env = "KVASIR_COLOR",
+        value_enum,
+        default_value = "auto"
+    )]
+    /// When to use colour in diagnostic output.
+    color: logging::ColorChoice,
+    #[arg(long, global = true, env = "KVASIR_FROZEN_TIME", value_parser = cli::time)]
+    /// Use this time, in seconds since the Unix epoch or as an RFC 3339 timestamp, as the
+    /// time of generation in front matter, manifests and templates, so that output is
+    /// reproducible. Defaults to the `SOURCE_DATE_EPOCH` environment variable, if set.
+    frozen_time: Option<chrono::DateTime<chrono::Utc>>,
+    #[arg(long, global = true, env = "KVASIR_TRACE_OUTPUT")]
+    /// Write the time taken to list and parse the source files, by file and by parser, to
+    /// this file in the Chrome trace format, which can be viewed as a flame graph.
+    trace_output: Option<PathBuf>,
+    #[arg(long, global = true, env = "KVASIR_CHECK_UPDATE")]
+    /// Warn if a newer release of kvasir is available. The check never fails the command.
+    check_update: bool,
+    #[arg(
+        long,
+        global = true,
+        env = "KVASIR_RELEASE_URL",
+        default_value = update::RELEASES_URL,
+        hide_default_value = true
+    )]
+    /// The URL of the latest release in the GitHub releases API, for `--check-update` and
+    /// `self-update`. Defaults to the latest kvasir release on GitHub.
+    release_url: String,
+    #[arg(short, long, env = "KVASIR_CONFIG")]
+    /// Path to the project configuration file. Defaults to `kvasir.toml` in the current
+    /// directory, if it exists.
+    config: Option<String>,
+    #[command(subcommand)]
     /// Subcommand to run.
     cmd: Command,
 }
 
-#[derive(Debug, StructOpt)]
+#[derive(Debug, Subcommand)]
+#[allow(clippy::large_enum_variant)]
 /// Command line sub-command to execute
 enum Command {
     /// Parse one or more source files into a single JSON structure.
+    #[command(after_help = "EXAMPLES:
+    kvasir parse --sources '/path/to/**/*.yaml' '/path/to/**/*.xml'
+    kvasir parse --profile prod --parser-option kubernetes.redact_secrets=true
+    KVASIR_SOURCES='config/*.toml' kvasir parse --timeout 5")]
     Parse {
-        #[structopt(long)]
-        /// One or more glob path expressions to search for source files.
+        #[arg(long, env = "KVASIR_SOURCES", num_args = 1.., value_parser = cli::glob_expression)]
+        /// One or more glob path expressions to search for source files, in addition to any
+        /// sources defined in the configuration file.
         sources: Vec<String>,
+        #[arg(long = "tag", env = "KVASIR_TAGS", num_args = 1..)]
+        /// One or more tags to apply to the source files specified with `--sources`.
+        tags: Vec<String>,
+        #[arg(long, env = "KVASIR_PROFILE")]
+        /// The name of a profile in the configuration file whose sources should be parsed.
+        profile: Option<String>,
+        #[arg(long = "parser-option", env = "KVASIR_PARSER_OPTIONS", num_args = 1.., value_parser = cli::parser_option)]
+        /// One or more parser options in the form `parser.option=value`, e.g.
+        /// `kubernetes.redact_secrets=true`.
+        parser_options: Vec<String>,
+        /// Parse each set of source files with the same content only once, as the first of
+        /// them, listing the paths of the others as the parsed file's `duplicates`.
+        #[arg(long, env = "KVASIR_DEDUPE")]
+        dedupe: bool,
+        /// List the references to values held outside each parsed file, such as
+        /// `{{vault:secret/orders#password}}` or `${ssm:/orders/db/url}` placeholders, as the
+        /// file's `external_refs`, without fetching the referenced values.
+        #[arg(long, env = "KVASIR_EXTERNAL_REFS")]
+        external_refs: bool,
+        #[arg(long, env = "KVASIR_TIMEOUT", value_parser = cli::seconds)]
+        /// The maximum time in seconds that each parser may take to parse a single file.
+        /// Parsing that takes longer is reported as a failure.
+        timeout: Option<f64>,
+        #[arg(long, env = "KVASIR_INCLUDE_FAILURES")]
+        /// Output an object containing the parsed `files`, the `failures` and a `summary` of
+        /// the counts and timing, with a `schemaVersion`, rather than an array of parsed files.
+        include_failures: bool,
+        #[arg(long, env = "KVASIR_FAIL_ON_PARSE_FAILURES")]
+        /// Fail with exit code 4 if any source file could not be parsed, after writing the
+        /// parsed output.
+        fail_on_parse_failures: bool,
+        #[arg(long, env = "KVASIR_OUTPUT_SCHEMA", value_enum, default_value = "1")]
+        /// The version of the JSON output schema: `1` (the default), for compatibility with
+        /// earlier releases, or `2` (the latest).
+        output_schema: OutputSchema,
+        #[arg(long, env = "KVASIR_CANONICAL")]
+        /// Output canonical JSON, with sorted keys, integral numbers written as integers and
+        /// the arrays declared `unordered` in the configuration file sorted, so that
+        /// semantically identical files produce identical output.
+        canonical: bool,
     },
 
     /// Parse one or more source files into a single JSON structure and format the structure using the
     /// specified templates.
+    #[command(after_help = "EXAMPLES:
+    kvasir document --sources '/path/to/**/*.yaml' --templates 'templates/*.tpl'
+    kvasir document --templates 'templates/*.tpl' --root-template base.tpl \\
+        --split-files --output-dir docs --front-matter
+    kvasir document --templates 'templates/*.tpl' --split-files --output-to-cwd \\
+        --per-file --incremental")]
     Document {
-        #[structopt(long)]
-        /// One or more glob path expressions to search for source files. Glob expressions
-        /// are defined within https://docs.rs/glob/latest/glob/struct.Pattern.html.
+        #[arg(long, env = "KVASIR_SOURCES", num_args = 1.., value_parser = cli::glob_expression)]
+        /// One or more glob path expressions to search for source files, in addition to any
+        /// sources defined in the configuration file. Glob expressions are defined within
+        /// https://docs.rs/glob/latest/glob/struct.Pattern.html.
         sources: Vec<String>,
-        #[structopt(short, long)]
+        #[arg(long = "tag", env = "KVASIR_TAGS", num_args = 1..)]
+        /// One or more tags to apply to the source files specified with `--sources`.
+        tags: Vec<String>,
+        #[arg(long, env = "KVASIR_PROFILE")]
+        /// The name of a profile in the configuration file whose sources and variables should
+        /// be used. The active profile is available to templates as `profile`.
+        profile: Option<String>,
+        #[arg(long = "var-file", env = "KVASIR_VAR_FILES")]
+        /// JSON, YAML or TOML files of template variables, overriding variables of the same
+        /// name in the configuration file. Variables are checked against the `var_schema` of
+        /// the configuration file, if any, before any files are parsed.
+        var_files: Vec<PathBuf>,
+        #[arg(short, long, env = "KVASIR_TEMPLATES", value_parser = cli::glob_expression)]
         /// A glob path expression to search for template files, or '-' if the template contents are to
         /// be read from `stdin`.
         templates: String,
-        #[structopt(short, long)]
-        /// Relative path to the root template, if more than one is found by the template glob expression.
+        #[arg(long, env = "KVASIR_ENGINE", default_value = "tera")]
+        /// The template engine used to render templates: `tera` or `handlebars`.
+        engine: Engine,
+        #[arg(short, long, env = "KVASIR_ROOT_TEMPLATE")]
+        /// Relative path to the root template, if more than one template found by the template glob
+        /// expression is not referenced by another template.
         root_template: Option<String>,
         /// Whether to write the template output to multiple files, split by the provided delimiter. To
         /// split files, the parser expects the delimiter and destination file name to be written to a
@@ -124,210 +293,1857 @@ enum Command {
         ///     8<-- output/dir/{{file.output_file_name}}.md
         ///     {{ file.content }}
         ///     {% endfor %}
-        #[structopt(long)]
+        /// Requires either `--output-dir` or `--output-to-cwd`.
+        #[arg(long, env = "KVASIR_SPLIT_FILES", requires = "output_location")]
         split_files: bool,
         /// Delimiter to search for in the template output to split files, defaulting to "8<--"
-        #[structopt(long, default_value = "8<--")]
+        #[arg(long, env = "KVASIR_SPLIT_DELIMITER", default_value = "8<--")]
         split_delimiter: String,
-        /// Root directory under which split output files are written. Defaults to the current directory.
-        #[structopt(long)]
-        output_dir: Option<String>,
-        // Allow overwriting existing files when splitting output files.
-        #[structopt(long)]
+        /// Existing root directory under which split output files are written.
+        #[arg(long, env = "KVASIR_OUTPUT_DIR", group = "output_location", requires = "split_files", value_parser = cli::existing_dir)]
+        output_dir: Option<PathBuf>,
+        /// Write split output files under the current directory.
+        #[arg(
+            long,
+            env = "KVASIR_OUTPUT_TO_CWD",
+            group = "output_location",
+            requires = "split_files"
+        )]
+        output_to_cwd: bool,
+        /// Allow overwriting existing files when splitting output files.
+        #[arg(long, env = "KVASIR_ALLOW_OVERWRITE", requires = "split_files")]
         allow_overwrite: bool,
+        /// Write split output files, and any manifest, report or index, into a subdirectory
+        /// of the output directory named after this version, e.g. `v1.4.2`, or after the
+        /// time of generation with `strftime` specifiers, e.g. `%Y-%m-%dT%H%M%S`, keeping the
+        /// earlier versions. Requires `--split-files`.
+        #[arg(long, env = "KVASIR_OUTPUT_VERSION", requires = "split_files")]
+        output_version: Option<String>,
+        /// Remove the oldest versions from the output directory once the output has been
+        /// written, keeping this many versions, including the one written.
+        #[arg(long, env = "KVASIR_KEEP_VERSIONS", requires = "output_version", value_parser = cli::count)]
+        keep_versions: Option<usize>,
+        /// Prepend YAML front matter (title, generation time and the `source` and `tags`
+        /// attributes of the split header line) to each split Markdown or HTML file.
+        #[arg(long, env = "KVASIR_FRONT_MATTER", requires = "split_files")]
+        front_matter: bool,
+        /// Path to a Tera template used to render front matter instead of the default YAML.
+        /// Implies `--front-matter`.
+        #[arg(long, env = "KVASIR_FRONT_MATTER_TEMPLATE", requires = "split_files")]
+        front_matter_template: Option<String>,
+        #[arg(long = "parser-option", env = "KVASIR_PARSER_OPTIONS", num_args = 1.., value_parser = cli::parser_option)]
+        /// One or more parser options in the form `parser.option=value`, e.g.
+        /// `kubernetes.redact_secrets=true`.
+        parser_options: Vec<String>,
+        /// Parse each set of source files with the same content only once, as the first of
+        /// them, listing the paths of the others as the parsed file's `duplicates`.
+        #[arg(long, env = "KVASIR_DEDUPE")]
+        dedupe: bool,
+        /// List the references to values held outside each parsed file, such as
+        /// `{{vault:secret/orders#password}}` or `${ssm:/orders/db/url}` placeholders, as the
+        /// file's `external_refs`, without fetching the referenced values.
+        #[arg(long, env = "KVASIR_EXTERNAL_REFS")]
+        external_refs: bool,
+        /// Write a `kvasir-manifest.json` file to the output directory, listing each generated
+        /// file with its SHA-256 hash, the source files that contributed to it and the templates
+        /// used. Requires `--split-files`.
+        #[arg(long, env = "KVASIR_MANIFEST", requires = "split_files")]
+        manifest: bool,
+        /// Only write split output files whose contributing source files have changed since
+        /// the manifest in the output directory was written, or which have been changed on
+        /// disk. Nothing is rendered if no sources or templates have changed. Implies
+        /// `--manifest` and requires `--split-files`.
+        #[arg(long, env = "KVASIR_INCREMENTAL", requires = "split_files")]
+        incremental: bool,
+        /// Record the progress of the run in a `.kvasir-progress` directory in the output
+        /// directory, and resume an interrupted run recorded there: source files that were
+        /// parsed are not parsed again, and output files that were written are not written
+        /// again. The directory is removed when the run completes. Requires `--split-files`.
+        #[arg(long, env = "KVASIR_RESUME", requires = "split_files")]
+        resume: bool,
+        /// Sign the manifest, and so the generated files it lists, with a detached GPG
+        /// signature or with an HMAC-SHA256 keyed by the `KVASIR_SIGNING_SECRET` environment
+        /// variable, so that the output can be checked with `kvasir verify`. Implies
+        /// `--manifest` and requires `--split-files`.
+        #[arg(long, env = "KVASIR_SIGN", value_enum, requires = "split_files")]
+        sign: Option<signing::Signing>,
+        /// The GPG key to sign the manifest with, instead of the default key.
+        #[arg(long, env = "KVASIR_SIGNING_KEY", requires = "sign")]
+        signing_key: Option<String>,
+        /// Report the parsed source files that are not documented by any split output file,
+        /// through the `source` attribute of split header lines, and write them to a
+        /// `kvasir-coverage.json` file in the output directory. Requires `--split-files`.
+        #[arg(long, env = "KVASIR_COVERAGE", requires = "split_files")]
+        coverage: bool,
+        /// Fail if less than this percentage of the parsed source files are documented by
+        /// split output files. Implies `--coverage`.
+        #[arg(long, env = "KVASIR_MIN_COVERAGE", requires = "split_files", value_parser = cli::percentage)]
+        min_coverage: Option<f64>,
+        /// Check the parsed files against the rules in the configuration file, as with
+        /// `kvasir lint`, failing after the output has been written if any rule with `error`
+        /// severity is violated.
+        #[arg(long, env = "KVASIR_LINT")]
+        lint: bool,
+        /// Scan the parsed files for likely secrets, such as AWS access keys, JSON Web Tokens,
+        /// private keys and other high-entropy tokens, as with `kvasir secrets`, failing
+        /// before any output is written if any are found.
+        #[arg(long, env = "KVASIR_FAIL_ON_SECRETS")]
+        fail_on_secrets: bool,
+        /// Fail with exit code 4 if any source file could not be parsed, before any output is
+        /// written.
+        #[arg(long, env = "KVASIR_FAIL_ON_PARSE_FAILURES")]
+        fail_on_parse_failures: bool,
+        /// Write a `search-index.json` file to the output directory, containing an inverted
+        /// index of the terms in the parsed source files and the split output files, for
+        /// client-side search. Requires `--split-files`.
+        #[arg(long, env = "KVASIR_SEARCH_INDEX", requires = "split_files")]
+        search_index: bool,
+        /// Split Markdown and HTML output files larger than this size in bytes, optionally
+        /// followed by `K` or `M` (e.g. `512K`), into numbered parts with links between them,
+        /// e.g. so that GitHub can render them. Requires `--split-files`.
+        #[arg(long, env = "KVASIR_MAX_PAGE_SIZE", requires = "split_files", value_parser = cli::size)]
+        max_page_size: Option<usize>,
+        /// Check the split Markdown and HTML output files for broken relative links, links to
+        /// missing anchors, duplicate heading anchors and images without alternative text,
+        /// failing after the output has been written if any are found. Requires
+        /// `--split-files`.
+        #[arg(long, env = "KVASIR_CHECK_OUTPUT", requires = "split_files")]
+        check_output: bool,
+        /// Spell-check the text of the split Markdown and HTML output files against the
+        /// dictionaries and words in the `[spelling]` section of the configuration file,
+        /// reporting unknown words with the parsed values containing them. Requires
+        /// `--split-files`.
+        #[arg(long, env = "KVASIR_SPELL_CHECK", requires = "split_files")]
+        spell_check: bool,
+        /// Write an index page listing the split output files to this path, relative to the
+        /// output directory, e.g. `index.md`. HTML index pages are also written with a
+        /// `sitemap.xml` file when the `[pages]` base URL is configured. Requires
+        /// `--split-files`.
+        #[arg(long, env = "KVASIR_INDEX", requires = "split_files")]
+        index: Option<PathBuf>,
+        /// Group pages on the index page by directory or by tag.
+        #[arg(
+            long,
+            env = "KVASIR_INDEX_GROUP_BY",
+            value_enum,
+            default_value = "directory",
+            requires = "index"
+        )]
+        index_group_by: sitemap::GroupBy,
+        /// Path to a Tera template used to render the index page instead of the built-in
+        /// Markdown or HTML template.
+        #[arg(long, env = "KVASIR_INDEX_TEMPLATE", requires = "index")]
+        index_template: Option<String>,
+        /// Upload the split output files, and any manifest, coverage report, search index
+        /// and sitemap, to the S3-compatible bucket in the `[publish]` section of the
+        /// configuration file, skipping files which are unchanged in the bucket. Requires
+        /// `--split-files`.
+        #[arg(long, env = "KVASIR_PUBLISH", requires = "split_files")]
+        publish: bool,
+        /// Do not post a summary of the run to the webhook in the `[notify]` section of the
+        /// configuration file, e.g. when running locally.
+        #[arg(long, env = "KVASIR_NO_NOTIFY")]
+        no_notify: bool,
+        /// Write metrics of the run, such as its duration, the number of files parsed and
+        /// the number of failures, to this file, replacing it, or post them to this `http` or
+        /// `https` URL. Metrics are written even if the run fails.
+        #[arg(long, env = "KVASIR_METRICS_OUTPUT")]
+        metrics_output: Option<String>,
+        /// The format of the metrics: `prometheus`, the Prometheus text format, or `otlp`,
+        /// OpenTelemetry OTLP metrics encoded as JSON.
+        #[arg(
+            long,
+            env = "KVASIR_METRICS_FORMAT",
+            value_enum,
+            default_value = "prometheus",
+            requires = "metrics_output"
+        )]
+        metrics_format: metrics::Format,
+        /// One or more labels in the form `name=value`, e.g. `repo=payments`, added to every
+        /// metric, or as resource attributes in OTLP, to identify the pipeline.
+        #[arg(long = "metrics-label", env = "KVASIR_METRICS_LABELS", num_args = 1.., value_parser = cli::label, requires = "metrics_output")]
+        metrics_labels: Vec<(String, String)>,
+        /// Render the root template once for each parsed file, which is available to the
+        /// template as `file`, rather than once for all files. Files are rendered concurrently
+        /// and the output of each is split into files. Requires `--split-files`.
+        #[arg(long, env = "KVASIR_PER_FILE", requires = "split_files")]
+        per_file: bool,
+        /// Render the root template once for each chunk of the specified number of files, with
+        /// `files` containing only the files in the chunk, rather than once for all files. This
+        /// limits the size of each template context for very large numbers of files. Chunks are
+        /// rendered concurrently and the output of each is split into files. Requires
+        /// `--split-files`.
+        #[arg(long, env = "KVASIR_CHUNK_SIZE", conflicts_with = "per_file", requires = "split_files", value_parser = cli::count)]
+        chunk_size: Option<usize>,
+        /// Render the root template once for each directory containing parsed files, which is
+        /// available to the template as `dir`, with `files` containing only the files directly
+        /// within the directory, e.g. to write a README for each module. Directories are
+        /// rendered concurrently and the output of each is split into files. Combine with
+        /// `--index` and `--index-template` to render a page for the root of the tree.
+        /// Requires `--split-files`.
+        #[arg(long, env = "KVASIR_PER_DIRECTORY", conflicts_with_all = ["per_file", "chunk_size"], requires = "split_files")]
+        per_directory: bool,
+        /// The number of threads used to render files with `--per-file`, `--chunk-size` or
+        /// `--per-directory`. Defaults to the number of CPUs.
+        #[arg(long, env = "KVASIR_JOBS", value_parser = cli::count)]
+        jobs: Option<usize>,
+        #[arg(long, env = "KVASIR_ALLOW_NETWORK")]
+        /// Allow templates to fetch data over HTTP with the `http_get` function.
+        allow_network: bool,
+        #[arg(long, env = "KVASIR_CACHE_DIR", default_value = templates::http::DEFAULT_CACHE_DIR)]
+        /// The directory in which responses fetched by `http_get` are cached.
+        cache_dir: PathBuf,
+        #[arg(long, env = "KVASIR_ALLOW_EXEC")]
+        /// Allow templates to run the commands allowed in the configuration file with the
+        /// `exec` function.
+        allow_exec: bool,
+        #[arg(long, env = "KVASIR_LOCALE")]
+        /// The locale, e.g. `de` or `de-AT`, whose translations are used by the `t` template
+        /// function, defaulting to the `default_locale` of the configuration file.
+        locale: Option<String>,
+        #[arg(long, env = "KVASIR_TIMEOUT", value_parser = cli::seconds)]
+        /// The maximum time in seconds that each parser may take to parse a single file.
+        /// Parsing that takes longer is reported as a failure.
+        timeout: Option<f64>,
+        #[arg(long, env = "KVASIR_MAX_INCLUDE_DEPTH", value_parser = cli::count)]
+        /// The maximum depth of templates extended, included, imported or used as partials
+        /// from the root template. Recursive templates are rejected when this is set.
+        max_include_depth: Option<usize>,
+        #[arg(long, env = "KVASIR_MAX_OUTPUT_SIZE", value_parser = cli::size)]
+        /// The maximum size in bytes, optionally followed by `K`, `M` or `G`, of all output
+        /// rendered by the templates, after which rendering fails.
+        max_output_size: Option<usize>,
+        #[arg(long, env = "KVASIR_RENDER_TIMEOUT", value_parser = cli::seconds)]
+        /// The maximum time in seconds that each render of the root template may take, after
+        /// which rendering fails.
+        render_timeout: Option<f64>,
+        #[arg(long, env = "KVASIR_PROFILE_TEMPLATES")]
+        /// Print the number of renders of the root template and calls of each custom filter
+        /// and function, such as `jsonpath` and `glob`, with the time taken, slowest first.
+        profile_templates: bool,
+        #[arg(long, env = "KVASIR_STATS")]
+        /// Add `stats` summarising all parsed files to the template context.
+        stats: bool,
+        #[arg(long, env = "KVASIR_DISTINCT", num_args = 1.., requires = "stats")]
+        /// One or more JSONPath expressions whose distinct values are counted in `stats`, in
+        /// addition to any in the configuration file.
+        distinct: Vec<String>,
+        #[arg(long, env = "KVASIR_DRIFT")]
+        /// Add a `drift` report to the template context, listing the keys which are missing
+        /// from some of the parsed files matched by each source glob.
+        drift: bool,
+        #[arg(long, env = "KVASIR_OUTPUT_SCHEMA", value_enum, default_value = "1")]
+        /// The version of the template context schema: `1` (the default), for compatibility
+        /// with earlier releases, or `2` (the latest).
+        output_schema: OutputSchema,
+    },
+
+    /// Parse one or more source files and output the context that templates would receive
+    /// as JSON.
+    #[command(after_help = "EXAMPLES:
+    kvasir context --sources '/path/to/**/*.yaml' --profile prod")]
+    Context {
+        #[arg(long, env = "KVASIR_SOURCES", num_args = 1.., value_parser = cli::glob_expression)]
+        /// One or more glob path expressions to search for source files, in addition to any
+        /// sources defined in the configuration file.
+        sources: Vec<String>,
+        #[arg(long = "tag", env = "KVASIR_TAGS", num_args = 1..)]
+        /// One or more tags to apply to the source files specified with `--sources`.
+        tags: Vec<String>,
+        #[arg(long, env = "KVASIR_PROFILE")]
+        /// The name of a profile in the configuration file whose sources and variables should
+        /// be used.
+        profile: Option<String>,
+        #[arg(long = "var-file", env = "KVASIR_VAR_FILES")]
+        /// JSON, YAML or TOML files of template variables, overriding variables of the same
+        /// name in the configuration file. Variables are checked against the `var_schema` of
+        /// the configuration file, if any, before any files are parsed.
+        var_files: Vec<PathBuf>,
+        #[arg(long = "parser-option", env = "KVASIR_PARSER_OPTIONS", num_args = 1.., value_parser = cli::parser_option)]
+        /// One or more parser options in the form `parser.option=value`, e.g.
+        /// `kubernetes.redact_secrets=true`.
+        parser_options: Vec<String>,
+        /// Parse each set of source files with the same content only once, as the first of
+        /// them, listing the paths of the others as the parsed file's `duplicates`.
+        #[arg(long, env = "KVASIR_DEDUPE")]
+        dedupe: bool,
+        /// List the references to values held outside each parsed file, such as
+        /// `{{vault:secret/orders#password}}` or `${ssm:/orders/db/url}` placeholders, as the
+        /// file's `external_refs`, without fetching the referenced values.
+        #[arg(long, env = "KVASIR_EXTERNAL_REFS")]
+        external_refs: bool,
+        #[arg(long, env = "KVASIR_STATS")]
+        /// Add `stats` summarising all parsed files to the template context.
+        stats: bool,
+        #[arg(long, env = "KVASIR_DISTINCT", num_args = 1.., requires = "stats")]
+        /// One or more JSONPath expressions whose distinct values are counted in `stats`, in
+        /// addition to any in the configuration file.
+        distinct: Vec<String>,
+        #[arg(long, env = "KVASIR_DRIFT")]
+        /// Add a `drift` report to the template context, listing the keys which are missing
+        /// from some of the parsed files matched by each source glob.
+        drift: bool,
+        #[arg(long, env = "KVASIR_OUTPUT_SCHEMA", value_enum, default_value = "1")]
+        /// The version of the template context schema: `1` (the default), for compatibility
+        /// with earlier releases, or `2` (the latest).
+        output_schema: OutputSchema,
+        #[arg(long, env = "KVASIR_CANONICAL")]
+        /// Output canonical JSON, with sorted keys, integral numbers written as integers and
+        /// the arrays declared `unordered` in the configuration file sorted, so that
+        /// semantically identical files produce identical output.
+        canonical: bool,
+    },
+
+    /// Parse one or more source files and output summary statistics of the parsed files as
+    /// JSON.
+    #[command(after_help = "EXAMPLES:
+    kvasir stats --sources 'k8s/**/*.yaml' --distinct '$.spec.template.spec.containers[*].image'")]
+    Stats {
+        #[arg(long, env = "KVASIR_SOURCES", num_args = 1.., value_parser = cli::glob_expression)]
+        /// One or more glob path expressions to search for source files, in addition to any
+        /// sources defined in the configuration file.
+        sources: Vec<String>,
+        #[arg(long = "tag", env = "KVASIR_TAGS", num_args = 1..)]
+        /// One or more tags to apply to the source files specified with `--sources`.
+        tags: Vec<String>,
+        #[arg(long, env = "KVASIR_PROFILE")]
+        /// The name of a profile in the configuration file whose sources should be parsed.
+        profile: Option<String>,
+        #[arg(long = "parser-option", env = "KVASIR_PARSER_OPTIONS", num_args = 1.., value_parser = cli::parser_option)]
+        /// One or more parser options in the form `parser.option=value`, e.g.
+        /// `kubernetes.redact_secrets=true`.
+        parser_options: Vec<String>,
+        #[arg(long, env = "KVASIR_DISTINCT", num_args = 1..)]
+        /// One or more JSONPath expressions whose distinct values are counted, in addition to
+        /// any in the configuration file.
+        distinct: Vec<String>,
+    },
+
+    /// Parse one or more source files and output the keys which are missing from some of the
+    /// files matched by each source glob as JSON.
+    #[command(after_help = "EXAMPLES:
+    kvasir drift --sources 'envs/*/config.yaml'")]
+    Drift {
+        #[arg(long, env = "KVASIR_SOURCES", num_args = 1.., value_parser = cli::glob_expression)]
+        /// One or more glob path expressions to search for source files, in addition to any
+        /// sources defined in the configuration file. The files matched by each are compared.
+        sources: Vec<String>,
+        #[arg(long, env = "KVASIR_PROFILE")]
+        /// The name of a profile in the configuration file whose sources should be parsed.
+        profile: Option<String>,
+        #[arg(long = "parser-option", env = "KVASIR_PARSER_OPTIONS", num_args = 1.., value_parser = cli::parser_option)]
+        /// One or more parser options in the form `parser.option=value`, e.g.
+        /// `kubernetes.redact_secrets=true`.
+        parser_options: Vec<String>,
+    },
+
+    /// Parse one or more source files and check them against the rules in the configuration
+    /// file, failing if any rule with `error` severity is violated.
+    #[command(after_help = "EXAMPLES:
+    kvasir lint --sources 'k8s/**/*.yaml' 'api/*.yaml'
+    kvasir lint --profile prod --json > violations.json")]
+    Lint {
+        #[arg(long, env = "KVASIR_SOURCES", num_args = 1.., value_parser = cli::glob_expression)]
+        /// One or more glob path expressions to search for source files, in addition to any
+        /// sources defined in the configuration file.
+        sources: Vec<String>,
+        #[arg(long = "tag", env = "KVASIR_TAGS", num_args = 1..)]
+        /// One or more tags to apply to the source files specified with `--sources`.
+        tags: Vec<String>,
+        #[arg(long, env = "KVASIR_PROFILE")]
+        /// The name of a profile in the configuration file whose sources should be parsed.
+        profile: Option<String>,
+        #[arg(long = "parser-option", env = "KVASIR_PARSER_OPTIONS", num_args = 1.., value_parser = cli::parser_option)]
+        /// One or more parser options in the form `parser.option=value`, e.g.
+        /// `kubernetes.redact_secrets=true`.
+        parser_options: Vec<String>,
+        #[arg(long, env = "KVASIR_JSON")]
+        /// Output the violations as a JSON array, as well as reporting them to `stderr`.
+        json: bool,
+    },
+
+    /// Parse one or more source files and report the likely secrets, such as leaked
+    /// credentials, found in their values.
+    #[command(after_help = "EXAMPLES:
+    kvasir secrets --sources 'config/**/*.yaml' --fail-on-secrets
+    kvasir secrets --profile prod --json > secrets.json")]
+    Secrets {
+        #[arg(long, env = "KVASIR_SOURCES", num_args = 1.., value_parser = cli::glob_expression)]
+        /// One or more glob path expressions to search for source files, in addition to any
+        /// sources defined in the configuration file.
+        sources: Vec<String>,
+        #[arg(long = "tag", env = "KVASIR_TAGS", num_args = 1..)]
+        /// One or more tags to apply to the source files specified with `--sources`.
+        tags: Vec<String>,
+        #[arg(long, env = "KVASIR_PROFILE")]
+        /// The name of a profile in the configuration file whose sources should be parsed.
+        profile: Option<String>,
+        #[arg(long = "parser-option", env = "KVASIR_PARSER_OPTIONS", num_args = 1.., value_parser = cli::parser_option)]
+        /// One or more parser options in the form `parser.option=value`, e.g.
+        /// `kubernetes.redact_secrets=true`.
+        parser_options: Vec<String>,
+        #[arg(long, env = "KVASIR_JSON")]
+        /// Output the likely secrets as a JSON array, with the first characters of each, as
+        /// well as reporting them to `stderr`.
+        json: bool,
+        #[arg(long, env = "KVASIR_FAIL_ON_SECRETS")]
+        /// Fail if any likely secrets are found, e.g. in CI.
+        fail_on_secrets: bool,
+    },
+
+    /// Check the pages in the manifest of an output directory for dead links, failing if any
+    /// are found.
+    ///
+    /// Relative links are checked against the files in the manifest and the output
+    /// directory, including links to `#anchors`. External URLs are also requested with
+    /// `--allow-network`.
+    #[command(after_help = "EXAMPLES:
+    kvasir check-links --output-dir docs --templates 'templates/*.tpl'
+    kvasir check-links --output-dir docs --allow-network --json > dead-links.json")]
+    CheckLinks {
+        #[arg(long, env = "KVASIR_OUTPUT_DIR", value_parser = cli::existing_dir)]
+        /// The directory containing the generated files and a `kvasir-manifest.json` file,
+        /// written by `kvasir document --manifest`.
+        output_dir: PathBuf,
+        #[arg(short, long, env = "KVASIR_TEMPLATES", value_parser = cli::glob_expression)]
+        /// A glob path expression to search for the templates which generated the files, so
+        /// that dead links can be reported with the template line containing them.
+        templates: Option<String>,
+        #[arg(long, env = "KVASIR_ALLOW_NETWORK")]
+        /// Also check the `http` and `https` URLs linked from the generated files.
+        allow_network: bool,
+        #[arg(long, env = "KVASIR_LINK_TIMEOUT", value_parser = cli::seconds, default_value_t = check::external::DEFAULT_TIMEOUT)]
+        /// The maximum time in seconds to wait for each external URL.
+        link_timeout: f64,
+        #[arg(long, env = "KVASIR_JSON")]
+        /// Output the dead links as a JSON array, as well as reporting them to `stderr`.
+        json: bool,
+    },
+
+    /// Verify the signature of the manifest of an output directory, written by
+    /// `kvasir document --sign`, and that the generated files it lists are unchanged.
+    #[command(after_help = "EXAMPLES:
+    kvasir verify --output-dir docs --sign gpg
+    KVASIR_SIGNING_SECRET=... kvasir verify --output-dir docs --sign hmac")]
+    Verify {
+        #[arg(long, env = "KVASIR_OUTPUT_DIR", value_parser = cli::existing_dir)]
+        /// The directory containing the generated files, the `kvasir-manifest.json` file and
+        /// its signature.
+        output_dir: PathBuf,
+        #[arg(long, env = "KVASIR_SIGN", value_enum)]
+        /// How the manifest was signed.
+        sign: signing::Signing,
+    },
+
+    /// Render templates against fixture source files and compare the output with stored
+    /// snapshots.
+    ///
+    /// Each subdirectory of the test case directory is a test case, containing fixture
+    /// source files in a `sources` directory and the expected output in a `snapshot` file.
+    #[command(after_help = "EXAMPLES:
+    kvasir test --templates 'templates/*.tpl' --cases tests
+    kvasir test --templates 'templates/*.tpl' --update")]
+    Test {
+        #[arg(short, long, env = "KVASIR_TEMPLATES", value_parser = cli::glob_expression)]
+        /// A glob path expression to search for template files.
+        templates: String,
+        #[arg(long, env = "KVASIR_ENGINE", default_value = "tera")]
+        /// The template engine used to render templates: `tera` or `handlebars`.
+        engine: Engine,
+        #[arg(short, long, env = "KVASIR_ROOT_TEMPLATE")]
+        /// Relative path to the root template, if more than one template found by the template glob
+        /// expression is not referenced by another template.
+        root_template: Option<String>,
+        #[arg(long, env = "KVASIR_CASES", default_value = "tests", value_parser = cli::existing_dir)]
+        /// The directory containing test cases.
+        cases: PathBuf,
+        #[arg(long, env = "KVASIR_UPDATE")]
+        /// Write the rendered output of each test case to its snapshot, instead of comparing.
+        update: bool,
+        #[arg(long, env = "KVASIR_PROFILE")]
+        /// The name of a profile in the configuration file whose variables should be used.
+        profile: Option<String>,
+        #[arg(long = "var-file", env = "KVASIR_VAR_FILES")]
+        /// JSON, YAML or TOML files of template variables, overriding variables of the same
+        /// name in the configuration file. Variables are checked against the `var_schema` of
+        /// the configuration file, if any, before any files are parsed.
+        var_files: Vec<PathBuf>,
+        #[arg(long = "parser-option", env = "KVASIR_PARSER_OPTIONS", num_args = 1.., value_parser = cli::parser_option)]
+        /// One or more parser options in the form `parser.option=value`, e.g.
+        /// `kubernetes.redact_secrets=true`.
+        parser_options: Vec<String>,
+        #[arg(long, env = "KVASIR_ALLOW_NETWORK")]
+        /// Allow templates to fetch data over HTTP with the `http_get` function.
+        allow_network: bool,
+        #[arg(long, env = "KVASIR_CACHE_DIR", default_value = templates::http::DEFAULT_CACHE_DIR)]
+        /// The directory in which responses fetched by `http_get` are cached.
+        cache_dir: PathBuf,
+        #[arg(long, env = "KVASIR_ALLOW_EXEC")]
+        /// Allow templates to run the commands allowed in the configuration file with the
+        /// `exec` function.
+        allow_exec: bool,
+        #[arg(long, env = "KVASIR_LOCALE")]
+        /// The locale, e.g. `de` or `de-AT`, whose translations are used by the `t` template
+        /// function, defaulting to the `default_locale` of the configuration file.
+        locale: Option<String>,
+        #[arg(long, env = "KVASIR_STATS")]
+        /// Add `stats` summarising all parsed files to the template context.
+        stats: bool,
+        #[arg(long, env = "KVASIR_DISTINCT", num_args = 1.., requires = "stats")]
+        /// One or more JSONPath expressions whose distinct values are counted in `stats`, in
+        /// addition to any in the configuration file.
+        distinct: Vec<String>,
+        #[arg(long, env = "KVASIR_OUTPUT_SCHEMA", value_enum, default_value = "1")]
+        /// The version of the template context schema: `1` (the default), for compatibility
+        /// with earlier releases, or `2` (the latest).
+        output_schema: OutputSchema,
     },
 
     /// List available file format parsers.
     Parsers {},
-}
 
-/// Initialise the logging environment.
-fn logger_environment(verbose: bool) -> Env<'static> {
-    env_logger::Env::new()
-        .filter_or(
-            "KVASIR_LOG",
-            if verbose {
-                "kvasir=debug"
-            } else {
-                "kvasir=warn"
-            },
-        )
-        .write_style("KVASIR_LOG_STYLE")
+    /// Describe the parsers, with the extensions and options they accept, the template
+    /// filters and functions, with their arguments, and, as JSON, the command line, so that
+    /// tools can check what this version of kvasir supports.
+    #[command(after_help = "EXAMPLES:
+    kvasir capabilities
+    kvasir capabilities --format json | jq '.parsers[].name'")]
+    Capabilities {
+        #[arg(
+            long,
+            env = "KVASIR_CAPABILITIES_FORMAT",
+            value_enum,
+            default_value = "text"
+        )]
+        /// The format of the description: `text` or `json`.
+        format: capabilities::Format,
+    },
+
+    /// Replace this kvasir with the latest release for this platform, once the release's
+    /// checksum, and its signature by the kvasir release key, have been verified.
+    #[command(after_help = "EXAMPLES:
+    kvasir self-update --check
+    sudo kvasir self-update
+    kvasir self-update --release-key 'AAAA 1111 BBBB 2222 CCCC 3333 DDDD 4444 0123 4567'")]
+    SelfUpdate {
+        #[arg(long, env = "KVASIR_SELF_UPDATE_CHECK")]
+        /// Only report whether a newer release is available, without installing it.
+        check: bool,
+        #[arg(long, env = "KVASIR_RELEASE_KEY")]
+        /// The fingerprint of the GPG key which must have signed the release's checksums,
+        /// instead of the kvasir release key, e.g. for releases of a fork.
+        release_key: Option<String>,
+        #[arg(long, env = "KVASIR_INSECURE_SKIP_SIGNATURE")]
+        /// Install a release whose checksums are not signed by the release key, verifying
+        /// only the checksum of the binary, which does not protect against a tampered
+        /// release.
+        insecure_skip_signature: bool,
+    },
+
+    /// Serve parse and render requests from editor plugins and other tools, as JSON-RPC 2.0
+    /// messages, one per line, over a Unix domain socket or, with a token, a local TCP
+    /// socket. Parsed source files are cached, and only parsed again once they change.
+    #[command(after_help = "EXAMPLES:
+    kvasir daemon
+    kvasir daemon --socket /tmp/kvasir.sock
+    kvasir daemon --listen 127.0.0.1:7979 --token-file ~/.config/kvasir/token")]
+    Daemon {
+        #[arg(long, env = "KVASIR_DAEMON_LISTEN")]
+        /// Listen on a TCP address, e.g. `127.0.0.1:7979`, instead of a Unix domain socket.
+        /// Requires a token, as any local user can connect.
+        listen: Option<String>,
+        #[arg(long, env = "KVASIR_DAEMON_SOCKET", conflicts_with = "listen")]
+        /// The Unix domain socket on which to listen, which only the user can connect to.
+        /// Defaults to `kvasir.sock` in `$XDG_RUNTIME_DIR`, or else in the temporary directory.
+        socket: Option<PathBuf>,
+        #[arg(long, env = "KVASIR_DAEMON_TOKEN", hide_env_values = true)]
+        /// The token which connections must give in an `authenticate` request before any
+        /// other request. Prefer the environment variable or `--token-file`, as command-line
+        /// arguments are visible to other users.
+        token: Option<String>,
+        #[arg(long, env = "KVASIR_DAEMON_TOKEN_FILE", conflicts_with = "token")]
+        /// A file holding the token which connections must give in an `authenticate` request.
+        token_file: Option<PathBuf>,
+        #[arg(long, env = "KVASIR_MAX_INCLUDE_DEPTH", value_parser = cli::count)]
+        /// The maximum depth of templates extended, included, imported or used as partials
+        /// from the root template of a render request.
+        max_include_depth: Option<usize>,
+        #[arg(long, env = "KVASIR_MAX_OUTPUT_SIZE", value_parser = cli::size, default_value = "100M")]
+        /// The maximum size in bytes, optionally followed by `K`, `M` or `G`, of the output
+        /// of a render request, after which rendering fails.
+        max_output_size: usize,
+        #[arg(long, env = "KVASIR_RENDER_TIMEOUT", value_parser = cli::seconds, default_value = "60")]
+        /// The maximum time in seconds that a render request may take, after which rendering
+        /// fails.
+        render_timeout: f64,
+    },
+
+    /// Run a Language Server Protocol server for templates over `stdin` and `stdout`, for
+    /// editors to complete fields of the template context and filters and functions, and to
+    /// report errors rendering the templates being edited with the parsed source files.
+    #[command(after_help = "EXAMPLES:
+    kvasir lsp --templates 'templates/*.tpl'
+    kvasir lsp --templates 'templates/*.tpl' --sources 'k8s/*.yaml' --per-file")]
+    Lsp {
+        #[arg(short, long, env = "KVASIR_TEMPLATES", value_parser = cli::glob_expression)]
+        /// A glob path expression to search for template files.
+        templates: String,
+        #[arg(long, env = "KVASIR_ENGINE", default_value = "tera")]
+        /// The template engine used to render templates: `tera` or `handlebars`.
+        engine: Engine,
+        #[arg(long, env = "KVASIR_PER_FILE")]
+        /// Render templates once for each parsed file, with the file available as `file`.
+        per_file: bool,
+        #[arg(long, env = "KVASIR_SOURCES", num_args = 1.., value_parser = cli::glob_expression)]
+        /// One or more glob path expressions to search for source files, in addition to any
+        /// sources defined in the configuration file. The source files are parsed when the
+        /// server starts.
+        sources: Vec<String>,
+        #[arg(long, env = "KVASIR_PROFILE")]
+        /// The name of a profile in the configuration file whose sources and variables should
+        /// be used.
+        profile: Option<String>,
+        #[arg(long = "var-file", env = "KVASIR_VAR_FILES")]
+        /// JSON, YAML or TOML files of template variables, overriding variables of the same
+        /// name in the configuration file.
+        var_files: Vec<PathBuf>,
+        #[arg(long = "parser-option", env = "KVASIR_PARSER_OPTIONS", num_args = 1.., value_parser = cli::parser_option)]
+        /// One or more parser options in the form `parser.option=value`, e.g.
+        /// `kubernetes.redact_secrets=true`.
+        parser_options: Vec<String>,
+    },
+
+    /// Print a shell completion script for kvasir to `stdout`.
+    ///
+    /// For example, `kvasir completions bash > /etc/bash_completion.d/kvasir`.
+    #[command(after_help = "EXAMPLES:
+    kvasir completions bash > /etc/bash_completion.d/kvasir
+    kvasir completions zsh > \"${fpath[1]}/_kvasir\"")]
+    Completions {
+        #[arg(env = "KVASIR_COMPLETIONS_SHELL")]
+        /// The shell to generate completions for.
+        shell: clap_complete::Shell,
+    },
+
+    /// Print the kvasir man page to `stdout`, or write man pages for kvasir and each
+    /// subcommand to a directory.
+    #[command(after_help = "EXAMPLES:
+    kvasir man | man -l -
+    kvasir man --dir /usr/local/share/man/man1")]
+    Man {
+        #[arg(long, env = "KVASIR_MAN_DIR")]
+        /// The directory in which to write a man page for kvasir and each subcommand.
+        dir: Option<String>,
+    },
+
+    /// Time the glob, parse and render pipeline over generated corpora of varying sizes.
+    #[command(hide = true)]
+    Bench {
+        #[arg(
+            long,
+            env = "KVASIR_BENCH_SIZES",
+            value_delimiter = ',',
+            default_value = "10,100,1000"
+        )]
+        /// The numbers of files in each generated corpus.
+        sizes: Vec<usize>,
+        #[arg(long, env = "KVASIR_BENCH_ITERATIONS", default_value = "3")]
+        /// The number of times to run the pipeline for each corpus.
+        iterations: u32,
+        #[arg(long, env = "KVASIR_BENCH_DIR")]
+        /// The directory in which to generate corpora, which is kept. Defaults to a
+        /// temporary directory, which is removed.
+        dir: Option<String>,
+    },
 }
 
-/// Application entry point.
-fn main() -> Result<(), Error> {
-    let opts = CLOptions::from_args();
+/// Application entry point, exiting with a code from [`exit`] describing why the run failed,
+/// if it did.
+fn main() {
+    let command = cli::split_env_lists(CLOptions::command(), &|name| std::env::var(name).ok());
+    let opts = command
+        .try_get_matches()
+        .and_then(|matches| CLOptions::from_arg_matches(&matches))
+        .unwrap_or_else(|e| {
+            let _ = e.print();
+            std::process::exit(match e.use_stderr() {
+                true => exit::USAGE,
+                false => exit::SUCCESS,
+            })
+        });
+    if let Err(e) = start(opts) {
+        eprintln!("Error: {:?}", e);
+        std::process::exit(exit::code(&e));
+    }
+}
 
+/// Run kvasir with the command-line options.
+fn start(opts: CLOptions) -> Result<(), Error> {
     // Initialise the logger
-    env_logger::init_from_env(logger_environment(opts.debug));
+    let verbose = if opts.debug {
+        opts.verbose.max(2)
+    } else {
+        opts.verbose
+    };
+    logging::init(opts.quiet, verbose, opts.color);
+
+    let config = Config::load(opts.config.as_deref())?;
+    cancel::install()?;
+    clock::freeze(opts.frozen_time)?;
+    let _trace = opts.trace_output.as_deref().map(trace::init).transpose()?;
+    if opts.check_update && !matches!(opts.cmd, Command::SelfUpdate { .. }) {
+        update::warn_if_outdated(&opts.release_url);
+    }
+
+    let notify = matches!(
+        opts.cmd,
+        Command::Document {
+            no_notify: false,
+            ..
+        }
+    );
+    let metrics = match &opts.cmd {
+        Command::Document {
+            metrics_output: Some(output),
+            metrics_format,
+            metrics_labels,
+            ..
+        } => Some(metrics::MetricsOptions {
+            output: output.to_owned(),
+            format: *metrics_format,
+            labels: metrics_labels.to_owned(),
+        }),
+        _ => None,
+    };
+    let notify_config = config.notify.to_owned();
+    let site_url = config.pages.base_url.to_owned();
+    let started = Instant::now();
+    let result = run(opts.cmd, config, &opts.release_url);
+    if notify {
+        notify::send(&notify_config, site_url.as_deref(), result.as_ref().err());
+    }
+    if let Some(metrics) = metrics {
+        metrics::send(&metrics, started.elapsed(), result.as_ref().err());
+    }
+    result
+}
 
-    match opts.cmd {
-        Command::Parse { sources: globs } => {
-            let (successes, _failures) = parse_files(globs);
-            println!("{}", serde_json::to_string_pretty(&successes).unwrap())
+/// Run a sub-command with the project configuration.
+fn run(cmd: Command, mut config: Config, release_url: &str) -> Result<(), Error> {
+    match cmd {
+        Command::Parse {
+            sources: globs,
+            tags,
+            profile,
+            parser_options,
+            dedupe,
+            external_refs,
+            timeout,
+            include_failures,
+            fail_on_parse_failures,
+            output_schema,
+            canonical,
+        } => {
+            config.activate(profile.as_deref())?;
+            let options = ParserOptions::from_args(&parser_options)?;
+            let started = Instant::now();
+            let (mut successes, failures) = parse_files(
+                all_sources(&config, &globs, &tags)?,
+                &available_parsers(&options, timeout)?,
+                &options,
+                dedupe,
+                None,
+            )?;
+            cancel::exit_if_cancelled(&format!("parsed {} files", successes.len()));
+            assign_owners(&config, &mut successes)?;
+            if external_refs {
+                references::annotate(&mut successes);
+            }
+            canonicalize_contents(&config, &mut successes, canonical);
+            output_schema.conform(&mut successes);
+            if include_failures || output_schema == OutputSchema::V2 {
+                let report = ParseReport::new(
+                    &successes,
+                    &failures,
+                    remote::failures(),
+                    started.elapsed(),
+                    output_schema,
+                );
+                println!("{}", to_json(&report, canonical)?)
+            } else {
+                println!("{}", to_json(&successes, canonical)?)
+            }
+            if fail_on_parse_failures && !failures.is_empty() {
+                bail!(ErrorKind::ParseFailures(failures.len()))
+            }
         }
         Command::Document {
             sources: globs,
+            tags,
+            profile,
+            var_files,
             templates,
+            engine,
             root_template: base,
             split_files,
             split_delimiter,
             output_dir,
+            output_to_cwd: _,
             allow_overwrite,
+            output_version,
+            keep_versions,
+            front_matter,
+            front_matter_template,
+            parser_options,
+            dedupe,
+            external_refs,
+            manifest,
+            incremental,
+            resume,
+            sign,
+            signing_key,
+            coverage,
+            min_coverage,
+            lint,
+            fail_on_secrets,
+            fail_on_parse_failures,
+            search_index,
+            max_page_size,
+            check_output,
+            spell_check,
+            index: index_page,
+            index_group_by,
+            index_template,
+            publish,
+            no_notify: _,
+            metrics_output: _,
+            metrics_format: _,
+            metrics_labels: _,
+            per_file,
+            chunk_size,
+            per_directory,
+            jobs,
+            allow_network,
+            cache_dir,
+            allow_exec,
+            locale,
+            timeout,
+            max_include_depth,
+            max_output_size,
+            render_timeout,
+            profile_templates,
+            stats,
+            distinct,
+            drift,
+            output_schema,
         } => {
-            match create_tera_instance(templates.as_str()).as_mut() {
-                Ok(tera) => {
-                    let root_template = get_base_template(
-                        templates,
-                        tera.get_template_names().collect_vec().as_slice(),
-                        base,
+            config.activate(profile.as_deref())?;
+            config.add_var_files(&var_files)?;
+            config.check_vars()?;
+            let options = ParserOptions::from_args(&parser_options)?;
+            if profile_templates {
+                templates::profile::enable();
+            }
+            if let Some(jobs) = jobs {
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(jobs)
+                    .build_global()
+                    .map_err(|e| e.to_string())?;
+            }
+            let base_dir = output_dir.unwrap_or_else(|| std::env::current_dir().unwrap());
+            let output_dir = match &output_version {
+                Some(version) => history::create(&base_dir, version)?,
+                None => base_dir.to_owned(),
+            };
+            let dictionary = if spell_check {
+                Some(spelling::Dictionary::load(&config.spelling)?)
+            } else {
+                None
+            };
+            let bucket = if publish {
+                Some(publish::Bucket::new(
+                    &config.publish,
+                    publish::Credentials::from_env()?,
+                )?)
+            } else {
+                None
+            };
+            let function_options = FunctionOptions {
+                parser_options: options.to_owned(),
+                http_options: HttpOptions {
+                    allow_network,
+                    cache_dir,
+                },
+                exec_options: ExecOptions {
+                    allow_exec,
+                    allowed: config.exec.allow.to_owned(),
+                },
+                translations: Translations::load(&config.i18n, locale.as_deref())?,
+                page_options: PageOptions::new(
+                    &config.pages,
+                    match config.pages.pattern {
+                        Some(_) => None,
+                        None => Manifest::read(&output_dir).unwrap_or_else(|e| {
+                            warn!("Could not read the previous manifest: {}", e);
+                            None
+                        }),
+                    },
+                ),
+            };
+            match Templates::load(engine, templates.as_str(), &function_options) {
+                Ok(loaded) => {
+                    let template =
+                        get_base_template(templates.as_str(), &loaded.dependencies(), base)?;
+                    let limits = RenderLimits::new(
+                        max_include_depth,
+                        max_output_size,
+                        render_timeout.map(Duration::from_secs_f64),
                     );
-                    if let Some(template) = root_template {
-                        // Add custom filters
-                        templates::filters::register_filters(tera);
-                        templates::functions::register_functions(tera);
-                        let (successes, _failures) = parse_files(globs);
-                        let rendered_contents = render_template(tera, &template, successes);
-                        if split_files {
-                            match split_template_content(
-                                split_delimiter.as_str(),
-                                rendered_contents.as_str(),
-                                output_dir.map_or_else(
-                                    || std::env::current_dir().unwrap(),
-                                    |p| Path::new(p.as_str()).to_path_buf(),
-                                ),
-                            ) {
-                                Ok(entries) => write_rendered_files(entries, allow_overwrite),
-                                Err(e) => {
-                                    error!("Could not split template content: {}", e.to_string())
+                    limits.check_depth(&template, &loaded.dependencies())?;
+
+                    // Compare sources and templates with the previous manifest, if any
+                    let sources = all_sources(&config, &globs, &tags)?;
+                    let source_paths = list_files(sources.to_owned())
+                        .0
+                        .into_iter()
+                        .map(|(p, _)| p)
+                        .filter(|p| p.is_file())
+                        .collect_vec();
+                    let previous = if incremental {
+                        Manifest::read(&output_dir)?
+                    } else {
+                        None
+                    };
+                    let template_checksums = loaded.checksums()?;
+                    let (previous, changed_sources) = match previous {
+                        Some(m)
+                            if !m.templates_changed(
+                                &engine.to_string(),
+                                &template,
+                                &template_checksums,
+                            ) =>
+                        {
+                            let changed = m.changed_sources(&manifest::checksums(&source_paths)?);
+                            (Some(m), changed)
+                        }
+                        _ => (None, Default::default()),
+                    };
+                    if let Some(m) = &previous {
+                        if changed_sources.is_empty()
+                            && m.files.iter().all(|f| {
+                                manifest::file_sha256(&output_dir.join(&f.path))
+                                    .is_ok_and(|h| h == f.sha256)
+                            })
+                        {
+                            info!("Output is up to date.");
+                            return Ok(());
+                        }
+                        info!("{} source files changed.", changed_sources.len());
+                    }
+
+                    let mut parsed = None;
+                    if resume {
+                        // Parser options are part of the cache's key, but the timeout is not
+                        let version = format!("{} {:?}", env!("CARGO_PKG_VERSION"), timeout);
+                        let (generated, cache) = progress::start(&output_dir, &version)?;
+                        // Output is generated at the time of the interrupted run, unless the
+                        // time is frozen, so that output holding the time can be recognised
+                        if !clock::is_frozen() {
+                            clock::freeze(Some(generated))?;
+                        }
+                        parsed = Some(cache);
+                    }
+                    let parse_started = Instant::now();
+                    let (mut successes, failures) = parse_files(
+                        sources.to_owned(),
+                        &available_parsers(&options, timeout)?,
+                        &options,
+                        dedupe,
+                        parsed.as_mut(),
+                    )?;
+                    // Closes the progress file of parsed files, which is removed once finished
+                    drop(parsed);
+                    metrics::phase("parse", parse_started);
+                    cancel::exit_if_cancelled("no files were written");
+                    assign_owners(&config, &mut successes)?;
+                    if external_refs {
+                        references::annotate(&mut successes);
+                    }
+                    if fail_on_secrets {
+                        secrets::report(&secrets::scan(&successes), true)?;
+                    }
+                    notify::record(|s| {
+                        s.sources_parsed = successes.iter().map(|s| &s.path).unique().count();
+                        s.parse_failures = failures.len();
+                    });
+                    if fail_on_parse_failures && !failures.is_empty() {
+                        bail!(ErrorKind::ParseFailures(failures.len()))
+                    }
+                    let coverage = coverage || min_coverage.is_some();
+                    let parsed_sources = if coverage {
+                        successes
+                            .iter()
+                            .map(|s| s.path.to_owned())
+                            .unique()
+                            .collect()
+                    } else {
+                        vec![]
+                    };
+                    let violations = if lint {
+                        lint::check(&config.rules, &successes)?
+                    } else {
+                        vec![]
+                    };
+                    let context = add_drift(
+                        add_stats(
+                            TemplateContext::new(successes, &failures, &config, output_schema),
+                            stats,
+                            &config,
+                            &distinct,
+                        )?,
+                        drift,
+                        &sources,
+                    );
+                    let mut index = search::SearchIndex::default();
+                    if search_index {
+                        index.add_sources(&context.files);
+                    }
+                    let spelling_files = match dictionary {
+                        Some(_) => context.files.to_owned(),
+                        None => vec![],
+                    };
+                    let render_started = Instant::now();
+                    let rendered = if per_file {
+                        loaded.render_each_file(&template, context, &limits)
+                    } else if let Some(size) = chunk_size {
+                        context
+                            .chunks(size)
+                            .into_par_iter()
+                            .map(|chunk| loaded.render(&template, chunk, &limits))
+                            .collect()
+                    } else if per_directory {
+                        context
+                            .directories()
+                            .into_par_iter()
+                            .map(|directory| loaded.render(&template, directory, &limits))
+                            .collect()
+                    } else {
+                        vec![loaded.render(&template, context, &limits)]
+                    };
+                    let rendered_contents = rendered
+                        .into_iter()
+                        .map(|r| {
+                            r.unwrap_or_else(|e| {
+                                error!("Could not render template: {}", e);
+                                notify::record(|s| s.render_failures += 1);
+                                "".to_string()
+                            })
+                        })
+                        .collect::<Vec<_>>()
+                        .concat();
+                    metrics::phase("render", render_started);
+                    if profile_templates {
+                        templates::profile::report()
+                            .iter()
+                            .for_each(|line| logging::summary(line));
+                    }
+                    if split_files {
+                        let front_matter_template =
+                            front_matter_template.map(fs::read_to_string).transpose()?;
+                        let index_template = index_template.map(fs::read_to_string).transpose()?;
+                        match output::split_template_content(
+                            split_delimiter.as_str(),
+                            rendered_contents.as_str(),
+                            output_dir.to_owned(),
+                        )
+                        .and_then(|mut entries| {
+                            if let Some(index) = &index_page {
+                                let page = sitemap::index_page(
+                                    &entries,
+                                    &output_dir,
+                                    index,
+                                    index_group_by,
+                                    index_template.as_deref(),
+                                )?;
+                                entries.retain(|e| e.path != page.path);
+                                entries.push(page);
+                            }
+                            Ok(entries)
+                        })
+                        .map(|entries| match max_page_size {
+                            Some(size) => pagination::paginate(entries, size),
+                            None => entries,
+                        })
+                        .and_then(|mut entries| {
+                            if front_matter || front_matter_template.is_some() {
+                                output::add_front_matter(
+                                    &mut entries,
+                                    front_matter_template.as_deref(),
+                                )?;
+                            }
+                            Ok(entries)
+                        }) {
+                            Ok(entries) => {
+                                output::check_collisions(&entries)?;
+                                // Only write stale files when rendering incrementally
+                                let (stale, unchanged): (Vec<_>, Vec<_>) =
+                                    entries.iter().cloned().partition(|e| {
+                                        previous.as_ref().is_none_or(|m| {
+                                            m.is_stale(e, &changed_sources, &output_dir)
+                                        })
+                                    });
+                                if previous.is_some() {
+                                    info!("{} output files unchanged.", unchanged.len());
+                                }
+                                let write_started = Instant::now();
+                                let (resumed, stale): (Vec<_>, Vec<_>) =
+                                    stale.into_iter().partition(progress::is_written);
+                                if !resumed.is_empty() {
+                                    info!(
+                                        "{} output files were written by the interrupted run.",
+                                        resumed.len()
+                                    );
+                                }
+                                let written = output::write_rendered_files(&stale, allow_overwrite)
+                                    .into_iter()
+                                    .chain(resumed.into_iter().map(|e| e.path))
+                                    .unique()
+                                    .collect_vec();
+                                metrics::phase("write", write_started);
+                                notify::record(|s| {
+                                    s.pages_written = written.len();
+                                    s.pages_unchanged = unchanged.len();
+                                });
+                                output::run_hooks(&config.hooks, &written, &output_dir);
+                                check_failures()?;
+                                // Reports are written before the manifest, so that they are signed
+                                let coverage_report = coverage.then(|| {
+                                    let report = coverage::Coverage::new(&parsed_sources, &entries);
+                                    match report.write(&output_dir) {
+                                        Ok(path) => info!("Wrote coverage {}", path.display()),
+                                        Err(e) => error!("Could not write coverage: {}", e),
+                                    }
+                                    report
+                                });
+                                if search_index {
+                                    index.add_pages(&entries, &output_dir);
+                                    match index.write(&output_dir) {
+                                        Ok(path) => info!("Wrote search index {}", path.display()),
+                                        Err(e) => error!("Could not write search index: {}", e),
+                                    }
+                                }
+                                if index_page.as_deref().is_some_and(sitemap::is_html) {
+                                    match &config.pages.base_url {
+                                        Some(base_url) => match sitemap::write_sitemap(
+                                            &entries,
+                                            &output_dir,
+                                            base_url,
+                                        ) {
+                                            Ok(path) => info!("Wrote sitemap {}", path.display()),
+                                            Err(e) => error!("Could not write sitemap: {}", e),
+                                        },
+                                        None => warn!(
+                                            "No sitemap was written as no base_url is configured in the [pages] section"
+                                        ),
+                                    }
+                                }
+                                if manifest || incremental || sign.is_some() {
+                                    let generated = written
+                                        .iter()
+                                        .cloned()
+                                        .chain(unchanged.into_iter().map(|e| e.path))
+                                        .unique()
+                                        .collect_vec();
+                                    let reports = [
+                                        history::VERSION_FILE,
+                                        coverage::COVERAGE_FILE,
+                                        search::SEARCH_INDEX_FILE,
+                                        sitemap::SITEMAP_FILE,
+                                    ]
+                                    .iter()
+                                    .map(|f| output_dir.join(f))
+                                    .filter(|f| f.is_file())
+                                    .collect_vec();
+                                    match Manifest::new(
+                                        &engine.to_string(),
+                                        &template,
+                                        template_checksums,
+                                        &source_paths,
+                                        &entries,
+                                        &generated,
+                                        &output_dir,
+                                    )
+                                    .and_then(|mut m| {
+                                        m.add_reports(&reports, &output_dir)?;
+                                        m.write(&output_dir)
+                                    }) {
+                                        Ok(path) => {
+                                            info!("Wrote manifest {}", path.display());
+                                            if let Some(sign) = sign {
+                                                let signature = signing::sign(
+                                                    &output_dir,
+                                                    sign,
+                                                    signing_key.as_deref(),
+                                                )?;
+                                                info!("Signed manifest {}", signature.display());
+                                            }
+                                        }
+                                        Err(e) => error!("Could not write manifest: {}", e),
+                                    }
                                 }
-                            };
-                        } else {
-                            println!("{}", rendered_contents);
+                                if let Some(report) = coverage_report {
+                                    report.report(min_coverage)?;
+                                }
+                                if check_output {
+                                    check::report(&check::check_pages(
+                                        &entries,
+                                        &output_dir,
+                                        &template,
+                                    ))?;
+                                }
+                                if let Some(dictionary) = &dictionary {
+                                    spelling::report(
+                                        &spelling::check(
+                                            &entries,
+                                            &output_dir,
+                                            &spelling_files,
+                                            dictionary,
+                                        ),
+                                        config.spelling.severity.unwrap_or(Severity::Warning),
+                                    )?;
+                                }
+                                if let Some(bucket) = &bucket {
+                                    let files = entries
+                                        .iter()
+                                        .map(|e| e.path.to_owned())
+                                        .chain(
+                                            [
+                                                manifest::MANIFEST_FILE,
+                                                coverage::COVERAGE_FILE,
+                                                search::SEARCH_INDEX_FILE,
+                                                sitemap::SITEMAP_FILE,
+                                            ]
+                                            .iter()
+                                            .map(|f| output_dir.join(f))
+                                            .chain(sign.map(|s| s.signature_file(&output_dir)))
+                                            .filter(|f| f.is_file()),
+                                        )
+                                        .unique()
+                                        .collect_vec();
+                                    let publish_started = Instant::now();
+                                    let summary = bucket.publish(&files, &output_dir)?;
+                                    metrics::phase("publish", publish_started);
+                                    notify::record(|s| s.published = Some(summary.uploaded));
+                                    info!(
+                                        "Published {} files, {} unchanged.",
+                                        summary.uploaded, summary.unchanged
+                                    );
+                                }
+                                if let Some(keep) = keep_versions {
+                                    for removed in history::prune(&base_dir, keep, &output_dir)? {
+                                        info!("Removed version {}", removed.display());
+                                    }
+                                }
+                            }
+                            Err(e) => bail!(ErrorKind::OutputFailure(format!(
+                                "Could not split template content: {}",
+                                e
+                            ))),
+                        };
+                    } else {
+                        if manifest {
+                            warn!("A manifest is only written with --split-files");
                         }
+                        println!("{}", rendered_contents);
+                        check_failures()?;
+                    }
+                    if lint {
+                        lint::report(&violations)?;
+                    }
+                    if resume {
+                        progress::finish()?;
                     }
                 }
-                Err(e) => error!("Could not parse templates: {:?}", e),
+                Err(e) => bail!(ErrorKind::TemplateFailure(format!(
+                    "Could not parse templates: {}",
+                    e
+                ))),
             }
         }
-        Command::Parsers {} => parsers::parsers()
-            .iter()
-            .for_each(|p| println!("{}", p.name())),
+        Command::Context {
+            sources: globs,
+            tags,
+            profile,
+            var_files,
+            parser_options,
+            dedupe,
+            external_refs,
+            stats,
+            distinct,
+            drift,
+            output_schema,
+            canonical,
+        } => {
+            config.activate(profile.as_deref())?;
+            config.add_var_files(&var_files)?;
+            config.check_vars()?;
+            let options = ParserOptions::from_args(&parser_options)?;
+            let sources = all_sources(&config, &globs, &tags)?;
+            let (mut successes, failures) = parse_files(
+                sources.to_owned(),
+                &parsers::parsers_with_options(&options),
+                &options,
+                dedupe,
+                None,
+            )?;
+            assign_owners(&config, &mut successes)?;
+            if external_refs {
+                references::annotate(&mut successes);
+            }
+            canonicalize_contents(&config, &mut successes, canonical);
+            let context = add_drift(
+                add_stats(
+                    TemplateContext::new(successes, &failures, &config, output_schema),
+                    stats,
+                    &config,
+                    &distinct,
+                )?,
+                drift,
+                &sources,
+            );
+            println!("{}", to_json(&context, canonical)?)
+        }
+        Command::Stats {
+            sources: globs,
+            tags,
+            profile,
+            parser_options,
+            distinct,
+        } => {
+            config.activate(profile.as_deref())?;
+            let options = ParserOptions::from_args(&parser_options)?;
+            let (successes, failures) = parse_files(
+                all_sources(&config, &globs, &tags)?,
+                &parsers::parsers_with_options(&options),
+                &options,
+                false,
+                None,
+            )?;
+            let context = TemplateContext::new(successes, &failures, &config, OutputSchema::LATEST);
+            let distinct = config.stats.distinct.iter().chain(&distinct).cloned();
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&stats::Stats::new(
+                    &context.files,
+                    context.failures.len(),
+                    &distinct.collect_vec()
+                )?)?
+            )
+        }
+        Command::Drift {
+            sources: globs,
+            profile,
+            parser_options,
+        } => {
+            config.activate(profile.as_deref())?;
+            let options = ParserOptions::from_args(&parser_options)?;
+            let sources = all_sources(&config, &globs, &[])?;
+            let (successes, failures) = parse_files(
+                sources.to_owned(),
+                &parsers::parsers_with_options(&options),
+                &options,
+                false,
+                None,
+            )?;
+            let context = TemplateContext::new(successes, &failures, &config, OutputSchema::LATEST)
+                .with_drift(&source_globs(&sources));
+            println!("{}", serde_json::to_string_pretty(&context.drift)?)
+        }
+        Command::Lint {
+            sources: globs,
+            tags,
+            profile,
+            parser_options,
+            json,
+        } => {
+            config.activate(profile.as_deref())?;
+            let options = ParserOptions::from_args(&parser_options)?;
+            let (successes, _) = parse_files(
+                all_sources(&config, &globs, &tags)?,
+                &parsers::parsers_with_options(&options),
+                &options,
+                false,
+                None,
+            )?;
+            let violations = lint::check(&config.rules, &successes)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&violations)?)
+            }
+            lint::report(&violations)?
+        }
+        Command::Secrets {
+            sources: globs,
+            tags,
+            profile,
+            parser_options,
+            json,
+            fail_on_secrets,
+        } => {
+            config.activate(profile.as_deref())?;
+            let options = ParserOptions::from_args(&parser_options)?;
+            let (successes, _) = parse_files(
+                all_sources(&config, &globs, &tags)?,
+                &parsers::parsers_with_options(&options),
+                &options,
+                false,
+                None,
+            )?;
+            let findings = secrets::scan(&successes);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&findings)?)
+            }
+            secrets::report(&findings, fail_on_secrets)?
+        }
+        Command::CheckLinks {
+            output_dir,
+            templates,
+            allow_network,
+            link_timeout,
+            json,
+        } => {
+            let manifest = match Manifest::read(&output_dir)? {
+                Some(manifest) => manifest,
+                None => bail!(format!(
+                    "No {} in {}, which is written by kvasir document --manifest",
+                    manifest::MANIFEST_FILE,
+                    output_dir.display()
+                )),
+            };
+            let pages = check::manifest_pages(&manifest, &output_dir);
+            info!("Checking links in {} pages.", pages.len());
+            let mut problems = check::check_links(&pages, &output_dir, &manifest.template);
+            if allow_network {
+                problems.extend(check::external::check_urls(
+                    &pages,
+                    &output_dir,
+                    &manifest.template,
+                    std::time::Duration::from_secs_f64(link_timeout),
+                ));
+            }
+            if let Some(templates) = templates {
+                let paths = glob::glob(&paths::strip_verbatim(&templates))
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|p| p.ok())
+                    .collect_vec();
+                check::locate(&mut problems, &paths);
+            }
+            if json {
+                println!("{}", serde_json::to_string_pretty(&problems)?)
+            }
+            check::report(&problems)?
+        }
+        Command::Verify { output_dir, sign } => {
+            let verification = signing::verify(&output_dir, sign)?;
+            for path in &verification.changed {
+                error!("{} does not match the signed manifest", path.display());
+            }
+            for path in &verification.unlisted {
+                error!("{} is not listed in the signed manifest", path.display());
+            }
+            if !verification.is_empty() {
+                bail!(ErrorKind::ValidationFailure(format!(
+                    "{} files do not match the signed manifest",
+                    verification.changed.len() + verification.unlisted.len()
+                )))
+            }
+            info!("The manifest and the files it lists match the signature.");
+        }
+        Command::Test {
+            templates,
+            engine,
+            root_template: base,
+            cases,
+            update,
+            profile,
+            var_files,
+            parser_options,
+            allow_network,
+            cache_dir,
+            allow_exec,
+            locale,
+            stats,
+            distinct,
+            output_schema,
+        } => {
+            config.activate(profile.as_deref())?;
+            config.add_var_files(&var_files)?;
+            config.check_vars()?;
+            let options = ParserOptions::from_args(&parser_options)?;
+            let available_parsers = parsers::parsers_with_options(&options);
+            let function_options = FunctionOptions {
+                parser_options: options.to_owned(),
+                http_options: HttpOptions {
+                    allow_network,
+                    cache_dir,
+                },
+                exec_options: ExecOptions {
+                    allow_exec,
+                    allowed: config.exec.allow.to_owned(),
+                },
+                translations: Translations::load(&config.i18n, locale.as_deref())?,
+                page_options: PageOptions::new(&config.pages, None),
+            };
+            let loaded = Templates::load(engine, templates.as_str(), &function_options)?;
+            let template = get_base_template(templates.as_str(), &loaded.dependencies(), base)?;
+            snapshots::run_cases(&cases, update, |case| {
+                let (successes, failures) = parse_files(
+                    vec![Source::from(case.sources_glob().as_str())],
+                    &available_parsers,
+                    &options,
+                    false,
+                    None,
+                )?;
+                loaded.render(
+                    &template,
+                    add_stats(
+                        TemplateContext::new(successes, &failures, &config, output_schema),
+                        stats,
+                        &config,
+                        &distinct,
+                    )?,
+                    &RenderLimits::default(),
+                )
+            })?
+        }
+        Command::Bench {
+            sizes,
+            iterations,
+            dir,
+        } => {
+            let path = dir.as_ref().map_or_else(bench::default_dir, PathBuf::from);
+            let timings = bench::run(&path, &sizes, iterations);
+            if dir.is_none() {
+                fs::remove_dir_all(&path)?;
+            }
+            println!(
+                "{:>8} {:>12} {:>12} {:>12}",
+                "files", "list", "parse", "render"
+            );
+            for t in timings? {
+                println!(
+                    "{:>8} {:>12?} {:>12?} {:>12?}",
+                    t.files, t.list, t.parse, t.render
+                );
+            }
+        }
+        Command::Parsers {} => {
+            parsers::parsers()
+                .iter()
+                .for_each(|p| println!("{}", p.name()));
+            parsers::directory_parsers(&ParserOptions::default())
+                .iter()
+                .for_each(|p| println!("{}", p.name()));
+        }
+        Command::Capabilities { format } => {
+            let capabilities = capabilities::Capabilities::new(&CLOptions::command());
+            match format {
+                capabilities::Format::Text => capabilities
+                    .lines()
+                    .iter()
+                    .for_each(|line| println!("{}", line)),
+                capabilities::Format::Json => {
+                    println!("{}", serde_json::to_string_pretty(&capabilities)?)
+                }
+            }
+        }
+        Command::SelfUpdate {
+            check,
+            release_key,
+            insecure_skip_signature,
+        } => {
+            let target = update::current_exe()?;
+            let signature = update::SignatureOptions {
+                key: release_key,
+                insecure_skip: insecure_skip_signature,
+            };
+            match update::update(release_url, &target, check, &signature)? {
+                update::Outcome::UpToDate(version) => {
+                    logging::summary(&format!("kvasir {} is the latest release.", version))
+                }
+                update::Outcome::Available(version) => logging::summary(&format!(
+                    "kvasir {} is available, and this is {}.",
+                    version,
+                    update::VERSION
+                )),
+                update::Outcome::Updated(version) => logging::summary(&format!(
+                    "Updated {} from kvasir {} to {}.",
+                    target.display(),
+                    update::VERSION,
+                    version
+                )),
+            }
+        }
+        Command::Daemon {
+            listen,
+            socket,
+            token,
+            token_file,
+            max_include_depth,
+            max_output_size,
+            render_timeout,
+        } => {
+            let token = match token_file {
+                Some(path) => Some(daemon::read_token(&path)?),
+                None => token,
+            };
+            let options = daemon::DaemonOptions {
+                listen,
+                socket,
+                token,
+                max_include_depth,
+                max_output_size: Some(max_output_size),
+                render_timeout: Some(Duration::from_secs_f64(render_timeout)),
+            };
+            daemon::serve(config, options)?
+        }
+        Command::Lsp {
+            templates,
+            engine,
+            per_file,
+            sources: globs,
+            profile,
+            var_files,
+            parser_options,
+        } => {
+            config.activate(profile.as_deref())?;
+            config.add_var_files(&var_files)?;
+            config.check_vars()?;
+            let options = ParserOptions::from_args(&parser_options)?;
+            // Templates can be edited before there are any source files
+            let (successes, failures) = match parse_files(
+                all_sources(&config, &globs, &[])?,
+                &parsers::parsers_with_options(&options),
+                &options,
+                false,
+                None,
+            ) {
+                Err(e) if matches!(e.kind(), ErrorKind::NoSources(_)) => {
+                    warn!("{}", e);
+                    Default::default()
+                }
+                result => result?,
+            };
+            let lsp_options = lsp::LspOptions {
+                templates,
+                engine,
+                per_file,
+                parser_options: options,
+            };
+            lsp::serve(lsp::Server::new(lsp_options, config, successes, failures)?)?
+        }
+        Command::Completions { shell } => clap_complete::generate(
+            shell,
+            &mut CLOptions::command(),
+            "kvasir",
+            &mut std::io::stdout(),
+        ),
+        Command::Man { dir } => match dir {
+            Some(dir) => {
+                fs::create_dir_all(&dir)?;
+                clap_mangen::generate_to(CLOptions::command(), &dir)?;
+                info!("Wrote man pages to {}.", dir);
+            }
+            None => clap_mangen::Man::new(CLOptions::command()).render(&mut std::io::stdout())?,
+        },
     }
     Ok(())
 }
 
-/// Write rendered templates information to one or more files.
-///
-/// By default, this function will refuse to overwrite existing files unless
-/// `allow_overwrite` is set.
-fn write_rendered_files(entries: Vec<(PathBuf, String)>, allow_overwrite: bool) {
-    entries.iter().for_each(|(file, content)| {
-        if (file.exists() && allow_overwrite) || !file.exists() {
-            debug!("Writing output file {}", file.display());
-            match std::fs::create_dir_all(file.parent().unwrap())
-                .and_then(|_| fs::write(file, content))
-            {
-                Ok(_) => (),
-                Err(e) => error!(
-                    "Could not write output file {}: {}",
-                    file.display(),
-                    e.to_string()
-                ),
-            }
-        } else {
-            error!(
-                "Could not write output file {}: File exists.",
-                file.display()
-            )
-        }
+/// Return the available parsers, limiting the time each may take to parse a file to
+/// `timeout` seconds if provided.
+fn available_parsers(
+    options: &ParserOptions,
+    timeout: Option<f64>,
+) -> Result<Vec<Box<dyn FileParser>>, Error> {
+    let parsers = parsers::parsers_with_options(options);
+    Ok(match timeout {
+        Some(t) => parsers::with_timeout(
+            parsers,
+            std::time::Duration::try_from_secs_f64(t)
+                .map_err(|e| format!("Invalid timeout {}: {}", t, e))?,
+        ),
+        None => parsers,
     })
 }
 
-/// Split the contents of the output template into a list of paths and contents using the
-/// specified delimiter.
-///
-/// The default base output directory is the current directory, chosen to avoid the
-/// possibility of overwriting arbitrary files. All output files must be within the
-/// output directory or an error will be generated. All content before the first split
-/// is ignored.
-fn split_template_content(
-    delimiter: &str,
-    contents: &str,
-    output_dir: PathBuf,
-) -> Result<Vec<(PathBuf, String)>, Error> {
-    if !output_dir.is_dir() {
-        bail!("Output directory must exist and be a directory.");
-    }
-
-    let files = contents
-        .split(delimiter)
-        .filter_map(|f| match f.lines().collect_vec().as_slice() {
-            [first, remaining @ ..] => {
-                let of = Path::new(&output_dir).join(Path::new(first.trim())).clean();
-
-                Some((of, remaining.join(LINE_ENDING)))
-            }
-            [] => None,
-        })
-        .filter(|x| x.0 != output_dir) // Remove anything before the first split
-        .collect_vec();
-
-    for (p, _) in files.iter() {
-        if !p.starts_with(&output_dir) {
-            bail!(format!(
-                "Output file {} is not a child of {}",
-                p.display(),
-                output_dir.display()
-            ))
-        }
+/// Put the parsed contents of files in canonical form if `canonical` is set, sorting the
+/// arrays declared unordered in the configuration file.
+fn canonicalize_contents(config: &Config, successes: &mut [ParseSuccess], canonical: bool) {
+    if canonical {
+        successes
+            .iter_mut()
+            .for_each(|s| canonical::canonicalize(&mut s.contents, &config.canonical.unordered));
     }
+}
 
-    Ok(files)
+/// Write output as pretty-printed JSON, in canonical form if `canonical` is set.
+fn to_json<T: serde::Serialize>(output: &T, canonical: bool) -> Result<String, Error> {
+    let mut value = serde_json::to_value(output)?;
+    if canonical {
+        canonical::canonicalize(&mut value, &[]);
+    }
+    Ok(serde_json::to_string_pretty(&value)?)
 }
 
-fn render_template(tera: &tera::Tera, root_template: &str, successes: Vec<ParseSuccess>) -> String {
-    let mut context = Context::new();
-    context.insert("files", &successes);
-    tera.render(root_template, &context).unwrap_or_else(|e| {
-        error!("Could not render template: {:?}", e);
-        "".to_string()
-    })
+/// Add summary statistics of the parsed files to a template context if `stats` is set,
+/// counting the distinct values of the JSONPath expressions in the configuration file and
+/// those provided on the command line.
+fn add_stats(
+    context: TemplateContext,
+    stats: bool,
+    config: &Config,
+    distinct: &[String],
+) -> Result<TemplateContext, Error> {
+    if stats {
+        context.with_stats(
+            &config
+                .stats
+                .distinct
+                .iter()
+                .chain(distinct)
+                .cloned()
+                .collect_vec(),
+        )
+    } else {
+        Ok(context)
+    }
 }
 
-/// Find the base template to use, based on the number of templates and user choice.
+/// Add a report of the drift between the parsed files matched by each source glob to a
+/// template context if `drift` is set.
+fn add_drift(context: TemplateContext, drift: bool, sources: &[Source]) -> TemplateContext {
+    if drift {
+        context.with_drift(&source_globs(sources))
+    } else {
+        context
+    }
+}
+
+/// Return the paths matched by each source glob.
+fn source_globs(sources: &[Source]) -> Vec<(String, Vec<PathBuf>)> {
+    sources
+        .iter()
+        .map(|s| {
+            let (files, _) = list_files(vec![s.to_owned()]);
+            (
+                s.glob.to_owned(),
+                files.into_iter().map(|(p, _)| p).collect(),
+            )
+        })
+        .collect()
+}
+
+/// Combine the sources defined in the configuration file with those provided on the
+/// command line, applying any command line tags to the latter, and fetch any remote sources.
+fn all_sources(config: &Config, globs: &[String], tags: &[String]) -> Result<Vec<Source>, Error> {
+    let sources = config
+        .sources
+        .iter()
+        .cloned()
+        .chain(globs.iter().map(|g| Source::new(g, tags)))
+        .collect();
+    remote::resolve(
+        sources,
+        &config.remote,
+        Path::new(templates::http::DEFAULT_CACHE_DIR),
+    )
+}
+
+/// Fail if any template could not be rendered or output file could not be written during the
+/// run, once the output which could be written has been.
+fn check_failures() -> Result<(), Error> {
+    let summary = notify::summary();
+    if summary.render_failures > 0 {
+        bail!(ErrorKind::TemplateFailure(format!(
+            "{} templates could not be rendered",
+            summary.render_failures
+        )))
+    }
+    if summary.write_failures > 0 {
+        bail!(ErrorKind::OutputFailure(format!(
+            "{} output files could not be written",
+            summary.write_failures
+        )))
+    }
+    Ok(())
+}
+
+/// Find the root template to render, based on the templates available and user choice.
+///
+/// Templates that are only referenced by other templates, through `extends`, `include` or
+/// `import`, are not considered as root templates. If more than one candidate remains then
+/// the root template must be chosen explicitly.
 fn get_base_template(
-    template_expr: String,
-    template_names: &[&str],
+    template_expr: &str,
+    dependencies: &BTreeMap<String, BTreeSet<String>>,
     base_template: Option<String>,
-) -> Option<String> {
-    match template_names {
-        [] => {
-            error!("No templates found for glob expression: {}", template_expr);
-            None
+) -> Result<String, Error> {
+    dependencies
+        .iter()
+        .filter(|(_, references)| !references.is_empty())
+        .for_each(|(name, references)| {
+            debug!(
+                "Template {} references {}",
+                name,
+                references.iter().join(", ")
+            )
+        });
+
+    if let Some(base) = base_template {
+        if !dependencies.contains_key(&base) {
+            bail!(ErrorKind::TemplateFailure(format!(
+                "Root template {} not found in templates: {}",
+                base,
+                dependencies.keys().join(", ")
+            )));
         }
-        [single] => Some(single.to_string()),
-        [first, ..] => Some(base_template.map_or_else(
-            || {
-                warn!(
-                    "No base template specified. Using first template found: {}",
-                    first
-                );
-                first.to_string()
-            },
-            |b| b,
+        return Ok(base);
+    }
+
+    match templates::dependencies::root_candidates(dependencies).as_slice() {
+        [] if dependencies.is_empty() => bail!(ErrorKind::TemplateFailure(format!(
+            "No templates found for glob expression: {}",
+            template_expr
+        ))),
+        [] => bail!(ErrorKind::TemplateFailure(
+            "No root template found: all templates are referenced by other templates".to_string()
         )),
+        [single] => Ok(single.to_owned()),
+        candidates => bail!(ErrorKind::TemplateFailure(format!(
+            "Multiple root templates found, use --root-template to choose one of: {}",
+            candidates.join(", ")
+        ))),
     }
 }
 
 /// Parse a list of files using one or more parsers, returning a list of successes and failures.
 ///
 /// Each file is provided to each parser in turn, first to check whether it can be parsed and
-/// then to attempt to parse it. Parsing errors are not fatal and do not prevent continuing
-/// parsing remaining files.
-fn parse_files(globs: Vec<String>) -> (Vec<ParseSuccess>, Vec<ParseFailure>) {
-    let (files, errors) = list_files(globs);
+/// then to attempt to parse it. Directories containing the marker files of a directory
+/// parser are then parsed as a whole. Parsing errors are not fatal and do not prevent continuing
+/// parsing remaining files. No further files are parsed once cancellation has been requested.
+/// The results of files found in the cache, if given, are reused, and those of files parsed
+/// are added to it. Fails if the sources match no files.
+fn parse_files(
+    sources: Vec<Source>,
+    available_parsers: &[Box<dyn FileParser>],
+    options: &ParserOptions,
+    dedupe: bool,
+    mut cache: Option<&mut ParseCache>,
+) -> Result<(Vec<ParseSuccess>, Vec<ParseFailure>), Error> {
+    let globs = sources.iter().map(|s| s.glob.to_owned()).join(", ");
+    let (files, errors) = tracing::info_span!("list").in_scope(|| list_files(sources));
 
     info!("{} files to process.", &files.len());
 
@@ -335,55 +2151,183 @@ fn parse_files(globs: Vec<String>) -> (Vec<ParseSuccess>, Vec<ParseFailure>) {
     errors
         .iter()
         .for_each(|e| warn!("Error listing file: {}", e));
+    if files.is_empty() {
+        bail!(ErrorKind::NoSources(format!(
+            "No source files matched: {}",
+            globs
+        )))
+    }
 
-    let available_parsers = parsers::parsers();
+    // Each file is hashed once, to find duplicates and cached results
+    let hashes = hash_files(&files);
+    let duplicates = duplicate_files(&files, &hashes);
+    let parsed_files = match dedupe {
+        true => {
+            let deduped = dedupe_files(&files, &duplicates);
+            info!("{} files to parse without duplicates.", &deduped.len());
+            deduped
+        }
+        false => files.to_owned(),
+    };
 
-    let (successes, failures): (Vec<ParseSuccess>, Vec<ParseFailure>) = files
+    let _span = tracing::info_span!("parse", files = parsed_files.len()).entered();
+    let key = options.key();
+    let (mut successes, mut failures): (Vec<ParseSuccess>, Vec<ParseFailure>) = parsed_files
         .iter()
-        .map(|f| parse_file(f, &available_parsers))
+        .take_while(|_| !cancel::cancelled())
+        .map(|(f, tags)| {
+            let hash = hashes.get(f);
+            let cached = hash
+                .zip(cache.as_deref())
+                .and_then(|(h, c)| c.get(f, h, &key));
+            let (mut successes, failures) = cached.unwrap_or_else(|| {
+                let (successes, failures) = parse_file(f, available_parsers, options);
+                if let (Some(hash), Some(cache)) = (hash, cache.as_deref_mut()) {
+                    cache.insert(f, hash, &key, &successes, &failures);
+                }
+                (successes, failures)
+            });
+            successes.iter_mut().for_each(|s| {
+                s.tags = tags.to_owned();
+                s.duplicates = duplicates.get(f).cloned().unwrap_or_default();
+            });
+            (successes, failures)
+        })
         .fold((Vec::new(), Vec::new()), |mut last, mut curr| {
             last.0.append(&mut curr.0);
             last.1.append(&mut curr.1);
             (last.0, last.1)
         });
+    let (directory_successes, directory_failures) = parse_directories(&files, options);
+    successes.extend(directory_successes);
+    failures.extend(directory_failures);
 
     info!("{} parsers succeeded.", &successes.len());
     info!("{} parsers failed.", &failures.len());
 
-    (successes, failures)
+    Ok((successes, failures))
 }
 
-/// Return a new Tera instance with either a directory of templates or by reading a
-/// single template from `stdin`.
-fn create_tera_instance(templates: &str) -> Result<tera::Tera, tera::Error> {
-    use std::io::Read;
-    if templates.eq("-") {
-        let mut buf = String::new();
-        std::io::stdin().read_to_string(&mut buf)?;
-        let mut tera = tera::Tera::default();
-        tera.add_raw_template("root", buf.as_str())?;
-        Ok(tera)
-    } else {
-        tera::Tera::new(templates)
+/// Attach the owners of each parsed file from the CODEOWNERS file, if there is one.
+fn assign_owners(config: &Config, successes: &mut [ParseSuccess]) -> Result<(), Error> {
+    if let Some(codeowners) = CodeOwners::find(config.codeowners.as_deref())? {
+        codeowners.assign(successes);
     }
+    Ok(())
+}
+
+/// Return the hash of the contents of each local file. Files which are not local, such as
+/// remote sources, have no hash.
+fn hash_files(files: &[(PathBuf, Vec<String>)]) -> HashMap<PathBuf, String> {
+    files
+        .par_iter()
+        .filter(|(f, _)| f.is_file())
+        .filter_map(|(f, _)| {
+            manifest::file_sha256(f)
+                .ok()
+                .map(|hash| (f.to_owned(), hash))
+        })
+        .collect()
+}
+
+/// Return the other files with the same content as each file which has duplicates, in the
+/// order in which they were listed.
+fn duplicate_files(
+    files: &[(PathBuf, Vec<String>)],
+    hashes: &HashMap<PathBuf, String>,
+) -> HashMap<PathBuf, Vec<PathBuf>> {
+    let mut groups: HashMap<&str, Vec<&PathBuf>> = HashMap::new();
+    for (path, _) in files {
+        if let Some(hash) = hashes.get(path) {
+            groups.entry(hash).or_default().push(path);
+        }
+    }
+    groups
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .flat_map(|group| {
+            group
+                .iter()
+                .map(|path| {
+                    let others = group.iter().filter(|p| *p != path);
+                    (
+                        path.to_path_buf(),
+                        others.map(|p| p.to_path_buf()).collect(),
+                    )
+                })
+                .collect_vec()
+        })
+        .collect()
+}
+
+/// Remove the files whose content is the same as that of an earlier file, adding their tags
+/// to those of the earlier file.
+fn dedupe_files(
+    files: &[(PathBuf, Vec<String>)],
+    duplicates: &HashMap<PathBuf, Vec<PathBuf>>,
+) -> Vec<(PathBuf, Vec<String>)> {
+    let mut deduped: Vec<(PathBuf, Vec<String>)> = vec![];
+    for (path, tags) in files {
+        let original = duplicates
+            .get(path)
+            .and_then(|others| deduped.iter().position(|(p, _)| others.contains(p)));
+        match original {
+            Some(i) => {
+                debug!(
+                    "Skipping {}, a duplicate of {}",
+                    path.display(),
+                    deduped[i].0.display()
+                );
+                let original_tags = &mut deduped[i].1;
+                original_tags.extend(tags.iter().cloned());
+                *original_tags = original_tags.iter().unique().cloned().collect();
+            }
+            None => deduped.push((path.to_owned(), tags.to_owned())),
+        }
+    }
+    deduped
 }
 
-/// Return a list of all unique paths that match one or more glob expressions.
+/// Return a list of all unique paths that match one or more source glob expressions, with
+/// the tags of each source that matched them.
 ///
-/// Paths which appear in more than one glob expression are de-duplicated.
-fn list_files(globs: Vec<String>) -> (Vec<PathBuf>, Vec<GlobError>) {
-    globs
+/// Paths which appear in more than one glob expression are de-duplicated, with the tags
+/// from each matching source combined.
+fn list_files(sources: Vec<Source>) -> (Vec<(PathBuf, Vec<String>)>, Vec<GlobError>) {
+    let (paths, errors): (Vec<(PathBuf, &Vec<String>)>, Vec<GlobError>) = sources
         .iter()
-        .flat_map(|g| glob::glob(g))
-        .flatten()
-        .unique_by(|r| match r {
-            Ok(p) => p.to_owned(),
-            Err(e) => e.path().to_path_buf(),
+        .flat_map(|s| {
+            glob::glob(&paths::strip_verbatim(&s.glob))
+                .map(|paths| paths.map(move |p| (p.map(|p| paths::normalise(&p)), &s.tags)))
         })
-        .partition_map(|r| match r {
-            Ok(v) => Either::Left(v),
+        .flatten()
+        .partition_map(|(r, tags)| match r {
+            Ok(v) => Either::Left((v, tags)),
             Err(v) => Either::Right(v),
-        })
+        });
+
+    let mut files: Vec<(PathBuf, Vec<String>)> = vec![];
+    let mut indexes: HashMap<PathBuf, usize> = HashMap::new();
+    for (path, tags) in paths {
+        match indexes.get(&path) {
+            Some(&i) => files[i].1.extend(tags.iter().cloned()),
+            None => {
+                indexes.insert(path.to_owned(), files.len());
+                files.push((path, tags.to_owned()));
+            }
+        }
+    }
+    files
+        .iter_mut()
+        .for_each(|(_, tags)| *tags = tags.drain(..).unique().collect());
+
+    (
+        files,
+        errors
+            .into_iter()
+            .unique_by(|e| e.path().to_path_buf())
+            .collect(),
+    )
 }
 
 /// Parse a file with one or more file parsers, returning a list of successes and failures.
@@ -392,31 +2336,59 @@ fn list_files(globs: Vec<String>) -> (Vec<PathBuf>, Vec<GlobError>) {
 /// which is intended to be a computationally and IO-cheap activity. Parsers which indicate
 /// that they can parse a file are then called to parse it into a JSON structure.
 ///
-/// File contents are available to both the parsing and parse check code. The contents are
-/// retrieved in an efficient fashion so that they are never read more than once. Files
-/// are read fully into memory.
-fn parse_file(f: &Path, parsers: &[Box<dyn FileParser>]) -> (Vec<ParseSuccess>, Vec<ParseFailure>) {
+/// File contents are shared by every parser through a [`ParseContext`], which reads them
+/// lazily so that they are never read more than once. Files are read fully into memory.
+fn parse_file(
+    f: &Path,
+    parsers: &[Box<dyn FileParser>],
+    options: &ParserOptions,
+) -> (Vec<ParseSuccess>, Vec<ParseFailure>) {
     info!("{}:", f.display());
+    let _span = tracing::info_span!("file", path = %f.display()).entered();
 
-    let contents: OnceCell<String> = OnceCell::new();
-    let get_contents = || -> Result<&str, Error> {
-        let c = contents.get_or_try_init(|| fs::read_to_string(f))?;
-        Ok(c.as_str())
-    };
-
-    let (parsed, errors): (Vec<ParseSuccess>, Vec<ParseFailure>) = parsers
-        .iter()
-        .filter(|p| p.can_parse(f, get_contents()))
-        .partition_map(|p| match p.parse(f, get_contents()) {
-            Ok(c) => {
-                debug!("  succeeded parsing with {}.", p.name());
-                Either::Left(ParseSuccess {
-                    path: f.to_owned(),
-                    parser: p.name().to_owned(),
-                    contents: c,
-                })
+    let context = ParseContext::new(f, options);
+    let mut parsed = vec![];
+    let mut errors = vec![];
+    for p in parsers.iter().filter(|p| p.can_parse(&context)) {
+        let span = tracing::info_span!("parser", name = p.name(), path = %f.display());
+        match span.in_scope(|| p.parse_documents(&context)) {
+            Ok(mut documents) => {
+                debug!(
+                    "  succeeded parsing {} documents with {}.",
+                    documents.len(),
+                    p.name()
+                );
+                // Warnings recorded through the context apply to the file as a whole
+                if let Some(first) = documents.first_mut() {
+                    first.warnings.extend(context.take_warnings());
+                }
+                let several = documents.len() > 1;
+                parsed.extend(documents.into_iter().enumerate().map(|(index, document)| {
+                    document
+                        .warnings
+                        .iter()
+                        .for_each(|w| warn!("  warning from {} ({}).", p.name(), w));
+                    ParseSuccess {
+                        path: f.to_owned(),
+                        parser: p.name().to_owned(),
+                        index: several.then_some(index),
+                        name: document.name,
+                        contents: document.value,
+                        descriptions: document.descriptions,
+                        metadata: document.metadata,
+                        warnings: document.warnings,
+                        tags: vec![],
+                        owners: vec![],
+                        duplicates: vec![],
+                        external_refs: vec![],
+                    }
+                }));
             }
             Err(e) => {
+                context
+                    .take_warnings()
+                    .iter()
+                    .for_each(|w| debug!("  warning from {} ({}).", p.name(), w));
                 let failure = ParseFailure {
                     path: f.to_owned(),
                     parser: p.name().to_owned(),
@@ -427,9 +2399,86 @@ fn parse_file(f: &Path, parsers: &[Box<dyn FileParser>]) -> (Vec<ParseSuccess>,
                     &failure.parser,
                     &failure.error.to_string()
                 );
-                Either::Right(failure)
+                errors.push(failure);
             }
-        });
+        }
+    }
+
+    (parsed, errors)
+}
+
+/// Parse the directories containing marker files with the directory parsers, returning a
+/// list of successes and failures.
+///
+/// Each directory is parsed at most once by each parser, however many of its marker files
+/// were listed, and takes the tags of the marker files that were listed.
+fn parse_directories(
+    files: &[(PathBuf, Vec<String>)],
+    options: &ParserOptions,
+) -> (Vec<ParseSuccess>, Vec<ParseFailure>) {
+    let mut parsed = vec![];
+    let mut errors = vec![];
+    for p in parsers::directory_parsers(options) {
+        let mut directories: Vec<(&Path, Vec<String>)> = vec![];
+        for (file, tags) in files {
+            let is_marker = file
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| p.markers().contains(&n));
+            if let Some(dir) = file.parent().filter(|_| is_marker) {
+                match directories.iter_mut().find(|(d, _)| *d == dir) {
+                    Some((_, existing)) => existing.extend(tags.iter().cloned()),
+                    None => directories.push((dir, tags.to_owned())),
+                }
+            }
+        }
+
+        for (dir, tags) in directories.into_iter().take_while(|_| !cancel::cancelled()) {
+            let context = ParseContext::new(dir, options);
+            if !p.can_parse(&context) {
+                continue;
+            }
+            info!("{}:", dir.display());
+            let span = tracing::info_span!("parser", name = p.name(), path = %dir.display());
+            match span.in_scope(|| p.parse(&context)) {
+                Ok(mut document) => {
+                    debug!("  succeeded parsing directory with {}.", p.name());
+                    document.warnings.extend(context.take_warnings());
+                    document
+                        .warnings
+                        .iter()
+                        .for_each(|w| warn!("  warning from {} ({}).", p.name(), w));
+                    parsed.push(ParseSuccess {
+                        path: dir.to_owned(),
+                        parser: p.name().to_owned(),
+                        index: None,
+                        name: document.name,
+                        contents: document.value,
+                        descriptions: document.descriptions,
+                        metadata: document.metadata,
+                        warnings: document.warnings,
+                        tags: tags.into_iter().unique().collect(),
+                        owners: vec![],
+                        duplicates: vec![],
+                        external_refs: vec![],
+                    });
+                }
+                Err(e) => {
+                    let failure = ParseFailure {
+                        path: dir.to_owned(),
+                        parser: p.name().to_owned(),
+                        error: e,
+                    };
+                    warn!(
+                        "  failed parsing directory with {} ({}).",
+                        &failure.parser,
+                        &failure.error.to_string()
+                    );
+                    errors.push(failure);
+                }
+            }
+        }
+    }
 
     (parsed, errors)
 }
@@ -437,28 +2486,213 @@ fn parse_file(f: &Path, parsers: &[Box<dyn FileParser>]) -> (Vec<ParseSuccess>,
 #[cfg(test)]
 mod tests {
 
-    use std::str::FromStr;
-
-    use crate::{parsers, ParseSuccess};
+    use crate::config::{Config, Source};
+    use crate::templates::dependencies::template_dependencies;
+    use crate::{parsers, ParseSuccess, ParserOptions};
     use itertools::Itertools;
     use jsonpath_lib::select;
     use serde_json::json;
+    use std::path::PathBuf;
+
+    #[test]
+    fn command_line() {
+        use crate::{CLOptions, Command};
+        use clap::{CommandFactory, Parser};
+        CLOptions::command().debug_assert();
+
+        let opts = CLOptions::parse_from([
+            "kvasir",
+            "parse",
+            "--sources",
+            "a/*.yaml",
+            "b/*.xml",
+            "--tag",
+            "infra",
+        ]);
+        match opts.cmd {
+            Command::Parse {
+                sources,
+                tags,
+                output_schema,
+                ..
+            } => {
+                assert_eq!(sources, ["a/*.yaml", "b/*.xml"]);
+                assert_eq!(tags, ["infra"]);
+                assert_eq!(output_schema, crate::OutputSchema::V1);
+            }
+            _ => panic!("Expected the parse command"),
+        }
+        assert!(
+            CLOptions::try_parse_from(["kvasir", "document", "-t", "*.tpl", "--split-files"])
+                .is_err()
+        );
+        assert!(CLOptions::try_parse_from([
+            "kvasir",
+            "document",
+            "-t",
+            "*.tpl",
+            "--split-files",
+            "--output-to-cwd"
+        ])
+        .is_ok());
+        assert!(
+            CLOptions::try_parse_from(["kvasir", "document", "-t", "*.tpl", "--per-file"]).is_err()
+        );
+        assert!(CLOptions::try_parse_from([
+            "kvasir",
+            "document",
+            "-t",
+            "*.tpl",
+            "--split-files",
+            "--output-to-cwd",
+            "--per-directory",
+            "--per-file"
+        ])
+        .is_err());
+
+        let mut completions = vec![];
+        clap_complete::generate(
+            clap_complete::Shell::Bash,
+            &mut CLOptions::command(),
+            "kvasir",
+            &mut completions,
+        );
+        assert!(String::from_utf8(completions)
+            .unwrap()
+            .contains("--split-files"));
+    }
+
+    #[test]
+    fn environment_variables() {
+        use crate::CLOptions;
+        use clap::CommandFactory;
+
+        // Every argument can be set from the environment, e.g. in a container, without a
+        // variable setting two arguments of a command, including its global arguments
+        fn check(command: &clap::Command, globals: &[String]) {
+            let mut names = globals.to_vec();
+            let mut inherited = globals.to_vec();
+            for arg in command.get_arguments() {
+                let env = match arg.get_env() {
+                    Some(env) => env.to_string_lossy().to_string(),
+                    None => panic!("--{} of {} has no env", arg.get_id(), command.get_name()),
+                };
+                if arg.is_global_set() {
+                    inherited.push(env.to_owned());
+                }
+                names.push(env);
+            }
+            assert!(names.iter().all_unique(), "{:?}", names);
+            for subcommand in command.get_subcommands() {
+                check(subcommand, &inherited);
+            }
+        }
+        check(&CLOptions::command(), &[]);
+    }
+
+    #[test]
+    fn environment_lists() {
+        use crate::{cli, CLOptions, Command};
+        use clap::{CommandFactory, FromArgMatches};
+
+        let parse = |args: &[&str], env: &[(&str, &str)]| {
+            let var = |name: &str| {
+                env.iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, v)| v.to_string())
+            };
+            let matches = cli::split_env_lists(CLOptions::command(), &var)
+                .try_get_matches_from(args)
+                .unwrap();
+            match CLOptions::from_arg_matches(&matches).unwrap().cmd {
+                Command::Parse {
+                    sources,
+                    parser_options,
+                    ..
+                } => (sources, parser_options),
+                _ => panic!("Expected the parse command"),
+            }
+        };
+
+        // Lists in environment variables are split on commas, and values on the command line
+        // are kept whole and override them
+        let env = [
+            ("KVASIR_SOURCES", "a/*.yaml,b/*.xml"),
+            (
+                "KVASIR_PARSER_OPTIONS",
+                "sql.dialect=mysql,jsonnet.ext_str.env=prod",
+            ),
+        ];
+        assert_eq!(
+            parse(&["kvasir", "parse"], &env),
+            (
+                vec!["a/*.yaml".to_string(), "b/*.xml".to_string()],
+                vec![
+                    "sql.dialect=mysql".to_string(),
+                    "jsonnet.ext_str.env=prod".to_string()
+                ]
+            )
+        );
+        assert_eq!(
+            parse(
+                &[
+                    "kvasir",
+                    "parse",
+                    "--sources",
+                    "a/*.{yaml,yml}",
+                    "--parser-option",
+                    "jsonnet.ext_code.ports=[80,443]",
+                    "jsonnet.ext_str.hosts=a,b"
+                ],
+                &env
+            ),
+            (
+                vec!["a/*.{yaml,yml}".to_string()],
+                vec![
+                    "jsonnet.ext_code.ports=[80,443]".to_string(),
+                    "jsonnet.ext_str.hosts=a,b".to_string()
+                ]
+            )
+        );
+    }
 
     #[test]
     fn list_files() {
         assert_eq!(
-            crate::list_files(vec!["test/resources/*.*".to_string()])
+            crate::list_files(vec![Source::from("test/resources/*.*")])
                 .0
                 .len(),
             11
         )
     }
 
+    #[test]
+    fn list_tagged_files() {
+        let tags = |t: &[&str]| t.iter().map(|t| t.to_string()).collect_vec();
+        let (files, _) = crate::list_files(vec![
+            Source::new("test/resources/test.ini", &tags(&["config"])),
+            Source::new("test/resources/*.ini", &tags(&["ini", "config"])),
+            Source::from("test/resources/test.json"),
+        ]);
+
+        assert_eq!(
+            files,
+            vec![
+                (
+                    std::path::PathBuf::from("test/resources/test.ini"),
+                    tags(&["config", "ini"])
+                ),
+                (std::path::PathBuf::from("test/resources/test.json"), vec![])
+            ]
+        );
+    }
+
     #[test]
     fn parse_file() {
         let result = crate::parse_file(
             std::path::Path::new("test/resources/test.ini"),
             parsers::parsers().as_slice(),
+            &ParserOptions::default(),
         );
 
         assert_eq!(result.0.len(), 1);
@@ -467,6 +2701,33 @@ mod tests {
         check_ini_file(&result.0);
     }
 
+    #[test]
+    fn parse_documents() {
+        let (successes, _) = crate::parse_file(
+            std::path::Path::new("test/resources/kubernetes/manifests.yaml"),
+            &parsers::parsers(),
+            &ParserOptions::default(),
+        );
+
+        assert_eq!(
+            successes
+                .iter()
+                .map(|s| (s.parser.as_str(), s.index, s.name.as_deref()))
+                .collect_vec(),
+            [
+                ("yaml", Some(0), None),
+                ("yaml", Some(1), None),
+                ("yaml", Some(2), None),
+                ("kubernetes", Some(0), Some("ConfigMap/app-config")),
+                ("kubernetes", Some(1), Some("Secret/app-secret"))
+            ]
+        );
+        assert_eq!(
+            successes[3].warnings,
+            ["Skipped unsupported Kubernetes kind 'Deployment'"]
+        );
+    }
+
     fn check_ini_file(parse_successes: &[ParseSuccess]) {
         match parse_successes {
             [success] => {
@@ -488,7 +2749,14 @@ mod tests {
 
     #[test]
     fn parse_files() {
-        let result = crate::parse_files(vec!["test/resources/*.ini".to_string()]);
+        let result = crate::parse_files(
+            vec![Source::from("test/resources/*.ini")],
+            &parsers::parsers(),
+            &ParserOptions::default(),
+            false,
+            None,
+        )
+        .unwrap();
 
         assert_eq!(result.0.len(), 1);
         assert_eq!(result.1.len(), 0);
@@ -498,25 +2766,203 @@ mod tests {
 
     #[test]
     fn parse_all_files() {
-        let result = crate::parse_files(vec!["test/resources/*.*".to_string()]);
+        let result = crate::parse_files(
+            vec![Source::from("test/resources/*.*")],
+            &parsers::parsers(),
+            &ParserOptions::default(),
+            false,
+            None,
+        )
+        .unwrap();
 
-        assert_eq!(result.0.len(), 7); // Successes
+        assert_eq!(result.0.len(), 8); // Successes
         assert_eq!(result.1.len(), 6); // Failures
     }
 
+    #[test]
+    fn assign_owners() {
+        let config = Config {
+            codeowners: Some(PathBuf::from(
+                "test/resources/codeowners/.github/CODEOWNERS",
+            )),
+            ..Default::default()
+        };
+        let (mut successes, _) = crate::parse_files(
+            vec![Source::from("test/resources/codeowners/*/*.*")],
+            &parsers::parsers(),
+            &ParserOptions::default(),
+            false,
+            None,
+        )
+        .unwrap();
+        crate::assign_owners(&config, &mut successes).unwrap();
+
+        let owners = successes
+            .iter()
+            .map(|s| {
+                (
+                    s.path.to_string_lossy().replace('\\', "/"),
+                    s.owners.to_owned(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            owners,
+            [
+                (
+                    "test/resources/codeowners/docs/site.yaml".to_string(),
+                    vec!["@credera/platform".to_string(), "@credera/docs".to_string()]
+                ),
+                (
+                    "test/resources/codeowners/services/orders.json".to_string(),
+                    vec!["@credera/services".to_string(), "@credera/api".to_string()]
+                ),
+                (
+                    "test/resources/codeowners/services/orders.yaml".to_string(),
+                    vec!["@credera/services".to_string()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_duplicates() {
+        let sources = || {
+            vec![
+                Source::new(
+                    "test/resources/duplicates/orders/*.yaml",
+                    &["orders".to_string()],
+                ),
+                Source::new(
+                    "test/resources/duplicates/*/*.yaml",
+                    &["services".to_string()],
+                ),
+            ]
+        };
+        let paths = |successes: &[ParseSuccess]| {
+            successes
+                .iter()
+                .map(|s| {
+                    (
+                        s.path.to_string_lossy().replace('\\', "/"),
+                        s.duplicates
+                            .iter()
+                            .map(|d| d.to_string_lossy().replace('\\', "/"))
+                            .collect_vec(),
+                    )
+                })
+                .collect_vec()
+        };
+        let orders = "test/resources/duplicates/orders/values.yaml".to_string();
+        let payments = "test/resources/duplicates/payments/values.yaml".to_string();
+        let shipping = "test/resources/duplicates/shipping/values.yaml".to_string();
+
+        let (successes, _) = crate::parse_files(
+            sources(),
+            &parsers::parsers(),
+            &ParserOptions::default(),
+            false,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            paths(&successes),
+            [
+                (orders.to_owned(), vec![payments.to_owned()]),
+                (payments.to_owned(), vec![orders.to_owned()]),
+                (shipping.to_owned(), vec![]),
+            ]
+        );
+
+        let (successes, _) = crate::parse_files(
+            sources(),
+            &parsers::parsers(),
+            &ParserOptions::default(),
+            true,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            paths(&successes),
+            [
+                (orders.to_owned(), vec![payments.to_owned()]),
+                (shipping.to_owned(), vec![]),
+            ]
+        );
+        assert_eq!(successes[0].tags, ["orders", "services"]);
+    }
+
+    #[test]
+    fn parse_directories() {
+        let result = crate::parse_files(
+            vec![Source::new(
+                "test/resources/directories/*/*",
+                &["unit".to_string()],
+            )],
+            &parsers::parsers(),
+            &ParserOptions::default(),
+            false,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            result
+                .0
+                .iter()
+                .filter(|s| s.path.is_dir())
+                .map(|s| (s.parser.as_str(), s.name.as_deref(), s.tags.as_slice()))
+                .collect_vec(),
+            [
+                ("helm-chart", Some("web"), ["unit".to_string()].as_slice()),
+                ("dbt-project", Some("shop"), ["unit".to_string()].as_slice()),
+                (
+                    "terraform-module",
+                    Some("module"),
+                    ["unit".to_string()].as_slice()
+                ),
+                (
+                    "maven-project",
+                    Some("shop"),
+                    ["unit".to_string()].as_slice()
+                ),
+                (
+                    "spring-boot",
+                    Some("orders"),
+                    ["unit".to_string()].as_slice()
+                ),
+                (
+                    "licenses",
+                    Some("licenses"),
+                    ["unit".to_string()].as_slice()
+                ),
+            ]
+        );
+    }
+
     #[test]
     fn base_template() {
         let template_dir = "test/templates/*";
         match tera::Tera::new(template_dir).as_mut() {
             Ok(tera) => {
+                let dependencies = template_dependencies(tera);
                 let root_template = crate::get_base_template(
-                    template_dir.to_string(),
-                    tera.get_template_names().collect_vec().as_slice(),
-                    Some("test/templates/base.tpl".to_string()),
+                    template_dir,
+                    &dependencies,
+                    Some("base.tpl".to_string()),
                 );
 
-                assert_eq!(root_template.unwrap(), "test/templates/base.tpl");
+                assert_eq!(root_template.unwrap(), "base.tpl");
                 assert_eq!(tera.get_template_names().collect_vec().as_slice().len(), 2);
+
+                // Both templates are root candidates, so one must be chosen
+                assert!(crate::get_base_template(template_dir, &dependencies, None).is_err());
+                assert!(crate::get_base_template(
+                    template_dir,
+                    &dependencies,
+                    Some("missing.tpl".to_string())
+                )
+                .is_err());
             }
             _ => {
                 panic!("Tera should have been created!")
@@ -525,40 +2971,14 @@ mod tests {
     }
 
     #[test]
-    fn split_template_content() {
-        let splits = crate::split_template_content(
-            "8<--",
-            r#"
-        8<-- /tmp/one
-        a
-        8<--/tmp/two
-        b
-        8<--/tmp/three
-        c
-        d
-        "#,
-            std::path::PathBuf::from_str("/tmp").unwrap(),
-        );
-
-        assert!(splits.is_ok());
-
-        let file_splits = splits.unwrap();
+    fn base_template_inheritance() {
+        let template_dir = "test/templates/inheritance/*";
+        let tera = tera::Tera::new(template_dir).unwrap();
+        let dependencies = template_dependencies(&tera);
 
-        println!("{:?}", &file_splits);
-        assert_eq!(file_splits.len(), 3);
-
-        match file_splits.as_slice() {
-            [one, two, three] => {
-                assert_eq!(one.0.to_str().unwrap(), "/tmp/one");
-                assert!(one.1.contains('a'));
-                assert_eq!(two.0.to_str().unwrap(), "/tmp/two");
-                assert!(two.1.contains('b'));
-                assert_eq!(three.0.to_str().unwrap(), "/tmp/three");
-                assert!(three.1.contains('d'));
-            }
-            _ => {
-                panic!("Three results should always be returned!")
-            }
-        }
+        assert_eq!(
+            crate::get_base_template(template_dir, &dependencies, None).unwrap(),
+            "page.tpl"
+        );
     }
 }
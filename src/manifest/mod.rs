@@ -0,0 +1,312 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! A machine-readable manifest of the files generated by a `document` run.
+
+use crate::errors::*;
+use crate::output::OutputFile;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The name of the manifest file written to the output directory.
+pub const MANIFEST_FILE: &str = "kvasir-manifest.json";
+
+/// A file and the SHA-256 hash of its contents.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Checksum {
+    pub path: PathBuf,
+    pub sha256: String,
+}
+
+/// A generated file, with the source files that contributed to it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeneratedFile {
+    /// The path of the file, relative to the output directory.
+    pub path: PathBuf,
+    pub sha256: String,
+    /// The source files that contributed to the file. These are taken from the `source`
+    /// header attribute (a comma-separated list) if present, or are otherwise all sources.
+    pub sources: Vec<PathBuf>,
+}
+
+/// The manifest of a `document` run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub kvasir_version: String,
+    pub generated: String,
+    pub engine: String,
+    /// The name of the root template.
+    pub template: String,
+    /// All templates found by the template glob expression, keyed by name.
+    pub templates: BTreeMap<String, String>,
+    pub sources: Vec<Checksum>,
+    pub files: Vec<GeneratedFile>,
+}
+
+/// Return the checksums of a list of files.
+pub fn checksums(paths: &[PathBuf]) -> Result<Vec<Checksum>> {
+    paths
+        .iter()
+        .map(|p| {
+            Ok(Checksum {
+                path: p.to_owned(),
+                sha256: file_sha256(p)?,
+            })
+        })
+        .collect()
+}
+
+/// Return the hex-encoded SHA-256 hash of some content.
+pub fn sha256(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+/// Return the hex-encoded SHA-256 hash of the contents of a file.
+pub fn file_sha256(path: &Path) -> Result<String> {
+    Ok(sha256(&fs::read(path).chain_err(|| {
+        format!("Could not read {} to calculate its hash", path.display())
+    })?))
+}
+
+impl Manifest {
+    /// Create a manifest for the written output files.
+    ///
+    /// `outputs` are the split output files, used to find the `source` attribute of each
+    /// written file, and `written` the paths of the files which were written.
+    pub fn new(
+        engine: &str,
+        template: &str,
+        templates: BTreeMap<String, String>,
+        sources: &[PathBuf],
+        outputs: &[OutputFile],
+        written: &[PathBuf],
+        output_dir: &Path,
+    ) -> Result<Manifest> {
+        let sources = checksums(sources)?;
+
+        let files = written
+            .iter()
+            .map(|path| {
+                let contributing = outputs
+                    .iter()
+                    .filter(|o| &o.path == path)
+                    .filter_map(|o| o.attributes.get("source"))
+                    .flat_map(|s| s.split(','))
+                    .map(|s| PathBuf::from(s.trim()))
+                    .filter(|s| !s.as_os_str().is_empty())
+                    .collect::<Vec<_>>();
+                Ok(GeneratedFile {
+                    path: path.strip_prefix(output_dir).unwrap_or(path).to_path_buf(),
+                    sha256: file_sha256(path)?,
+                    sources: if contributing.is_empty() {
+                        sources.iter().map(|s| s.path.to_owned()).collect()
+                    } else {
+                        contributing
+                    },
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Manifest {
+            kvasir_version: env!("CARGO_PKG_VERSION").to_string(),
+            generated: crate::clock::timestamp(),
+            engine: engine.to_string(),
+            template: template.to_string(),
+            templates,
+            sources,
+            files,
+        })
+    }
+
+    /// Read the manifest from the output directory, if one exists.
+    pub fn read(output_dir: &Path) -> Result<Option<Manifest>> {
+        let path = output_dir.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let manifest = serde_json::from_str(&fs::read_to_string(&path)?)
+            .chain_err(|| format!("Could not read manifest {}", path.display()))?;
+        Ok(Some(manifest))
+    }
+
+    /// Return the source files that were added, removed or changed since the manifest was
+    /// written.
+    pub fn changed_sources(&self, sources: &[Checksum]) -> BTreeSet<PathBuf> {
+        let previous: BTreeSet<&Checksum> = self.sources.iter().collect();
+        let current: BTreeSet<&Checksum> = sources.iter().collect();
+        previous
+            .symmetric_difference(&current)
+            .map(|c| c.path.to_owned())
+            .collect()
+    }
+
+    /// Return whether the templates, template engine or root template have changed since
+    /// the manifest was written.
+    pub fn templates_changed(
+        &self,
+        engine: &str,
+        template: &str,
+        templates: &BTreeMap<String, String>,
+    ) -> bool {
+        self.engine != engine || self.template != template || &self.templates != templates
+    }
+
+    /// Return whether an output file must be written: if it is not in the manifest, any of
+    /// its contributing sources have changed, or the file has changed since it was written.
+    pub fn is_stale(
+        &self,
+        output: &OutputFile,
+        changed_sources: &BTreeSet<PathBuf>,
+        output_dir: &Path,
+    ) -> bool {
+        let relative = output.path.strip_prefix(output_dir).unwrap_or(&output.path);
+        match self.files.iter().find(|f| f.path == relative) {
+            Some(previous) => {
+                previous.sources.iter().any(|s| changed_sources.contains(s))
+                    || file_sha256(&output.path).map_or(true, |h| h != previous.sha256)
+            }
+            None => true,
+        }
+    }
+
+    /// Add files written alongside the pages, such as the search index, so that they are
+    /// signed with the pages. They have no sources, so are never taken for pages.
+    pub fn add_reports(&mut self, paths: &[PathBuf], output_dir: &Path) -> Result<()> {
+        for path in paths {
+            self.files.push(GeneratedFile {
+                path: path.strip_prefix(output_dir).unwrap_or(path).to_path_buf(),
+                sha256: file_sha256(path)?,
+                sources: vec![],
+            });
+        }
+        Ok(())
+    }
+
+    /// Write the manifest as JSON to the output directory.
+    pub fn write(&self, output_dir: &Path) -> Result<PathBuf> {
+        let path = output_dir.join(MANIFEST_FILE);
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::manifest::{sha256, Checksum, GeneratedFile, Manifest, MANIFEST_FILE};
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn manifest() {
+        let dir = std::env::temp_dir().join(format!("kvasir-manifest-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let outputs = crate::output::split_template_content(
+            "8<--",
+            "8<-- a.md source=test/resources/test.json\na\n8<-- b.md\nb\n",
+            dir.to_owned(),
+        )
+        .unwrap();
+        let written = crate::output::write_rendered_files(&outputs, true);
+        let sources = vec![
+            PathBuf::from("test/resources/test.json"),
+            PathBuf::from("test/resources/test.xml"),
+        ];
+
+        let manifest = Manifest::new(
+            "tera",
+            "base.tpl",
+            BTreeMap::from([("base.tpl".to_string(), sha256(b"base"))]),
+            &sources,
+            &outputs,
+            &written,
+            &dir,
+        )
+        .unwrap();
+
+        assert_eq!(manifest.sources.len(), 2);
+        assert_eq!(manifest.files[0].path, PathBuf::from("a.md"));
+        assert_eq!(manifest.files[0].sha256, sha256(b"a"));
+        assert_eq!(manifest.files[0].sources, sources[..1]);
+        assert_eq!(manifest.files[1].sources, sources);
+
+        manifest.write(&dir).unwrap();
+        let read: Manifest =
+            serde_json::from_str(&fs::read_to_string(dir.join(MANIFEST_FILE)).unwrap()).unwrap();
+        assert_eq!(read, manifest);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn incremental() {
+        let dir = std::env::temp_dir().join(format!("kvasir-incremental-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let outputs = crate::output::split_template_content(
+            "8<--",
+            "8<-- a.md source=a.json\na\n8<-- b.md source=b.json\nb\n8<-- c.md\nc\n",
+            dir.to_owned(),
+        )
+        .unwrap();
+        crate::output::write_rendered_files(&outputs, true);
+        let checksum = |path: &str, content: &[u8]| Checksum {
+            path: PathBuf::from(path),
+            sha256: sha256(content),
+        };
+        let manifest = Manifest {
+            kvasir_version: "0".to_string(),
+            generated: "".to_string(),
+            engine: "tera".to_string(),
+            template: "base.tpl".to_string(),
+            templates: BTreeMap::new(),
+            sources: vec![checksum("a.json", b"a"), checksum("b.json", b"b")],
+            files: ["a.md", "b.md"]
+                .iter()
+                .zip(["a", "b"])
+                .map(|(path, content)| GeneratedFile {
+                    path: PathBuf::from(path),
+                    sha256: sha256(content.as_bytes()),
+                    sources: vec![PathBuf::from(format!("{}.json", content))],
+                })
+                .collect(),
+        };
+
+        let changed =
+            manifest.changed_sources(&[checksum("a.json", b"a"), checksum("b.json", b"B")]);
+        assert_eq!(changed, BTreeSet::from([PathBuf::from("b.json")]));
+        assert!(!manifest.is_stale(&outputs[0], &changed, &dir));
+        assert!(manifest.is_stale(&outputs[1], &changed, &dir));
+        assert!(manifest.is_stale(&outputs[2], &changed, &dir));
+
+        fs::write(dir.join("a.md"), "edited").unwrap();
+        assert!(manifest.is_stale(&outputs[0], &changed, &dir));
+
+        assert!(!manifest.templates_changed("tera", "base.tpl", &BTreeMap::new()));
+        assert!(manifest.templates_changed("handlebars", "base.tpl", &BTreeMap::new()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn hash() {
+        assert_eq!(
+            sha256(b"kvasir"),
+            "831ce4a48d424b3b8d752655a4451e1669262c24c47cb90ea5ec90d1e7d56312"
+        );
+    }
+}
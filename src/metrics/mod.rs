@@ -0,0 +1,385 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Metrics of a `kvasir document` run, written with `--metrics-output` so that the health of
+//! documentation pipelines can be monitored.
+//!
+//! The metrics are taken from the run summary recorded for notifications, along with the
+//! time taken by each phase of the run, and are written in the Prometheus text format, e.g.
+//! for the node exporter's textfile collector or a Pushgateway, or as OpenTelemetry OTLP
+//! JSON, e.g. for a collector's OTLP/HTTP receiver. Like notifications, metrics are written
+//! even if the run fails part way through, and a failure to write them is logged rather
+//! than failing the run.
+
+use crate::errors::*;
+use crate::notify::{self, Summary};
+use clap::ValueEnum;
+use log::{error, info};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// The timeout of the request when metrics are sent to a URL.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+static PHASES: Mutex<Vec<(&'static str, Duration)>> = Mutex::new(vec![]);
+
+/// The format in which metrics are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum Format {
+    /// The Prometheus text exposition format.
+    #[default]
+    Prometheus,
+    /// OpenTelemetry OTLP metrics, encoded as JSON.
+    Otlp,
+}
+
+/// Where and how the metrics of a run are written.
+#[derive(Debug, Clone)]
+pub struct MetricsOptions {
+    /// The file to write, or an `http` or `https` URL to post the metrics to.
+    pub output: String,
+    pub format: Format,
+    /// Labels added to every metric, or resource attributes in OTLP.
+    pub labels: Vec<(String, String)>,
+}
+
+/// A metric with one or more values, each distinguished by an optional attribute.
+#[derive(Debug, PartialEq)]
+struct Metric {
+    /// The OpenTelemetry name of the metric, from which the Prometheus name is derived.
+    name: &'static str,
+    /// The unit of the metric: `s` for seconds, or `1` for counts.
+    unit: &'static str,
+    description: &'static str,
+    points: Vec<(Option<(&'static str, String)>, f64)>,
+}
+
+impl Metric {
+    fn new(name: &'static str, unit: &'static str, description: &'static str, value: f64) -> Self {
+        Metric {
+            name,
+            unit,
+            description,
+            points: vec![(None, value)],
+        }
+    }
+
+    /// Return the Prometheus name of the metric, e.g. `kvasir_run_duration_seconds`.
+    fn prometheus_name(&self) -> String {
+        let name = self.name.replace('.', "_");
+        match self.unit {
+            "s" => name + "_seconds",
+            _ => name,
+        }
+    }
+}
+
+/// Record the time taken by a phase of the run, such as `parse`, since it started.
+pub fn phase(name: &'static str, started: Instant) {
+    if let Ok(mut phases) = PHASES.lock() {
+        phases.push((name, started.elapsed()));
+    }
+}
+
+/// Return the metrics of a run.
+fn metrics(
+    summary: &Summary,
+    phases: &[(&'static str, Duration)],
+    duration: Duration,
+    failed: bool,
+    time: SystemTime,
+) -> Vec<Metric> {
+    let timestamp = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let mut metrics = vec![
+        Metric::new(
+            "kvasir.run.duration",
+            "s",
+            "The time taken by the run.",
+            duration.as_secs_f64(),
+        ),
+        Metric {
+            name: "kvasir.phase.duration",
+            unit: "s",
+            description: "The time taken by each phase of the run.",
+            points: phases
+                .iter()
+                .map(|(name, d)| (Some(("phase", name.to_string())), d.as_secs_f64()))
+                .collect(),
+        },
+        Metric::new(
+            "kvasir.run.success",
+            "1",
+            "Whether the run succeeded without any failures: 1 or 0.",
+            if failed { 0.0 } else { 1.0 },
+        ),
+        Metric::new(
+            "kvasir.run.timestamp",
+            "s",
+            "The time at which the run finished, in seconds since the Unix epoch.",
+            timestamp.as_secs_f64(),
+        ),
+        Metric::new(
+            "kvasir.sources.parsed",
+            "1",
+            "The number of source files parsed.",
+            summary.sources_parsed as f64,
+        ),
+        Metric {
+            name: "kvasir.failures",
+            unit: "1",
+            description: "The number of source files which could not be parsed, remote sources which could not be fetched, templates which could not be rendered and output files which could not be written.",
+            points: [
+                ("parse", summary.parse_failures),
+                ("remote", summary.remote_failures),
+                ("render", summary.render_failures),
+                ("write", summary.write_failures),
+            ]
+            .iter()
+            .map(|(kind, count)| (Some(("kind", kind.to_string())), *count as f64))
+            .collect(),
+        },
+        Metric {
+            name: "kvasir.pages",
+            unit: "1",
+            description: "The number of output files written, and left unchanged by an incremental run.",
+            points: vec![
+                (
+                    Some(("state", "written".to_string())),
+                    summary.pages_written as f64,
+                ),
+                (
+                    Some(("state", "unchanged".to_string())),
+                    summary.pages_unchanged as f64,
+                ),
+            ],
+        },
+    ];
+    if let Some(published) = summary.published {
+        metrics.push(Metric::new(
+            "kvasir.files.published",
+            "1",
+            "The number of files uploaded to object storage.",
+            published as f64,
+        ));
+    }
+    metrics.retain(|m| !m.points.is_empty());
+    metrics
+}
+
+/// Escape a Prometheus label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Return the metrics in the Prometheus text exposition format.
+fn prometheus(metrics: &[Metric], labels: &[(String, String)]) -> String {
+    let mut text = String::new();
+    for metric in metrics {
+        let name = metric.prometheus_name();
+        text += &format!("# HELP {} {}\n", name, metric.description);
+        text += &format!("# TYPE {} gauge\n", name);
+        for (attribute, value) in &metric.points {
+            let labels = labels
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .chain(attribute.iter().map(|(k, v)| (*k, v.as_str())))
+                .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+                .collect::<Vec<_>>();
+            match labels.is_empty() {
+                true => text += &format!("{} {}\n", name, value),
+                false => text += &format!("{}{{{}}} {}\n", name, labels.join(","), value),
+            }
+        }
+    }
+    text
+}
+
+/// Return OTLP attributes with string values.
+fn attributes<'a>(attributes: impl Iterator<Item = (&'a str, &'a str)>) -> Value {
+    attributes
+        .map(|(k, v)| json!({"key": k, "value": {"stringValue": v}}))
+        .collect()
+}
+
+/// Return the metrics as an OTLP `ExportMetricsServiceRequest`, encoded as JSON.
+fn otlp(metrics: &[Metric], labels: &[(String, String)], time: SystemTime) -> Value {
+    let time = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string();
+    let resource = [
+        ("service.name", "kvasir"),
+        ("service.version", env!("CARGO_PKG_VERSION")),
+    ];
+    json!({
+        "resourceMetrics": [{
+            "resource": {
+                "attributes": attributes(
+                    resource
+                        .into_iter()
+                        .chain(labels.iter().map(|(k, v)| (k.as_str(), v.as_str())))
+                ),
+            },
+            "scopeMetrics": [{
+                "scope": {"name": "kvasir", "version": env!("CARGO_PKG_VERSION")},
+                "metrics": metrics.iter().map(|metric| json!({
+                    "name": metric.name,
+                    "unit": metric.unit,
+                    "description": metric.description,
+                    "gauge": {
+                        "dataPoints": metric.points.iter().map(|(attribute, value)| json!({
+                            "timeUnixNano": time,
+                            "asDouble": value,
+                            "attributes": attributes(
+                                attribute.iter().map(|(k, v)| (*k, v.as_str()))
+                            ),
+                        })).collect::<Vec<_>>(),
+                    },
+                })).collect::<Vec<_>>(),
+            }],
+        }],
+    })
+}
+
+/// Write the metrics to a file, replacing it atomically so that collectors never read a
+/// partly written file, or post them to a URL.
+fn write(output: &str, body: &str, content_type: &str) -> Result<()> {
+    if output.starts_with("http://") || output.starts_with("https://") {
+        ureq::post(output)
+            .timeout(TIMEOUT)
+            .set("Content-Type", content_type)
+            .send_string(body)
+            .map_err(|e| format!("Could not send metrics to {}: {}", output, e))?;
+        return Ok(());
+    }
+    let path = Path::new(output);
+    let name = path
+        .file_name()
+        .ok_or_else(|| format!("Invalid metrics file {}", output))?;
+    let temp = path.with_file_name(format!(".{}.tmp", name.to_string_lossy()));
+    fs::write(&temp, body)
+        .and_then(|_| fs::rename(&temp, path))
+        .chain_err(|| format!("Could not write metrics file {}", output))
+}
+
+/// Write the metrics of the current run, which took the given duration and failed if an
+/// error stopped it or any file could not be fetched, parsed or rendered.
+pub fn send(options: &MetricsOptions, duration: Duration, error: Option<&Error>) {
+    let summary = notify::summary();
+    let phases = PHASES.lock().map(|p| p.to_owned()).unwrap_or_default();
+    let time = SystemTime::now();
+    let metrics = metrics(
+        &summary,
+        &phases,
+        duration,
+        error.is_some() || summary.has_failures(),
+        time,
+    );
+    let result = match options.format {
+        Format::Prometheus => write(
+            &options.output,
+            &prometheus(&metrics, &options.labels),
+            "text/plain; version=0.0.4",
+        ),
+        Format::Otlp => write(
+            &options.output,
+            &otlp(&metrics, &options.labels, time).to_string(),
+            "application/json",
+        ),
+    };
+    match result {
+        Ok(_) => info!("Wrote metrics to {}", options.output),
+        Err(e) => error!("Could not write metrics: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::metrics::{metrics, otlp, prometheus, write};
+    use crate::notify::Summary;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn formats() {
+        let summary = Summary {
+            sources_parsed: 12,
+            parse_failures: 1,
+            pages_written: 3,
+            ..Default::default()
+        };
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_622_548_800);
+        let metrics = metrics(
+            &summary,
+            &[("parse", Duration::from_millis(1500))],
+            Duration::from_secs(2),
+            true,
+            time,
+        );
+        let labels = [
+            ("repo".to_string(), "payments".to_string()),
+            ("team".to_string(), "a \"b\"".to_string()),
+        ];
+
+        let text = prometheus(&metrics, &labels);
+        for line in [
+            "# HELP kvasir_run_duration_seconds The time taken by the run.",
+            "# TYPE kvasir_run_duration_seconds gauge",
+            "kvasir_run_duration_seconds{repo=\"payments\",team=\"a \\\"b\\\"\"} 2",
+            "kvasir_phase_duration_seconds{repo=\"payments\",team=\"a \\\"b\\\"\",phase=\"parse\"} 1.5",
+            "kvasir_run_success{repo=\"payments\",team=\"a \\\"b\\\"\"} 0",
+            "kvasir_run_timestamp_seconds{repo=\"payments\",team=\"a \\\"b\\\"\"} 1622548800",
+            "kvasir_failures{repo=\"payments\",team=\"a \\\"b\\\"\",kind=\"parse\"} 1",
+            "kvasir_pages{repo=\"payments\",team=\"a \\\"b\\\"\",state=\"written\"} 3",
+        ] {
+            assert!(text.lines().any(|l| l == line), "{}\n{}", line, text);
+        }
+        assert!(!text.contains("published"));
+        assert!(prometheus(&metrics, &[]).contains("\nkvasir_sources_parsed 12\n"));
+
+        let json = otlp(&metrics, &labels, time);
+        let resource = &json["resourceMetrics"][0];
+        assert_eq!(
+            resource["resource"]["attributes"][2],
+            serde_json::json!({"key": "repo", "value": {"stringValue": "payments"}})
+        );
+        let phase = &resource["scopeMetrics"][0]["metrics"][1];
+        assert_eq!(phase["name"], "kvasir.phase.duration");
+        assert_eq!(phase["unit"], "s");
+        assert_eq!(
+            phase["gauge"]["dataPoints"][0],
+            serde_json::json!({
+                "timeUnixNano": "1622548800000000000",
+                "asDouble": 1.5,
+                "attributes": [{"key": "phase", "value": {"stringValue": "parse"}}],
+            })
+        );
+
+        let path = std::env::temp_dir().join(format!("kvasir-{}.prom", std::process::id()));
+        write(&path.to_string_lossy(), &text, "text/plain").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), text);
+        std::fs::remove_file(path).unwrap();
+    }
+}
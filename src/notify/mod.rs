@@ -0,0 +1,266 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Notifications summarising a `kvasir document` run, posted to a Slack or Microsoft Teams
+//! incoming webhook configured in the `[notify]` section of the configuration file.
+//!
+//! The summary is recorded as the run progresses, in the same way as cancellation is
+//! tracked, so that a notification can still be sent if the run fails part way through.
+
+use crate::config::{NotifyConfig, NotifyFormat, NotifyWhen};
+use crate::errors::*;
+use log::{error, info};
+use serde_json::{json, Value};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The environment variable holding the webhook URL, which overrides the configuration so
+/// that the URL can be kept out of version control.
+pub const WEBHOOK_VAR: &str = "KVASIR_NOTIFY_WEBHOOK";
+
+/// The timeout of the webhook request.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+static SUMMARY: Mutex<Summary> = Mutex::new(Summary {
+    sources_parsed: 0,
+    pages_written: 0,
+    pages_unchanged: 0,
+    parse_failures: 0,
+    render_failures: 0,
+    write_failures: 0,
+    remote_failures: 0,
+    published: None,
+});
+
+/// A summary of a documentation run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    /// The number of source files parsed.
+    pub sources_parsed: usize,
+    /// The number of output files written.
+    pub pages_written: usize,
+    /// The number of output files left unchanged by an incremental run.
+    pub pages_unchanged: usize,
+    /// The number of source files which could not be parsed.
+    pub parse_failures: usize,
+    /// The number of templates which could not be rendered.
+    pub render_failures: usize,
+    /// The number of output files which could not be written.
+    pub write_failures: usize,
+    /// The number of remote sources skipped because they could not be fetched.
+    pub remote_failures: usize,
+    /// The number of files uploaded with `--publish`, if published.
+    pub published: Option<usize>,
+}
+
+impl Summary {
+    /// Return whether any part of the run failed.
+    pub fn has_failures(&self) -> bool {
+        self.parse_failures > 0
+            || self.render_failures > 0
+            || self.write_failures > 0
+            || self.remote_failures > 0
+    }
+}
+
+/// Update the summary of the current run.
+pub fn record(update: impl FnOnce(&mut Summary)) {
+    if let Ok(mut summary) = SUMMARY.lock() {
+        update(&mut summary);
+    }
+}
+
+/// Return the summary of the current run.
+pub fn summary() -> Summary {
+    SUMMARY.lock().map(|s| s.to_owned()).unwrap_or_default()
+}
+
+/// Post the summary of the current run to the configured webhook, if any, unless the run
+/// does not match the configured `when` condition. Failures to notify are logged rather
+/// than failing the run.
+pub fn send(config: &NotifyConfig, site_url: Option<&str>, error: Option<&Error>) {
+    let webhook = match std::env::var(WEBHOOK_VAR)
+        .ok()
+        .filter(|w| !w.is_empty())
+        .or_else(|| config.webhook.to_owned())
+    {
+        Some(webhook) => webhook,
+        None => return,
+    };
+    let summary = summary();
+    let failed = error.is_some() || summary.has_failures();
+    let notify = match config.when {
+        NotifyWhen::Always => true,
+        NotifyWhen::Failure => failed,
+        NotifyWhen::Changes => failed || summary.pages_written > 0,
+    };
+    if !notify {
+        return;
+    }
+    let site_url = config.site_url.as_deref().or(site_url);
+    let error = error.map(|e| e.to_string());
+    let payload = payload(config.format, &summary, error.as_deref(), site_url);
+    match ureq::post(&webhook)
+        .timeout(TIMEOUT)
+        .set("Content-Type", "application/json")
+        .send_string(&payload.to_string())
+    {
+        Ok(_) => info!("Sent notification."),
+        Err(e) => error!("Could not send notification: {}", e),
+    }
+}
+
+/// Return the title and lines of the message summarising a run.
+fn message(summary: &Summary, error: Option<&str>) -> (String, Vec<String>) {
+    let title = if error.is_some() || summary.has_failures() {
+        "Documentation run failed"
+    } else if summary.pages_written > 0 {
+        "Documentation updated"
+    } else {
+        "Documentation unchanged"
+    };
+    let mut lines = vec![format!(
+        "{} pages updated, {} unchanged",
+        summary.pages_written, summary.pages_unchanged
+    )];
+    if let Some(published) = summary.published {
+        lines.push(format!("{} files published", published));
+    }
+    if summary.parse_failures > 0 {
+        lines.push(format!(
+            "{} source files could not be parsed",
+            summary.parse_failures
+        ));
+    }
+    if summary.remote_failures > 0 {
+        lines.push(format!(
+            "{} remote sources could not be fetched",
+            summary.remote_failures
+        ));
+    }
+    if summary.render_failures > 0 {
+        lines.push(format!(
+            "{} templates could not be rendered",
+            summary.render_failures
+        ));
+    }
+    if summary.write_failures > 0 {
+        lines.push(format!(
+            "{} output files could not be written",
+            summary.write_failures
+        ));
+    }
+    if let Some(error) = error {
+        lines.push(format!("Error: {}", error));
+    }
+    (title.to_string(), lines)
+}
+
+/// Return the webhook payload summarising a run, in the format of the receiving service.
+fn payload(
+    format: NotifyFormat,
+    summary: &Summary,
+    error: Option<&str>,
+    site_url: Option<&str>,
+) -> Value {
+    let (title, lines) = message(summary, error);
+    let failed = error.is_some() || summary.has_failures();
+    match format {
+        NotifyFormat::Slack => {
+            let mut text = format!("*kvasir: {}*", title);
+            for line in lines {
+                text.push_str(&format!("\n• {}", line));
+            }
+            if let Some(url) = site_url {
+                text.push_str(&format!("\n<{}|View the documentation>", url));
+            }
+            json!({ "text": text })
+        }
+        NotifyFormat::Teams => {
+            let mut card = json!({
+                "@type": "MessageCard",
+                "@context": "https://schema.org/extensions",
+                "summary": format!("kvasir: {}", title),
+                "themeColor": if failed { "D93F0B" } else { "2EB886" },
+                "title": format!("kvasir: {}", title),
+                "text": lines.iter().map(|l| format!("* {}", l)).collect::<Vec<_>>().join("\n"),
+            });
+            if let Some(url) = site_url {
+                card["potentialAction"] = json!([{
+                    "@type": "OpenUri",
+                    "name": "View the documentation",
+                    "targets": [{ "os": "default", "uri": url }],
+                }]);
+            }
+            card
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::config::NotifyFormat;
+    use crate::notify::{payload, Summary};
+    use serde_json::json;
+
+    #[test]
+    fn payloads() {
+        let summary = Summary {
+            pages_written: 3,
+            pages_unchanged: 10,
+            parse_failures: 1,
+            published: Some(3),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            payload(
+                NotifyFormat::Slack,
+                &summary,
+                None,
+                Some("https://docs.example.com")
+            ),
+            json!({"text": "*kvasir: Documentation run failed*\n• 3 pages updated, 10 unchanged\n• 3 files published\n• 1 source files could not be parsed\n<https://docs.example.com|View the documentation>"})
+        );
+
+        let card = payload(
+            NotifyFormat::Teams,
+            &Summary {
+                pages_written: 2,
+                ..Default::default()
+            },
+            None,
+            Some("https://docs.example.com"),
+        );
+        assert_eq!(card["title"], json!("kvasir: Documentation updated"));
+        assert_eq!(card["themeColor"], json!("2EB886"));
+        assert_eq!(card["text"], json!("* 2 pages updated, 0 unchanged"));
+        assert_eq!(
+            card["potentialAction"][0]["targets"][0]["uri"],
+            json!("https://docs.example.com")
+        );
+
+        let failed = payload(
+            NotifyFormat::Teams,
+            &Summary::default(),
+            Some("No templates"),
+            None,
+        );
+        assert_eq!(failed["themeColor"], json!("D93F0B"));
+        assert!(failed.get("potentialAction").is_none());
+    }
+}
@@ -0,0 +1,885 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Splitting of rendered template output into files and writing them to disk.
+
+use crate::cancel;
+use crate::config::Hook;
+use crate::errors::*;
+use crate::notify;
+use crate::paths;
+use crate::progress;
+use itertools::Itertools;
+use log::{debug, error, info};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// OS-specific line endings
+#[cfg(windows)]
+const LINE_ENDING: &str = "\r\n";
+#[cfg(not(windows))]
+const LINE_ENDING: &str = "\n";
+
+/// File extensions of output files to which front matter is added.
+const FRONT_MATTER_EXTENSIONS: [&str; 4] = ["md", "markdown", "html", "htm"];
+
+/// A single output file split from rendered template output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputFile {
+    /// The path to which the file will be written.
+    pub path: PathBuf,
+    /// The contents of the file.
+    pub content: String,
+    /// Attributes provided as `key=value` pairs on the split header line.
+    pub attributes: BTreeMap<String, String>,
+    /// Options controlling how the file is written, from the split header line.
+    pub options: WriteOptions,
+}
+
+/// File extensions of output files whose section markers are written as HTML comments,
+/// rather than as `#` comments.
+const HTML_COMMENT_EXTENSIONS: [&str; 6] = ["md", "markdown", "html", "htm", "xml", "svg"];
+
+/// How an output file is written when it already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum WriteMode {
+    /// Replace the existing file, if overwriting is allowed.
+    #[default]
+    Replace,
+    /// Append to the existing file.
+    Append,
+    /// Replace a named section of the existing file, between its marker lines.
+    Section,
+}
+
+/// Per-file write options, set with the `mode`, `section` and `overwrite` header attributes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WriteOptions {
+    pub mode: WriteMode,
+    /// Whether an existing file may be replaced, overriding `--allow-overwrite`.
+    pub overwrite: Option<bool>,
+    /// The name of the section replaced in `section` mode.
+    pub section: Option<String>,
+}
+
+impl WriteOptions {
+    /// Remove the write options from a set of header attributes.
+    ///
+    /// A `section` attribute without a `mode` implies `section` mode.
+    fn from_attributes(attributes: &mut BTreeMap<String, String>) -> Result<WriteOptions> {
+        let section = attributes.remove("section");
+        let mode = match (attributes.remove("mode").as_deref(), &section) {
+            (None, Some(_)) | (Some("section"), Some(_)) => WriteMode::Section,
+            (Some("section"), None) => bail!("The section write mode requires a section attribute"),
+            (_, Some(_)) => bail!("A section attribute requires the section write mode"),
+            (None | Some("replace"), None) => WriteMode::Replace,
+            (Some("append"), None) => WriteMode::Append,
+            (Some(m), None) => bail!(format!(
+                "Unknown write mode '{}', expected replace, append or section",
+                m
+            )),
+        };
+        let overwrite = attributes
+            .remove("overwrite")
+            .map(|o| {
+                o.parse::<bool>()
+                    .map_err(|_| format!("Invalid overwrite value '{}', expected true or false", o))
+            })
+            .transpose()?;
+        Ok(WriteOptions {
+            mode,
+            overwrite,
+            section,
+        })
+    }
+}
+
+/// Split a header line into whitespace-separated tokens, keeping quoted values together
+/// and removing their quotes.
+fn tokenise(line: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, c) if c.is_whitespace() => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            (_, c) => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+/// The attributes which may follow the output path in a split header. Other `key=value`
+/// tokens are part of the path, such as in `docs/a b=c.md`, unless the path is given as a
+/// `path` attribute.
+const ATTRIBUTES: [&str; 7] = [
+    "path",
+    "mode",
+    "section",
+    "overwrite",
+    "source",
+    "tags",
+    "title",
+];
+
+/// Return the key and value of a `key=value` attribute token.
+fn attribute(token: &str) -> Option<(String, String)> {
+    token.split_once('=').and_then(|(k, v)| {
+        (!k.is_empty()
+            && k.chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+        .then(|| (k.to_string(), v.to_string()))
+    })
+}
+
+/// Parse a split header line into an output path and any trailing `key=value` attributes.
+/// Fails if the header has no path.
+///
+/// The path may instead be given as a `path` attribute, e.g. `8<-- path=docs/a.md`, which
+/// allows attributes other than those known, such as for a front matter template.
+fn parse_header(line: &str) -> Result<(String, BTreeMap<String, String>)> {
+    let tokens = tokenise(line);
+    let named = tokens.iter().any(|t| t.starts_with("path="));
+    let first_attribute = tokens
+        .iter()
+        .rposition(|t| {
+            !attribute(t).is_some_and(|(k, _)| named || ATTRIBUTES.contains(&k.as_str()))
+        })
+        .map_or(0, |i| i + 1);
+    let mut attributes: BTreeMap<String, String> = tokens[first_attribute..]
+        .iter()
+        .filter_map(|t| attribute(t))
+        .collect();
+    let path = match tokens[..first_attribute].join(" ") {
+        p if p.is_empty() => attributes.remove("path").unwrap_or_default(),
+        p => p,
+    };
+    if path.is_empty() {
+        bail!("Split header '{}' has no output path", line.trim())
+    }
+    Ok((path, attributes))
+}
+
+/// Split the contents of the output template into a list of output files using the
+/// specified delimiter.
+///
+/// The line following each delimiter contains the path of the output file, optionally
+/// followed by `key=value` attributes, e.g. `8<-- docs/a.md source=a.yaml tags=a,b`. The
+/// `mode` (`replace`, `append` or `section`), `section` and `overwrite` attributes set how
+/// the file is written.
+///
+/// The default base output directory is the current directory, chosen to avoid the
+/// possibility of overwriting arbitrary files. All output files must be within the
+/// output directory or an error will be generated. All content before the first split
+/// is ignored.
+pub fn split_template_content(
+    delimiter: &str,
+    contents: &str,
+    output_dir: PathBuf,
+) -> Result<Vec<OutputFile>> {
+    if !output_dir.is_dir() {
+        bail!("Output directory must exist and be a directory.");
+    }
+
+    let files = contents
+        .split(delimiter)
+        .skip(1) // Remove anything before the first split
+        .filter_map(|f| match f.lines().collect_vec().as_slice() {
+            [first, remaining @ ..] => {
+                Some(parse_header(first).and_then(|(path, mut attributes)| {
+                    WriteOptions::from_attributes(&mut attributes).map(|options| OutputFile {
+                        path: paths::clean(&output_dir.join(Path::new(&path))),
+                        content: remaining.join(LINE_ENDING),
+                        attributes,
+                        options,
+                    })
+                }))
+            }
+            [] => None,
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    for file in files.iter() {
+        if !paths::is_within(&file.path, &output_dir) {
+            bail!(format!(
+                "Output file {} is not a child of {}",
+                file.path.display(),
+                output_dir.display()
+            ))
+        }
+    }
+
+    Ok(files)
+}
+
+/// Describe where an output file came from, by its `source` attribute, or otherwise by its
+/// position in the split output.
+fn origin(file: &OutputFile, index: usize) -> String {
+    match file.attributes.get("source") {
+        Some(source) => format!("source {}", source),
+        None => format!("split file {} (which has no source attribute)", index + 1),
+    }
+}
+
+/// Check that no two output files are written to the same path, other than by appending to
+/// it or by replacing different sections of it, failing with the sources of both files
+/// rather than letting the last file win.
+pub fn check_collisions(files: &[OutputFile]) -> Result<()> {
+    let mut paths: HashMap<&Path, usize> = HashMap::new();
+    let mut sections: HashMap<(&Path, &str), usize> = HashMap::new();
+    for (index, file) in files.iter().enumerate() {
+        let path = file.path.as_path();
+        let collision = match (file.options.mode, &file.options.section) {
+            (WriteMode::Replace, _) => paths.get(path),
+            (WriteMode::Section, Some(section)) => sections.get(&(path, section.as_str())),
+            _ => None,
+        };
+        if let Some(&previous) = collision {
+            bail!(ErrorKind::OutputFailure(format!(
+                "Output file {} is written by both {} and {}",
+                file.path.display(),
+                origin(&files[previous], previous),
+                origin(file, index)
+            )))
+        }
+        paths.entry(path).or_insert(index);
+        if let Some(section) = &file.options.section {
+            sections.entry((path, section.as_str())).or_insert(index);
+        }
+    }
+    Ok(())
+}
+
+/// Return whether a line is the `begin` or `end` marker of a named section, containing
+/// `kvasir:begin <name>` or `kvasir:end <name>` within a comment of any syntax.
+fn is_marker(line: &str, marker: &str, name: &str) -> bool {
+    line.split_whitespace()
+        .tuple_windows()
+        .any(|(m, n)| m == marker && n == name)
+}
+
+/// Replace the lines between the marker lines of a named section of a file with new
+/// content, or add the section with its marker lines to the end of the file if it does
+/// not have the section.
+fn merge_section(existing: &str, path: &Path, name: &str, content: &str) -> Result<String> {
+    let lines: Vec<&str> = existing.split_inclusive('\n').collect();
+    let mut merged = String::new();
+    let rest = match lines
+        .iter()
+        .position(|l| is_marker(l, "kvasir:begin", name))
+    {
+        Some(begin) => {
+            let Some(end) = lines[begin + 1..]
+                .iter()
+                .position(|l| is_marker(l, "kvasir:end", name))
+            else {
+                bail!(format!(
+                    "Section {} of {} has no end marker",
+                    name,
+                    path.display()
+                ))
+            };
+            merged.push_str(&lines[..=begin].concat());
+            lines[begin + 1 + end..].concat()
+        }
+        None => {
+            let html = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| HTML_COMMENT_EXTENSIONS.contains(&e.to_lowercase().as_str()));
+            let marker = |marker: &str| match html {
+                true => format!("<!-- {} {} -->", marker, name),
+                false => format!("# {} {}", marker, name),
+            };
+            merged.push_str(existing);
+            if !merged.is_empty() && !merged.ends_with('\n') {
+                merged.push_str(LINE_ENDING);
+            }
+            merged.push_str(&marker("kvasir:begin"));
+            merged.push_str(LINE_ENDING);
+            marker("kvasir:end") + LINE_ENDING
+        }
+    };
+    if !content.is_empty() {
+        merged.push_str(content);
+        if !content.ends_with('\n') {
+            merged.push_str(LINE_ENDING);
+        }
+    }
+    merged.push_str(&rest);
+    Ok(merged)
+}
+
+/// Values made available to front matter templates.
+#[derive(Debug, Serialize)]
+struct FrontMatter {
+    title: String,
+    generated: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(skip)]
+    path: String,
+    #[serde(skip)]
+    attributes: BTreeMap<String, String>,
+}
+
+impl FrontMatter {
+    /// Create the front matter for an output file from its path and header attributes.
+    ///
+    /// The title defaults to the file name without its extension, and tags are provided
+    /// as a comma-separated list.
+    fn new(file: &OutputFile, generated: &str) -> FrontMatter {
+        FrontMatter {
+            title: file.attributes.get("title").cloned().unwrap_or_else(|| {
+                file.path
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or_default()
+            }),
+            generated: generated.to_string(),
+            source: file.attributes.get("source").cloned(),
+            tags: file
+                .attributes
+                .get("tags")
+                .map(|t| {
+                    t.split(',')
+                        .map(|t| t.trim().to_string())
+                        .filter(|t| !t.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            path: file.path.display().to_string(),
+            attributes: file.attributes.to_owned(),
+        }
+    }
+
+    /// Render the front matter, either as YAML or using the provided Tera template.
+    fn render(&self, template: Option<&str>) -> Result<String> {
+        match template {
+            Some(t) => {
+                let mut context = tera::Context::from_serialize(self)
+                    .map_err(|e| format!("Could not create front matter context: {}", e))?;
+                context.insert("path", &self.path);
+                context.insert("attributes", &self.attributes);
+                tera::Tera::one_off(t, &context, false)
+                    .map_err(|e| format!("Could not render front matter template: {:?}", e).into())
+            }
+            None => Ok(format!(
+                "{}---{}",
+                serde_yaml::to_string(self)?.replace('\n', LINE_ENDING),
+                LINE_ENDING
+            )),
+        }
+    }
+}
+
+/// Prepend front matter to each Markdown and HTML output file.
+///
+/// Front matter contains the title of the page, the time at which it was generated and
+/// the `source` and `tags` attributes from the split header line, if present. If a
+/// template is provided it is rendered with these values (and the output `path` and
+/// all header `attributes`) instead of the default YAML.
+///
+/// Files in `section` mode have none, as their content is placed within a page, and files
+/// in `append` mode only have front matter if they start a new page, rather than adding to
+/// an existing file or one started by an earlier output file.
+pub fn add_front_matter(files: &mut [OutputFile], template: Option<&str>) -> Result<()> {
+    let generated = crate::clock::timestamp();
+    let mut started = HashSet::new();
+    for file in files.iter_mut().filter(|f| {
+        let starts_page = match f.options.mode {
+            WriteMode::Replace => true,
+            WriteMode::Append => !f.path.exists() && started.insert(f.path.to_owned()),
+            WriteMode::Section => false,
+        };
+        starts_page
+            && f.path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| FRONT_MATTER_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+    }) {
+        let front_matter = FrontMatter::new(file, &generated).render(template)?;
+        file.content = format!("{}{}", front_matter, file.content);
+    }
+    Ok(())
+}
+
+/// Write rendered templates information to one or more files, returning the paths of
+/// the files which were written.
+///
+/// By default, this function will refuse to overwrite existing files unless
+/// `allow_overwrite` is set, or the file's `overwrite` option is set. Files in `append`
+/// mode are appended to, on a new line, and files in `section` mode have their section
+/// replaced, either being created if they do not exist. Files are written atomically, and
+/// no further files are written once cancellation has been requested.
+pub fn write_rendered_files(entries: &[OutputFile], allow_overwrite: bool) -> Vec<PathBuf> {
+    entries
+        .iter()
+        .take_while(|_| !cancel::cancelled())
+        .filter_map(|entry| {
+            let file = &entry.path;
+            let overwrite = entry.options.overwrite.unwrap_or(allow_overwrite);
+            let merge = entry.options.mode != WriteMode::Replace;
+            if !file.exists() || overwrite || merge {
+                debug!("Writing output file {}", file.display());
+                match std::fs::create_dir_all(file.parent().unwrap())
+                    .map_err(Error::from)
+                    .and_then(|_| write_entry(entry))
+                {
+                    Ok(_) => {
+                        progress::record_written(entry);
+                        Some(file.to_owned())
+                    }
+                    Err(e) => {
+                        error!(
+                            "Could not write output file {}: {}",
+                            file.display(),
+                            e.to_string()
+                        );
+                        notify::record(|s| s.write_failures += 1);
+                        None
+                    }
+                }
+            } else {
+                error!(
+                    "Could not write output file {}: File exists.",
+                    file.display()
+                );
+                notify::record(|s| s.write_failures += 1);
+                None
+            }
+        })
+        .unique()
+        .collect()
+}
+
+/// Write an output file in its write mode, merging its content with that of an existing
+/// file when appending or replacing a section.
+fn write_entry(entry: &OutputFile) -> Result<()> {
+    let path = &entry.path;
+    let data = match (entry.options.mode, &entry.options.section) {
+        (WriteMode::Append, _) if path.exists() => {
+            let mut data = fs::read(path)?;
+            if data.last().is_some_and(|b| *b != b'\n') && !entry.content.is_empty() {
+                data.extend_from_slice(LINE_ENDING.as_bytes());
+            }
+            data.extend_from_slice(entry.content.as_bytes());
+            data
+        }
+        (WriteMode::Section, Some(section)) => {
+            let existing = match path.exists() {
+                true => fs::read_to_string(path)?,
+                false => String::new(),
+            };
+            merge_section(&existing, path, section, &entry.content)?.into_bytes()
+        }
+        _ => entry.content.as_bytes().to_vec(),
+    };
+    Ok(write_atomically(path, &data)?)
+}
+
+/// Write a file by writing to a temporary file in the same directory and renaming it, so
+/// that an interrupted write never leaves a partially written file.
+fn write_atomically(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let temp = path.with_file_name(format!(
+        ".{}.kvasir-tmp",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    fs::write(&temp, data)
+        .and_then(|_| fs::rename(&temp, path))
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&temp);
+        })
+}
+
+/// Run the configured hooks on each written file matching the hook's pattern.
+///
+/// Patterns are matched against the path of each file relative to the output directory.
+/// Hook failures are logged but do not prevent other hooks from running.
+pub fn run_hooks(hooks: &[Hook], files: &[PathBuf], output_dir: &Path) {
+    for hook in hooks {
+        let pattern = match glob::Pattern::new(&hook.pattern) {
+            Ok(p) => p,
+            Err(e) => {
+                error!("Invalid hook pattern {}: {}", hook.pattern, e);
+                continue;
+            }
+        };
+        let matching = files
+            .iter()
+            .filter(|f| pattern.matches_path(f.strip_prefix(output_dir).unwrap_or(f)))
+            .collect_vec();
+
+        if hook.batch {
+            if !matching.is_empty() {
+                run_hook(hook, &matching);
+            }
+        } else {
+            matching.iter().for_each(|f| run_hook(hook, &[f]));
+        }
+    }
+}
+
+/// Run a single hook command with the specified files as its final arguments.
+fn run_hook(hook: &Hook, files: &[&PathBuf]) {
+    let (program, args) = match hook.command.split_first() {
+        Some(c) => c,
+        None => {
+            error!("Hook for pattern {} has no command.", hook.pattern);
+            return;
+        }
+    };
+    info!(
+        "Running hook {} on {}",
+        hook.command.join(" "),
+        files.iter().map(|f| f.display()).join(" ")
+    );
+    match std::process::Command::new(program)
+        .args(args)
+        .args(files)
+        .output()
+    {
+        Ok(output) if output.status.success() => (),
+        Ok(output) => error!(
+            "Hook {} failed ({}): {}",
+            hook.command.join(" "),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        Err(e) => error!("Could not run hook {}: {}", hook.command.join(" "), e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::str::FromStr;
+
+    #[test]
+    fn split_template_content() {
+        let splits = crate::output::split_template_content(
+            "8<--",
+            r#"
+        8<-- /tmp/one
+        a
+        8<--/tmp/two
+        b
+        8<--/tmp/three
+        c
+        d
+        "#,
+            std::path::PathBuf::from_str("/tmp").unwrap(),
+        );
+
+        assert!(splits.is_ok());
+
+        let file_splits = splits.unwrap();
+
+        println!("{:?}", &file_splits);
+        assert_eq!(file_splits.len(), 3);
+
+        match file_splits.as_slice() {
+            [one, two, three] => {
+                assert_eq!(one.path.to_str().unwrap(), "/tmp/one");
+                assert!(one.content.contains('a'));
+                assert_eq!(two.path.to_str().unwrap(), "/tmp/two");
+                assert!(two.content.contains('b'));
+                assert_eq!(three.path.to_str().unwrap(), "/tmp/three");
+                assert!(three.content.contains('d'));
+            }
+            _ => {
+                panic!("Three results should always be returned!")
+            }
+        }
+    }
+
+    #[test]
+    fn collisions() {
+        let split = |contents: &str| {
+            crate::output::split_template_content("8<--", contents, PathBuf::from("/tmp")).unwrap()
+        };
+        let check = |contents| crate::output::check_collisions(&split(contents));
+
+        assert!(check("8<-- a.md source=a.yaml\na\n8<-- b.md source=b.yaml\nb\n").is_ok());
+        assert!(check("8<-- log.md\na\n8<-- log.md mode=append\nb\n").is_ok());
+        assert!(check("8<-- i.md\ni\n8<-- i.md section=a\na\n8<-- i.md section=b\nb\n").is_ok());
+        assert!(check("8<-- i.md section=a\na\n8<-- i.md section=a\nb\n").is_err());
+        assert!(check("8<-- i.md section=a\na\n8<-- i.md\nb\n").is_err());
+        assert_eq!(
+            check("8<-- svc.md source=a.yaml\na\n8<-- x.md\nx\n8<-- svc.md source=b.yaml\nb\n")
+                .unwrap_err()
+                .to_string(),
+            "Output file /tmp/svc.md is written by both source a.yaml and source b.yaml"
+        );
+        assert_eq!(
+            check("8<-- svc.md\na\n8<-- ./svc.md\nb\n")
+                .unwrap_err()
+                .to_string(),
+            "Output file /tmp/svc.md is written by both split file 1 (which has no source \
+             attribute) and split file 2 (which has no source attribute)"
+        );
+    }
+
+    #[test]
+    fn split_header_attributes() {
+        let splits = crate::output::split_template_content(
+            "8<--",
+            "8<-- docs/my page.md source=a.yaml tags=\"a, b\"\ncontent\n",
+            PathBuf::from("/tmp"),
+        )
+        .unwrap();
+
+        assert_eq!(splits[0].path, PathBuf::from("/tmp/docs/my page.md"));
+        assert_eq!(splits[0].attributes.get("source").unwrap(), "a.yaml");
+        assert_eq!(splits[0].attributes.get("tags").unwrap(), "a, b");
+
+        let split = |contents: &str| {
+            crate::output::split_template_content("8<--", contents, PathBuf::from("/tmp"))
+        };
+        // Only known attributes follow a path, so other `key=value` tokens are part of it
+        let splits = split("8<-- docs/x y=z.md mode=append\ncontent\n").unwrap();
+        assert_eq!(splits[0].path, PathBuf::from("/tmp/docs/x y=z.md"));
+        let splits = split("8<-- path=docs/x.md weight=2\ncontent\n").unwrap();
+        assert_eq!(splits[0].path, PathBuf::from("/tmp/docs/x.md"));
+        assert_eq!(splits[0].attributes.get("weight").unwrap(), "2");
+        assert_eq!(
+            split("8<-- mode=append\ncontent\n")
+                .unwrap_err()
+                .to_string(),
+            "Split header 'mode=append' has no output path"
+        );
+        assert!(split("8<--\ncontent\n").is_err());
+        // Anything before the first split is ignored
+        assert!(split("preamble\n").unwrap().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_hooks() {
+        let dir = std::env::temp_dir().join(format!("kvasir-hooks-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("docs")).unwrap();
+        let files = crate::output::write_rendered_files(
+            &[
+                crate::output::OutputFile {
+                    path: dir.join("docs/a.md"),
+                    content: "a".to_string(),
+                    attributes: Default::default(),
+                    options: Default::default(),
+                },
+                crate::output::OutputFile {
+                    path: dir.join("b.txt"),
+                    content: "b".to_string(),
+                    attributes: Default::default(),
+                    options: Default::default(),
+                },
+            ],
+            true,
+        );
+        assert_eq!(files.len(), 2);
+
+        let hook = crate::config::Hook {
+            pattern: "**/*.md".to_string(),
+            command: vec![
+                "sh".to_string(),
+                "-c".to_string(),
+                "echo hooked >> \"$0\"".to_string(),
+            ],
+            batch: false,
+        };
+        crate::output::run_hooks(&[hook], &files, &dir);
+
+        assert_eq!(
+            std::fs::read_to_string(dir.join("docs/a.md")).unwrap(),
+            "ahooked\n"
+        );
+        assert_eq!(std::fs::read_to_string(dir.join("b.txt")).unwrap(), "b");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn front_matter() {
+        let mut files = crate::output::split_template_content(
+            "8<--",
+            "8<-- a.md source=a.yaml tags=infra,prod\n# A\n8<-- b.txt\nB\n",
+            PathBuf::from("/tmp"),
+        )
+        .unwrap();
+
+        crate::output::add_front_matter(&mut files, None).unwrap();
+        let lines = files[0].content.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "---");
+        assert_eq!(lines[1], "title: a");
+        assert!(lines[2].starts_with("generated: "));
+        assert_eq!(lines[3], "source: a.yaml");
+        assert_eq!(
+            &lines[4..],
+            &["tags:", "  - infra", "  - prod", "---", "# A"]
+        );
+        assert_eq!(files[1].content, "B");
+
+        let mut files = crate::output::split_template_content(
+            "8<--",
+            "8<-- path=a.md weight=2\n# A\n",
+            PathBuf::from("/tmp"),
+        )
+        .unwrap();
+        crate::output::add_front_matter(
+            &mut files,
+            Some("+++\ntitle = \"{{ title }}\"\nweight = {{ attributes.weight }}\n+++\n"),
+        )
+        .unwrap();
+        assert_eq!(files[0].content, "+++\ntitle = \"a\"\nweight = 2\n+++\n# A");
+
+        // Only files starting a new page have front matter
+        let dir = std::env::temp_dir().join(format!("kvasir-front-matter-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.md"), "# A\n").unwrap();
+        let mut files = crate::output::split_template_content(
+            "8<--",
+            "8<-- a.md mode=append\nmore\n\
+             8<-- b.md mode=append\nfirst\n\
+             8<-- b.md mode=append\nsecond\n\
+             8<-- c.md mode=section section=s\nsection\n",
+            dir.to_owned(),
+        )
+        .unwrap();
+        crate::output::add_front_matter(&mut files, Some("---\n")).unwrap();
+        assert_eq!(
+            files.iter().map(|f| f.content.as_str()).collect::<Vec<_>>(),
+            ["more", "---\nfirst", "second", "section"]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn split_header_options() {
+        let dir = std::env::temp_dir().join(format!("kvasir-options-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.md"), "a\n").unwrap();
+        std::fs::write(dir.join("b.md"), "b\n").unwrap();
+        std::fs::write(dir.join("c.md"), "c\n").unwrap();
+        std::fs::write(dir.join("d.md"), "d").unwrap();
+
+        let files = crate::output::split_template_content(
+            "8<--",
+            "8<-- path=a.md mode=append source=x\nappended\n\
+             8<-- b.md overwrite=true\nreplaced\n\
+             8<-- c.md\nunchanged\n\
+             8<-- d.md mode=append\nappended\n",
+            dir.to_owned(),
+        )
+        .unwrap();
+        assert_eq!(files[0].path, dir.join("a.md"));
+        assert_eq!(files[0].options.mode, crate::output::WriteMode::Append);
+        assert_eq!(files[0].attributes.keys().collect::<Vec<_>>(), ["source"]);
+        assert_eq!(files[1].options.overwrite, Some(true));
+
+        let written = crate::output::write_rendered_files(&files, false);
+        assert_eq!(written.len(), 3);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("a.md")).unwrap(),
+            "a\nappended"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("b.md")).unwrap(),
+            "replaced"
+        );
+        assert_eq!(std::fs::read_to_string(dir.join("c.md")).unwrap(), "c\n");
+        assert_eq!(
+            std::fs::read_to_string(dir.join("d.md")).unwrap(),
+            "d\nappended"
+        );
+
+        assert!(crate::output::split_template_content(
+            "8<--",
+            "8<-- a.md mode=prepend\n",
+            dir.to_owned()
+        )
+        .is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn section_mode() {
+        let dir = std::env::temp_dir().join(format!("kvasir-sections-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("CHANGELOG.md"),
+            "# Changes\n<!-- kvasir:begin orders -->\nold\n<!-- kvasir:end orders -->\nfooter\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("broken.md"), "<!-- kvasir:begin orders -->\nold\n").unwrap();
+
+        let write = |contents: &str| {
+            let files =
+                crate::output::split_template_content("8<--", contents, dir.to_owned()).unwrap();
+            assert!(files
+                .iter()
+                .all(|f| f.options.mode == crate::output::WriteMode::Section));
+            crate::output::write_rendered_files(&files, false)
+        };
+        let written = write(
+            "8<-- CHANGELOG.md section=orders\n* 1.4.2\n* 1.4.1\n\
+             8<-- CHANGELOG.md mode=section section=payments\n* 2.0.0\n\
+             8<-- config.yaml section=generated\nkey: value\n\
+             8<-- broken.md section=orders\nnew\n",
+        );
+        assert_eq!(written, [dir.join("CHANGELOG.md"), dir.join("config.yaml")]);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("CHANGELOG.md")).unwrap(),
+            "# Changes\n<!-- kvasir:begin orders -->\n* 1.4.2\n* 1.4.1\n<!-- kvasir:end orders -->\n\
+             footer\n<!-- kvasir:begin payments -->\n* 2.0.0\n<!-- kvasir:end payments -->\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("config.yaml")).unwrap(),
+            "# kvasir:begin generated\nkey: value\n# kvasir:end generated\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("broken.md")).unwrap(),
+            "<!-- kvasir:begin orders -->\nold\n"
+        );
+
+        // Sections are replaced again on later runs
+        write("8<-- config.yaml section=generated\nkey: changed\n");
+        assert_eq!(
+            std::fs::read_to_string(dir.join("config.yaml")).unwrap(),
+            "# kvasir:begin generated\nkey: changed\n# kvasir:end generated\n"
+        );
+
+        for invalid in [
+            "8<-- a.md mode=section\n",
+            "8<-- a.md mode=append section=a\n",
+        ] {
+            assert!(
+                crate::output::split_template_content("8<--", invalid, dir.to_owned()).is_err()
+            );
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
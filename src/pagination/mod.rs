@@ -0,0 +1,298 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Pagination of split Markdown and HTML output files which are too large, for example for
+//! GitHub to render.
+//!
+//! A page larger than the maximum size is split into numbered parts, `page.md`,
+//! `page-2.md`, `page-3.md` and so on, so that links to the first part remain valid. Pages
+//! are split before headings where possible, and otherwise between paragraphs, but never
+//! within fenced code blocks. Each part ends with links to the previous and next parts.
+
+use crate::output::{OutputFile, WriteMode};
+use std::path::{Path, PathBuf};
+
+/// Whether a page is Markdown or HTML, which determines where it is split and how links
+/// are written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Markdown,
+    Html,
+}
+
+impl Format {
+    /// Return the format of a file from its extension, if it can be paginated.
+    fn of(path: &Path) -> Option<Format> {
+        match path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .as_deref()
+        {
+            Some("md") | Some("markdown") => Some(Format::Markdown),
+            Some("html") | Some("htm") => Some(Format::Html),
+            _ => None,
+        }
+    }
+
+    /// Return whether a line is a heading, before which a page is best split.
+    fn is_heading(&self, line: &str) -> bool {
+        let line = line.trim_start();
+        match self {
+            Format::Markdown => line.starts_with('#'),
+            Format::Html => ["<h1", "<h2", "<h3"]
+                .iter()
+                .any(|h| line.to_lowercase().starts_with(h)),
+        }
+    }
+
+    /// Return the navigation links appended to a part.
+    fn navigation(
+        &self,
+        previous: Option<&str>,
+        next: Option<&str>,
+        part: usize,
+        parts: usize,
+    ) -> String {
+        match self {
+            Format::Markdown => {
+                let mut links = vec![];
+                if let Some(previous) = previous {
+                    links.push(format!("[← Previous]({})", previous));
+                }
+                links.push(format!("Part {} of {}", part, parts));
+                if let Some(next) = next {
+                    links.push(format!("[Next →]({})", next));
+                }
+                format!("\n---\n\n{}\n", links.join(" · "))
+            }
+            Format::Html => {
+                let mut links = vec![];
+                if let Some(previous) = previous {
+                    links.push(format!(
+                        "<a href=\"{}\" rel=\"prev\">← Previous</a>",
+                        previous
+                    ));
+                }
+                links.push(format!("<span>Part {} of {}</span>", part, parts));
+                if let Some(next) = next {
+                    links.push(format!("<a href=\"{}\" rel=\"next\">Next →</a>", next));
+                }
+                format!(
+                    "\n<nav class=\"kvasir-pagination\">{}</nav>\n",
+                    links.join(" ")
+                )
+            }
+        }
+    }
+}
+
+/// Split each Markdown and HTML file larger than the maximum size in bytes into numbered
+/// parts with links between them. Other files, and files appended to, are unchanged.
+pub fn paginate(files: Vec<OutputFile>, max_size: usize) -> Vec<OutputFile> {
+    files
+        .into_iter()
+        .flat_map(|file| match Format::of(&file.path) {
+            Some(format)
+                if file.content.len() > max_size && file.options.mode == WriteMode::Replace =>
+            {
+                split_file(file, format, max_size)
+            }
+            _ => vec![file],
+        })
+        .collect()
+}
+
+/// Split a file into parts of at most the maximum size where possible.
+fn split_file(file: OutputFile, format: Format, max_size: usize) -> Vec<OutputFile> {
+    let parts = pack(blocks(&file.content, format, max_size), max_size);
+    if parts.len() < 2 {
+        return vec![file];
+    }
+    let paths: Vec<PathBuf> = (1..=parts.len())
+        .map(|n| part_path(&file.path, n))
+        .collect();
+    let name = |i: usize| {
+        paths[i]
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default()
+    };
+    parts
+        .iter()
+        .enumerate()
+        .map(|(i, content)| OutputFile {
+            path: paths[i].to_owned(),
+            content: format!(
+                "{}{}",
+                content,
+                format.navigation(
+                    (i > 0).then(|| name(i - 1)).as_deref(),
+                    (i + 1 < parts.len()).then(|| name(i + 1)).as_deref(),
+                    i + 1,
+                    parts.len()
+                )
+            ),
+            attributes: file.attributes.to_owned(),
+            options: file.options.to_owned(),
+        })
+        .collect()
+}
+
+/// Return the path of a numbered part of a file: the file itself for the first part, and
+/// otherwise the file with the number after its name, e.g. `api-2.md`.
+fn part_path(path: &Path, part: usize) -> PathBuf {
+    if part == 1 {
+        return path.to_path_buf();
+    }
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match path.extension() {
+        Some(extension) => {
+            path.with_file_name(format!("{}-{}.{}", stem, part, extension.to_string_lossy()))
+        }
+        None => path.with_file_name(format!("{}-{}", stem, part)),
+    }
+}
+
+/// Split content into blocks which begin at headings outside code fences, further splitting
+/// blocks larger than the maximum size between paragraphs.
+fn blocks(content: &str, format: Format, max_size: usize) -> Vec<String> {
+    let mut sections: Vec<Vec<(&str, bool)>> = vec![vec![]];
+    let mut fenced = false;
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        let is_fence = format == Format::Markdown
+            && (trimmed.starts_with("```") || trimmed.starts_with("~~~"));
+        if !fenced && format.is_heading(line) && sections.last().is_some_and(|s| !s.is_empty()) {
+            sections.push(vec![]);
+        }
+        if is_fence {
+            fenced = !fenced;
+        }
+        // Whether the page may be split after this line
+        let breakable = !fenced && line.trim().is_empty();
+        if let Some(section) = sections.last_mut() {
+            section.push((line, breakable));
+        }
+    }
+
+    let mut blocks = vec![];
+    for section in sections {
+        let size: usize = section.iter().map(|(l, _)| l.len()).sum();
+        if size <= max_size {
+            blocks.push(section.iter().map(|(l, _)| *l).collect());
+            continue;
+        }
+        let mut paragraph = String::new();
+        for (line, breakable) in section {
+            paragraph.push_str(line);
+            if breakable {
+                blocks.push(std::mem::take(&mut paragraph));
+            }
+        }
+        if !paragraph.is_empty() {
+            blocks.push(paragraph);
+        }
+    }
+    blocks
+}
+
+/// Pack blocks into as few parts of at most the maximum size as possible whilst keeping
+/// their order. Blocks larger than the maximum size have a part to themselves.
+fn pack(blocks: Vec<String>, max_size: usize) -> Vec<String> {
+    let mut parts: Vec<String> = vec![];
+    let mut current = String::new();
+    for block in blocks {
+        if !current.is_empty() && current.len() + block.len() > max_size {
+            parts.push(std::mem::take(&mut current));
+        }
+        current.push_str(&block);
+    }
+    if !current.trim().is_empty() || parts.is_empty() {
+        parts.push(current);
+    } else if let Some(last) = parts.last_mut() {
+        last.push_str(&current);
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::output::OutputFile;
+    use crate::pagination::paginate;
+    use std::path::PathBuf;
+
+    fn file(path: &str, content: &str) -> OutputFile {
+        OutputFile {
+            path: PathBuf::from(path),
+            content: content.to_string(),
+            attributes: Default::default(),
+            options: Default::default(),
+        }
+    }
+
+    #[test]
+    fn markdown() {
+        let content =
+            "# API\nIntro\n## GET /a\nText a\n```\n# not a heading\n```\n## GET /b\nText b\n";
+        let pages = paginate(
+            vec![file("docs/api.md", content), file("small.md", "# Small\n")],
+            40,
+        );
+
+        assert_eq!(
+            pages
+                .iter()
+                .map(|p| p.path.to_str().unwrap())
+                .collect::<Vec<_>>(),
+            ["docs/api.md", "docs/api-2.md", "docs/api-3.md", "small.md"]
+        );
+        assert_eq!(
+            pages[0].content,
+            "# API\nIntro\n\n---\n\nPart 1 of 3 · [Next →](api-2.md)\n"
+        );
+        assert_eq!(
+            pages[1].content,
+            "## GET /a\nText a\n```\n# not a heading\n```\n\n---\n\n[← Previous](api.md) · Part 2 of 3 · [Next →](api-3.md)\n"
+        );
+        assert!(pages[2].content.starts_with("## GET /b\nText b\n"));
+        assert!(pages[2]
+            .content
+            .ends_with("[← Previous](api-2.md) · Part 3 of 3\n"));
+        assert_eq!(pages[3].content, "# Small\n");
+    }
+
+    #[test]
+    fn paragraphs() {
+        let content = "# Long\nOne one one.\n\nTwo two two.\n\nThree three.\n";
+        let pages = paginate(vec![file("long.html", content)], 40);
+        assert_eq!(pages.len(), 2);
+        assert!(pages[0]
+            .content
+            .starts_with("# Long\nOne one one.\n\nTwo two two.\n\n"));
+        assert!(pages[0]
+            .content
+            .contains("<a href=\"long-2.html\" rel=\"next\">Next →</a>"));
+        assert!(pages[1].content.starts_with("Three three.\n"));
+
+        let other = paginate(vec![file("data.json", content)], 10);
+        assert_eq!(other[0].content, content);
+    }
+}
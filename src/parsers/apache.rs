@@ -0,0 +1,241 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Parser for Apache httpd configuration files, such as `httpd.conf` and `.htaccess`.
+
+use super::{has_extension, Capabilities, FileParser, ParseContext, ParsedDocument};
+use crate::errors::*;
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// The names of files which are always Apache configuration files.
+const FILE_NAMES: &[&str] = &["httpd.conf", "apache2.conf", ".htaccess"];
+
+/// The names of directories whose `.conf` files are Apache configuration files.
+const DIRECTORIES: &[&str] = &[
+    "apache",
+    "apache2",
+    "httpd",
+    "sites-available",
+    "sites-enabled",
+    "conf-available",
+    "conf-enabled",
+    "mods-available",
+    "mods-enabled",
+];
+
+/// File parser for Apache httpd configuration files.
+///
+/// Parses `httpd.conf`, `apache2.conf` and `.htaccess` files, and `.conf` files within an
+/// `apache2` or `httpd` directory or a Debian-style `sites-enabled` directory, for example.
+/// Returns the `directives` in order, each with its `name`, its `args`, with quotes removed,
+/// and its `line`. Sections such as `<VirtualHost>`, `<Directory>` and `<IfModule>` are
+/// returned as directives with the `directives` within them. The `virtual_hosts`, including
+/// those within other sections, are also summarised with their `addresses`, `server_name`,
+/// `server_aliases`, `document_root` and `line`, for inventories of the sites served.
+pub struct ApacheConfParser {}
+
+/// A section whose closing tag has not yet been read.
+struct Section {
+    name: String,
+    args: Vec<String>,
+    line: usize,
+    directives: Vec<Value>,
+}
+
+impl ApacheConfParser {
+    /// Split the arguments of a directive, removing quotes and the escaping of quotes.
+    fn args(text: &str, number: usize) -> Result<Vec<String>> {
+        let mut args = vec![];
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                c if c.is_whitespace() => continue,
+                '"' | '\'' => {
+                    let mut arg = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('\\') if chars.peek() == Some(&c) => {
+                                chars.next();
+                                arg.push(c)
+                            }
+                            Some(next) if next == c => break,
+                            Some(next) => arg.push(next),
+                            None => bail!(format!("Unterminated quote on line {}", number)),
+                        }
+                    }
+                    args.push(arg);
+                }
+                c => {
+                    let mut arg = c.to_string();
+                    while let Some(next) = chars.next_if(|n| !n.is_whitespace()) {
+                        arg.push(next);
+                    }
+                    args.push(arg);
+                }
+            }
+        }
+        Ok(args)
+    }
+
+    /// Return the logical lines of a file with their line numbers, joining lines ending
+    /// with a backslash to the following line, and skipping blank lines and comments.
+    fn lines(text: &str) -> Vec<(usize, String)> {
+        let mut lines = vec![];
+        let mut continued: Option<(usize, String)> = None;
+        for (number, line) in text.lines().enumerate() {
+            let (start, mut joined) = continued.take().unwrap_or((number + 1, String::new()));
+            let trimmed = line.trim();
+            if joined.is_empty() && (trimmed.is_empty() || trimmed.starts_with('#')) {
+                continue;
+            }
+            match trimmed.strip_suffix('\\') {
+                Some(part) => {
+                    joined.push_str(part);
+                    joined.push(' ');
+                    continued = Some((start, joined));
+                }
+                None => {
+                    joined.push_str(trimmed);
+                    lines.push((start, joined));
+                }
+            }
+        }
+        lines.extend(continued);
+        lines
+    }
+
+    /// Summarise the virtual hosts within a list of directives, including within sections.
+    fn virtual_hosts(directives: &[Value], hosts: &mut Vec<Value>) {
+        for directive in directives {
+            let children = directive["directives"].as_array().map(Vec::as_slice);
+            let name = directive["name"].as_str().unwrap_or_default();
+            if name.eq_ignore_ascii_case("VirtualHost") {
+                let children = children.unwrap_or_default();
+                let args = |wanted: &str| {
+                    children
+                        .iter()
+                        .filter(|d| {
+                            d["name"]
+                                .as_str()
+                                .is_some_and(|n| n.eq_ignore_ascii_case(wanted))
+                        })
+                        .flat_map(|d| d["args"].as_array().cloned().unwrap_or_default())
+                        .collect::<Vec<_>>()
+                };
+                hosts.push(json!({
+                    "addresses": directive["args"],
+                    "server_name": args("ServerName").first(),
+                    "server_aliases": args("ServerAlias"),
+                    "document_root": args("DocumentRoot").first(),
+                    "line": directive["line"],
+                }));
+            } else if let Some(children) = children {
+                ApacheConfParser::virtual_hosts(children, hosts);
+            }
+        }
+    }
+}
+
+impl FileParser for ApacheConfParser {
+    fn name(&self) -> &'static str {
+        "apache"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn can_parse(&self, context: &ParseContext) -> bool {
+        let path = context.path();
+        let file_name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        FILE_NAMES.contains(&file_name)
+            || (has_extension(path, &["conf"])
+                && path
+                    .parent()
+                    .into_iter()
+                    .flat_map(Path::components)
+                    .any(|c| {
+                        c.as_os_str()
+                            .to_str()
+                            .is_some_and(|d| DIRECTORIES.contains(&d))
+                    }))
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let mut sections: Vec<Section> = vec![];
+        let mut directives: Vec<Value> = vec![];
+        for (number, line) in ApacheConfParser::lines(context.text()?) {
+            let directive = if let Some(tag) = line.strip_prefix("</") {
+                let name = tag.strip_suffix('>').unwrap_or(tag).trim();
+                match sections.pop() {
+                    Some(section) if section.name.eq_ignore_ascii_case(name) => json!({
+                        "name": section.name,
+                        "args": section.args,
+                        "line": section.line,
+                        "directives": section.directives,
+                    }),
+                    Some(section) => bail!(format!(
+                        "Unexpected </{}> on line {}, expected </{}> closing line {}",
+                        name, number, section.name, section.line
+                    )),
+                    None => bail!(format!(
+                        "Unexpected </{}> on line {} outside any section",
+                        name, number
+                    )),
+                }
+            } else if let Some(tag) = line.strip_prefix('<') {
+                let tag = tag
+                    .strip_suffix('>')
+                    .ok_or_else(|| format!("Unterminated section tag on line {}", number))?;
+                let (name, args) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+                sections.push(Section {
+                    name: name.to_string(),
+                    args: ApacheConfParser::args(args, number)?,
+                    line: number,
+                    directives: vec![],
+                });
+                continue;
+            } else {
+                let (name, args) = line.split_once(char::is_whitespace).unwrap_or((&line, ""));
+                json!({
+                    "name": name,
+                    "args": ApacheConfParser::args(args, number)?,
+                    "line": number,
+                })
+            };
+            match sections.last_mut() {
+                Some(section) => section.directives.push(directive),
+                None => directives.push(directive),
+            }
+        }
+        if let Some(section) = sections.last() {
+            bail!(format!(
+                "Section <{}> on line {} is not closed",
+                section.name, section.line
+            ))
+        }
+        let mut virtual_hosts = vec![];
+        ApacheConfParser::virtual_hosts(&directives, &mut virtual_hosts);
+        Ok(ParsedDocument::new(json!({
+            "directives": directives,
+            "virtual_hosts": virtual_hosts,
+        })))
+    }
+}
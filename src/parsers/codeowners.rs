@@ -0,0 +1,357 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! CODEOWNERS files, in GitHub or GitLab syntax, and the owners they assign to parsed files.
+//!
+//! Patterns follow `.gitignore` rules: a pattern without a slash, other than a trailing one,
+//! matches at any depth, a pattern naming a directory matches everything beneath it, and the
+//! last matching pattern wins. Patterns in different GitLab `[Section]`s are matched
+//! separately, and the owners from the last matching pattern of each section are combined.
+
+use super::{Capabilities, FileParser, ParseContext, ParseSuccess, ParsedDocument};
+use crate::errors::*;
+use glob::{MatchOptions, Pattern};
+use log::{debug, warn};
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The locations of a CODEOWNERS file relative to the root of a repository, in the order
+/// in which they are searched.
+const LOCATIONS: &[&str] = &[
+    ".github/CODEOWNERS",
+    "CODEOWNERS",
+    "docs/CODEOWNERS",
+    ".gitlab/CODEOWNERS",
+];
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// A GitLab section of a CODEOWNERS file.
+struct Section {
+    name: String,
+    /// Whether approval from the section's owners is optional, for a `^[Section]`.
+    optional: bool,
+    /// The number of approvals required, for a `[Section][2]`.
+    approvals: Option<u64>,
+    /// The owners of patterns in the section which name no owners.
+    owners: Vec<String>,
+}
+
+/// A pattern and the owners of the files it matches.
+struct Rule {
+    line: usize,
+    pattern: String,
+    /// The owners of matching files, which may be empty to leave them without an owner.
+    owners: Vec<String>,
+    /// The index of the section of the rule, if any.
+    section: Option<usize>,
+    globs: Vec<Pattern>,
+}
+
+/// The rules of a CODEOWNERS file.
+pub struct CodeOwners {
+    /// The directory against which patterns are matched.
+    root: PathBuf,
+    sections: Vec<Section>,
+    rules: Vec<Rule>,
+}
+
+/// Return the glob patterns matching the files owned through a CODEOWNERS pattern.
+fn globs(pattern: &str) -> Result<Vec<Pattern>> {
+    let directory = pattern.ends_with('/');
+    let trimmed = pattern.trim_end_matches('/');
+    let anchored = trimmed.contains('/');
+    let trimmed = trimmed.trim_start_matches('/');
+    let base = match trimmed {
+        "" => "**".to_string(),
+        _ if anchored || trimmed.starts_with("**") => trimmed.to_string(),
+        _ => format!("**/{}", trimmed),
+    };
+    let pattern = |glob: &str| Pattern::new(glob).map_err(|e| e.to_string());
+    let mut globs = vec![pattern(&base)?];
+    // As in GitHub, `docs/*` owns the files in `docs` but not those in its subdirectories
+    if directory || !trimmed.rsplit('/').next().unwrap_or_default().contains('*') {
+        globs.push(pattern(&format!("{}/**", base))?);
+    }
+    Ok(globs)
+}
+
+/// Return a section header, such as `[Docs]`, `^[Docs][2] @docs`, with its name, whether it
+/// is optional, the number of approvals required and the rest of the line.
+fn section_header(line: &str) -> Option<(&str, bool, Option<u64>, &str)> {
+    let (optional, header) = match line.strip_prefix('^') {
+        Some(header) => (true, header),
+        None => (false, line),
+    };
+    let (name, rest) = header.strip_prefix('[')?.split_once(']')?;
+    let (approvals, rest) = match rest.strip_prefix('[').and_then(|r| r.split_once(']')) {
+        Some((approvals, rest)) => (Some(approvals.trim().parse().ok()?), rest),
+        None => (None, rest),
+    };
+    (rest.is_empty() || rest.starts_with(char::is_whitespace)).then_some((
+        name.trim(),
+        optional,
+        approvals,
+        rest,
+    ))
+}
+
+impl CodeOwners {
+    /// Parse the text of a CODEOWNERS file, with patterns matched against paths relative to
+    /// the root directory, returning warnings of any invalid patterns.
+    pub fn parse(text: &str, root: &Path) -> (CodeOwners, Vec<String>) {
+        let mut sections: Vec<Section> = vec![];
+        let mut rules = vec![];
+        let mut warnings = vec![];
+        for (number, line) in text.lines().enumerate() {
+            let line = match line.find(" #") {
+                Some(comment) => &line[..comment],
+                None => line,
+            }
+            .trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some((name, optional, approvals, owners)) = section_header(line) {
+                sections.push(Section {
+                    name: name.to_string(),
+                    optional,
+                    approvals,
+                    owners: owners.split_whitespace().map(String::from).collect(),
+                });
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let pattern = words.next().unwrap_or_default().replace("\\#", "#");
+            let mut owners: Vec<String> = words.map(String::from).collect();
+            if owners.is_empty() {
+                if let Some(section) = sections.last() {
+                    owners = section.owners.to_owned();
+                }
+            }
+            match globs(&pattern) {
+                Ok(globs) => rules.push(Rule {
+                    line: number + 1,
+                    pattern,
+                    owners,
+                    section: sections.len().checked_sub(1),
+                    globs,
+                }),
+                Err(e) => warnings.push(format!(
+                    "Invalid pattern {} on line {}: {}",
+                    pattern,
+                    number + 1,
+                    e
+                )),
+            }
+        }
+        let owners = CodeOwners {
+            root: root.to_owned(),
+            sections,
+            rules,
+        };
+        (owners, warnings)
+    }
+
+    /// Read a CODEOWNERS file. Patterns are matched against paths relative to the directory
+    /// containing it or, if it is within `.github`, `.gitlab` or `docs`, the parent of that
+    /// directory.
+    pub fn load(path: &Path) -> Result<CodeOwners> {
+        let path = path
+            .canonicalize()
+            .chain_err(|| format!("Could not find CODEOWNERS file {}", path.display()))?;
+        let text = fs::read_to_string(&path)
+            .chain_err(|| format!("Could not read CODEOWNERS file {}", path.display()))?;
+        let dir = path.parent().unwrap_or(Path::new("/"));
+        let root = match dir.file_name().and_then(|n| n.to_str()) {
+            Some(".github" | ".gitlab" | "docs") => dir.parent().unwrap_or(dir),
+            _ => dir,
+        };
+        let (owners, warnings) = CodeOwners::parse(&text, root);
+        warnings
+            .iter()
+            .for_each(|w| warn!("{} in {}", w, path.display()));
+        Ok(owners)
+    }
+
+    /// Read the CODEOWNERS file at a path or, if no path is given, at one of the standard
+    /// locations within the current directory, if there is one.
+    pub fn find(path: Option<&Path>) -> Result<Option<CodeOwners>> {
+        let path = match path {
+            Some(path) => path.to_owned(),
+            None => match LOCATIONS.iter().map(PathBuf::from).find(|p| p.is_file()) {
+                Some(path) => path,
+                None => return Ok(None),
+            },
+        };
+        debug!("Reading owners from {}", path.display());
+        CodeOwners::load(&path).map(Some)
+    }
+
+    /// Return the owners of a path relative to the root directory, with `/` separators.
+    pub fn owners(&self, path: &str) -> Vec<String> {
+        // The last matching rule of each section, in the order the sections first match
+        let mut matched: Vec<(Option<usize>, &Rule)> = vec![];
+        for rule in &self.rules {
+            if !rule
+                .globs
+                .iter()
+                .any(|g| g.matches_with(path, MATCH_OPTIONS))
+            {
+                continue;
+            }
+            match matched.iter_mut().find(|(s, _)| *s == rule.section) {
+                Some(last) => last.1 = rule,
+                None => matched.push((rule.section, rule)),
+            }
+        }
+        let mut owners: Vec<String> = vec![];
+        for owner in matched.into_iter().flat_map(|(_, rule)| &rule.owners) {
+            if !owners.contains(owner) {
+                owners.push(owner.to_owned());
+            }
+        }
+        owners
+    }
+
+    /// Attach the owners of each parsed file. Files outside the root directory are left
+    /// without owners.
+    pub fn assign(&self, successes: &mut [ParseSuccess]) {
+        for success in successes {
+            let relative = success.path.canonicalize().ok().and_then(|path| {
+                path.strip_prefix(&self.root).ok().map(|p| {
+                    p.components()
+                        .map(|c| c.as_os_str().to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join("/")
+                })
+            });
+            if let Some(relative) = relative {
+                success.owners = self.owners(&relative);
+            }
+        }
+    }
+}
+
+/// File parser for `CODEOWNERS` files.
+///
+/// Returns the `rules` of the file in order, each with its `line`, `pattern`, `owners` and
+/// `section`, if any, the `owners` named in the file, each with the `patterns` they own,
+/// and any GitLab `sections`, with their `name`, whether they are `optional`, the number of
+/// `approvals` required and their default `owners`. Patterns in a section which name no
+/// owners are given the section's default owners.
+pub struct CodeOwnersParser {}
+
+impl FileParser for CodeOwnersParser {
+    fn name(&self) -> &'static str {
+        "codeowners"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn can_parse(&self, context: &ParseContext) -> bool {
+        context
+            .path()
+            .file_name()
+            .is_some_and(|n| n == "CODEOWNERS")
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let (codeowners, warnings) = CodeOwners::parse(context.text()?, Path::new(""));
+        warnings.into_iter().for_each(|w| context.warn(w));
+
+        let mut owners: Map<String, Value> = Map::new();
+        for rule in &codeowners.rules {
+            for owner in &rule.owners {
+                if let Value::Array(patterns) =
+                    owners.entry(owner.to_owned()).or_insert_with(|| json!([]))
+                {
+                    patterns.push(json!(rule.pattern));
+                }
+            }
+        }
+        let section_name = |index: Option<usize>| index.map(|i| &codeowners.sections[i].name);
+
+        Ok(ParsedDocument::new(json!({
+            "rules": codeowners
+                .rules
+                .iter()
+                .map(|r| json!({
+                    "line": r.line,
+                    "pattern": r.pattern,
+                    "owners": r.owners,
+                    "section": section_name(r.section),
+                }))
+                .collect::<Vec<_>>(),
+            "owners": owners
+                .into_iter()
+                .map(|(owner, patterns)| json!({"owner": owner, "patterns": patterns}))
+                .collect::<Vec<_>>(),
+            "sections": codeowners
+                .sections
+                .iter()
+                .map(|s| json!({
+                    "name": s.name,
+                    "optional": s.optional,
+                    "approvals": s.approvals,
+                    "owners": s.owners,
+                }))
+                .collect::<Vec<_>>(),
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::parsers::codeowners::CodeOwners;
+    use std::path::Path;
+
+    #[test]
+    fn owners() {
+        let (codeowners, warnings) = CodeOwners::parse(
+            "* @org/platform\n\
+             *.tf @org/infra # Terraform\n\
+             /docs/ @org/docs\n\
+             docs/generated/*\n\
+             apps/*.yaml @org/apps\n\
+             \n\
+             ^[Security][2] @org/security\n\
+             **/secrets/\n",
+            Path::new(""),
+        );
+        assert!(warnings.is_empty());
+        let owners = |path| codeowners.owners(path);
+        assert_eq!(owners("README.md"), vec!["@org/platform"]);
+        assert_eq!(owners("modules/vpc/main.tf"), vec!["@org/infra"]);
+        assert_eq!(owners("docs/guide/intro.md"), vec!["@org/docs"]);
+        assert_eq!(owners("docs/generated/api.md"), Vec::<String>::new());
+        assert_eq!(owners("docs/generated/v1/api.md"), vec!["@org/docs"]);
+        assert_eq!(owners("apps/web.yaml"), vec!["@org/apps"]);
+        assert_eq!(owners("apps/web/values.yaml"), vec!["@org/platform"]);
+        assert_eq!(
+            owners("apps/secrets/db.yaml"),
+            vec!["@org/platform", "@org/security"]
+        );
+    }
+}
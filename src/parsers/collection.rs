@@ -0,0 +1,301 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Parser for Postman collections and Insomnia exports.
+
+use super::{has_extension, Capabilities, FileParser, ParseContext, ParsedDocument};
+use crate::errors::*;
+use serde_json::{json, Map, Value};
+
+/// The prefix of the schema URL of Postman v2 collections.
+const POSTMAN_SCHEMA: &str = "schema.getpostman.com/json/collection/v2";
+
+/// The marker of an Insomnia export.
+const INSOMNIA_EXPORT: &str = "__export_format";
+
+/// Return a string field of a JSON object, or an empty string.
+fn field<'a>(value: &'a Value, name: &str) -> &'a str {
+    value.get(name).and_then(|v| v.as_str()).unwrap_or_default()
+}
+
+/// Return a Postman description, which is either a string or an object with `content`.
+fn description(value: &Value) -> Option<String> {
+    match value.get("description") {
+        Some(Value::String(s)) if !s.is_empty() => Some(s.to_owned()),
+        Some(Value::Object(d)) => d.get("content").and_then(|c| c.as_str()).map(String::from),
+        _ => None,
+    }
+}
+
+/// Return the enabled headers of a request as `name` and `value` pairs, from a list of
+/// objects with the names of the name and value fields.
+fn headers(headers: Option<&Value>, name: &str, value: &str) -> Vec<Value> {
+    headers
+        .and_then(|h| h.as_array())
+        .into_iter()
+        .flatten()
+        .filter(|h| !h.get("disabled").and_then(|d| d.as_bool()).unwrap_or(false))
+        .map(|h| json!({"name": field(h, name), "value": field(h, value)}))
+        .collect()
+}
+
+/// Add a request to the folder with a path, creating the folder if required.
+fn add_request(folders: &mut Vec<Value>, path: &str, request: Value) {
+    let index = match folders.iter().position(|f| f["path"] == path) {
+        Some(index) => index,
+        None => {
+            folders.push(json!({
+                "name": path.rsplit('/').next().unwrap_or_default(),
+                "path": path,
+                "description": null,
+                "requests": [],
+            }));
+            folders.len() - 1
+        }
+    };
+    if let Some(Value::Array(requests)) = folders[index].get_mut("requests") {
+        requests.push(request);
+    }
+}
+
+/// Join a folder path and the name of a subfolder.
+fn join(path: &str, name: &str) -> String {
+    match path {
+        "" => name.to_string(),
+        path => format!("{}/{}", path, name),
+    }
+}
+
+/// File parser for Postman v2 collections and Insomnia v4 exports, as JSON or YAML.
+///
+/// Returns the `format` (`postman` or `insomnia`), `name` and `description` of the
+/// collection, its `variables` and its `folders`, in order, each with its `name`, `path`
+/// (e.g. `Orders/Admin`, or empty for requests outside any folder), `description` and
+/// `requests`. Each request has a `name`, `method`, `url`, enabled `headers`, `body`, with
+/// its `type` and `content`, `description` and example responses as `examples`.
+pub struct CollectionParser {}
+
+impl CollectionParser {
+    /// Add the requests of a list of Postman items, which are requests or folders of
+    /// further items, to the folders.
+    fn postman_items(items: &Value, path: &str, folders: &mut Vec<Value>) {
+        for item in items.as_array().into_iter().flatten() {
+            match (item.get("item"), item.get("request")) {
+                (Some(children), _) => {
+                    let path = join(path, field(item, "name"));
+                    // Folders are listed in order even if they only contain folders
+                    folders.push(json!({
+                        "name": field(item, "name"),
+                        "path": path,
+                        "description": description(item),
+                        "requests": [],
+                    }));
+                    CollectionParser::postman_items(children, &path, folders);
+                }
+                (None, Some(request)) => add_request(
+                    folders,
+                    path,
+                    CollectionParser::postman_request(item, request),
+                ),
+                (None, None) => {}
+            }
+        }
+    }
+
+    /// Return a Postman request in the normalised model.
+    fn postman_request(item: &Value, request: &Value) -> Value {
+        // A request may be given as only its URL
+        let url = match request.get("url").unwrap_or(request) {
+            Value::String(url) => url.to_owned(),
+            url => field(url, "raw").to_string(),
+        };
+        let body = request.get("body").and_then(|body| {
+            let mode = field(body, "mode");
+            let content = match mode {
+                "raw" => body.get("raw").cloned(),
+                "graphql" => body.get("graphql").cloned(),
+                "urlencoded" | "formdata" => {
+                    Some(Value::Array(headers(body.get(mode), "key", "value")))
+                }
+                "file" => body.get("file").and_then(|f| f.get("src")).cloned(),
+                _ => None,
+            }?;
+            let language = body
+                .pointer("/options/raw/language")
+                .and_then(|l| l.as_str());
+            Some(json!({"type": language.unwrap_or(mode), "content": content}))
+        });
+        let examples: Vec<Value> = item
+            .get("response")
+            .and_then(|r| r.as_array())
+            .into_iter()
+            .flatten()
+            .map(|r| {
+                json!({
+                    "name": field(r, "name"),
+                    "status": r.get("status"),
+                    "code": r.get("code"),
+                    "headers": headers(r.get("header"), "key", "value"),
+                    "body": r.get("body"),
+                })
+            })
+            .collect();
+        json!({
+            "name": field(item, "name"),
+            "method": match field(request, "method") {
+                "" => "GET".to_string(),
+                method => method.to_uppercase(),
+            },
+            "url": url,
+            "headers": headers(request.get("header"), "key", "value"),
+            "body": body,
+            "description": description(request).or_else(|| description(item)),
+            "examples": examples,
+        })
+    }
+
+    /// Parse a Postman v2 collection.
+    fn postman(collection: &Value) -> Value {
+        let info = collection.get("info").unwrap_or(&Value::Null);
+        let mut folders = vec![];
+        CollectionParser::postman_items(
+            collection.get("item").unwrap_or(&Value::Null),
+            "",
+            &mut folders,
+        );
+        json!({
+            "format": "postman",
+            "name": field(info, "name"),
+            "description": description(info),
+            "variables": collection
+                .get("variable")
+                .and_then(|v| v.as_array())
+                .into_iter()
+                .flatten()
+                .map(|v| (field(v, "key").to_string(), v.get("value").cloned().unwrap_or(Value::Null)))
+                .collect::<Map<_, _>>(),
+            "folders": folders,
+        })
+    }
+
+    /// Parse an Insomnia export, whose resources refer to their parent by `parentId`.
+    fn insomnia(export: &Value) -> Result<Value> {
+        let resources = export
+            .get("resources")
+            .and_then(|r| r.as_array())
+            .ok_or("Insomnia export has no resources")?;
+        let by_id = |id: &str| resources.iter().find(|r| field(r, "_id") == id);
+        let path = |resource: &Value| {
+            let mut names = vec![];
+            let mut parent = by_id(field(resource, "parentId"));
+            while let Some(group) = parent.filter(|p| field(p, "_type") == "request_group") {
+                names.insert(0, field(group, "name"));
+                parent = by_id(field(group, "parentId"));
+            }
+            names.join("/")
+        };
+        let workspace = resources.iter().find(|r| field(r, "_type") == "workspace");
+
+        let mut folders = vec![];
+        for resource in resources {
+            match field(resource, "_type") {
+                "request_group" => folders.push(json!({
+                    "name": field(resource, "name"),
+                    "path": join(&path(resource), field(resource, "name")),
+                    "description": resource.get("description").filter(|d| d.as_str() != Some("")),
+                    "requests": [],
+                })),
+                "request" => {
+                    let body = resource.get("body").and_then(|body| {
+                        let content = match body.get("text") {
+                            Some(text) => text.to_owned(),
+                            None => Value::Array(headers(body.get("params"), "name", "value")),
+                        };
+                        body.get("mimeType")
+                            .map(|t| json!({"type": t, "content": content}))
+                    });
+                    let request = json!({
+                        "name": field(resource, "name"),
+                        "method": field(resource, "method").to_uppercase(),
+                        "url": field(resource, "url"),
+                        "headers": headers(resource.get("headers"), "name", "value"),
+                        "body": body,
+                        "description": resource.get("description").filter(|d| d.as_str() != Some("")),
+                        "examples": [],
+                    });
+                    add_request(&mut folders, &path(resource), request);
+                }
+                _ => {}
+            }
+        }
+        let variables = resources
+            .iter()
+            .filter(|r| field(r, "_type") == "environment")
+            .filter_map(|r| r.get("data").and_then(|d| d.as_object()))
+            .flat_map(|d| d.iter().map(|(k, v)| (k.to_owned(), v.to_owned())))
+            .collect::<Map<_, _>>();
+        Ok(json!({
+            "format": "insomnia",
+            "name": workspace.map(|w| field(w, "name")).unwrap_or_default(),
+            "description": workspace.and_then(|w| w.get("description")).filter(|d| d.as_str() != Some("")),
+            "variables": variables,
+            "folders": folders,
+        }))
+    }
+}
+
+impl FileParser for CollectionParser {
+    fn name(&self) -> &'static str {
+        "api-collection"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["json", "yaml", "yml"],
+            ..Default::default()
+        }
+    }
+
+    fn can_parse(&self, context: &ParseContext) -> bool {
+        has_extension(context.path(), self.capabilities().extensions)
+            && context
+                .text()
+                .is_ok_and(|c| c.contains(POSTMAN_SCHEMA) || c.contains(INSOMNIA_EXPORT))
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let collection: Value = serde_yaml::from_str(context.text()?)?;
+        let value = if collection.get(INSOMNIA_EXPORT).is_some() {
+            CollectionParser::insomnia(&collection)?
+        } else if collection
+            .pointer("/info/schema")
+            .and_then(|s| s.as_str())
+            .is_some_and(|s| s.contains(POSTMAN_SCHEMA))
+        {
+            CollectionParser::postman(&collection)
+        } else {
+            bail!("Not a Postman v2 collection or Insomnia export")
+        };
+        Ok(ParsedDocument {
+            name: value["name"]
+                .as_str()
+                .filter(|n| !n.is_empty())
+                .map(String::from),
+            value,
+            ..Default::default()
+        })
+    }
+}
@@ -0,0 +1,530 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Extraction of comments from source files as descriptions of the keys they precede.
+//!
+//! These are deliberately lightweight, line-based scanners rather than full parsers:
+//! the file has already been parsed successfully by the time descriptions are read, so
+//! they only need to track enough structure to know the dotted path of each key.
+
+use super::Descriptions;
+use std::collections::HashMap;
+
+/// Comment lines collected since the last key.
+#[derive(Default)]
+struct Pending(Vec<String>);
+
+impl Pending {
+    fn push(&mut self, comment: &str) {
+        self.0.push(comment.trim().to_string());
+    }
+
+    fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    /// Record the pending comments as the description of a key, if there are any.
+    fn describe(&mut self, descriptions: &mut Descriptions, key: String) {
+        if !self.0.is_empty() {
+            descriptions.insert(key, self.0.join("\n"));
+        }
+        self.clear();
+    }
+}
+
+/// Join path segments into a dotted key.
+fn dotted<S: AsRef<str>>(segments: &[S]) -> String {
+    segments
+        .iter()
+        .map(|s| s.as_ref())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Remove surrounding quotes from a key.
+fn unquote(key: &str) -> &str {
+    let key = key.trim();
+    if key.len() >= 2
+        && ((key.starts_with('"') && key.ends_with('"'))
+            || (key.starts_with('\'') && key.ends_with('\'')))
+    {
+        &key[1..key.len() - 1]
+    } else {
+        key
+    }
+}
+
+/// Return the position of the first occurrence of a separator outside of quotes.
+fn find_unquoted(line: &str, separator: impl Fn(&str) -> bool) -> Option<usize> {
+    let mut quote: Option<char> = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => (),
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '#') => return None,
+            (None, _) if separator(&line[i..]) => return Some(i),
+            _ => (),
+        }
+    }
+    None
+}
+
+/// A key or sequence item within the YAML structure being scanned.
+struct Frame {
+    indent: usize,
+    segment: String,
+    item: bool,
+}
+
+/// Extract descriptions from comments preceding keys and sequence items in YAML.
+///
+/// Sequence items are addressed by their index, e.g. `containers.0.image`.
+pub fn yaml(contents: &str) -> Descriptions {
+    let mut descriptions = Descriptions::new();
+    let mut pending = Pending::default();
+    let mut stack: Vec<Frame> = vec![];
+    let mut indexes: HashMap<String, usize> = HashMap::new();
+    let mut block_indent: Option<usize> = None;
+
+    let path = |stack: &[Frame]| dotted(&stack.iter().map(|f| &f.segment).collect::<Vec<_>>());
+
+    for line in contents.lines() {
+        let trimmed = line.trim_start();
+        let mut indent = line.len() - trimmed.len();
+
+        // Skip the contents of block scalars.
+        match block_indent {
+            Some(i) if trimmed.is_empty() || indent > i => continue,
+            _ => block_indent = None,
+        }
+
+        if trimmed.is_empty() {
+            pending.clear();
+            continue;
+        }
+        if let Some(comment) = trimmed.strip_prefix('#') {
+            pending.push(comment);
+            continue;
+        }
+        if trimmed.starts_with("---") || trimmed.starts_with("...") {
+            stack.clear();
+            indexes.clear();
+            pending.clear();
+            continue;
+        }
+
+        let mut rest = trimmed;
+        let mut item = false;
+        while let Some(r) = rest
+            .strip_prefix("- ")
+            .or(if rest == "-" { Some("") } else { None })
+        {
+            while stack
+                .last()
+                .is_some_and(|f| f.indent > indent || (f.indent == indent && f.item))
+            {
+                stack.pop();
+            }
+            let index = indexes
+                .entry(path(&stack))
+                .and_modify(|i| *i += 1)
+                .or_insert(0);
+            stack.push(Frame {
+                indent,
+                segment: index.to_string(),
+                item: true,
+            });
+            let next = r.trim_start();
+            indent += rest.len() - next.len();
+            rest = next;
+            item = true;
+        }
+
+        let separator = find_unquoted(rest, |s| {
+            s.starts_with(": ") || (s.starts_with(':') && s.len() == 1)
+        });
+        match separator {
+            Some(pos) if !rest.starts_with(['{', '[', '|', '>', '&', '*', '!']) => {
+                while stack.last().is_some_and(|f| f.indent >= indent) {
+                    stack.pop();
+                }
+                let key = unquote(&rest[..pos]).to_string();
+                let mut segments = stack.iter().map(|f| f.segment.as_str()).collect::<Vec<_>>();
+                segments.push(&key);
+                pending.describe(&mut descriptions, dotted(&segments));
+
+                let value = rest[pos + 1..].trim_start();
+                if value.starts_with(['|', '>']) {
+                    block_indent = Some(indent);
+                }
+                stack.push(Frame {
+                    indent,
+                    segment: key,
+                    item: false,
+                });
+            }
+            _ if item => pending.describe(&mut descriptions, path(&stack)),
+            _ => pending.clear(),
+        }
+    }
+
+    descriptions
+}
+
+/// Extract descriptions from comments preceding tables and keys in TOML.
+///
+/// Arrays of tables are addressed by their index, e.g. `servers.0.host`.
+pub fn toml(contents: &str) -> Descriptions {
+    let mut descriptions = Descriptions::new();
+    let mut pending = Pending::default();
+    let mut table: Vec<String> = vec![];
+    let mut indexes: HashMap<String, usize> = HashMap::new();
+    let mut multiline = false;
+
+    let split_key = |key: &str| {
+        let mut segments = vec![];
+        let mut rest = key.trim();
+        while !rest.is_empty() {
+            let end = find_unquoted(rest, |s| s.starts_with('.')).unwrap_or(rest.len());
+            segments.push(unquote(&rest[..end]).to_string());
+            rest = rest[end..].trim_start_matches('.').trim();
+        }
+        segments
+    };
+
+    for line in contents.lines() {
+        let trimmed = line.trim();
+
+        // Skip the contents of multi-line strings.
+        if multiline {
+            multiline = !(trimmed.contains("\"\"\"") || trimmed.contains("'''"));
+            continue;
+        }
+
+        if trimmed.is_empty() {
+            pending.clear();
+        } else if let Some(comment) = trimmed.strip_prefix('#') {
+            pending.push(comment);
+        } else if let Some(header) = trimmed
+            .strip_prefix("[[")
+            .and_then(|h| h.split("]]").next())
+        {
+            let segments = split_key(header);
+            let index = indexes
+                .entry(dotted(&segments))
+                .and_modify(|i| *i += 1)
+                .or_insert(0);
+            table = segments;
+            table.push(index.to_string());
+            pending.describe(&mut descriptions, dotted(&table));
+        } else if let Some(header) = trimmed.strip_prefix('[').and_then(|h| h.split(']').next()) {
+            table = split_key(header);
+            pending.describe(&mut descriptions, dotted(&table));
+        } else if let Some(pos) = find_unquoted(trimmed, |s| s.starts_with('=')) {
+            let mut segments = table.clone();
+            segments.extend(split_key(&trimmed[..pos]));
+            pending.describe(&mut descriptions, dotted(&segments));
+
+            let value = &trimmed[pos + 1..];
+            multiline = ["\"\"\"", "'''"]
+                .iter()
+                .any(|q| value.matches(q).count() == 1);
+        } else {
+            pending.clear();
+        }
+    }
+
+    descriptions
+}
+
+/// Extract descriptions from comments preceding sections and keys in INI files.
+pub fn ini(contents: &str) -> Descriptions {
+    let mut descriptions = Descriptions::new();
+    let mut pending = Pending::default();
+    let mut section = String::new();
+
+    for line in contents.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            pending.clear();
+        } else if let Some(comment) = trimmed.strip_prefix(';').or(trimmed.strip_prefix('#')) {
+            pending.push(comment);
+        } else if let Some(header) = trimmed.strip_prefix('[').and_then(|h| h.split(']').next()) {
+            section = header.trim().to_string();
+            pending.describe(&mut descriptions, section.to_owned());
+        } else if let Some(pos) = trimmed.find(['=', ':']) {
+            let key = trimmed[..pos].trim();
+            pending.describe(&mut descriptions, dotted(&[section.as_str(), key]));
+        } else {
+            pending.clear();
+        }
+    }
+
+    descriptions
+}
+
+/// Words which begin a table constraint, rather than a column definition.
+const SQL_CONSTRAINTS: [&str; 8] = [
+    "constraint",
+    "primary",
+    "foreign",
+    "unique",
+    "check",
+    "key",
+    "index",
+    "exclude",
+];
+
+/// Return the name of a table or view created by a SQL statement, if any.
+fn sql_created_name(statement: &str) -> Option<String> {
+    let words = statement.split_whitespace().collect::<Vec<_>>();
+    let lower = words.iter().map(|w| w.to_lowercase()).collect::<Vec<_>>();
+    if lower.first().map(|w| w.as_str()) != Some("create") {
+        return None;
+    }
+    let object = lower.iter().position(|w| w == "table" || w == "view")?;
+    let mut name_index = object + 1;
+    if lower.get(name_index).map(|w| w.as_str()) == Some("if") {
+        name_index += 3; // IF NOT EXISTS
+    }
+    words.get(name_index).map(|n| {
+        n.split('(')
+            .next()
+            .unwrap_or_default()
+            .trim_matches(|c| matches!(c, '`' | '"' | '[' | ']'))
+            .to_string()
+    })
+}
+
+/// Split a line of SQL into its code and any trailing `--` comment, returning the change in
+/// the depth of parentheses. Parentheses and comment markers within string literals and
+/// quoted identifiers are ignored, and `quote` tracks literals which continue onto the
+/// next line.
+fn sql_code<'a>(line: &'a str, quote: &mut Option<char>) -> (&'a str, Option<&'a str>, i32) {
+    let mut depth = 0;
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match (*quote, c) {
+            (Some(q), c) if c == q => *quote = None,
+            (Some(_), _) => (),
+            (None, '\'' | '"' | '`') => *quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, '-') if chars.peek().is_some_and(|(_, n)| *n == '-') => {
+                return (line[..i].trim(), Some(&line[i + 2..]), depth);
+            }
+            _ => (),
+        }
+    }
+    (line.trim(), None, depth)
+}
+
+/// Extract descriptions from comments preceding (or trailing) tables, views and column
+/// definitions in SQL files.
+///
+/// Columns are addressed as `table.column`.
+pub fn sql(contents: &str) -> Descriptions {
+    let mut descriptions = Descriptions::new();
+    let mut pending = Pending::default();
+    let mut table: Option<String> = None;
+    let mut depth: i32 = 0;
+    let mut quote = None;
+
+    for line in contents.lines() {
+        let trimmed = line.trim();
+        if quote.is_some() {
+            depth = (depth + sql_code(trimmed, &mut quote).2).max(0);
+            continue;
+        }
+        if trimmed.is_empty() {
+            continue;
+        }
+        if let Some(comment) = trimmed.strip_prefix("--") {
+            pending.push(comment);
+            continue;
+        }
+        if let Some(comment) = trimmed
+            .strip_prefix("/*")
+            .and_then(|c| c.strip_suffix("*/"))
+        {
+            pending.push(comment.trim_start_matches('*'));
+            continue;
+        }
+
+        let (code, trailing, change) = sql_code(trimmed, &mut quote);
+        if pending.0.is_empty() {
+            if let Some(comment) = trailing {
+                pending.push(comment);
+            }
+        }
+
+        match (&table, depth) {
+            (Some(t), 1) => {
+                let column = code
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or_default()
+                    .trim_matches(|c| matches!(c, '`' | '"' | '[' | ']' | ','));
+                if !column.is_empty()
+                    && !column.starts_with(')')
+                    && !SQL_CONSTRAINTS.contains(&column.to_lowercase().as_str())
+                {
+                    pending.describe(&mut descriptions, format!("{}.{}", t, column));
+                }
+            }
+            (None, 0) => {
+                if let Some(name) = sql_created_name(code) {
+                    pending.describe(&mut descriptions, name.to_owned());
+                    table = Some(name);
+                }
+            }
+            _ => (),
+        }
+        pending.clear();
+
+        depth = (depth + change).max(0);
+        if code.ends_with(';') && quote.is_none() {
+            table = None;
+            depth = 0;
+        }
+    }
+
+    descriptions
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn yaml() {
+        let descriptions = super::yaml(
+            r#"
+# The application name
+name: app
+spec:
+  # Containers to run
+  containers:
+    # The main container
+    - name: web
+      # Image to run
+      image: nginx
+    - name: sidecar
+      script: |
+        # not a comment
+        run: this
+      # Sidecar image
+      image: envoy
+"#,
+        );
+
+        assert_eq!(descriptions.get("name").unwrap(), "The application name");
+        assert_eq!(
+            descriptions.get("spec.containers").unwrap(),
+            "Containers to run"
+        );
+        assert_eq!(
+            descriptions.get("spec.containers.0.name").unwrap(),
+            "The main container"
+        );
+        assert_eq!(
+            descriptions.get("spec.containers.0.image").unwrap(),
+            "Image to run"
+        );
+        assert_eq!(
+            descriptions.get("spec.containers.1.image").unwrap(),
+            "Sidecar image"
+        );
+        assert_eq!(descriptions.len(), 5);
+    }
+
+    #[test]
+    fn toml() {
+        let descriptions = super::toml(
+            r#"
+# Package metadata
+[package]
+# The package name
+name = "kvasir"
+
+# A server
+[[servers]]
+host = "a"
+
+[[servers]]
+# The second host
+host = "b"
+"#,
+        );
+
+        assert_eq!(descriptions.get("package").unwrap(), "Package metadata");
+        assert_eq!(
+            descriptions.get("package.name").unwrap(),
+            "The package name"
+        );
+        assert_eq!(descriptions.get("servers.0").unwrap(), "A server");
+        assert_eq!(
+            descriptions.get("servers.1.host").unwrap(),
+            "The second host"
+        );
+    }
+
+    #[test]
+    fn ini() {
+        let descriptions = super::ini(&std::fs::read_to_string("test/resources/test.ini").unwrap());
+        assert_eq!(
+            descriptions.get("database.server").unwrap(),
+            "use IP address in case network name resolution is not working"
+        );
+    }
+
+    #[test]
+    fn sql() {
+        let descriptions = super::sql(
+            r#"
+-- Registered users
+CREATE TABLE IF NOT EXISTS users (
+    -- Unique identifier
+    id INT PRIMARY KEY,
+    email VARCHAR(255), -- Login email address
+    -- Account status
+    status VARCHAR(10) DEFAULT 'a)--b' CHECK (status <> ')'),
+    PRIMARY KEY (id)
+);
+)
+-- Orders placed
+CREATE TABLE orders (
+    -- Ordering user
+    user_id INT,
+    note TEXT DEFAULT 'multi-line
+  (and -- not a comment
+'
+);
+"#,
+        );
+
+        assert_eq!(descriptions.get("users").unwrap(), "Registered users");
+        assert_eq!(descriptions.get("users.id").unwrap(), "Unique identifier");
+        assert_eq!(
+            descriptions.get("users.email").unwrap(),
+            "Login email address"
+        );
+        assert_eq!(descriptions.get("users.status").unwrap(), "Account status");
+        assert_eq!(descriptions.get("orders").unwrap(), "Orders placed");
+        assert_eq!(descriptions.get("orders.user_id").unwrap(), "Ordering user");
+        assert_eq!(descriptions.len(), 6);
+    }
+}
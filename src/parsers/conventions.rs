@@ -0,0 +1,511 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Parsers for files recording the conventions of a repository: `.editorconfig`,
+//! `.gitattributes` and Git configuration such as `.gitconfig` and `.gitmodules`.
+//!
+//! These files have no extension, so they are recognised by their file names.
+
+use super::kubernetes::REDACTED;
+use super::{Capabilities, FileParser, ParseContext, ParsedDocument};
+use crate::errors::*;
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Return the file name of a path.
+fn file_name(path: &Path) -> &str {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default()
+}
+
+/// Return the lines of a file with their line numbers, along with the comments
+/// immediately preceding each, skipping blank lines and comments beginning with one of the
+/// comment characters.
+fn lines<'a>(text: &'a str, comment: &[char]) -> Vec<(usize, &'a str, Option<String>)> {
+    let mut lines = vec![];
+    let mut comments: Vec<&str> = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            comments.clear();
+        } else if trimmed.starts_with(comment) {
+            comments.push(trimmed.trim_start_matches(comment).trim());
+        } else {
+            let description = (!comments.is_empty()).then(|| comments.join("\n"));
+            lines.push((number + 1, trimmed, description));
+            comments.clear();
+        }
+    }
+    lines
+}
+
+/// File parser for `.editorconfig` files.
+///
+/// Returns whether the file is the `root` EditorConfig file and its `sections` in order,
+/// each with its `glob`, the `description` from the comments preceding it, if any, and its
+/// `properties`. The properties are also returned in `globs`, an object keyed by glob, which
+/// combines sections with the same glob as EditorConfig does, later properties taking
+/// precedence, so that the conventions of many repositories can be compared by glob.
+/// Property names are lower-cased, as are the values of the properties defined by the
+/// EditorConfig specification, and `true`, `false` and integer values are converted to JSON
+/// types.
+pub struct EditorConfigParser {}
+
+impl EditorConfigParser {
+    /// Convert a property value to JSON.
+    fn value(key: &str, raw: &str) -> Value {
+        let standard = [
+            "indent_style",
+            "indent_size",
+            "tab_width",
+            "end_of_line",
+            "charset",
+            "trim_trailing_whitespace",
+            "insert_final_newline",
+            "max_line_length",
+        ];
+        let raw = match standard.contains(&key) {
+            true => raw.to_lowercase(),
+            false => raw.to_string(),
+        };
+        match raw.as_str() {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ => raw
+                .parse::<u64>()
+                .map(|n| json!(n))
+                .unwrap_or(Value::String(raw)),
+        }
+    }
+}
+
+impl FileParser for EditorConfigParser {
+    fn name(&self) -> &'static str {
+        "editorconfig"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn can_parse(&self, context: &ParseContext) -> bool {
+        file_name(context.path()) == ".editorconfig"
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let mut root = false;
+        let mut sections: Vec<Value> = vec![];
+        for (number, line, description) in lines(context.text()?, &['#', ';']) {
+            if let Some(glob) = line.strip_prefix('[') {
+                match glob.strip_suffix(']') {
+                    Some(glob) => sections.push(json!({
+                        "glob": glob,
+                        "description": description,
+                        "properties": {},
+                    })),
+                    None => bail!(format!("Unterminated section header on line {}", number)),
+                }
+            } else if let Some((key, value)) = line.split_once(['=', ':']) {
+                let key = key.trim().to_lowercase();
+                match sections.last_mut() {
+                    Some(section) => {
+                        section["properties"][&key] = EditorConfigParser::value(&key, value.trim())
+                    }
+                    None if key == "root" => root = value.trim().eq_ignore_ascii_case("true"),
+                    None => context.warn(format!(
+                        "Property {} on line {} is outside any section",
+                        key, number
+                    )),
+                }
+            } else {
+                bail!(format!("Expected a key and value on line {}", number))
+            }
+        }
+        let mut globs = Map::new();
+        for section in &sections {
+            let glob = section["glob"].as_str().unwrap_or_default();
+            let properties = globs.entry(glob).or_insert_with(|| json!({}));
+            for (key, value) in section["properties"].as_object().into_iter().flatten() {
+                properties[key] = value.to_owned();
+            }
+        }
+        Ok(ParsedDocument::new(json!({
+            "root": root,
+            "sections": sections,
+            "globs": globs,
+        })))
+    }
+}
+
+/// File parser for `.gitattributes` files.
+///
+/// Returns the `patterns` in order, each with its `pattern`, the `description` from the
+/// comments preceding it, if any, and its `attributes`: `true` if set, `false` if unset
+/// with `-`, `null` if unspecified with `!` and otherwise the value given with `=`. Also
+/// returns the attribute `macros` defined with `[attr]`, the patterns tracked by Git LFS as
+/// `lfs` and the patterns treated as `binary`, including the built-in `binary` macro.
+pub struct GitAttributesParser {}
+
+impl GitAttributesParser {
+    /// Parse a list of attributes.
+    fn attributes<'a>(attributes: impl Iterator<Item = &'a str>) -> Map<String, Value> {
+        attributes
+            .map(|a| match a.split_once('=') {
+                Some((name, value)) => (name.to_string(), json!(value)),
+                None => match (a.strip_prefix('-'), a.strip_prefix('!')) {
+                    (Some(name), _) => (name.to_string(), Value::Bool(false)),
+                    (_, Some(name)) => (name.to_string(), Value::Null),
+                    _ => (a.to_string(), Value::Bool(true)),
+                },
+            })
+            .collect()
+    }
+
+    /// Split a line into its pattern, which may be quoted, and its attributes.
+    fn split(line: &str) -> (String, &str) {
+        if let Some(quoted) = line.strip_prefix('"') {
+            let mut pattern = String::new();
+            let mut chars = quoted.char_indices();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '\\' => pattern.extend(chars.next().map(|(_, c)| c)),
+                    '"' => return (pattern, &quoted[i + 1..]),
+                    c => pattern.push(c),
+                }
+            }
+            (pattern, "")
+        } else {
+            let (pattern, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            (pattern.to_string(), rest)
+        }
+    }
+}
+
+impl FileParser for GitAttributesParser {
+    fn name(&self) -> &'static str {
+        "gitattributes"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn can_parse(&self, context: &ParseContext) -> bool {
+        file_name(context.path()) == ".gitattributes"
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let mut patterns = vec![];
+        let mut macros = Map::new();
+        let mut lfs = vec![];
+        let mut binary = vec![];
+        for (_, line, description) in lines(context.text()?, &['#']) {
+            let (pattern, rest) = GitAttributesParser::split(line);
+            let attributes = GitAttributesParser::attributes(rest.split_whitespace());
+            if let Some(name) = pattern.strip_prefix("[attr]") {
+                macros.insert(name.to_string(), Value::Object(attributes));
+                continue;
+            }
+            if attributes.get("filter").and_then(|f| f.as_str()) == Some("lfs") {
+                lfs.push(pattern.to_owned());
+            }
+            if attributes.get("binary") == Some(&Value::Bool(true))
+                || attributes.get("text") == Some(&Value::Bool(false))
+            {
+                binary.push(pattern.to_owned());
+            }
+            patterns.push(json!({
+                "pattern": pattern,
+                "description": description,
+                "attributes": attributes,
+            }));
+        }
+        Ok(ParsedDocument::new(json!({
+            "patterns": patterns,
+            "macros": macros,
+            "lfs": lfs,
+            "binary": binary,
+        })))
+    }
+}
+
+/// The maximum depth of nested includes in Git configuration files, as in Git.
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+/// File parser for Git configuration files: `.gitconfig`, a repository's `.git/config`,
+/// `.gitmodules`, `.lfsconfig` and files such as `work.gitconfig` or `.gitconfig-work`
+/// included by them.
+///
+/// Returns the `sections` as objects keyed by lower-cased section and key names, with
+/// subsections such as `[remote "origin"]` nested within their section. Keys without a
+/// value are `true` and repeated keys, such as a remote's `fetch` refspecs, become arrays.
+/// Values of keys which may hold credentials, such as `http.extraheader`, are redacted.
+/// Files included with `include.path` are read, relative to the including file, and their
+/// keys merged where the include appears. Conditional `includeIf` files are not read, as
+/// their conditions depend on the repository in which Git runs. Also returns the
+/// `includes`, with their `path`, `condition` and whether they were `included`, and the
+/// `submodules` with their `name`, `path`, `url` and `branch`.
+pub struct GitConfigParser {}
+
+/// The sections and includes read from a Git configuration file and the files it includes.
+#[derive(Default)]
+struct GitConfig {
+    sections: Map<String, Value>,
+    includes: Vec<Value>,
+}
+
+impl GitConfigParser {
+    /// Return whether a key may hold credentials, whose value is redacted.
+    fn is_secret(key: &str) -> bool {
+        ["password", "token", "extraheader", "secret"]
+            .iter()
+            .any(|s| key.ends_with(s))
+    }
+
+    /// Parse a section header, without its brackets, returning the section and subsection.
+    fn header(header: &str) -> (String, Option<String>) {
+        match header.split_once(char::is_whitespace) {
+            Some((section, subsection)) => {
+                let subsection = subsection.trim();
+                let subsection = subsection
+                    .strip_prefix('"')
+                    .and_then(|s| s.strip_suffix('"'))
+                    .unwrap_or(subsection)
+                    .replace("\\\"", "\"")
+                    .replace("\\\\", "\\");
+                (section.to_lowercase(), Some(subsection))
+            }
+            // The deprecated `[section.subsection]` syntax
+            None => match header.split_once('.') {
+                Some((section, subsection)) => {
+                    (section.to_lowercase(), Some(subsection.to_lowercase()))
+                }
+                None => (header.to_lowercase(), None),
+            },
+        }
+    }
+
+    /// Parse a value, removing quotes and comments and interpreting escape sequences.
+    fn value(raw: &str) -> String {
+        let mut value = String::new();
+        let mut quoted = false;
+        let mut chars = raw.trim().chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => quoted = !quoted,
+                '#' | ';' if !quoted => break,
+                '\\' => match chars.next() {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some('b') => {
+                        value.pop();
+                    }
+                    Some(c) => value.push(c),
+                    None => {}
+                },
+                c => value.push(c),
+            }
+        }
+        value.trim_end().to_string()
+    }
+
+    /// Insert a value into a section, combining repeated keys into an array.
+    fn insert(section: &mut Map<String, Value>, key: String, value: Value) {
+        match section.get_mut(&key) {
+            Some(Value::Array(values)) => values.push(value),
+            Some(existing) => {
+                let first = existing.take();
+                *existing = Value::Array(vec![first, value]);
+            }
+            None => {
+                section.insert(key, value);
+            }
+        }
+    }
+
+    /// Return the path of a file included by a file in a directory, expanding a leading
+    /// `~` to the home directory.
+    fn include_path(dir: &Path, path: &str) -> Option<PathBuf> {
+        match path.strip_prefix("~/") {
+            Some(rest) => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(rest)),
+            None => Some(dir.join(path)),
+        }
+    }
+
+    /// Read the text of a configuration file into the configuration, reading the files it
+    /// includes at the given depth of nesting. Errors in included files name the file.
+    fn read(
+        context: &ParseContext,
+        path: &Path,
+        text: &str,
+        depth: usize,
+        config: &mut GitConfig,
+    ) -> Result<()> {
+        let at = |number: usize| match depth {
+            0 => format!("line {}", number),
+            _ => format!("line {} of {}", number, path.display()),
+        };
+        // Join continuation lines ending with a backslash
+        let text = text.replace("\\\r\n", "").replace("\\\n", "");
+
+        let mut current: Option<(String, Option<String>)> = None;
+        for (number, line, _) in lines(&text, &['#', ';']) {
+            let mut line = line;
+            if let Some(header) = line.strip_prefix('[') {
+                let (header, rest) = header
+                    .split_once(']')
+                    .ok_or_else(|| format!("Unterminated section header on {}", at(number)))?;
+                current = Some(GitConfigParser::header(header.trim()));
+                // A key may follow the header on the same line
+                line = rest.trim();
+                if line.is_empty() || line.starts_with(['#', ';']) {
+                    continue;
+                }
+            }
+            let Some((section, subsection)) = &current else {
+                bail!(format!("Key on {} is outside any section", at(number)))
+            };
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (
+                    key.trim().to_lowercase(),
+                    Value::String(GitConfigParser::value(value)),
+                ),
+                None => (
+                    GitConfigParser::value(line).to_lowercase(),
+                    Value::Bool(true),
+                ),
+            };
+            let mut included = None;
+            if (section == "include" || section == "includeif") && key == "path" {
+                let include = value.as_str().unwrap_or_default();
+                if section == "include" {
+                    let dir = path.parent().unwrap_or(Path::new(""));
+                    included = GitConfigParser::include_path(dir, include);
+                }
+                config.includes.push(json!({
+                    "path": include,
+                    "condition": subsection,
+                    "included": included.is_some(),
+                }));
+            }
+            let value = match GitConfigParser::is_secret(&key) {
+                true => Value::String(REDACTED.to_string()),
+                false => value,
+            };
+
+            let mut target = match config
+                .sections
+                .entry(section.to_owned())
+                .or_insert_with(|| json!({}))
+            {
+                Value::Object(map) => map,
+                _ => bail!(format!("Section {} conflicts with a key", section)),
+            };
+            if let Some(subsection) = subsection {
+                target = match target
+                    .entry(subsection.to_owned())
+                    .or_insert_with(|| json!({}))
+                {
+                    Value::Object(map) => map,
+                    _ => bail!(format!(
+                        "Subsection {}.{} conflicts with a key",
+                        section, subsection
+                    )),
+                };
+            }
+            GitConfigParser::insert(target, key, value);
+
+            // Keys of an included file are read where it is included, so that later keys
+            // take precedence as in Git
+            if let Some(included) = included {
+                if depth >= MAX_INCLUDE_DEPTH {
+                    bail!(format!(
+                        "Include on {} exceeds the maximum depth of {}, which may be a cycle",
+                        at(number),
+                        MAX_INCLUDE_DEPTH
+                    ))
+                }
+                match fs::read_to_string(&included) {
+                    Ok(text) => {
+                        GitConfigParser::read(context, &included, &text, depth + 1, config)?
+                    }
+                    // Git ignores included files which do not exist
+                    Err(e) => {
+                        context.warn(format!("Could not read {}: {}", included.display(), e));
+                        if let Some(include) = config.includes.last_mut() {
+                            include["included"] = Value::Bool(false);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FileParser for GitConfigParser {
+    fn name(&self) -> &'static str {
+        "gitconfig"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn can_parse(&self, context: &ParseContext) -> bool {
+        let path = context.path();
+        let name = file_name(path);
+        [".gitconfig", "gitconfig", ".gitmodules", ".lfsconfig"].contains(&name)
+            || name.starts_with(".gitconfig-")
+            || name.ends_with(".gitconfig")
+            // A repository's `.git/config`, or the user's `~/.config/git/config`
+            || (name == "config"
+                && path.parent().is_some_and(|p| {
+                    file_name(p) == ".git"
+                        || (file_name(p) == "git"
+                            && p.parent().is_some_and(|p| file_name(p) == ".config"))
+                }))
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let mut config = GitConfig::default();
+        GitConfigParser::read(context, context.path(), context.text()?, 0, &mut config)?;
+        let GitConfig { sections, includes } = config;
+
+        let submodules: Vec<Value> = sections
+            .get("submodule")
+            .and_then(|s| s.as_object())
+            .into_iter()
+            .flatten()
+            .filter(|(_, s)| s.is_object())
+            .map(|(name, s)| {
+                json!({
+                    "name": name,
+                    "path": s.get("path"),
+                    "url": s.get("url"),
+                    "branch": s.get("branch"),
+                })
+            })
+            .collect();
+        Ok(ParsedDocument::new(json!({
+            "sections": sections,
+            "includes": includes,
+            "submodules": submodules,
+        })))
+    }
+}
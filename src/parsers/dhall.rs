@@ -0,0 +1,86 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Parser for Dhall files, which are normalized into the JSON value they produce.
+
+use super::{Capabilities, FileParser, ParseContext, ParsedDocument};
+use crate::errors::*;
+use serde_dhall::{NumKind, SimpleValue};
+use serde_json::{Map, Number, Value};
+
+/// File parser for Dhall files, `.dhall`.
+///
+/// Each file is type checked and normalized, with local and environment variable imports
+/// resolved relative to the file, and returns the JSON value of the result. Optional values
+/// are returned as the value or `null`, and union alternatives as their name, or an object
+/// of their name and value if they have one, e.g. `{"GRPC": 50051}`. Files which normalize
+/// to a function or type cannot be represented as JSON and fail to parse, as do remote
+/// imports.
+pub struct DhallParser {}
+
+impl DhallParser {
+    /// Convert a normalized Dhall value into JSON.
+    fn to_json(value: SimpleValue) -> Value {
+        match value {
+            SimpleValue::Num(NumKind::Bool(b)) => Value::Bool(b),
+            SimpleValue::Num(NumKind::Natural(n)) => Value::Number(n.into()),
+            SimpleValue::Num(NumKind::Integer(n)) => Value::Number(n.into()),
+            SimpleValue::Num(NumKind::Double(n)) => {
+                Number::from_f64(n.into()).map_or(Value::Null, Value::Number)
+            }
+            SimpleValue::Text(s) => Value::String(s),
+            SimpleValue::Optional(value) => value.map_or(Value::Null, |v| DhallParser::to_json(*v)),
+            SimpleValue::List(values) => {
+                Value::Array(values.into_iter().map(DhallParser::to_json).collect())
+            }
+            SimpleValue::Record(fields) => Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(k, v)| (k, DhallParser::to_json(v)))
+                    .collect(),
+            ),
+            SimpleValue::Union(name, None) => Value::String(name),
+            SimpleValue::Union(name, Some(value)) => {
+                Value::Object(Map::from_iter([(name, DhallParser::to_json(*value))]))
+            }
+        }
+    }
+}
+
+impl FileParser for DhallParser {
+    fn name(&self) -> &'static str {
+        "dhall"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["dhall"],
+            ..Default::default()
+        }
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        // Imports are resolved relative to the path of the file being parsed
+        match serde_dhall::from_file(context.path()).parse::<SimpleValue>() {
+            Ok(value) => Ok(ParsedDocument::new(DhallParser::to_json(value))),
+            Err(e) => bail!(e
+                .to_string()
+                .replace("<current file>", &context.path().display().to_string())
+                .trim()
+                .to_string()),
+        }
+    }
+}
@@ -0,0 +1,552 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Parsers for directories whose files together form a single unit, such as a Helm chart.
+
+use super::{ParseContext, ParsedDocument, ParserOptions};
+use crate::errors::*;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// Interface to parsers of directories, rather than single files.
+///
+/// A directory is parsed when one of its marker files is found by the source globs, and
+/// produces a single document for the whole directory. The context of a directory parser
+/// has the path of the directory, so its contents are not available through
+/// [`ParseContext::text`].
+pub trait DirectoryParser: Send + Sync {
+    /// Return the name of the parser.
+    fn name(&self) -> &'static str;
+
+    /// Return the names of the files which mark a directory as parseable by this parser.
+    fn markers(&self) -> &'static [&'static str];
+
+    /// Return whether a directory containing a marker file is able to be parsed by this
+    /// parser, by default always.
+    fn can_parse(&self, _context: &ParseContext) -> bool {
+        true
+    }
+
+    /// Parse a directory and return its aggregate document or an explanatory error.
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument>;
+}
+
+/// Return a list of available directory parser instances, configured with the provided
+/// options.
+pub fn directory_parsers(_options: &ParserOptions) -> Vec<Box<dyn DirectoryParser>> {
+    vec![
+        Box::new(HelmChartParser {}),
+        Box::new(DbtProjectParser {}),
+        Box::new(TerraformModuleParser {}),
+        Box::new(MavenProjectParser {}),
+        Box::new(super::SpringBootParser {}),
+        Box::new(super::LicenseScanner {}),
+    ]
+}
+
+/// Read and parse a YAML file within a directory.
+fn read_yaml(path: &Path) -> Result<Value> {
+    Ok(serde_yaml::from_str(&fs::read_to_string(path)?)?)
+}
+
+/// Return the paths, relative to `dir` and with `/` separators, of the files beneath `dir`
+/// with one of the specified extensions, in sorted order.
+fn files_with_extensions(dir: &Path, extensions: &[&str]) -> Vec<String> {
+    let mut files: Vec<String> = WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && super::has_extension(e.path(), extensions))
+        .filter_map(|e| {
+            e.path().strip_prefix(dir).ok().map(|p| {
+                p.components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// Directory parser for Helm charts.
+///
+/// Returns the chart definition from `Chart.yaml`, the default `values` from
+/// `values.yaml` and the names of the chart's `templates`.
+pub struct HelmChartParser {}
+impl DirectoryParser for HelmChartParser {
+    fn name(&self) -> &'static str {
+        "helm-chart"
+    }
+
+    fn markers(&self) -> &'static [&'static str] {
+        &["Chart.yaml"]
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let dir = context.path();
+        let chart = read_yaml(&dir.join("Chart.yaml"))?;
+        let values = match dir.join("values.yaml") {
+            path if path.is_file() => read_yaml(&path)?,
+            _ => {
+                context.warn("Chart has no values.yaml");
+                Value::Object(Map::new())
+            }
+        };
+        let templates =
+            files_with_extensions(&dir.join("templates"), &["yaml", "yml", "tpl", "txt"]);
+        Ok(ParsedDocument {
+            name: chart.get("name").and_then(|n| n.as_str()).map(String::from),
+            value: json!({
+                "chart": chart,
+                "values": values,
+                "templates": templates,
+            }),
+            ..Default::default()
+        })
+    }
+}
+
+/// Directory parser for dbt projects.
+///
+/// Returns the project definition from `dbt_project.yml` and the `models` within its model
+/// paths, each with a `name`, `path` and, where a properties file provides one, a
+/// `description`.
+pub struct DbtProjectParser {}
+impl DirectoryParser for DbtProjectParser {
+    fn name(&self) -> &'static str {
+        "dbt-project"
+    }
+
+    fn markers(&self) -> &'static [&'static str] {
+        &["dbt_project.yml"]
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let dir = context.path();
+        let project = read_yaml(&dir.join("dbt_project.yml"))?;
+        let model_paths: Vec<String> = match project.get("model-paths") {
+            Some(Value::Array(paths)) => paths
+                .iter()
+                .filter_map(|p| p.as_str().map(String::from))
+                .collect(),
+            _ => vec!["models".to_string()],
+        };
+
+        // Descriptions of models, from the properties files within the model paths
+        let mut descriptions = Map::new();
+        for path in &model_paths {
+            for file in files_with_extensions(&dir.join(path), &["yml", "yaml"]) {
+                match read_yaml(&dir.join(path).join(&file)) {
+                    Ok(properties) => properties
+                        .get("models")
+                        .and_then(|m| m.as_array())
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|m| Some((m.get("name")?.as_str()?, m.get("description")?)))
+                        .for_each(|(name, description)| {
+                            descriptions.insert(name.to_string(), description.to_owned());
+                        }),
+                    Err(e) => context.warn(format!("Could not parse {}/{}: {}", path, file, e)),
+                }
+            }
+        }
+
+        let models: Vec<Value> = model_paths
+            .iter()
+            .flat_map(|path| {
+                files_with_extensions(&dir.join(path), &["sql"])
+                    .into_iter()
+                    .map(move |file| format!("{}/{}", path, file))
+            })
+            .map(|file| {
+                let name = Path::new(&file)
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let mut model = Map::new();
+                if let Some(description) = descriptions.get(&name) {
+                    model.insert("description".to_string(), description.to_owned());
+                }
+                model.insert("name".to_string(), Value::String(name));
+                model.insert("path".to_string(), Value::String(file));
+                Value::Object(model)
+            })
+            .collect();
+
+        Ok(ParsedDocument {
+            name: project
+                .get("name")
+                .and_then(|n| n.as_str())
+                .map(String::from),
+            value: json!({
+                "project": project,
+                "models": models,
+            }),
+            ..Default::default()
+        })
+    }
+}
+
+/// Directory parser for Terraform modules.
+///
+/// Returns the names of the `variables`, `outputs`, `resources`, `data` sources, `modules`
+/// and `providers` declared at the top level of the `.tf` files in the module directory.
+/// Subdirectories, which are separate modules, are not included.
+pub struct TerraformModuleParser {}
+impl DirectoryParser for TerraformModuleParser {
+    fn name(&self) -> &'static str {
+        "terraform-module"
+    }
+
+    fn markers(&self) -> &'static [&'static str] {
+        &["main.tf", "variables.tf", "outputs.tf"]
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let dir = context.path();
+        let mut files = vec![];
+        let mut blocks: Map<String, Value> = [
+            "variables",
+            "outputs",
+            "resources",
+            "data",
+            "modules",
+            "providers",
+        ]
+        .iter()
+        .map(|k| (k.to_string(), Value::Array(vec![])))
+        .collect();
+
+        let mut paths: Vec<_> = fs::read_dir(dir)?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.is_file() && super::has_extension(p, &["tf"]))
+            .collect();
+        paths.sort();
+        for path in paths {
+            files.push(Value::String(
+                path.file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into(),
+            ));
+            for line in fs::read_to_string(&path)?.lines() {
+                if let Some((kind, name)) = block_header(line) {
+                    if let Some(Value::Array(names)) = blocks.get_mut(kind) {
+                        names.push(Value::String(name));
+                    }
+                }
+            }
+        }
+
+        blocks.insert("files".to_string(), Value::Array(files));
+        Ok(ParsedDocument {
+            name: dir.file_name().map(|n| n.to_string_lossy().to_string()),
+            value: Value::Object(blocks),
+            ..Default::default()
+        })
+    }
+}
+
+/// Return the kind of block and its name, such as `aws_s3_bucket.logs` for a resource,
+/// if a line opens a top-level Terraform block.
+fn block_header(line: &str) -> Option<(&'static str, String)> {
+    let (keyword, rest) = line.split_once(char::is_whitespace)?;
+    let kind = match keyword {
+        "variable" => "variables",
+        "output" => "outputs",
+        "resource" => "resources",
+        "data" => "data",
+        "module" => "modules",
+        "provider" => "providers",
+        _ => return None,
+    };
+    let labels: Vec<&str> = rest
+        .trim_end()
+        .strip_suffix('{')?
+        .split_whitespace()
+        .map(|l| l.trim_matches('"'))
+        .collect();
+    match labels.as_slice() {
+        [] => None,
+        labels => Some((kind, labels.join("."))),
+    }
+}
+
+/// The parts of a Maven POM returned for a project and each of its modules.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Pom {
+    group_id: Option<String>,
+    artifact_id: String,
+    version: Option<String>,
+    packaging: Option<String>,
+    name: Option<String>,
+    description: Option<String>,
+    #[serde(default)]
+    modules: PomModules,
+}
+
+/// The modules of a Maven multi-module project.
+#[derive(Debug, Default, Deserialize)]
+struct PomModules {
+    #[serde(default)]
+    module: Vec<String>,
+}
+
+impl Pom {
+    /// Read a POM file.
+    fn read(path: &Path) -> Result<Pom> {
+        Ok(serde_xml_rs::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Return the coordinates and descriptive details of the POM as JSON.
+    fn to_json(&self) -> Map<String, Value> {
+        [
+            ("groupId", &self.group_id),
+            ("artifactId", &Some(self.artifact_id.to_owned())),
+            ("version", &self.version),
+            ("packaging", &self.packaging),
+            ("name", &self.name),
+            ("description", &self.description),
+        ]
+        .into_iter()
+        .filter_map(|(k, v)| {
+            v.as_ref()
+                .map(|v| (k.to_string(), Value::String(v.trim().into())))
+        })
+        .collect()
+    }
+}
+
+/// Directory parser for Maven multi-module builds.
+///
+/// Only `pom.xml` files which declare `modules` are parsed. Returns the coordinates of the
+/// project and of each of its `modules`, read from the `pom.xml` of each module.
+pub struct MavenProjectParser {}
+impl DirectoryParser for MavenProjectParser {
+    fn name(&self) -> &'static str {
+        "maven-project"
+    }
+
+    fn markers(&self) -> &'static [&'static str] {
+        &["pom.xml"]
+    }
+
+    fn can_parse(&self, context: &ParseContext) -> bool {
+        fs::read_to_string(context.path().join("pom.xml")).is_ok_and(|p| p.contains("<modules>"))
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let dir = context.path();
+        let pom = Pom::read(&dir.join("pom.xml"))?;
+        let mut project = pom.to_json();
+        let modules = pom
+            .modules
+            .module
+            .iter()
+            .map(|module| {
+                let mut value = match Pom::read(&dir.join(module).join("pom.xml")) {
+                    Ok(module_pom) => module_pom.to_json(),
+                    Err(e) => {
+                        context.warn(format!("Could not read module {}: {}", module, e));
+                        Map::new()
+                    }
+                };
+                value.insert("path".to_string(), Value::String(module.to_owned()));
+                Value::Object(value)
+            })
+            .collect();
+        project.insert("modules".to_string(), Value::Array(modules));
+        Ok(ParsedDocument {
+            name: Some(pom.artifact_id),
+            value: Value::Object(project),
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::parsers::directory::block_header;
+    use crate::parsers::{directory_parsers, ParseContext, ParserOptions};
+    use serde_json::json;
+    use std::path::Path;
+
+    fn parse(parser: &str, dir: &str) -> serde_json::Value {
+        let options = ParserOptions::default();
+        let parser = directory_parsers(&options)
+            .into_iter()
+            .find(|p| p.name() == parser)
+            .unwrap();
+        let context = ParseContext::new(Path::new(dir), &options);
+        assert!(parser.can_parse(&context));
+        parser.parse(&context).unwrap().value
+    }
+
+    #[test]
+    fn helm_chart() {
+        assert_eq!(
+            parse("helm-chart", "test/resources/directories/chart"),
+            json!({
+                "chart": {"apiVersion": "v2", "name": "web", "version": "1.2.0"},
+                "values": {"replicaCount": 2},
+                "templates": ["_helpers.tpl", "deployment.yaml"]
+            })
+        );
+    }
+
+    #[test]
+    fn dbt_project() {
+        assert_eq!(
+            parse("dbt-project", "test/resources/directories/dbt")["models"],
+            json!([
+                {
+                    "description": "One row per customer",
+                    "name": "customers",
+                    "path": "models/customers.sql"
+                },
+                {"name": "orders", "path": "models/staging/orders.sql"}
+            ])
+        );
+    }
+
+    #[test]
+    fn terraform_module() {
+        assert_eq!(
+            parse("terraform-module", "test/resources/directories/module"),
+            json!({
+                "variables": ["bucket_name"],
+                "outputs": ["bucket_arn"],
+                "resources": ["aws_s3_bucket.logs"],
+                "data": [],
+                "modules": [],
+                "providers": ["aws"],
+                "files": ["main.tf", "outputs.tf", "variables.tf"]
+            })
+        );
+        assert_eq!(
+            block_header("module \"vpc\" {"),
+            Some(("modules", "vpc".to_string()))
+        );
+        assert_eq!(block_header("  variable \"nested\" {"), None);
+        assert_eq!(block_header("locals {"), None);
+    }
+
+    #[test]
+    fn maven_project() {
+        assert_eq!(
+            parse("maven-project", "test/resources/directories/maven"),
+            json!({
+                "groupId": "org.example",
+                "artifactId": "shop",
+                "version": "1.0.0",
+                "packaging": "pom",
+                "modules": [
+                    {"groupId": "org.example", "artifactId": "shop-core", "path": "core"},
+                    {"path": "missing"}
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn spring_boot() {
+        let value = parse("spring-boot", "test/resources/directories/spring");
+        assert_eq!(
+            value["files"],
+            json!([
+                "application.yml",
+                "application.properties",
+                "application-prod.yml",
+                "application-proddb.properties"
+            ])
+        );
+        assert_eq!(
+            value["profiles"],
+            json!(["dev", "prod", "proddb", "prodmq", "test"])
+        );
+        assert_eq!(value["active"], json!(["dev"]));
+
+        let default = &value["effective"]["default"];
+        assert_eq!(default["profiles"], json!(["dev"]));
+        assert_eq!(
+            default["properties"]["server.port"],
+            json!({"value": 8080, "source": "application.yml", "line": 9})
+        );
+        assert_eq!(
+            default["properties"]["app.hosts[1]"]["value"],
+            json!("b.example.com")
+        );
+        assert_eq!(
+            default["properties"]["logging.level.root"],
+            json!({"value": "DEBUG", "source": "application.properties", "line": 4})
+        );
+
+        let prod = &value["effective"]["prod"];
+        assert_eq!(prod["profiles"], json!(["prod", "proddb", "prodmq"]));
+        assert_eq!(prod["properties"]["server.port"]["value"], json!(80));
+        assert_eq!(prod["properties"]["server.port"]["line"], json!(20));
+        assert_eq!(default["properties"]["app.hosts[1]"]["line"], json!(13));
+        assert_eq!(
+            prod["properties"]["app.timeout"],
+            json!({"value": 60, "source": "application-prod.yml", "line": 2})
+        );
+        assert_eq!(
+            prod["properties"]["spring.datasource.url"]["source"],
+            json!("application-proddb.properties")
+        );
+        assert!(prod["properties"].get("logging.level.root").is_none());
+        assert!(prod["properties"]
+            .get("spring.config.activate.on-profile")
+            .is_none());
+    }
+
+    #[test]
+    fn licenses() {
+        let result = parse("licenses", "test/resources/directories/licenses");
+        assert_eq!(
+            result["license_files"],
+            json!([{
+                "license": "MIT",
+                "name": "MIT License",
+                "copyright": ["Copyright (c) 2021 Example Ltd"],
+                "path": "LICENSE"
+            }])
+        );
+        assert_eq!(
+            result["files"],
+            json!([
+                {"path": "src/main.rs", "license": "MIT OR Apache-2.0", "source": "spdx"},
+                {"path": "src/server.py", "license": "Apache-2.0", "source": "header"},
+                {"path": "src/styles.css", "license": "MIT", "source": "spdx"}
+            ])
+        );
+        assert_eq!(result["unlicensed"], json!(["src/util.js"]));
+        assert_eq!(
+            result["summary"],
+            json!([
+                {"license": "Apache-2.0", "files": 1},
+                {"license": "MIT", "files": 1},
+                {"license": "MIT OR Apache-2.0", "files": 1}
+            ])
+        );
+    }
+}
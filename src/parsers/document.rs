@@ -0,0 +1,180 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! The inputs and outputs of a file parser.
+
+use super::{Descriptions, ParserOptions};
+use crate::errors::*;
+use once_cell::unsync::OnceCell;
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+
+/// A file being parsed, whose contents are read when first requested and then shared by
+/// every parser of the file.
+///
+/// Parsers report problems which do not prevent parsing through [`ParseContext::warn`],
+/// which are collected into the warnings of the parsed document.
+pub struct ParseContext<'a> {
+    path: &'a Path,
+    options: &'a ParserOptions,
+    contents: OnceCell<std::result::Result<Vec<u8>, String>>,
+    warnings: RefCell<Vec<String>>,
+}
+
+impl<'a> ParseContext<'a> {
+    /// Create a context for a file, which is read from disk if its contents are requested.
+    pub fn new(path: &'a Path, options: &'a ParserOptions) -> ParseContext<'a> {
+        ParseContext {
+            path,
+            options,
+            contents: OnceCell::new(),
+            warnings: RefCell::default(),
+        }
+    }
+
+    /// Create a context for a file whose contents have already been read, or could not be.
+    pub fn with_contents(
+        path: &'a Path,
+        options: &'a ParserOptions,
+        contents: std::result::Result<Vec<u8>, String>,
+    ) -> ParseContext<'a> {
+        ParseContext {
+            path,
+            options,
+            contents: OnceCell::with_value(contents),
+            warnings: RefCell::default(),
+        }
+    }
+
+    /// Create a context for text which is not read from disk, such as an embedded file.
+    pub fn with_text(path: &'a Path, options: &'a ParserOptions, text: &str) -> ParseContext<'a> {
+        ParseContext::with_contents(path, options, Ok(text.as_bytes().to_vec()))
+    }
+
+    /// Return the path of the file.
+    pub fn path(&self) -> &Path {
+        self.path
+    }
+
+    /// Return the options for all parsers.
+    pub fn options(&self) -> &ParserOptions {
+        self.options
+    }
+
+    /// Return the contents of the file, reading it if required.
+    pub fn bytes(&self) -> Result<&[u8]> {
+        self.contents
+            .get_or_init(|| fs::read(self.path).map_err(|e| e.to_string()))
+            .as_deref()
+            .map_err(|e| format!("Could not read {}: {}", self.path.display(), e).into())
+    }
+
+    /// Return the contents of the file as UTF-8 text, reading it if required.
+    pub fn text(&self) -> Result<&str> {
+        std::str::from_utf8(self.bytes()?)
+            .map_err(|e| format!("{} is not valid UTF-8: {}", self.path.display(), e).into())
+    }
+
+    /// Record a problem which did not prevent the file from being parsed.
+    pub fn warn(&self, message: impl Into<String>) {
+        self.warnings.borrow_mut().push(message.into());
+    }
+
+    /// Remove and return the warnings recorded since they were last taken.
+    pub fn take_warnings(&self) -> Vec<String> {
+        self.warnings.take()
+    }
+}
+
+/// The result of successfully parsing a file, or one of the documents within a file.
+#[derive(Debug, Default)]
+pub struct ParsedDocument {
+    /// The name of the document within the file, such as the name of a Kubernetes resource.
+    pub name: Option<String>,
+    /// The parsed contents of the file.
+    pub value: Value,
+    /// Descriptions of keys within the file, taken from its comments.
+    pub descriptions: Descriptions,
+    /// Details of the file format, such as the SQL dialect or Kubernetes kind.
+    pub metadata: Map<String, Value>,
+    /// Problems which did not prevent the file from being parsed.
+    pub warnings: Vec<String>,
+}
+
+impl ParsedDocument {
+    /// Create a document with parsed contents only.
+    pub fn new(value: Value) -> ParsedDocument {
+        ParsedDocument {
+            value,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<Value> for ParsedDocument {
+    fn from(value: Value) -> ParsedDocument {
+        ParsedDocument::new(value)
+    }
+}
+
+/// What a parser is able to parse and return.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Capabilities {
+    /// The extensions, without a leading `.`, of the files the parser accepts.
+    pub extensions: &'static [&'static str],
+    /// Whether the parser reads binary files, rather than UTF-8 text.
+    pub binary: bool,
+    /// Whether the parser can return descriptions of keys, with the `comments` option.
+    pub descriptions: bool,
+    /// Whether the parser can return several documents from a single file.
+    pub documents: bool,
+    /// The options the parser accepts, besides `comments`.
+    pub options: &'static [&'static str],
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::parsers::{ParseContext, ParserOptions};
+    use std::path::Path;
+
+    #[test]
+    fn contents() {
+        let options = ParserOptions::default();
+        let context = ParseContext::new(Path::new("test/resources/test.ini"), &options);
+        assert!(context.text().unwrap().contains("[owner]"));
+        assert_eq!(context.bytes().unwrap(), context.text().unwrap().as_bytes());
+
+        let missing = ParseContext::new(Path::new("test/resources/missing.ini"), &options);
+        assert!(missing.bytes().is_err());
+        assert!(missing
+            .text()
+            .unwrap_err()
+            .to_string()
+            .contains("missing.ini"));
+
+        let binary = ParseContext::with_contents(Path::new("a.bin"), &options, Ok(vec![0xff]));
+        assert!(binary.bytes().is_ok());
+        assert!(binary.text().is_err());
+
+        binary.warn("first");
+        binary.warn("second".to_string());
+        assert_eq!(binary.take_warnings(), ["first", "second"]);
+        assert!(binary.take_warnings().is_empty());
+    }
+}
@@ -0,0 +1,182 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Parser for dotenv files, such as `.env`, `.env.production` and `database.env`.
+
+use super::{
+    has_extension, Capabilities, Descriptions, FileParser, ParseContext, ParsedDocument,
+    ParserOptions,
+};
+use crate::errors::*;
+use serde_json::{Map, Value};
+
+/// File parser for dotenv files: `.env`, `.env.*` and `*.env`.
+///
+/// Returns a flat object of the variables in the file, each with a string value. Lines may
+/// begin with `export`, and lines beginning with `#` are comments, as is the remainder of a
+/// line following whitespace and `#` after an unquoted value. Values in single quotes are
+/// literal, whilst values in double quotes may contain `\n`, `\t`, `\"` and `\\` escapes,
+/// and both may span several lines. References to other variables, such as `${HOME}`, are
+/// not expanded. With the `comments` option, the comments preceding each variable are
+/// returned as its description.
+pub struct EnvFileParser {
+    comments: bool,
+}
+
+impl EnvFileParser {
+    /// Create a new parser using the `dotenv` parser options.
+    pub fn new(options: &ParserOptions) -> EnvFileParser {
+        EnvFileParser {
+            comments: options.flag("dotenv", "comments"),
+        }
+    }
+
+    /// Return whether a file is a dotenv file, by its name.
+    fn is_env_file(context: &ParseContext) -> bool {
+        let name = context
+            .path()
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        name == ".env" || name.starts_with(".env.") || has_extension(context.path(), &["env"])
+    }
+
+    /// Read a quoted value beginning after its opening quote, returning the value and the
+    /// remainder of the text after the closing quote, or `None` if it is unterminated.
+    fn quoted(text: &str, quote: char) -> Option<(String, &str)> {
+        let mut value = String::new();
+        let mut chars = text.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                c if c == quote => return Some((value, &text[i + 1..])),
+                '\\' if quote == '"' => match chars.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, 'r')) => value.push('\r'),
+                    Some((_, 't')) => value.push('\t'),
+                    Some((_, c @ ('"' | '\\' | '$'))) => value.push(c),
+                    Some((_, c)) => {
+                        value.push('\\');
+                        value.push(c);
+                    }
+                    None => value.push('\\'),
+                },
+                c => value.push(c),
+            }
+        }
+        None
+    }
+}
+
+impl FileParser for EnvFileParser {
+    fn name(&self) -> &'static str {
+        "dotenv"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["env"],
+            descriptions: true,
+            ..Default::default()
+        }
+    }
+
+    fn can_parse(&self, context: &ParseContext) -> bool {
+        EnvFileParser::is_env_file(context)
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let mut variables = Map::new();
+        let mut descriptions = Descriptions::new();
+        let mut comments: Vec<&str> = vec![];
+
+        let text = context.text()?;
+        let mut rest = text;
+        let mut number = 0;
+        while !rest.is_empty() {
+            let (line, next) = rest.split_once('\n').unwrap_or((rest, ""));
+            number += 1;
+            rest = next;
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                comments.clear();
+                continue;
+            }
+            if let Some(comment) = trimmed.strip_prefix('#') {
+                comments.push(comment.trim());
+                continue;
+            }
+
+            let declaration = trimmed
+                .strip_prefix("export")
+                .filter(|d| d.starts_with(char::is_whitespace))
+                .unwrap_or(trimmed);
+            let Some((key, value)) = declaration.split_once('=') else {
+                bail!(format!("Expected a variable and value on line {}", number))
+            };
+            let key = key.trim();
+            if key.is_empty() || key.contains(char::is_whitespace) {
+                bail!(format!(
+                    "Invalid variable name {:?} on line {}",
+                    key, number
+                ))
+            }
+
+            // Quoted values may continue onto the following lines
+            let value = value.trim_start();
+            let value = match value.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let start = value.as_ptr() as usize - text.as_ptr() as usize + 1;
+                    let Some((value, after)) = EnvFileParser::quoted(&text[start..], quote) else {
+                        bail!(format!("Unterminated quoted value on line {}", number))
+                    };
+                    number += text[start..text.len() - after.len()].matches('\n').count();
+                    let (after, next) = after.split_once('\n').unwrap_or((after, ""));
+                    let after = after.trim();
+                    if !after.is_empty() && !after.starts_with('#') {
+                        bail!(format!(
+                            "Unexpected {:?} after quoted value on line {}",
+                            after, number
+                        ))
+                    }
+                    rest = next;
+                    value
+                }
+                _ => match value.find(" #").or_else(|| value.find("\t#")) {
+                    Some(comment) => value[..comment].trim_end().to_string(),
+                    None => value.trim_end().to_string(),
+                },
+            };
+
+            if variables.contains_key(key) {
+                context.warn(format!(
+                    "Variable {} is repeated, keeping the last value",
+                    key
+                ));
+            }
+            variables.insert(key.to_string(), Value::String(value));
+            if self.comments && !comments.is_empty() {
+                descriptions.insert(key.to_string(), comments.join("\n"));
+            }
+            comments.clear();
+        }
+
+        Ok(ParsedDocument {
+            value: Value::Object(variables),
+            descriptions,
+            ..Default::default()
+        })
+    }
+}
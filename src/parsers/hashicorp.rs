@@ -0,0 +1,355 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Parsers for HashiCorp Vault policies and Consul agent and service configuration, in
+//! HCL or JSON.
+
+use super::kubernetes::REDACTED;
+use super::{has_extension, Capabilities, FileParser, ParseContext, ParsedDocument};
+use crate::errors::*;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+/// Keys which identify Consul agent configuration.
+const AGENT_KEYS: &[&str] = &[
+    "datacenter",
+    "data_dir",
+    "bootstrap_expect",
+    "retry_join",
+    "node_name",
+];
+
+/// Keys of a Consul check naming its type, with the check's target as their value.
+const CHECK_TYPES: &[&str] = &[
+    "http",
+    "tcp",
+    "udp",
+    "grpc",
+    "h2ping",
+    "args",
+    "script",
+    "docker_container_id",
+    "alias_service",
+    "os_service",
+    "ttl",
+];
+
+/// Parse HCL or, for `.json` files, JSON configuration.
+fn read(context: &ParseContext) -> Result<Value> {
+    let text = context.text()?;
+    match has_extension(context.path(), &["json"]) {
+        true => Ok(serde_json::from_str(text)?),
+        false => Ok(hcl::from_str(text)?),
+    }
+}
+
+/// Return whether a line of HCL or JSON sets a key or opens a block with its name.
+fn sets_key(line: &str, key: &str) -> bool {
+    let line = line.trim_start();
+    let rest = line
+        .strip_prefix(key)
+        .or_else(|| line.strip_prefix(&format!("\"{}\"", key)));
+    rest.is_some_and(|r| r.trim_start().starts_with(['=', ':', '{', '"']))
+}
+
+/// Return a block which may appear once or be repeated as a list of blocks.
+fn blocks(value: Option<&Value>) -> Vec<&Value> {
+    match value {
+        Some(Value::Array(items)) => items.iter().collect(),
+        Some(item @ Value::Object(_)) => vec![item],
+        _ => vec![],
+    }
+}
+
+/// Return a value which may be a single item or a list of items as a list.
+fn list(value: Option<&Value>) -> Value {
+    match value {
+        None | Some(Value::Null) => json!([]),
+        Some(Value::Array(items)) => json!(items),
+        Some(item) => json!([item]),
+    }
+}
+
+/// Redact the values of keys which hold secrets, such as the gossip `encrypt` key and ACL
+/// tokens, within a configuration.
+fn redact(value: &mut Value, secret: bool) {
+    match value {
+        Value::Object(map) => {
+            for (key, item) in map.iter_mut() {
+                let secret =
+                    secret || key == "encrypt" || key.ends_with("token") || key == "tokens";
+                redact(item, secret);
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|i| redact(i, secret)),
+        Value::Null => {}
+        leaf if secret => *leaf = Value::String(REDACTED.to_string()),
+        _ => {}
+    }
+}
+
+/// File parser for Vault ACL policies, in HCL or JSON.
+///
+/// Returns the `paths` of the policy in order, each with its `path`, the `description` from
+/// the comments preceding it, if any, its `capabilities`, whether it is a `wildcard` path
+/// using `*` or `+`, and any parameter constraints and wrapping TTLs. Also returns the
+/// paths granted each capability, `by_capability`, and the `denied` paths.
+pub struct VaultPolicyParser {}
+
+impl VaultPolicyParser {
+    /// Return the comments preceding each `path` block of an HCL policy, keyed by path.
+    fn descriptions(text: &str) -> BTreeMap<String, String> {
+        let mut descriptions = BTreeMap::new();
+        let mut comments: Vec<&str> = vec![];
+        for line in text.lines().map(|l| l.trim()) {
+            if let Some(comment) = line.strip_prefix('#').or_else(|| line.strip_prefix("//")) {
+                comments.push(comment.trim());
+                continue;
+            }
+            let path = line
+                .strip_prefix("path")
+                .map(|r| r.trim_start())
+                .and_then(|r| r.strip_prefix('"'))
+                .and_then(|r| r.split_once('"'))
+                .map(|(path, _)| path);
+            if let (Some(path), false) = (path, comments.is_empty()) {
+                descriptions.insert(path.to_string(), comments.join("\n"));
+            }
+            comments.clear();
+        }
+        descriptions
+    }
+}
+
+impl FileParser for VaultPolicyParser {
+    fn name(&self) -> &'static str {
+        "vault-policy"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["hcl", "json"],
+            ..Default::default()
+        }
+    }
+
+    fn can_parse(&self, context: &ParseContext) -> bool {
+        has_extension(context.path(), self.capabilities().extensions)
+            && context
+                .text()
+                .is_ok_and(|c| c.contains("capabilities") && c.lines().any(|l| sets_key(l, "path")))
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let policy = read(context)?;
+        let descriptions = VaultPolicyParser::descriptions(context.text()?);
+        let Some(Value::Object(rules)) = policy.get("path") else {
+            bail!("Policy has no path rules")
+        };
+
+        let mut paths = vec![];
+        let mut by_capability: Map<String, Value> = Map::new();
+        let mut denied = vec![];
+        for (path, rule) in rules {
+            for rule in blocks(Some(rule)) {
+                let capabilities: Vec<&str> = rule
+                    .get("capabilities")
+                    .and_then(|c| c.as_array())
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|c| c.as_str())
+                    .collect();
+                for capability in &capabilities {
+                    if let Value::Array(granted) = by_capability
+                        .entry(capability.to_string())
+                        .or_insert_with(|| json!([]))
+                    {
+                        granted.push(json!(path));
+                    }
+                }
+                if capabilities.contains(&"deny") {
+                    denied.push(path.to_owned());
+                }
+                paths.push(json!({
+                    "path": path,
+                    "description": descriptions.get(path),
+                    "capabilities": capabilities,
+                    "wildcard": path.contains(['*', '+']),
+                    "required_parameters": rule.get("required_parameters"),
+                    "allowed_parameters": rule.get("allowed_parameters"),
+                    "denied_parameters": rule.get("denied_parameters"),
+                    "min_wrapping_ttl": rule.get("min_wrapping_ttl"),
+                    "max_wrapping_ttl": rule.get("max_wrapping_ttl"),
+                }));
+            }
+        }
+
+        Ok(ParsedDocument {
+            name: context
+                .path()
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string()),
+            value: json!({
+                "paths": paths,
+                "by_capability": by_capability,
+                "denied": denied,
+            }),
+            ..Default::default()
+        })
+    }
+}
+
+/// File parser for Consul agent configuration and service definitions, in HCL or JSON.
+///
+/// Returns the `kind` of configuration, `agent` if it configures an agent and otherwise
+/// `services`, the agent's `datacenter`, `node_name`, whether it is a `server`, its
+/// `retry_join` addresses and `acl` settings, and the `services` defined, each with its
+/// `name`, `id`, `address`, `port`, `tags`, `meta`, health `checks`, with their `type` and
+/// `target`, and `connect` settings, with its sidecar `upstreams`. The full configuration
+/// is returned as `config`, without service definitions, with the gossip encryption key and
+/// ACL tokens redacted.
+pub struct ConsulConfigParser {}
+
+impl ConsulConfigParser {
+    /// Describe a health check.
+    fn check(check: &Value) -> Value {
+        let (kind, target) = CHECK_TYPES
+            .iter()
+            .find_map(|t| check.get(*t).map(|target| (*t, target)))
+            .map(|(kind, target)| match target {
+                Value::Array(args) => (
+                    Some(kind),
+                    json!(args
+                        .iter()
+                        .filter_map(|a| a.as_str())
+                        .collect::<Vec<_>>()
+                        .join(" ")),
+                ),
+                target => (Some(kind), target.to_owned()),
+            })
+            .unwrap_or((None, Value::Null));
+        json!({
+            "name": check.get("name"),
+            "type": kind,
+            "target": target,
+            "interval": check.get("interval"),
+            "timeout": check.get("timeout"),
+        })
+    }
+
+    /// Describe a service definition.
+    fn service(service: &Value) -> Value {
+        let checks: Vec<Value> = blocks(service.get("check"))
+            .into_iter()
+            .chain(blocks(service.get("checks")))
+            .map(ConsulConfigParser::check)
+            .collect();
+        let connect = service.get("connect").map(|connect| {
+            let sidecar = connect.get("sidecar_service");
+            let upstreams: Vec<Value> = blocks(
+                sidecar
+                    .and_then(|s| s.get("proxy"))
+                    .and_then(|p| p.get("upstreams")),
+            )
+            .into_iter()
+            .map(|u| {
+                json!({
+                    "destination_name": u.get("destination_name"),
+                    "local_bind_port": u.get("local_bind_port"),
+                })
+            })
+            .collect();
+            json!({
+                "native": connect.get("native").and_then(|n| n.as_bool()).unwrap_or(false),
+                "sidecar": sidecar.is_some(),
+                "upstreams": upstreams,
+            })
+        });
+        json!({
+            "name": service.get("name"),
+            "id": service.get("id").or_else(|| service.get("name")),
+            "address": service.get("address"),
+            "port": service.get("port"),
+            "tags": service.get("tags").cloned().unwrap_or(json!([])),
+            "meta": service.get("meta").cloned().unwrap_or(json!({})),
+            "checks": checks,
+            "connect": connect,
+        })
+    }
+}
+
+impl FileParser for ConsulConfigParser {
+    fn name(&self) -> &'static str {
+        "consul"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["hcl", "json"],
+            ..Default::default()
+        }
+    }
+
+    fn can_parse(&self, context: &ParseContext) -> bool {
+        has_extension(context.path(), self.capabilities().extensions)
+            && context.text().is_ok_and(|c| {
+                let has = |key: &str| c.lines().any(|l| sets_key(l, key));
+                // Nomad jobs also define services with checks
+                !has("job")
+                    && (AGENT_KEYS.iter().any(|k| has(k))
+                        || ((has("service") || has("services"))
+                            && (has("check") || has("checks") || has("connect"))))
+            })
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let mut config = match read(context)? {
+            Value::Object(config) => config,
+            _ => bail!("Consul configuration is not an object"),
+        };
+        let services: Vec<Value> = blocks(config.get("service"))
+            .into_iter()
+            .chain(blocks(config.get("services")))
+            .map(ConsulConfigParser::service)
+            .collect();
+        config.remove("service");
+        config.remove("services");
+        let mut config = Value::Object(config);
+        redact(&mut config, false);
+
+        let agent = AGENT_KEYS.iter().any(|k| config.get(*k).is_some());
+        Ok(ParsedDocument {
+            name: config
+                .get("node_name")
+                .and_then(|n| n.as_str())
+                .map(String::from),
+            value: json!({
+                "kind": if agent { "agent" } else { "services" },
+                "datacenter": config.get("datacenter"),
+                "node_name": config.get("node_name"),
+                "server": config.get("server").and_then(|s| s.as_bool()).unwrap_or(false),
+                "retry_join": list(config.get("retry_join")),
+                "acl": config.get("acl").map(|acl| json!({
+                    "enabled": acl.get("enabled"),
+                    "default_policy": acl.get("default_policy"),
+                })),
+                "services": services,
+                "config": config,
+            }),
+            ..Default::default()
+        })
+    }
+}
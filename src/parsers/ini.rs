@@ -0,0 +1,363 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Parser for INI files.
+
+use super::{
+    comments, Capabilities, Descriptions, FileParser, ParseContext, ParsedDocument, ParserOptions,
+};
+use crate::errors::*;
+use serde_json::{Map, Number, Value};
+
+/// File parser for INI files.
+///
+/// By default, sections are returned as objects containing string values, with keys
+/// outside of any section at the top level and repeated keys taking the last value.
+/// The following options change this behaviour:
+///
+/// * `nested_sections`: sections named `[a.b]` are nested within the object for `a`.
+/// * `repeated_keys`: keys which appear more than once in a section become arrays.
+/// * `coerce_types`: integer, float and boolean values are converted to JSON types and
+///   quotes are removed from quoted values.
+/// * `comments`: comments preceding sections and keys are returned as descriptions.
+///
+/// Keys are separated from values by `=` or `:`, and comments starting with `;` or `#`
+/// after whitespace at the end of a line are ignored, except within quoted values.
+pub struct IniParser {
+    comments: bool,
+    nested_sections: bool,
+    repeated_keys: bool,
+    coerce_types: bool,
+}
+
+impl IniParser {
+    /// Create a new parser using the `ini` parser options.
+    pub fn new(options: &ParserOptions) -> IniParser {
+        IniParser {
+            comments: options.flag("ini", "comments"),
+            nested_sections: options.flag("ini", "nested_sections"),
+            repeated_keys: options.flag("ini", "repeated_keys"),
+            coerce_types: options.flag("ini", "coerce_types"),
+        }
+    }
+
+    /// Convert a raw value to JSON, coercing its type if required.
+    fn value(&self, raw: &str) -> Value {
+        if !self.coerce_types {
+            return Value::String(raw.to_string());
+        }
+        match raw.to_lowercase().as_str() {
+            "true" | "yes" | "on" => return Value::Bool(true),
+            "false" | "no" | "off" => return Value::Bool(false),
+            _ => (),
+        }
+        if let Ok(i) = raw.parse::<i64>() {
+            return Value::Number(i.into());
+        }
+        if let Some(f) = raw.parse::<f64>().ok().and_then(Number::from_f64) {
+            return Value::Number(f);
+        }
+        let unquoted = raw
+            .strip_prefix('"')
+            .and_then(|r| r.strip_suffix('"'))
+            .or_else(|| raw.strip_prefix('\'').and_then(|r| r.strip_suffix('\'')));
+        Value::String(unquoted.unwrap_or(raw).to_string())
+    }
+
+    /// Return the object for a section, creating it (and any parents) if required.
+    fn section<'a>(
+        &self,
+        root: &'a mut Map<String, Value>,
+        name: &str,
+    ) -> Result<&'a mut Map<String, Value>> {
+        let segments: Vec<&str> = if self.nested_sections {
+            name.split('.').map(|s| s.trim()).collect()
+        } else {
+            vec![name]
+        };
+
+        let mut current = root;
+        for segment in segments {
+            current = match current
+                .entry(segment.to_string())
+                .or_insert_with(|| Value::Object(Map::new()))
+            {
+                Value::Object(map) => map,
+                _ => bail!(format!(
+                    "Section [{}] conflicts with an existing key {}",
+                    name, segment
+                )),
+            }
+        }
+        Ok(current)
+    }
+
+    /// Insert a key into a section, combining repeated keys into an array if required,
+    /// and otherwise warning that the earlier value is replaced.
+    fn insert(
+        &self,
+        section: &mut Map<String, Value>,
+        key: &str,
+        value: Value,
+        context: &ParseContext,
+    ) {
+        match section.get_mut(key) {
+            Some(Value::Array(values)) if self.repeated_keys => values.push(value),
+            Some(existing) if self.repeated_keys => {
+                let first = existing.take();
+                *existing = Value::Array(vec![first, value]);
+            }
+            existing => {
+                if existing.is_some() {
+                    context.warn(format!("Key {} is repeated, keeping the last value", key));
+                }
+                section.insert(key.to_string(), value);
+            }
+        }
+    }
+}
+
+/// Return whether the rest of a line is empty or an inline comment.
+fn is_comment(rest: &str) -> bool {
+    let rest = rest.trim_start();
+    rest.is_empty() || rest.starts_with([';', '#'])
+}
+
+/// Remove an inline comment from a value, which must follow whitespace and is not
+/// recognised within a quoted value.
+fn strip_comment(value: &str) -> &str {
+    let value = value.trim_start();
+    if let Some(quote @ ('"' | '\'')) = value.chars().next() {
+        if let Some(end) = value[1..].find(quote).map(|end| end + 2) {
+            if is_comment(&value[end..]) {
+                return &value[..end];
+            }
+        }
+    }
+    let comment = value
+        .char_indices()
+        .zip(value.chars().skip(1))
+        .find(|((_, c), next)| c.is_whitespace() && matches!(next, ';' | '#'));
+    match comment {
+        Some(((i, _), _)) => value[..i].trim(),
+        None => value.trim(),
+    }
+}
+
+impl FileParser for IniParser {
+    fn name(&self) -> &'static str {
+        "ini"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["ini"],
+            descriptions: true,
+            options: &["nested_sections", "repeated_keys", "coerce_types"],
+            ..Default::default()
+        }
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let mut root = Map::new();
+        let mut section: Option<String> = None;
+
+        let text = context.text()?;
+        for (number, line) in text.lines().enumerate() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with(';') || trimmed.starts_with('#') {
+                continue;
+            }
+
+            if let Some(name) = trimmed.strip_prefix('[') {
+                match name.split_once(']').filter(|(_, rest)| is_comment(rest)) {
+                    Some((name, _)) => {
+                        let name = name.trim();
+                        self.section(&mut root, name)?;
+                        section = Some(name.to_string());
+                    }
+                    None => bail!(format!(
+                        "Unterminated section header on line {}",
+                        number + 1
+                    )),
+                }
+            } else if let Some((key, value)) = trimmed.split_once(['=', ':']) {
+                let target = match &section {
+                    Some(name) => self.section(&mut root, name)?,
+                    None => &mut root,
+                };
+                self.insert(
+                    target,
+                    key.trim(),
+                    self.value(strip_comment(value)),
+                    context,
+                );
+            } else {
+                bail!(format!("Expected a key and value on line {}", number + 1))
+            }
+        }
+
+        Ok(ParsedDocument {
+            value: Value::Object(root),
+            descriptions: if self.comments {
+                comments::ini(text)
+            } else {
+                Descriptions::new()
+            },
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parsers::{FileParser, IniParser, ParseContext, ParserOptions};
+    use serde_json::json;
+    use std::path::Path;
+
+    const CONTENTS: &str = r#"
+name = top level
+[server]
+host = example.com
+port = 8080
+enabled = true
+alias = www
+alias = web
+
+[server.tls]
+ratio = 0.5
+cert = "server.pem"
+"#;
+
+    fn parse(options: &[&str]) -> serde_json::Value {
+        let options =
+            ParserOptions::from_args(&options.iter().map(|o| o.to_string()).collect::<Vec<_>>())
+                .unwrap();
+        IniParser::new(&options)
+            .parse(&ParseContext::with_text(
+                Path::new("test.ini"),
+                &options,
+                CONTENTS,
+            ))
+            .unwrap()
+            .value
+    }
+
+    #[test]
+    fn default_options() {
+        assert_eq!(
+            parse(&[]),
+            json!({
+                "name": "top level",
+                "server": {
+                    "host": "example.com",
+                    "port": "8080",
+                    "enabled": "true",
+                    "alias": "web"
+                },
+                "server.tls": {
+                    "ratio": "0.5",
+                    "cert": "\"server.pem\""
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn all_options() {
+        assert_eq!(
+            parse(&[
+                "ini.nested_sections=true",
+                "ini.repeated_keys=true",
+                "ini.coerce_types=true"
+            ]),
+            json!({
+                "name": "top level",
+                "server": {
+                    "host": "example.com",
+                    "port": 8080,
+                    "enabled": true,
+                    "alias": ["www", "web"],
+                    "tls": {
+                        "ratio": 0.5,
+                        "cert": "server.pem"
+                    }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn repeated_key_warning() {
+        let options = ParserOptions::default();
+        let context = ParseContext::with_text(Path::new("test.ini"), &options, CONTENTS);
+        IniParser::new(&options).parse(&context).unwrap();
+        assert_eq!(
+            context.take_warnings(),
+            ["Key alias is repeated, keeping the last value"]
+        );
+    }
+
+    #[test]
+    fn separators_and_inline_comments() {
+        let options = ParserOptions::from_args(&["ini.coerce_types=true".to_string()]).unwrap();
+        let text = r#"
+[server] ; the web server
+host: example.com # the host name
+port = 8080 ; the port
+url = http://example.com/#anchor
+motd = "closed; try later" ; quoted
+note = 'a # b'
+empty =
+"#;
+        let result = IniParser::new(&options)
+            .parse(&ParseContext::with_text(
+                Path::new("test.ini"),
+                &options,
+                text,
+            ))
+            .unwrap()
+            .value;
+        assert_eq!(
+            result,
+            json!({
+                "server": {
+                    "host": "example.com",
+                    "port": 8080,
+                    "url": "http://example.com/#anchor",
+                    "motd": "closed; try later",
+                    "note": "a # b",
+                    "empty": ""
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        let options = ParserOptions::default();
+        let parser = IniParser::new(&options);
+        let parse = |text| {
+            parser.parse(&ParseContext::with_text(
+                Path::new("test.ini"),
+                &options,
+                text,
+            ))
+        };
+        assert!(parse("[section\nkey=value").is_err());
+        assert!(parse("[section]\nkey").is_err());
+    }
+}
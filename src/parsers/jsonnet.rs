@@ -0,0 +1,158 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Parser for Jsonnet files, which are evaluated into the JSON they produce.
+
+use super::{has_extension, Capabilities, FileParser, ParseContext, ParsedDocument, ParserOptions};
+use crate::errors::*;
+use jrsonnet_evaluator::error::LocError;
+use jrsonnet_evaluator::{EvaluationState, FileImportResolver, FuncVal, Val};
+use serde_json::{Map, Number, Value};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// File parser for Jsonnet files, `.jsonnet` and `.libsonnet`.
+///
+/// Each file is evaluated with the Jsonnet standard library, and returns the JSON value it
+/// evaluates to. Imports are resolved relative to the importing file, and then within the
+/// library directories given by the `jpath` option, separated as in the `PATH` environment
+/// variable. External variables are set with the `ext_str.<name>` and `ext_code.<name>`
+/// options, and if the file evaluates to a function, it is called with the top-level
+/// arguments set with the `tla_str.<name>` and `tla_code.<name>` options. Functions within
+/// the result are returned as their signature, e.g. `function(name, replicas)`, and the
+/// hidden fields of `.libsonnet` libraries are returned, as these are typically functions.
+pub struct JsonnetParser {
+    library_paths: Vec<PathBuf>,
+    ext_strs: BTreeMap<String, String>,
+    ext_codes: BTreeMap<String, String>,
+    tla_strs: BTreeMap<String, String>,
+    tla_codes: BTreeMap<String, String>,
+}
+
+impl JsonnetParser {
+    /// Create a new parser using the `jsonnet` parser options.
+    pub fn new(options: &ParserOptions) -> JsonnetParser {
+        JsonnetParser {
+            library_paths: options
+                .get("jsonnet", "jpath")
+                .map(|paths| std::env::split_paths(paths).collect())
+                .unwrap_or_default(),
+            ext_strs: options.prefixed("jsonnet", "ext_str"),
+            ext_codes: options.prefixed("jsonnet", "ext_code"),
+            tla_strs: options.prefixed("jsonnet", "tla_str"),
+            tla_codes: options.prefixed("jsonnet", "tla_code"),
+        }
+    }
+
+    /// Create an evaluator with the standard library, library paths and variables.
+    fn evaluator(&self) -> std::result::Result<EvaluationState, LocError> {
+        let state = EvaluationState::default();
+        state.with_stdlib();
+        state.set_import_resolver(Box::new(FileImportResolver {
+            library_paths: self.library_paths.to_owned(),
+        }));
+        for (name, value) in &self.ext_strs {
+            state.add_ext_str(name.as_str().into(), value.as_str().into());
+        }
+        for (name, code) in &self.ext_codes {
+            state.add_ext_code(name.as_str().into(), code.as_str().into())?;
+        }
+        for (name, value) in &self.tla_strs {
+            state.add_tla_str(name.as_str().into(), value.as_str().into());
+        }
+        for (name, code) in &self.tla_codes {
+            state.add_tla_code(name.as_str().into(), code.as_str().into())?;
+        }
+        Ok(state)
+    }
+
+    /// Convert an evaluated Jsonnet value into JSON, with hidden fields if `hidden` is set,
+    /// returning functions as their signature.
+    fn to_json(value: &Val, hidden: bool) -> std::result::Result<Value, LocError> {
+        Ok(match value {
+            Val::Null => Value::Null,
+            Val::Bool(b) => Value::Bool(*b),
+            Val::Str(s) => Value::String(s.to_string()),
+            Val::Num(n) if n.fract() == 0.0 && n.abs() < 2f64.powi(53) => {
+                Value::Number((*n as i64).into())
+            }
+            Val::Num(n) => Number::from_f64(*n).map_or(Value::Null, Value::Number),
+            Val::Arr(values) => Value::Array(
+                values
+                    .iter()
+                    .map(|v| JsonnetParser::to_json(&v?, hidden))
+                    .collect::<std::result::Result<_, _>>()?,
+            ),
+            Val::Obj(object) => {
+                let mut map = Map::new();
+                for key in object.fields_ex(hidden) {
+                    if let Some(v) = object.get(key.clone())? {
+                        map.insert(key.to_string(), JsonnetParser::to_json(&v, hidden)?);
+                    }
+                }
+                Value::Object(map)
+            }
+            Val::Func(function) => Value::String(match &**function {
+                FuncVal::Normal(desc) => format!(
+                    "function({})",
+                    desc.params
+                        .0
+                        .iter()
+                        .map(|p| p.0.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                _ => format!("function {}", function.name()),
+            }),
+        })
+    }
+}
+
+impl FileParser for JsonnetParser {
+    fn name(&self) -> &'static str {
+        "jsonnet"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["jsonnet", "libsonnet"],
+            options: &[
+                "jpath",
+                "ext_str.<name>",
+                "ext_code.<name>",
+                "tla_str.<name>",
+                "tla_code.<name>",
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let text = context.text()?;
+        let library = has_extension(context.path(), &["libsonnet"]);
+        let state = self
+            .evaluator()
+            .map_err(|e| format!("Invalid Jsonnet variable: {}", e.error()))?;
+        let value = state.run_in_state(|| {
+            let value = state.evaluate_snippet_raw(context.path().into(), text.into())?;
+            JsonnetParser::to_json(&state.with_tla(value)?, library)
+        });
+        match value {
+            Ok(value) => Ok(ParsedDocument::new(value)),
+            Err(e) => bail!(state.stringify_err(&e).trim().to_string()),
+        }
+    }
+}
@@ -0,0 +1,435 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Parsers for Apache Kafka broker and client configuration and Schema Registry exports.
+
+use super::kubernetes::REDACTED;
+use super::{
+    has_extension, Capabilities, FileParser, ParseContext, ParsedDocument, PropertiesParser,
+};
+use crate::errors::*;
+use java_properties::LineContent;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+/// Keys, any of which identify a properties file as Kafka configuration.
+const MARKERS: &[&str] = &[
+    "broker.id",
+    "node.id",
+    "process.roles",
+    "zookeeper.connect",
+    "bootstrap.servers",
+    "key.serializer",
+    "value.serializer",
+    "key.deserializer",
+    "value.deserializer",
+    "key.converter",
+];
+
+/// Keys, besides those beginning `ssl.`, `sasl.` or `security.`, which control
+/// authentication and authorisation.
+const SECURITY_KEYS: &[&str] = &[
+    "authorizer.class.name",
+    "super.users",
+    "allow.everyone.if.no.acl.found",
+    "principal.builder.class",
+    "listener.security.protocol.map",
+];
+
+/// Broker keys which set the defaults of topics.
+const TOPIC_DEFAULT_KEYS: &[&str] = &[
+    "num.partitions",
+    "default.replication.factor",
+    "min.insync.replicas",
+    "auto.create.topics.enable",
+    "delete.topic.enable",
+    "log.retention.hours",
+    "log.retention.ms",
+    "log.retention.bytes",
+    "log.cleanup.policy",
+    "compression.type",
+];
+
+/// Return whether a key holds a secret, whose value is redacted.
+fn is_secret(key: &str) -> bool {
+    key.ends_with("password") || key.ends_with("jaas.config") || key.ends_with("secret")
+}
+
+/// Return the values of a comma-separated list.
+fn split(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+/// File parser for Kafka broker, controller and client `.properties` files, such as
+/// `server.properties`, `producer.properties` or a Kafka Connect worker configuration.
+///
+/// Returns the `roles` of the configuration, its `properties` with secrets such as
+/// passwords and JAAS configuration redacted, the `bootstrap_servers`, `listeners` and
+/// `advertised_listeners` with their security protocols, the `topics` named by properties
+/// such as `offset.storage.topic`, the `topic_defaults` of a broker and the `security`
+/// settings relevant to authentication and ACLs.
+pub struct KafkaPropertiesParser {}
+
+impl KafkaPropertiesParser {
+    /// Return the roles of a configuration, from the properties it sets.
+    fn roles(properties: &BTreeMap<String, String>) -> Vec<&'static str> {
+        let has = |key: &str| properties.contains_key(key);
+        let process_roles = properties
+            .get("process.roles")
+            .map(|r| split(r))
+            .unwrap_or_default();
+        let mut roles = vec![];
+        if has("broker.id")
+            || has("zookeeper.connect")
+            || has("log.dirs")
+            || process_roles.iter().any(|r| r == "broker")
+        {
+            roles.push("broker");
+        }
+        if process_roles.iter().any(|r| r == "controller") {
+            roles.push("controller");
+        }
+        if has("key.converter") || has("value.converter") {
+            roles.push("connect");
+        }
+        if has("application.id") {
+            roles.push("streams");
+        }
+        if has("key.serializer") || has("value.serializer") {
+            roles.push("producer");
+        }
+        if has("key.deserializer") || has("value.deserializer") {
+            roles.push("consumer");
+        }
+        if roles.is_empty() {
+            roles.push("client");
+        }
+        roles
+    }
+
+    /// Return the listeners of a `listeners` or `advertised.listeners` property, with the
+    /// security protocol of each from the `listener.security.protocol.map`, if any.
+    fn listeners(value: Option<&String>, protocols: &BTreeMap<String, String>) -> Vec<Value> {
+        value
+            .map(|v| split(v))
+            .unwrap_or_default()
+            .iter()
+            .map(|listener| {
+                let (name, address) = listener.split_once("://").unwrap_or(("", listener));
+                let (host, port) = address.rsplit_once(':').unwrap_or((address, ""));
+                let protocol = protocols.get(name).map(|p| p.as_str()).unwrap_or(name);
+                json!({
+                    "name": name,
+                    "host": host,
+                    "port": port.parse::<u64>().ok(),
+                    "protocol": protocol,
+                })
+            })
+            .collect()
+    }
+}
+
+impl FileParser for KafkaPropertiesParser {
+    fn name(&self) -> &'static str {
+        "kafka-properties"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["properties"],
+            ..Default::default()
+        }
+    }
+
+    fn can_parse(&self, context: &ParseContext) -> bool {
+        has_extension(context.path(), self.capabilities().extensions)
+            && context.text().is_ok_and(|c| {
+                c.lines().any(|l| {
+                    l.split(['=', ':'])
+                        .next()
+                        .is_some_and(|k| MARKERS.contains(&k.trim()))
+                })
+            })
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let properties: BTreeMap<String, String> = PropertiesParser::lines(context.text()?)?
+            .into_iter()
+            .filter_map(|(_, l)| match l {
+                LineContent::KVPair(k, v) => Some((k, v)),
+                LineContent::Comment(_) => None,
+            })
+            .collect();
+        let value = |key: &str, v: &str| match is_secret(key) {
+            true => Value::String(REDACTED.to_string()),
+            false => Value::String(v.to_string()),
+        };
+
+        let protocols: BTreeMap<String, String> = properties
+            .get("listener.security.protocol.map")
+            .map(|m| split(m))
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|entry| entry.split_once(':'))
+            .map(|(name, protocol)| (name.trim().to_string(), protocol.trim().to_string()))
+            .collect();
+
+        let topics: Vec<Value> = properties
+            .iter()
+            .filter(|(k, _)| {
+                ["topic", "topics"].contains(&k.as_str())
+                    || k.ends_with(".topic")
+                    || k.ends_with(".topics")
+                    || k.ends_with(".topic.name")
+            })
+            .flat_map(|(k, v)| {
+                split(v)
+                    .into_iter()
+                    .map(move |name| json!({"name": name, "property": k}))
+            })
+            .collect();
+
+        let security: Map<String, Value> = properties
+            .iter()
+            .filter(|(k, _)| {
+                ["ssl.", "sasl.", "security."]
+                    .iter()
+                    .any(|prefix| k.starts_with(prefix))
+                    || k.contains(".ssl.")
+                    || k.contains(".sasl.")
+                    || SECURITY_KEYS.contains(&k.as_str())
+            })
+            .map(|(k, v)| match k.as_str() {
+                "super.users" => (
+                    k.to_owned(),
+                    json!(v.split(';').map(|u| u.trim()).collect::<Vec<_>>()),
+                ),
+                _ => (k.to_owned(), value(k, v)),
+            })
+            .collect();
+
+        let roles = KafkaPropertiesParser::roles(&properties);
+        let topic_defaults: Map<String, Value> = match roles.contains(&"broker") {
+            true => TOPIC_DEFAULT_KEYS
+                .iter()
+                .filter_map(|k| properties.get(*k).map(|v| (k.to_string(), value(k, v))))
+                .collect(),
+            false => Map::new(),
+        };
+
+        let mut metadata = Map::new();
+        metadata.insert("roles".to_string(), json!(roles));
+        Ok(ParsedDocument {
+            value: json!({
+                "roles": roles,
+                "properties": properties
+                    .iter()
+                    .map(|(k, v)| (k.to_owned(), value(k, v)))
+                    .collect::<Map<_, _>>(),
+                "bootstrap_servers": properties
+                    .get("bootstrap.servers")
+                    .map(|s| split(s))
+                    .unwrap_or_default(),
+                "listeners": KafkaPropertiesParser::listeners(properties.get("listeners"), &protocols),
+                "advertised_listeners": KafkaPropertiesParser::listeners(
+                    properties.get("advertised.listeners"),
+                    &protocols
+                ),
+                "topics": topics,
+                "topic_defaults": topic_defaults,
+                "security": security,
+            }),
+            metadata,
+            ..Default::default()
+        })
+    }
+}
+
+/// File parser for exports of Confluent Schema Registry subjects: a JSON array of the
+/// schema versions returned by `GET /subjects/{subject}/versions/{version}`, each with a
+/// `subject`, `version`, `id`, `schema` and optional `schemaType`, or an object with such
+/// an array as its `schemas` or `subjects`.
+///
+/// Returns the `subjects`, each with its `topic` and whether it is the `key` or `value`
+/// schema of the topic under the default topic name strategy, and its `versions` in order
+/// with the parsed `schema` and its top-level `fields`, and the `topics` with the subjects
+/// of their keys and values.
+pub struct SchemaRegistryParser {}
+
+impl SchemaRegistryParser {
+    /// Return the name, type and documentation of each top-level field of an Avro record or
+    /// JSON Schema object.
+    fn fields(schema: &Value) -> Vec<Value> {
+        let describe = |t: &Value| match t {
+            Value::String(s) => s.to_owned(),
+            Value::Array(union) => union
+                .iter()
+                .map(|u| match u {
+                    Value::String(s) => s.to_owned(),
+                    other => other
+                        .get("type")
+                        .and_then(|t| t.as_str())
+                        .unwrap_or("complex")
+                        .to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(" | "),
+            other => other
+                .get("type")
+                .and_then(|t| t.as_str())
+                .unwrap_or("complex")
+                .to_string(),
+        };
+        if let Some(Value::Array(fields)) = schema.get("fields") {
+            fields
+                .iter()
+                .map(|f| {
+                    json!({
+                        "name": f.get("name"),
+                        "type": f.get("type").map(describe),
+                        "doc": f.get("doc"),
+                        "default": f.get("default"),
+                    })
+                })
+                .collect()
+        } else if let Some(Value::Object(properties)) = schema.get("properties") {
+            properties
+                .iter()
+                .map(|(name, p)| {
+                    json!({
+                        "name": name,
+                        "type": p.get("type").map(describe),
+                        "doc": p.get("description"),
+                        "default": p.get("default"),
+                    })
+                })
+                .collect()
+        } else {
+            vec![]
+        }
+    }
+}
+
+impl FileParser for SchemaRegistryParser {
+    fn name(&self) -> &'static str {
+        "schema-registry"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["json"],
+            ..Default::default()
+        }
+    }
+
+    fn can_parse(&self, context: &ParseContext) -> bool {
+        has_extension(context.path(), self.capabilities().extensions)
+            && context
+                .text()
+                .is_ok_and(|c| c.contains("\"subject\"") && c.contains("\"schema\""))
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let export: Value = serde_json::from_str(context.text()?)?;
+        let versions = match &export {
+            Value::Array(versions) => versions,
+            Value::Object(o) => match o.get("schemas").or_else(|| o.get("subjects")) {
+                Some(Value::Array(versions)) => versions,
+                _ => bail!("Schema Registry export has no schemas"),
+            },
+            _ => bail!("Schema Registry export is not an array or object"),
+        };
+
+        let mut subjects: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+        for version in versions {
+            let subject = version
+                .get("subject")
+                .and_then(|s| s.as_str())
+                .ok_or("Schema version has no subject")?;
+            let schema_type = version
+                .get("schemaType")
+                .and_then(|t| t.as_str())
+                .unwrap_or("AVRO");
+            let text = version
+                .get("schema")
+                .and_then(|s| s.as_str())
+                .ok_or_else(|| format!("Schema version of {} has no schema", subject))?;
+            let schema = match schema_type {
+                "PROTOBUF" => Value::String(text.to_string()),
+                _ => serde_json::from_str(text).unwrap_or_else(|e| {
+                    context.warn(format!("Could not parse schema of {}: {}", subject, e));
+                    Value::String(text.to_string())
+                }),
+            };
+            subjects
+                .entry(subject.to_string())
+                .or_default()
+                .push(json!({
+                    "version": version.get("version"),
+                    "id": version.get("id"),
+                    "type": schema_type,
+                    "fields": SchemaRegistryParser::fields(&schema),
+                    "schema": schema,
+                    "references": version.get("references").cloned().unwrap_or(json!([])),
+                }));
+        }
+
+        let mut topics: BTreeMap<String, Map<String, Value>> = BTreeMap::new();
+        let subjects: Vec<Value> = subjects
+            .into_iter()
+            .map(|(subject, mut versions)| {
+                versions.sort_by_key(|v| v["version"].as_u64());
+                let (topic, kind) = match subject.rsplit_once('-') {
+                    Some((topic, kind)) if kind == "key" || kind == "value" => {
+                        (Some(topic.to_string()), Some(kind.to_string()))
+                    }
+                    _ => (None, None),
+                };
+                if let (Some(topic), Some(kind)) = (&topic, &kind) {
+                    topics
+                        .entry(topic.to_owned())
+                        .or_default()
+                        .insert(kind.to_owned(), json!(subject));
+                }
+                json!({
+                    "subject": subject,
+                    "topic": topic,
+                    "kind": kind,
+                    "latest": versions.last().map(|v| v["version"].to_owned()),
+                    "versions": versions,
+                })
+            })
+            .collect();
+
+        Ok(ParsedDocument::new(json!({
+            "subjects": subjects,
+            "topics": topics
+                .into_iter()
+                .map(|(name, kinds)| {
+                    json!({
+                        "name": name,
+                        "key": kinds.get("key"),
+                        "value": kinds.get("value"),
+                    })
+                })
+                .collect::<Vec<_>>(),
+        })))
+    }
+}
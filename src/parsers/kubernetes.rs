@@ -0,0 +1,231 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Parser for Kubernetes `ConfigMap` and `Secret` manifests.
+
+use super::sops::{self, ENCRYPTED};
+use super::{has_extension, Capabilities, FileParser, ParseContext, ParsedDocument, ParserOptions};
+use crate::errors::*;
+use base64::Engine;
+use log::trace;
+use serde_json::{Map, Value};
+use std::path::Path;
+
+/// Placeholder value used in place of redacted secret values.
+pub const REDACTED: &str = "**redacted**";
+
+/// Kinds of Kubernetes resource whose data entries can be expanded.
+const KINDS: [&str; 2] = ["ConfigMap", "Secret"];
+
+/// File parser for Kubernetes `ConfigMap` and `Secret` manifests.
+///
+/// Each `data` (and `stringData`) entry is run through the other kvasir parsers,
+/// chosen using the entry key as a file name, so that an `application.properties`
+/// entry is parsed as Java properties. Parsed entries are replaced with an object
+/// containing the name of the parser and the parsed contents, whilst entries that
+/// cannot be parsed are left as strings. `Secret` data entries are base64-decoded
+/// first and, if the `redact_secrets` option is set, all secret values are redacted
+/// whilst keeping their structure. Manifests encrypted with SOPS are not decrypted: their
+/// encrypted entries are masked, and their `sops` settings are removed.
+pub struct KubernetesParser {
+    redact_secrets: bool,
+}
+
+impl KubernetesParser {
+    /// Create a new parser using the `kubernetes` parser options.
+    pub fn new(options: &ParserOptions) -> KubernetesParser {
+        KubernetesParser {
+            redact_secrets: options.flag("kubernetes", "redact_secrets"),
+        }
+    }
+
+    /// Parse a single data entry, using the key as a file name to select a parser
+    /// configured with the options of the manifest being parsed.
+    fn expand_entry(&self, key: &str, value: &str, secret: bool, options: &ParserOptions) -> Value {
+        let path = Path::new(key);
+        let redact_value = |v: Value| {
+            if secret && self.redact_secrets {
+                redact(v)
+            } else {
+                v
+            }
+        };
+
+        let context = ParseContext::with_text(path, options, value);
+        super::parsers_with_options(options)
+            .iter()
+            .filter(|p| p.name() != self.name() && p.can_parse(&context))
+            .find_map(|p| match p.parse(&context) {
+                Ok(document) => Some(serde_json::json!({
+                    "parser": p.name(),
+                    "contents": redact_value(document.value),
+                })),
+                Err(e) => {
+                    trace!("  could not parse entry {} with {}: {}", key, p.name(), e);
+                    None
+                }
+            })
+            .unwrap_or_else(|| redact_value(Value::String(value.to_string())))
+    }
+
+    /// Expand all entries of a data map, base64-decoding entries if required.
+    fn expand_data(
+        &self,
+        data: &Map<String, Value>,
+        encoded: bool,
+        secret: bool,
+        options: &ParserOptions,
+    ) -> Result<Value> {
+        let mut expanded = Map::new();
+        for (key, value) in data {
+            if value.as_str().is_some_and(sops::is_encrypted) {
+                expanded.insert(key.to_owned(), Value::String(ENCRYPTED.to_string()));
+                continue;
+            }
+            let text = match (value.as_str(), encoded) {
+                (Some(v), true) => {
+                    String::from_utf8(base64::engine::general_purpose::STANDARD.decode(v.trim())?)
+                        .map_err(|e| format!("Secret entry {} is not valid UTF-8: {}", key, e))?
+                }
+                (Some(v), false) => v.to_string(),
+                (None, _) => bail!(format!("Data entry {} is not a string", key)),
+            };
+            expanded.insert(
+                key.to_owned(),
+                self.expand_entry(key, &text, secret, options),
+            );
+        }
+        Ok(Value::Object(expanded))
+    }
+}
+
+impl FileParser for KubernetesParser {
+    fn name(&self) -> &'static str {
+        "kubernetes"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["yaml", "yml"],
+            documents: true,
+            options: &["redact_secrets"],
+            ..Default::default()
+        }
+    }
+
+    fn can_parse(&self, context: &ParseContext) -> bool {
+        has_extension(context.path(), self.capabilities().extensions)
+            && context.text().is_ok_and(|c| {
+                c.lines().any(|l| {
+                    l.strip_prefix("kind:")
+                        .is_some_and(|k| KINDS.contains(&k.trim()))
+                })
+            })
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let manifest: Value = serde_yaml::from_str(context.text()?)?;
+        let kind = manifest
+            .get("kind")
+            .and_then(|k| k.as_str())
+            .unwrap_or_default()
+            .to_string();
+        match self.expand_manifest(manifest, context.options())? {
+            Some(document) => Ok(document),
+            None => bail!(format!("Unsupported Kubernetes kind '{}'", kind)),
+        }
+    }
+
+    /// Parse each `ConfigMap` and `Secret` manifest in a multi-document YAML file as a
+    /// separate document named after its kind and name, skipping manifests of other kinds
+    /// with a warning.
+    fn parse_documents(&self, context: &ParseContext) -> Result<Vec<ParsedDocument>> {
+        let mut documents = vec![];
+        for text in super::yaml_documents(context.text()?) {
+            let manifest: Value = serde_yaml::from_str(text)?;
+            let kind = manifest
+                .get("kind")
+                .and_then(|k| k.as_str())
+                .unwrap_or_default()
+                .to_string();
+            match self.expand_manifest(manifest, context.options())? {
+                Some(document) => documents.push(document),
+                None => context.warn(format!("Skipped unsupported Kubernetes kind '{}'", kind)),
+            }
+        }
+        if documents.is_empty() {
+            bail!("No ConfigMap or Secret manifests found")
+        }
+        Ok(documents)
+    }
+}
+
+impl KubernetesParser {
+    /// Expand the data entries of a single manifest, returning nothing if the manifest is
+    /// not of a supported kind.
+    fn expand_manifest(
+        &self,
+        mut manifest: Value,
+        options: &ParserOptions,
+    ) -> Result<Option<ParsedDocument>> {
+        let kind = match manifest.get("kind").and_then(|k| k.as_str()) {
+            Some(kind) if KINDS.contains(&kind) => kind.to_string(),
+            _ => return Ok(None),
+        };
+        let secret = kind == "Secret";
+        let name = manifest
+            .pointer("/metadata/name")
+            .and_then(|n| n.as_str())
+            .map(|n| format!("{}/{}", kind, n));
+
+        let encrypted = sops::metadata(&manifest).is_some();
+        if let Some(object) = manifest.as_object_mut() {
+            if encrypted {
+                object.remove("sops");
+            }
+            // Secret `data` entries are base64-encoded, whereas `stringData` is plain text.
+            for (field, encoded) in [("data", secret), ("stringData", false)] {
+                if let Some(Value::Object(data)) = object.get(field) {
+                    let expanded = self.expand_data(data, encoded, secret, options)?;
+                    object.insert(field.to_string(), expanded);
+                }
+            }
+        }
+
+        Ok(Some(ParsedDocument {
+            name,
+            value: manifest,
+            metadata: Map::from_iter(
+                [
+                    ("kind", Some(kind)),
+                    ("encryption", encrypted.then(|| "sops".to_string())),
+                ]
+                .into_iter()
+                .filter_map(|(k, v)| Some((k.to_string(), Value::String(v?)))),
+            ),
+            ..Default::default()
+        }))
+    }
+}
+
+/// Replace all leaf values within a JSON value with a redaction placeholder.
+fn redact(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(map.into_iter().map(|(k, v)| (k, redact(v))).collect()),
+        Value::Array(values) => Value::Array(values.into_iter().map(redact).collect()),
+        _ => Value::String(REDACTED.to_string()),
+    }
+}
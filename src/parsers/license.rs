@@ -0,0 +1,502 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Identification of licenses, from license files such as `LICENSE` and `COPYING` and from
+//! `SPDX-License-Identifier` tags and license notices in the headers of source files.
+//!
+//! Licenses are identified by the distinctive phrases of their texts and notices, such as
+//! "Apache License, Version 2.0", rather than by comparing full texts, so a license file with
+//! a modified text is identified by its title.
+
+use super::directory::DirectoryParser;
+use super::{Capabilities, FileParser, ParseContext, ParsedDocument};
+use crate::errors::*;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use walkdir::WalkDir;
+
+/// A license which can be identified.
+struct Known {
+    /// The SPDX identifier of the license.
+    id: &'static str,
+    name: &'static str,
+    /// The phrase which begins the title or notice of the license.
+    title: &'static str,
+    /// A phrase, such as the version, which must closely follow the title.
+    near: Option<&'static str>,
+    /// A phrase which must appear anywhere in the text.
+    requires: Option<&'static str>,
+}
+
+/// The identifiable licenses. Where titles match at the same position, the first license
+/// listed is chosen.
+const LICENSES: &[Known] = &[
+    Known {
+        id: "Apache-2.0",
+        name: "Apache License 2.0",
+        title: "apache license",
+        near: Some("version 2.0"),
+        requires: None,
+    },
+    Known {
+        id: "MIT",
+        name: "MIT License",
+        title: "permission is hereby granted, free of charge",
+        near: None,
+        requires: None,
+    },
+    Known {
+        id: "MIT",
+        name: "MIT License",
+        title: "mit license",
+        near: None,
+        requires: None,
+    },
+    Known {
+        id: "AGPL-3.0",
+        name: "GNU Affero General Public License v3.0",
+        title: "gnu affero general public license",
+        near: Some("version 3"),
+        requires: None,
+    },
+    Known {
+        id: "LGPL-3.0",
+        name: "GNU Lesser General Public License v3.0",
+        title: "gnu lesser general public license",
+        near: Some("version 3"),
+        requires: None,
+    },
+    Known {
+        id: "LGPL-2.1",
+        name: "GNU Lesser General Public License v2.1",
+        title: "gnu lesser general public license",
+        near: Some("version 2.1"),
+        requires: None,
+    },
+    Known {
+        id: "GPL-3.0",
+        name: "GNU General Public License v3.0",
+        title: "gnu general public license",
+        near: Some("version 3"),
+        requires: None,
+    },
+    Known {
+        id: "GPL-2.0",
+        name: "GNU General Public License v2.0",
+        title: "gnu general public license",
+        near: Some("version 2"),
+        requires: None,
+    },
+    Known {
+        id: "MPL-2.0",
+        name: "Mozilla Public License 2.0",
+        title: "mozilla public license",
+        near: Some("2.0"),
+        requires: None,
+    },
+    Known {
+        id: "EPL-2.0",
+        name: "Eclipse Public License 2.0",
+        title: "eclipse public license",
+        near: Some("2.0"),
+        requires: None,
+    },
+    Known {
+        id: "EPL-1.0",
+        name: "Eclipse Public License 1.0",
+        title: "eclipse public license",
+        near: Some("1.0"),
+        requires: None,
+    },
+    Known {
+        id: "BSL-1.0",
+        name: "Boost Software License 1.0",
+        title: "boost software license",
+        near: Some("1.0"),
+        requires: None,
+    },
+    Known {
+        id: "BSD-3-Clause",
+        name: "BSD 3-Clause License",
+        title: "redistribution and use in source and binary forms",
+        near: None,
+        requires: Some("neither the name"),
+    },
+    Known {
+        id: "BSD-2-Clause",
+        name: "BSD 2-Clause License",
+        title: "redistribution and use in source and binary forms",
+        near: None,
+        requires: None,
+    },
+    Known {
+        id: "ISC",
+        name: "ISC License",
+        title: "permission to use, copy, modify, and",
+        near: None,
+        requires: None,
+    },
+    Known {
+        id: "Unlicense",
+        name: "The Unlicense",
+        title: "this is free and unencumbered software",
+        near: None,
+        requires: None,
+    },
+    Known {
+        id: "CC0-1.0",
+        name: "Creative Commons Zero v1.0 Universal",
+        title: "cc0 1.0 universal",
+        near: None,
+        requires: None,
+    },
+];
+
+/// The distance within which the phrase following a title must begin.
+const NEAR: usize = 120;
+
+/// The number of bytes at the start of a source file which are searched for a license.
+const HEADER_BYTES: u64 = 4096;
+
+/// The marker of an SPDX license identifier.
+const SPDX_TAG: &str = "SPDX-License-Identifier:";
+
+/// The names, in upper case and without extension, of license files.
+const LICENSE_NAMES: &[&str] = &["LICENSE", "LICENCE", "COPYING", "UNLICENSE"];
+
+/// The extensions of license files, besides none at all.
+const LICENSE_EXTENSIONS: &[&str] = &["md", "txt", "rst"];
+
+/// The extensions of source files whose headers are scanned.
+const SOURCE_EXTENSIONS: &[&str] = &[
+    "rs", "java", "kt", "scala", "groovy", "go", "py", "rb", "php", "js", "jsx", "ts", "tsx",
+    "mjs", "c", "h", "cc", "cpp", "hpp", "cs", "swift", "sh", "bash", "ps1", "sql", "tf", "hcl",
+    "proto", "css", "scss", "vue", "svelte",
+];
+
+/// Directories which are not scanned, besides hidden directories.
+const SKIPPED_DIRECTORIES: &[&str] = &["target", "node_modules", "vendor", "build", "dist"];
+
+/// Lower-case text and replace punctuation and comment markers with spaces, collapsing
+/// whitespace, so that phrases can be found regardless of line breaks and comment style.
+fn normalise(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            c if c.is_alphanumeric() || ".,-+()".contains(c) => c.to_ascii_lowercase(),
+            _ => ' ',
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Identify the license of a text, returning its SPDX identifier and name. The license
+/// whose title appears first is chosen, so that a license which refers to another, such as
+/// the LGPL referring to the GPL, is identified correctly.
+fn identify(text: &str) -> Option<(&'static str, &'static str)> {
+    let text = normalise(text);
+    LICENSES
+        .iter()
+        .filter_map(|known| {
+            let found = text.match_indices(known.title).map(|(i, _)| i).find(|i| {
+                known.near.is_none_or(|near| {
+                    let start = i + known.title.len();
+                    let mut end = (start + NEAR).min(text.len());
+                    while !text.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    text.get(start..end)
+                        .is_some_and(|following| following.contains(near))
+                })
+            })?;
+            known
+                .requires
+                .is_none_or(|r| text.contains(r))
+                .then_some((found, (known.id, known.name)))
+        })
+        .min_by_key(|(position, _)| *position)
+        .map(|(_, license)| license)
+}
+
+/// Return the SPDX license expression of an `SPDX-License-Identifier` tag in some text.
+fn spdx_expression(text: &str) -> Option<String> {
+    let (_, rest) = text.split_once(SPDX_TAG)?;
+    // The expression ends with the line or with the end of a comment on the same line
+    let expression: String = rest
+        .chars()
+        .take_while(|c| c.is_alphanumeric() || " -.+():".contains(*c))
+        .collect();
+    let expression = expression.trim().trim_end_matches('-').trim();
+    (!expression.is_empty()).then(|| expression.to_string())
+}
+
+/// Return the copyright notices in a text.
+fn copyrights(text: &str) -> Vec<String> {
+    text.lines()
+        .map(|l| l.trim_matches(|c: char| c.is_whitespace() || "*#/;-".contains(c)))
+        .filter(|l| {
+            let lower = l.to_lowercase();
+            (lower.starts_with("copyright") && !lower.starts_with("copyright notice"))
+                || lower.starts_with("(c)")
+                || lower.starts_with('©')
+        })
+        .map(String::from)
+        .collect()
+}
+
+/// Return whether a file name is that of a license file, such as `LICENSE`, `COPYING.md`
+/// or `LICENSE-MIT`.
+fn is_license_file(name: &str) -> bool {
+    let upper = name.to_uppercase();
+    let (stem, extension) = match upper.rsplit_once('.') {
+        Some((stem, extension)) => (stem, Some(extension.to_lowercase())),
+        None => (upper.as_str(), None),
+    };
+    extension.is_none_or(|e| LICENSE_EXTENSIONS.contains(&e.as_str()))
+        && LICENSE_NAMES.iter().any(|n| {
+            stem.strip_prefix(n)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(['-', '_']))
+        })
+}
+
+/// Describe the license of a license file's text.
+fn describe(text: &str) -> Value {
+    let (license, name) = match spdx_expression(text) {
+        Some(expression) => (Some(expression), None),
+        None => match identify(text) {
+            Some((id, name)) => (Some(id.to_string()), Some(name)),
+            None => (None, None),
+        },
+    };
+    json!({
+        "license": license,
+        "name": name,
+        "copyright": copyrights(text),
+    })
+}
+
+/// File parser for license files, such as `LICENSE`, `LICENCE.md`, `COPYING` or
+/// `LICENSE-APACHE`.
+///
+/// Returns the SPDX identifier of the `license`, or the expression of an
+/// `SPDX-License-Identifier` tag in the file, its `name` and the `copyright` notices of the
+/// file. The license is `null`, with a warning, if it is not identified.
+pub struct LicenseFileParser {}
+
+impl FileParser for LicenseFileParser {
+    fn name(&self) -> &'static str {
+        "license"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: LICENSE_EXTENSIONS,
+            ..Default::default()
+        }
+    }
+
+    fn can_parse(&self, context: &ParseContext) -> bool {
+        context
+            .path()
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(is_license_file)
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let value = describe(context.text()?);
+        if value["license"].is_null() {
+            context.warn("License is not identified");
+        }
+        Ok(ParsedDocument {
+            name: value["license"].as_str().map(String::from),
+            value,
+            ..Default::default()
+        })
+    }
+}
+
+/// Directory parser scanning a project for licenses.
+///
+/// Returns the `license_files` of the directory, each with its `path` and the `license`,
+/// `name` and `copyright` found as by the `license` parser, the source `files` beneath the
+/// directory whose headers declare a license, with the `license` and whether its `source`
+/// is an `spdx` tag or a license notice in the `header`, the `unlicensed` source files
+/// which declare no license and a `summary` of the number of `files` with each license.
+/// Hidden directories and build output and dependency directories, such as `target` and
+/// `node_modules`, are not scanned.
+pub struct LicenseScanner {}
+
+impl LicenseScanner {
+    /// Read the start of a source file, in which a license header is expected.
+    fn header(path: &std::path::Path) -> Result<String> {
+        let mut bytes = vec![];
+        File::open(path)?
+            .take(HEADER_BYTES)
+            .read_to_end(&mut bytes)?;
+        Ok(String::from_utf8_lossy(&bytes).to_string())
+    }
+}
+
+impl DirectoryParser for LicenseScanner {
+    fn name(&self) -> &'static str {
+        "licenses"
+    }
+
+    fn markers(&self) -> &'static [&'static str] {
+        &[
+            "LICENSE",
+            "LICENSE.md",
+            "LICENSE.txt",
+            "LICENCE",
+            "LICENCE.md",
+            "LICENCE.txt",
+            "COPYING",
+            "UNLICENSE",
+        ]
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let dir = context.path();
+        let mut license_files = vec![];
+        let mut files = vec![];
+        let mut unlicensed = vec![];
+        let mut summary: BTreeMap<String, usize> = BTreeMap::new();
+        let walker = WalkDir::new(dir)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|e| {
+                let name = e.file_name().to_string_lossy();
+                e.depth() == 0
+                    || !e.file_type().is_dir()
+                    || !(name.starts_with('.') || SKIPPED_DIRECTORIES.contains(&name.as_ref()))
+            });
+        for entry in walker
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+        {
+            let path = entry.path();
+            let relative = path
+                .strip_prefix(dir)
+                .unwrap_or(path)
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let name = entry.file_name().to_string_lossy();
+            if entry.depth() == 1 && is_license_file(&name) {
+                match std::fs::read_to_string(path) {
+                    Ok(text) => {
+                        let mut license = describe(&text);
+                        license["path"] = json!(relative);
+                        license_files.push(license);
+                    }
+                    Err(e) => context.warn(format!("Could not read {}: {}", relative, e)),
+                }
+            } else if super::has_extension(path, SOURCE_EXTENSIONS) {
+                let header = match LicenseScanner::header(path) {
+                    Ok(header) => header,
+                    Err(e) => {
+                        context.warn(format!("Could not read {}: {}", relative, e));
+                        continue;
+                    }
+                };
+                let found = match spdx_expression(&header) {
+                    Some(expression) => Some((expression, "spdx")),
+                    None => identify(&header).map(|(id, _)| (id.to_string(), "header")),
+                };
+                match found {
+                    Some((license, source)) => {
+                        *summary.entry(license.to_owned()).or_default() += 1;
+                        files.push(json!({"path": relative, "license": license, "source": source}));
+                    }
+                    None => unlicensed.push(relative),
+                }
+            }
+        }
+
+        Ok(ParsedDocument {
+            name: dir.file_name().map(|n| n.to_string_lossy().to_string()),
+            value: json!({
+                "license_files": license_files,
+                "files": files,
+                "unlicensed": unlicensed,
+                "summary": summary
+                    .into_iter()
+                    .map(|(license, files)| json!({"license": license, "files": files}))
+                    .collect::<Vec<_>>(),
+            }),
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::parsers::license::{identify, is_license_file, spdx_expression};
+
+    #[test]
+    fn identify_licenses() {
+        assert_eq!(
+            identify("Licensed under the Apache License,\n * Version 2.0 (the \"License\")"),
+            Some(("Apache-2.0", "Apache License 2.0"))
+        );
+        assert_eq!(
+            identify("GNU LESSER GENERAL PUBLIC LICENSE\nVersion 2.1, February 1999\n\nThe GNU General Public License, version 2"),
+            Some(("LGPL-2.1", "GNU Lesser General Public License v2.1"))
+        );
+        assert_eq!(
+            identify(
+                "Redistribution and use in source and binary forms, with or without modification"
+            ),
+            Some(("BSD-2-Clause", "BSD 2-Clause License"))
+        );
+        assert_eq!(identify("All rights reserved."), None);
+    }
+
+    #[test]
+    fn spdx() {
+        assert_eq!(
+            spdx_expression("/* SPDX-License-Identifier: (MIT OR Apache-2.0) */\n").as_deref(),
+            Some("(MIT OR Apache-2.0)")
+        );
+        assert_eq!(
+            spdx_expression("<!-- SPDX-License-Identifier: GPL-2.0-or-later -->").as_deref(),
+            Some("GPL-2.0-or-later")
+        );
+        assert_eq!(spdx_expression("SPDX-License-Identifier: \";"), None);
+    }
+
+    #[test]
+    fn license_files() {
+        for name in [
+            "LICENSE",
+            "LICENCE.md",
+            "COPYING",
+            "LICENSE-MIT",
+            "license.txt",
+        ] {
+            assert!(is_license_file(name), "{}", name);
+        }
+        for name in ["license.rs", "LICENSES", "licensing.md"] {
+            assert!(!is_license_file(name), "{}", name);
+        }
+    }
+}
@@ -15,69 +15,309 @@
 */
 
 use super::errors::*;
+use crate::references::ExternalRef;
 use hocon::HoconLoader;
+use java_properties::{LineContent, PropertiesIter};
 use log::{trace, warn};
 use openapiv3::OpenAPI;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Map, Value};
+use sqlparser::ast::Statement;
 use sqlparser::dialect::{
     Dialect, GenericDialect, HiveDialect, MsSqlDialect, MySqlDialect, PostgreSqlDialect,
     SQLiteDialect,
 };
-use sqlparser::parser::{Parser, ParserError};
-use std::fs;
+use sqlparser::parser::Parser;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::{Path, PathBuf};
 
+mod apache;
+mod codeowners;
+mod collection;
+mod comments;
+mod conventions;
+mod dhall;
+mod directory;
+mod document;
+mod dotenv;
+mod hashicorp;
+mod ini;
+mod jsonnet;
+mod kafka;
+mod kubernetes;
+mod license;
+mod protobuf;
+mod sops;
+mod spring;
+mod terraform;
+mod terraform_config;
+mod terragrunt;
+mod timeout;
+mod updates;
+
+pub use apache::ApacheConfParser;
+pub use codeowners::{CodeOwners, CodeOwnersParser};
+pub use collection::CollectionParser;
+pub use conventions::{EditorConfigParser, GitAttributesParser, GitConfigParser};
+pub use dhall::DhallParser;
+pub use directory::{directory_parsers, DirectoryParser};
+pub use document::{Capabilities, ParseContext, ParsedDocument};
+pub use dotenv::EnvFileParser;
+pub use hashicorp::{ConsulConfigParser, VaultPolicyParser};
+pub use ini::IniParser;
+pub use jsonnet::JsonnetParser;
+pub use kafka::{KafkaPropertiesParser, SchemaRegistryParser};
+pub use kubernetes::KubernetesParser;
+pub use license::{LicenseFileParser, LicenseScanner};
+pub use protobuf::ProtoParser;
+pub use sops::{is_encrypted, SopsParser};
+pub use spring::SpringBootParser;
+pub use terraform::TerraformStateParser;
+pub use terraform_config::HclParser;
+pub use terragrunt::TerragruntParser;
+pub use timeout::with_timeout;
+pub use updates::{DependabotParser, RenovateParser};
+
 /// Return whether a path has one of the list of specified extensions.
 fn has_extension(path: &Path, extensions: &[&str]) -> bool {
     match path.extension() {
-        Some(ext) => ext.to_str().map_or(false, |e| extensions.contains(&e)),
+        Some(ext) => ext.to_str().is_some_and(|e| extensions.contains(&e)),
         None => false,
     }
 }
 
 /// Interface to common functionality for file parsers.
-pub trait FileParser {
+///
+/// Parsers must be thread-safe, so that they can be used with parsing time limits.
+pub trait FileParser: Send + Sync {
     /// Return the name of the parser.
     fn name(&self) -> &'static str;
 
-    /// Return whether the specified path is able to be parsed by this parser.
+    /// Return what the parser is able to parse and return.
+    fn capabilities(&self) -> Capabilities;
+
+    /// Return whether a file is able to be parsed by this parser, by default if it has
+    /// one of the extensions in the parser's capabilities.
     ///
     /// This check is not intended to be expensive. Whilst the contents of the
     /// file are available for use if required, use the path alone wherever
     /// possible to minimise IO.
-    fn can_parse(&self, path: &Path, contents: Result<&str>) -> bool;
+    fn can_parse(&self, context: &ParseContext) -> bool {
+        has_extension(context.path(), self.capabilities().extensions)
+    }
 
-    /// Parse a file and return a JSON result or an explanatory error.
-    fn parse(&self, path: &Path, contents: Result<&str>) -> Result<Value>;
+    /// Parse a file and return the parsed document or an explanatory error.
+    ///
+    /// Descriptions of keys are taken from the comments in the file, keyed by the dotted
+    /// path of the key, by parsers which are aware of comments and for which comment
+    /// extraction has been enabled.
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument>;
+
+    /// Parse a file which may contain several logical documents, such as a multi-document
+    /// YAML file, returning each document in the order in which it appears in the file.
+    ///
+    /// By default, the whole file is parsed as a single document.
+    fn parse_documents(&self, context: &ParseContext) -> Result<Vec<ParsedDocument>> {
+        Ok(vec![self.parse(context)?])
+    }
+}
+
+/// Split YAML text into the text of each document, at each `---` document start marker.
+///
+/// Sections which contain only comments or whitespace are dropped, unless the whole text
+/// contains no documents, in which case the text is returned as a single document.
+fn yaml_documents(text: &str) -> Vec<&str> {
+    let mut starts = vec![0];
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        if line.starts_with("---") && line[3..].chars().next().is_none_or(char::is_whitespace) {
+            starts.push(offset);
+        }
+        offset += line.len();
+    }
+    starts.push(text.len());
+
+    let documents: Vec<&str> = starts
+        .windows(2)
+        .map(|w| &text[w[0]..w[1]])
+        .filter(|d| {
+            d.lines().any(|l| {
+                let l = l.trim();
+                !l.is_empty() && !l.starts_with('#') && l != "---" && l != "..."
+            })
+        })
+        .collect();
+    if documents.is_empty() {
+        vec![text]
+    } else {
+        documents
+    }
+}
+
+/// Descriptions of keys within a parsed file, keyed by the dotted path of the key.
+pub type Descriptions = BTreeMap<String, String>;
+
+/// Options passed to individual parsers, keyed by parser name and option name.
+///
+/// Options are supplied on the command line as `parser.option=value`, e.g.
+/// `kubernetes.redact_secrets=true`. Options set for the parser `*` apply to all
+/// parsers, unless overridden for a specific parser.
+#[derive(Debug, Default, Clone)]
+pub struct ParserOptions(HashMap<String, HashMap<String, String>>);
+
+impl ParserOptions {
+    /// Build a set of parser options from a list of `parser.option=value` expressions.
+    pub fn from_args(args: &[String]) -> Result<ParserOptions> {
+        let mut options = ParserOptions::default();
+        for arg in args {
+            match arg.split_once('=').and_then(|(k, v)| {
+                k.split_once('.')
+                    .map(|(parser, option)| (parser.trim(), option.trim(), v.trim()))
+            }) {
+                Some((parser, option, value)) if !parser.is_empty() && !option.is_empty() => {
+                    options.set(parser, option, value)
+                }
+                _ => bail!(format!(
+                    "Invalid parser option '{}', expected 'parser.option=value'",
+                    arg
+                )),
+            }
+        }
+        Ok(options)
+    }
+
+    /// Return the options as sorted `parser.option=value` expressions, so that the same
+    /// options given in any order are recognised, such as when caching parsed files.
+    pub fn key(&self) -> Vec<String> {
+        let mut key = self
+            .0
+            .iter()
+            .flat_map(|(parser, options)| {
+                options
+                    .iter()
+                    .map(move |(option, value)| format!("{}.{}={}", parser, option, value))
+            })
+            .collect::<Vec<_>>();
+        key.sort();
+        key
+    }
+
+    /// Set the value of an option for a parser.
+    pub fn set(&mut self, parser: &str, option: &str, value: &str) {
+        self.0
+            .entry(parser.to_string())
+            .or_default()
+            .insert(option.to_string(), value.to_string());
+    }
+
+    /// Return the value of an option for a parser, if it has been set.
+    pub fn get(&self, parser: &str, option: &str) -> Option<&str> {
+        [parser, "*"]
+            .iter()
+            .find_map(|p| self.0.get(*p).and_then(|o| o.get(option)))
+            .map(|v| v.as_str())
+    }
+
+    /// Return the options for a parser whose names begin with a prefix followed by `.`,
+    /// keyed by the remainder of their names, e.g. `env` for `jsonnet.ext_str.env=prod`.
+    pub fn prefixed(&self, parser: &str, prefix: &str) -> BTreeMap<String, String> {
+        ["*", parser]
+            .iter()
+            .filter_map(|p| self.0.get(*p))
+            .flatten()
+            .filter_map(|(option, value)| {
+                option
+                    .strip_prefix(prefix)
+                    .and_then(|o| o.strip_prefix('.'))
+                    .filter(|name| !name.is_empty())
+                    .map(|name| (name.to_string(), value.to_owned()))
+            })
+            .collect()
+    }
+
+    /// Return whether a boolean option has been enabled for a parser.
+    pub fn flag(&self, parser: &str, option: &str) -> bool {
+        self.get(parser, option)
+            .is_some_and(|v| matches!(v.to_lowercase().as_str(), "true" | "yes" | "on" | "1"))
+    }
 }
 
 /// Return a list of available file parser instances.
 pub fn parsers() -> Vec<Box<dyn FileParser>> {
+    parsers_with_options(&ParserOptions::default())
+}
+
+/// Return a list of available file parser instances, configured with the provided options.
+pub fn parsers_with_options(options: &ParserOptions) -> Vec<Box<dyn FileParser>> {
     vec![
         Box::new(JsonParser {}),
-        Box::new(YamlParser {}),
-        Box::new(PropertiesParser {}),
+        Box::new(YamlParser::new(options)),
+        Box::new(PropertiesParser::new(options)),
         Box::new(OpenAPIParser {}),
-        Box::new(TomlParser {}),
-        Box::new(IniParser {}),
+        Box::new(TomlParser::new(options)),
+        Box::new(IniParser::new(options)),
+        Box::new(EnvFileParser::new(options)),
         Box::new(XmlParser {}),
         Box::new(HoconParser {}),
-        Box::new(SqlParser {}),
+        Box::new(SqlParser::new(options)),
+        Box::new(ProtoParser::new(options)),
+        Box::new(JsonnetParser::new(options)),
+        Box::new(DhallParser {}),
+        Box::new(KubernetesParser::new(options)),
+        Box::new(SopsParser {}),
+        Box::new(TerraformStateParser {}),
+        Box::new(HclParser {}),
+        Box::new(TerragruntParser {}),
+        Box::new(VaultPolicyParser {}),
+        Box::new(ConsulConfigParser {}),
+        Box::new(ApacheConfParser {}),
+        Box::new(KafkaPropertiesParser {}),
+        Box::new(SchemaRegistryParser {}),
+        Box::new(CollectionParser {}),
+        Box::new(EditorConfigParser {}),
+        Box::new(GitAttributesParser {}),
+        Box::new(GitConfigParser {}),
+        Box::new(RenovateParser {}),
+        Box::new(DependabotParser {}),
+        Box::new(LicenseFileParser {}),
+        Box::new(CodeOwnersParser {}),
     ]
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
-/// A successful file parsing result.
+/// A successful file parsing result, for one of the documents within a file.
 pub struct ParseSuccess {
     pub path: PathBuf,
     pub parser: String,
+    /// The position of the document within the file, from zero, if the file contains more
+    /// than one document.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<usize>,
+    /// The name of the document within the file, if it has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
     pub contents: Value,
+    #[serde(default, skip_serializing_if = "Descriptions::is_empty")]
+    pub descriptions: Descriptions,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub metadata: Map<String, Value>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// The owners of the file, from the CODEOWNERS file.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub owners: Vec<String>,
+    /// The other source files with the same content as the file.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub duplicates: Vec<PathBuf>,
+    /// References to values held outside the file, with `--external-refs`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub external_refs: Vec<ExternalRef>,
 }
 
 /// A failed file parsing result.
 pub struct ParseFailure {
-    #[allow(unused)]
     pub path: PathBuf,
     pub parser: String,
     pub error: Error, // Can't implement Serialize/Deserialize
@@ -90,60 +330,221 @@ impl FileParser for JsonParser {
         "json"
     }
 
-    fn can_parse(&self, path: &Path, #[allow(unused_variables)] contents: Result<&str>) -> bool {
-        has_extension(path, &["json", "tfstate"])
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["json", "tfstate"],
+            documents: true,
+            ..Default::default()
+        }
     }
 
-    fn parse(
-        &self,
-        path: &Path,
-        #[allow(unused_variables)] contents: Result<&str>,
-    ) -> Result<Value> {
-        let contents = fs::read_to_string(path)?;
-        Ok(serde_json::from_str(contents.as_str())?)
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        Ok(ParsedDocument::new(serde_json::from_str(context.text()?)?))
+    }
+
+    /// Parse each of a sequence of concatenated JSON values as a separate document.
+    fn parse_documents(&self, context: &ParseContext) -> Result<Vec<ParsedDocument>> {
+        serde_json::Deserializer::from_str(context.text()?)
+            .into_iter::<Value>()
+            .map(|v| Ok(ParsedDocument::new(v?)))
+            .collect()
     }
 }
 
 /// File parser for YAML files.
-pub struct YamlParser {}
+pub struct YamlParser {
+    comments: bool,
+}
+
+impl YamlParser {
+    /// Create a new parser using the `yaml` parser options.
+    pub fn new(options: &ParserOptions) -> YamlParser {
+        YamlParser {
+            comments: options.flag("yaml", "comments"),
+        }
+    }
+}
+
 impl FileParser for YamlParser {
     fn name(&self) -> &'static str {
         "yaml"
     }
 
-    fn can_parse(&self, path: &Path, #[allow(unused_variables)] contents: Result<&str>) -> bool {
-        has_extension(path, &["yaml"])
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["yaml"],
+            descriptions: true,
+            documents: true,
+            ..Default::default()
+        }
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        self.parse_text(context.text()?)
+    }
+
+    fn parse_documents(&self, context: &ParseContext) -> Result<Vec<ParsedDocument>> {
+        yaml_documents(context.text()?)
+            .into_iter()
+            .map(|d| self.parse_text(d))
+            .collect()
     }
+}
 
-    fn parse(
-        &self,
-        #[allow(unused_variables)] path: &Path,
-        contents: Result<&str>,
-    ) -> Result<Value> {
-        Ok(serde_yaml::from_str(contents?)?)
+impl YamlParser {
+    /// Parse the text of a single YAML document.
+    fn parse_text(&self, text: &str) -> Result<ParsedDocument> {
+        Ok(ParsedDocument {
+            value: serde_yaml::from_str(text)?,
+            descriptions: if self.comments {
+                comments::yaml(text)
+            } else {
+                Descriptions::new()
+            },
+            ..Default::default()
+        })
     }
 }
 
 /// File parser for Java Properties files.
-pub struct PropertiesParser {}
+///
+/// Properties are returned in the order in which they appear in the source file. If
+/// the `expand_keys` option is set, dotted keys such as `spring.datasource.url` are
+/// expanded into nested objects. Where a key is both a value and the parent of other
+/// keys (e.g. `a=1` and `a.b=2`), the conflicting key is kept in its dotted form.
+///
+/// If the `comments` option is set, comments immediately preceding a property are
+/// returned as its description.
+pub struct PropertiesParser {
+    expand_keys: bool,
+    comments: bool,
+}
+
+impl PropertiesParser {
+    /// Create a new parser using the `java-properties` parser options.
+    pub fn new(options: &ParserOptions) -> PropertiesParser {
+        PropertiesParser {
+            expand_keys: options.flag("java-properties", "expand_keys"),
+            comments: options.flag("java-properties", "comments"),
+        }
+    }
+
+    /// Return the comments immediately preceding each property, keyed by property.
+    /// Comments separated from the property by a blank line are not its description.
+    fn descriptions(lines: &[(usize, LineContent)]) -> Descriptions {
+        let mut descriptions = Descriptions::new();
+        let mut comments: Vec<String> = vec![];
+        let mut previous = 0;
+        for (number, line) in lines {
+            if *number > previous + 1 {
+                comments.clear();
+            }
+            previous = *number;
+            match line {
+                LineContent::Comment(c) => comments.push(c.trim().to_string()),
+                LineContent::KVPair(key, _) => {
+                    if !comments.is_empty() {
+                        descriptions.insert(key.to_owned(), comments.join("\n"));
+                    }
+                    comments.clear();
+                }
+            }
+        }
+        descriptions
+    }
+
+    /// Read all lines from a properties file with their line numbers.
+    fn lines(contents: &str) -> Result<Vec<(usize, LineContent)>> {
+        PropertiesIter::new(contents.as_bytes())
+            .map(|l| {
+                l.map(|l| (l.line_number(), l.consume_content()))
+                    .map_err(|e| e.to_string().into())
+            })
+            .collect()
+    }
+}
+
+/// Insert a value into a JSON object at the position described by a dotted key,
+/// creating intermediate objects as required.
+fn insert_dotted(map: &mut Map<String, Value>, key: &str, value: Value) {
+    match key.split_once('.') {
+        Some((head, tail)) => match map
+            .entry(head.to_string())
+            .or_insert_with(|| Value::Object(Map::new()))
+        {
+            Value::Object(child) => insert_dotted(child, tail, value),
+            _ => {
+                map.insert(key.to_string(), value);
+            }
+        },
+        None => {
+            map.insert(key.to_string(), value);
+        }
+    }
+}
+
 impl FileParser for PropertiesParser {
     fn name(&self) -> &'static str {
         "java-properties"
     }
 
-    fn can_parse(&self, path: &Path, #[allow(unused_variables)] contents: Result<&str>) -> bool {
-        has_extension(path, &["properties"])
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["properties"],
+            descriptions: true,
+            options: &["expand_keys"],
+            ..Default::default()
+        }
     }
 
-    fn parse(
-        &self,
-        #[allow(unused_variables)] path: &Path,
-        contents: Result<&str>,
-    ) -> Result<Value> {
-        match java_properties::read(contents?.as_bytes()) {
-            Ok(props) => Ok(serde_json::to_value(props)?),
-            Err(error) => Err(error.to_string().into()),
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let lines = PropertiesParser::lines(context.text()?)?;
+        let pairs = lines
+            .iter()
+            .filter_map(|(_, l)| match l {
+                LineContent::KVPair(key, value) => Some((key, value)),
+                LineContent::Comment(_) => None,
+            })
+            .collect::<Vec<_>>();
+
+        // Keys which have another key as a prefix cannot be nested beneath it.
+        let keys = pairs
+            .iter()
+            .map(|(k, _)| k.as_str())
+            .collect::<HashSet<_>>();
+        let conflicts = |key: &str| {
+            key.match_indices('.')
+                .any(|(i, _)| keys.contains(&key[..i]))
+        };
+
+        let mut properties = Map::new();
+        let mut seen = HashSet::new();
+        for (key, value) in pairs.iter() {
+            if !seen.insert(key.as_str()) {
+                context.warn(format!("Key {} is repeated, keeping the last value", key));
+            }
+            if self.expand_keys && conflicts(key) {
+                context.warn(format!(
+                    "Key {} conflicts with a shorter key and is not expanded",
+                    key
+                ));
+            }
+            if self.expand_keys && !conflicts(key) {
+                insert_dotted(&mut properties, key, Value::String(value.to_string()));
+            } else {
+                properties.insert(key.to_string(), Value::String(value.to_string()));
+            }
         }
+
+        Ok(ParsedDocument {
+            value: Value::Object(properties),
+            descriptions: if self.comments {
+                PropertiesParser::descriptions(&lines)
+            } else {
+                Descriptions::new()
+            },
+            ..Default::default()
+        })
     }
 }
 
@@ -154,60 +555,140 @@ impl FileParser for OpenAPIParser {
         "openapi-v3"
     }
 
-    fn can_parse(&self, path: &Path, #[allow(unused_variables)] contents: Result<&str>) -> bool {
-        has_extension(path, &["yaml", "json"])
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["yaml", "json"],
+            ..Default::default()
+        }
     }
 
-    fn parse(
-        &self,
-        #[allow(unused_variables)] path: &Path,
-        contents: Result<&str>,
-    ) -> Result<Value> {
-        let api: OpenAPI = serde_json::from_str(contents?)?;
-        Ok(serde_json::to_value(api)?)
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let api: OpenAPI = serde_json::from_str(context.text()?)?;
+        Ok(ParsedDocument {
+            metadata: Map::from_iter([(
+                "version".to_string(),
+                Value::String(api.openapi.to_owned()),
+            )]),
+            value: serde_json::to_value(api)?,
+            ..Default::default()
+        })
     }
 }
 
 /// File parser for TOML files.
-pub struct TomlParser {}
-impl FileParser for TomlParser {
-    fn name(&self) -> &'static str {
-        "toml"
+///
+/// Tables, inline tables and arrays of tables are all returned as JSON objects (or
+/// arrays of objects). Datetimes are returned as ISO-8601 strings by default or, if the
+/// `datetimes` option is set to `tagged`, as objects recording the kind of datetime:
+///
+/// ```json
+/// { "type": "datetime", "kind": "offset-datetime", "value": "1979-05-27T07:32:00Z" }
+/// ```
+///
+/// Non-finite floats, which cannot be represented in JSON, are returned as strings.
+pub struct TomlParser {
+    comments: bool,
+    tag_datetimes: bool,
+}
+
+impl TomlParser {
+    /// Create a new parser using the `toml` parser options.
+    pub fn new(options: &ParserOptions) -> TomlParser {
+        let tag_datetimes = match options.get("toml", "datetimes") {
+            None | Some("string") => false,
+            Some("tagged") => true,
+            Some(other) => {
+                warn!(
+                    "Unknown toml.datetimes option '{}', expected 'string' or 'tagged'.",
+                    other
+                );
+                false
+            }
+        };
+        TomlParser {
+            comments: options.flag("toml", "comments"),
+            tag_datetimes,
+        }
     }
 
-    fn can_parse(&self, path: &Path, #[allow(unused_variables)] contents: Result<&str>) -> bool {
-        has_extension(path, &["toml"])
+    /// Convert a TOML value into its JSON representation, warning of lossy conversions.
+    fn to_json(&self, value: toml::Value, context: &ParseContext) -> Value {
+        use toml::Value as Toml;
+        match value {
+            Toml::String(s) => Value::String(s),
+            Toml::Integer(i) => Value::Number(i.into()),
+            Toml::Float(f) => serde_json::Number::from_f64(f)
+                .map(Value::Number)
+                .unwrap_or_else(|| {
+                    context.warn(format!("Non-finite float {} is returned as a string", f));
+                    Value::String(f.to_string())
+                }),
+            Toml::Boolean(b) => Value::Bool(b),
+            Toml::Datetime(d) => {
+                let iso = d.to_string().replacen(' ', "T", 1);
+                if self.tag_datetimes {
+                    serde_json::json!({
+                        "type": "datetime",
+                        "kind": datetime_kind(&iso),
+                        "value": iso,
+                    })
+                } else {
+                    Value::String(iso)
+                }
+            }
+            Toml::Array(values) => Value::Array(
+                values
+                    .into_iter()
+                    .map(|v| self.to_json(v, context))
+                    .collect(),
+            ),
+            Toml::Table(table) => Value::Object(
+                table
+                    .into_iter()
+                    .map(|(k, v)| (k, self.to_json(v, context)))
+                    .collect(),
+            ),
+        }
     }
+}
 
-    fn parse(
-        &self,
-        #[allow(unused_variables)] path: &Path,
-        contents: Result<&str>,
-    ) -> Result<Value> {
-        use toml::Value;
-        Ok(serde_json::to_value(contents?.parse::<Value>()?)?)
+/// Return the kind of a TOML datetime from its ISO-8601 representation.
+fn datetime_kind(iso: &str) -> &'static str {
+    match iso.split_once('T') {
+        Some((_, time)) if time.ends_with(['Z', 'z']) || time.contains(['+', '-']) => {
+            "offset-datetime"
+        }
+        Some(_) => "local-datetime",
+        None if iso.contains(':') => "local-time",
+        None => "local-date",
     }
 }
 
-/// File parser for INI files.
-pub struct IniParser {}
-impl FileParser for IniParser {
+impl FileParser for TomlParser {
     fn name(&self) -> &'static str {
-        "ini"
+        "toml"
     }
 
-    fn can_parse(&self, path: &Path, #[allow(unused_variables)] contents: Result<&str>) -> bool {
-        has_extension(path, &["ini"])
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["toml"],
+            descriptions: true,
+            options: &["datetimes"],
+            ..Default::default()
+        }
     }
 
-    fn parse(
-        &self,
-        #[allow(unused_variables)] path: &Path,
-        contents: Result<&str>,
-    ) -> Result<Value> {
-        Ok(serde_json::to_value(serde_ini::from_str::<Value>(
-            contents?,
-        )?)?)
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let text = context.text()?;
+        Ok(ParsedDocument {
+            value: self.to_json(text.parse::<toml::Value>()?, context),
+            descriptions: if self.comments {
+                comments::toml(text)
+            } else {
+                Descriptions::new()
+            },
+            ..Default::default()
+        })
     }
 }
 
@@ -218,18 +699,17 @@ impl FileParser for XmlParser {
         "xml"
     }
 
-    fn can_parse(&self, path: &Path, #[allow(unused_variables)] contents: Result<&str>) -> bool {
-        has_extension(path, &["xml"])
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["xml"],
+            ..Default::default()
+        }
     }
 
-    fn parse(
-        &self,
-        #[allow(unused_variables)] path: &Path,
-        contents: Result<&str>,
-    ) -> Result<Value> {
-        Ok(serde_json::to_value(serde_xml_rs::from_str::<Value>(
-            contents?,
-        )?)?)
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        Ok(ParsedDocument::new(serde_json::to_value(
+            serde_xml_rs::from_str::<Value>(context.text()?)?,
+        )?))
     }
 }
 
@@ -240,18 +720,19 @@ impl FileParser for HoconParser {
         "hocon"
     }
 
-    fn can_parse(&self, path: &Path, #[allow(unused_variables)] contents: Result<&str>) -> bool {
-        has_extension(path, &["conf"])
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["conf"],
+            ..Default::default()
+        }
     }
 
-    fn parse(
-        &self,
-        #[allow(unused_variables)] path: &Path,
-        contents: Result<&str>,
-    ) -> Result<Value> {
-        Ok(serde_json::to_value(
-            HoconLoader::new().load_str(contents?)?.resolve()?,
-        )?)
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        Ok(ParsedDocument::new(serde_json::to_value(
+            HoconLoader::new()
+                .load_str(context.text()?)?
+                .resolve::<Value>()?,
+        )?))
     }
 }
 
@@ -259,65 +740,1505 @@ impl FileParser for HoconParser {
 ///
 /// This parser will iterate through different SQL parsers until a file is successfully
 /// parsed, returning an error if none of the parsers succeed.
-pub struct SqlParser {}
+///
+/// If the `split_statements` option is set, each statement is returned as a separate
+/// document, without descriptions.
+pub struct SqlParser {
+    comments: bool,
+    split_statements: bool,
+}
+
+impl SqlParser {
+    /// Create a new parser using the `sql` parser options.
+    pub fn new(options: &ParserOptions) -> SqlParser {
+        SqlParser {
+            comments: options.flag("sql", "comments"),
+            split_statements: options.flag("sql", "split_statements"),
+        }
+    }
+
+    /// Parse SQL text with each dialect in turn, returning the statements and the name of
+    /// the first dialect which could parse them. The error of each dialect is only reported
+    /// if none of them could parse the text.
+    fn statements(context: &ParseContext) -> Result<(Vec<Statement>, &'static str)> {
+        let dialects: Vec<(&str, Box<dyn Dialect>)> = vec![
+            ("generic", Box::new(GenericDialect {})),
+            ("postgresql", Box::new(PostgreSqlDialect {})),
+            ("mysql", Box::new(MySqlDialect {})),
+            ("sqlite", Box::new(SQLiteDialect {})),
+            ("mssql", Box::new(MsSqlDialect {})),
+            ("hive", Box::new(HiveDialect {})),
+        ];
+
+        let text = context.text()?;
+        let mut errors = vec![];
+        for (name, dialect) in dialects.into_iter() {
+            trace!("  parsing with sql dialect {}", name);
+            match Parser::parse_sql(dialect.as_ref(), text) {
+                Ok(statements) => return Ok((statements, name)),
+                Err(e) => {
+                    trace!("  not valid {} sql: {}", name, e);
+                    errors.push(format!("{}: {}", name, e));
+                }
+            }
+        }
+        bail!(
+            "Could not parse with any SQL parser dialects ({})",
+            errors.join("; ")
+        )
+    }
+}
+
+/// Return the metadata of a parsed SQL document.
+fn sql_metadata(dialect: &str) -> Map<String, Value> {
+    Map::from_iter([("dialect".to_string(), Value::String(dialect.to_string()))])
+}
+
 impl FileParser for SqlParser {
     fn name(&self) -> &'static str {
         "sql"
     }
 
-    fn can_parse(&self, path: &Path, #[allow(unused_variables)] contents: Result<&str>) -> bool {
-        has_extension(path, &["sql"])
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["sql"],
+            descriptions: true,
+            documents: true,
+            options: &["split_statements"],
+            ..Default::default()
+        }
     }
 
-    fn parse(
-        &self,
-        #[allow(unused_variables)] path: &Path,
-        contents: Result<&str>,
-    ) -> Result<Value> {
-        let parsers: Vec<Box<dyn Dialect>> = vec![
-            Box::new(GenericDialect {}),
-            Box::new(PostgreSqlDialect {}),
-            Box::new(MySqlDialect {}),
-            Box::new(SQLiteDialect {}),
-            Box::new(MsSqlDialect {}),
-            Box::new(HiveDialect {}),
-        ];
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let (statements, dialect) = SqlParser::statements(context)?;
+        Ok(ParsedDocument {
+            value: serde_json::to_value(&statements)?,
+            descriptions: if self.comments {
+                comments::sql(context.text()?)
+            } else {
+                Descriptions::new()
+            },
+            metadata: sql_metadata(dialect),
+            ..Default::default()
+        })
+    }
 
-        let result = parsers
+    fn parse_documents(&self, context: &ParseContext) -> Result<Vec<ParsedDocument>> {
+        if !self.split_statements {
+            return Ok(vec![self.parse(context)?]);
+        }
+        let (statements, dialect) = SqlParser::statements(context)?;
+        statements
             .iter()
-            .map(|dialect| {
-                trace!("  parsing with sql parser {:?}", dialect);
-                Parser::parse_sql(
-                    dialect.as_ref(),
-                    contents.as_ref().map_err(|_| {
-                        ParserError::ParserError("Could not read file contents.".to_string())
-                    })?,
-                )
-                .map_err(|e| {
-                    warn!("  parsing error: {}", e.to_string());
-                    e
+            .map(|statement| {
+                Ok(ParsedDocument {
+                    value: serde_json::to_value(statement)?,
+                    metadata: sql_metadata(dialect),
+                    ..Default::default()
                 })
             })
-            .find(|p| p.is_ok())
-            .map(|f| match f {
-                Ok(statements) => Ok(serde_json::to_value(&statements)),
-                Err(e) => Err(e.to_string()),
-            })
-            .unwrap_or_else(|| bail!("Could not parse with any SQL parser dialects"));
-
-        Ok(result??)
+            .collect()
     }
 }
 
-// Protobuf Parser
 // CSV Parser
 
 #[cfg(test)]
 mod tests {
     use crate::parsers;
+    use crate::parsers::{FileParser, ParseContext, ParserOptions};
+    use jsonpath_lib::select;
+    use serde_json::json;
+    use std::path::Path;
 
     #[test]
     fn available_parsers() {
         assert_eq!(parsers::parsers().len(), crate::parsers::parsers().len())
     }
+
+    #[test]
+    fn parser_options() {
+        let options = ParserOptions::from_args(&[
+            "kubernetes.redact_secrets=true".to_string(),
+            "ini.name = value".to_string(),
+        ])
+        .unwrap();
+
+        assert!(options.flag("kubernetes", "redact_secrets"));
+        assert!(!options.flag("kubernetes", "other"));
+        assert_eq!(options.get("ini", "name"), Some("value"));
+        assert!(ParserOptions::from_args(&["redact_secrets=true".to_string()]).is_err());
+    }
+
+    #[test]
+    fn properties() {
+        let path = "test/resources/properties/application.properties";
+        let contents = std::fs::read_to_string(path).unwrap();
+
+        let defaults = ParserOptions::default();
+        let flat = parsers::PropertiesParser::new(&defaults)
+            .parse(&ParseContext::with_text(
+                Path::new(path),
+                &defaults,
+                &contents,
+            ))
+            .unwrap()
+            .value;
+        assert_eq!(
+            flat.as_object().unwrap().keys().collect::<Vec<_>>(),
+            vec![
+                "spring.datasource.url",
+                "spring.datasource.username",
+                "server.port",
+                "logging.level",
+                "logging"
+            ]
+        );
+
+        let options = ParserOptions::from_args(&[
+            "java-properties.expand_keys=true".to_string(),
+            "java-properties.comments=true".to_string(),
+        ])
+        .unwrap();
+        let nested = parsers::PropertiesParser::new(&options)
+            .parse(&ParseContext::with_text(
+                Path::new(path),
+                &options,
+                &contents,
+            ))
+            .unwrap();
+        assert_eq!(
+            nested.value,
+            json!({
+                "spring": {
+                    "datasource": {
+                        "url": "jdbc:postgresql://localhost/app",
+                        "username": "app"
+                    }
+                },
+                "server": { "port": "8080" },
+                "logging.level": "debug",
+                "logging": "enabled"
+            })
+        );
+
+        let descriptions = nested.descriptions;
+        assert_eq!(
+            descriptions.get("spring.datasource.url").unwrap(),
+            "Database connection\nUses the local database by default."
+        );
+        assert_eq!(descriptions.get("server.port").unwrap(), "HTTP port");
+        assert!(!descriptions.contains_key("spring.datasource.username"));
+        assert!(!descriptions.contains_key("logging.level"));
+    }
+
+    #[test]
+    fn yaml_documents() {
+        assert_eq!(parsers::yaml_documents("a: 1\n"), ["a: 1\n"]);
+        assert_eq!(parsers::yaml_documents(""), [""]);
+        assert_eq!(
+            parsers::yaml_documents("# header\n---\na: 1\n--- \nb: |\n  ---\n---\n"),
+            ["---\na: 1\n", "--- \nb: |\n  ---\n"]
+        );
+
+        let options = ParserOptions::default();
+        let context = ParseContext::with_text(Path::new("a.yaml"), &options, "a: 1\n---\nb: 2\n");
+        let documents = parsers::YamlParser::new(&options)
+            .parse_documents(&context)
+            .unwrap();
+        assert_eq!(
+            documents.iter().map(|d| &d.value).collect::<Vec<_>>(),
+            [&json!({"a": 1}), &json!({"b": 2})]
+        );
+    }
+
+    #[test]
+    fn json_documents() {
+        let options = ParserOptions::default();
+        let context =
+            ParseContext::with_text(Path::new("a.json"), &options, "{\"a\": 1}\n{\"b\": 2} [3]");
+        let documents = parsers::JsonParser {}.parse_documents(&context).unwrap();
+        assert_eq!(
+            documents.iter().map(|d| &d.value).collect::<Vec<_>>(),
+            [&json!({"a": 1}), &json!({"b": 2}), &json!([3])]
+        );
+        assert!(parsers::JsonParser {}.parse(&context).is_err());
+    }
+
+    #[test]
+    fn sql_documents() {
+        let text = "CREATE TABLE a (id INT);\nCREATE TABLE b (id INT);";
+        let parse = |options: &ParserOptions| {
+            parsers::SqlParser::new(options)
+                .parse_documents(&ParseContext::with_text(Path::new("a.sql"), options, text))
+                .unwrap()
+        };
+
+        let combined = parse(&ParserOptions::default());
+        assert_eq!(combined.len(), 1);
+        assert_eq!(combined[0].value.as_array().unwrap().len(), 2);
+
+        let options = ParserOptions::from_args(&["sql.split_statements=true".to_string()]).unwrap();
+        let split = parse(&options);
+        assert_eq!(split.len(), 2);
+        assert_eq!(split[1].value, combined[0].value[1]);
+        assert_eq!(split[1].metadata["dialect"], json!("generic"));
+    }
+
+    #[test]
+    fn sql_dialects() {
+        let options = ParserOptions::default();
+        let parse = |text: &str| {
+            let context = ParseContext::with_text(Path::new("a.sql"), &options, text);
+            let result = parsers::SqlParser::new(&options).parse(&context);
+            (result, context.take_warnings())
+        };
+
+        // Dialects which cannot parse the text are not reported if another can
+        let (parsed, warnings) = parse("SELECT `id` FROM a");
+        assert_eq!(parsed.unwrap().metadata["dialect"], json!("mysql"));
+        assert!(warnings.is_empty());
+
+        let error = parse("SELEC 1").0.unwrap_err().to_string();
+        assert!(error.contains("Could not parse with any SQL parser dialects"));
+        assert!(error.contains("hive: "));
+    }
+
+    #[test]
+    fn toml() {
+        let contents = r#"
+released = 1979-05-27T07:32:00Z
+local = 1979-05-27 07:32:00
+day = 1979-05-27
+time = 07:32:00
+point = { x = 1, y = 2 }
+infinite = inf
+
+[[releases]]
+version = "1.0"
+
+[[releases]]
+version = "2.0"
+"#;
+        let parse = |options: &[&str]| {
+            let options = ParserOptions::from_args(
+                &options.iter().map(|o| o.to_string()).collect::<Vec<_>>(),
+            )
+            .unwrap();
+            parsers::TomlParser::new(&options)
+                .parse(&ParseContext::with_text(
+                    Path::new("test.toml"),
+                    &options,
+                    contents,
+                ))
+                .unwrap()
+                .value
+        };
+
+        assert_eq!(
+            parse(&[]),
+            json!({
+                "released": "1979-05-27T07:32:00Z",
+                "local": "1979-05-27T07:32:00",
+                "day": "1979-05-27",
+                "time": "07:32:00",
+                "point": { "x": 1, "y": 2 },
+                "infinite": "inf",
+                "releases": [{ "version": "1.0" }, { "version": "2.0" }]
+            })
+        );
+
+        let tagged = parse(&["toml.datetimes=tagged"]);
+        assert_eq!(
+            tagged["released"],
+            json!({
+                "type": "datetime",
+                "kind": "offset-datetime",
+                "value": "1979-05-27T07:32:00Z"
+            })
+        );
+        assert_eq!(tagged["local"]["kind"], json!("local-datetime"));
+        assert_eq!(tagged["day"]["kind"], json!("local-date"));
+        assert_eq!(tagged["time"]["kind"], json!("local-time"));
+    }
+
+    fn parse_terraform_state(path: &str) -> serde_json::Value {
+        let options = ParserOptions::default();
+        parsers::TerraformStateParser {}
+            .parse(&ParseContext::new(Path::new(path), &options))
+            .unwrap()
+            .value
+    }
+
+    #[test]
+    fn terraform_legacy_state() {
+        let result = parse_terraform_state("test/resources/test.tfstate");
+
+        assert_eq!(
+            select(&result, "$.by_type.aws_route53_record").unwrap()[0],
+            &json!(["aws_route53_record.site"])
+        );
+        let record = select(
+            &result,
+            "$.resources[?(@.address=='aws_route53_record.site')]",
+        )
+        .unwrap()[0]
+            .to_owned();
+        assert_eq!(record["provider"], json!("aws"));
+        assert_eq!(record["name"], json!("site"));
+        assert_eq!(
+            record["dependencies"],
+            json!(["aws_route53_zone.primary", "aws_s3_bucket.site"])
+        );
+        assert_eq!(
+            record["key_attributes"]["id"],
+            json!("Z2OIQETM3FU6D_mikeball.me_A")
+        );
+    }
+
+    #[test]
+    fn terraform_state() {
+        let result = parse_terraform_state("test/resources/terraform/v4.tfstate");
+
+        assert_eq!(result["terraform_version"], json!("1.5.7"));
+        assert_eq!(result["outputs"]["bucket_name"], json!("docs-site"));
+        assert_eq!(
+            result["by_provider"]["aws"],
+            json!([
+                "aws_s3_bucket.site",
+                "module.dns.aws_route53_record.www[0]",
+                "data.aws_caller_identity.current"
+            ])
+        );
+        let record = select(
+            &result,
+            "$.resources[?(@.address=='module.dns.aws_route53_record.www[0]')]",
+        )
+        .unwrap()[0]
+            .to_owned();
+        assert_eq!(record["module"], json!("module.dns"));
+        assert_eq!(record["dependencies"], json!(["aws_s3_bucket.site"]));
+    }
+
+    #[test]
+    fn terraform_config() {
+        let result = parse_with(
+            &parsers::HclParser {},
+            "test/resources/terraform/module/main.tf",
+        );
+        assert_eq!(
+            result["terraform"]["required_providers"]["random"],
+            json!({"version": "~> 3.6"})
+        );
+        assert_eq!(result["terraform"]["backend"]["type"], json!("s3"));
+        assert_eq!(
+            result["provider_config"]["aws.us"]["expressions"]["region"],
+            json!({"constant_value": "us-east-1"})
+        );
+        assert_eq!(
+            select(&result, "$.root_module.resources[*].address").unwrap(),
+            [
+                &json!("aws_s3_bucket.site"),
+                &json!("aws_acm_certificate.site"),
+                &json!("data.aws_route53_zone.main")
+            ]
+        );
+        let certificate = &result["root_module"]["resources"][1];
+        assert_eq!(certificate["provider_config_key"], json!("aws.us"));
+        assert_eq!(
+            certificate["count_expression"],
+            json!({"expression": "var.certificate ? 1 : 0", "references": ["var.certificate"]})
+        );
+        assert_eq!(certificate["depends_on"], json!(["aws_s3_bucket.site"]));
+        assert_eq!(
+            result["root_module"]["resources"][0]["expressions"]["versioning"],
+            json!([{"enabled": {"constant_value": true}}])
+        );
+        assert_eq!(
+            result["root_module"]["module_calls"]["dns"]["expressions"]["zone_id"]["references"],
+            json!([
+                "data.aws_route53_zone.main",
+                "data.aws_route53_zone.main.zone_id"
+            ])
+        );
+        assert_eq!(
+            result["root_module"]["locals"]["name"]["references"],
+            json!(["var.prefix"])
+        );
+
+        let result = parse_with(
+            &parsers::HclParser {},
+            "test/resources/terraform/module/variables.tf",
+        );
+        assert_eq!(
+            result["root_module"]["variables"]["prefix"],
+            json!({
+                "type": "string",
+                "default": null,
+                "required": true,
+                "description": "Prefix of resource names",
+                "sensitive": false,
+                "nullable": true,
+                "validation": ["The prefix must be at most 16 characters."]
+            })
+        );
+        assert_eq!(
+            result["root_module"]["variables"]["tags"]["type"],
+            json!("map(string)")
+        );
+        assert_eq!(
+            result["root_module"]["outputs"]["api_key"]["sensitive"],
+            json!(true)
+        );
+
+        let options = ParserOptions::default();
+        let policy = Path::new("test/resources/hashicorp/app-policy.hcl");
+        assert!(!parsers::HclParser {}.can_parse(&ParseContext::new(policy, &options)));
+    }
+
+    fn parse_kubernetes(path: &str, options: &ParserOptions) -> serde_json::Value {
+        let parser = parsers::KubernetesParser::new(options);
+        let context = ParseContext::new(Path::new(path), options);
+        assert!(parser.can_parse(&context));
+        let document = parser.parse(&context).unwrap();
+        assert_eq!(document.metadata["kind"], json!(document.value["kind"]));
+        document.value
+    }
+
+    #[test]
+    fn kubernetes_config_map() {
+        let result = parse_kubernetes(
+            "test/resources/kubernetes/configmap.yaml",
+            &ParserOptions::default(),
+        );
+
+        assert_eq!(
+            select(&result, "$.data['application.properties'].parser").unwrap()[0],
+            &json!("java-properties")
+        );
+        assert_eq!(
+            select(
+                &result,
+                "$.data['application.properties'].contents['server.port']"
+            )
+            .unwrap()[0],
+            &json!("8080")
+        );
+        assert_eq!(
+            select(&result, "$.data['settings.ini'].contents.database.port").unwrap()[0],
+            &json!("5432")
+        );
+        assert_eq!(
+            select(&result, "$.data.LOG_LEVEL").unwrap()[0],
+            &json!("debug")
+        );
+    }
+
+    #[test]
+    fn kubernetes_documents() {
+        let options = ParserOptions::default();
+        let path = Path::new("test/resources/kubernetes/manifests.yaml");
+        let parser = parsers::KubernetesParser::new(&options);
+        let context = ParseContext::new(path, &options);
+        assert!(parser.can_parse(&context));
+
+        let documents = parser.parse_documents(&context).unwrap();
+        assert_eq!(
+            documents
+                .iter()
+                .map(|d| d.name.as_deref())
+                .collect::<Vec<_>>(),
+            [Some("ConfigMap/app-config"), Some("Secret/app-secret")]
+        );
+        assert_eq!(documents[0].value["data"]["LOG_LEVEL"], json!("info"));
+        assert_eq!(documents[1].value["stringData"]["token"], json!("abc123"));
+        assert_eq!(documents[1].metadata["kind"], json!("Secret"));
+    }
+
+    #[test]
+    fn kubernetes_secret() {
+        let path = "test/resources/kubernetes/secret.yaml";
+        let result = parse_kubernetes(path, &ParserOptions::default());
+        assert_eq!(
+            select(&result, "$.data['credentials.yaml'].contents.password").unwrap()[0],
+            &json!("hunter2")
+        );
+        assert_eq!(
+            select(&result, "$.data.token").unwrap()[0],
+            &json!("abc123")
+        );
+
+        let mut options = ParserOptions::default();
+        options.set("kubernetes", "redact_secrets", "true");
+        let redacted = parse_kubernetes(path, &options);
+        assert_eq!(
+            select(&redacted, "$.data['credentials.yaml'].contents.password").unwrap()[0],
+            &json!(parsers::kubernetes::REDACTED)
+        );
+        assert_eq!(
+            select(&redacted, "$.data.token").unwrap()[0],
+            &json!(parsers::kubernetes::REDACTED)
+        );
+    }
+
+    fn parse_with(parser: &dyn FileParser, path: &str) -> serde_json::Value {
+        let options = ParserOptions::default();
+        let context = ParseContext::new(Path::new(path), &options);
+        assert!(parser.can_parse(&context));
+        parser.parse(&context).unwrap().value
+    }
+
+    #[test]
+    fn jsonnet() {
+        let path = "test/resources/jsonnet/orders.jsonnet";
+        let options = ParserOptions::from_args(&[
+            "jsonnet.jpath=test/resources/jsonnet/lib".to_string(),
+            "jsonnet.ext_str.env=production".to_string(),
+            "jsonnet.tla_str.replicas=3".to_string(),
+        ])
+        .unwrap();
+        let parser = parsers::JsonnetParser::new(&options);
+        let context = ParseContext::new(Path::new(path), &options);
+        assert!(parser.can_parse(&context));
+        let parsed = parser.parse(&context).unwrap().value;
+        assert_eq!(parsed["environment"], "production");
+        assert_eq!(
+            parsed["deployment"],
+            json!({
+                "apiVersion": "apps/v1",
+                "kind": "Deployment",
+                "metadata": {"labels": {"app": "orders"}, "name": "orders"},
+                "spec": {
+                    "replicas": 3,
+                    "template": {
+                        "spec": {"containers": [{"image": "orders:1.4.2", "name": "orders"}]}
+                    }
+                }
+            })
+        );
+        assert_eq!(
+            parsed["ports"],
+            json!([{"containerPort": 8080}, {"containerPort": 9090}])
+        );
+        assert_eq!(
+            (parsed["ratio"].clone(), parsed["offset"].clone()),
+            (json!(2.5), json!(-1.5))
+        );
+
+        // Libraries are returned with the signatures of their functions
+        assert_eq!(
+            parse_with(&parser, "test/resources/jsonnet/lib/service.libsonnet"),
+            json!({
+                "deployment": "function(name, image, replicas)",
+                "port": "function(number)"
+            })
+        );
+
+        // Missing external variables and imports fail with the location of the error
+        let default = ParserOptions::default();
+        let error = parsers::JsonnetParser::new(&default)
+            .parse(&ParseContext::new(Path::new(path), &default))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("service.libsonnet"), "{}", error);
+        let context = ParseContext::with_text(
+            Path::new("broken.jsonnet"),
+            &options,
+            "{ a: std.extVar('missing') }",
+        );
+        let error = parser.parse(&context).unwrap_err().to_string();
+        assert!(error.contains("missing"), "{}", error);
+    }
+
+    #[test]
+    fn dhall() {
+        let parser = parsers::DhallParser {};
+        assert_eq!(
+            parse_with(&parser, "test/resources/dhall/orders.dhall"),
+            json!({
+                "name": "orders",
+                "environment": "development",
+                "replicas": 3,
+                "ports": [{"containerPort": 8080}, {"containerPort": 9090}],
+                "protocols": ["HTTP", {"GRPC": 50051}],
+                "owner": null,
+                "ratio": 2.5,
+                "offset": -1,
+                "labels": {"app": "orders", "tier": "backend"}
+            })
+        );
+
+        // Failures name the position of the error, and functions are not values
+        let dir = std::env::temp_dir().join(format!("kvasir-dhall-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let error = |text: &str| {
+            let path = dir.join("broken.dhall");
+            std::fs::write(&path, text).unwrap();
+            let options = ParserOptions::default();
+            parser
+                .parse(&ParseContext::new(&path, &options))
+                .unwrap_err()
+                .to_string()
+        };
+        let syntax = error("{ a = 1 +\n  , b = \"x\" }\n");
+        assert!(syntax.contains("--> 2:3"), "{}", syntax);
+        let types = error("{ a = 1 + \"x\" }\n");
+        assert!(
+            types.contains(&format!("{}:1:7", dir.join("broken.dhall").display())),
+            "{}",
+            types
+        );
+        assert!(error("\\(x : Natural) -> x\n").contains("simple value"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn apache() {
+        let parser = parsers::ApacheConfParser {};
+        let parsed = parse_with(&parser, "test/resources/apache/httpd.conf");
+        assert_eq!(
+            parsed["directives"][4],
+            json!({"name": "LogFormat", "args": ["%h %l %u %t \"%r\" %>s %b", "common"], "line": 7})
+        );
+        assert_eq!(
+            select(
+                &parsed,
+                "$.directives[?(@.name == 'Directory')].directives[*].name"
+            )
+            .unwrap(),
+            ["Options", "AllowOverride", "Require"]
+        );
+        assert_eq!(
+            parsed["virtual_hosts"],
+            json!([{
+                "addresses": ["*:443", "[::]:443"],
+                "server_name": "orders.example.com",
+                "server_aliases": ["www.orders.example.com", "api.orders.example.com"],
+                "document_root": "/var/www/orders",
+                "line": 16
+            }])
+        );
+        assert_eq!(
+            parse_with(&parser, "test/resources/apache/sites-enabled/payments.conf")
+                ["virtual_hosts"][0]["server_name"],
+            "payments.example.com"
+        );
+
+        let options = ParserOptions::default();
+        let parse = |path: &str, text: &str| {
+            let context = ParseContext::with_text(Path::new(path), &options, text);
+            assert!(parser.can_parse(&context));
+            parser.parse(&context).map(|p| p.value)
+        };
+        assert_eq!(
+            parse(
+                ".htaccess",
+                "RewriteEngine On\nRewriteRule ^old$ /new [R=301,L]\n"
+            )
+            .unwrap()["directives"][1]["args"],
+            json!(["^old$", "/new", "[R=301,L]"])
+        );
+        for (text, error) in [
+            (
+                "<Directory />\n</Location>\n",
+                "Unexpected </Location> on line 2, expected </Directory> closing line 1",
+            ),
+            (
+                "</Directory>\n",
+                "Unexpected </Directory> on line 1 outside any section",
+            ),
+            (
+                "<VirtualHost *:80>\nServerName a\n",
+                "Section <VirtualHost> on line 1 is not closed",
+            ),
+            ("ServerName \"a\n", "Unterminated quote on line 1"),
+        ] {
+            assert_eq!(parse("httpd.conf", text).unwrap_err().to_string(), error);
+        }
+        assert!(!parser.can_parse(&ParseContext::new(
+            Path::new("nginx/conf.d/a.conf"),
+            &options
+        )));
+    }
+
+    #[test]
+    fn dotenv() {
+        let path = "test/resources/dotenv/.env.production";
+        let options = ParserOptions::from_args(&["dotenv.comments=true".to_string()]).unwrap();
+        let context = ParseContext::new(Path::new(path), &options);
+        let parsed = parsers::EnvFileParser::new(&options)
+            .parse(&context)
+            .unwrap();
+        assert_eq!(
+            parsed.value,
+            json!({
+                "APP_URL": "https://orders.example.com",
+                "APP_NAME": "Orders \"API\"",
+                "LOG_LEVEL": "debug",
+                "GREETING": "Hello ${USER} \\n",
+                "EMPTY": "",
+                "CERTIFICATE": "-----BEGIN CERTIFICATE-----\nMIIBszCCAVmgAwIBAgIU\n-----END CERTIFICATE-----",
+                "FEATURES": "search#beta"
+            })
+        );
+        assert_eq!(
+            parsed.descriptions.get("APP_URL").map(String::as_str),
+            Some("The public URL of the service")
+        );
+        assert_eq!(
+            context.take_warnings(),
+            ["Variable LOG_LEVEL is repeated, keeping the last value"]
+        );
+
+        let parser = parsers::EnvFileParser::new(&options);
+        for (path, accepted) in [
+            (".env", true),
+            ("config/db.env", true),
+            ("environment", false),
+        ] {
+            let context = ParseContext::with_text(Path::new(path), &options, "");
+            assert_eq!(parser.can_parse(&context), accepted, "{}", path);
+        }
+        let context = ParseContext::with_text(Path::new(".env"), &options, "A='unterminated\n");
+        assert!(parser.parse(&context).is_err());
+    }
+
+    #[test]
+    fn protobuf() {
+        let path = "test/resources/protobuf/orders.proto";
+        let result = parse_with(&parsers::ProtoParser::new(&ParserOptions::default()), path);
+        assert_eq!(result["syntax"], json!("proto3"));
+        assert_eq!(result["package"], json!("shop.orders.v1"));
+        assert_eq!(
+            result["imports"][1],
+            json!({"path": "google/protobuf/timestamp.proto", "modifier": "public"})
+        );
+        assert_eq!(
+            result["options"]["java_package"],
+            json!("com.example.shop.orders.v1")
+        );
+        assert_eq!(
+            select(&result, "$.messages[*].full_name").unwrap(),
+            [
+                &json!("shop.orders.v1.Order"),
+                &json!("shop.orders.v1.Order.Item"),
+                &json!("shop.orders.v1.GetOrderRequest")
+            ]
+        );
+
+        let order = &result["messages"][0];
+        assert_eq!(
+            order["description"],
+            json!("An order placed by a customer.\nOrders are immutable once placed.")
+        );
+        assert_eq!(
+            order["fields"][0],
+            json!({
+                "name": "id",
+                "number": 1,
+                "type": "string",
+                "label": null,
+                "oneof": null,
+                "options": {},
+                "description": "The unique identifier of the order."
+            })
+        );
+        assert_eq!(order["fields"][1]["label"], json!("repeated"));
+        assert_eq!(
+            order["fields"][1]["description"],
+            json!("The items ordered")
+        );
+        assert_eq!(
+            order["fields"][2]["options"],
+            json!({"deprecated": true, "(validate.rules).timestamp.required": true})
+        );
+        assert_eq!(
+            order["fields"][3]["map"],
+            json!({"key_type": "string", "value_type": "string"})
+        );
+        assert_eq!(order["fields"][5]["oneof"], json!("payment"));
+        assert_eq!(
+            order["oneofs"][0],
+            json!({
+                "name": "payment",
+                "description": "How the order is paid for.",
+                "fields": ["card_token", "voucher_code"],
+                "options": {}
+            })
+        );
+        assert_eq!(
+            order["reserved"],
+            json!({
+                "ranges": [{"start": 6, "end": 6}, {"start": 8, "end": 9}],
+                "names": ["discount"]
+            })
+        );
+        assert_eq!(
+            result["messages"][1]["parent"],
+            json!("shop.orders.v1.Order")
+        );
+
+        let status = &result["enums"][0];
+        assert_eq!(status["full_name"], json!("shop.orders.v1.Order.Status"));
+        assert_eq!(
+            status["values"][1]["description"],
+            json!("Awaiting dispatch")
+        );
+        assert_eq!(status["values"][2]["options"], json!({"deprecated": true}));
+
+        let service = &result["services"][0];
+        assert_eq!(service["description"], json!("Manages orders."));
+        assert_eq!(service["options"], json!({"deprecated": false}));
+        assert_eq!(
+            service["methods"][0]["options"]["(google.api.http)"],
+            json!({
+                "get": "/v1/orders/{id}",
+                "additional_bindings": {"get": "/v1/customers/-/orders/{id}"}
+            })
+        );
+        assert_eq!(
+            service["methods"][1],
+            json!({
+                "name": "WatchOrders",
+                "input_type": "GetOrderRequest",
+                "output_type": "Order",
+                "client_streaming": true,
+                "server_streaming": true,
+                "options": {},
+                "description": null
+            })
+        );
+
+        let options = ParserOptions::from_args(&["protobuf.comments=true".to_string()]).unwrap();
+        let descriptions = parsers::ProtoParser::new(&options)
+            .parse(&ParseContext::new(Path::new(path), &options))
+            .unwrap()
+            .descriptions;
+        assert_eq!(
+            descriptions.get("shop.orders.v1.Order.id").unwrap(),
+            "The unique identifier of the order."
+        );
+        assert_eq!(
+            descriptions
+                .get("shop.orders.v1.Order.Status.STATUS_PLACED")
+                .unwrap(),
+            "Awaiting dispatch"
+        );
+        assert_eq!(
+            descriptions
+                .get("shop.orders.v1.OrderService.GetOrder")
+                .unwrap(),
+            "Returns an order."
+        );
+        assert!(!descriptions.contains_key("shop.orders.v1.GetOrderRequest"));
+    }
+
+    #[test]
+    fn kafka_broker() {
+        let result = parse_with(
+            &parsers::KafkaPropertiesParser {},
+            "test/resources/kafka/server.properties",
+        );
+        assert_eq!(result["roles"], json!(["broker"]));
+        assert_eq!(
+            result["advertised_listeners"][1],
+            json!({"name": "EXTERNAL", "host": "kafka-1.example.com", "port": 9093, "protocol": "SASL_SSL"})
+        );
+        assert_eq!(
+            result["topic_defaults"],
+            json!({"num.partitions": "6", "default.replication.factor": "3", "auto.create.topics.enable": "false"})
+        );
+        assert_eq!(
+            result["security"]["super.users"],
+            json!(["User:admin", "User:kafka"])
+        );
+        assert_eq!(
+            result["security"]["ssl.keystore.password"],
+            json!(parsers::kubernetes::REDACTED)
+        );
+        assert!(result["security"].get("num.partitions").is_none());
+    }
+
+    #[test]
+    fn kafka_client() {
+        let result = parse_with(
+            &parsers::KafkaPropertiesParser {},
+            "test/resources/kafka/connect.properties",
+        );
+        assert_eq!(result["roles"], json!(["connect"]));
+        assert_eq!(
+            result["bootstrap_servers"],
+            json!(["kafka-1:9092", "kafka-2:9092"])
+        );
+        assert_eq!(
+            select(&result, "$.topics[*].name").unwrap(),
+            [
+                &json!("connect-configs"),
+                &json!("connect-offsets"),
+                &json!("connect-status")
+            ]
+        );
+        assert_eq!(
+            result["properties"]["sasl.jaas.config"],
+            json!(parsers::kubernetes::REDACTED)
+        );
+        assert_eq!(result["topic_defaults"], json!({}));
+
+        let options = ParserOptions::default();
+        let plain = Path::new("test/resources/properties/application.properties");
+        assert!(!parsers::KafkaPropertiesParser {}.can_parse(&ParseContext::new(plain, &options)));
+    }
+
+    #[test]
+    fn schema_registry() {
+        let result = parse_with(
+            &parsers::SchemaRegistryParser {},
+            "test/resources/kafka/subjects.json",
+        );
+        assert_eq!(
+            result["topics"],
+            json!([{"name": "orders", "key": "orders-key", "value": "orders-value"}])
+        );
+        assert_eq!(
+            select(&result, "$.subjects[*].subject").unwrap(),
+            [
+                &json!("customer-events"),
+                &json!("orders-key"),
+                &json!("orders-value")
+            ]
+        );
+        let value = &result["subjects"][2];
+        assert_eq!(value["latest"], json!(2));
+        assert_eq!(value["versions"][0]["id"], json!(10));
+        assert_eq!(
+            value["versions"][1]["fields"][2],
+            json!({"name": "note", "type": "null | string", "doc": null, "default": null})
+        );
+        assert_eq!(
+            result["subjects"][1]["versions"][0]["fields"][0]["doc"],
+            json!("The order ID")
+        );
+        assert_eq!(result["subjects"][0]["topic"], json!(null));
+        assert!(result["subjects"][0]["versions"][0]["schema"].is_string());
+    }
+
+    #[test]
+    fn postman_collection() {
+        let result = parse_with(
+            &parsers::CollectionParser {},
+            "test/resources/collections/orders.postman_collection.json",
+        );
+        assert_eq!(result["format"], json!("postman"));
+        assert_eq!(result["name"], json!("Orders API"));
+        assert_eq!(
+            result["variables"],
+            json!({"baseUrl": "https://orders.example.com"})
+        );
+        assert_eq!(
+            select(&result, "$.folders[*].path").unwrap(),
+            [&json!(""), &json!("Orders"), &json!("Orders/Admin")]
+        );
+        assert_eq!(result["folders"][0]["requests"][0]["method"], json!("GET"));
+        assert_eq!(
+            result["folders"][0]["requests"][0]["url"],
+            json!("{{baseUrl}}/health")
+        );
+        let create = &result["folders"][1]["requests"][0];
+        assert_eq!(
+            result["folders"][1]["description"],
+            json!("Create and read orders.")
+        );
+        assert_eq!(
+            create["headers"],
+            json!([{"name": "Content-Type", "value": "application/json"}])
+        );
+        assert_eq!(create["body"]["type"], json!("json"));
+        assert_eq!(create["examples"][0]["code"], json!(201));
+        assert_eq!(create["description"], json!("Creates an order."));
+        let cancel = &result["folders"][2]["requests"][0];
+        assert_eq!(cancel["method"], json!("DELETE"));
+        assert_eq!(
+            cancel["body"],
+            json!({"type": "urlencoded", "content": [{"name": "reason", "value": "duplicate"}]})
+        );
+    }
+
+    #[test]
+    fn insomnia_export() {
+        let result = parse_with(
+            &parsers::CollectionParser {},
+            "test/resources/collections/insomnia.json",
+        );
+        assert_eq!(result["format"], json!("insomnia"));
+        assert_eq!(result["name"], json!("Payments"));
+        assert_eq!(
+            result["variables"],
+            json!({"base_url": "https://payments.example.com"})
+        );
+        assert_eq!(
+            select(&result, "$.folders[*].path").unwrap(),
+            [&json!("Refunds"), &json!("")]
+        );
+        let refund = &result["folders"][0]["requests"][0];
+        assert_eq!(refund["url"], json!("{{ base_url }}/refunds"));
+        assert_eq!(
+            refund["body"],
+            json!({"type": "application/json", "content": "{\"payment\": \"p-1\"}"})
+        );
+        assert_eq!(result["folders"][0]["description"], json!(null));
+        assert_eq!(result["folders"][1]["requests"][0]["body"], json!(null));
+
+        let options = ParserOptions::default();
+        let plain = Path::new("test/resources/test.json");
+        assert!(!parsers::CollectionParser {}.can_parse(&ParseContext::new(plain, &options)));
+    }
+
+    #[test]
+    fn editorconfig() {
+        let result = parse_with(
+            &parsers::EditorConfigParser {},
+            "test/resources/conventions/.editorconfig",
+        );
+        assert_eq!(result["root"], json!(true));
+        assert_eq!(
+            result["sections"][0],
+            json!({
+                "glob": "*",
+                "description": "Defaults for all files",
+                "properties": {
+                    "charset": "utf-8",
+                    "end_of_line": "lf",
+                    "insert_final_newline": true,
+                    "indent_style": "space",
+                    "indent_size": 4
+                }
+            })
+        );
+        assert_eq!(result["sections"][1]["glob"], json!("*.{yml,yaml}"));
+        assert_eq!(
+            result["sections"][2]["description"],
+            json!("Make requires tabs")
+        );
+        assert_eq!(
+            result["globs"]["Makefile"],
+            json!({"indent_style": "tab", "indent_size": 8})
+        );
+        assert_eq!(result["globs"]["*.{yml,yaml}"], json!({"indent_size": 2}));
+    }
+
+    #[test]
+    fn gitattributes() {
+        // The fixture is not named .gitattributes, so that Git does not apply it
+        let options = ParserOptions::default();
+        let text = std::fs::read_to_string("test/resources/conventions/gitattributes").unwrap();
+        let context = ParseContext::with_text(Path::new(".gitattributes"), &options, &text);
+        assert!(parsers::GitAttributesParser {}.can_parse(&context));
+        let result = parsers::GitAttributesParser {}
+            .parse(&context)
+            .unwrap()
+            .value;
+        assert_eq!(
+            result["patterns"][0],
+            json!({
+                "pattern": "*",
+                "description": "Normalise line endings",
+                "attributes": {"text": "auto", "eol": "lf"}
+            })
+        );
+        assert_eq!(
+            result["macros"],
+            json!({"generated": {"diff": false, "linguist-generated": true}})
+        );
+        assert_eq!(result["lfs"], json!(["*.psd"]));
+        assert_eq!(result["binary"], json!(["*.psd", "*.ico"]));
+        assert_eq!(
+            result["patterns"][5],
+            json!({
+                "pattern": "docs/api spec.json",
+                "description": null,
+                "attributes": {"generated": true, "eol": null}
+            })
+        );
+    }
+
+    #[test]
+    fn gitconfig() {
+        let result = parse_with(
+            &parsers::GitConfigParser {},
+            "test/resources/conventions/.gitconfig",
+        );
+        let sections = &result["sections"];
+        assert_eq!(sections["core"]["editor"], json!("code --wait"));
+        assert_eq!(sections["pull"]["rebase"], json!(true));
+        assert_eq!(sections["alias"]["lg"], json!("log --graph \t\t--oneline"));
+        // Keys from the included work.gitconfig are merged into their sections
+        assert_eq!(
+            sections["remote"]["origin"]["fetch"],
+            json!([
+                "+refs/heads/*:refs/remotes/origin/*",
+                "+refs/tags/*:refs/tags/*",
+                "+refs/notes/*:refs/notes/*"
+            ])
+        );
+        assert_eq!(sections["user"]["signingkey"], json!("0A46826A"));
+        assert_eq!(
+            sections["http"]["https://git.example.com"]["extraheader"],
+            json!(crate::parsers::kubernetes::REDACTED)
+        );
+        assert_eq!(
+            result["includes"],
+            json!([
+                {"path": "~/.gitconfig-work", "condition": "gitdir:~/work/", "included": false},
+                {"path": "work.gitconfig", "condition": null, "included": true},
+                {"path": "missing.gitconfig", "condition": null, "included": false}
+            ])
+        );
+
+        let dir = std::env::temp_dir().join(format!("kvasir-gitconfig-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(".gitconfig");
+        std::fs::write(&path, "[include]\n\tpath = .gitconfig\n").unwrap();
+        let options = ParserOptions::default();
+        let error = parsers::GitConfigParser {}
+            .parse(&ParseContext::new(&path, &options))
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Include on line 2 of {} exceeds the maximum depth of 10, which may be a cycle",
+                path.display()
+            )
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+        for path in [".lfsconfig", "home/.config/git/config", "work.gitconfig"] {
+            assert!(parsers::GitConfigParser {}
+                .can_parse(&ParseContext::new(Path::new(path), &options)));
+        }
+        assert!(!parsers::GitConfigParser {}
+            .can_parse(&ParseContext::new(Path::new("deploy/git/config"), &options)));
+
+        let modules = parse_with(
+            &parsers::GitConfigParser {},
+            "test/resources/conventions/.gitmodules",
+        );
+        assert_eq!(
+            modules["submodules"],
+            json!([{
+                "name": "vendor/schemas",
+                "path": "vendor/schemas",
+                "url": "https://github.com/example/schemas.git",
+                "branch": "main"
+            }])
+        );
+    }
+
+    #[test]
+    fn renovate() {
+        let result = parse_with(
+            &parsers::RenovateParser {},
+            "test/resources/updates/renovate.json5",
+        );
+        assert_eq!(
+            result["extends"],
+            json!(["config:recommended", ":semanticCommits"])
+        );
+        assert_eq!(result["schedule"], json!(["before 6am on monday"]));
+        assert_eq!(result["ignore_deps"], json!(["legacy-sdk"]));
+        assert_eq!(
+            result["package_rules"][0],
+            json!({
+                "description": "Automerge patch updates of dev dependencies",
+                "match": {"matchDepTypes": ["devDependencies"], "matchUpdateTypes": ["patch"]},
+                "settings": {"automerge": true}
+            })
+        );
+        assert_eq!(
+            result["package_rules"][1]["settings"],
+            json!({"groupName": "AWS SDK"})
+        );
+        assert_eq!(
+            result["vulnerability_alerts"],
+            json!({"labels": ["security"]})
+        );
+
+        let options = ParserOptions::default();
+        let context = ParseContext::with_text(
+            Path::new("renovate.json"),
+            &options,
+            r#"{"extends": ["config:base"], "automerge": true}"#,
+        );
+        let result = parsers::RenovateParser {}.parse(&context).unwrap().value;
+        assert_eq!(result["automerge"], json!(true));
+        assert_eq!(result["package_rules"], json!([]));
+    }
+
+    #[test]
+    fn dependabot() {
+        let result = parse_with(
+            &parsers::DependabotParser {},
+            "test/resources/updates/.github/dependabot.yml",
+        );
+        assert_eq!(
+            result["registries"],
+            json!([{
+                "type": "npm-registry",
+                "url": "https://npm.example.com",
+                "token": crate::parsers::kubernetes::REDACTED,
+                "name": "npm-internal"
+            }])
+        );
+        let npm = &result["updates"][0];
+        assert_eq!(npm["ecosystem"], json!("npm"));
+        assert_eq!(npm["directories"], json!(["/"]));
+        assert_eq!(npm["schedule"]["interval"], json!("weekly"));
+        assert_eq!(npm["open_pull_requests_limit"], json!(5));
+        assert_eq!(npm["ignore"], json!([{"dependency-name": "legacy-sdk"}]));
+        assert_eq!(npm["groups"][0]["name"], json!("aws"));
+        assert_eq!(npm["groups"][0]["patterns"], json!(["@aws-sdk/*"]));
+        let docker = &result["updates"][1];
+        assert_eq!(
+            docker["directories"],
+            json!(["/services/api", "/services/web"])
+        );
+        assert_eq!(docker["labels"], json!(["docker", "dependencies"]));
+    }
+
+    #[test]
+    fn license_file() {
+        let result = parse_with(
+            &parsers::LicenseFileParser {},
+            "test/resources/licenses/COPYING",
+        );
+        assert_eq!(
+            result,
+            json!({
+                "license": "GPL-3.0",
+                "name": "GNU General Public License v3.0",
+                "copyright": ["Copyright (C) 2007 Free Software Foundation, Inc. <https://fsf.org/>"]
+            })
+        );
+    }
+
+    #[test]
+    fn terragrunt() {
+        let result = parse_with(
+            &parsers::TerragruntParser {},
+            "test/resources/terragrunt/prod/app/terragrunt.hcl",
+        );
+        assert_eq!(
+            result["includes"],
+            json!([{
+                "name": "env",
+                "path": "${find_in_parent_folders(\"env.hcl\")}",
+                "resolved": "../env.hcl",
+                "merge_strategy": "deep",
+                "expose": true
+            }])
+        );
+        assert_eq!(result["chain"], json!(["../../root.hcl", "../env.hcl"]));
+        assert_eq!(
+            result["source"],
+            json!("git::git@github.com:acme/modules.git//service?ref=v1.2.0")
+        );
+        assert_eq!(
+            result["remote_state"]["config"]["bucket"],
+            json!("acme-tf-state")
+        );
+        assert_eq!(
+            result["inputs"],
+            json!({
+                "company": "acme",
+                "environment": "prod",
+                "tags": {"cost_centre": "platform", "service": "orders"},
+                "name": "orders-api",
+                "replicas": 3,
+                "vpc_id": "${dependency.vpc.outputs.vpc_id}"
+            })
+        );
+        assert_eq!(
+            result["dependencies"],
+            json!([
+                {"name": "vpc", "config_path": "../vpc"},
+                {"name": null, "config_path": "../database"}
+            ])
+        );
+        assert!(result["effective"].get("locals").is_some());
+        assert_eq!(result["effective"]["locals"]["account"], json!(null));
+    }
+
+    #[test]
+    fn vault_policy() {
+        let result = parse_with(
+            &parsers::VaultPolicyParser {},
+            "test/resources/hashicorp/app-policy.hcl",
+        );
+        assert_eq!(
+            select(&result, "$.paths[*].path").unwrap(),
+            [
+                &json!("secret/data/orders/*"),
+                &json!("database/creds/orders"),
+                &json!("transit/encrypt/+"),
+                &json!("sys/*")
+            ]
+        );
+        assert_eq!(
+            result["paths"][1]["description"],
+            json!("Issue database credentials\nfor the orders role")
+        );
+        assert_eq!(result["paths"][2]["wildcard"], json!(true));
+        assert_eq!(
+            result["paths"][2]["allowed_parameters"],
+            json!({"plaintext": []})
+        );
+        assert_eq!(result["paths"][2]["max_wrapping_ttl"], json!("1h"));
+        assert_eq!(
+            result["by_capability"]["read"],
+            json!(["secret/data/orders/*", "database/creds/orders"])
+        );
+        assert_eq!(result["denied"], json!(["sys/*"]));
+
+        let options = ParserOptions::default();
+        let agent = Path::new("test/resources/hashicorp/consul-agent.hcl");
+        assert!(!parsers::VaultPolicyParser {}.can_parse(&ParseContext::new(agent, &options)));
+    }
+
+    #[test]
+    fn consul_agent() {
+        let result = parse_with(
+            &parsers::ConsulConfigParser {},
+            "test/resources/hashicorp/consul-agent.hcl",
+        );
+        assert_eq!(result["kind"], json!("agent"));
+        assert_eq!(result["datacenter"], json!("eu-west-2"));
+        assert_eq!(result["server"], json!(true));
+        assert_eq!(
+            result["acl"],
+            json!({"enabled": true, "default_policy": "deny"})
+        );
+        let redacted = json!(crate::parsers::kubernetes::REDACTED);
+        assert_eq!(result["config"]["encrypt"], redacted);
+        assert_eq!(
+            result["config"]["acl"]["tokens"]["initial_management"],
+            redacted
+        );
+        assert_eq!(result["config"].get("service"), None);
+        assert_eq!(
+            result["services"],
+            json!([{
+                "name": "orders",
+                "id": "orders",
+                "address": null,
+                "port": 8080,
+                "tags": ["api", "v1"],
+                "meta": {},
+                "checks": [{
+                    "name": null,
+                    "type": "http",
+                    "target": "http://localhost:8080/health",
+                    "interval": "10s",
+                    "timeout": null
+                }],
+                "connect": {
+                    "native": false,
+                    "sidecar": true,
+                    "upstreams": [{"destination_name": "payments", "local_bind_port": 9191}]
+                }
+            }])
+        );
+    }
+
+    #[test]
+    fn consul_services() {
+        let result = parse_with(
+            &parsers::ConsulConfigParser {},
+            "test/resources/hashicorp/services.json",
+        );
+        assert_eq!(result["kind"], json!("services"));
+        assert_eq!(result["services"][0]["id"], json!("web-1"));
+        assert_eq!(
+            select(&result, "$.services[0].checks[*].type").unwrap(),
+            [&json!("tcp"), &json!("args")]
+        );
+        assert_eq!(
+            result["services"][0]["checks"][1]["target"],
+            json!("/bin/check --web")
+        );
+    }
+
+    #[test]
+    fn sops() {
+        let encrypted = json!(parsers::sops::ENCRYPTED);
+        let result = parse_with(&parsers::SopsParser {}, "test/resources/sops/config.json");
+        assert_eq!(result["values"]["database"]["host"], json!("db.internal"));
+        assert_eq!(result["values"]["database"]["port"], encrypted);
+        assert_eq!(result["encrypted"], json!(["database.port", "replicas[0]"]));
+        assert_eq!(
+            result["sops"],
+            json!({
+                "version": "3.8.1",
+                "lastmodified": "2024-03-01T09:30:00Z",
+                "keys": [{
+                    "type": "kms",
+                    "id": "arn:aws:kms:eu-west-2:111122223333:key/1234abcd-12ab-34cd-56ef-1234567890ab"
+                }],
+                "unencrypted_suffix": "_unencrypted"
+            })
+        );
+
+        let path = "test/resources/sops/secret.yaml";
+        let result = parse_with(&parsers::SopsParser {}, path);
+        assert_eq!(
+            result["encrypted"],
+            json!(["data.password", "data.username"])
+        );
+        assert_eq!(result["sops"]["keys"][0]["type"], json!("age"));
+        assert_eq!(
+            result["sops"]["encrypted_regex"],
+            json!("^(data|stringData)$")
+        );
+
+        let options = ParserOptions::default();
+        let parser = parsers::KubernetesParser::new(&options);
+        let document = parser
+            .parse(&ParseContext::new(Path::new(path), &options))
+            .unwrap();
+        assert_eq!(document.value["data"]["password"], encrypted);
+        assert_eq!(document.value["stringData"]["host"], json!("db.internal"));
+        assert_eq!(document.value.get("sops"), None);
+        assert_eq!(document.metadata["encryption"], json!("sops"));
+    }
+
+    #[test]
+    fn codeowners() {
+        let result = parse_with(
+            &parsers::CodeOwnersParser {},
+            "test/resources/codeowners/.github/CODEOWNERS",
+        );
+        assert_eq!(
+            result["rules"][2],
+            json!({
+                "line": 6,
+                "pattern": "services/*.json",
+                "owners": ["@credera/services", "@credera/api"],
+                "section": null
+            })
+        );
+        assert_eq!(
+            select(&result, "$.rules[3:].owners").unwrap(),
+            [&json!(["@credera/docs"]), &json!(["@credera/docs"])]
+        );
+        assert_eq!(
+            result["owners"][1],
+            json!({"owner": "@credera/services", "patterns": ["/services/", "services/*.json"]})
+        );
+        assert_eq!(
+            result["sections"],
+            json!([{
+                "name": "Documentation",
+                "optional": false,
+                "approvals": null,
+                "owners": ["@credera/docs"]
+            }])
+        );
+    }
 }
@@ -0,0 +1,885 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Parser for Protocol Buffers schemas in `.proto` files, in proto2, proto3 or editions
+//! syntax.
+//!
+//! As with `protoc`, the comments immediately preceding a declaration, or failing that the
+//! comment following it on the same line, are its description. Types are returned as
+//! written, without resolving them against imported files.
+
+use super::{Capabilities, Descriptions, FileParser, ParseContext, ParsedDocument, ParserOptions};
+use crate::errors::*;
+use serde_json::{json, Map, Number, Value};
+
+/// A token of a `.proto` file.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// An identifier, which may be qualified, e.g. `google.protobuf.Timestamp`.
+    Ident(String),
+    /// A number, as written.
+    Number(String),
+    /// A string literal, without quotes and with escapes resolved.
+    Str(String),
+    Symbol(char),
+}
+
+/// A token with its line and the comments attached to it.
+#[derive(Debug)]
+struct Lexed {
+    token: Token,
+    line: usize,
+    /// The comments on the lines immediately preceding the token.
+    leading: Option<String>,
+    /// The comment following the token on the same line.
+    trailing: Option<String>,
+}
+
+/// Remove the leading `*` of each line of a block comment, and surrounding blank lines.
+fn block_comment(text: &str) -> String {
+    text.lines()
+        .map(|l| {
+            let l = l.trim();
+            l.strip_prefix('*').unwrap_or(l).trim()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// Resolve the escapes of a string literal.
+fn unescape(chars: &mut std::iter::Peekable<std::str::Chars>, quote: char) -> Option<String> {
+    let mut value = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                'r' => value.push('\r'),
+                '0' => value.push('\0'),
+                c => value.push(c),
+            },
+            '\n' => return None,
+            c if c == quote => return Some(value),
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+/// Split the text of a `.proto` file into tokens, attaching comments to them.
+fn tokenize(text: &str) -> Result<Vec<Lexed>> {
+    let mut tokens: Vec<Lexed> = vec![];
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    // Comments since the last token, and the line on which the last of them ended
+    let mut comments: Vec<String> = vec![];
+    let mut comment_end = 0;
+    while let Some(&c) = chars.peek() {
+        let start = line;
+        if c == '\n' {
+            line += 1;
+            chars.next();
+            continue;
+        }
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        if c == '/' {
+            chars.next();
+            let comment = match chars.next() {
+                Some('/') => {
+                    let mut comment = String::new();
+                    while let Some(c) = chars.next_if(|c| *c != '\n') {
+                        comment.push(c);
+                    }
+                    comment.trim().to_string()
+                }
+                Some('*') => {
+                    let mut comment = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('*') if chars.peek() == Some(&'/') => {
+                                chars.next();
+                                break;
+                            }
+                            Some(c) => {
+                                if c == '\n' {
+                                    line += 1;
+                                }
+                                comment.push(c)
+                            }
+                            None => bail!(format!("Unterminated comment on line {}", start)),
+                        }
+                    }
+                    block_comment(&comment)
+                }
+                _ => bail!(format!("Unexpected '/' on line {}", start)),
+            };
+            match tokens.last_mut() {
+                // A comment on the same line as the previous token is its trailing comment
+                Some(previous) if previous.line == start && comments.is_empty() => {
+                    previous.trailing = Some(comment)
+                }
+                _ => {
+                    // Comments separated by a blank line are detached from the token
+                    if !comments.is_empty() && start > comment_end + 1 {
+                        comments.clear();
+                    }
+                    comments.push(comment);
+                    comment_end = line;
+                }
+            }
+            continue;
+        }
+
+        let token = if c.is_ascii_alphabetic() || c == '_' || c == '.' {
+            let mut ident = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || "_.".contains(*c)) {
+                ident.push(c);
+            }
+            Token::Ident(ident)
+        } else if c.is_ascii_digit() {
+            let mut number = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '.') {
+                number.push(c);
+                // Exponents may be signed, e.g. `1e-5`
+                if "eE".contains(c) && !number.starts_with("0x") {
+                    if let Some(sign) = chars.next_if(|c| "+-".contains(*c)) {
+                        number.push(sign);
+                    }
+                }
+            }
+            Token::Number(number)
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            match unescape(&mut chars, c) {
+                Some(value) => Token::Str(value),
+                None => bail!(format!("Unterminated string on line {}", start)),
+            }
+        } else {
+            chars.next();
+            Token::Symbol(c)
+        };
+        let leading =
+            (!comments.is_empty() && start <= comment_end + 1).then(|| comments.join("\n"));
+        comments.clear();
+        tokens.push(Lexed {
+            token,
+            line: start,
+            leading,
+            trailing: None,
+        });
+    }
+    Ok(tokens)
+}
+
+/// Convert a number as written to JSON.
+fn number(text: &str, negative: bool) -> Option<Value> {
+    let sign = if negative { -1 } else { 1 };
+    let integer = match text {
+        t if t.starts_with("0x") || t.starts_with("0X") => i64::from_str_radix(&t[2..], 16).ok(),
+        t if t.len() > 1 && t.starts_with('0') && t.chars().all(|c| c.is_ascii_digit()) => {
+            i64::from_str_radix(&t[1..], 8).ok()
+        }
+        t => t.parse::<i64>().ok(),
+    };
+    match integer {
+        Some(i) => Some(json!(sign * i)),
+        None => text
+            .parse::<f64>()
+            .ok()
+            .and_then(|f| Number::from_f64(sign as f64 * f))
+            .map(Value::Number),
+    }
+}
+
+/// A recursive descent parser over the tokens of a `.proto` file.
+struct Parser {
+    tokens: Vec<Lexed>,
+    position: usize,
+    package: Option<String>,
+    messages: Vec<Value>,
+    enums: Vec<Value>,
+    extensions: Vec<Value>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|t| &t.token)
+    }
+
+    /// Return the line of the current token, or of the last token at the end of the file.
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .or(self.tokens.last())
+            .map_or(1, |t| t.line)
+    }
+
+    fn next(&mut self) -> Result<&Lexed> {
+        let line = self.line();
+        self.position += 1;
+        match self.tokens.get(self.position - 1) {
+            Some(token) => Ok(token),
+            None => bail!(format!("Unexpected end of file after line {}", line)),
+        }
+    }
+
+    /// Return whether the current token is the given symbol, consuming it if so.
+    fn accept(&mut self, symbol: char) -> bool {
+        let accepted = self.peek() == Some(&Token::Symbol(symbol));
+        if accepted {
+            self.position += 1;
+        }
+        accepted
+    }
+
+    /// Return whether the current token is the given keyword, consuming it if so.
+    fn accept_keyword(&mut self, keyword: &str) -> bool {
+        let accepted = matches!(self.peek(), Some(Token::Ident(i)) if i == keyword);
+        if accepted {
+            self.position += 1;
+        }
+        accepted
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<()> {
+        let line = self.line();
+        match &self.next()?.token {
+            Token::Symbol(c) if *c == symbol => Ok(()),
+            token => bail!(format!(
+                "Expected '{}' but found {:?} on line {}",
+                symbol, token, line
+            )),
+        }
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        let line = self.line();
+        match &self.next()?.token {
+            Token::Ident(ident) => Ok(ident.to_owned()),
+            token => bail!(format!(
+                "Expected a name but found {:?} on line {}",
+                token, line
+            )),
+        }
+    }
+
+    /// Read a string literal, concatenating adjacent literals.
+    fn string(&mut self) -> Result<String> {
+        let line = self.line();
+        let mut value = match &self.next()?.token {
+            Token::Str(s) => s.to_owned(),
+            token => bail!(format!(
+                "Expected a string but found {:?} on line {}",
+                token, line
+            )),
+        };
+        while let Some(Token::Str(s)) = self.peek() {
+            value.push_str(&s.to_owned());
+            self.position += 1;
+        }
+        Ok(value)
+    }
+
+    /// Read an integer, such as a field number.
+    fn integer(&mut self) -> Result<Value> {
+        let line = self.line();
+        let negative = self.accept('-');
+        match &self.next()?.token {
+            Token::Number(n) => match number(n, negative) {
+                Some(value) if value.is_i64() => Ok(value),
+                _ => bail!(format!("Invalid integer {} on line {}", n, line)),
+            },
+            token => bail!(format!(
+                "Expected a number but found {:?} on line {}",
+                token, line
+            )),
+        }
+    }
+
+    /// Return the description of a declaration from the leading comments of its first token
+    /// or the trailing comment of its last token.
+    fn description(&self, first: usize, last: usize) -> Value {
+        let comment = |i: usize, leading: bool| {
+            self.tokens.get(i).and_then(|t| match leading {
+                true => t.leading.to_owned(),
+                false => t.trailing.to_owned(),
+            })
+        };
+        json!(comment(first, true).or_else(|| comment(last, false)))
+    }
+
+    /// Return the full name of a declaration within a scope.
+    fn full_name(&self, scope: &str, name: &str) -> String {
+        match (scope.is_empty(), &self.package) {
+            (false, _) => format!("{}.{}", scope, name),
+            (true, Some(package)) => format!("{}.{}", package, name),
+            (true, None) => name.to_string(),
+        }
+    }
+
+    /// Read the name of an option, such as `java_package` or `(validate.rules).string`.
+    fn option_name(&mut self) -> Result<String> {
+        let mut name = String::new();
+        loop {
+            match self.peek() {
+                Some(Token::Symbol('=')) | None => break,
+                Some(Token::Symbol(c @ ('(' | ')'))) => name.push(*c),
+                Some(Token::Ident(i)) => name.push_str(i),
+                Some(token) => bail!(format!(
+                    "Unexpected {:?} in option name on line {}",
+                    token,
+                    self.line()
+                )),
+            }
+            self.position += 1;
+        }
+        Ok(name)
+    }
+
+    /// Read the value of an option, which may be a message literal in text format.
+    fn value(&mut self) -> Result<Value> {
+        let line = self.line();
+        if self.accept('{') {
+            return self.aggregate('}');
+        }
+        if self.accept('<') {
+            return self.aggregate('>');
+        }
+        if self.accept('[') {
+            let mut values = vec![];
+            while !self.accept(']') {
+                values.push(self.value()?);
+                self.accept(',');
+            }
+            return Ok(json!(values));
+        }
+        let negative = self.accept('-');
+        if let Some(Token::Str(_)) = self.peek() {
+            return Ok(json!(self.string()?));
+        }
+        Ok(match &self.next()?.token {
+            Token::Number(n) => match number(n, negative) {
+                Some(value) => value,
+                None => bail!(format!("Invalid number {} on line {}", n, line)),
+            },
+            Token::Ident(i) => match i.as_str() {
+                "true" => json!(true),
+                "false" => json!(false),
+                "inf" | "nan" => json!(format!("{}{}", if negative { "-" } else { "" }, i)),
+                _ => json!(i),
+            },
+            token => bail!(format!(
+                "Unexpected {:?} in option value on line {}",
+                token, line
+            )),
+        })
+    }
+
+    /// Read a message literal in text format, up to its closing delimiter, repeating fields
+    /// given more than once as arrays.
+    fn aggregate(&mut self, close: char) -> Result<Value> {
+        let mut fields = Map::new();
+        while !self.accept(close) {
+            let name = match self.accept('[') {
+                true => {
+                    let name = self.ident()?;
+                    self.expect(']')?;
+                    format!("[{}]", name)
+                }
+                false => self.ident()?,
+            };
+            self.accept(':');
+            let value = self.value()?;
+            match fields.get_mut(&name) {
+                Some(Value::Array(values)) => values.push(value),
+                Some(existing) => *existing = json!([existing.take(), value]),
+                None => {
+                    fields.insert(name, value);
+                }
+            }
+            let _ = self.accept(',') || self.accept(';');
+        }
+        Ok(Value::Object(fields))
+    }
+
+    /// Read an `option name = value;` statement, after the `option` keyword, into options.
+    fn option(&mut self, options: &mut Map<String, Value>) -> Result<()> {
+        let name = self.option_name()?;
+        self.expect('=')?;
+        let value = self.value()?;
+        self.expect(';')?;
+        options.insert(name, value);
+        Ok(())
+    }
+
+    /// Read the options in brackets after a field or enum value, if any.
+    fn field_options(&mut self) -> Result<Map<String, Value>> {
+        let mut options = Map::new();
+        if self.accept('[') {
+            loop {
+                let name = self.option_name()?;
+                self.expect('=')?;
+                options.insert(name, self.value()?);
+                if !self.accept(',') {
+                    break;
+                }
+            }
+            self.expect(']')?;
+        }
+        Ok(options)
+    }
+
+    /// Read the ranges and names of a `reserved` or `extensions` statement, after the
+    /// keyword.
+    fn ranges(&mut self) -> Result<(Vec<Value>, Vec<Value>)> {
+        let mut ranges = vec![];
+        let mut names = vec![];
+        loop {
+            match self.peek() {
+                Some(Token::Str(_)) => names.push(json!(self.string()?)),
+                // Names are identifiers in editions
+                Some(Token::Ident(_)) => names.push(json!(self.ident()?)),
+                _ => {
+                    let start = self.integer()?;
+                    let end = match self.accept_keyword("to") {
+                        true if self.accept_keyword("max") => json!("max"),
+                        true => self.integer()?,
+                        false => start.to_owned(),
+                    };
+                    ranges.push(json!({"start": start, "end": end}));
+                }
+            }
+            if !self.accept(',') {
+                break;
+            }
+        }
+        // Extension ranges may have options
+        self.field_options()?;
+        self.expect(';')?;
+        Ok((ranges, names))
+    }
+
+    /// Read a field, from its label or type to its terminating `;`, adding any group it
+    /// declares to the messages.
+    fn field(&mut self, scope: &str, oneof: Option<&str>) -> Result<Value> {
+        let first = self.position;
+        let label = match self.peek() {
+            Some(Token::Ident(i)) if ["optional", "repeated", "required"].contains(&i.as_str()) => {
+                let label = i.to_owned();
+                self.position += 1;
+                Some(label)
+            }
+            _ => None,
+        };
+        let mut map = None;
+        let field_type = match self.ident()?.as_str() {
+            "map" if self.accept('<') => {
+                let key = self.ident()?;
+                self.expect(',')?;
+                let value = self.ident()?;
+                self.expect('>')?;
+                map = Some(json!({"key_type": key, "value_type": value}));
+                format!("map<{}, {}>", key, value)
+            }
+            field_type => field_type.to_string(),
+        };
+        let mut name = self.ident()?;
+        self.expect('=')?;
+        let number = self.integer()?;
+        let options = self.field_options()?;
+        let mut field_type = field_type;
+        if field_type == "group" {
+            // Groups declare a message, named after the group, and a field of that type
+            field_type = name.to_owned();
+            name = name.to_lowercase();
+            self.expect('{')?;
+            self.message_body(&field_type, scope, first)?;
+        } else {
+            self.expect(';')?;
+        }
+        let mut field = json!({
+            "name": name,
+            "number": number,
+            "type": field_type,
+            "label": label,
+            "oneof": oneof,
+            "options": options,
+            "description": self.description(first, self.position - 1),
+        });
+        if let Some(map) = map {
+            field["map"] = map;
+        }
+        Ok(field)
+    }
+
+    /// Read an enum, after the `enum` keyword, adding it to the enums.
+    fn enumeration(&mut self, scope: &str) -> Result<()> {
+        let first = self.position - 1;
+        let name = self.ident()?;
+        self.expect('{')?;
+        let description = self.description(first, self.position - 1);
+        let mut values = vec![];
+        let mut options = Map::new();
+        let mut reserved_ranges = vec![];
+        let mut reserved_names = vec![];
+        while !self.accept('}') {
+            let start = self.position;
+            if self.accept(';') {
+                continue;
+            } else if self.accept_keyword("option") {
+                self.option(&mut options)?;
+            } else if self.accept_keyword("reserved") {
+                let (ranges, names) = self.ranges()?;
+                reserved_ranges.extend(ranges);
+                reserved_names.extend(names);
+            } else {
+                let value_name = self.ident()?;
+                self.expect('=')?;
+                let number = self.integer()?;
+                let value_options = self.field_options()?;
+                self.expect(';')?;
+                values.push(json!({
+                    "name": value_name,
+                    "number": number,
+                    "options": value_options,
+                    "description": self.description(start, self.position - 1),
+                }));
+            }
+        }
+        self.enums.push(json!({
+            "name": name,
+            "full_name": self.full_name(scope, &name),
+            "description": description,
+            "values": values,
+            "reserved": {"ranges": reserved_ranges, "names": reserved_names},
+            "options": options,
+        }));
+        Ok(())
+    }
+
+    /// Read an `extend` block, after the `extend` keyword, adding its fields to the
+    /// extensions.
+    fn extend(&mut self, scope: &str) -> Result<()> {
+        let extendee = self.ident()?;
+        self.expect('{')?;
+        let mut fields = vec![];
+        while !self.accept('}') {
+            if !self.accept(';') {
+                fields.push(self.field(scope, None)?);
+            }
+        }
+        self.extensions.push(json!({
+            "extendee": extendee,
+            "scope": scope,
+            "fields": fields,
+        }));
+        Ok(())
+    }
+
+    /// Read a message, after the `message` keyword, adding it and any nested messages to
+    /// the messages.
+    fn message(&mut self, scope: &str) -> Result<()> {
+        let first = self.position - 1;
+        let name = self.ident()?;
+        self.expect('{')?;
+        self.message_body(&name, scope, first)
+    }
+
+    /// Read the body of a message, after its opening brace, adding it to the messages.
+    fn message_body(&mut self, name: &str, scope: &str, first: usize) -> Result<()> {
+        let full_name = self.full_name(scope, name);
+        let description = self.description(first, self.position - 1);
+        // Messages are listed before the messages nested within them
+        let index = self.messages.len();
+        self.messages.push(Value::Null);
+        let mut fields = vec![];
+        let mut oneofs = vec![];
+        let mut options = Map::new();
+        let mut reserved_ranges = vec![];
+        let mut reserved_names = vec![];
+        let mut extension_ranges = vec![];
+        while !self.accept('}') {
+            if self.accept(';') {
+                continue;
+            } else if self.accept_keyword("option") {
+                self.option(&mut options)?;
+            } else if self.accept_keyword("message") {
+                self.message(&full_name)?;
+            } else if self.accept_keyword("enum") {
+                self.enumeration(&full_name)?;
+            } else if self.accept_keyword("extend") {
+                self.extend(&full_name)?;
+            } else if self.accept_keyword("reserved") {
+                let (ranges, names) = self.ranges()?;
+                reserved_ranges.extend(ranges);
+                reserved_names.extend(names);
+            } else if self.accept_keyword("extensions") {
+                extension_ranges.extend(self.ranges()?.0);
+            } else if self.accept_keyword("oneof") {
+                let first = self.position - 1;
+                let oneof = self.ident()?;
+                self.expect('{')?;
+                let oneof_description = self.description(first, self.position - 1);
+                let mut oneof_options = Map::new();
+                let mut names = vec![];
+                while !self.accept('}') {
+                    if self.accept(';') {
+                        continue;
+                    } else if self.accept_keyword("option") {
+                        self.option(&mut oneof_options)?;
+                    } else {
+                        let field = self.field(&full_name, Some(&oneof))?;
+                        names.push(field["name"].to_owned());
+                        fields.push(field);
+                    }
+                }
+                oneofs.push(json!({
+                    "name": oneof,
+                    "description": oneof_description,
+                    "fields": names,
+                    "options": oneof_options,
+                }));
+            } else {
+                fields.push(self.field(&full_name, None)?);
+            }
+        }
+        let parent = (!scope.is_empty()).then(|| scope.to_string());
+        self.messages[index] = json!({
+            "name": name,
+            "full_name": full_name,
+            "parent": parent,
+            "description": description,
+            "fields": fields,
+            "oneofs": oneofs,
+            "reserved": {"ranges": reserved_ranges, "names": reserved_names},
+            "extension_ranges": extension_ranges,
+            "options": options,
+        });
+        Ok(())
+    }
+
+    /// Read a service, after the `service` keyword.
+    fn service(&mut self) -> Result<Value> {
+        let first = self.position - 1;
+        let name = self.ident()?;
+        self.expect('{')?;
+        let description = self.description(first, self.position - 1);
+        let mut methods = vec![];
+        let mut options = Map::new();
+        while !self.accept('}') {
+            if self.accept(';') {
+                continue;
+            } else if self.accept_keyword("option") {
+                self.option(&mut options)?;
+            } else if self.accept_keyword("rpc") {
+                let first = self.position - 1;
+                let method = self.ident()?;
+                self.expect('(')?;
+                let client_streaming = self.accept_keyword("stream");
+                let input_type = self.ident()?;
+                self.expect(')')?;
+                if !self.accept_keyword("returns") {
+                    bail!(format!("Expected returns on line {}", self.line()))
+                }
+                self.expect('(')?;
+                let server_streaming = self.accept_keyword("stream");
+                let output_type = self.ident()?;
+                self.expect(')')?;
+                let mut method_options = Map::new();
+                if self.accept('{') {
+                    while !self.accept('}') {
+                        if self.accept_keyword("option") {
+                            self.option(&mut method_options)?;
+                        } else {
+                            self.expect(';')?;
+                        }
+                    }
+                    self.accept(';');
+                } else {
+                    self.expect(';')?;
+                }
+                methods.push(json!({
+                    "name": method,
+                    "input_type": input_type,
+                    "output_type": output_type,
+                    "client_streaming": client_streaming,
+                    "server_streaming": server_streaming,
+                    "options": method_options,
+                    "description": self.description(first, self.position - 1),
+                }));
+            } else {
+                bail!(format!(
+                    "Expected an rpc or option in service {} on line {}",
+                    name,
+                    self.line()
+                ))
+            }
+        }
+        Ok(json!({
+            "name": name,
+            "full_name": self.full_name("", &name),
+            "description": description,
+            "methods": methods,
+            "options": options,
+        }))
+    }
+}
+
+/// File parser for Protocol Buffers schemas in `.proto` files.
+///
+/// Returns the `syntax` (`proto2`, `proto3` or `editions`) and any `edition`, the `package`,
+/// `imports` and file `options`, and lists of the `messages`, `enums`, `services` and
+/// `extensions` declared in the file. Nested messages and enums are listed alongside
+/// top-level ones, with their `full_name` and the `parent` message. Messages have their
+/// `fields`, each with its `name`, `number`, `type`, any `label`, `oneof` and `map` key and
+/// value types, and its `options`, their `oneofs`, `reserved` numbers and names and
+/// `options`. Enums have their `values`, and services their `methods` with input and output
+/// types and whether they stream. Each declaration has the `description` from its comments.
+///
+/// If the `comments` option is set, the descriptions are also returned keyed by the full
+/// name of each message, enum and service, and of their fields, values and methods, e.g.
+/// `shop.v1.Order.id`.
+pub struct ProtoParser {
+    comments: bool,
+}
+
+impl ProtoParser {
+    /// Create a new parser using the `protobuf` parser options.
+    pub fn new(options: &ParserOptions) -> ProtoParser {
+        ProtoParser {
+            comments: options.flag("protobuf", "comments"),
+        }
+    }
+}
+
+/// Return the descriptions of the declarations of a parsed file, keyed by their full names.
+fn descriptions(value: &Value) -> Descriptions {
+    let mut descriptions = Descriptions::new();
+    let mut describe = |name: String, declaration: &Value| {
+        if let Some(description) = declaration["description"].as_str() {
+            descriptions.insert(name, description.to_string());
+        }
+    };
+    let declarations = [
+        ("messages", "fields"),
+        ("enums", "values"),
+        ("services", "methods"),
+    ];
+    for (kind, members) in declarations {
+        for declaration in value[kind].as_array().into_iter().flatten() {
+            let full_name = declaration["full_name"].as_str().unwrap_or_default();
+            describe(full_name.to_string(), declaration);
+            for member in declaration[members].as_array().into_iter().flatten() {
+                let name = member["name"].as_str().unwrap_or_default();
+                describe(format!("{}.{}", full_name, name), member);
+            }
+        }
+    }
+    descriptions
+}
+
+impl FileParser for ProtoParser {
+    fn name(&self) -> &'static str {
+        "protobuf"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["proto"],
+            descriptions: true,
+            ..Default::default()
+        }
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let mut parser = Parser {
+            tokens: tokenize(context.text()?)?,
+            position: 0,
+            package: None,
+            messages: vec![],
+            enums: vec![],
+            extensions: vec![],
+        };
+        let mut syntax = "proto2".to_string();
+        let mut edition = None;
+        let mut imports = vec![];
+        let mut options = Map::new();
+        let mut services = vec![];
+        while parser.peek().is_some() {
+            if parser.accept(';') {
+                continue;
+            }
+            let line = parser.line();
+            match parser.ident()?.as_str() {
+                "syntax" => {
+                    parser.expect('=')?;
+                    syntax = parser.string()?;
+                    parser.expect(';')?;
+                }
+                "edition" => {
+                    parser.expect('=')?;
+                    syntax = "editions".to_string();
+                    edition = Some(parser.string()?);
+                    parser.expect(';')?;
+                }
+                "package" => {
+                    parser.package = Some(parser.ident()?);
+                    parser.expect(';')?;
+                }
+                "import" => {
+                    let modifier = match parser.peek() {
+                        Some(Token::Ident(i)) if i == "public" || i == "weak" => {
+                            Some(parser.ident()?)
+                        }
+                        _ => None,
+                    };
+                    imports.push(json!({"path": parser.string()?, "modifier": modifier}));
+                    parser.expect(';')?;
+                }
+                "option" => parser.option(&mut options)?,
+                "message" => parser.message("")?,
+                "enum" => parser.enumeration("")?,
+                "service" => services.push(parser.service()?),
+                "extend" => parser.extend("")?,
+                keyword => bail!(format!("Unexpected {} on line {}", keyword, line)),
+            }
+        }
+        let value = json!({
+            "syntax": syntax,
+            "edition": edition,
+            "package": parser.package,
+            "imports": imports,
+            "options": options,
+            "messages": parser.messages,
+            "enums": parser.enums,
+            "services": services,
+            "extensions": parser.extensions,
+        });
+        Ok(ParsedDocument {
+            descriptions: match self.comments {
+                true => descriptions(&value),
+                false => Descriptions::new(),
+            },
+            value,
+            ..Default::default()
+        })
+    }
+}
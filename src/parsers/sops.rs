@@ -0,0 +1,181 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Support for files encrypted with [SOPS](https://github.com/getsops/sops), whose values
+//! are replaced with `ENC[...]` strings and whose encryption settings are kept under a
+//! top-level `sops` key.
+//!
+//! Files are not decrypted: the structure of the file is documented with its encrypted
+//! values masked, so that no key material is required.
+
+use super::{
+    has_extension, yaml_documents, Capabilities, FileParser, ParseContext, ParsedDocument,
+};
+use crate::errors::*;
+use serde_json::{json, Map, Value};
+
+/// Placeholder value used in place of values encrypted with SOPS.
+pub const ENCRYPTED: &str = "**encrypted**";
+
+/// The keys of SOPS metadata naming the master keys of each type, with the key within each
+/// entry identifying the master key.
+const MASTER_KEYS: &[(&str, &str)] = &[
+    ("age", "recipient"),
+    ("kms", "arn"),
+    ("gcp_kms", "resource_id"),
+    ("azure_kv", "vault_url"),
+    ("hc_vault", "vault_address"),
+    ("pgp", "fp"),
+];
+
+/// Return whether a value is encrypted by SOPS, e.g. `ENC[AES256_GCM,data:...,type:str]`.
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with("ENC[AES256_GCM,") && value.ends_with(']')
+}
+
+/// Return the SOPS metadata of a document, if it is encrypted with SOPS.
+pub fn metadata(document: &Value) -> Option<&Map<String, Value>> {
+    document
+        .get("sops")
+        .and_then(|s| s.as_object())
+        .filter(|s| s.contains_key("mac") && s.contains_key("version"))
+}
+
+/// Replace the encrypted values within a document with a placeholder, adding the dotted key
+/// of each to a list of keys.
+pub fn mask(value: &mut Value, key: &str, encrypted: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => {
+            for (name, child) in map.iter_mut() {
+                let path = match key.is_empty() {
+                    true => name.to_owned(),
+                    false => format!("{}.{}", key, name),
+                };
+                mask(child, &path, encrypted);
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                mask(item, &format!("{}[{}]", key, i), encrypted);
+            }
+        }
+        Value::String(text) if is_encrypted(text) => {
+            *value = Value::String(ENCRYPTED.to_string());
+            encrypted.push(key.to_owned());
+        }
+        _ => {}
+    }
+}
+
+/// File parser for YAML and JSON files encrypted with SOPS.
+///
+/// Returns the `values` of the file with each encrypted value masked, the dotted keys of the
+/// `encrypted` values, and the `sops` settings of the file: its `version`, when it was
+/// `lastmodified`, the master `keys` able to decrypt it, each with its `type` and `id`, such
+/// as an age recipient or KMS key ARN, and which keys are encrypted, e.g. with
+/// `encrypted_regex` or `unencrypted_suffix`. Values are not decrypted.
+pub struct SopsParser {}
+
+impl SopsParser {
+    /// Describe the settings of an encrypted document.
+    fn settings(sops: &Map<String, Value>) -> Value {
+        let mut keys = vec![];
+        // Master keys are listed by type, or by type within each of the `key_groups`
+        let groups = sops
+            .get("key_groups")
+            .and_then(|g| g.as_array())
+            .map(|g| g.iter().collect::<Vec<_>>())
+            .unwrap_or_else(|| vec![&Value::Null]);
+        for group in groups {
+            let group = group.as_object().unwrap_or(sops);
+            for (kind, id) in MASTER_KEYS {
+                let entries = group.get(*kind).and_then(|k| k.as_array());
+                for entry in entries.into_iter().flatten() {
+                    keys.push(json!({"type": kind, "id": entry.get(*id)}));
+                }
+            }
+        }
+        let mut settings = Map::new();
+        settings.insert("version".to_string(), json!(sops.get("version")));
+        settings.insert("lastmodified".to_string(), json!(sops.get("lastmodified")));
+        settings.insert("keys".to_string(), json!(keys));
+        for (key, value) in sops.iter().filter(|(k, _)| k.contains("crypted_")) {
+            settings.insert(key.to_owned(), value.to_owned());
+        }
+        Value::Object(settings)
+    }
+
+    /// Describe a single encrypted document.
+    fn document(mut value: Value) -> Result<ParsedDocument> {
+        let Some(sops) = metadata(&value).map(SopsParser::settings) else {
+            bail!("Document is not encrypted with SOPS")
+        };
+        if let Some(map) = value.as_object_mut() {
+            map.remove("sops");
+        }
+        let mut encrypted = vec![];
+        mask(&mut value, "", &mut encrypted);
+        Ok(ParsedDocument {
+            value: json!({
+                "values": value,
+                "encrypted": encrypted,
+                "sops": sops,
+            }),
+            ..Default::default()
+        })
+    }
+}
+
+impl FileParser for SopsParser {
+    fn name(&self) -> &'static str {
+        "sops"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["yaml", "yml", "json"],
+            documents: true,
+            ..Default::default()
+        }
+    }
+
+    fn can_parse(&self, context: &ParseContext) -> bool {
+        has_extension(context.path(), self.capabilities().extensions)
+            && context
+                .text()
+                .is_ok_and(|c| c.contains("ENC[AES256_GCM,") && c.contains("sops"))
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let text = context.text()?;
+        match has_extension(context.path(), &["json"]) {
+            true => SopsParser::document(serde_json::from_str(text)?),
+            false => SopsParser::document(serde_yaml::from_str(text)?),
+        }
+    }
+
+    /// Parse each encrypted document of a multi-document YAML file, such as a list of
+    /// Kubernetes manifests encrypted together.
+    fn parse_documents(&self, context: &ParseContext) -> Result<Vec<ParsedDocument>> {
+        if has_extension(context.path(), &["json"]) {
+            return Ok(vec![self.parse(context)?]);
+        }
+        yaml_documents(context.text()?)
+            .into_iter()
+            .map(|text| SopsParser::document(serde_yaml::from_str(text)?))
+            .collect()
+    }
+}
@@ -0,0 +1,541 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Directory parser for Spring Boot configuration, resolving the effective configuration
+//! of each profile.
+
+use super::directory::DirectoryParser;
+use super::{yaml_documents, ParseContext, ParsedDocument};
+use crate::errors::*;
+use java_properties::{LineContent, PropertiesIter};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+
+/// The key activating a document for profiles, since Spring Boot 2.4.
+const ON_PROFILE: &str = "spring.config.activate.on-profile";
+
+/// The legacy key activating a document for profiles.
+const LEGACY_PROFILES: &str = "spring.profiles";
+
+/// The extensions of configuration files, in increasing order of precedence.
+const EXTENSIONS: &[&str] = &["yml", "yaml", "properties"];
+
+/// A document within a configuration file, with its flattened properties.
+#[derive(Debug)]
+struct Document {
+    /// The name of the file containing the document.
+    file: String,
+    /// The profile expression activating the document, if it is profile-specific.
+    on_profile: Option<String>,
+    properties: Vec<(String, Value)>,
+    /// The line of the file on which each property is defined, where it is known.
+    lines: BTreeMap<String, usize>,
+}
+
+/// Flatten a YAML value into properties with dotted keys and indexed lists, e.g.
+/// `server.port` and `app.hosts[0]`, as Spring Boot does.
+fn flatten(value: &Value, prefix: &str, properties: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Object(map) => {
+            for (key, item) in map {
+                let key = match prefix {
+                    "" => key.to_owned(),
+                    prefix => format!("{}.{}", prefix, key),
+                };
+                flatten(item, &key, properties);
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                flatten(item, &format!("{}[{}]", prefix, i), properties);
+            }
+        }
+        scalar => properties.push((prefix.to_string(), scalar.to_owned())),
+    }
+}
+
+/// Return the line on which each key of a YAML document is defined, by its flattened key,
+/// given the line of the file on which the document starts.
+///
+/// Keys are found from the indentation of block mappings and sequences, so keys within
+/// flow collections, such as `{a: 1}`, have no line. Lines indented beneath a key with a
+/// scalar value continue the scalar rather than defining keys.
+fn yaml_lines(text: &str, first: usize) -> BTreeMap<String, usize> {
+    let mut lines = BTreeMap::new();
+    // The indentation and flattened key of each enclosing mapping key or sequence item
+    let mut parents: Vec<(usize, String, bool)> = vec![];
+    let mut items: BTreeMap<String, usize> = BTreeMap::new();
+    let mut block: Option<usize> = None;
+    for (number, line) in text.lines().enumerate() {
+        let number = first + number;
+        let content = line.trim_start();
+        let mut indent = line.len() - content.len();
+        if block.is_some_and(|b| indent > b || content.is_empty()) {
+            continue;
+        }
+        block = None;
+        if content.is_empty() || content.starts_with('#') || content.starts_with("---") {
+            continue;
+        }
+        let mut content = content;
+        while content == "-" || content.starts_with("- ") {
+            while parents
+                .last()
+                .is_some_and(|(i, _, item)| *i > indent || (*i == indent && *item))
+            {
+                parents.pop();
+            }
+            let parent = parents.last().map(|(_, k, _)| k.as_str()).unwrap_or("");
+            let index = items.entry(parent.to_string()).or_insert(0);
+            let key = format!("{}[{}]", parent, index);
+            *index += 1;
+            lines.insert(key.to_owned(), number);
+            let rest = content[1..].trim_start();
+            indent += content.len() - rest.len();
+            parents.push((indent, key, true));
+            content = rest;
+        }
+        let Some((name, value)) = mapping_key(content) else {
+            continue;
+        };
+        while parents
+            .last()
+            .is_some_and(|(i, _, item)| *i > indent || (*i == indent && !*item))
+        {
+            parents.pop();
+        }
+        let key = match parents.last() {
+            Some((_, parent, _)) => format!("{}.{}", parent, name),
+            None => name.to_string(),
+        };
+        lines.insert(key.to_owned(), number);
+        let value = match value.find(" #") {
+            Some(comment) => value[..comment].trim(),
+            None => value.trim(),
+        };
+        // Anchors and tags alone, such as `&defaults`, precede a nested collection
+        let nested = value.is_empty()
+            || (value.starts_with(['&', '!']) && !value.contains(char::is_whitespace));
+        if !nested {
+            block = Some(indent);
+        }
+        items.remove(&key);
+        parents.push((indent, key, false));
+    }
+    lines
+}
+
+/// Split a line of a YAML block mapping into its key, unquoted, and the rest of the line,
+/// or return `None` if the line does not start with a key.
+fn mapping_key(content: &str) -> Option<(&str, &str)> {
+    let (name, rest) = match content.chars().next() {
+        Some(quote @ ('"' | '\'')) => {
+            let end = content[1..].find(quote)? + 1;
+            (&content[1..end], content[end + 1..].trim_start())
+        }
+        _ => {
+            let end = content
+                .find(": ")
+                .or_else(|| content.ends_with(':').then(|| content.len() - 1))?;
+            (content[..end].trim(), &content[end..])
+        }
+    };
+    match rest.strip_prefix(':')? {
+        "" => Some((name, "")),
+        value if value.starts_with(' ') => Some((name, value)),
+        _ => None,
+    }
+}
+
+/// Return a property value as text, as Spring Boot reads it.
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.to_owned(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// Return the values of a property, which may be a comma-separated list or an indexed list
+/// such as `spring.profiles.include[0]`.
+fn list(properties: &[(String, Value)], key: &str) -> Vec<String> {
+    properties
+        .iter()
+        .filter(|(k, _)| {
+            k == key
+                || k.strip_prefix(key)
+                    .is_some_and(|rest| rest.starts_with('[') && rest.ends_with(']'))
+        })
+        .flat_map(|(_, v)| {
+            text(v)
+                .split(',')
+                .map(|p| p.trim().to_string())
+                .collect::<Vec<_>>()
+        })
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+impl Document {
+    /// Create a document from its flattened properties, removing those which activate it
+    /// for profiles.
+    fn new(
+        file: &str,
+        properties: Vec<(String, Value)>,
+        lines: BTreeMap<String, usize>,
+    ) -> Document {
+        let legacy = properties
+            .iter()
+            .any(|(k, v)| k == LEGACY_PROFILES && v.is_string());
+        let activation = match legacy {
+            true => LEGACY_PROFILES,
+            false => ON_PROFILE,
+        };
+        let profiles = list(&properties, activation);
+        Document {
+            file: file.to_string(),
+            on_profile: (!profiles.is_empty()).then(|| profiles.join(" | ")),
+            properties: properties
+                .into_iter()
+                .filter(|(k, _)| k != activation && !k.starts_with(&format!("{}[", activation)))
+                .collect(),
+            lines,
+        }
+    }
+
+    /// Return whether the document applies when some profiles are active.
+    fn applies(&self, active: &[String]) -> bool {
+        self.on_profile
+            .as_deref()
+            .is_none_or(|expression| matches(expression, active))
+    }
+}
+
+/// Return whether a profile expression, such as `prod`, `!test`, `prod & cloud` or
+/// `dev | test`, matches the active profiles. Commas are treated as `|`, as in legacy
+/// `spring.profiles` lists, and parentheses are ignored.
+fn matches(expression: &str, active: &[String]) -> bool {
+    expression.split(['|', ',']).any(|alternative| {
+        alternative.split('&').all(|term| {
+            let term = term.trim().trim_matches(['(', ')']).trim();
+            match term.strip_prefix('!') {
+                Some(negated) => !active.iter().any(|a| a == negated.trim()),
+                None => active.iter().any(|a| a == term),
+            }
+        })
+    })
+}
+
+/// Return the profile names within a profile expression.
+fn expression_profiles(expression: &str) -> impl Iterator<Item = String> + '_ {
+    expression
+        .split(['|', ',', '&', '!', '(', ')'])
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+}
+
+/// Directory parser for Spring Boot configuration.
+///
+/// Reads the `application.yml`, `application.yaml` and `application.properties` files and
+/// the profile-specific `application-{profile}` files of the same directory, including
+/// multi-document files whose documents are activated with
+/// `spring.config.activate.on-profile` or the legacy `spring.profiles`. Returns the
+/// `files` read, the `profiles` found, the `active` profiles when none is requested and the
+/// `effective` configuration of the default and each profile: the active profiles,
+/// expanded with `spring.profiles.include` and `spring.profiles.group`, and each property
+/// with its `value` and the `source` file and `line` it is taken from, applying Spring
+/// Boot's order of precedence.
+pub struct SpringBootParser {}
+
+impl SpringBootParser {
+    /// Read the documents of a configuration file, warning of files which cannot be parsed.
+    fn read(context: &ParseContext, file: &str) -> Vec<Document> {
+        let path = context.path().join(file);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                context.warn(format!("Could not read {}: {}", file, e));
+                return vec![];
+            }
+        };
+        let documents: Result<Vec<Document>> = if file.ends_with(".properties") {
+            // Multi-document properties files separate documents with `#---` or `!---`, and
+            // each part is read with the number of lines preceding it
+            let mut parts = vec![(0, String::new())];
+            for (number, line) in contents.lines().enumerate() {
+                match line.trim() {
+                    "#---" | "!---" => parts.push((number + 1, String::new())),
+                    _ => parts.last_mut().into_iter().for_each(|(_, p)| {
+                        p.push_str(line);
+                        p.push('\n');
+                    }),
+                }
+            }
+            parts
+                .iter()
+                .map(|(offset, part)| {
+                    let mut properties = vec![];
+                    let mut lines = BTreeMap::new();
+                    for line in PropertiesIter::new(part.as_bytes()) {
+                        let line = line.map_err(|e| e.to_string())?;
+                        let number = offset + line.line_number();
+                        if let LineContent::KVPair(k, v) = line.consume_content() {
+                            lines.insert(k.to_owned(), number);
+                            properties.push((k, Value::String(v)));
+                        }
+                    }
+                    Ok(Document::new(file, properties, lines))
+                })
+                .collect()
+        } else {
+            yaml_documents(&contents)
+                .into_iter()
+                .map(|text| {
+                    let value: Value = serde_yaml::from_str(text)?;
+                    let mut properties = vec![];
+                    flatten(&value, "", &mut properties);
+                    // Documents are slices of the file, so their first line can be counted
+                    let start = text.as_ptr() as usize - contents.as_ptr() as usize;
+                    let first = contents[..start].matches('\n').count() + 1;
+                    let lines = yaml_lines(text, first);
+                    Ok(Document::new(file, properties, lines))
+                })
+                .collect()
+        };
+        documents.unwrap_or_else(|e| {
+            context.warn(format!("Could not parse {}: {}", file, e));
+            vec![]
+        })
+    }
+}
+
+impl DirectoryParser for SpringBootParser {
+    fn name(&self) -> &'static str {
+        "spring-boot"
+    }
+
+    fn markers(&self) -> &'static [&'static str] {
+        &[
+            "application.yml",
+            "application.yaml",
+            "application.properties",
+        ]
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let dir = context.path();
+        let mut names: Vec<String> = fs::read_dir(dir)?
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().is_file())
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+
+        // The files of each profile, in increasing order of precedence
+        let profile_of =
+            |name: &str, extension: &str| match name.strip_suffix(&format!(".{}", extension))? {
+                "application" => Some(String::new()),
+                stem => stem
+                    .strip_prefix("application-")
+                    .filter(|p| !p.is_empty())
+                    .map(String::from),
+            };
+        let mut files: Vec<(String, String)> = vec![];
+        for extension in EXTENSIONS {
+            for name in &names {
+                if let Some(profile) = profile_of(name, extension) {
+                    files.push((profile, name.to_owned()));
+                }
+            }
+        }
+        files.sort_by_key(|(profile, _)| (!profile.is_empty(), profile.to_owned()));
+        let documents: Vec<(String, Document)> = files
+            .iter()
+            .flat_map(|(profile, file)| {
+                SpringBootParser::read(context, file)
+                    .into_iter()
+                    .map(move |d| (profile.to_owned(), d))
+            })
+            .collect();
+
+        // Profiles are activated, included and grouped by the unconditional documents of
+        // the base files
+        let base: Vec<(String, Value)> = documents
+            .iter()
+            .filter(|(profile, d)| profile.is_empty() && d.on_profile.is_none())
+            .flat_map(|(_, d)| d.properties.iter().cloned())
+            .collect();
+        // A later value of a property replaces an earlier one, but indexed lists are combined
+        let last = |key: &str| {
+            base.iter()
+                .rev()
+                .find(|(k, _)| k == key)
+                .map(|(k, v)| list(&[(k.to_owned(), v.to_owned())], key))
+                .unwrap_or_else(|| list(&base, key))
+        };
+        let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (key, _) in &base {
+            if let Some(group) = key
+                .strip_prefix("spring.profiles.group.")
+                .map(|g| g.split('[').next().unwrap_or(g))
+            {
+                if !groups.contains_key(group) {
+                    let members = list(&base, &format!("spring.profiles.group.{}", group));
+                    groups.insert(group.to_string(), members);
+                }
+            }
+        }
+        let includes = last("spring.profiles.include");
+        let default_active = match last("spring.profiles.active") {
+            active if active.is_empty() => vec!["default".to_string()],
+            active => active,
+        };
+        let expand = |requested: &[String]| {
+            let mut active: Vec<String> = vec![];
+            let mut pending: Vec<String> = includes.iter().chain(requested).cloned().collect();
+            while !pending.is_empty() {
+                let profile = pending.remove(0);
+                if active.contains(&profile) {
+                    continue;
+                }
+                if let Some(members) = groups.get(&profile) {
+                    pending.extend(members.iter().cloned());
+                }
+                active.push(profile);
+            }
+            active
+        };
+
+        let mut profiles: BTreeSet<String> = BTreeSet::new();
+        for (profile, document) in &documents {
+            profiles.extend((!profile.is_empty()).then(|| profile.to_owned()));
+            profiles.extend(
+                document
+                    .on_profile
+                    .iter()
+                    .flat_map(|e| expression_profiles(e)),
+            );
+        }
+        profiles.extend(groups.keys().cloned());
+        profiles.extend(groups.values().flatten().cloned());
+        profiles.extend(includes.iter().cloned());
+        profiles.extend(default_active.iter().cloned());
+        profiles.remove("default");
+
+        let effective = |requested: &[String]| {
+            let active = expand(requested);
+            let mut properties = Map::new();
+            let layers = std::iter::once("")
+                .chain(active.iter().map(|a| a.as_str()))
+                .flat_map(|layer| documents.iter().filter(move |(p, _)| p == layer));
+            for (_, document) in layers.filter(|(_, d)| d.applies(&active)) {
+                for (key, value) in &document.properties {
+                    properties.insert(
+                        key.to_owned(),
+                        json!({
+                            "value": value,
+                            "source": document.file,
+                            "line": document.lines.get(key),
+                        }),
+                    );
+                }
+            }
+            json!({"profiles": active, "properties": properties})
+        };
+        let mut configurations = Map::new();
+        configurations.insert("default".to_string(), effective(&default_active));
+        for profile in &profiles {
+            configurations.insert(profile.to_owned(), effective(&[profile.to_owned()]));
+        }
+
+        let name = configurations["default"]["properties"]["spring.application.name"]["value"]
+            .as_str()
+            .map(String::from)
+            .or_else(|| dir.file_name().map(|n| n.to_string_lossy().to_string()));
+        Ok(ParsedDocument {
+            name,
+            value: json!({
+                "files": files.iter().map(|(_, f)| f).collect::<Vec<_>>(),
+                "profiles": profiles,
+                "active": default_active,
+                "groups": groups,
+                "effective": configurations,
+            }),
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::yaml_lines;
+
+    fn lines(text: &str) -> Vec<(String, usize)> {
+        yaml_lines(text, 1).into_iter().collect()
+    }
+
+    fn expected(lines: &[(&str, usize)]) -> Vec<(String, usize)> {
+        lines.iter().map(|(k, n)| (k.to_string(), *n)).collect()
+    }
+
+    #[test]
+    fn quoted_keys() {
+        let text = "app:\n  \"a: b\": 1\n  'c': 2\n  d:e: 3\n";
+        assert_eq!(
+            lines(text),
+            expected(&[("app", 1), ("app.a: b", 2), ("app.c", 3), ("app.d:e", 4)])
+        );
+    }
+
+    #[test]
+    fn multi_line_scalars() {
+        let text = "\
+description: a long
+  value: not a key
+script: |
+  run: me
+
+  again: too
+quoted: \"first
+  second: line\"
+anchored: &defaults
+  port: 8080
+server:
+  items:
+    - name: a # comment
+      note:
+        text
+    - b
+";
+        assert_eq!(
+            lines(text),
+            expected(&[
+                ("anchored", 9),
+                ("anchored.port", 10),
+                ("description", 1),
+                ("quoted", 7),
+                ("script", 3),
+                ("server", 11),
+                ("server.items", 12),
+                ("server.items[0]", 13),
+                ("server.items[0].name", 13),
+                ("server.items[0].note", 14),
+                ("server.items[1]", 16),
+            ])
+        );
+    }
+}
@@ -0,0 +1,313 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Parser for Terraform state files.
+
+use super::{Capabilities, FileParser, ParseContext, ParsedDocument};
+use crate::errors::*;
+use itertools::Itertools;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// Attributes considered to identify a resource, where present.
+const KEY_ATTRIBUTES: [&str; 5] = ["id", "arn", "name", "self_link", "tags"];
+
+/// A single resource instance within a Terraform state file.
+#[derive(Debug, Serialize)]
+struct Resource {
+    address: String,
+    module: String,
+    mode: String,
+    #[serde(rename = "type")]
+    resource_type: String,
+    name: String,
+    provider: String,
+    key_attributes: Map<String, Value>,
+    attributes: Value,
+    dependencies: Vec<String>,
+}
+
+/// The normalised model of a Terraform state file.
+#[derive(Debug, Serialize)]
+struct State {
+    version: u64,
+    terraform_version: Option<String>,
+    serial: Option<u64>,
+    outputs: Map<String, Value>,
+    resources: Vec<Resource>,
+    by_type: BTreeMap<String, Vec<String>>,
+    by_provider: BTreeMap<String, Vec<String>>,
+}
+
+/// File parser for Terraform state files.
+///
+/// Rather than returning the raw state JSON, which changes shape between Terraform
+/// versions, this parser returns a stable model of the resources in the state with
+/// their provider, key attributes and dependencies, along with indexes of resource
+/// addresses by type and provider. Both the legacy (version 1-3) and current
+/// (version 4) state formats are supported.
+pub struct TerraformStateParser {}
+impl FileParser for TerraformStateParser {
+    fn name(&self) -> &'static str {
+        "terraform-state"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["tfstate"],
+            ..Default::default()
+        }
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let raw: Value = serde_json::from_str(context.text()?)?;
+        let version = raw
+            .get("version")
+            .and_then(|v| v.as_u64())
+            .ok_or("Terraform state has no version")?;
+
+        let (resources, outputs) = match version {
+            1..=3 => legacy_resources(&raw),
+            4 => current_resources(&raw),
+            _ => bail!(format!("Unsupported Terraform state version {}", version)),
+        };
+
+        let index = |key: fn(&Resource) -> &String| {
+            resources
+                .iter()
+                .into_group_map_by(|r| key(r).to_owned())
+                .into_iter()
+                .map(|(k, rs)| (k, rs.iter().map(|r| r.address.to_owned()).collect_vec()))
+                .collect::<BTreeMap<_, _>>()
+        };
+
+        let state = State {
+            version,
+            terraform_version: raw
+                .get("terraform_version")
+                .and_then(|v| v.as_str())
+                .map(|v| v.to_string()),
+            serial: raw.get("serial").and_then(|v| v.as_u64()),
+            outputs,
+            by_type: index(|r| &r.resource_type),
+            by_provider: index(|r| &r.provider),
+            resources,
+        };
+
+        Ok(ParsedDocument::new(serde_json::to_value(state)?))
+    }
+}
+
+/// Return the short provider name, e.g. `aws`, from a provider reference such as
+/// `provider["registry.terraform.io/hashicorp/aws"]` or `provider.aws.west`.
+fn provider_name(provider: Option<&str>, resource_type: &str) -> String {
+    let name = match provider {
+        Some(p) => match (p.find("[\""), p.rfind("\"]")) {
+            (Some(start), Some(end)) if start + 2 <= end => p[start + 2..end].rsplit('/').next(),
+            _ => p
+                .rsplit("provider.")
+                .next()
+                .and_then(|n| n.split('.').next()),
+        },
+        None => resource_type.split('_').next(),
+    };
+    name.unwrap_or(resource_type).to_string()
+}
+
+/// Return the subset of attributes that identify a resource.
+fn key_attributes(attributes: &Value) -> Map<String, Value> {
+    KEY_ATTRIBUTES
+        .iter()
+        .filter_map(|k| attributes.get(k).map(|v| (k.to_string(), v.to_owned())))
+        .collect()
+}
+
+/// Return the string values of a JSON array, de-duplicated and in their original order.
+fn string_list(value: Option<&Value>) -> Vec<String> {
+    value
+        .and_then(|v| v.as_array())
+        .map(|a| {
+            a.iter()
+                .filter_map(|d| d.as_str().map(|s| s.to_string()))
+                .unique()
+                .collect_vec()
+        })
+        .unwrap_or_default()
+}
+
+/// Extract resources and outputs from a version 4 state file.
+fn current_resources(raw: &Value) -> (Vec<Resource>, Map<String, Value>) {
+    let empty = vec![];
+    let resources = raw
+        .get("resources")
+        .and_then(|r| r.as_array())
+        .unwrap_or(&empty)
+        .iter()
+        .flat_map(|r| {
+            let str_field = |f: &str| r.get(f).and_then(|v| v.as_str()).unwrap_or_default();
+            let module = str_field("module");
+            let mode = if str_field("mode").is_empty() {
+                "managed"
+            } else {
+                str_field("mode")
+            };
+            let resource_type = str_field("type");
+            let name = str_field("name");
+            let base = format!(
+                "{}{}{}.{}",
+                if module.is_empty() {
+                    "".to_string()
+                } else {
+                    format!("{}.", module)
+                },
+                if mode == "data" { "data." } else { "" },
+                resource_type,
+                name
+            );
+            let provider = provider_name(r.get("provider").and_then(|v| v.as_str()), resource_type);
+
+            r.get("instances")
+                .and_then(|i| i.as_array())
+                .unwrap_or(&empty)
+                .iter()
+                .map(|instance| {
+                    let address = match instance.get("index_key") {
+                        Some(Value::String(k)) => format!("{}[\"{}\"]", base, k),
+                        Some(Value::Number(n)) => format!("{}[{}]", base, n),
+                        _ => base.to_owned(),
+                    };
+                    let attributes = instance
+                        .get("attributes")
+                        .cloned()
+                        .unwrap_or(Value::Object(Map::new()));
+                    Resource {
+                        address,
+                        module: if module.is_empty() { "root" } else { module }.to_string(),
+                        mode: mode.to_string(),
+                        resource_type: resource_type.to_string(),
+                        name: name.to_string(),
+                        provider: provider.to_owned(),
+                        key_attributes: key_attributes(&attributes),
+                        attributes,
+                        dependencies: string_list(instance.get("dependencies")),
+                    }
+                })
+                .collect_vec()
+        })
+        .collect_vec();
+
+    let outputs = raw
+        .get("outputs")
+        .and_then(|o| o.as_object())
+        .map(|o| {
+            o.iter()
+                .map(|(k, v)| (k.to_owned(), v.get("value").cloned().unwrap_or(Value::Null)))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    (resources, outputs)
+}
+
+/// Extract resources and outputs from a version 1-3 state file, in which resources are
+/// listed per module and attributes are flattened into strings.
+fn legacy_resources(raw: &Value) -> (Vec<Resource>, Map<String, Value>) {
+    let empty = vec![];
+    let modules = raw
+        .get("modules")
+        .and_then(|m| m.as_array())
+        .unwrap_or(&empty);
+
+    let module_name = |module: &Value| {
+        let path = string_list(module.get("path"));
+        match path.as_slice() {
+            [] | [_] => "root".to_string(),
+            [_, rest @ ..] => rest.iter().map(|p| format!("module.{}", p)).join("."),
+        }
+    };
+
+    let resources = modules
+        .iter()
+        .flat_map(|module| {
+            let module_name = module_name(module);
+            module
+                .get("resources")
+                .and_then(|r| r.as_object())
+                .map(|r| {
+                    r.iter()
+                        .map(|(key, resource)| {
+                            let resource_type = resource
+                                .get("type")
+                                .and_then(|v| v.as_str())
+                                .unwrap_or_default();
+                            let (mode, name) = match key.strip_prefix("data.") {
+                                Some(k) => ("data", k),
+                                None => ("managed", key.as_str()),
+                            };
+                            let name = name
+                                .strip_prefix(resource_type)
+                                .map(|n| n.trim_start_matches('.'))
+                                .unwrap_or(name);
+                            let attributes = resource
+                                .get("primary")
+                                .and_then(|p| p.get("attributes"))
+                                .cloned()
+                                .unwrap_or(Value::Object(Map::new()));
+                            Resource {
+                                address: if module_name == "root" {
+                                    key.to_owned()
+                                } else {
+                                    format!("{}.{}", module_name, key)
+                                },
+                                module: module_name.to_owned(),
+                                mode: mode.to_string(),
+                                resource_type: resource_type.to_string(),
+                                name: name.to_string(),
+                                provider: provider_name(
+                                    resource
+                                        .get("provider")
+                                        .and_then(|v| v.as_str())
+                                        .filter(|p| !p.is_empty()),
+                                    resource_type,
+                                ),
+                                key_attributes: key_attributes(&attributes),
+                                attributes,
+                                dependencies: string_list(resource.get("depends_on")),
+                            }
+                        })
+                        .collect_vec()
+                })
+                .unwrap_or_default()
+        })
+        .collect_vec();
+
+    let outputs = modules
+        .iter()
+        .filter(|m| module_name(m) == "root")
+        .filter_map(|m| m.get("outputs").and_then(|o| o.as_object()))
+        .flat_map(|o| {
+            o.iter().map(|(k, v)| {
+                (
+                    k.to_owned(),
+                    v.get("value").cloned().unwrap_or_else(|| v.to_owned()),
+                )
+            })
+        })
+        .collect();
+
+    (resources, outputs)
+}
@@ -0,0 +1,399 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Parser for Terraform and OpenTofu configuration in HCL.
+//!
+//! HCL expressions are not evaluated: as in the `configuration` of `terraform show -json`,
+//! literal values are returned as `constant_value` and other expressions with the
+//! `references` they make, such as `var.region` or `aws_s3_bucket.site`.
+
+use super::{has_extension, Capabilities, FileParser, ParseContext, ParsedDocument};
+use crate::errors::*;
+use hcl::{Block, Body, Expression, ObjectKey};
+use serde_json::{json, Map, Value};
+
+/// Block types which identify Terraform configuration in a `.hcl` file.
+const BLOCKS: &[&str] = &[
+    "resource", "data", "variable", "output", "module", "provider",
+];
+
+/// Arguments of resources and modules which are not passed to the provider or module.
+const META_ARGUMENTS: &[&str] = &["count", "for_each", "depends_on", "provider", "providers"];
+
+/// Roots of references other than to resources, e.g. `var.region`.
+const REFERENCE_ROOTS: &[&str] = &["var", "local", "module", "data"];
+
+/// Return the literal value of an expression, if it has no references or function calls.
+fn constant(expression: &Expression) -> Option<Value> {
+    match expression {
+        Expression::Null => Some(Value::Null),
+        Expression::Bool(b) => Some(json!(b)),
+        Expression::Number(n) => serde_json::to_value(n).ok(),
+        Expression::String(s) => Some(json!(s)),
+        Expression::Array(items) => items
+            .iter()
+            .map(constant)
+            .collect::<Option<Vec<_>>>()
+            .map(Value::from),
+        Expression::Object(object) => object
+            .iter()
+            .map(|(key, value)| Some((object_key(key)?, constant(value)?)))
+            .collect::<Option<Map<_, _>>>()
+            .map(Value::Object),
+        _ => None,
+    }
+}
+
+/// Return the text of a literal object key.
+fn object_key(key: &ObjectKey) -> Option<String> {
+    match key {
+        ObjectKey::Identifier(identifier) => Some(identifier.to_string()),
+        ObjectKey::Expression(Expression::String(s)) => Some(s.to_owned()),
+        _ => None,
+    }
+}
+
+/// Return the references made by HCL expression text, both to whole objects, such as
+/// `aws_s3_bucket.site`, and to their attributes, such as `aws_s3_bucket.site.arn`, in the
+/// order in which they are first made.
+fn references(text: &str) -> Vec<String> {
+    let mut references: Vec<String> = vec![];
+    let mut add = |reference: String| {
+        if !references.contains(&reference) {
+            references.push(reference);
+        }
+    };
+    // Whether each enclosing context is a quoted string, rather than code
+    let mut strings = vec![false];
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if *strings.last().unwrap_or(&false) {
+            match c {
+                '\\' => i += 1,
+                '"' => {
+                    strings.pop();
+                }
+                '$' | '%' if chars.get(i + 1) == Some(&'{') => {
+                    strings.push(false);
+                    i += 1;
+                }
+                _ => {}
+            }
+            i += 1;
+            continue;
+        }
+        match c {
+            '"' => strings.push(true),
+            '{' => strings.push(false),
+            '}' => {
+                strings.pop();
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len()
+                    && (chars[i].is_ascii_alphanumeric() || "_-.".contains(chars[i]))
+                {
+                    i += 1;
+                }
+                let chain: String = chars[start..i].iter().collect();
+                let parts: Vec<&str> = chain.trim_end_matches('.').split('.').collect();
+                // Resources are named by their type and name, and data sources by `data`,
+                // their type and name
+                let object = match parts[0] {
+                    "data" => 3,
+                    root if REFERENCE_ROOTS.contains(&root) => 2,
+                    root if root.contains('_') => 2,
+                    _ => 0,
+                };
+                if object > 0 && parts.len() >= object {
+                    add(parts[..object].join("."));
+                    if parts.len() > object {
+                        add(parts.join("."));
+                    }
+                }
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    references
+}
+
+/// Describe an expression as a `constant_value` or with the `references` it makes.
+fn expression(expression: &Expression) -> Value {
+    match constant(expression) {
+        Some(value) => json!({"constant_value": value}),
+        None => json!({
+            "expression": expression.to_string(),
+            "references": references(&expression.to_string()),
+        }),
+    }
+}
+
+/// Describe the arguments of a body, with each nested block as a list of the bodies of
+/// blocks of its type, skipping the given arguments and blocks.
+fn expressions(body: &Body, skip: &[&str]) -> Map<String, Value> {
+    let mut described = Map::new();
+    for attribute in body.attributes().filter(|a| !skip.contains(&a.key())) {
+        described.insert(attribute.key().to_string(), expression(attribute.expr()));
+    }
+    for block in body.blocks().filter(|b| !skip.contains(&b.identifier())) {
+        let nested = json!(expressions(block.body(), &[]));
+        match described.get_mut(block.identifier()) {
+            Some(Value::Array(blocks)) => blocks.push(nested),
+            _ => {
+                described.insert(block.identifier().to_string(), json!([nested]));
+            }
+        }
+    }
+    described
+}
+
+/// Return the literal value of an argument of a block, if it has one.
+fn argument(body: &Body, key: &str) -> Option<Value> {
+    body.attributes()
+        .find(|a| a.key() == key)
+        .and_then(|a| constant(a.expr()))
+}
+
+/// Return the HCL text of an argument of a block, such as a variable's type.
+fn argument_text(body: &Body, key: &str) -> Option<String> {
+    body.attributes()
+        .find(|a| a.key() == key)
+        .map(|a| a.expr().to_string())
+}
+
+/// Return the labels of a block.
+fn labels(block: &Block) -> Vec<&str> {
+    block.labels().iter().map(|l| l.as_str()).collect()
+}
+
+/// File parser for Terraform and OpenTofu configuration, in `.tf` files or in `.hcl` files
+/// defining resources, data sources, variables, outputs, modules or providers.
+///
+/// Returns a structure comparable to the `configuration` of `terraform show -json`: the
+/// `terraform` settings, with the `required_version`, `required_providers` and `backend`,
+/// the `provider_config` of each provider by name and alias, and the `root_module` with its
+/// `resources` and data sources, each with its `address`, `mode`, `type`, `name`,
+/// `provider_config_key`, `expressions`, any `count_expression` or `for_each_expression`
+/// and `depends_on`, its `module_calls`, its `variables`, with their `type`, `default`,
+/// whether they are `required`, having no default, their `description`, `sensitive` and
+/// `nullable` settings and `validation` error messages, its
+/// `outputs`, with their `expression`, `description` and `sensitive` settings, and its
+/// `locals`.
+pub struct HclParser {}
+
+impl HclParser {
+    /// Describe a resource or data source.
+    fn resource(block: &Block, mode: &str) -> Result<Value> {
+        let [resource_type, name] = labels(block)[..] else {
+            bail!(format!(
+                "{} block without a type and name",
+                block.identifier()
+            ))
+        };
+        let body = block.body();
+        let provider = argument_text(body, "provider").unwrap_or_else(|| {
+            resource_type
+                .split('_')
+                .next()
+                .unwrap_or_default()
+                .to_string()
+        });
+        let meta = |key: &str| {
+            body.attributes()
+                .find(|a| a.key() == key)
+                .map(|a| expression(a.expr()))
+        };
+        Ok(json!({
+            "address": match mode {
+                "data" => format!("data.{}.{}", resource_type, name),
+                _ => format!("{}.{}", resource_type, name),
+            },
+            "mode": mode,
+            "type": resource_type,
+            "name": name,
+            "provider_config_key": provider,
+            "expressions": expressions(body, &[META_ARGUMENTS, &["lifecycle", "provisioner", "connection"]].concat()),
+            "count_expression": meta("count"),
+            "for_each_expression": meta("for_each"),
+            "depends_on": meta("depends_on")
+                .and_then(|d| d.get("references").cloned())
+                .unwrap_or(json!([])),
+        }))
+    }
+
+    /// Describe a variable.
+    fn variable(body: &Body) -> Value {
+        let validations: Vec<Value> = body
+            .blocks()
+            .filter(|b| b.identifier() == "validation")
+            .map(|v| json!(argument(v.body(), "error_message")))
+            .collect();
+        json!({
+            "type": argument_text(body, "type"),
+            "default": argument(body, "default"),
+            "required": !body.attributes().any(|a| a.key() == "default"),
+            "description": argument(body, "description"),
+            "sensitive": argument(body, "sensitive").unwrap_or(json!(false)),
+            "nullable": argument(body, "nullable").unwrap_or(json!(true)),
+            "validation": validations,
+        })
+    }
+
+    /// Describe an output.
+    fn output(body: &Body) -> Value {
+        json!({
+            "expression": body
+                .attributes()
+                .find(|a| a.key() == "value")
+                .map(|a| expression(a.expr())),
+            "description": argument(body, "description"),
+            "sensitive": argument(body, "sensitive").unwrap_or(json!(false)),
+        })
+    }
+
+    /// Describe the `terraform` settings block.
+    fn settings(block: &Block) -> Value {
+        let body = block.body();
+        let mut providers = Map::new();
+        for required in body
+            .blocks()
+            .filter(|b| b.identifier() == "required_providers")
+        {
+            for attribute in required.body().attributes() {
+                let requirement = match constant(attribute.expr()) {
+                    // Providers may be given by version constraint alone
+                    Some(Value::String(version)) => json!({"version": version}),
+                    Some(requirement) => requirement,
+                    None => json!({"expression": attribute.expr().to_string()}),
+                };
+                providers.insert(attribute.key().to_string(), requirement);
+            }
+        }
+        let backend = body
+            .blocks()
+            .find(|b| b.identifier() == "backend")
+            .map(|b| {
+                json!({
+                    "type": labels(b).first(),
+                    "config": expressions(b.body(), &[]),
+                })
+            });
+        json!({
+            "required_version": argument(body, "required_version"),
+            "required_providers": providers,
+            "backend": backend,
+        })
+    }
+}
+
+impl FileParser for HclParser {
+    fn name(&self) -> &'static str {
+        "hcl"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["tf", "hcl"],
+            ..Default::default()
+        }
+    }
+
+    fn can_parse(&self, context: &ParseContext) -> bool {
+        has_extension(context.path(), &["tf"])
+            || (has_extension(context.path(), &["hcl"])
+                && context.text().is_ok_and(|c| {
+                    c.lines().any(|l| {
+                        BLOCKS.iter().any(|b| {
+                            l.strip_prefix(b)
+                                .is_some_and(|r| r.trim_start().starts_with('"'))
+                        })
+                    })
+                }))
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let body = hcl::parse(context.text()?)?;
+        let mut terraform = Value::Null;
+        let mut providers = Map::new();
+        let mut resources = vec![];
+        let mut modules = Map::new();
+        let mut variables = Map::new();
+        let mut outputs = Map::new();
+        let mut locals = Map::new();
+        for block in body.blocks() {
+            let labels = labels(block);
+            let name = labels.first().map(|l| l.to_string()).unwrap_or_default();
+            match block.identifier() {
+                "terraform" => terraform = HclParser::settings(block),
+                "provider" => {
+                    let alias = argument(block.body(), "alias");
+                    let key = match alias.as_ref().and_then(|a| a.as_str()) {
+                        Some(alias) => format!("{}.{}", name, alias),
+                        None => name.to_owned(),
+                    };
+                    providers.insert(
+                        key,
+                        json!({
+                            "name": name,
+                            "alias": alias,
+                            "expressions": expressions(block.body(), &["alias"]),
+                        }),
+                    );
+                }
+                "resource" => resources.push(HclParser::resource(block, "managed")?),
+                "data" => resources.push(HclParser::resource(block, "data")?),
+                "module" => {
+                    let body = block.body();
+                    modules.insert(
+                        name,
+                        json!({
+                            "source": argument(body, "source"),
+                            "version": argument(body, "version"),
+                            "expressions": expressions(body, &[META_ARGUMENTS, &["source", "version"]].concat()),
+                            "count_expression": body.attributes().find(|a| a.key() == "count").map(|a| expression(a.expr())),
+                            "for_each_expression": body.attributes().find(|a| a.key() == "for_each").map(|a| expression(a.expr())),
+                        }),
+                    );
+                }
+                "variable" => {
+                    variables.insert(name, HclParser::variable(block.body()));
+                }
+                "output" => {
+                    outputs.insert(name, HclParser::output(block.body()));
+                }
+                "locals" => locals.extend(expressions(block.body(), &[])),
+                _ => {}
+            }
+        }
+
+        Ok(ParsedDocument::new(json!({
+            "terraform": terraform,
+            "provider_config": providers,
+            "root_module": {
+                "resources": resources,
+                "module_calls": modules,
+                "variables": variables,
+                "outputs": outputs,
+                "locals": locals,
+            },
+        })))
+    }
+}
@@ -0,0 +1,324 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Parser for Terragrunt configuration, resolving the configuration each `terragrunt.hcl`
+//! inherits through its `include` blocks.
+//!
+//! HCL expressions are not evaluated: they are returned as `${...}` strings, apart from the
+//! functions used to locate included files and references to literal `local` values.
+
+use super::{Capabilities, FileParser, ParseContext, ParsedDocument};
+use crate::errors::*;
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// The file name of Terragrunt configuration, which `find_in_parent_folders()` looks for
+/// by default.
+const CONFIG: &str = "terragrunt.hcl";
+
+/// Keys of the configuration holding blocks or values keyed by name, which are merged
+/// name by name rather than replaced by a shallow merge.
+const KEYED: &[&str] = &["inputs", "dependency", "generate"];
+
+/// An included configuration file.
+struct Include {
+    name: Option<String>,
+    /// The expression of the path of the file.
+    path: String,
+    /// The file, if its path can be resolved.
+    resolved: Option<PathBuf>,
+    merge_strategy: String,
+    expose: bool,
+}
+
+/// Read and parse a Terragrunt configuration file.
+fn read(path: &Path) -> Result<Map<String, Value>> {
+    match hcl::from_str(&fs::read_to_string(path)?)? {
+        Value::Object(config) => Ok(config),
+        _ => bail!("Configuration is not a body of attributes and blocks"),
+    }
+}
+
+/// Resolve the path of an included file from the directory of the including file, where
+/// the path is literal or uses `find_in_parent_folders()` or `get_terragrunt_dir()`.
+fn resolve(dir: &Path, expression: &str) -> Option<PathBuf> {
+    if let Some(arguments) = expression
+        .strip_prefix("${find_in_parent_folders(")
+        .and_then(|e| e.strip_suffix(")}"))
+    {
+        let name = match arguments.trim().trim_matches('"') {
+            "" => CONFIG,
+            name => name,
+        };
+        return dir
+            .ancestors()
+            .skip(1)
+            .map(|d| d.join(name))
+            .find(|p| p.is_file());
+    }
+    let expression = expression.replace("${get_terragrunt_dir()}", &dir.to_string_lossy());
+    (!expression.contains("${")).then(|| dir.join(expression))
+}
+
+/// Return the path of a file relative to a directory, with `/` separators, where both are
+/// absolute and normalised.
+fn relative(dir: &Path, path: &Path) -> String {
+    let dir: Vec<Component> = dir.components().collect();
+    let path: Vec<Component> = path.components().collect();
+    let common = dir.iter().zip(&path).take_while(|(a, b)| a == b).count();
+    std::iter::repeat_n("..".to_string(), dir.len() - common)
+        .chain(
+            path[common..]
+                .iter()
+                .map(|c| c.as_os_str().to_string_lossy().to_string()),
+        )
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Return the includes of a configuration, which are either a single unlabelled `include`
+/// block or `include` blocks labelled with their names.
+fn includes(config: &Map<String, Value>, dir: &Path) -> Vec<Include> {
+    let include = |name: Option<&String>, block: &Value| {
+        let path = block
+            .get("path")
+            .and_then(|p| p.as_str())
+            .unwrap_or_default();
+        Include {
+            name: name.cloned(),
+            path: path.to_string(),
+            resolved: resolve(dir, path).and_then(|p| p.canonicalize().ok()),
+            merge_strategy: block
+                .get("merge_strategy")
+                .and_then(|s| s.as_str())
+                .unwrap_or("shallow")
+                .to_string(),
+            expose: block
+                .get("expose")
+                .and_then(|e| e.as_bool())
+                .unwrap_or(false),
+        }
+    };
+    match config.get("include") {
+        Some(Value::Object(block)) if block.get("path").is_some_and(|p| p.is_string()) => {
+            vec![include(None, &Value::Object(block.to_owned()))]
+        }
+        Some(Value::Object(blocks)) => blocks
+            .iter()
+            .map(|(name, block)| include(Some(name), block))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Merge two values recursively, the child taking precedence. Lists are concatenated.
+fn deep_merge(parent: Value, child: Value) -> Value {
+    match (parent, child) {
+        (Value::Object(mut parent), Value::Object(child)) => {
+            for (key, value) in child {
+                let merged = match parent.remove(&key) {
+                    Some(existing) => deep_merge(existing, value),
+                    None => value,
+                };
+                parent.insert(key, merged);
+            }
+            Value::Object(parent)
+        }
+        (Value::Array(mut parent), Value::Array(child)) => {
+            parent.extend(child);
+            Value::Array(parent)
+        }
+        (_, child) => child,
+    }
+}
+
+/// Merge an included configuration into the configuration including it, with Terragrunt's
+/// `merge_strategy`. The `include` and `locals` of the included configuration are not
+/// inherited.
+fn merge(
+    mut parent: Map<String, Value>,
+    child: Map<String, Value>,
+    strategy: &str,
+) -> Map<String, Value> {
+    parent.remove("include");
+    parent.remove("locals");
+    match strategy {
+        "no_merge" => child,
+        "deep" => match deep_merge(Value::Object(parent), Value::Object(child)) {
+            Value::Object(merged) => merged,
+            _ => Map::new(),
+        },
+        _ => {
+            for (key, value) in child {
+                let merged = match (parent.remove(&key), value) {
+                    (Some(Value::Object(mut existing)), Value::Object(value))
+                        if KEYED.contains(&key.as_str()) =>
+                    {
+                        existing.extend(value);
+                        Value::Object(existing)
+                    }
+                    (_, value) => value,
+                };
+                parent.insert(key, merged);
+            }
+            parent
+        }
+    }
+}
+
+/// Replace references to literal local values, such as `${local.env}`, within a value.
+fn interpolate(value: &mut Value, locals: &Map<String, Value>) {
+    match value {
+        Value::String(s) if s.contains("${local.") => {
+            let mut text = s.to_owned();
+            for (name, local) in locals {
+                let reference = format!("${{local.{}}}", name);
+                if text == reference {
+                    *value = local.to_owned();
+                    return;
+                }
+                match local {
+                    Value::String(l) => text = text.replace(&reference, l),
+                    Value::Number(_) | Value::Bool(_) => {
+                        text = text.replace(&reference, &local.to_string())
+                    }
+                    _ => {}
+                }
+            }
+            *value = Value::String(text);
+        }
+        Value::Array(items) => items.iter_mut().for_each(|i| interpolate(i, locals)),
+        Value::Object(map) => map.values_mut().for_each(|v| interpolate(v, locals)),
+        _ => {}
+    }
+}
+
+/// File parser for Terragrunt `terragrunt.hcl` files.
+///
+/// Returns the `includes` of the file, each with its `name`, if labelled, its `path`
+/// expression, the `resolved` path relative to the file, its `merge_strategy` and whether it
+/// is exposed, and the `chain` of files included, directly or through other includes, from
+/// the most distant. Also returns the `effective` configuration, with included
+/// configurations merged according to their merge strategies, and from it the Terraform
+/// `source`, the `remote_state` block, the `inputs`, the `dependencies`, from `dependency`
+/// blocks and `dependencies` paths, and the file's own `locals`.
+pub struct TerragruntParser {}
+
+impl TerragruntParser {
+    /// Return the effective configuration of a file and the files it includes, from the
+    /// most distant, warning of includes which cannot be resolved or read.
+    fn effective(
+        path: &Path,
+        visited: &mut Vec<PathBuf>,
+        context: &ParseContext,
+    ) -> Result<(Map<String, Value>, Vec<PathBuf>)> {
+        visited.push(path.to_owned());
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let mut config = read(path)?;
+        let mut chain = vec![];
+        for include in includes(&config, dir) {
+            let Some(resolved) = include.resolved else {
+                context.warn(format!("Could not resolve include path {}", include.path));
+                continue;
+            };
+            if visited.contains(&resolved) {
+                context.warn(format!("Include of {} is circular", resolved.display()));
+                continue;
+            }
+            match TerragruntParser::effective(&resolved, visited, context) {
+                Ok((parent, parent_chain)) => {
+                    chain.extend(parent_chain);
+                    chain.push(resolved);
+                    config = merge(parent, config, &include.merge_strategy);
+                }
+                Err(e) => context.warn(format!("Could not read {}: {}", resolved.display(), e)),
+            }
+        }
+        Ok((config, chain))
+    }
+}
+
+impl FileParser for TerragruntParser {
+    fn name(&self) -> &'static str {
+        "terragrunt"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["hcl"],
+            ..Default::default()
+        }
+    }
+
+    fn can_parse(&self, context: &ParseContext) -> bool {
+        context.path().file_name().is_some_and(|n| n == CONFIG)
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let path = context.path().canonicalize()?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let own = read(&path)?;
+        let locals = match own.get("locals") {
+            Some(Value::Object(locals)) => locals.to_owned(),
+            _ => Map::new(),
+        };
+        let (mut effective, chain) = TerragruntParser::effective(&path, &mut vec![], context)?;
+        effective.remove("include");
+        let mut effective = Value::Object(effective);
+        interpolate(&mut effective, &locals);
+
+        let mut dependencies: Vec<Value> = effective
+            .get("dependency")
+            .and_then(|d| d.as_object())
+            .into_iter()
+            .flatten()
+            .map(|(name, block)| json!({"name": name, "config_path": block.get("config_path")}))
+            .collect();
+        dependencies.extend(
+            effective
+                .pointer("/dependencies/paths")
+                .and_then(|p| p.as_array())
+                .into_iter()
+                .flatten()
+                .map(|p| json!({"name": null, "config_path": p})),
+        );
+
+        Ok(ParsedDocument {
+            name: dir.file_name().map(|n| n.to_string_lossy().to_string()),
+            value: json!({
+                "includes": includes(&own, dir)
+                    .into_iter()
+                    .map(|i| json!({
+                        "name": i.name,
+                        "path": i.path,
+                        "resolved": i.resolved.map(|r| relative(dir, &r)),
+                        "merge_strategy": i.merge_strategy,
+                        "expose": i.expose,
+                    }))
+                    .collect::<Vec<_>>(),
+                "chain": chain.iter().map(|c| relative(dir, c)).collect::<Vec<_>>(),
+                "source": effective.pointer("/terraform/source"),
+                "remote_state": effective.get("remote_state"),
+                "inputs": effective.get("inputs").cloned().unwrap_or(json!({})),
+                "dependencies": dependencies,
+                "locals": locals,
+                "effective": effective,
+            }),
+            ..Default::default()
+        })
+    }
+}
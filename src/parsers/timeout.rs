@@ -0,0 +1,166 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Time limits on parsing individual files.
+
+use super::{Capabilities, FileParser, ParseContext, ParsedDocument};
+use crate::errors::*;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+/// A file parser which limits the time taken by another parser to parse a single file.
+///
+/// Parsing runs on a separate thread. A parser cannot be interrupted, so a parser which
+/// times out continues on its thread in the background, but its result is discarded and
+/// parsing continues with the next parser or file.
+pub struct TimeoutParser {
+    parser: Arc<dyn FileParser>,
+    timeout: Duration,
+}
+
+impl TimeoutParser {
+    /// Run a parsing function on a separate thread with its own copy of the context,
+    /// passing any warnings it records back to the original context.
+    fn run<T: Send + 'static>(
+        &self,
+        context: &ParseContext,
+        parse: fn(&dyn FileParser, &ParseContext) -> Result<T>,
+    ) -> Result<T> {
+        let parser = self.parser.clone();
+        let path = context.path().to_path_buf();
+        let options = context.options().clone();
+        let contents = context
+            .bytes()
+            .map(|c| c.to_vec())
+            .map_err(|e| e.to_string());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let context = ParseContext::with_contents(&path, &options, contents);
+            let result = parse(parser.as_ref(), &context);
+            // The receiver is dropped if parsing timed out, so the result is ignored
+            let _ = sender.send((result, context.take_warnings()));
+        });
+        match receiver.recv_timeout(self.timeout) {
+            Ok((result, warnings)) => {
+                warnings.into_iter().for_each(|w| context.warn(w));
+                result
+            }
+            Err(_) => bail!(format!(
+                "Parsing timed out after {}s",
+                self.timeout.as_secs_f64()
+            )),
+        }
+    }
+}
+
+impl FileParser for TimeoutParser {
+    fn name(&self) -> &'static str {
+        self.parser.name()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.parser.capabilities()
+    }
+
+    fn can_parse(&self, context: &ParseContext) -> bool {
+        self.parser.can_parse(context)
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        self.run(context, |p, context| p.parse(context))
+    }
+
+    fn parse_documents(&self, context: &ParseContext) -> Result<Vec<ParsedDocument>> {
+        self.run(context, |p, context| p.parse_documents(context))
+    }
+}
+
+/// Limit the time taken by each parser to parse a single file.
+pub fn with_timeout(
+    parsers: Vec<Box<dyn FileParser>>,
+    timeout: Duration,
+) -> Vec<Box<dyn FileParser>> {
+    parsers
+        .into_iter()
+        .map(|parser| {
+            Box::new(TimeoutParser {
+                parser: Arc::from(parser),
+                timeout,
+            }) as Box<dyn FileParser>
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::errors::*;
+    use crate::parsers::timeout::with_timeout;
+    use crate::parsers::{Capabilities, FileParser, ParseContext, ParsedDocument, ParserOptions};
+    use serde_json::Value;
+    use std::path::Path;
+    use std::time::Duration;
+
+    /// A parser which takes a second to parse any file.
+    struct SlowParser {}
+    impl FileParser for SlowParser {
+        fn name(&self) -> &'static str {
+            "slow"
+        }
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities::default()
+        }
+
+        fn can_parse(&self, _: &ParseContext) -> bool {
+            true
+        }
+
+        fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+            if context.text()? == "slow" {
+                std::thread::sleep(Duration::from_secs(1));
+            }
+            Ok(ParsedDocument::new(Value::Null))
+        }
+    }
+
+    #[test]
+    fn timeout() {
+        let parsers = with_timeout(vec![Box::new(SlowParser {})], Duration::from_millis(100));
+        let options = ParserOptions::default();
+        let parse =
+            |text| parsers[0].parse(&ParseContext::with_text(Path::new("a.txt"), &options, text));
+
+        assert_eq!(parsers[0].name(), "slow");
+        assert!(parse("fast").is_ok());
+        assert_eq!(
+            parsers[0]
+                .parse_documents(&ParseContext::with_text(
+                    Path::new("a.txt"),
+                    &options,
+                    "fast"
+                ))
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(parse("slow").unwrap_err().to_string().contains("timed out"));
+        assert!(parsers[0]
+            .parse(&ParseContext::new(Path::new("missing.txt"), &options))
+            .is_err());
+    }
+}
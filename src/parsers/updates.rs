@@ -0,0 +1,314 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Parsers for the configuration of the Renovate and Dependabot dependency update tools.
+
+use super::kubernetes::REDACTED;
+use super::{Capabilities, FileParser, ParseContext, ParsedDocument};
+use crate::errors::*;
+use serde_json::{json, Map, Value};
+
+/// The file names of Renovate configuration files.
+const RENOVATE_FILES: &[&str] = &[
+    "renovate.json",
+    "renovate.json5",
+    ".renovaterc",
+    ".renovaterc.json",
+    ".renovaterc.json5",
+];
+
+/// Keys of Renovate package rules which select the packages a rule applies to, besides
+/// those beginning `match` or `exclude`.
+const RENOVATE_SELECTORS: &[&str] = &[
+    "packageNames",
+    "packagePatterns",
+    "sourceUrlPrefixes",
+    "depTypeList",
+    "paths",
+    "languages",
+    "managers",
+    "datasources",
+    "updateTypes",
+];
+
+/// Return the file name of a path being parsed.
+fn file_name<'a>(context: &'a ParseContext) -> &'a str {
+    context
+        .path()
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default()
+}
+
+/// Return a value which may be a single item or a list of items as a list.
+fn list(value: Option<&Value>) -> Value {
+    match value {
+        None | Some(Value::Null) => json!([]),
+        Some(Value::Array(items)) => Value::Array(items.to_owned()),
+        Some(item) => json!([item]),
+    }
+}
+
+/// Remove comments and trailing commas from JSON5 text, leaving the text to be parsed as
+/// JSON or, for unquoted keys and single-quoted strings, as YAML.
+fn strip_json5(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut stripped = String::new();
+    let mut quote: Option<char> = None;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match quote {
+            Some(q) => {
+                stripped.push(c);
+                if c == '\\' {
+                    stripped.extend(chars.get(i + 1));
+                    i += 1;
+                } else if c == q {
+                    quote = None;
+                }
+            }
+            None if c == '/' && chars.get(i + 1) == Some(&'/') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            None if c == '/' && chars.get(i + 1) == Some(&'*') => {
+                i += 2;
+                while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                    i += 1;
+                }
+                i += 2;
+                continue;
+            }
+            None if c == ',' => {
+                let next = chars[i + 1..].iter().find(|c| !c.is_whitespace());
+                if !matches!(next, Some('}') | Some(']')) {
+                    stripped.push(c);
+                }
+            }
+            None => {
+                if c == '"' || c == '\'' {
+                    quote = Some(c);
+                }
+                stripped.push(c);
+            }
+        }
+        i += 1;
+    }
+    stripped
+}
+
+/// File parser for Renovate configuration: `renovate.json`, `renovate.json5` and
+/// `.renovaterc` files.
+///
+/// Returns the presets the configuration `extends`, its `schedule` and `timezone`, the
+/// general update settings such as `automerge`, `labels` and pull request limits, the
+/// `enabled_managers` and ignored dependencies and paths, the `package_rules` in order,
+/// each with its `description`, the `match` criteria selecting packages and the `settings`
+/// it applies, and the `vulnerability_alerts` and `lock_file_maintenance` settings.
+pub struct RenovateParser {}
+
+impl FileParser for RenovateParser {
+    fn name(&self) -> &'static str {
+        "renovate"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["json", "json5"],
+            ..Default::default()
+        }
+    }
+
+    fn can_parse(&self, context: &ParseContext) -> bool {
+        RENOVATE_FILES.contains(&file_name(context))
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let text = strip_json5(context.text()?);
+        let config: Value = match serde_json::from_str(&text) {
+            Ok(config) => config,
+            Err(_) => serde_yaml::from_str(&text)?,
+        };
+        if !config.is_object() {
+            bail!("Renovate configuration is not an object")
+        }
+
+        let package_rules: Vec<Value> = config
+            .get("packageRules")
+            .and_then(|r| r.as_array())
+            .into_iter()
+            .flatten()
+            .map(|rule| {
+                let mut selectors = Map::new();
+                let mut settings = Map::new();
+                for (key, value) in rule.as_object().into_iter().flatten() {
+                    if key == "description" {
+                        continue;
+                    } else if key.starts_with("match")
+                        || key.starts_with("exclude")
+                        || RENOVATE_SELECTORS.contains(&key.as_str())
+                    {
+                        selectors.insert(key.to_owned(), value.to_owned());
+                    } else {
+                        settings.insert(key.to_owned(), value.to_owned());
+                    }
+                }
+                let description = match rule.get("description") {
+                    Some(Value::Array(lines)) => Some(
+                        lines
+                            .iter()
+                            .filter_map(|l| l.as_str())
+                            .collect::<Vec<_>>()
+                            .join(" "),
+                    ),
+                    Some(Value::String(description)) => Some(description.to_owned()),
+                    _ => None,
+                };
+                json!({
+                    "description": description,
+                    "match": selectors,
+                    "settings": settings,
+                })
+            })
+            .collect();
+
+        Ok(ParsedDocument::new(json!({
+            "tool": "renovate",
+            "extends": list(config.get("extends")),
+            "schedule": list(config.get("schedule")),
+            "timezone": config.get("timezone"),
+            "automerge": config.get("automerge"),
+            "automerge_type": config.get("automergeType"),
+            "labels": list(config.get("labels")),
+            "reviewers": list(config.get("reviewers")),
+            "assignees": list(config.get("assignees")),
+            "pr_concurrent_limit": config.get("prConcurrentLimit"),
+            "pr_hourly_limit": config.get("prHourlyLimit"),
+            "range_strategy": config.get("rangeStrategy"),
+            "enabled_managers": list(config.get("enabledManagers")),
+            "ignore_deps": list(config.get("ignoreDeps")),
+            "ignore_paths": list(config.get("ignorePaths")),
+            "package_rules": package_rules,
+            "vulnerability_alerts": config.get("vulnerabilityAlerts"),
+            "lock_file_maintenance": config.get("lockFileMaintenance"),
+        })))
+    }
+}
+
+/// File parser for Dependabot version updates configuration, `.github/dependabot.yml`.
+///
+/// Returns the private `registries`, with credentials redacted, and the `updates` in order,
+/// each with its `ecosystem`, `directories`, `schedule`, `target_branch`, pull request
+/// limit, `labels`, `reviewers` and `assignees`, the dependencies it will `allow` and
+/// `ignore`, its dependency `groups` and the `registries` it uses.
+pub struct DependabotParser {}
+
+impl FileParser for DependabotParser {
+    fn name(&self) -> &'static str {
+        "dependabot"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extensions: &["yml", "yaml"],
+            ..Default::default()
+        }
+    }
+
+    fn can_parse(&self, context: &ParseContext) -> bool {
+        ["dependabot.yml", "dependabot.yaml"].contains(&file_name(context))
+    }
+
+    fn parse(&self, context: &ParseContext) -> Result<ParsedDocument> {
+        let config: Value = serde_yaml::from_str(context.text()?)?;
+        match config.get("version").and_then(|v| v.as_u64()) {
+            Some(2) => (),
+            Some(version) => bail!(format!("Unsupported Dependabot version {}", version)),
+            None => bail!("Dependabot configuration has no version"),
+        }
+
+        let registries: Vec<Value> = config
+            .get("registries")
+            .and_then(|r| r.as_object())
+            .into_iter()
+            .flatten()
+            .map(|(name, registry)| {
+                let mut registry = registry.as_object().cloned().unwrap_or_default();
+                for (key, value) in registry.iter_mut() {
+                    if ["password", "key", "token"].contains(&key.as_str()) {
+                        *value = Value::String(REDACTED.to_string());
+                    }
+                }
+                registry.insert("name".to_string(), json!(name));
+                Value::Object(registry)
+            })
+            .collect();
+
+        let updates: Vec<Value> = config
+            .get("updates")
+            .and_then(|u| u.as_array())
+            .into_iter()
+            .flatten()
+            .map(|update| {
+                let field = |name: &str| update.get(name).cloned().unwrap_or(Value::Null);
+                let directories = match update.get("directories") {
+                    Some(directories) => list(Some(directories)),
+                    None => list(update.get("directory")),
+                };
+                let groups: Vec<Value> = update
+                    .get("groups")
+                    .and_then(|g| g.as_object())
+                    .into_iter()
+                    .flatten()
+                    .map(|(name, group)| {
+                        json!({
+                            "name": name,
+                            "applies_to": group.get("applies-to"),
+                            "dependency_type": group.get("dependency-type"),
+                            "patterns": list(group.get("patterns")),
+                            "exclude_patterns": list(group.get("exclude-patterns")),
+                            "update_types": list(group.get("update-types")),
+                        })
+                    })
+                    .collect();
+                json!({
+                    "ecosystem": field("package-ecosystem"),
+                    "directories": directories,
+                    "schedule": field("schedule"),
+                    "target_branch": field("target-branch"),
+                    "open_pull_requests_limit": field("open-pull-requests-limit"),
+                    "versioning_strategy": field("versioning-strategy"),
+                    "labels": list(update.get("labels")),
+                    "reviewers": list(update.get("reviewers")),
+                    "assignees": list(update.get("assignees")),
+                    "allow": list(update.get("allow")),
+                    "ignore": list(update.get("ignore")),
+                    "groups": groups,
+                    "registries": list(update.get("registries")),
+                })
+            })
+            .collect();
+
+        Ok(ParsedDocument::new(json!({
+            "tool": "dependabot",
+            "registries": registries,
+            "updates": updates,
+        })))
+    }
+}
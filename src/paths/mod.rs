@@ -0,0 +1,288 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Platform-independent path handling, including Windows long (`\\?\`) and UNC paths.
+//!
+//! The Rust standard library adds the long path prefix itself when a path is too long for
+//! the Windows APIs, so paths are handled without the prefix, which glob expressions cannot
+//! match.
+
+use std::path::{Component, Path, PathBuf};
+
+/// The prefix of a Windows verbatim (long) path, e.g. `\\?\C:\docs`.
+const VERBATIM_PREFIX: &str = r"\\?\";
+/// The prefix of a Windows verbatim UNC path, e.g. `\\?\UNC\server\share`.
+const VERBATIM_UNC_PREFIX: &str = r"\\?\UNC\";
+
+/// Remove any Windows verbatim prefix from a path, so that `\\?\C:\docs` becomes `C:\docs`
+/// and `\\?\UNC\server\share` becomes `\\server\share`.
+pub fn strip_verbatim(path: &str) -> String {
+    if let Some(unc) = path.strip_prefix(VERBATIM_UNC_PREFIX) {
+        format!(r"\\{}", unc)
+    } else {
+        path.strip_prefix(VERBATIM_PREFIX)
+            .unwrap_or(path)
+            .to_string()
+    }
+}
+
+/// Replace backslash path separators with forward slashes.
+fn to_forward_slashes(path: &str) -> String {
+    path.replace('\\', "/")
+}
+
+/// Normalise a listed source path, removing any verbatim prefix and, on Windows, using
+/// forward slashes as separators so that paths are consistent across platforms.
+pub fn normalise(path: &Path) -> PathBuf {
+    let path = strip_verbatim(&path.to_string_lossy());
+    PathBuf::from(if cfg!(windows) {
+        to_forward_slashes(&path)
+    } else {
+        path
+    })
+}
+
+/// Lexically clean a path, removing `.` components and resolving `..` components, whilst
+/// keeping any Windows drive or UNC prefix.
+///
+/// `..` components at the root of a path are removed, whilst those at the start of a
+/// relative path are kept.
+pub fn clean(path: &Path) -> PathBuf {
+    let mut components: Vec<Component> = vec![];
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match components.last() {
+                Some(Component::Normal(_)) => {
+                    components.pop();
+                }
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => components.push(component),
+            },
+            _ => components.push(component),
+        }
+    }
+    if components.is_empty() {
+        PathBuf::from(".")
+    } else {
+        components.iter().collect()
+    }
+}
+
+/// Split a path into its root, such as `/`, `//server/share/` or `C:/`, and its normal
+/// components, once any verbatim prefix has been removed, separators have been replaced
+/// with forward slashes and `.` and `..` components have been resolved lexically.
+fn portable_components(path: &str) -> (String, Vec<String>) {
+    let path = to_forward_slashes(&strip_verbatim(path));
+    let (root, rest) = if let Some(unc) = path.strip_prefix("//") {
+        let mut parts = unc.splitn(3, '/');
+        let server = parts.next().unwrap_or_default();
+        let share = parts.next().unwrap_or_default();
+        (
+            format!("//{}/{}/", server, share),
+            parts.next().unwrap_or_default().to_string(),
+        )
+    } else if path.len() >= 2
+        && path.as_bytes()[1] == b':'
+        && path.as_bytes()[0].is_ascii_alphabetic()
+    {
+        let rest = &path[2..];
+        match rest.strip_prefix('/') {
+            Some(rest) => (format!("{}/", &path[..2]), rest.to_string()),
+            None => (path[..2].to_string(), rest.to_string()),
+        }
+    } else if let Some(rest) = path.strip_prefix('/') {
+        ("/".to_string(), rest.to_string())
+    } else {
+        (String::new(), path)
+    };
+
+    let mut components: Vec<String> = vec![];
+    for component in rest.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => match components.last() {
+                Some(last) if last != ".." => {
+                    components.pop();
+                }
+                _ if !root.is_empty() => {}
+                _ => components.push(component.to_string()),
+            },
+            _ => components.push(component.to_string()),
+        }
+    }
+    (root, components)
+}
+
+/// Normalise a path for use on any platform and in links, with forward slashes as
+/// separators, no repeated separators or `.` components and `..` components resolved
+/// lexically.
+pub fn portable(path: &str) -> String {
+    let (root, components) = portable_components(path);
+    match (root.is_empty(), components.is_empty()) {
+        (true, true) => ".".to_string(),
+        _ => format!("{}{}", root, components.join("/")),
+    }
+}
+
+/// Join a path onto a base path, as portable paths. A path with a root replaces the base.
+pub fn join(base: &str, path: &str) -> String {
+    if !portable_components(path).0.is_empty() {
+        portable(path)
+    } else {
+        portable(&format!("{}/{}", to_forward_slashes(base), path))
+    }
+}
+
+/// Return a portable path relative to a base directory, or `None` if the path and base do
+/// not share a root, such as a relative path and an absolute base.
+pub fn relative_to(path: &str, base: &str) -> Option<String> {
+    let (path_root, path) = portable_components(path);
+    let (base_root, base) = portable_components(base);
+    if path_root != base_root {
+        return None;
+    }
+    let common = path
+        .iter()
+        .zip(base.iter())
+        .take_while(|(p, b)| p == b)
+        .count();
+    if base[common..].iter().any(|c| c == "..") {
+        return None;
+    }
+    let relative: Vec<&str> = std::iter::repeat_n("..", base.len() - common)
+        .chain(path[common..].iter().map(|c| c.as_str()))
+        .collect();
+    Some(if relative.is_empty() {
+        ".".to_string()
+    } else {
+        relative.join("/")
+    })
+}
+
+/// Return whether a path is within a directory, once both have been cleaned and any
+/// verbatim prefixes removed.
+pub fn is_within(path: &Path, dir: &Path) -> bool {
+    let without_prefix = |p: &Path| clean(Path::new(&strip_verbatim(&p.to_string_lossy())));
+    without_prefix(path).starts_with(without_prefix(dir))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::paths::{
+        clean, is_within, join, normalise, portable, relative_to, strip_verbatim,
+        to_forward_slashes,
+    };
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn verbatim() {
+        assert_eq!(strip_verbatim(r"\\?\C:\docs\a.yaml"), r"C:\docs\a.yaml");
+        assert_eq!(
+            strip_verbatim(r"\\?\UNC\server\share\a.yaml"),
+            r"\\server\share\a.yaml"
+        );
+        assert_eq!(
+            strip_verbatim(r"\\server\share\a.yaml"),
+            r"\\server\share\a.yaml"
+        );
+        assert_eq!(strip_verbatim("docs/a.yaml"), "docs/a.yaml");
+    }
+
+    #[test]
+    fn separators() {
+        assert_eq!(
+            to_forward_slashes(r"\\server\share\docs\a.yaml"),
+            "//server/share/docs/a.yaml"
+        );
+        assert_eq!(
+            normalise(Path::new("docs/a.yaml")),
+            PathBuf::from("docs/a.yaml")
+        );
+    }
+
+    #[test]
+    fn cleaning() {
+        assert_eq!(clean(Path::new("/tmp/./a/../b")), PathBuf::from("/tmp/b"));
+        assert_eq!(clean(Path::new("/../a")), PathBuf::from("/a"));
+        assert_eq!(clean(Path::new("../a/./b/..")), PathBuf::from("../a"));
+        assert_eq!(clean(Path::new("a/..")), PathBuf::from("."));
+
+        assert!(is_within(
+            Path::new("/tmp/out/a/../b.md"),
+            Path::new("/tmp/out")
+        ));
+        assert!(!is_within(
+            Path::new("/tmp/out/../b.md"),
+            Path::new("/tmp/out")
+        ));
+    }
+
+    #[test]
+    fn portable_paths() {
+        assert_eq!(portable(r"docs\.\api\\..\index.md"), "docs/index.md");
+        assert_eq!(portable("/docs//a/../../../b"), "/b");
+        assert_eq!(portable("../a/./b/.."), "../a");
+        assert_eq!(portable("a/.."), ".");
+        assert_eq!(portable(r"C:\docs\a.md"), "C:/docs/a.md");
+        assert_eq!(
+            portable(r"\\?\UNC\server\share\a\..\b.md"),
+            "//server/share/b.md"
+        );
+
+        assert_eq!(
+            join("docs/api", "../guides/intro.md"),
+            "docs/guides/intro.md"
+        );
+        assert_eq!(join("docs", "/tmp/a.md"), "/tmp/a.md");
+        assert_eq!(join(r"docs\api", "v1"), "docs/api/v1");
+
+        assert_eq!(
+            relative_to("docs/guides/intro.md", "docs/api").unwrap(),
+            "../guides/intro.md"
+        );
+        assert_eq!(relative_to("/out/a/b.md", "/out").unwrap(), "a/b.md");
+        assert_eq!(relative_to("docs", "docs/").unwrap(), ".");
+        assert_eq!(
+            relative_to(r"C:\out\a.md", "C:/out/docs").unwrap(),
+            "../a.md"
+        );
+        assert!(relative_to("docs/a.md", "/docs").is_none());
+        assert!(relative_to("a.md", "../docs").is_none());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows() {
+        assert_eq!(
+            clean(Path::new(r"\\server\share\docs\..\a.md")),
+            PathBuf::from(r"\\server\share\a.md")
+        );
+        assert!(is_within(
+            Path::new(r"\\?\C:\out\docs\a.md"),
+            Path::new(r"C:\out")
+        ));
+        assert!(!is_within(
+            Path::new(r"\\?\UNC\server\share\out\..\a.md"),
+            Path::new(r"\\server\share\out")
+        ));
+        assert_eq!(
+            normalise(Path::new(r"\\?\C:\docs\a.yaml")),
+            PathBuf::from("C:/docs/a.yaml")
+        );
+    }
+}
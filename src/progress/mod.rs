@@ -0,0 +1,289 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Progress of a `kvasir document --resume` run, persisted so that an interrupted run can be
+//! resumed without repeating completed work.
+//!
+//! As the run progresses, the results of each parsed source file and the hash of each output
+//! file written are appended to files in the `.kvasir-progress` directory of the output
+//! directory, so that progress survives the process being killed part way through. A run
+//! with `--resume` reuses the results of source files whose contents and parser options are
+//! unchanged, and skips output files which were already written with the same contents.
+//! The directory is removed once a run completes.
+//!
+//! The time at which the interrupted run generated its output is also recorded, and a
+//! resumed run generates its output at the same time, so that output files holding the time,
+//! such as in their front matter, are recognised as already written.
+//!
+//! Parsed files are recorded by the cache of parsed files returned by [`start`], which is
+//! passed to parsing. Written files are tracked in the same way as cancellation and
+//! notifications, so that writing records its progress without it being passed through the
+//! pipeline.
+
+use crate::cache::ParseCache;
+use crate::clock;
+use crate::errors::*;
+use crate::manifest::{self, Checksum};
+use crate::output::{OutputFile, WriteMode};
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// The name of the directory within the output directory holding the progress of a run.
+pub const PROGRESS_DIR: &str = ".kvasir-progress";
+
+/// The file listing the results of each parsed source file, one JSON object per line.
+const PARSED_FILE: &str = "parsed.jsonl";
+
+/// The file listing the hash of each output file written, one JSON object per line.
+const WRITTEN_FILE: &str = "written.jsonl";
+
+/// The file holding the time at which the run generates its output, as an RFC 3339
+/// timestamp.
+const GENERATED_FILE: &str = "generated";
+
+static PROGRESS: Mutex<Option<Progress>> = Mutex::new(None);
+
+/// The progress of the current run.
+struct Progress {
+    dir: PathBuf,
+    written: HashSet<Checksum>,
+    written_file: File,
+}
+
+/// Read the JSON lines of a progress file, ignoring a final line left incomplete by an
+/// interrupted run.
+fn read_lines<T: for<'de> Deserialize<'de>>(path: &Path) -> Vec<T> {
+    let Ok(file) = File::open(path) else {
+        return vec![];
+    };
+    BufReader::new(file)
+        .lines()
+        .map_while(|line| line.ok())
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect()
+}
+
+/// Open a progress file for appending.
+fn append(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .chain_err(|| format!("Could not open progress file {}", path.display()))
+}
+
+/// Append a line of JSON to a progress file, logging rather than failing the run if it
+/// cannot be written.
+fn write_line<T: Serialize>(file: &mut File, value: &T) {
+    let result = serde_json::to_string(value)
+        .map_err(Error::from)
+        .and_then(|line| Ok(writeln!(file, "{}", line)?));
+    if let Err(e) = result {
+        warn!("Could not record progress: {}", e);
+    }
+}
+
+/// Return the hash identifying an output file written in a given way with given contents.
+fn output_hash(entry: &OutputFile) -> Checksum {
+    Checksum {
+        path: entry.path.to_owned(),
+        sha256: manifest::sha256(
+            format!(
+                "{:?}\n{:?}\n{}",
+                entry.options.mode, entry.options.section, entry.content
+            )
+            .as_bytes(),
+        ),
+    }
+}
+
+/// Start recording the progress of a run in the output directory, resuming from the
+/// progress of a previous run, if any.
+///
+/// Return the time at which the run should generate its output: that of the previous run,
+/// if any, or else the current time, which is recorded for any later run. Also return the
+/// cache of parsed files, which records them for any later run. Parsed files are only
+/// reused if they were parsed with the same version, identifying the version of kvasir and
+/// settings other than the parser options, such as the parse timeout.
+pub fn start(output_dir: &Path, version: &str) -> Result<(DateTime<Utc>, ParseCache)> {
+    let dir = output_dir.join(PROGRESS_DIR);
+    fs::create_dir_all(&dir)
+        .chain_err(|| format!("Could not create progress directory {}", dir.display()))?;
+    let generated_file = dir.join(GENERATED_FILE);
+    let generated = match fs::read_to_string(&generated_file)
+        .ok()
+        .and_then(|t| clock::parse(&t))
+    {
+        Some(generated) => generated,
+        None => {
+            let now = clock::now();
+            fs::write(
+                &generated_file,
+                now.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            )
+            .chain_err(|| format!("Could not write progress file {}", generated_file.display()))?;
+            now
+        }
+    };
+    let parsed = ParseCache::open(&dir.join(PARSED_FILE), version)?;
+    let written: HashSet<Checksum> = read_lines(&dir.join(WRITTEN_FILE)).into_iter().collect();
+    if !parsed.is_empty() || !written.is_empty() {
+        info!(
+            "Resuming a previous run: {} source files parsed and {} output files written.",
+            parsed.len(),
+            written.len()
+        );
+    }
+    let progress = Progress {
+        written_file: append(&dir.join(WRITTEN_FILE))?,
+        dir,
+        written,
+    };
+    if let Ok(mut current) = PROGRESS.lock() {
+        *current = Some(progress);
+    }
+    Ok((generated, parsed))
+}
+
+/// Return whether an output file was written with the same contents by a previous run.
+/// Files which replace an existing file must also be unchanged since.
+pub fn is_written(entry: &OutputFile) -> bool {
+    let Ok(progress) = PROGRESS.lock() else {
+        return false;
+    };
+    let Some(progress) = progress.as_ref() else {
+        return false;
+    };
+    progress.written.contains(&output_hash(entry))
+        && (entry.options.mode != WriteMode::Replace
+            || manifest::file_sha256(&entry.path)
+                .is_ok_and(|h| h == manifest::sha256(entry.content.as_bytes())))
+}
+
+/// Record that an output file has been written.
+pub fn record_written(entry: &OutputFile) {
+    if let Ok(mut progress) = PROGRESS.lock() {
+        if let Some(progress) = progress.as_mut() {
+            let checksum = output_hash(entry);
+            if progress.written.insert(checksum.to_owned()) {
+                write_line(&mut progress.written_file, &checksum);
+            }
+        }
+    }
+}
+
+/// Finish recording the progress of a completed run, removing the progress directory.
+pub fn finish() -> Result<()> {
+    let progress = PROGRESS.lock().ok().and_then(|mut p| p.take());
+    if let Some(progress) = progress {
+        drop(progress.written_file);
+        fs::remove_dir_all(&progress.dir).chain_err(|| {
+            format!(
+                "Could not remove progress directory {}",
+                progress.dir.display()
+            )
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::manifest;
+    use crate::output::{OutputFile, WriteMode, WriteOptions};
+    use crate::parsers::{ParseFailure, ParseSuccess};
+    use crate::progress::{finish, is_written, record_written, start, PROGRESS_DIR};
+    use std::fs;
+    use std::io::Write;
+
+    #[test]
+    fn resume() {
+        let dir = std::env::temp_dir().join(format!("kvasir-progress-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("orders.yaml");
+        fs::write(&source, "name: orders").unwrap();
+        let success: ParseSuccess = serde_json::from_value(serde_json::json!({
+            "path": source,
+            "parser": "yaml",
+            "contents": {"name": "orders"},
+        }))
+        .unwrap();
+        let entry = |name: &str, content: &str, mode: WriteMode| OutputFile {
+            path: dir.join(name),
+            content: content.to_string(),
+            attributes: Default::default(),
+            options: WriteOptions {
+                mode,
+                ..Default::default()
+            },
+        };
+        let page = entry("orders.md", "# Orders", WriteMode::Replace);
+        let changelog = entry("CHANGELOG.md", "orders", WriteMode::Append);
+
+        let hash = manifest::file_sha256(&source).unwrap();
+        let (generated, mut cache) = start(&dir, "1.0").unwrap();
+        assert!(cache.get(&source, &hash, &[]).is_none());
+        let failure = ParseFailure {
+            path: source.to_owned(),
+            parser: "openapi-v3".to_string(),
+            error: "missing field `openapi`".into(),
+        };
+        cache.insert(&source, &hash, &[], &[success.to_owned()], &[failure]);
+        drop(cache);
+        fs::write(&page.path, &page.content).unwrap();
+        record_written(&page);
+        record_written(&changelog);
+        // A line left incomplete when the run was interrupted is ignored
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(dir.join(PROGRESS_DIR).join("written.jsonl"))
+            .unwrap();
+        write!(file, "{{\"path\": \"page").unwrap();
+
+        // The resumed run generates its output at the time of the interrupted run
+        let (resumed, cache) = start(&dir, "1.0").unwrap();
+        assert_eq!(resumed, generated);
+        let (successes, failures) = cache.get(&source, &hash, &[]).unwrap();
+        assert_eq!(successes[0].contents, success.contents);
+        assert_eq!(failures[0].parser, "openapi-v3");
+        assert_eq!(failures[0].error.to_string(), "missing field `openapi`");
+        assert!(is_written(&page) && is_written(&changelog));
+        assert!(!is_written(&entry(
+            "orders.md",
+            "# Changed",
+            WriteMode::Replace
+        )));
+        fs::write(&page.path, "# Edited").unwrap();
+        assert!(!is_written(&page));
+
+        // Files parsed by another version of kvasir are parsed again
+        drop(cache);
+        let (_, cache) = start(&dir, "2.0").unwrap();
+        assert!(cache.get(&source, &hash, &[]).is_none());
+
+        drop(cache);
+        finish().unwrap();
+        assert!(!dir.join(PROGRESS_DIR).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
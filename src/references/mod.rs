@@ -0,0 +1,183 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! References to values held outside parsed files, such as secrets in Vault or parameters
+//! in AWS Systems Manager, found in placeholders like `{{vault:secret/orders#password}}` or
+//! `${ssm:/orders/db/url}` and listed as the `external_refs` of each file with
+//! `--external-refs`.
+//!
+//! Placeholders are `{{...}}` or `${...}` containing the name of a store, optionally
+//! preceded by `resolve:` as in CloudFormation dynamic references, and the path of the
+//! value within the store, optionally followed by `#` and a field of the value. Referenced
+//! values are never fetched.
+
+use crate::parsers::ParseSuccess;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The stores whose placeholders are recognised.
+const STORES: &[&str] = &["vault", "ssm", "secretsmanager"];
+
+/// A reference from a value of a parsed file to a value held in an external store.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExternalRef {
+    /// The dotted key of the value containing the placeholder, e.g. `db.password`.
+    pub key: String,
+    /// The store holding the referenced value, e.g. `vault` or `ssm`.
+    pub store: String,
+    /// The path of the referenced value within the store.
+    pub path: String,
+    /// The field of the referenced value, if any, e.g. `password`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    /// The placeholder, as written in the file.
+    pub placeholder: String,
+}
+
+/// Return the store, path and field of a placeholder's contents, if it is a reference.
+fn reference(inner: &str) -> Option<(&str, &str, Option<&str>)> {
+    let inner = inner.trim();
+    let inner = inner.strip_prefix("resolve:").unwrap_or(inner);
+    let (store, target) = inner.split_once(':')?;
+    if !STORES.contains(&store) || target.is_empty() {
+        return None;
+    }
+    match target.split_once('#') {
+        Some((path, field)) => Some((store, path, Some(field))),
+        None => Some((store, target, None)),
+    }
+}
+
+/// Add the references made by the placeholders in a string value to a list of references.
+fn find_in_text(text: &str, key: &str, found: &mut Vec<ExternalRef>) {
+    let mut rest = text;
+    while let Some(start) = rest.find(['{', '$']) {
+        let (open, close) = match &rest[start..] {
+            r if r.starts_with("{{") => ("{{", "}}"),
+            r if r.starts_with("${") => ("${", "}"),
+            _ => {
+                rest = &rest[start + 1..];
+                continue;
+            }
+        };
+        let after = &rest[start + open.len()..];
+        let Some(end) = after.find(close) else { break };
+        if let Some((store, path, field)) = reference(&after[..end]) {
+            found.push(ExternalRef {
+                key: key.to_owned(),
+                store: store.to_string(),
+                path: path.to_string(),
+                field: field.map(String::from),
+                placeholder: rest[start..start + open.len() + end + close.len()].to_string(),
+            });
+        }
+        rest = &after[end + close.len()..];
+    }
+}
+
+/// Return the references made by the placeholders within a value, in the order of the keys
+/// of the value.
+pub fn find(value: &Value, key: &str) -> Vec<ExternalRef> {
+    let mut found = vec![];
+    match value {
+        Value::Object(map) => {
+            for (name, child) in map {
+                let path = match key.is_empty() {
+                    true => name.to_owned(),
+                    false => format!("{}.{}", key, name),
+                };
+                found.extend(find(child, &path));
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                found.extend(find(item, &format!("{}[{}]", key, i)));
+            }
+        }
+        Value::String(text) => find_in_text(text, key, &mut found),
+        _ => {}
+    }
+    found
+}
+
+/// Set the external references of each parsed file.
+pub fn annotate(files: &mut [ParseSuccess]) {
+    for file in files {
+        file.external_refs = find(&file.contents, "");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::references::{find, ExternalRef};
+    use serde_json::json;
+
+    #[test]
+    fn external_refs() {
+        let value = json!({
+            "db": {
+                "url": "jdbc:postgresql://${ssm:/orders/db/host}:5432/orders",
+                "password": "{{vault:secret/data/orders#password}}"
+            },
+            "api_keys": ["{{ resolve:secretsmanager:orders/api-key }}", "${env:HOME}"],
+            "greeting": "Hello {{name}}"
+        });
+        let reference =
+            |key: &str, store: &str, path: &str, field: Option<&str>, placeholder| ExternalRef {
+                key: key.to_string(),
+                store: store.to_string(),
+                path: path.to_string(),
+                field: field.map(String::from),
+                placeholder: String::from(placeholder),
+            };
+        assert_eq!(
+            find(&value, ""),
+            [
+                reference(
+                    "db.url",
+                    "ssm",
+                    "/orders/db/host",
+                    None,
+                    "${ssm:/orders/db/host}"
+                ),
+                reference(
+                    "db.password",
+                    "vault",
+                    "secret/data/orders",
+                    Some("password"),
+                    "{{vault:secret/data/orders#password}}"
+                ),
+                reference(
+                    "api_keys[0]",
+                    "secretsmanager",
+                    "orders/api-key",
+                    None,
+                    "{{ resolve:secretsmanager:orders/api-key }}"
+                ),
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(&find(&value, "")[0]).unwrap(),
+            json!({
+                "key": "db.url",
+                "store": "ssm",
+                "path": "/orders/db/host",
+                "placeholder": "${ssm:/orders/db/host}"
+            })
+        );
+    }
+}
@@ -0,0 +1,226 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Remote sources, given as `http` or `https` URLs in place of glob expressions.
+//!
+//! Each remote source is downloaded to the cache directory before parsing, and is parsed
+//! from there, keeping the file name of the URL so that parsers recognise it. Requests
+//! which time out, fail to connect or receive a `429` or `5xx` response are retried with
+//! exponential backoff, configured in the `[remote]` section of the configuration file,
+//! whilst other error responses fail immediately.
+//!
+//! Sources that cannot be fetched fail the run by default, so that flaky servers do not
+//! silently produce incomplete documentation. With `on_failure = "warn"` they are skipped
+//! with a warning instead, and counted in the summary of the run.
+
+use crate::config::{RemoteConfig, RemoteFailure, Source};
+use crate::errors::*;
+use crate::manifest::sha256;
+use crate::notify;
+use log::{info, warn};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// The default number of times a failed request is retried.
+const DEFAULT_RETRIES: u32 = 3;
+
+/// The default number of seconds to wait before the first retry.
+const DEFAULT_BACKOFF: f64 = 1.0;
+
+/// The default maximum number of seconds taken by each request.
+const DEFAULT_TIMEOUT: f64 = 30.0;
+
+static FAILURES: AtomicUsize = AtomicUsize::new(0);
+
+/// Return whether a source is a remote URL rather than a glob expression.
+pub fn is_remote(glob: &str) -> bool {
+    glob.starts_with("http://") || glob.starts_with("https://")
+}
+
+/// Return the number of remote sources skipped in this run because they could not be
+/// fetched.
+pub fn failures() -> usize {
+    FAILURES.load(Ordering::SeqCst)
+}
+
+/// Return the file name of a URL, without any query or fragment.
+fn file_name(url: &str) -> &str {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let (_, name) = path.split_once("://").unwrap_or(("", path));
+    match name.rsplit_once('/') {
+        Some((_, name)) if !name.is_empty() => name,
+        _ => "index",
+    }
+}
+
+/// Make a single request for a URL, returning the response body, and whether a failure
+/// may be retried.
+fn request(url: &str, timeout: Duration) -> std::result::Result<Vec<u8>, (String, bool)> {
+    match ureq::get(url).timeout(timeout).call() {
+        Ok(response) => {
+            let mut body = vec![];
+            response
+                .into_reader()
+                .read_to_end(&mut body)
+                .map_err(|e| (e.to_string(), true))?;
+            Ok(body)
+        }
+        Err(ureq::Error::Status(status, response)) => Err((
+            format!("{} {}", status, response.status_text()),
+            status == 429 || status >= 500,
+        )),
+        Err(e) => Err((e.to_string(), true)),
+    }
+}
+
+/// Download a URL to a directory within the cache directory named after its hash,
+/// retrying failed requests, and return the path of the downloaded file.
+pub fn fetch(url: &str, config: &RemoteConfig, cache_dir: &Path) -> Result<PathBuf> {
+    let retries = config.retries.unwrap_or(DEFAULT_RETRIES);
+    let timeout = Duration::from_secs_f64(config.timeout.unwrap_or(DEFAULT_TIMEOUT));
+    let mut backoff = Duration::from_secs_f64(config.backoff.unwrap_or(DEFAULT_BACKOFF));
+    let mut attempt = 0;
+    let body = loop {
+        match request(url, timeout) {
+            Ok(body) => break body,
+            Err((error, true)) if attempt < retries => {
+                attempt += 1;
+                warn!(
+                    "Could not fetch {} ({}), retrying in {:.1}s ({} of {})",
+                    url,
+                    error,
+                    backoff.as_secs_f64(),
+                    attempt,
+                    retries
+                );
+                std::thread::sleep(backoff);
+                backoff *= 2;
+            }
+            Err((error, _)) => bail!(format!("{} after {} attempts", error, attempt + 1)),
+        }
+    };
+    let dir = cache_dir
+        .join("sources")
+        .join(&sha256(url.as_bytes())[..16]);
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(file_name(url));
+    fs::write(&path, body)?;
+    info!("Fetched {} to {}", url, path.display());
+    Ok(path)
+}
+
+/// Replace each remote source with the file it is downloaded to, failing if a source
+/// cannot be fetched, or skipping it with a warning if so configured.
+pub fn resolve(
+    sources: Vec<Source>,
+    config: &RemoteConfig,
+    cache_dir: &Path,
+) -> Result<Vec<Source>> {
+    let mut resolved = vec![];
+    for source in sources {
+        if !is_remote(&source.glob) {
+            resolved.push(source);
+            continue;
+        }
+        match fetch(&source.glob, config, cache_dir) {
+            Ok(path) => resolved.push(Source::new(
+                &glob::Pattern::escape(&path.to_string_lossy()),
+                &source.tags,
+            )),
+            Err(e) => match config.on_failure {
+                RemoteFailure::Fail => {
+                    bail!(format!(
+                        "Could not fetch remote source {}: {}",
+                        source.glob, e
+                    ))
+                }
+                RemoteFailure::Warn => {
+                    warn!(
+                        "Could not fetch remote source {}, which is not documented: {}",
+                        source.glob, e
+                    );
+                    FAILURES.fetch_add(1, Ordering::SeqCst);
+                    notify::record(|s| s.remote_failures += 1);
+                }
+            },
+        }
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::config::{RemoteConfig, RemoteFailure, Source};
+    use crate::remote::{fetch, file_name, resolve};
+    use crate::testing::{Response, Server};
+
+    fn unavailable() -> Response {
+        Response::new("503 Service Unavailable")
+    }
+
+    fn ok() -> Response {
+        Response::ok("a: 1\r\n")
+    }
+
+    fn config(retries: u32, on_failure: RemoteFailure) -> RemoteConfig {
+        RemoteConfig {
+            retries: Some(retries),
+            backoff: Some(0.0),
+            timeout: Some(5.0),
+            on_failure,
+        }
+    }
+
+    #[test]
+    fn file_names() {
+        assert_eq!(file_name("https://example.com/a/b.yaml?ref=main"), "b.yaml");
+        assert_eq!(file_name("https://example.com/"), "index");
+        assert_eq!(file_name("https://example.com"), "index");
+    }
+
+    #[test]
+    fn retries() {
+        let cache_dir = std::env::temp_dir().join(format!("kvasir-remote-{}", std::process::id()));
+
+        let server = Server::sequence(vec![unavailable(), unavailable(), ok()]);
+        let path = fetch(
+            &format!("{}/config/app.yaml", server.url),
+            &config(2, RemoteFailure::Fail),
+            &cache_dir,
+        )
+        .unwrap();
+        assert_eq!(server.requests().len(), 3);
+        assert!(path.ends_with("app.yaml"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a: 1\r\n");
+
+        let server = Server::sequence(vec![unavailable(), unavailable()]);
+        let error = fetch(&server.url, &config(1, RemoteFailure::Fail), &cache_dir).unwrap_err();
+        assert_eq!(server.requests().len(), 2);
+        assert!(error.to_string().contains("503"));
+
+        // Client errors are not retried
+        let server = Server::sequence(vec![Response::new("404 Not Found"), ok()]);
+        let sources = vec![Source::new(&server.url, &[]), Source::from("*.yaml")];
+        assert!(resolve(sources, &config(3, RemoteFailure::Fail), &cache_dir).is_err());
+        assert_eq!(server.requests().len(), 1);
+
+        std::fs::remove_dir_all(&cache_dir).unwrap();
+    }
+}
@@ -0,0 +1,223 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Versioned JSON output schemas.
+//!
+//! The JSON written by `parse` and `context`, and the context provided to templates, follow
+//! a schema selected with `--output-schema`. Keys are only ever added to within a schema
+//! version; any other change to the structure of the output introduces a new version, and
+//! earlier versions remain available.
+//!
+//! Schema version 1 is used by default, so that the output only changes shape when a newer
+//! version is asked for. `parse` writes the following envelope with `--include-failures` or
+//! from schema version 2, and otherwise writes only the `files`:
+//!
+//! * `schemaVersion`: the version of the schema.
+//! * `files`: the successfully parsed source files.
+//! * `failures`: the source files that a parser accepted but could not parse, each with a
+//!   `path`, `parser` and `error`.
+//! * `summary`: the number of `files`, `failures` and parser `warnings`, the number of
+//!   `remoteFailures`, remote sources skipped as they could not be fetched, and the
+//!   `durationMs` taken to list and parse the source files.
+
+use crate::context::Failure;
+use crate::parsers::{ParseFailure, ParseSuccess};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::time::Duration;
+
+/// A version of the JSON output schema.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputSchema {
+    /// The original output: `parse` writes an array of files unless `--include-failures`
+    /// is given, and the template context has a top-level `profile` key.
+    #[value(name = "1")]
+    V1,
+    /// `parse` always writes the envelope, and the template context has a `schemaVersion`
+    /// key but no top-level `profile` key.
+    #[value(name = "2")]
+    V2,
+}
+
+impl OutputSchema {
+    /// The schema version used unless another is asked for.
+    pub const DEFAULT: OutputSchema = OutputSchema::V1;
+
+    /// The latest schema version.
+    pub const LATEST: OutputSchema = OutputSchema::V2;
+
+    /// Conform parsed files to the schema. Schema version 1 only has the `index` and `name`
+    /// of the documents in files containing more than one document, whereas later versions
+    /// have the `index` of every document.
+    pub fn conform(self, files: &mut [ParseSuccess]) {
+        for file in files {
+            match self {
+                OutputSchema::V1 if file.index.is_none() => file.name = None,
+                OutputSchema::V1 => {}
+                OutputSchema::V2 => {
+                    file.index.get_or_insert(0);
+                }
+            }
+        }
+    }
+
+    /// Return the version number of the schema.
+    pub fn version(self) -> u32 {
+        match self {
+            OutputSchema::V1 => 1,
+            OutputSchema::V2 => 2,
+        }
+    }
+}
+
+/// The result of parsing source files, including failures.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParseReport<'a> {
+    pub schema_version: u32,
+    pub files: &'a [ParseSuccess],
+    pub failures: Vec<Failure>,
+    pub summary: Summary,
+}
+
+/// Counts and timing of a parse run.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Summary {
+    pub files: usize,
+    pub failures: usize,
+    pub warnings: usize,
+    pub remote_failures: usize,
+    pub duration_ms: u128,
+}
+
+impl<'a> ParseReport<'a> {
+    /// Create a report with the given schema from parsing results, the number of remote
+    /// sources that could not be fetched and the time taken to produce them.
+    pub fn new(
+        successes: &'a [ParseSuccess],
+        failures: &[ParseFailure],
+        remote_failures: usize,
+        duration: Duration,
+        schema: OutputSchema,
+    ) -> ParseReport<'a> {
+        ParseReport {
+            schema_version: schema.version(),
+            files: successes,
+            failures: failures.iter().map(Failure::from).collect(),
+            summary: Summary {
+                files: successes.len(),
+                failures: failures.len(),
+                warnings: successes.iter().map(|s| s.warnings.len()).sum(),
+                remote_failures,
+                duration_ms: duration.as_millis(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::parsers::{ParseFailure, ParseSuccess};
+    use crate::report::{OutputSchema, ParseReport};
+    use serde_json::json;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    fn success(path: &str, index: Option<usize>, name: Option<&str>) -> ParseSuccess {
+        ParseSuccess {
+            path: PathBuf::from(path),
+            parser: "json".to_string(),
+            index,
+            name: name.map(String::from),
+            contents: json!({"a": 1}),
+            descriptions: Default::default(),
+            metadata: Default::default(),
+            warnings: vec![],
+            tags: vec![],
+            owners: vec![],
+            duplicates: vec![],
+            external_refs: vec![],
+        }
+    }
+
+    #[test]
+    fn envelope() {
+        let mut successes = vec![success("a.json", None, None)];
+        successes[0].warnings.push("repeated key".to_string());
+        let failures = vec![ParseFailure {
+            path: PathBuf::from("b.yaml"),
+            parser: "yaml".to_string(),
+            error: "invalid".into(),
+        }];
+
+        assert_eq!(
+            serde_json::to_value(ParseReport::new(
+                &successes,
+                &failures,
+                0,
+                Duration::from_millis(12),
+                OutputSchema::V1
+            ))
+            .unwrap(),
+            json!({
+                "schemaVersion": 1,
+                "files": [{
+                    "path": "a.json",
+                    "parser": "json",
+                    "contents": {"a": 1},
+                    "warnings": ["repeated key"]
+                }],
+                "failures": [{"path": "b.yaml", "parser": "yaml", "error": "invalid"}],
+                "summary": {"files": 1, "failures": 1, "warnings": 1, "remoteFailures": 0, "durationMs": 12}
+            })
+        );
+    }
+
+    #[test]
+    fn documents() {
+        let files = || {
+            vec![
+                success("a.json", None, Some("single")),
+                success("b.json", Some(0), Some("first")),
+                success("b.json", Some(1), None),
+            ]
+        };
+
+        let mut v1 = files();
+        OutputSchema::V1.conform(&mut v1);
+        assert_eq!(
+            serde_json::to_string(&v1).unwrap(),
+            concat!(
+                r#"[{"path":"a.json","parser":"json","contents":{"a":1}},"#,
+                r#"{"path":"b.json","parser":"json","index":0,"name":"first","contents":{"a":1}},"#,
+                r#"{"path":"b.json","parser":"json","index":1,"contents":{"a":1}}]"#
+            )
+        );
+
+        let mut v2 = files();
+        OutputSchema::V2.conform(&mut v2);
+        assert_eq!(
+            serde_json::to_value(&v2).unwrap(),
+            json!([
+                {"path": "a.json", "parser": "json", "index": 0, "name": "single", "contents": {"a": 1}},
+                {"path": "b.json", "parser": "json", "index": 0, "name": "first", "contents": {"a": 1}},
+                {"path": "b.json", "parser": "json", "index": 1, "contents": {"a": 1}}
+            ])
+        );
+    }
+}
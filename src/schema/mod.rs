@@ -0,0 +1,219 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Validation of template variables against a JSON Schema.
+//!
+//! The commonly used keywords are supported: `type`, `enum`, `const`, `required`,
+//! `properties`, `additionalProperties`, `items`, `minimum`, `maximum`, `minLength`,
+//! `maxLength`, `minItems` and `maxItems`. Other keywords are ignored.
+
+use serde_json::Value;
+
+/// Validate a value against a schema, returning a message for each violation. Messages
+/// begin with the path of the invalid value below `root`, e.g. `vars.ports[0]`.
+pub fn validate(schema: &Value, value: &Value, root: &str) -> Vec<String> {
+    let mut errors = vec![];
+    check(schema, value, root, &mut errors);
+    errors
+}
+
+/// Check a value against a schema, adding a message for each violation.
+fn check(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    let schema = match schema {
+        Value::Object(schema) => schema,
+        Value::Bool(false) => {
+            errors.push(format!("{}: is not allowed", path));
+            return;
+        }
+        _ => return,
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(|t| t.as_str()).collect(),
+            _ => vec![],
+        };
+        if !types.is_empty() && !types.iter().any(|t| is_type(value, t)) {
+            errors.push(format!(
+                "{}: expected {}, found {}",
+                path,
+                types.join(" or "),
+                type_name(value)
+            ));
+            return;
+        }
+    }
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            errors.push(format!(
+                "{}: expected one of {}, found {}",
+                path,
+                allowed
+                    .iter()
+                    .map(|a| a.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                value
+            ));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            errors.push(format!("{}: expected {}, found {}", path, expected, value));
+        }
+    }
+
+    let bound = |name: &str| schema.get(name).and_then(|b| b.as_f64());
+    let mut check_bound = |name: &str, actual: f64, below: bool, what: &str| {
+        if let Some(limit) = bound(name) {
+            if (below && actual < limit) || (!below && actual > limit) {
+                errors.push(format!(
+                    "{}: {} must be {} {}, found {}",
+                    path,
+                    what,
+                    if below { "at least" } else { "at most" },
+                    limit,
+                    actual
+                ));
+            }
+        }
+    };
+    match value {
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or_default();
+            check_bound("minimum", n, true, "value");
+            check_bound("maximum", n, false, "value");
+        }
+        Value::String(s) => {
+            let length = s.chars().count() as f64;
+            check_bound("minLength", length, true, "length");
+            check_bound("maxLength", length, false, "length");
+        }
+        Value::Array(items) => {
+            check_bound("minItems", items.len() as f64, true, "number of items");
+            check_bound("maxItems", items.len() as f64, false, "number of items");
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check(item_schema, item, &format!("{}[{}]", path, i), errors);
+                }
+            }
+        }
+        Value::Object(map) => {
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for name in required.iter().filter_map(|r| r.as_str()) {
+                    if !map.contains_key(name) {
+                        errors.push(format!("{}: missing required variable '{}'", path, name));
+                    }
+                }
+            }
+            let properties = schema.get("properties").and_then(|p| p.as_object());
+            for (name, property) in map {
+                let property_path = format!("{}.{}", path, name);
+                match properties.and_then(|p| p.get(name)) {
+                    Some(property_schema) => {
+                        check(property_schema, property, &property_path, errors)
+                    }
+                    None => match schema.get("additionalProperties") {
+                        Some(Value::Bool(false)) => {
+                            errors.push(format!("{}: unexpected variable", property_path))
+                        }
+                        Some(additional) => check(additional, property, &property_path, errors),
+                        None => {}
+                    },
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Return whether a value has a JSON Schema type.
+fn is_type(value: &Value, expected: &str) -> bool {
+    match expected {
+        "integer" => value.as_f64().is_some_and(|n| n.fract() == 0.0),
+        "number" => value.is_number(),
+        other => type_name(value) == other,
+    }
+}
+
+/// Return the JSON Schema type name of a value.
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::schema::validate;
+    use serde_json::json;
+
+    #[test]
+    fn variables() {
+        let schema = json!({
+            "type": "object",
+            "required": ["environment", "team"],
+            "properties": {
+                "environment": {"type": "string", "enum": ["dev", "prod"]},
+                "team": {"type": "string"},
+                "replicas": {"type": "integer", "minimum": 1},
+                "ports": {"type": "array", "items": {"type": "integer"}},
+                "owner": {"type": "object", "required": ["email"]}
+            },
+            "additionalProperties": false
+        });
+
+        assert!(validate(
+            &schema,
+            &json!({"environment": "dev", "team": "platform", "replicas": 2.0}),
+            "vars"
+        )
+        .is_empty());
+        assert_eq!(
+            validate(
+                &schema,
+                &json!({
+                    "environment": "test",
+                    "replicas": 0,
+                    "ports": [80, "443"],
+                    "owner": {},
+                    "extra": true
+                }),
+                "vars"
+            ),
+            [
+                "vars: missing required variable 'team'",
+                "vars.environment: expected one of \"dev\", \"prod\", found \"test\"",
+                "vars.replicas: value must be at least 1, found 0",
+                "vars.ports[1]: expected integer, found string",
+                "vars.owner: missing required variable 'email'",
+                "vars.extra: unexpected variable"
+            ]
+        );
+        assert_eq!(
+            validate(&schema, &json!({"environment": 3, "team": "a"}), "vars"),
+            ["vars.environment: expected string, found integer"]
+        );
+    }
+}
@@ -0,0 +1,249 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! A search index over parsed source files and generated pages, for client-side search.
+//!
+//! The index is written as `search-index.json` in the output directory, containing:
+//!
+//! * `documents`: each indexed page and source file, with an `id`, a `type` of `page` or
+//!   `source`, a `path` and a `title`. Pages also list the `sources` that contributed to
+//!   them, and source files the `parser` that parsed them.
+//! * `index`: an inverted index from each lower-cased term to the `[id, count]` pairs of
+//!   the documents containing it, most frequent first.
+
+use crate::errors::*;
+use crate::output::OutputFile;
+use serde::Serialize;
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The name of the search index file written to the output directory.
+pub const SEARCH_INDEX_FILE: &str = "search-index.json";
+
+/// Common words which are not indexed.
+const STOP_WORDS: [&str; 20] = [
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "in", "is", "it", "of", "on",
+    "or", "that", "the", "to", "with",
+];
+
+/// A page or source file within the search index.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Document {
+    pub id: usize,
+    #[serde(rename = "type")]
+    pub document_type: &'static str,
+    pub path: String,
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parser: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<String>,
+}
+
+/// An inverted index of the terms in parsed source files and generated pages.
+#[derive(Debug, Default, Serialize)]
+pub struct SearchIndex {
+    pub documents: Vec<Document>,
+    pub index: BTreeMap<String, Vec<(usize, usize)>>,
+}
+
+impl SearchIndex {
+    /// Index parsed files, as they appear in the template context, by their path and the
+    /// keys and values of their contents.
+    pub fn add_sources(&mut self, files: &[Value]) {
+        for file in files {
+            let field = |name: &str| file.get(name).and_then(|v| v.as_str()).unwrap_or_default();
+            let mut text = field("path").to_string();
+            collect_text(file.get("contents").unwrap_or(&Value::Null), &mut text);
+            let title = file
+                .get("name")
+                .and_then(|n| n.as_str())
+                .unwrap_or(field("path"))
+                .to_string();
+            self.add(
+                Document {
+                    id: self.documents.len(),
+                    document_type: "source",
+                    path: field("path").to_string(),
+                    title,
+                    parser: Some(field("parser").to_string()),
+                    sources: vec![],
+                },
+                &text,
+            );
+        }
+    }
+
+    /// Index the contents of generated pages, with paths relative to the output directory.
+    pub fn add_pages(&mut self, pages: &[OutputFile], output_dir: &Path) {
+        for page in pages {
+            let path = page.path.strip_prefix(output_dir).unwrap_or(&page.path);
+            self.add(
+                Document {
+                    id: self.documents.len(),
+                    document_type: "page",
+                    path: path
+                        .components()
+                        .map(|c| c.as_os_str().to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join("/"),
+                    title: page_title(page),
+                    parser: None,
+                    sources: page
+                        .attributes
+                        .get("source")
+                        .into_iter()
+                        .flat_map(|s| s.split(','))
+                        .map(|s| s.trim().to_string())
+                        .filter(|s| !s.is_empty())
+                        .collect(),
+                },
+                &page.content,
+            );
+        }
+    }
+
+    /// Add a document to the index with the terms of its text.
+    fn add(&mut self, document: Document, text: &str) {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for term in terms(text) {
+            *counts.entry(term).or_default() += 1;
+        }
+        for (term, count) in counts {
+            let postings = self.index.entry(term).or_default();
+            postings.push((document.id, count));
+            postings.sort_by_key(|p| Reverse(p.1));
+        }
+        self.documents.push(document);
+    }
+
+    /// Write the index as JSON to the output directory.
+    pub fn write(&self, output_dir: &Path) -> Result<PathBuf> {
+        let path = output_dir.join(SEARCH_INDEX_FILE);
+        fs::write(&path, serde_json::to_string(self)?)?;
+        Ok(path)
+    }
+}
+
+/// Return the title of a page: its `title` attribute, its first Markdown heading or its
+/// file name without the extension.
+pub fn page_title(page: &OutputFile) -> String {
+    page.attributes
+        .get("title")
+        .cloned()
+        .or_else(|| {
+            page.content
+                .lines()
+                .find_map(|l| l.strip_prefix('#'))
+                .map(|h| h.trim_start_matches('#').trim().to_string())
+        })
+        .unwrap_or_else(|| {
+            page.path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default()
+        })
+}
+
+/// Append the keys and scalar values within a value to some text.
+fn collect_text(value: &Value, text: &mut String) {
+    match value {
+        Value::Object(map) => map.iter().for_each(|(k, v)| {
+            text.push(' ');
+            text.push_str(k);
+            collect_text(v, text);
+        }),
+        Value::Array(values) => values.iter().for_each(|v| collect_text(v, text)),
+        Value::String(s) => {
+            text.push(' ');
+            text.push_str(s);
+        }
+        Value::Null => {}
+        other => {
+            text.push(' ');
+            text.push_str(&other.to_string());
+        }
+    }
+}
+
+/// Split text into lower-cased terms of at least two characters, ignoring markup and
+/// common words.
+fn terms(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|t| t.chars().count() > 1)
+        .map(|t| t.to_lowercase())
+        .filter(|t| !STOP_WORDS.contains(&t.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::search::{terms, SearchIndex};
+    use serde_json::json;
+
+    #[test]
+    fn terms_of_text() {
+        assert_eq!(
+            terms("# The <b>Web</b> server: port=8080, a x_y").collect::<Vec<_>>(),
+            ["web", "server", "port", "8080", "x_y"]
+        );
+    }
+
+    #[test]
+    fn index() {
+        let output_dir = std::env::temp_dir();
+        let pages = crate::output::split_template_content(
+            "8<--",
+            "8<-- web.md source=k8s/web.yaml\n# Web service\nRuns nginx, nginx and more.\n",
+            output_dir.to_owned(),
+        )
+        .unwrap();
+
+        let mut index = SearchIndex::default();
+        index.add_sources(&[json!({
+            "path": "k8s/web.yaml",
+            "parser": "yaml",
+            "contents": {"image": "nginx", "replicas": 2}
+        })]);
+        index.add_pages(&pages, &output_dir);
+
+        assert_eq!(
+            serde_json::to_value(&index).unwrap()["documents"],
+            json!([
+                {
+                    "id": 0,
+                    "type": "source",
+                    "path": "k8s/web.yaml",
+                    "title": "k8s/web.yaml",
+                    "parser": "yaml"
+                },
+                {
+                    "id": 1,
+                    "type": "page",
+                    "path": "web.md",
+                    "title": "Web service",
+                    "sources": ["k8s/web.yaml"]
+                }
+            ])
+        );
+        assert_eq!(index.index["nginx"], [(1, 2), (0, 1)]);
+        assert_eq!(index.index["replicas"], [(0, 1)]);
+        assert_eq!(index.index["web"], [(0, 1), (1, 1)]);
+    }
+}
@@ -0,0 +1,287 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Signing of the manifest of a `document` run with `--sign`, and verification of the
+//! signature and of the generated files listed in the manifest with `kvasir verify`.
+//!
+//! As the manifest lists the SHA-256 hash of every generated file, signing the manifest
+//! signs the generated files. Manifests are signed either with a detached, ASCII-armoured
+//! GPG signature, written to `kvasir-manifest.json.asc` by the `gpg` command, or with an
+//! HMAC-SHA256 of the manifest keyed by the secret in the `KVASIR_SIGNING_SECRET`
+//! environment variable, written hex-encoded to `kvasir-manifest.json.hmac`.
+
+use crate::errors::*;
+use crate::manifest::{file_sha256, Manifest, MANIFEST_FILE};
+use clap::ValueEnum;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use walkdir::WalkDir;
+
+/// The environment variable holding the secret used to sign manifests with HMAC.
+pub const SECRET_VAR: &str = "KVASIR_SIGNING_SECRET";
+
+/// How a manifest is signed.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Signing {
+    /// A detached GPG signature, made with the `gpg` command.
+    Gpg,
+    /// An HMAC-SHA256 keyed by the secret in `KVASIR_SIGNING_SECRET`.
+    Hmac,
+}
+
+/// Files written to the output directory which are not listed in the manifest: the
+/// manifest and its signatures.
+const UNSIGNED_FILES: [&str; 3] = [
+    MANIFEST_FILE,
+    "kvasir-manifest.json.asc",
+    "kvasir-manifest.json.hmac",
+];
+
+/// The files of an output directory which do not match its signed manifest.
+#[derive(Debug, Default, PartialEq)]
+pub struct Verification {
+    /// Files listed in the manifest which have changed or are missing.
+    pub changed: Vec<PathBuf>,
+    /// Files in the output directory which are not listed in the manifest.
+    pub unlisted: Vec<PathBuf>,
+}
+
+impl Verification {
+    /// Return whether the output directory matches the manifest.
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.unlisted.is_empty()
+    }
+}
+
+impl Signing {
+    /// Return the path of the signature of the manifest in an output directory.
+    pub fn signature_file(&self, output_dir: &Path) -> PathBuf {
+        let extension = match self {
+            Signing::Gpg => "asc",
+            Signing::Hmac => "hmac",
+        };
+        output_dir.join(format!("{}.{}", MANIFEST_FILE, extension))
+    }
+}
+
+/// Return the secret used to sign manifests with HMAC.
+fn secret() -> Result<Vec<u8>> {
+    match std::env::var(SECRET_VAR) {
+        Ok(secret) if !secret.is_empty() => Ok(secret.into_bytes()),
+        _ => bail!(format!("{} must be set to sign with HMAC", SECRET_VAR)),
+    }
+}
+
+/// Return the HMAC-SHA256 of a manifest.
+fn hmac(secret: &[u8], manifest: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(manifest);
+    mac
+}
+
+/// Decode hex-encoded bytes.
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.trim();
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Run `gpg` with the given arguments, returning its standard output, or failing with its
+/// error output if it fails.
+pub fn gpg(args: &[&str]) -> Result<String> {
+    let output = Command::new("gpg")
+        .arg("--batch")
+        .args(args)
+        .output()
+        .chain_err(|| "Could not run gpg")?;
+    if !output.status.success() {
+        bail!(format!(
+            "gpg failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Sign the manifest in an output directory, with the given GPG key or the default key,
+/// returning the path of the signature.
+pub fn sign(output_dir: &Path, signing: Signing, key: Option<&str>) -> Result<PathBuf> {
+    let manifest = output_dir.join(MANIFEST_FILE);
+    let signature = signing.signature_file(output_dir);
+    match signing {
+        Signing::Gpg => {
+            let (manifest, signature) = (manifest.to_string_lossy(), signature.to_string_lossy());
+            let mut args = vec!["--yes", "--armor", "--output", &signature];
+            if let Some(key) = key {
+                args.extend(["--local-user", key]);
+            }
+            args.extend(["--detach-sign", &manifest]);
+            gpg(&args)?;
+        }
+        Signing::Hmac => {
+            let mac = hmac(&secret()?, &fs::read(&manifest)?);
+            let hex: String = mac
+                .finalize()
+                .into_bytes()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            fs::write(&signature, hex + "\n")?;
+        }
+    }
+    Ok(signature)
+}
+
+/// Verify the signature of the manifest in an output directory, that each generated file
+/// listed in the manifest is unchanged and that the directory contains no other files,
+/// returning the paths of any changed, missing or unlisted files.
+pub fn verify(output_dir: &Path, signing: Signing) -> Result<Verification> {
+    let signature = signing.signature_file(output_dir);
+    if !signature.is_file() {
+        bail!(ErrorKind::ValidationFailure(format!(
+            "No signature {}",
+            signature.display()
+        )))
+    }
+    let manifest_path = output_dir.join(MANIFEST_FILE);
+    match signing {
+        // A signature which does not verify fails validation, unlike failing to run gpg
+        Signing::Gpg => {
+            gpg(&[
+                "--verify",
+                &signature.to_string_lossy(),
+                &manifest_path.to_string_lossy(),
+            ])
+            .map_err(|e| match e.kind() {
+                ErrorKind::Msg(message) if message.starts_with("gpg failed") => {
+                    ErrorKind::ValidationFailure(message.to_owned()).into()
+                }
+                _ => e,
+            })?;
+        }
+        Signing::Hmac => {
+            let expected = decode_hex(&fs::read_to_string(&signature)?)
+                .ok_or("The HMAC signature is not hex-encoded")?;
+            if hmac(&secret()?, &fs::read(&manifest_path)?)
+                .verify_slice(&expected)
+                .is_err()
+            {
+                bail!(ErrorKind::ValidationFailure(
+                    "The manifest does not match its HMAC signature".to_string()
+                ))
+            }
+        }
+    }
+
+    let Some(manifest) = Manifest::read(output_dir)? else {
+        bail!(format!("No {} in {}", MANIFEST_FILE, output_dir.display()))
+    };
+    let listed = manifest
+        .files
+        .iter()
+        .map(|f| f.path.as_path())
+        .chain(UNSIGNED_FILES.iter().map(Path::new))
+        .collect::<HashSet<_>>();
+    let mut unlisted = vec![];
+    for entry in WalkDir::new(output_dir).sort_by_file_name() {
+        let entry = entry.chain_err(|| format!("Could not read {}", output_dir.display()))?;
+        let path = entry
+            .path()
+            .strip_prefix(output_dir)
+            .unwrap_or(entry.path());
+        if entry.file_type().is_file() && !listed.contains(path) {
+            unlisted.push(path.to_path_buf());
+        }
+    }
+    let changed = manifest
+        .files
+        .into_iter()
+        .filter(|f| {
+            file_sha256(&output_dir.join(&f.path)).map_or(true, |sha256| sha256 != f.sha256)
+        })
+        .map(|f| f.path)
+        .collect();
+    Ok(Verification { changed, unlisted })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::manifest::MANIFEST_FILE;
+    use crate::signing::{sign, verify, Signing, Verification, SECRET_VAR};
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn hmac_signatures() {
+        let dir = std::env::temp_dir().join(format!("kvasir-signing-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.md"), "a").unwrap();
+        fs::write(dir.join("b.md"), "b").unwrap();
+        fs::write(
+            dir.join(MANIFEST_FILE),
+            serde_json::json!({
+                "kvasir_version": "0.0.0",
+                "generated": "2024-01-01T00:00:00Z",
+                "engine": "tera",
+                "template": "base.tpl",
+                "templates": {},
+                "sources": [],
+                "files": [
+                    {"path": "a.md", "sha256": crate::manifest::sha256(b"a"), "sources": []},
+                    {"path": "b.md", "sha256": crate::manifest::sha256(b"b"), "sources": []}
+                ]
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        std::env::set_var(SECRET_VAR, "trusted-pipeline");
+        let signature = sign(&dir, Signing::Hmac, None).unwrap();
+        assert_eq!(signature, dir.join("kvasir-manifest.json.hmac"));
+        assert_eq!(fs::read_to_string(&signature).unwrap().trim().len(), 64);
+        assert!(verify(&dir, Signing::Hmac).unwrap().is_empty());
+
+        fs::write(dir.join("b.md"), "changed").unwrap();
+        fs::create_dir_all(dir.join("extra")).unwrap();
+        fs::write(dir.join("extra").join("c.md"), "c").unwrap();
+        fs::write(dir.join("search-index.json"), "[]").unwrap();
+        assert_eq!(
+            verify(&dir, Signing::Hmac).unwrap(),
+            Verification {
+                changed: vec![PathBuf::from("b.md")],
+                unlisted: vec![
+                    PathBuf::from("extra/c.md"),
+                    PathBuf::from("search-index.json")
+                ]
+            }
+        );
+
+        std::env::set_var(SECRET_VAR, "another-secret");
+        assert!(verify(&dir, Signing::Hmac).is_err());
+        assert!(verify(&dir, Signing::Gpg).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,338 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Index pages and XML sitemaps listing the split output files.
+//!
+//! The index page is rendered with a built-in Markdown or HTML template, chosen by the
+//! extension of the index page, or with a custom Tera template. Templates are provided:
+//!
+//! * `groups`: the pages grouped by directory or tag, each with a `name` and its `pages`.
+//!   Pages in the output directory itself, or without tags, are in a group with an empty
+//!   name, which is listed first.
+//! * `pages`: every page, each with its `path` relative to the output directory, a `link`
+//!   relative to the index page, a `title` and its `tags` and `source` attributes.
+//! * `generated`: the time at which the index was generated.
+
+use crate::errors::*;
+use crate::output::{OutputFile, WriteOptions};
+use crate::paths;
+use crate::search::page_title;
+use clap::ValueEnum;
+use itertools::Itertools;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The name of the sitemap file written to the output directory.
+pub const SITEMAP_FILE: &str = "sitemap.xml";
+
+/// The built-in template for Markdown index pages.
+const MARKDOWN_TEMPLATE: &str = "# Index
+{% for group in groups %}{% if group.name %}
+## {{ group.name }}
+{% endif %}
+{% for page in group.pages %}* [{{ page.title }}]({{ page.link }})
+{% endfor %}{% endfor %}";
+
+/// The built-in template for HTML index pages.
+const HTML_TEMPLATE: &str = "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>Index</title>
+</head>
+<body>
+<h1>Index</h1>
+{% for group in groups %}{% if group.name %}<h2>{{ group.name | escape }}</h2>
+{% endif %}<ul>
+{% for page in group.pages %}<li><a href=\"{{ page.link | urlencode }}\">{{ page.title | escape }}</a></li>
+{% endfor %}</ul>
+{% endfor %}</body>
+</html>
+";
+
+/// How pages are grouped on the index page.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum GroupBy {
+    /// Group pages by the directory containing them.
+    Directory,
+    /// Group pages by each of their `tags` attributes.
+    Tag,
+}
+
+/// A page listed on the index page.
+#[derive(Debug, Clone, Serialize)]
+struct Page {
+    path: String,
+    link: String,
+    title: String,
+    tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+}
+
+/// A group of pages on the index page.
+#[derive(Debug, Serialize)]
+struct Group {
+    name: String,
+    pages: Vec<Page>,
+}
+
+/// Return whether a file is an HTML page, for which a sitemap is written.
+pub fn is_html(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| ["html", "htm"].contains(&e.to_lowercase().as_str()))
+}
+
+/// Return the path of an output file relative to the output directory, as a portable path.
+fn relative_path(path: &Path, output_dir: &Path) -> String {
+    paths::portable(
+        &path
+            .strip_prefix(output_dir)
+            .unwrap_or(path)
+            .to_string_lossy(),
+    )
+}
+
+/// Create an index page at the provided path, relative to the output directory, listing
+/// the other output files grouped by directory or tag.
+///
+/// The page is rendered with the provided Tera template or, by default, the built-in HTML
+/// template for `.html` and `.htm` pages and the Markdown template for any other page. It
+/// replaces any existing file, as it is entirely generated.
+pub fn index_page(
+    files: &[OutputFile],
+    output_dir: &Path,
+    index: &Path,
+    group_by: GroupBy,
+    template: Option<&str>,
+) -> Result<OutputFile> {
+    let index_path = output_dir.join(index);
+    if !paths::is_within(&index_path, output_dir) {
+        bail!(format!(
+            "Index page {} is not a child of {}",
+            index.display(),
+            output_dir.display()
+        ))
+    }
+    let index_directory = paths::join(&relative_path(&index_path, output_dir), "..");
+    let pages: Vec<Page> = files
+        .iter()
+        .filter(|f| paths::clean(&f.path) != paths::clean(&index_path))
+        .map(|f| {
+            let path = relative_path(&f.path, output_dir);
+            Page {
+                link: paths::relative_to(&path, &index_directory).unwrap_or(path.to_owned()),
+                path,
+                title: page_title(f),
+                tags: f
+                    .attributes
+                    .get("tags")
+                    .into_iter()
+                    .flat_map(|t| t.split(','))
+                    .map(|t| t.trim().to_string())
+                    .filter(|t| !t.is_empty())
+                    .collect(),
+                source: f.attributes.get("source").cloned(),
+            }
+        })
+        .sorted_by(|a, b| a.path.cmp(&b.path))
+        .collect();
+
+    let mut groups: BTreeMap<String, Vec<Page>> = BTreeMap::new();
+    for page in &pages {
+        let names = match group_by {
+            GroupBy::Directory => vec![match page.path.rsplit_once('/') {
+                Some((directory, _)) => directory.to_string(),
+                None => String::new(),
+            }],
+            GroupBy::Tag if page.tags.is_empty() => vec![String::new()],
+            GroupBy::Tag => page.tags.to_owned(),
+        };
+        for name in names {
+            groups.entry(name).or_default().push(page.to_owned());
+        }
+    }
+
+    let mut context = tera::Context::new();
+    context.insert(
+        "groups",
+        &groups
+            .into_iter()
+            .map(|(name, pages)| Group { name, pages })
+            .collect::<Vec<_>>(),
+    );
+    context.insert("pages", &pages);
+    context.insert("generated", &crate::clock::timestamp());
+    let template = template.unwrap_or(if is_html(index) {
+        HTML_TEMPLATE
+    } else {
+        MARKDOWN_TEMPLATE
+    });
+    let content = tera::Tera::one_off(template, &context, false)
+        .map_err(|e| format!("Could not render index page template: {:?}", e))?;
+
+    Ok(OutputFile {
+        path: index_path,
+        content,
+        attributes: BTreeMap::from([("title".to_string(), "Index".to_string())]),
+        options: WriteOptions {
+            overwrite: Some(true),
+            ..Default::default()
+        },
+    })
+}
+
+/// Return an XML sitemap of the HTML output files, with URLs under the base URL.
+pub fn sitemap(files: &[OutputFile], output_dir: &Path, base_url: &str) -> String {
+    let modified = crate::clock::now().format("%Y-%m-%d").to_string();
+    let urls: String = files
+        .iter()
+        .filter(|f| is_html(&f.path))
+        .map(|f| relative_path(&f.path, output_dir))
+        .sorted()
+        .map(|path| {
+            format!(
+                "  <url>\n    <loc>{}/{}</loc>\n    <lastmod>{}</lastmod>\n  </url>\n",
+                xml_escape(base_url.trim_end_matches('/')),
+                xml_escape(&path),
+                modified
+            )
+        })
+        .collect();
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n{}</urlset>\n",
+        urls
+    )
+}
+
+/// Write an XML sitemap of the HTML output files to the output directory.
+pub fn write_sitemap(files: &[OutputFile], output_dir: &Path, base_url: &str) -> Result<PathBuf> {
+    let path = output_dir.join(SITEMAP_FILE);
+    fs::write(&path, sitemap(files, output_dir, base_url))?;
+    Ok(path)
+}
+
+/// Escape text for use within XML.
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::output::OutputFile;
+    use crate::sitemap::{index_page, sitemap, GroupBy};
+    use std::collections::BTreeMap;
+    use std::path::{Path, PathBuf};
+
+    fn page(path: &str, attributes: &[(&str, &str)]) -> OutputFile {
+        OutputFile {
+            path: PathBuf::from("/docs").join(path),
+            content: String::new(),
+            attributes: attributes
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<BTreeMap<_, _>>(),
+            options: Default::default(),
+        }
+    }
+
+    #[test]
+    fn index() {
+        let pages = vec![
+            page("services/web.md", &[("title", "Web"), ("tags", "prod,web")]),
+            page("overview.md", &[("tags", "prod")]),
+            page("services/api.md", &[]),
+        ];
+        let output_dir = Path::new("/docs");
+
+        let index = index_page(
+            &pages,
+            output_dir,
+            Path::new("index.md"),
+            GroupBy::Directory,
+            None,
+        )
+        .unwrap();
+        assert_eq!(index.path, PathBuf::from("/docs/index.md"));
+        assert_eq!(
+            index.content,
+            "# Index\n\n* [overview](overview.md)\n\n## services\n\n* [api](services/api.md)\n* [Web](services/web.md)\n"
+        );
+
+        let by_tag = index_page(
+            &pages,
+            output_dir,
+            Path::new("services/tags.md"),
+            GroupBy::Tag,
+            Some("{% for g in groups %}{{ g.name }}:{% for p in g.pages %} {{ p.link }}{% endfor %};{% endfor %}"),
+        )
+        .unwrap();
+        assert_eq!(
+            by_tag.content,
+            ": api.md;prod: ../overview.md web.md;web: web.md;"
+        );
+
+        let html = index_page(
+            &pages,
+            output_dir,
+            Path::new("index.html"),
+            GroupBy::Directory,
+            None,
+        )
+        .unwrap();
+        assert!(html
+            .content
+            .contains("<li><a href=\"services/web.md\">Web</a></li>"));
+        assert!(index_page(
+            &pages,
+            output_dir,
+            Path::new("../index.md"),
+            GroupBy::Tag,
+            None
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn sitemap_urls() {
+        let pages = vec![
+            page("b & c.html", &[]),
+            page("a/index.htm", &[]),
+            page("x.md", &[]),
+        ];
+        let xml = sitemap(&pages, Path::new("/docs"), "https://docs.example.com/");
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<urlset"));
+        let locs: Vec<&str> = xml
+            .lines()
+            .filter_map(|l| l.trim().strip_prefix("<loc>"))
+            .collect();
+        assert_eq!(
+            locs,
+            [
+                "https://docs.example.com/a/index.htm</loc>",
+                "https://docs.example.com/b &amp; c.html</loc>"
+            ]
+        );
+    }
+}
@@ -0,0 +1,231 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Snapshot testing of templates.
+//!
+//! Each subdirectory of a test case directory is a single test case, containing fixture
+//! source files within a `sources` directory and the expected template output in a
+//! `snapshot` file:
+//!
+//! ```text
+//! tests/
+//!   openapi/
+//!     sources/
+//!       petstore.yaml
+//!     snapshot
+//! ```
+
+use crate::errors::*;
+use log::{error, info};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The name of the directory containing the fixture source files of a test case.
+pub const SOURCES_DIR: &str = "sources";
+/// The name of the file containing the expected output of a test case.
+pub const SNAPSHOT_FILE: &str = "snapshot";
+
+/// The result of comparing rendered output against a stored snapshot.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// The output matched the snapshot.
+    Passed,
+    /// The output differed from the snapshot, first at the given (1-based) line.
+    Failed(usize),
+    /// No snapshot exists for the test case.
+    Missing,
+    /// The snapshot was written or rewritten with the rendered output.
+    Updated,
+}
+
+/// A test case, being a directory of fixture source files and a snapshot.
+#[derive(Debug)]
+pub struct Case {
+    pub name: String,
+    pub dir: PathBuf,
+}
+
+impl Case {
+    /// Return a glob expression matching all fixture source files of the test case.
+    pub fn sources_glob(&self) -> String {
+        self.dir
+            .join(SOURCES_DIR)
+            .join("**")
+            .join("*")
+            .display()
+            .to_string()
+    }
+
+    /// Return the path of the snapshot file of the test case.
+    pub fn snapshot_path(&self) -> PathBuf {
+        self.dir.join(SNAPSHOT_FILE)
+    }
+}
+
+/// List the test cases within a directory, in name order.
+pub fn list_cases(dir: &Path) -> Result<Vec<Case>> {
+    let mut cases = fs::read_dir(dir)
+        .chain_err(|| format!("Could not read test case directory {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.join(SOURCES_DIR).is_dir())
+        .map(|path| Case {
+            name: path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            dir: path,
+        })
+        .collect::<Vec<Case>>();
+    cases.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(cases)
+}
+
+/// Compare rendered output with the snapshot of a test case, or write the snapshot if
+/// `update` is set.
+pub fn check_snapshot(case: &Case, rendered: &str, update: bool) -> Result<Outcome> {
+    let path = case.snapshot_path();
+    if update {
+        if fs::read_to_string(&path).is_ok_and(|s| s == rendered) {
+            return Ok(Outcome::Passed);
+        }
+        fs::write(&path, rendered)?;
+        return Ok(Outcome::Updated);
+    }
+    match fs::read_to_string(&path) {
+        Ok(expected) if expected == rendered => Ok(Outcome::Passed),
+        Ok(expected) => Ok(Outcome::Failed(first_difference(&expected, rendered))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Outcome::Missing),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Run all test cases in a directory, rendering each with the provided function and
+/// comparing the output with its snapshot. Returns an error if any test case fails.
+pub fn run_cases<F>(dir: &Path, update: bool, render: F) -> Result<()>
+where
+    F: Fn(&Case) -> Result<String>,
+{
+    let cases = list_cases(dir)?;
+    if cases.is_empty() {
+        bail!(format!("No test cases found in {}", dir.display()))
+    }
+
+    let mut failed = 0;
+    for case in &cases {
+        let outcome = render(case).and_then(|rendered| check_snapshot(case, &rendered, update));
+        match outcome {
+            Ok(Outcome::Passed) => info!("{} ... ok", case.name),
+            Ok(Outcome::Updated) => info!("{} ... updated", case.name),
+            Ok(Outcome::Missing) => {
+                failed += 1;
+                error!(
+                    "{} ... missing snapshot, run with --update to create it",
+                    case.name
+                )
+            }
+            Ok(Outcome::Failed(line)) => {
+                failed += 1;
+                error!(
+                    "{} ... output differs from {} at line {}",
+                    case.name,
+                    case.snapshot_path().display(),
+                    line
+                )
+            }
+            Err(e) => {
+                failed += 1;
+                error!("{} ... error: {}", case.name, e)
+            }
+        }
+    }
+
+    crate::logging::summary(&format!(
+        "{} test cases, {} passed, {} failed",
+        cases.len(),
+        cases.len() - failed,
+        failed
+    ));
+    if failed > 0 {
+        bail!(ErrorKind::ValidationFailure(format!(
+            "{} of {} test cases failed",
+            failed,
+            cases.len()
+        )))
+    }
+    Ok(())
+}
+
+/// Return the (1-based) number of the first line that differs between two strings.
+fn first_difference(expected: &str, actual: &str) -> usize {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    let mut line = 1;
+    loop {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(e), Some(a)) if e == a => line += 1,
+            _ => return line,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::snapshots::{check_snapshot, list_cases, run_cases, Outcome, SNAPSHOT_FILE};
+    use std::fs;
+
+    #[test]
+    fn snapshots() {
+        let dir = std::env::temp_dir().join(format!("kvasir-snapshots-{}", std::process::id()));
+        fs::create_dir_all(dir.join("b/sources")).unwrap();
+        fs::create_dir_all(dir.join("a/sources")).unwrap();
+        fs::create_dir_all(dir.join("not-a-case")).unwrap();
+
+        let cases = list_cases(&dir).unwrap();
+        assert_eq!(
+            cases.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(),
+            ["a", "b"]
+        );
+
+        let case = &cases[0];
+        assert_eq!(
+            check_snapshot(case, "one\ntwo\n", false).unwrap(),
+            Outcome::Missing
+        );
+        assert_eq!(
+            check_snapshot(case, "one\ntwo\n", true).unwrap(),
+            Outcome::Updated
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("a").join(SNAPSHOT_FILE)).unwrap(),
+            "one\ntwo\n"
+        );
+        assert_eq!(
+            check_snapshot(case, "one\ntwo\n", false).unwrap(),
+            Outcome::Passed
+        );
+        assert_eq!(
+            check_snapshot(case, "one\nthree\n", false).unwrap(),
+            Outcome::Failed(2)
+        );
+
+        assert!(run_cases(&dir, false, |_| Ok("one\ntwo\n".to_string())).is_err());
+        assert!(run_cases(&dir, true, |_| Ok("one\ntwo\n".to_string())).is_ok());
+        assert!(run_cases(&dir, false, |_| Ok("one\ntwo\n".to_string())).is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
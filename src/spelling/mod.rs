@@ -0,0 +1,326 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Dictionary-based spell-checking of the text of generated Markdown and HTML pages.
+//!
+//! Code, link targets, HTML tags and tokens which look like identifiers, paths or URLs are
+//! not checked, nor are words containing digits, capitals after the first letter or
+//! underscores. Misspelt words are traced back to the parsed values which contain them, so
+//! that a misspelling in a source file can be fixed at its source.
+
+use crate::check::{prose_lines, Markup};
+use crate::config::{Severity, SpellingConfig};
+use crate::errors::*;
+use crate::output::OutputFile;
+use crate::paths;
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+/// The known words, lower-cased.
+#[derive(Debug, Default)]
+pub struct Dictionary {
+    words: HashSet<String>,
+}
+
+/// A misspelt word on a generated page.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Misspelling {
+    /// The path of the page, relative to the output directory.
+    pub page: String,
+    pub line: usize,
+    pub word: String,
+    /// The parsed values containing the word, as the path of the source file and the
+    /// JSONPath of the value, e.g. `k8s/web.yaml $.metadata.annotations.description`. This
+    /// is empty if the word comes from a template.
+    pub values: Vec<String>,
+}
+
+impl Dictionary {
+    /// Load the configured dictionaries and project words.
+    pub fn load(config: &SpellingConfig) -> Result<Dictionary> {
+        if config.dictionaries.is_empty() && config.words.is_empty() {
+            bail!("No dictionaries or words are configured in the [spelling] section")
+        }
+        let mut dictionary = Dictionary::default();
+        for path in &config.dictionaries {
+            debug!("Reading dictionary {}", path.display());
+            let contents = fs::read_to_string(path)
+                .chain_err(|| format!("Could not read dictionary {}", path.display()))?;
+            dictionary.add(&contents);
+        }
+        for word in &config.words {
+            dictionary.words.insert(word.to_lowercase());
+        }
+        Ok(dictionary)
+    }
+
+    /// Add the words of a word list, one per line. Hunspell affix flags following a `/`
+    /// and a leading word count are ignored.
+    fn add(&mut self, list: &str) {
+        for line in list.lines() {
+            let word = line.split('/').next().unwrap_or_default().trim();
+            if !word.is_empty() && !word.chars().all(|c| c.is_ascii_digit()) {
+                self.words.insert(word.to_lowercase());
+            }
+        }
+    }
+
+    /// Return whether a word, or the word without a possessive `'s`, is known.
+    fn contains(&self, word: &str) -> bool {
+        let word = word.to_lowercase().replace('’', "'");
+        self.words.contains(&word)
+            || word
+                .strip_suffix("'s")
+                .is_some_and(|w| self.words.contains(w))
+    }
+}
+
+/// Return the lines of a page with code, HTML tags and Markdown link targets removed.
+fn text_lines(content: &str, markup: Markup) -> Vec<(usize, String)> {
+    let mut code = false;
+    let mut lines = vec![];
+    for (number, line) in prose_lines(content, markup) {
+        let chars: Vec<char> = line.chars().collect();
+        let mut text = String::new();
+        let mut i = 0;
+        while i < chars.len() {
+            if chars[i] == '<' {
+                let end = chars[i..]
+                    .iter()
+                    .position(|c| *c == '>')
+                    .map_or(chars.len(), |e| i + e);
+                let tag: String = chars[i + 1..end].iter().collect();
+                let closing = tag.starts_with('/');
+                let name: String = tag
+                    .trim_start_matches('/')
+                    .chars()
+                    .take_while(|c| c.is_ascii_alphanumeric())
+                    .collect::<String>()
+                    .to_lowercase();
+                if ["code", "pre", "script", "style"].contains(&name.as_str()) {
+                    code = !closing;
+                }
+                text.push(' ');
+                i = end + 1;
+            } else if chars[i] == ']' && chars.get(i + 1) == Some(&'(') {
+                let end = chars[i..]
+                    .iter()
+                    .position(|c| *c == ')')
+                    .map_or(chars.len(), |e| i + e);
+                text.push(' ');
+                i = end + 1;
+            } else {
+                if !code {
+                    text.push(chars[i]);
+                }
+                i += 1;
+            }
+        }
+        lines.push((number, text));
+    }
+    lines
+}
+
+/// Return the words of some text which are checked.
+fn words(text: &str) -> Vec<&str> {
+    text.split_whitespace()
+        .filter(|token| {
+            let trimmed = token.trim_end_matches(|c: char| c.is_ascii_punctuation());
+            !trimmed.contains(['/', '\\', '@', '=', '_', '.', '&', '$', '{', '}'])
+        })
+        .flat_map(|token| token.split(|c: char| !c.is_alphabetic() && !"'’".contains(c)))
+        .map(|word| word.trim_matches(|c| "'’".contains(c)))
+        .filter(|word| {
+            word.chars().count() > 1
+                && !word.chars().skip(1).any(|c| c.is_uppercase())
+                && !word.contains(char::is_numeric)
+        })
+        .collect()
+}
+
+/// Return the paths of the string values within a parsed value containing a word.
+fn find_values(value: &Value, word: &str, path: &str, found: &mut Vec<String>) {
+    match value {
+        Value::String(s) if words(s).contains(&word) => found.push(path.to_string()),
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                find_values(item, word, &format!("{}[{}]", path, i), found);
+            }
+        }
+        Value::Object(map) => {
+            for (key, item) in map {
+                find_values(item, word, &format!("{}.{}", path, key), found);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Spell-check generated pages with paths under the output directory, returning each
+/// misspelt word with the parsed values, from the template context's `files`, which
+/// contain it. Values are looked for in the sources in the `source` attribute of a page, if
+/// any, and otherwise in all sources.
+pub fn check(
+    pages: &[OutputFile],
+    output_dir: &Path,
+    files: &[Value],
+    dictionary: &Dictionary,
+) -> Vec<Misspelling> {
+    let mut misspellings = vec![];
+    for page in pages {
+        let Some(markup) = Markup::of(&page.path) else {
+            continue;
+        };
+        let sources: Vec<&str> = page
+            .attributes
+            .get("source")
+            .map(|s| s.split(',').map(|s| s.trim()).collect())
+            .unwrap_or_default();
+        let relative = paths::portable(
+            &page
+                .path
+                .strip_prefix(output_dir)
+                .unwrap_or(&page.path)
+                .to_string_lossy(),
+        );
+        for (line, text) in text_lines(&page.content, markup) {
+            let mut seen = HashSet::new();
+            for word in words(&text) {
+                if dictionary.contains(word) || !seen.insert(word) {
+                    continue;
+                }
+                let mut values = vec![];
+                for file in files {
+                    let path = file.get("path").and_then(|p| p.as_str()).unwrap_or("");
+                    if sources.is_empty() || sources.contains(&path) {
+                        let mut found = vec![];
+                        let contents = file.get("contents").unwrap_or(&Value::Null);
+                        find_values(contents, word, "$", &mut found);
+                        values.extend(found.into_iter().map(|f| format!("{} {}", path, f)));
+                    }
+                }
+                misspellings.push(Misspelling {
+                    page: relative.to_owned(),
+                    line,
+                    word: word.to_string(),
+                    values,
+                });
+            }
+        }
+    }
+    misspellings
+}
+
+/// Log each misspelling with the configured severity, with a summary, returning an error if
+/// the severity is `error` and there are any misspellings.
+pub fn report(misspellings: &[Misspelling], severity: Severity) -> Result<()> {
+    for m in misspellings {
+        let origin = match m.values.is_empty() {
+            true => "in template text".to_string(),
+            false => format!("in {}", m.values.join(", ")),
+        };
+        match severity {
+            Severity::Error => error!(
+                "{}:{}: Unknown word '{}' ({})",
+                m.page, m.line, m.word, origin
+            ),
+            Severity::Warning => warn!(
+                "{}:{}: Unknown word '{}' ({})",
+                m.page, m.line, m.word, origin
+            ),
+            Severity::Info => info!(
+                "{}:{}: Unknown word '{}' ({})",
+                m.page, m.line, m.word, origin
+            ),
+        }
+    }
+    crate::logging::summary(&format!("{} unknown words", misspellings.len()));
+    if severity == Severity::Error && !misspellings.is_empty() {
+        bail!(ErrorKind::ValidationFailure(format!(
+            "{} unknown words",
+            misspellings.len()
+        )))
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::check::Markup;
+    use crate::output::OutputFile;
+    use crate::spelling::{check, text_lines, words, Dictionary};
+    use serde_json::json;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn text() {
+        assert_eq!(
+            text_lines(
+                "See [the docs](https://exmaple.com/pg) for `kubectl`.\n```\nbadd code\n```\n<p>Ok <code>fooo</code> done</p>",
+                Markup::Markdown
+            ),
+            [
+                (1, "See [the docs  for .".to_string()),
+                (5, " Ok    done ".to_string())
+            ]
+        );
+        assert_eq!(
+            words("The service's port: 8080, see web.yaml and http://x.io. HTTP isn't myService"),
+            ["The", "service's", "port", "see", "and", "isn't"]
+        );
+    }
+
+    #[test]
+    fn misspellings() {
+        let mut dictionary = Dictionary::default();
+        dictionary.add("3\nthe/S\nservice/MS\nhandles\nrequests\nfor\n");
+        dictionary.words.insert("kvasir".to_string());
+        let pages = vec![OutputFile {
+            path: PathBuf::from("/docs/web.md"),
+            content: "# The Servce\nThe service handles reqests for Kvasir.\nThe Servce's"
+                .to_string(),
+            attributes: [("source".to_string(), "k8s/web.yaml".to_string())].into(),
+            options: Default::default(),
+        }];
+        let files = vec![
+            json!({"path": "k8s/web.yaml", "contents": {"metadata": {"labels": ["Servce"]}}}),
+            json!({"path": "k8s/db.yaml", "contents": {"name": "Servce"}}),
+        ];
+
+        let found = check(&pages, Path::new("/docs"), &files, &dictionary);
+        assert_eq!(
+            found
+                .iter()
+                .map(|m| (m.line, m.word.as_str(), m.values.to_owned()))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    1,
+                    "Servce",
+                    vec!["k8s/web.yaml $.metadata.labels[0]".to_string()]
+                ),
+                (2, "reqests", vec![]),
+                (3, "Servce's", vec![])
+            ]
+        );
+        assert_eq!(found[0].page, "web.md");
+    }
+}
@@ -0,0 +1,203 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Summary statistics of parsed files, written by `kvasir stats` and available to templates
+//! as `stats` with `--stats`.
+//!
+//! * `files` and `failures`: the number of parsed documents and of parsing failures.
+//! * `byParser`, `byDirectory` and `byTag`: the number of parsed documents for each parser,
+//!   directory and tag.
+//! * `keys`: the number of parsed documents containing each key, by dotted path, with `[]`
+//!   standing for any element of an array (e.g. `spec.containers[].image`).
+//! * `distinct`: for each selected JSONPath expression, the distinct values it selects from
+//!   the contents of parsed documents, each with a `value` and `count`, most frequent first.
+
+use crate::errors::*;
+use itertools::Itertools;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// Summary statistics of parsed files.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Stats {
+    pub files: usize,
+    pub failures: usize,
+    pub by_parser: BTreeMap<String, usize>,
+    pub by_directory: BTreeMap<String, usize>,
+    pub by_tag: BTreeMap<String, usize>,
+    pub keys: BTreeMap<String, usize>,
+    pub distinct: BTreeMap<String, Vec<DistinctValue>>,
+}
+
+/// A value selected by a JSONPath expression, with the number of times it was selected.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DistinctValue {
+    pub value: Value,
+    pub count: usize,
+}
+
+impl Stats {
+    /// Calculate statistics from parsed files, as they appear in the template context, and
+    /// the number of parsing failures, selecting distinct values with each of the `distinct`
+    /// JSONPath expressions.
+    pub fn new(files: &[Value], failures: usize, distinct: &[String]) -> Result<Stats> {
+        let mut stats = Stats {
+            files: files.len(),
+            failures,
+            ..Default::default()
+        };
+        let mut selected: BTreeMap<&str, Vec<Value>> = BTreeMap::new();
+
+        for file in files {
+            let field = |name: &str| file.get(name).and_then(|v| v.as_str()).unwrap_or_default();
+            *stats
+                .by_parser
+                .entry(field("parser").to_string())
+                .or_default() += 1;
+            let directory = Path::new(field("path"))
+                .parent()
+                .map(|p| p.display().to_string())
+                .unwrap_or_default();
+            *stats.by_directory.entry(directory).or_default() += 1;
+            for tag in file
+                .get("tags")
+                .and_then(|t| t.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|t| t.as_str())
+            {
+                *stats.by_tag.entry(tag.to_string()).or_default() += 1;
+            }
+
+            let contents = file.get("contents").unwrap_or(&Value::Null);
+            let mut keys = BTreeSet::new();
+            collect_keys(contents, "", &mut keys);
+            for key in keys {
+                *stats.keys.entry(key).or_default() += 1;
+            }
+
+            for path in distinct {
+                selected.entry(path).or_default().extend(
+                    jsonpath_lib::select(contents, path)
+                        .map_err(|e| format!("Invalid JSONPath expression {}: {}", path, e))?
+                        .into_iter()
+                        .cloned(),
+                );
+            }
+        }
+
+        stats.distinct = distinct
+            .iter()
+            .map(|path| {
+                let values = selected.remove(path.as_str()).unwrap_or_default();
+                (path.to_owned(), distinct_values(values))
+            })
+            .collect();
+        Ok(stats)
+    }
+}
+
+/// Add the dotted path of every key within a value to a set of keys.
+pub fn collect_keys(value: &Value, prefix: &str, keys: &mut BTreeSet<String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map {
+                let path = if prefix.is_empty() {
+                    key.to_owned()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                collect_keys(child, &path, keys);
+                keys.insert(path);
+            }
+        }
+        Value::Array(values) => {
+            let path = format!("{}[]", prefix);
+            values.iter().for_each(|v| collect_keys(v, &path, keys));
+        }
+        _ => {}
+    }
+}
+
+/// Count the occurrences of each distinct value, most frequent first and then in the order
+/// in which they were first found.
+pub fn distinct_values(values: Vec<Value>) -> Vec<DistinctValue> {
+    let mut counts: Vec<DistinctValue> = vec![];
+    for value in values {
+        match counts.iter_mut().find(|d| d.value == value) {
+            Some(existing) => existing.count += 1,
+            None => counts.push(DistinctValue { value, count: 1 }),
+        }
+    }
+    counts
+        .into_iter()
+        .sorted_by(|a, b| b.count.cmp(&a.count))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::stats::Stats;
+    use serde_json::json;
+
+    #[test]
+    fn stats() {
+        let files = [
+            json!({
+                "path": "k8s/web.yaml",
+                "parser": "yaml",
+                "contents": {"spec": {"containers": [{"image": "nginx"}, {"image": "envoy"}]}},
+                "tags": ["infra"]
+            }),
+            json!({
+                "path": "k8s/api.yaml",
+                "parser": "yaml",
+                "contents": {"spec": {"containers": [{"image": "nginx"}]}}
+            }),
+            json!({"path": "app.json", "parser": "json", "contents": {"name": "app"}}),
+        ];
+
+        let stats = Stats::new(&files, 1, &["$.spec.containers[*].image".to_string()]).unwrap();
+        assert_eq!(
+            serde_json::to_value(stats).unwrap(),
+            json!({
+                "files": 3,
+                "failures": 1,
+                "byParser": {"json": 1, "yaml": 2},
+                "byDirectory": {"": 1, "k8s": 2},
+                "byTag": {"infra": 1},
+                "keys": {
+                    "name": 1,
+                    "spec": 2,
+                    "spec.containers": 2,
+                    "spec.containers[].image": 2
+                },
+                "distinct": {
+                    "$.spec.containers[*].image": [
+                        {"value": "nginx", "count": 2},
+                        {"value": "envoy", "count": 1}
+                    ]
+                }
+            })
+        );
+
+        assert!(Stats::new(&files, 0, &["$.[".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,137 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! String ordering for the sorting filters: natural ordering, in which numbers within
+//! strings are compared by value (`file2` before `file10`), and locale-aware collation.
+
+use icu_collator::{Collator, CollatorOptions, Numeric};
+use icu_locid::Locale;
+use std::cmp::Ordering;
+
+/// Compare strings in natural order: runs of digits are compared by their numeric value
+/// and other text case-insensitively, with ties broken by the strings themselves.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut left = chunks(a);
+    let mut right = chunks(b);
+    loop {
+        match (left.next(), right.next()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(l), Some(r)) => {
+                let ordering = match (is_number(l), is_number(r)) {
+                    (true, true) => {
+                        let (l_value, r_value) =
+                            (l.trim_start_matches('0'), r.trim_start_matches('0'));
+                        l_value
+                            .len()
+                            .cmp(&r_value.len())
+                            .then_with(|| l_value.cmp(r_value))
+                            .then_with(|| l.len().cmp(&r.len()))
+                    }
+                    _ => l.to_lowercase().cmp(&r.to_lowercase()),
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+        }
+    }
+}
+
+/// Split a string into alternating runs of ASCII digits and other characters.
+fn chunks(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        let first = rest.chars().next()?;
+        let end = rest
+            .find(|c: char| c.is_ascii_digit() != first.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (chunk, remainder) = rest.split_at(end);
+        rest = remainder;
+        Some(chunk)
+    })
+}
+
+/// Return whether a chunk is a run of digits.
+fn is_number(chunk: &str) -> bool {
+    chunk.starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// Return a collator for a locale identifier, such as `de` or `sv-SE`, which compares
+/// numbers within strings by value if `numeric` is set.
+pub fn collator(locale: &str, numeric: bool) -> Result<Collator, String> {
+    let locale: Locale = locale
+        .parse()
+        .map_err(|e| format!("Invalid locale {}: {}", locale, e))?;
+    let mut options = CollatorOptions::new();
+    if numeric {
+        options.numeric = Some(Numeric::On);
+    }
+    Collator::try_new(&(&locale).into(), options)
+        .map_err(|e| format!("No collation for locale {}: {}", locale, e))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::templates::collation::{collator, natural_cmp};
+
+    #[test]
+    fn natural() {
+        let mut files = vec![
+            "file10.md",
+            "File2.md",
+            "file2.md",
+            "file1.md",
+            "file02.md",
+            "a",
+        ];
+        files.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            files,
+            [
+                "a",
+                "file1.md",
+                "File2.md",
+                "file2.md",
+                "file02.md",
+                "file10.md"
+            ]
+        );
+        let mut versions = vec!["v1.10.0", "v1.9.2", "v1.9.10"];
+        versions.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(versions, ["v1.9.2", "v1.9.10", "v1.10.0"]);
+    }
+
+    #[test]
+    fn locales() {
+        let mut words = vec!["zebra", "äpfel", "apple", "Zürich"];
+        let german = collator("de", false).unwrap();
+        words.sort_by(|a, b| german.compare(a, b));
+        assert_eq!(words, ["äpfel", "apple", "zebra", "Zürich"]);
+
+        let swedish = collator("sv", false).unwrap();
+        words.sort_by(|a, b| swedish.compare(a, b));
+        assert_eq!(words, ["apple", "zebra", "Zürich", "äpfel"]);
+
+        let numeric = collator("en", true).unwrap();
+        let mut files = vec!["file10", "file9"];
+        files.sort_by(|a, b| numeric.compare(a, b));
+        assert_eq!(files, ["file9", "file10"]);
+        assert!(collator("not a locale!", false).is_err());
+    }
+}
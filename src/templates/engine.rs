@@ -0,0 +1,434 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Template engines used to render the template context.
+
+use super::limits::RenderLimits;
+use super::profile::{self, Kind};
+use super::{dependencies, filters, functions};
+use crate::context::{FileContext, TemplateContext};
+use crate::errors::*;
+use crate::manifest::{file_sha256, sha256};
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, RenderContext, RenderError, RenderErrorReason,
+    ScopedJson,
+};
+use rayon::prelude::*;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The name given to a template read from `stdin`.
+pub const STDIN_TEMPLATE: &str = "root";
+
+/// A supported template engine.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Engine {
+    Tera,
+    Handlebars,
+}
+
+impl FromStr for Engine {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "tera" => Ok(Engine::Tera),
+            "handlebars" => Ok(Engine::Handlebars),
+            _ => Err(format!(
+                "Unknown template engine '{}', expected tera or handlebars",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for Engine {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Engine::Tera => write!(f, "tera"),
+            Engine::Handlebars => write!(f, "handlebars"),
+        }
+    }
+}
+
+/// A set of templates loaded by a template engine, with the custom filters, functions and
+/// helpers registered.
+pub enum Templates {
+    Tera(Box<tera::Tera>),
+    /// Handlebars templates along with their sources, which are used to find partials.
+    Handlebars(Box<Handlebars<'static>>, BTreeMap<String, String>),
+}
+
+impl Templates {
+    /// Load the templates matching a glob expression, or a single template from `stdin` if
+    /// the expression is `-`, with custom functions configured with the provided options.
+    pub fn load(
+        engine: Engine,
+        templates: &str,
+        options: &functions::FunctionOptions,
+    ) -> Result<Templates> {
+        let templates = &crate::paths::strip_verbatim(templates);
+        match engine {
+            Engine::Tera => {
+                let mut tera = create_tera_instance(templates)?;
+                filters::register_filters(&mut tera);
+                functions::register_functions(&mut tera, options);
+                Ok(Templates::Tera(Box::new(tera)))
+            }
+            Engine::Handlebars => {
+                Templates::from_sources(engine, read_templates(templates)?, options)
+            }
+        }
+    }
+
+    /// Load templates from their sources, keyed by name, with custom functions configured
+    /// with the provided options.
+    pub fn from_sources(
+        engine: Engine,
+        sources: BTreeMap<String, String>,
+        options: &functions::FunctionOptions,
+    ) -> Result<Templates> {
+        match engine {
+            Engine::Tera => {
+                let mut tera = tera::Tera::default();
+                tera.add_raw_templates(&sources)?;
+                filters::register_filters(&mut tera);
+                functions::register_functions(&mut tera, options);
+                Ok(Templates::Tera(Box::new(tera)))
+            }
+            Engine::Handlebars => {
+                let mut handlebars = Handlebars::new();
+                handlebars.register_escape_fn(handlebars::no_escape);
+                for (name, source) in &sources {
+                    handlebars
+                        .register_template_string(name, source)
+                        .map_err(|e| {
+                            ErrorKind::TemplateFailure(format!(
+                                "Could not parse template {}: {}",
+                                name, e
+                            ))
+                        })?;
+                }
+                register_helpers(&mut handlebars, options);
+                Ok(Templates::Handlebars(Box::new(handlebars), sources))
+            }
+        }
+    }
+
+    /// Return the names of the templates referenced by each template.
+    pub fn dependencies(&self) -> BTreeMap<String, BTreeSet<String>> {
+        match self {
+            Templates::Tera(tera) => dependencies::template_dependencies(tera),
+            Templates::Handlebars(_, sources) => sources
+                .iter()
+                .map(|(name, source)| (name.to_owned(), partial_references(source)))
+                .collect(),
+        }
+    }
+
+    /// Return the SHA-256 hash of each template read from a file, keyed by name.
+    pub fn checksums(&self) -> Result<BTreeMap<String, String>> {
+        match self {
+            Templates::Tera(tera) => tera
+                .templates
+                .iter()
+                .filter_map(|(name, t)| t.path.as_ref().map(|p| (name, p)))
+                .map(|(name, path)| Ok((name.to_owned(), file_sha256(Path::new(path))?)))
+                .collect(),
+            Templates::Handlebars(_, sources) => Ok(sources
+                .iter()
+                .map(|(name, source)| (name.to_owned(), sha256(source.as_bytes())))
+                .collect()),
+        }
+    }
+
+    /// Render a template with the provided context, within the given limits.
+    pub fn render(
+        &self,
+        name: &str,
+        context: TemplateContext,
+        limits: &RenderLimits,
+    ) -> Result<String> {
+        match self {
+            Templates::Tera(tera) => {
+                let context = context.into_tera()?;
+                profile::time(Kind::Template, name, || {
+                    limits.render(name, |w| Ok(tera.render_to(name, &context, w)?))
+                })
+            }
+            Templates::Handlebars(handlebars, _) => profile::time(Kind::Template, name, || {
+                limits.render(name, |w| {
+                    handlebars
+                        .render_to_write(name, &context, w)
+                        .map_err(|e| format!("Could not render template: {}", e).into())
+                })
+            }),
+        }
+    }
+
+    /// Render a template once for each file in the context, concurrently, with the file
+    /// available to the template as `file`.
+    ///
+    /// Rather than copying the context for each file, each rendering thread has its own
+    /// copy of a Tera context whilst Handlebars templates share the context.
+    pub fn render_each_file(
+        &self,
+        name: &str,
+        context: TemplateContext,
+        limits: &RenderLimits,
+    ) -> Vec<Result<String>> {
+        match self {
+            Templates::Tera(tera) => {
+                let base = match context.into_tera() {
+                    Ok(base) => base,
+                    Err(e) => return vec![Err(e.into())],
+                };
+                let empty = vec![];
+                let files = base
+                    .get("files")
+                    .and_then(|f| f.as_array())
+                    .unwrap_or(&empty);
+                files
+                    .par_iter()
+                    .map_init(
+                        || base.clone(),
+                        |context, file| {
+                            context.insert("file", file);
+                            profile::time(Kind::Template, name, || {
+                                limits.render(name, |w| Ok(tera.render_to(name, context, w)?))
+                            })
+                        },
+                    )
+                    .collect()
+            }
+            Templates::Handlebars(handlebars, _) => context
+                .files
+                .par_iter()
+                .map(|file| {
+                    profile::time(Kind::Template, name, || {
+                        limits.render(name, |w| {
+                            handlebars
+                                .render_to_write(
+                                    name,
+                                    &FileContext {
+                                        context: &context,
+                                        file,
+                                    },
+                                    w,
+                                )
+                                .map_err(|e| format!("Could not render template: {}", e).into())
+                        })
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Return a new Tera instance with either a directory of templates or by reading a
+/// single template from `stdin`.
+fn create_tera_instance(templates: &str) -> Result<tera::Tera> {
+    use std::io::Read;
+    if templates.eq("-") {
+        let mut buf = String::new();
+        std::io::stdin().read_to_string(&mut buf)?;
+        let mut tera = tera::Tera::default();
+        tera.add_raw_template(STDIN_TEMPLATE, buf.as_str())?;
+        Ok(tera)
+    } else {
+        Ok(tera::Tera::new(templates)?)
+    }
+}
+
+/// Return the directory preceding the first wildcard in a glob expression for templates,
+/// relative to which the templates are named.
+pub fn template_dir(templates: &str) -> &str {
+    templates
+        .find(['*', '?', '['])
+        .map_or(templates, |i| &templates[..i])
+}
+
+/// Read the templates matching a glob expression, or a single template from `stdin` if the
+/// expression is `-`, keyed by name.
+///
+/// As with Tera, templates are named by their path relative to the directory preceding
+/// the first wildcard in the glob expression.
+pub fn read_templates(templates: &str) -> Result<BTreeMap<String, String>> {
+    use std::io::Read;
+    if templates.eq("-") {
+        let mut buf = String::new();
+        std::io::stdin().read_to_string(&mut buf)?;
+        return Ok(BTreeMap::from([(STDIN_TEMPLATE.to_string(), buf)]));
+    }
+
+    let parent_dir = template_dir(templates);
+    let mut sources = BTreeMap::new();
+    for path in glob::glob(templates).map_err(|e| e.to_string())? {
+        let path = path.map_err(|e| e.to_string())?;
+        if path.is_file() {
+            let display = path.display().to_string().replace('\\', "/");
+            let name = display
+                .strip_prefix(parent_dir)
+                .unwrap_or(&display)
+                .to_string();
+            sources.insert(name, fs::read_to_string(&path)?);
+        }
+    }
+    Ok(sources)
+}
+
+/// Return the names of the partials referenced by a Handlebars template, through
+/// `{{> name}}` or `{{#> name}}`.
+fn partial_references(source: &str) -> BTreeSet<String> {
+    source
+        .split("{{")
+        .skip(1)
+        .filter_map(|tag| {
+            tag.trim_start_matches('~')
+                .trim_start_matches('#')
+                .strip_prefix('>')
+        })
+        .filter_map(|tag| {
+            tag.split(|c: char| c.is_whitespace() || c == '}' || c == '~')
+                .find(|t| !t.is_empty())
+                .map(|t| {
+                    t.trim_matches(|c| c == '\'' || c == '[' || c == ']')
+                        .to_string()
+                })
+        })
+        .collect()
+}
+
+/// A Handlebars helper that calls a Tera filter or function, so that templates have the
+/// same filters and functions available with either engine.
+///
+/// Filters are called with the first helper parameter as their value and hash
+/// arguments as their arguments, e.g. `{{filename file.path}}` or
+/// `{{#each (parsedby files parser="json")}}`.
+enum TeraHelper {
+    Filter(filters::Filter),
+    Function(Box<dyn tera::Function>),
+}
+
+impl HelperDef for TeraHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> std::result::Result<ScopedJson<'rc>, RenderError> {
+        let args: HashMap<String, Value> = h
+            .hash()
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.value().to_owned()))
+            .collect();
+        let result = match self {
+            TeraHelper::Filter(filter) => profile::time(Kind::Filter, h.name(), || {
+                filter(h.param(0).map_or(&Value::Null, |p| p.value()), &args)
+            }),
+            TeraHelper::Function(function) => {
+                profile::time(Kind::Function, h.name(), || function.call(&args))
+            }
+        };
+        result
+            .map(ScopedJson::Derived)
+            .map_err(|e| RenderErrorReason::Other(format!("{}: {}", h.name(), e)).into())
+    }
+}
+
+/// Register the custom filters and functions as Handlebars helpers.
+fn register_helpers(handlebars: &mut Handlebars, options: &functions::FunctionOptions) {
+    for (name, filter) in filters::filters() {
+        handlebars.register_helper(name, Box::new(TeraHelper::Filter(filter)));
+    }
+    for (name, function) in functions::functions(options) {
+        handlebars.register_helper(name, Box::new(TeraHelper::Function(function)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::config::Config;
+    use crate::context::TemplateContext;
+    use crate::parsers::ParseSuccess;
+    use crate::report::OutputSchema;
+    use crate::templates::engine::{partial_references, Engine, Templates};
+    use crate::templates::limits::RenderLimits;
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    #[test]
+    fn engine() {
+        assert_eq!("tera".parse::<Engine>().unwrap(), Engine::Tera);
+        assert_eq!("handlebars".parse::<Engine>().unwrap(), Engine::Handlebars);
+        assert!("mustache".parse::<Engine>().is_err());
+    }
+
+    #[test]
+    fn partials() {
+        assert_eq!(
+            partial_references("{{> header}} {{~#> layout title=\"x\"}}{{/layout}} {{name}}")
+                .into_iter()
+                .collect::<Vec<_>>(),
+            ["header", "layout"]
+        );
+    }
+
+    #[test]
+    fn handlebars() {
+        let templates = Templates::load(
+            Engine::Handlebars,
+            "test/templates/handlebars/*",
+            &Default::default(),
+        )
+        .unwrap();
+        let dependencies = templates.dependencies();
+        assert_eq!(
+            crate::templates::dependencies::root_candidates(&dependencies),
+            ["page.hbs"]
+        );
+
+        let config = Config::default();
+        let successes = vec![ParseSuccess {
+            path: PathBuf::from("dir/a.json"),
+            parser: "json".to_string(),
+            index: None,
+            name: None,
+            contents: json!({"fruit": "Apple & Pear"}),
+            descriptions: Default::default(),
+            metadata: Default::default(),
+            warnings: vec![],
+            tags: vec![],
+            owners: vec![],
+            duplicates: vec![],
+            external_refs: vec![],
+        }];
+        let context = TemplateContext::new(successes, &[], &config, OutputSchema::LATEST);
+
+        assert_eq!(
+            templates
+                .render("page.hbs", context, &RenderLimits::default())
+                .unwrap(),
+            "# Files\n\n* a.json (json): Apple & Pear\n"
+        );
+    }
+}
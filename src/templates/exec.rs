@@ -0,0 +1,132 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! The `exec` template function, which runs an allowed command and returns its output.
+//!
+//! Commands can only be run with `--allow-exec`, and only if they are named in the
+//! `allow` list of the `[exec]` section of the configuration file:
+//!
+//! ```toml
+//! [exec]
+//! allow = ["git", "terraform"]
+//! ```
+
+use log::debug;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::process::Command;
+
+/// Options controlling the commands that templates may run.
+#[derive(Debug, Clone, Default)]
+pub struct ExecOptions {
+    /// Whether templates may run commands.
+    pub allow_exec: bool,
+    /// The commands that templates may run, as given to `cmd`.
+    pub allowed: Vec<String>,
+}
+
+/// Run the command provided in the `cmd` argument with the optional `args` array of
+/// arguments, returning its standard output without trailing whitespace.
+///
+/// The command is not run through a shell. An error is returned if the command is not
+/// allowed, could not be started or exits unsuccessfully.
+pub fn exec(args: &HashMap<String, Value>, options: &ExecOptions) -> tera::Result<Value> {
+    let cmd = args
+        .get("cmd")
+        .ok_or("No cmd parameter.")?
+        .as_str()
+        .ok_or("Empty or non-string cmd parameter.")?;
+    if !options.allow_exec {
+        return Err(format!(
+            "Running commands is disabled, could not run {}. Allow it with --allow-exec.",
+            cmd
+        )
+        .into());
+    }
+    if !options.allowed.iter().any(|a| a == cmd) {
+        return Err(format!(
+            "Command {} is not in the exec allow list of the configuration file.",
+            cmd
+        )
+        .into());
+    }
+    let arguments: Vec<String> = match args.get("args") {
+        None => vec![],
+        Some(Value::Array(values)) => values
+            .iter()
+            .map(|v| match v {
+                Value::String(s) => s.to_owned(),
+                other => other.to_string(),
+            })
+            .collect(),
+        Some(_) => return Err("args must be an array.".into()),
+    };
+
+    debug!("Running {} {}", cmd, arguments.join(" "));
+    let output = Command::new(cmd)
+        .args(&arguments)
+        .output()
+        .map_err(|e| format!("Could not run {}: {}", cmd, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Command {} failed ({}): {}",
+            cmd,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(Value::String(
+        String::from_utf8_lossy(&output.stdout)
+            .trim_end()
+            .to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::templates::exec::{exec, ExecOptions};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    #[cfg(unix)]
+    #[test]
+    fn run() {
+        let options = ExecOptions {
+            allow_exec: true,
+            allowed: vec!["echo".to_string(), "false".to_string()],
+        };
+        let args = |cmd: &str, args: serde_json::Value| {
+            HashMap::from([("cmd".to_string(), json!(cmd)), ("args".to_string(), args)])
+        };
+
+        assert_eq!(
+            exec(&args("echo", json!(["version", 1])), &options).unwrap(),
+            json!("version 1")
+        );
+        assert!(exec(&args("false", json!([])), &options).is_err());
+        assert!(exec(&args("ls", json!([])), &options).is_err());
+        assert!(exec(
+            &args("echo", json!([])),
+            &ExecOptions {
+                allow_exec: false,
+                ..options
+            }
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,239 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! The `http_get` template function, which fetches data over HTTP with on-disk caching.
+//!
+//! Responses are cached in a directory as JSON files named after the SHA-256 hash of the
+//! URL and request headers, so that repeated runs within the cache lifetime of a response
+//! make no requests.
+
+use crate::manifest::sha256;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The default directory in which responses are cached.
+pub const DEFAULT_CACHE_DIR: &str = ".kvasir-cache";
+
+/// The default number of seconds for which a cached response is used.
+const DEFAULT_CACHE_TTL: u64 = 3600;
+
+/// The maximum time taken by a request.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Options controlling network access from templates.
+#[derive(Debug, Clone)]
+pub struct HttpOptions {
+    /// Whether templates may make network requests.
+    pub allow_network: bool,
+    /// The directory in which responses are cached.
+    pub cache_dir: PathBuf,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        HttpOptions {
+            allow_network: false,
+            cache_dir: PathBuf::from(DEFAULT_CACHE_DIR),
+        }
+    }
+}
+
+/// A cached response.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedResponse {
+    url: String,
+    /// The time at which the response was fetched, in seconds since the Unix epoch.
+    fetched: u64,
+    content_type: String,
+    body: String,
+}
+
+/// Fetch the URL provided in the `url` argument with a `GET` request, returning the response
+/// body, parsed if it is JSON, or an error if the response is not successful.
+///
+/// Request headers are provided in the optional `headers` argument, either as an object or
+/// as an array of `Name: value` strings. Responses are cached for `cache_ttl` seconds,
+/// defaulting to an hour, and a `cache_ttl` of zero always makes a request. Network access
+/// must be allowed with `--allow-network`, even for cached responses.
+pub fn http_get(args: &HashMap<String, Value>, options: &HttpOptions) -> tera::Result<Value> {
+    let url = args
+        .get("url")
+        .ok_or("No url parameter.")?
+        .as_str()
+        .ok_or("Empty or non-string url parameter.")?;
+    if !options.allow_network {
+        return Err(format!(
+            "Network access is disabled, could not fetch {}. Allow it with --allow-network.",
+            url
+        )
+        .into());
+    }
+    let headers = headers(args.get("headers"))?;
+    let ttl = match args.get("cache_ttl") {
+        Some(ttl) => ttl
+            .as_u64()
+            .ok_or("cache_ttl must be a whole number of seconds.")?,
+        None => DEFAULT_CACHE_TTL,
+    };
+
+    let key = sha256(
+        std::iter::once(url.to_string())
+            .chain(headers.iter().map(|(k, v)| format!("{}: {}", k, v)))
+            .collect::<Vec<_>>()
+            .join("\n")
+            .as_bytes(),
+    );
+    let cache_path = options.cache_dir.join(format!("{}.json", key));
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    let response = match read_cache(&cache_path) {
+        Some(cached) if ttl > 0 && now.saturating_sub(cached.fetched) < ttl => {
+            debug!("Using cached response for {}", url);
+            cached
+        }
+        _ => {
+            let response = fetch(url, &headers, now)?;
+            if ttl > 0 {
+                write_cache(&cache_path, &response)?;
+            }
+            response
+        }
+    };
+
+    if response.content_type.contains("json") {
+        serde_json::from_str(&response.body)
+            .map_err(|e| format!("Could not parse JSON from {}: {}", url, e).into())
+    } else {
+        Ok(Value::String(response.body))
+    }
+}
+
+/// Return request headers from an object or an array of `Name: value` strings, sorted by
+/// name.
+fn headers(value: Option<&Value>) -> tera::Result<BTreeMap<String, String>> {
+    match value {
+        None => Ok(BTreeMap::new()),
+        Some(Value::Object(map)) => map
+            .iter()
+            .map(|(k, v)| match v {
+                Value::String(s) => Ok((k.to_owned(), s.to_owned())),
+                other => Ok((k.to_owned(), other.to_string())),
+            })
+            .collect(),
+        Some(Value::Array(values)) => values
+            .iter()
+            .map(|v| {
+                v.as_str()
+                    .and_then(|h| h.split_once(':'))
+                    .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+                    .ok_or_else(|| format!("Invalid header {}, expected 'Name: value'.", v).into())
+            })
+            .collect(),
+        Some(_) => Err("headers must be an object or an array of 'Name: value' strings.".into()),
+    }
+}
+
+/// Make a `GET` request.
+fn fetch(url: &str, headers: &BTreeMap<String, String>, now: u64) -> tera::Result<CachedResponse> {
+    debug!("Fetching {}", url);
+    let mut request = ureq::get(url).timeout(TIMEOUT);
+    for (name, value) in headers {
+        request = request.set(name, value);
+    }
+    let response = request
+        .call()
+        .map_err(|e| format!("Could not fetch {}: {}", url, e))?;
+    Ok(CachedResponse {
+        url: url.to_string(),
+        fetched: now,
+        content_type: response.content_type().to_string(),
+        body: response
+            .into_string()
+            .map_err(|e| format!("Could not read response from {}: {}", url, e))?,
+    })
+}
+
+/// Read a cached response, if one exists and can be read.
+fn read_cache(path: &Path) -> Option<CachedResponse> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+}
+
+/// Write a response to the cache.
+fn write_cache(path: &Path, response: &CachedResponse) -> tera::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Could not create cache directory {}: {}", dir.display(), e))?;
+    }
+    fs::write(path, serde_json::to_string(response)?)
+        .map_err(|e| format!("Could not write cache file {}: {}", path.display(), e).into())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::templates::http::{http_get, HttpOptions};
+    use crate::testing::{Response, Server};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    #[test]
+    fn get() {
+        let options = HttpOptions {
+            allow_network: true,
+            cache_dir: std::env::temp_dir().join(format!("kvasir-http-{}", std::process::id())),
+        };
+        let server = Server::sequence(vec![
+            Response::ok(r#"{"version": "1.2.3"}"#).header("Content-Type", "application/json")
+        ]);
+        let args: HashMap<String, serde_json::Value> = HashMap::from([
+            ("url".to_string(), json!(format!("{}/data", server.url))),
+            ("headers".to_string(), json!(["Accept: application/json"])),
+        ]);
+
+        assert_eq!(
+            http_get(&args, &options).unwrap(),
+            json!({"version": "1.2.3"})
+        );
+        // The server has closed, so the second response must come from the cache
+        assert_eq!(
+            http_get(&args, &options).unwrap(),
+            json!({"version": "1.2.3"})
+        );
+
+        let mut uncached = args.to_owned();
+        uncached.insert("cache_ttl".to_string(), json!(0));
+        assert!(http_get(&uncached, &options).is_err());
+
+        let disabled = HttpOptions {
+            allow_network: false,
+            ..options.to_owned()
+        };
+        assert!(http_get(&args, &disabled).is_err());
+
+        std::fs::remove_dir_all(&options.cache_dir).unwrap();
+    }
+}
@@ -0,0 +1,219 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Translation of template text with the `t` template function.
+//!
+//! Messages are read from a YAML catalogue for each locale, e.g. `locales/de.yaml`, in the
+//! directory configured in the `[i18n]` section of the configuration file. Messages are
+//! looked up in the catalogue of the locale selected with `--locale`, then that of its
+//! language (`de` for `de-AT`), then that of the default locale.
+
+use crate::config::I18nConfig;
+use crate::errors::*;
+use log::debug;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// The directory from which catalogues are read if none is configured.
+pub const DEFAULT_DIR: &str = "locales";
+
+/// The catalogues of messages used for a locale, most specific first.
+#[derive(Debug, Clone, Default)]
+pub struct Translations {
+    /// The selected locale, if any.
+    pub locale: Option<String>,
+    catalogues: Vec<(String, Value)>,
+}
+
+impl Translations {
+    /// Load the catalogues for a locale, or for the default locale if none is selected.
+    ///
+    /// An error is returned if a locale is selected but neither it nor its language has a
+    /// catalogue, so that a misspelt locale does not silently produce untranslated output.
+    pub fn load(config: &I18nConfig, locale: Option<&str>) -> Result<Translations> {
+        let dir = Path::new(config.dir.as_deref().unwrap_or(DEFAULT_DIR));
+        let mut requested: Vec<String> = vec![];
+        if let Some(locale) = locale {
+            requested.push(locale.to_string());
+            if let Some((language, _)) = locale.split_once(['-', '_']) {
+                requested.push(language.to_string());
+            }
+        }
+
+        let mut catalogues: Vec<(String, Value)> = vec![];
+        for name in requested.iter().chain(config.default_locale.iter()) {
+            if catalogues.iter().any(|(n, _)| n == name) {
+                continue;
+            }
+            for extension in ["yaml", "yml"] {
+                let path = dir.join(format!("{}.{}", name, extension));
+                if path.is_file() {
+                    debug!("Reading translations from {}", path.display());
+                    let contents = fs::read_to_string(&path)
+                        .chain_err(|| format!("Could not read translations {}", path.display()))?;
+                    let catalogue: Value = serde_yaml::from_str(&contents)
+                        .chain_err(|| format!("Could not parse translations {}", path.display()))?;
+                    catalogues.push((name.to_string(), catalogue));
+                    break;
+                }
+            }
+        }
+        if let Some(locale) = locale {
+            if !catalogues.iter().any(|(n, _)| requested.contains(n)) {
+                bail!(format!(
+                    "No translations for locale {} in {}",
+                    locale,
+                    dir.display()
+                ))
+            }
+        }
+        Ok(Translations {
+            locale: locale
+                .map(|l| l.to_string())
+                .or(config.default_locale.to_owned()),
+            catalogues,
+        })
+    }
+
+    /// Return the message with a dotted key from the most specific catalogue containing it.
+    fn message(&self, key: &str) -> Option<&Value> {
+        self.catalogues.iter().find_map(|(_, catalogue)| {
+            key.split('.')
+                .try_fold(catalogue, |value, part| value.get(part))
+                .filter(|v| !v.is_null())
+        })
+    }
+}
+
+/// Return the translation of the message with the dotted key in the `key` argument.
+///
+/// Other arguments replace placeholders of the same name in braces, e.g. `{name}`. A
+/// message with `one` and `other` forms is pluralised by the `count` argument. The
+/// optional `default` argument is returned if no catalogue contains the message, and an
+/// error otherwise.
+pub fn t(args: &HashMap<String, Value>, translations: &Translations) -> tera::Result<Value> {
+    let key = args
+        .get("key")
+        .ok_or("No key parameter.")?
+        .as_str()
+        .ok_or("Empty or non-string key parameter.")?;
+    let message = match (translations.message(key), args.get("default")) {
+        (Some(message), _) => message,
+        (None, Some(default)) => default,
+        (None, None) => {
+            return Err(format!(
+                "No translation for {} in locale {}",
+                key,
+                translations.locale.as_deref().unwrap_or("(none)")
+            )
+            .into())
+        }
+    };
+    let message = match (message, args.get("count").and_then(|c| c.as_f64())) {
+        (Value::Object(forms), Some(count)) => forms
+            .get(if count == 1.0 { "one" } else { "other" })
+            .or_else(|| forms.get("other"))
+            .ok_or_else(|| format!("No plural form for {} with count {}", key, count))?,
+        (message, _) => message,
+    };
+    let mut text = match message {
+        Value::String(s) => s.to_owned(),
+        Value::Object(_) | Value::Array(_) => {
+            return Err(format!("The translation for {} is not text", key).into())
+        }
+        other => other.to_string(),
+    };
+    for (name, value) in args.iter().filter(|(n, _)| *n != "key" && *n != "default") {
+        let value = match value {
+            Value::String(s) => s.to_owned(),
+            other => other.to_string(),
+        };
+        text = text.replace(&format!("{{{}}}", name), &value);
+    }
+    Ok(Value::String(text))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::config::I18nConfig;
+    use crate::templates::i18n::{t, Translations};
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::fs;
+
+    fn args(values: serde_json::Value) -> HashMap<String, serde_json::Value> {
+        serde_json::from_value(values).unwrap()
+    }
+
+    #[test]
+    fn translate() {
+        let dir = std::env::temp_dir().join(format!("kvasir-i18n-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("en.yaml"),
+            "title: Services\nservice:\n  ports:\n    one: \"{name} has {count} port\"\n    other: \"{name} has {count} ports\"\n  owner: \"Owner: {owner}\"\n",
+        )
+        .unwrap();
+        fs::write(dir.join("de.yaml"), "title: Dienste\n").unwrap();
+        let config = I18nConfig {
+            dir: Some(dir.to_string_lossy().to_string()),
+            default_locale: Some("en".to_string()),
+        };
+
+        let german = Translations::load(&config, Some("de-AT")).unwrap();
+        assert_eq!(
+            t(&args(json!({"key": "title"})), &german).unwrap(),
+            json!("Dienste")
+        );
+        assert_eq!(
+            t(
+                &args(json!({"key": "service.ports", "name": "web", "count": 1})),
+                &german
+            )
+            .unwrap(),
+            json!("web has 1 port")
+        );
+        assert_eq!(
+            t(
+                &args(json!({"key": "service.ports", "name": "web", "count": 3})),
+                &german
+            )
+            .unwrap(),
+            json!("web has 3 ports")
+        );
+        assert!(t(&args(json!({"key": "missing"})), &german).is_err());
+        assert_eq!(
+            t(
+                &args(json!({"key": "missing", "default": "Hi {who}", "who": "all"})),
+                &german
+            )
+            .unwrap(),
+            json!("Hi all")
+        );
+
+        let default = Translations::load(&config, None).unwrap();
+        assert_eq!(
+            t(&args(json!({"key": "title"})), &default).unwrap(),
+            json!("Services")
+        );
+        assert!(Translations::load(&config, Some("fr")).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,359 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Filters compatible with commonly used Jinja2 filters, so that templates written for
+//! Python tooling can be ported with few changes.
+//!
+//! Arguments are named, as Tera has no positional filter arguments, so that Jinja's
+//! `selectattr("kind", "equalto", "Service")` becomes
+//! `selectattr(attribute="kind", test="equalto", value="Service")`.
+
+use serde_json::{json, Value};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Return the value at a dotted path of attributes within a value, as in Jinja.
+fn attribute<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(value, |v, key| match v {
+        Value::Array(a) => key.parse::<usize>().ok().and_then(|i| a.get(i)),
+        _ => v.get(key),
+    })
+}
+
+/// Return the `attribute` argument, which must be provided.
+fn attribute_param(params: &HashMap<String, Value>) -> tera::Result<&str> {
+    params
+        .get("attribute")
+        .ok_or("No attribute parameter.")?
+        .as_str()
+        .ok_or_else(|| "Empty or non-string attribute parameter.".into())
+}
+
+/// Return whether a value is true in Jinja: not `null`, `false`, `0`, an empty string or
+/// an empty array or object.
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().is_some_and(|n| n != 0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(a) => !a.is_empty(),
+        Value::Object(o) => !o.is_empty(),
+    }
+}
+
+/// Compare two values as Jinja would: numbers by value, strings case-insensitively where
+/// `case_sensitive` is not set, and values of other types by their type and JSON text.
+fn compare(a: &Value, b: &Value, case_sensitive: bool) -> Ordering {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a
+            .as_f64()
+            .partial_cmp(&b.as_f64())
+            .unwrap_or(Ordering::Equal),
+        (Value::String(a), Value::String(b)) if !case_sensitive => {
+            a.to_lowercase().cmp(&b.to_lowercase())
+        }
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        _ => rank(a)
+            .cmp(&rank(b))
+            .then_with(|| a.to_string().cmp(&b.to_string())),
+    }
+}
+
+/// Return the position of the type of a value in the order of types.
+fn rank(value: &Value) -> u8 {
+    match value {
+        Value::Null => 0,
+        Value::Bool(_) => 1,
+        Value::Number(_) => 2,
+        Value::String(_) => 3,
+        Value::Array(_) => 4,
+        Value::Object(_) => 5,
+    }
+}
+
+/// Apply a Jinja test to a value, with the argument of the test, if any. A missing value is
+/// only `undefined`.
+fn test(value: Option<&Value>, name: &str, argument: Option<&Value>) -> tera::Result<bool> {
+    let argument = || argument.ok_or_else(|| format!("The {} test requires a value.", name));
+    let ordering = |a: &Value, b: &Value| match (a, b) {
+        (Value::Number(_), Value::Number(_)) | (Value::String(_), Value::String(_)) => {
+            Some(compare(a, b, true))
+        }
+        _ => None,
+    };
+    let value = match (name, value) {
+        ("defined", v) => return Ok(v.is_some()),
+        ("undefined", v) => return Ok(v.is_none()),
+        (_, None) => return Ok(false),
+        (_, Some(v)) => v,
+    };
+    Ok(match name {
+        "none" => value.is_null(),
+        "true" => value == &Value::Bool(true),
+        "false" => value == &Value::Bool(false),
+        "truthy" => truthy(value),
+        "falsy" => !truthy(value),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "mapping" => value.is_object(),
+        "sequence" | "iterable" => value.is_array() || value.is_string(),
+        "equalto" | "eq" | "==" | "sameas" => value == argument()?,
+        "ne" | "!=" => value != argument()?,
+        "gt" | ">" | "greaterthan" => ordering(value, argument()?) == Some(Ordering::Greater),
+        "ge" | ">=" => ordering(value, argument()?).is_some_and(|o| o != Ordering::Less),
+        "lt" | "<" | "lessthan" => ordering(value, argument()?) == Some(Ordering::Less),
+        "le" | "<=" => ordering(value, argument()?).is_some_and(|o| o != Ordering::Greater),
+        "in" => match argument()? {
+            Value::Array(values) => values.contains(value),
+            Value::Object(map) => value.as_str().is_some_and(|k| map.contains_key(k)),
+            Value::String(s) => value.as_str().is_some_and(|v| s.contains(v)),
+            _ => false,
+        },
+        other => return Err(format!("Unknown test {}.", other).into()),
+    })
+}
+
+/// Select or reject the elements of an array whose attribute passes a test.
+fn filter_by_attribute(
+    value: &Value,
+    params: &HashMap<String, Value>,
+    keep: bool,
+) -> tera::Result<Value> {
+    let elements = value.as_array().ok_or("Value must be an array")?;
+    let path = attribute_param(params)?;
+    let name = match params.get("test") {
+        Some(t) => t.as_str().ok_or("Non-string test parameter.")?,
+        None => "truthy",
+    };
+    let mut selected = vec![];
+    for element in elements {
+        if test(attribute(element, path), name, params.get("value"))? == keep {
+            selected.push(element.to_owned());
+        }
+    }
+    Ok(Value::Array(selected))
+}
+
+/// Return the elements of an array whose `attribute` passes a test, like Jinja's
+/// `selectattr`. The test is named by the `test` argument, with its argument in `value`, and
+/// defaults to whether the attribute is true.
+pub fn selectattr(value: &Value, params: &HashMap<String, Value>) -> tera::Result<Value> {
+    filter_by_attribute(value, params, true)
+}
+
+/// Return the elements of an array whose `attribute` fails a test, like Jinja's
+/// `rejectattr`.
+pub fn rejectattr(value: &Value, params: &HashMap<String, Value>) -> tera::Result<Value> {
+    filter_by_attribute(value, params, false)
+}
+
+/// Serialize a value as JSON, like Jinja's `tojson`, indented by the optional `indent`
+/// number of spaces.
+pub fn tojson(value: &Value, params: &HashMap<String, Value>) -> tera::Result<Value> {
+    match params.get("indent").and_then(|i| i.as_u64()) {
+        Some(indent) => {
+            let indent = " ".repeat(indent as usize);
+            let mut json = vec![];
+            let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+            let mut serializer = serde_json::Serializer::with_formatter(&mut json, formatter);
+            serde::Serialize::serialize(value, &mut serializer)?;
+            Ok(Value::String(String::from_utf8_lossy(&json).to_string()))
+        }
+        None => Ok(Value::String(serde_json::to_string(value)?)),
+    }
+}
+
+/// Return the `[key, value]` pairs of an object sorted by key, or by value if the `by`
+/// argument is `value`, like Jinja's `dictsort`. Strings are compared case-insensitively
+/// unless `case_sensitive` is `true`, and the order is reversed if `reverse` is `true`.
+pub fn dictsort(value: &Value, params: &HashMap<String, Value>) -> tera::Result<Value> {
+    let map = value.as_object().ok_or("Value must be an object")?;
+    let flag = |name: &str| params.get(name).and_then(|v| v.as_bool()) == Some(true);
+    let by_value = match params.get("by").and_then(|b| b.as_str()) {
+        None | Some("key") => false,
+        Some("value") => true,
+        Some(other) => return Err(format!("by must be key or value, not {}.", other).into()),
+    };
+    let mut pairs: Vec<(Value, &Value)> = map
+        .iter()
+        .map(|(k, v)| (Value::String(k.to_owned()), v))
+        .collect();
+    pairs.sort_by(|a, b| {
+        let (a, b) = if by_value { (a.1, b.1) } else { (&a.0, &b.0) };
+        compare(a, b, flag("case_sensitive"))
+    });
+    if flag("reverse") {
+        pairs.reverse();
+    }
+    Ok(Value::Array(
+        pairs.into_iter().map(|(k, v)| json!([k, v])).collect(),
+    ))
+}
+
+/// Group the elements of an array by an `attribute`, like Jinja's `groupby`, returning an
+/// array of groups sorted by the attribute, each with the `grouper` value and the `list` of
+/// elements. Elements without the attribute are grouped under `null`, or the `default`
+/// argument if provided.
+pub fn groupby(value: &Value, params: &HashMap<String, Value>) -> tera::Result<Value> {
+    let elements = value.as_array().ok_or("Value must be an array")?;
+    let path = attribute_param(params)?;
+    let default = params.get("default").cloned().unwrap_or(Value::Null);
+    let case_sensitive = params.get("case_sensitive").and_then(|v| v.as_bool()) == Some(true);
+
+    let mut groups: Vec<(Value, Vec<Value>)> = vec![];
+    for element in elements {
+        let grouper = attribute(element, path)
+            .cloned()
+            .unwrap_or_else(|| default.to_owned());
+        match groups
+            .iter_mut()
+            .find(|(g, _)| compare(g, &grouper, case_sensitive) == Ordering::Equal)
+        {
+            Some((_, list)) => list.push(element.to_owned()),
+            None => groups.push((grouper, vec![element.to_owned()])),
+        }
+    }
+    groups.sort_by(|a, b| compare(&a.0, &b.0, case_sensitive));
+    Ok(Value::Array(
+        groups
+            .into_iter()
+            .map(|(grouper, list)| json!({"grouper": grouper, "list": list}))
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::templates::jinja::{dictsort, groupby, rejectattr, selectattr, tojson};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn params(values: serde_json::Value) -> HashMap<String, serde_json::Value> {
+        serde_json::from_value(values).unwrap()
+    }
+
+    #[test]
+    fn select_and_reject() {
+        let resources = json!([
+            {"kind": "Service", "spec": {"ports": [80]}, "public": true},
+            {"kind": "Deployment", "spec": {"replicas": 3}},
+            {"kind": "Service", "spec": {"ports": []}, "public": false}
+        ]);
+
+        assert_eq!(
+            selectattr(&resources, &params(json!({"attribute": "public"})))
+                .unwrap()
+                .as_array()
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            rejectattr(
+                &resources,
+                &params(json!({"attribute": "kind", "test": "equalto", "value": "Service"}))
+            )
+            .unwrap(),
+            json!([{"kind": "Deployment", "spec": {"replicas": 3}}])
+        );
+        assert_eq!(
+            selectattr(
+                &resources,
+                &params(json!({"attribute": "spec.replicas", "test": "gt", "value": 2}))
+            )
+            .unwrap()[0]["kind"],
+            "Deployment"
+        );
+        assert_eq!(
+            selectattr(
+                &resources,
+                &params(json!({"attribute": "spec.ports.0", "test": "defined"}))
+            )
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .len(),
+            1
+        );
+        assert!(selectattr(
+            &resources,
+            &params(json!({"attribute": "kind", "test": "matches"}))
+        )
+        .is_err());
+        assert!(selectattr(
+            &resources,
+            &params(json!({"attribute": "kind", "test": "equalto"}))
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn json_and_dicts() {
+        assert_eq!(
+            tojson(&json!({"a": [1]}), &HashMap::new()).unwrap(),
+            json!("{\"a\":[1]}")
+        );
+        assert_eq!(
+            tojson(&json!({"a": 1}), &params(json!({"indent": 2}))).unwrap(),
+            json!("{\n  \"a\": 1\n}")
+        );
+
+        let scores = json!({"b": 1, "C": 3, "a": 2});
+        assert_eq!(
+            dictsort(&scores, &HashMap::new()).unwrap(),
+            json!([["a", 2], ["b", 1], ["C", 3]])
+        );
+        assert_eq!(
+            dictsort(&scores, &params(json!({"case_sensitive": true}))).unwrap(),
+            json!([["C", 3], ["a", 2], ["b", 1]])
+        );
+        assert_eq!(
+            dictsort(&scores, &params(json!({"by": "value", "reverse": true}))).unwrap(),
+            json!([["C", 3], ["a", 2], ["b", 1]])
+        );
+    }
+
+    #[test]
+    fn groups() {
+        let services = json!([
+            {"name": "web", "team": "platform"},
+            {"name": "db", "team": "data"},
+            {"name": "api", "team": "Platform"},
+            {"name": "cache"}
+        ]);
+
+        assert_eq!(
+            groupby(
+                &services,
+                &params(json!({"attribute": "team", "default": "none"}))
+            )
+            .unwrap(),
+            json!([
+                {"grouper": "data", "list": [{"name": "db", "team": "data"}]},
+                {"grouper": "none", "list": [{"name": "cache"}]},
+                {"grouper": "platform", "list": [
+                    {"name": "web", "team": "platform"},
+                    {"name": "api", "team": "Platform"}
+                ]}
+            ])
+        );
+        assert!(groupby(&services, &HashMap::new()).is_err());
+    }
+}
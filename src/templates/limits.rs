@@ -0,0 +1,257 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Limits on rendering templates, guarding against runaway templates.
+//!
+//! Rendered output is written through a [`LimitedWriter`], which stops rendering as soon as
+//! the output of a run exceeds its maximum size or a render exceeds its time limit, so that
+//! a runaway loop fails quickly rather than filling memory or disk. The depth of includes is
+//! checked before rendering, from the templates referenced by each template.
+
+use crate::errors::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Error as IoError, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Limits on rendering the root template.
+#[derive(Debug, Default)]
+pub struct RenderLimits {
+    /// The maximum depth of templates extended, included or imported from the root
+    /// template.
+    max_include_depth: Option<usize>,
+    /// The maximum size in bytes of all output rendered in a run.
+    max_output_size: Option<usize>,
+    /// The maximum time taken by each render of the root template.
+    timeout: Option<Duration>,
+    /// The number of bytes rendered so far in the run.
+    written: AtomicUsize,
+}
+
+impl RenderLimits {
+    /// Create limits from their optional values.
+    pub fn new(
+        max_include_depth: Option<usize>,
+        max_output_size: Option<usize>,
+        timeout: Option<Duration>,
+    ) -> RenderLimits {
+        RenderLimits {
+            max_include_depth,
+            max_output_size,
+            timeout,
+            written: AtomicUsize::new(0),
+        }
+    }
+
+    /// Check that no chain of references from the root template to other templates is
+    /// deeper than the maximum include depth, or recursive, as the depth of recursion
+    /// cannot be known before rendering.
+    pub fn check_depth(
+        &self,
+        root: &str,
+        dependencies: &BTreeMap<String, BTreeSet<String>>,
+    ) -> Result<()> {
+        let Some(max) = self.max_include_depth else {
+            return Ok(());
+        };
+        fn walk(
+            name: &str,
+            dependencies: &BTreeMap<String, BTreeSet<String>>,
+            chain: &mut Vec<String>,
+            max: usize,
+        ) -> Result<()> {
+            if chain.iter().any(|n| n == name) {
+                bail!(ErrorKind::TemplateFailure(format!(
+                    "Template {} includes itself recursively: {} -> {}",
+                    name,
+                    chain.join(" -> "),
+                    name
+                )))
+            }
+            chain.push(name.to_string());
+            if chain.len() > max + 1 {
+                bail!(ErrorKind::TemplateFailure(format!(
+                    "Template {} exceeds the maximum include depth of {}: {}",
+                    chain[0],
+                    max,
+                    chain.join(" -> ")
+                )))
+            }
+            for dependency in dependencies.get(name).into_iter().flatten() {
+                walk(dependency, dependencies, chain, max)?;
+            }
+            chain.pop();
+            Ok(())
+        }
+        walk(root, dependencies, &mut vec![], max)
+    }
+
+    /// Render a template through a writer enforcing the output size and time limits,
+    /// returning the output or an error naming the template if a limit is exceeded.
+    pub fn render(
+        &self,
+        name: &str,
+        render: impl FnOnce(&mut LimitedWriter) -> Result<()>,
+    ) -> Result<String> {
+        let mut writer = LimitedWriter {
+            output: vec![],
+            limits: self,
+            started: Instant::now(),
+            exceeded: None,
+        };
+        let result = render(&mut writer);
+        let exceeded = writer.exceeded.or_else(|| {
+            // Templates which loop without writing output are only stopped once rendered
+            self.timeout
+                .filter(|timeout| writer.started.elapsed() > *timeout)
+                .map(Exceeded::Timeout)
+        });
+        match exceeded {
+            Some(Exceeded::Size(max)) => bail!(format!(
+                "Template {} exceeded the maximum output size of {} bytes",
+                name, max
+            )),
+            Some(Exceeded::Timeout(timeout)) => bail!(format!(
+                "Template {} exceeded the render timeout of {}s",
+                name,
+                timeout.as_secs_f64()
+            )),
+            None => {}
+        }
+        result?;
+        String::from_utf8(writer.output)
+            .map_err(|_| format!("Template {} rendered invalid UTF-8", name).into())
+    }
+}
+
+/// The limit exceeded by a render.
+#[derive(Debug, Clone, Copy)]
+enum Exceeded {
+    Size(usize),
+    Timeout(Duration),
+}
+
+/// A writer collecting rendered output, which fails once a limit is exceeded so that the
+/// template engine stops rendering.
+pub struct LimitedWriter<'a> {
+    output: Vec<u8>,
+    limits: &'a RenderLimits,
+    started: Instant,
+    exceeded: Option<Exceeded>,
+}
+
+impl Write for LimitedWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.limits.written.fetch_add(buf.len(), Ordering::SeqCst) + buf.len();
+        if let Some(max) = self.limits.max_output_size.filter(|max| written > *max) {
+            self.exceeded = Some(Exceeded::Size(max));
+        } else if let Some(timeout) = self
+            .limits
+            .timeout
+            .filter(|timeout| self.started.elapsed() > *timeout)
+        {
+            self.exceeded = Some(Exceeded::Timeout(timeout));
+        }
+        match self.exceeded {
+            Some(_) => Err(IoError::other("render limit exceeded")),
+            None => {
+                self.output.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::templates::limits::RenderLimits;
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::io::Write;
+    use std::time::Duration;
+
+    #[test]
+    fn output_size() {
+        let limits = RenderLimits::new(None, Some(10), None);
+        let render = |text: &'static str| {
+            limits.render("base.tpl", |w| {
+                text.as_bytes()
+                    .chunks(2)
+                    .try_for_each(|chunk| w.write_all(chunk))?;
+                Ok(())
+            })
+        };
+        assert_eq!(render("12345").unwrap(), "12345");
+        // The limit applies to all output rendered in a run
+        assert_eq!(
+            render("123456").unwrap_err().to_string(),
+            "Template base.tpl exceeded the maximum output size of 10 bytes"
+        );
+    }
+
+    #[test]
+    fn timeout() {
+        let limits = RenderLimits::new(None, None, Some(Duration::from_millis(50)));
+        let error = limits
+            .render("base.tpl", |w| loop {
+                w.write_all(b"x")?;
+                std::thread::sleep(Duration::from_millis(10));
+            })
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Template base.tpl exceeded the render timeout of 0.05s"
+        );
+        assert!(limits
+            .render("base.tpl", |w| Ok(w.write_all(b"fast")?))
+            .is_ok());
+    }
+
+    #[test]
+    fn include_depth() {
+        let dependencies: BTreeMap<String, BTreeSet<String>> = [
+            ("base.tpl", vec!["a.tpl"]),
+            ("a.tpl", vec!["b.tpl"]),
+            ("b.tpl", vec![]),
+            ("loop.tpl", vec!["item.tpl"]),
+            ("item.tpl", vec!["loop.tpl"]),
+        ]
+        .into_iter()
+        .map(|(name, deps)| {
+            (
+                name.to_string(),
+                deps.into_iter().map(String::from).collect(),
+            )
+        })
+        .collect();
+        let check = |max, root| RenderLimits::new(max, None, None).check_depth(root, &dependencies);
+
+        assert!(check(None, "loop.tpl").is_ok());
+        assert!(check(Some(2), "base.tpl").is_ok());
+        assert_eq!(
+            check(Some(1), "base.tpl").unwrap_err().to_string(),
+            "Template base.tpl exceeds the maximum include depth of 1: base.tpl -> a.tpl -> b.tpl"
+        );
+        assert_eq!(
+            check(Some(5), "loop.tpl").unwrap_err().to_string(),
+            "Template loop.tpl includes itself recursively: loop.tpl -> item.tpl -> loop.tpl"
+        );
+    }
+}
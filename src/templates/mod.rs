@@ -1,20 +1,109 @@
+pub mod collation;
+pub mod engine;
+pub mod exec;
+pub mod http;
+pub mod i18n;
+pub mod jinja;
+pub mod limits;
+pub mod pages;
+pub mod profile;
+
 pub mod filters {
     //! Custom filters provided to Tera templates.
 
+    use crate::templates::jinja;
+    use crate::templates::profile::{self, Kind};
     use serde_json::to_value;
-    use serde_json::Value;
-    use std::collections::HashMap;
+    use serde_json::{json, Value};
+    use std::collections::{BTreeMap, HashMap};
     use std::path::Path;
     use tera::Error;
 
-    /// Register custom tera filters
+    /// The signature of a Tera filter, which is called with a value and named arguments.
+    pub type Filter = fn(&Value, &HashMap<String, Value>) -> tera::Result<Value>;
+
+    /// Return the custom filters by name.
+    pub fn filters() -> Vec<(&'static str, Filter)> {
+        vec![
+            ("jsonpath", json_path),
+            ("filename", filename),
+            ("extension", extension),
+            ("directory", directory),
+            ("parsedby", parsed_by),
+            ("file", file),
+            ("tagged", tagged),
+            ("format_date", format_date),
+            ("to_csv", to_csv),
+            ("type_of", type_of),
+            ("is_empty", is_empty),
+            ("default_deep", default_deep),
+            ("coalesce", coalesce),
+            ("truncate_middle", truncate_middle),
+            ("wrap", wrap),
+            ("code_fence", code_fence),
+            ("xml_escape", xml_escape),
+            ("html_escape", html_escape),
+            ("yaml_quote", yaml_quote),
+            ("count_by", count_by),
+            ("pivot", pivot),
+            ("relative_to", relative_to),
+            ("join_path", join_path),
+            ("normalize", normalize),
+            ("sort_natural", sort_natural),
+            ("sort_locale", sort_locale),
+            ("tojson", jinja::tojson),
+            ("dictsort", jinja::dictsort),
+            ("selectattr", jinja::selectattr),
+            ("rejectattr", jinja::rejectattr),
+            ("groupby", jinja::groupby),
+        ]
+    }
+
+    /// The named arguments of each custom filter.
+    pub const ARGUMENTS: &[(&str, &[&str])] = &[
+        ("jsonpath", &["path"]),
+        ("filename", &[]),
+        ("extension", &[]),
+        ("directory", &[]),
+        ("parsedby", &["parser"]),
+        ("file", &["path"]),
+        ("tagged", &["tag"]),
+        ("format_date", &["format"]),
+        ("to_csv", &["columns", "headers", "delimiter", "header"]),
+        ("type_of", &[]),
+        ("is_empty", &[]),
+        ("default_deep", &["defaults"]),
+        ("coalesce", &["paths", "default"]),
+        ("truncate_middle", &["length", "separator"]),
+        ("wrap", &["width"]),
+        ("code_fence", &["lang"]),
+        ("xml_escape", &[]),
+        ("html_escape", &[]),
+        ("yaml_quote", &[]),
+        ("count_by", &["path"]),
+        ("pivot", &["rows", "columns"]),
+        ("relative_to", &["base"]),
+        ("join_path", &["path"]),
+        ("normalize", &[]),
+        ("sort_natural", &["attribute", "reverse"]),
+        (
+            "sort_locale",
+            &["locale", "attribute", "numeric", "reverse"],
+        ),
+        ("tojson", &["indent"]),
+        ("dictsort", &["by", "case_sensitive", "reverse"]),
+        ("selectattr", &["attribute", "test", "value"]),
+        ("rejectattr", &["attribute", "test", "value"]),
+        ("groupby", &["attribute", "default", "case_sensitive"]),
+    ];
+
+    /// Register custom tera filters, timed when profiling templates.
     pub fn register_filters(tera: &mut tera::Tera) {
-        tera.register_filter("jsonpath", json_path);
-        tera.register_filter("filename", filename);
-        tera.register_filter("extension", extension);
-        tera.register_filter("directory", directory);
-        tera.register_filter("parsedby", parsed_by);
-        tera.register_filter("file", file);
+        for (name, filter) in filters() {
+            tera.register_filter(name, move |value: &Value, args: &HashMap<String, Value>| {
+                profile::time(Kind::Filter, name, || filter(value, args))
+            });
+        }
     }
 
     /// Return a JSON value by applying the provided JSON path to the provided ihput value.
@@ -36,6 +125,110 @@ pub mod filters {
         Ok(json)
     }
 
+    /// Return the first non-null value selected from a value by the JSONPath expressions in
+    /// the `paths` argument, tried in order, or the optional `default` argument if none
+    /// selects a non-null value.
+    pub fn coalesce(value: &Value, params: &HashMap<String, Value>) -> tera::Result<Value> {
+        let paths = params
+            .get("paths")
+            .ok_or("No paths parameter.")?
+            .as_array()
+            .ok_or("paths must be an array of JSONPath expressions.")?;
+        for path in paths {
+            let path = path
+                .as_str()
+                .ok_or("paths must be an array of JSONPath expressions.")?;
+            let selected = jsonpath_lib::select(value, path)
+                .map_err(|e| Error::msg(format!("Invalid JSONPath expression {}: {}", path, e)))?;
+            if let Some(found) = selected.into_iter().find(|v| !v.is_null()) {
+                return Ok(found.to_owned());
+            }
+        }
+        Ok(params.get("default").cloned().unwrap_or(Value::Null))
+    }
+
+    /// Return the values selected by a JSONPath expression from each element of an array.
+    fn select_each<'a>(value: &'a Value, path: &str) -> tera::Result<Vec<Vec<&'a Value>>> {
+        value
+            .as_array()
+            .ok_or("Value must be an array")?
+            .iter()
+            .map(|element| {
+                jsonpath_lib::select(element, path)
+                    .map_err(|e| Error::msg(format!("Invalid JSONPath expression {}: {}", path, e)))
+            })
+            .collect()
+    }
+
+    /// Return a string argument which must be provided.
+    fn required_str<'a>(params: &'a HashMap<String, Value>, name: &str) -> tera::Result<&'a str> {
+        params
+            .get(name)
+            .ok_or_else(|| format!("No {} parameter.", name))?
+            .as_str()
+            .ok_or_else(|| format!("Empty or non-string {} parameter.", name).into())
+    }
+
+    /// Count the values selected by the JSONPath expression in the `path` argument from each
+    /// element of an array, returning each distinct non-null value with its `count`, most
+    /// frequent first.
+    pub fn count_by(value: &Value, params: &HashMap<String, Value>) -> tera::Result<Value> {
+        let path = required_str(params, "path")?;
+        let values = select_each(value, path)?
+            .into_iter()
+            .flatten()
+            .filter(|v| !v.is_null())
+            .cloned()
+            .collect();
+        Ok(to_value(crate::stats::distinct_values(values))?)
+    }
+
+    /// Cross-tabulate the elements of an array by the first values selected by the JSONPath
+    /// expressions in the `rows` and `columns` arguments, ignoring elements without both.
+    ///
+    /// Returns the sorted distinct `columns`, the `rows`, each with its `value`, the `counts`
+    /// for each column and its `total`, and the `totals` for each column and overall `total`.
+    pub fn pivot(value: &Value, params: &HashMap<String, Value>) -> tera::Result<Value> {
+        let key = |selected: &[&Value]| -> Option<String> {
+            selected.iter().find(|v| !v.is_null()).map(|v| match v {
+                Value::String(s) => s.to_owned(),
+                other => other.to_string(),
+            })
+        };
+        let rows = select_each(value, required_str(params, "rows")?)?;
+        let columns = select_each(value, required_str(params, "columns")?)?;
+
+        let mut counts: BTreeMap<String, BTreeMap<String, usize>> = BTreeMap::new();
+        let mut totals: BTreeMap<String, usize> = BTreeMap::new();
+        for (row, column) in rows.iter().zip(columns.iter()) {
+            if let (Some(row), Some(column)) = (key(row), key(column)) {
+                *counts
+                    .entry(row)
+                    .or_default()
+                    .entry(column.to_owned())
+                    .or_default() += 1;
+                *totals.entry(column).or_default() += 1;
+            }
+        }
+
+        Ok(json!({
+            "columns": totals.keys().collect::<Vec<_>>(),
+            "rows": counts
+                .iter()
+                .map(|(row, row_counts)| json!({
+                    "value": row,
+                    "counts": totals
+                        .keys()
+                        .map(|c| (c.to_owned(), json!(row_counts.get(c).copied().unwrap_or_default())))
+                        .collect::<serde_json::Map<_, _>>(),
+                    "total": row_counts.values().sum::<usize>(),
+                }))
+                .collect::<Vec<_>>(),
+            "totals": totals,
+            "total": totals.values().sum::<usize>(),
+        }))
+    }
+
     /// Filter files for those parsed by the specified parser, provided in the `parser` argument.
     ///
     /// The hashmap must contain a key with the value "parser".
@@ -75,6 +268,428 @@ pub mod filters {
         Ok(json)
     }
 
+    /// Filter files for those with the specified tag, provided in the `tag` argument.
+    ///
+    /// The hashmap must contain a key with the value "tag".
+    pub fn tagged(value: &Value, params: &HashMap<String, Value>) -> tera::Result<Value> {
+        let tag = params
+            .get("tag")
+            .ok_or("No tag parameter.")?
+            .as_str()
+            .ok_or("Empty or non-string tag parameter.")?;
+
+        Ok(Value::Array(
+            value
+                .as_array()
+                .ok_or("Files must be an array")?
+                .iter()
+                .filter(|f| {
+                    f.get("tags")
+                        .and_then(|t| t.as_array())
+                        .is_some_and(|t| t.iter().any(|t| t.as_str() == Some(tag)))
+                })
+                .cloned()
+                .collect(),
+        ))
+    }
+
+    /// Format a time, given as seconds since the Unix epoch, an RFC 3339 timestamp or a
+    /// `YYYY-MM-DD` date, with the `strftime` string in the optional `format` argument,
+    /// defaulting to `%Y-%m-%d`. Times are formatted in UTC.
+    pub fn format_date(value: &Value, params: &HashMap<String, Value>) -> tera::Result<Value> {
+        let time = match value {
+            Value::Number(n) => n.as_i64().map(|n| n.to_string()),
+            Value::String(s) => Some(s.to_owned()),
+            _ => None,
+        }
+        .and_then(|t| crate::clock::parse(&t))
+        .ok_or_else(|| format!("Could not parse {} as a time", value))?;
+        let format = match params.get("format") {
+            Some(f) => f.as_str().ok_or("Non-string format parameter.")?,
+            None => "%Y-%m-%d",
+        };
+        Ok(Value::String(
+            crate::clock::format(&time, format)
+                .ok_or_else(|| format!("Invalid date format {}", format))?,
+        ))
+    }
+
+    /// Render an array of objects as CSV, one row per object, with a header row of the
+    /// column names unless the `header` argument is `false`.
+    ///
+    /// The optional `columns` argument lists the keys of the columns, which may be dotted
+    /// paths into nested objects (e.g. `metadata.name`), and defaults to every key of the
+    /// objects in the order first found. Column names in the header row can be replaced with
+    /// the `headers` argument. Fields are separated by the `delimiter` argument, defaulting
+    /// to `,` (use `"\t"` for TSV), and quoted where necessary. Nested arrays and objects
+    /// are written as JSON.
+    pub fn to_csv(value: &Value, params: &HashMap<String, Value>) -> tera::Result<Value> {
+        let rows = value
+            .as_array()
+            .ok_or("Value must be an array of objects")?;
+        let strings = |name: &str| -> tera::Result<Option<Vec<String>>> {
+            match params.get(name) {
+                Some(Value::Array(values)) => values
+                    .iter()
+                    .map(|v| {
+                        v.as_str()
+                            .map(|s| s.to_string())
+                            .ok_or_else(|| format!("{} must be an array of strings.", name).into())
+                    })
+                    .collect::<tera::Result<Vec<_>>>()
+                    .map(Some),
+                Some(_) => Err(format!("{} must be an array of strings.", name).into()),
+                None => Ok(None),
+            }
+        };
+        let columns = match strings("columns")? {
+            Some(columns) => columns,
+            None => {
+                let mut columns: Vec<String> = vec![];
+                for key in rows
+                    .iter()
+                    .filter_map(|r| r.as_object())
+                    .flat_map(|r| r.keys())
+                {
+                    if !columns.contains(key) {
+                        columns.push(key.to_owned());
+                    }
+                }
+                columns
+            }
+        };
+        let headers = strings("headers")?.unwrap_or_else(|| columns.to_owned());
+        if headers.len() != columns.len() {
+            return Err("headers must have one name for each column.".into());
+        }
+        let delimiter = match params.get("delimiter") {
+            Some(d) => d.as_str().ok_or("Non-string delimiter parameter.")?,
+            None => ",",
+        };
+        let header = params.get("header").and_then(|h| h.as_bool()) != Some(false);
+
+        let mut csv = String::new();
+        let mut write_row = |fields: Vec<String>| {
+            csv.push_str(
+                &fields
+                    .iter()
+                    .map(|f| csv_field(f, delimiter))
+                    .collect::<Vec<_>>()
+                    .join(delimiter),
+            );
+            csv.push('\n');
+        };
+        if header {
+            write_row(headers);
+        }
+        for row in rows {
+            write_row(
+                columns
+                    .iter()
+                    .map(|c| match row.get(c).or_else(|| dotted(row, c)) {
+                        None | Some(Value::Null) => String::new(),
+                        Some(Value::String(s)) => s.to_owned(),
+                        Some(other) => other.to_string(),
+                    })
+                    .collect(),
+            );
+        }
+        Ok(Value::String(csv))
+    }
+
+    /// Sort an array by a string comparison of its elements or, with the optional
+    /// `attribute` argument, of the value at a dotted path within each element. Non-string
+    /// values are compared as JSON and missing values as empty strings. The order is
+    /// reversed if the `reverse` argument is `true`.
+    fn sort_by_text(
+        value: &Value,
+        params: &HashMap<String, Value>,
+        compare: impl Fn(&str, &str) -> std::cmp::Ordering,
+    ) -> tera::Result<Value> {
+        let elements = value.as_array().ok_or("Value must be an array")?;
+        let attribute = match params.get("attribute") {
+            Some(a) => Some(a.as_str().ok_or("Non-string attribute parameter.")?),
+            None => None,
+        };
+        let mut keyed: Vec<(String, &Value)> = elements
+            .iter()
+            .map(|element| {
+                let key = match attribute {
+                    Some(attribute) => dotted(element, attribute),
+                    None => Some(element),
+                };
+                let key = match key {
+                    None | Some(Value::Null) => String::new(),
+                    Some(Value::String(s)) => s.to_owned(),
+                    Some(other) => other.to_string(),
+                };
+                (key, element)
+            })
+            .collect();
+        keyed.sort_by(|a, b| compare(&a.0, &b.0));
+        if params.get("reverse").and_then(|r| r.as_bool()) == Some(true) {
+            keyed.reverse();
+        }
+        Ok(Value::Array(
+            keyed.into_iter().map(|(_, v)| v.to_owned()).collect(),
+        ))
+    }
+
+    /// Sort an array in natural order, in which numbers within strings are compared by
+    /// value, so that `file2` comes before `file10`, with the optional `attribute` and
+    /// `reverse` arguments.
+    pub fn sort_natural(value: &Value, params: &HashMap<String, Value>) -> tera::Result<Value> {
+        sort_by_text(value, params, crate::templates::collation::natural_cmp)
+    }
+
+    /// Sort an array by the collation rules of the locale in the `locale` argument, such as
+    /// `de` or `sv-SE`, with the optional `attribute` and `reverse` arguments. Numbers
+    /// within strings are compared by value if the `numeric` argument is `true`.
+    pub fn sort_locale(value: &Value, params: &HashMap<String, Value>) -> tera::Result<Value> {
+        let locale = required_str(params, "locale")?;
+        let numeric = params.get("numeric").and_then(|n| n.as_bool()) == Some(true);
+        let collator = crate::templates::collation::collator(locale, numeric)?;
+        sort_by_text(value, params, |a, b| collator.compare(a, b))
+    }
+
+    /// Return the value at a dotted path of keys within a value.
+    fn dotted<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+        path.split('.').try_fold(value, |v, key| v.get(key))
+    }
+
+    /// Quote a CSV field if it contains the delimiter, a quote or a line break.
+    fn csv_field(field: &str, delimiter: &str) -> String {
+        if field.contains(delimiter) || field.contains(['"', '\n', '\r']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    }
+
+    /// Return the JSON type of a value: `null`, `boolean`, `number`, `string`, `array` or
+    /// `object`.
+    pub fn type_of(
+        value: &Value,
+        #[allow(unused_variables)] params: &HashMap<String, Value>,
+    ) -> tera::Result<Value> {
+        Ok(Value::from(match value {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        }))
+    }
+
+    /// Return whether a value is empty: `null`, a string of only whitespace, or an array or
+    /// object without elements. Numbers and booleans, including `0` and `false`, are never
+    /// empty.
+    pub fn is_empty(
+        value: &Value,
+        #[allow(unused_variables)] params: &HashMap<String, Value>,
+    ) -> tera::Result<Value> {
+        Ok(Value::Bool(match value {
+            Value::Null => true,
+            Value::Bool(_) | Value::Number(_) => false,
+            Value::String(s) => s.trim().is_empty(),
+            Value::Array(a) => a.is_empty(),
+            Value::Object(o) => o.is_empty(),
+        }))
+    }
+
+    /// Return a value with defaults applied from the object in the `defaults` argument.
+    ///
+    /// Keys of the defaults which are missing or `null` in the value are added, and objects
+    /// present in both are merged recursively. A `null` value is replaced by the defaults.
+    pub fn default_deep(value: &Value, params: &HashMap<String, Value>) -> tera::Result<Value> {
+        let defaults = params.get("defaults").ok_or("No defaults parameter.")?;
+        Ok(merge_defaults(value, defaults))
+    }
+
+    /// Recursively apply defaults to a value.
+    fn merge_defaults(value: &Value, defaults: &Value) -> Value {
+        match (value, defaults) {
+            (Value::Null, _) => defaults.to_owned(),
+            (Value::Object(value), Value::Object(defaults)) => {
+                let mut merged = value.to_owned();
+                for (key, default) in defaults {
+                    let entry = merged.entry(key.to_owned()).or_insert(Value::Null);
+                    *entry = merge_defaults(entry, default);
+                }
+                Value::Object(merged)
+            }
+            _ => value.to_owned(),
+        }
+    }
+
+    /// Return a number argument of at least one, or a default if it is not provided.
+    fn positive_param(
+        params: &HashMap<String, Value>,
+        name: &str,
+        default: usize,
+    ) -> tera::Result<usize> {
+        match params.get(name) {
+            Some(n) => n
+                .as_u64()
+                .filter(|n| *n > 0)
+                .map(|n| n as usize)
+                .ok_or_else(|| format!("{} must be a positive whole number.", name).into()),
+            None => Ok(default),
+        }
+    }
+
+    /// Shorten a string longer than the `length` argument, defaulting to 40 characters, by
+    /// replacing its middle with the `separator` argument, defaulting to `…`, so that both
+    /// the start and end of long paths or hashes remain visible.
+    pub fn truncate_middle(value: &Value, params: &HashMap<String, Value>) -> tera::Result<Value> {
+        let text = value.as_str().ok_or("Value must be a string")?;
+        let length = positive_param(params, "length", 40)?;
+        let separator = match params.get("separator") {
+            Some(s) => s.as_str().ok_or("Non-string separator parameter.")?,
+            None => "…",
+        };
+        let chars: Vec<char> = text.chars().collect();
+        if chars.len() <= length {
+            return Ok(value.to_owned());
+        }
+        let kept = length.saturating_sub(separator.chars().count());
+        let end = kept / 2;
+        let start = kept - end;
+        Ok(Value::String(format!(
+            "{}{}{}",
+            chars[..start].iter().collect::<String>(),
+            separator,
+            chars[chars.len() - end..].iter().collect::<String>()
+        )))
+    }
+
+    /// Wrap each line of a string at the column in the `width` argument, defaulting to 80,
+    /// breaking between words where possible and within words longer than the width.
+    pub fn wrap(value: &Value, params: &HashMap<String, Value>) -> tera::Result<Value> {
+        let text = value.as_str().ok_or("Value must be a string")?;
+        let width = positive_param(params, "width", 80)?;
+        let mut wrapped: Vec<String> = vec![];
+        for line in text.split('\n') {
+            let mut current = String::new();
+            for word in line.split_whitespace() {
+                let mut word: Vec<char> = word.chars().collect();
+                let current_len = current.chars().count();
+                if current_len > 0 && current_len + 1 + word.len() > width {
+                    wrapped.push(std::mem::take(&mut current));
+                }
+                while word.len() > width {
+                    if !current.is_empty() {
+                        wrapped.push(std::mem::take(&mut current));
+                    }
+                    wrapped.push(word.drain(..width).collect());
+                }
+                if !current.is_empty() {
+                    current.push(' ');
+                }
+                current.extend(word);
+            }
+            wrapped.push(current);
+        }
+        Ok(Value::String(wrapped.join("\n")))
+    }
+
+    /// Fence a string as a Markdown code block, with the language in the optional `lang`
+    /// argument. The fence is longer than any run of backticks within the string, so that
+    /// the content cannot close it.
+    pub fn code_fence(value: &Value, params: &HashMap<String, Value>) -> tera::Result<Value> {
+        let text = match value {
+            Value::String(s) => s.to_owned(),
+            other => serde_json::to_string_pretty(other)?,
+        };
+        let lang = match params.get("lang") {
+            Some(l) => l.as_str().ok_or("Non-string lang parameter.")?,
+            None => "",
+        };
+        let longest = text
+            .split(|c| c != '`')
+            .map(|run| run.len())
+            .max()
+            .unwrap_or_default();
+        let fence = "`".repeat((longest + 1).max(3));
+        Ok(Value::String(format!(
+            "{}{}\n{}\n{}",
+            fence,
+            lang,
+            text.trim_end_matches('\n'),
+            fence
+        )))
+    }
+
+    /// Return the text of a scalar value, for the escaping filters.
+    fn scalar_text(value: &Value) -> tera::Result<String> {
+        match value {
+            Value::String(s) => Ok(s.to_owned()),
+            Value::Null => Ok(String::new()),
+            Value::Array(_) | Value::Object(_) => {
+                Err("Value must be a string, number or boolean".into())
+            }
+            other => Ok(other.to_string()),
+        }
+    }
+
+    /// Escape the characters of a string which are special in XML text and attributes.
+    pub fn xml_escape(
+        value: &Value,
+        #[allow(unused_variables)] params: &HashMap<String, Value>,
+    ) -> tera::Result<Value> {
+        escape_markup(value, "&apos;")
+    }
+
+    /// Escape the characters of a string which are special in HTML text and attributes.
+    pub fn html_escape(
+        value: &Value,
+        #[allow(unused_variables)] params: &HashMap<String, Value>,
+    ) -> tera::Result<Value> {
+        escape_markup(value, "&#x27;")
+    }
+
+    /// Escape markup characters, with the provided entity for apostrophes.
+    fn escape_markup(value: &Value, apostrophe: &str) -> tera::Result<Value> {
+        let text = scalar_text(value)?;
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                '\'' => escaped.push_str(apostrophe),
+                c => escaped.push(c),
+            }
+        }
+        Ok(Value::String(escaped))
+    }
+
+    /// Quote a value as a double-quoted YAML string, escaping backslashes, quotes and
+    /// control characters, so that it is read back as the same string whatever it contains.
+    pub fn yaml_quote(
+        value: &Value,
+        #[allow(unused_variables)] params: &HashMap<String, Value>,
+    ) -> tera::Result<Value> {
+        let text = scalar_text(value)?;
+        let mut quoted = String::with_capacity(text.len() + 2);
+        quoted.push('"');
+        for c in text.chars() {
+            match c {
+                '\\' => quoted.push_str("\\\\"),
+                '"' => quoted.push_str("\\\""),
+                '\n' => quoted.push_str("\\n"),
+                '\r' => quoted.push_str("\\r"),
+                '\t' => quoted.push_str("\\t"),
+                c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('"');
+        Ok(Value::String(quoted))
+    }
+
     /// Return the filename of a path.
     pub fn filename(
         value: &Value,
@@ -100,6 +715,54 @@ pub mod filters {
         )?)
     }
 
+    /// Return a path relative to the directory in the `base` argument, with forward slashes
+    /// as separators, e.g. for links between generated pages.
+    pub fn relative_to(value: &Value, params: &HashMap<String, Value>) -> tera::Result<Value> {
+        let path = value.as_str().ok_or("Path must be a string")?;
+        let base = required_str(params, "base")?;
+        Ok(Value::String(
+            crate::paths::relative_to(path, base).ok_or_else(|| {
+                format!("Could not find the path of {} relative to {}", path, base)
+            })?,
+        ))
+    }
+
+    /// Join the path, or array of path segments, in the `path` argument onto a path, with
+    /// forward slashes as separators. A segment with a root replaces the path before it.
+    pub fn join_path(value: &Value, params: &HashMap<String, Value>) -> tera::Result<Value> {
+        let base = value.as_str().ok_or("Path must be a string")?;
+        let segments = match params.get("path").ok_or("No path parameter.")? {
+            Value::String(s) => vec![s.as_str()],
+            Value::Array(a) => a
+                .iter()
+                .map(|s| {
+                    s.as_str()
+                        .ok_or("path must be a string or array of strings.")
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?,
+            _ => return Err("path must be a string or array of strings.".into()),
+        };
+        Ok(Value::String(
+            segments
+                .iter()
+                .fold(crate::paths::portable(base), |joined, segment| {
+                    crate::paths::join(&joined, segment)
+                }),
+        ))
+    }
+
+    /// Normalise a path, with forward slashes as separators, no repeated separators or `.`
+    /// components and `..` components resolved, so that paths written on Windows and Linux
+    /// are the same.
+    pub fn normalize(
+        value: &Value,
+        #[allow(unused_variables)] params: &HashMap<String, Value>,
+    ) -> tera::Result<Value> {
+        Ok(Value::String(crate::paths::portable(
+            value.as_str().ok_or("Path must be a string")?,
+        )))
+    }
+
     /// Return the filename extension of a path.
     pub fn extension(
         value: &Value,
@@ -116,17 +779,186 @@ pub mod filters {
 
 pub mod functions {
 
+    use crate::parsers::{self, ParserOptions};
+    use crate::templates::exec::{self, ExecOptions};
+    use crate::templates::http::{self, HttpOptions};
+    use crate::templates::i18n::{self, Translations};
+    use crate::templates::pages::{self, PageOptions};
+    use crate::templates::profile::{self, Kind};
     use itertools::Itertools;
     use log::error;
     use serde_json::to_value;
     use serde_json::Value;
     use std::collections::HashMap;
+    use std::path::Path;
 
-    pub fn register_functions(tera: &mut tera::Tera) {
-        tera.register_function("glob", glob);
+    /// Options for the custom functions, such as the parser options used by `parse`, the
+    /// network access allowed to `http_get`, the commands allowed to `exec`, the page
+    /// naming used by `page_for` and the translations used by `t`.
+    #[derive(Debug, Clone, Default)]
+    pub struct FunctionOptions {
+        pub parser_options: ParserOptions,
+        pub http_options: HttpOptions,
+        pub exec_options: ExecOptions,
+        pub page_options: PageOptions,
+        pub translations: Translations,
     }
 
-    /// Return the filename extension of a path.
+    /// Return the custom functions by name, configured with the provided options.
+    pub fn functions(options: &FunctionOptions) -> Vec<(&'static str, Box<dyn tera::Function>)> {
+        let parser_options = options.parser_options.to_owned();
+        let http_options = options.http_options.to_owned();
+        let exec_options = options.exec_options.to_owned();
+        let page_options = options.page_options.to_owned();
+        let translations = options.translations.to_owned();
+        vec![
+            ("glob", Box::new(glob)),
+            ("now", Box::new(now)),
+            ("uuid", Box::new(uuid)),
+            ("stable_id", Box::new(stable_id)),
+            (
+                "parse",
+                Box::new(move |args: &HashMap<String, Value>| parse(args, &parser_options)),
+            ),
+            (
+                "http_get",
+                Box::new(move |args: &HashMap<String, Value>| http::http_get(args, &http_options)),
+            ),
+            (
+                "exec",
+                Box::new(move |args: &HashMap<String, Value>| exec::exec(args, &exec_options)),
+            ),
+            (
+                "page_for",
+                Box::new(move |args: &HashMap<String, Value>| pages::page_for(args, &page_options)),
+            ),
+            (
+                "t",
+                Box::new(move |args: &HashMap<String, Value>| i18n::t(args, &translations)),
+            ),
+        ]
+    }
+
+    /// The named arguments of each custom function.
+    pub const ARGUMENTS: &[(&str, &[&str])] = &[
+        ("glob", &["glob"]),
+        ("now", &["timestamp", "format"]),
+        ("uuid", &[]),
+        ("stable_id", &["value", "length", "prefix"]),
+        ("parse", &["path", "parser"]),
+        ("http_get", &["url", "headers", "cache_ttl"]),
+        ("exec", &["cmd", "args"]),
+        ("page_for", &["path", "from"]),
+        ("t", &["key", "count", "default"]),
+    ];
+
+    /// Register custom tera functions, timed when profiling templates.
+    pub fn register_functions(tera: &mut tera::Tera, options: &FunctionOptions) {
+        for (name, function) in functions(options) {
+            tera.register_function(name, move |args: &HashMap<String, Value>| {
+                profile::time(Kind::Function, name, || function.call(args))
+            });
+        }
+    }
+
+    /// Parse the file at the path provided in the `path` argument, returning its parsed
+    /// documents in the same form as the `files` of the template context.
+    ///
+    /// The file is parsed by every parser that accepts it, or only by the parser named in
+    /// the optional `parser` argument. An error is returned if no parser could parse it.
+    pub fn parse(args: &HashMap<String, Value>, options: &ParserOptions) -> tera::Result<Value> {
+        let path = args
+            .get("path")
+            .ok_or("No path parameter.")?
+            .as_str()
+            .ok_or("Empty or non-string path parameter.")?;
+        let parser = match args.get("parser") {
+            Some(p) => Some(p.as_str().ok_or("Non-string parser parameter.")?),
+            None => None,
+        };
+
+        let available: Vec<_> = parsers::parsers_with_options(options)
+            .into_iter()
+            .filter(|p| parser.is_none_or(|name| p.name() == name))
+            .collect();
+        if available.is_empty() {
+            return Err(format!("Unknown parser '{}'.", parser.unwrap_or_default()).into());
+        }
+
+        let (successes, failures) = crate::parse_file(Path::new(path), &available, options);
+        if successes.is_empty() {
+            return Err(match failures.first() {
+                Some(f) => format!("Could not parse {} with {}: {}", path, f.parser, f.error),
+                None => format!("No parser accepted {}.", path),
+            }
+            .into());
+        }
+        Ok(Value::Array(
+            successes
+                .into_iter()
+                .map(crate::context::file_value)
+                .collect(),
+        ))
+    }
+
+    /// Return the current time, or the time frozen with `--frozen-time` or
+    /// `SOURCE_DATE_EPOCH`, as an RFC 3339 timestamp in UTC.
+    ///
+    /// Seconds since the Unix epoch are returned instead if the `timestamp` argument is
+    /// `true`, or the time formatted with the `strftime` string in the `format` argument.
+    pub fn now(args: &HashMap<String, Value>) -> tera::Result<Value> {
+        let time = crate::clock::now();
+        if args.get("timestamp").and_then(|t| t.as_bool()) == Some(true) {
+            return Ok(Value::from(time.timestamp()));
+        }
+        match args.get("format") {
+            Some(format) => {
+                let format = format.as_str().ok_or("Non-string format parameter.")?;
+                Ok(Value::String(
+                    crate::clock::format(&time, format)
+                        .ok_or_else(|| format!("Invalid date format {}", format))?,
+                ))
+            }
+            None => Ok(Value::String(crate::clock::timestamp())),
+        }
+    }
+
+    /// Return a random version 4 UUID, which differs on every call.
+    pub fn uuid(#[allow(unused_variables)] args: &HashMap<String, Value>) -> tera::Result<Value> {
+        Ok(Value::String(uuid::Uuid::new_v4().to_string()))
+    }
+
+    /// The default number of hex characters in an id returned by `stable_id`.
+    const STABLE_ID_LENGTH: usize = 12;
+
+    /// Return an id derived from the SHA-256 hash of the `value` argument, which is the same
+    /// for the same value on every run. Strings are hashed as they are and other values as
+    /// JSON.
+    ///
+    /// The id is the first `length` hex characters of the hash, defaulting to 12, after the
+    /// optional `prefix` argument.
+    pub fn stable_id(args: &HashMap<String, Value>) -> tera::Result<Value> {
+        let value = args.get("value").ok_or("No value parameter.")?;
+        let length = match args.get("length") {
+            Some(l) => {
+                l.as_u64()
+                    .filter(|l| (1..=64).contains(l))
+                    .ok_or("length must be a whole number from 1 to 64.")? as usize
+            }
+            None => STABLE_ID_LENGTH,
+        };
+        let prefix = match args.get("prefix") {
+            Some(p) => p.as_str().ok_or("Non-string prefix parameter.")?,
+            None => "",
+        };
+        let hash = match value {
+            Value::String(s) => crate::manifest::sha256(s.as_bytes()),
+            other => crate::manifest::sha256(serde_json::to_string(other)?.as_bytes()),
+        };
+        Ok(Value::String(format!("{}{}", prefix, &hash[..length])))
+    }
+
+    /// Return the paths of the files matching the glob expression in the `glob` argument.
     pub fn glob(args: &HashMap<String, Value>) -> tera::Result<Value> {
         let paths = glob::glob(
             args.get("glob")
@@ -152,16 +984,92 @@ pub mod functions {
     }
 }
 
+pub mod dependencies {
+    //! Dependency tracking between templates, used to select the root template.
+
+    use std::collections::{BTreeMap, BTreeSet};
+    use tera::ast::Node;
+
+    /// Return the names of the templates referenced by each template, through `extends`,
+    /// `include` or `import`.
+    pub fn template_dependencies(tera: &tera::Tera) -> BTreeMap<String, BTreeSet<String>> {
+        tera.templates
+            .iter()
+            .map(|(name, template)| {
+                let mut references = BTreeSet::new();
+                references.extend(template.parent.iter().cloned());
+                references.extend(
+                    template
+                        .imported_macro_files
+                        .iter()
+                        .map(|(file, _)| file.to_owned()),
+                );
+                template
+                    .macros
+                    .values()
+                    .for_each(|m| collect_includes(&m.body, &mut references));
+                collect_includes(&template.ast, &mut references);
+                (name.to_owned(), references)
+            })
+            .collect()
+    }
+
+    /// Return the templates that are not referenced by any other template, and so may be
+    /// rendered as the root template.
+    pub fn root_candidates(dependencies: &BTreeMap<String, BTreeSet<String>>) -> Vec<String> {
+        let referenced: BTreeSet<&String> = dependencies.values().flatten().collect();
+        dependencies
+            .keys()
+            .filter(|name| !referenced.contains(name))
+            .cloned()
+            .collect()
+    }
+
+    /// Add the names of all templates included within a list of nodes, including those
+    /// nested within blocks, loops, conditions and filter sections.
+    fn collect_includes(nodes: &[Node], references: &mut BTreeSet<String>) {
+        for node in nodes {
+            match node {
+                Node::Include(_, names, _) => references.extend(names.iter().cloned()),
+                Node::Block(_, block, _) => collect_includes(&block.body, references),
+                Node::FilterSection(_, section, _) => collect_includes(&section.body, references),
+                Node::Forloop(_, forloop, _) => {
+                    collect_includes(&forloop.body, references);
+                    if let Some(body) = &forloop.empty_body {
+                        collect_includes(body, references);
+                    }
+                }
+                Node::If(condition, _) => {
+                    condition
+                        .conditions
+                        .iter()
+                        .for_each(|(_, _, body)| collect_includes(body, references));
+                    if let Some((_, body)) = &condition.otherwise {
+                        collect_includes(body, references);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use std::collections::HashMap;
 
+    use crate::templates::dependencies;
     use crate::templates::filters;
     use crate::templates::functions;
     use itertools::Itertools;
     use serde_json::json;
 
+    /// Convert a JSON object into the arguments of a filter or function.
+    fn params(values: serde_json::Value) -> HashMap<String, serde_json::Value> {
+        serde_json::from_value(values).unwrap()
+    }
+
     #[test]
     fn extension() {
         let map: HashMap<String, serde_json::Value> = HashMap::new();
@@ -207,6 +1115,35 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse() {
+        let options = Default::default();
+        let args = |values: &[(&str, &str)]| -> HashMap<String, serde_json::Value> {
+            values
+                .iter()
+                .map(|(k, v)| (k.to_string(), json!(v)))
+                .collect()
+        };
+
+        let parsed =
+            functions::parse(&args(&[("path", "test/resources/test.ini")]), &options).unwrap();
+        assert_eq!(parsed[0]["parser"], "ini");
+        assert_eq!(parsed[0]["path"], "test/resources/test.ini");
+        assert_eq!(parsed[0]["contents"]["owner"]["name"], "John Doe");
+
+        assert!(functions::parse(
+            &args(&[("path", "test/resources/test.ini"), ("parser", "json")]),
+            &options
+        )
+        .is_err());
+        assert!(functions::parse(
+            &args(&[("path", "test/resources/test.ini"), ("parser", "missing")]),
+            &options
+        )
+        .is_err());
+        assert!(functions::parse(&args(&[]), &options).is_err());
+    }
+
     #[test]
     fn directory() {
         let map: HashMap<String, serde_json::Value> = HashMap::new();
@@ -216,6 +1153,349 @@ mod tests {
         );
     }
 
+    #[test]
+    fn paths() {
+        assert_eq!(
+            filters::relative_to(
+                &json!("docs/guides/intro.md"),
+                &params(json!({"base": "docs/api"}))
+            )
+            .unwrap(),
+            json!("../guides/intro.md")
+        );
+        assert!(filters::relative_to(&json!("a.md"), &params(json!({"base": "/docs"}))).is_err());
+        assert_eq!(
+            filters::join_path(&json!("docs"), &params(json!({"path": ["api", "v1.md"]}))).unwrap(),
+            json!("docs/api/v1.md")
+        );
+        assert_eq!(
+            filters::join_path(&json!("docs/api"), &params(json!({"path": "../index.md"})))
+                .unwrap(),
+            json!("docs/index.md")
+        );
+        assert_eq!(
+            filters::normalize(&json!(r"docs\api\.\v1.md"), &HashMap::new()).unwrap(),
+            json!("docs/api/v1.md")
+        );
+    }
+
+    #[test]
+    fn tagged() {
+        let files = json!([
+            { "path": "a.yaml", "tags": ["infra", "prod"] },
+            { "path": "b.yaml", "tags": ["infra"] },
+            { "path": "c.yaml" }
+        ]);
+
+        let mut map: HashMap<String, serde_json::Value> = HashMap::new();
+        map.insert("tag".to_string(), json!("prod"));
+
+        assert_eq!(
+            filters::tagged(&files, &map).unwrap(),
+            json!([{ "path": "a.yaml", "tags": ["infra", "prod"] }])
+        );
+    }
+
+    #[test]
+    fn dates() {
+        let mut map: HashMap<String, serde_json::Value> = HashMap::new();
+        assert_eq!(
+            filters::format_date(&json!(1709296200), &map).unwrap(),
+            json!("2024-03-01")
+        );
+        map.insert("format".to_string(), json!("%d %B %Y %H:%M"));
+        assert_eq!(
+            filters::format_date(&json!("2024-03-01T12:30:00Z"), &map).unwrap(),
+            json!("01 March 2024 12:30")
+        );
+        assert!(filters::format_date(&json!("soon"), &map).is_err());
+
+        assert!(functions::now(&HashMap::new()).unwrap().is_string());
+        let timestamp = HashMap::from([("timestamp".to_string(), json!(true))]);
+        assert!(functions::now(&timestamp).unwrap().is_i64());
+        let year = HashMap::from([("format".to_string(), json!("%Y"))]);
+        assert_eq!(functions::now(&year).unwrap().as_str().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn ids() {
+        let uuid = functions::uuid(&HashMap::new()).unwrap();
+        assert_eq!(uuid.as_str().unwrap().len(), 36);
+        assert_ne!(uuid, functions::uuid(&HashMap::new()).unwrap());
+
+        assert_eq!(
+            functions::stable_id(&params(json!({"value": "abc"}))).unwrap(),
+            json!("ba7816bf8f01")
+        );
+        assert_eq!(
+            functions::stable_id(&params(
+                json!({"value": "abc", "length": 6, "prefix": "id-"})
+            ))
+            .unwrap(),
+            json!("id-ba7816")
+        );
+        assert_eq!(
+            functions::stable_id(&params(json!({"value": {"a": [1, 2]}}))).unwrap(),
+            functions::stable_id(&params(json!({"value": {"a": [1, 2]}}))).unwrap()
+        );
+        assert!(functions::stable_id(&params(json!({"value": "abc", "length": 0}))).is_err());
+        assert!(functions::stable_id(&HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn to_csv() {
+        let rows = json!([
+            {"name": "web", "team": "platform", "meta": {"port": 80}},
+            {"name": "api, v2", "team": "say \"hi\"", "replicas": 3}
+        ]);
+        assert_eq!(
+            filters::to_csv(&rows, &HashMap::new()).unwrap(),
+            json!("name,team,meta,replicas\nweb,platform,\"{\"\"port\"\":80}\",\n\"api, v2\",\"say \"\"hi\"\"\",,3\n")
+        );
+        assert_eq!(
+            filters::to_csv(
+                &rows,
+                &params(json!({
+                    "columns": ["name", "meta.port"],
+                    "headers": ["Service", "Port"],
+                    "delimiter": "\t"
+                }))
+            )
+            .unwrap(),
+            json!("Service\tPort\nweb\t80\napi, v2\t\n")
+        );
+        assert_eq!(
+            filters::to_csv(
+                &rows,
+                &params(json!({"columns": ["name"], "header": false}))
+            )
+            .unwrap(),
+            json!("web\n\"api, v2\"\n")
+        );
+        assert!(filters::to_csv(&rows, &params(json!({"headers": ["Name"]}))).is_err());
+        assert!(filters::to_csv(&json!("rows"), &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn introspection() {
+        let map: HashMap<String, serde_json::Value> = HashMap::new();
+        let types = [
+            json!(null),
+            json!(false),
+            json!(0),
+            json!(""),
+            json!([]),
+            json!({}),
+        ]
+        .iter()
+        .map(|v| filters::type_of(v, &map).unwrap())
+        .collect_vec();
+        assert_eq!(
+            types,
+            ["null", "boolean", "number", "string", "array", "object"]
+        );
+
+        let empty = [
+            json!(null),
+            json!(" "),
+            json!([]),
+            json!({}),
+            json!(0),
+            json!(false),
+        ]
+        .iter()
+        .map(|v| filters::is_empty(v, &map).unwrap())
+        .collect_vec();
+        assert_eq!(empty, [true, true, true, true, false, false]);
+
+        let defaults = HashMap::from([(
+            "defaults".to_string(),
+            json!({"replicas": 1, "image": {"tag": "latest", "pull": "Always"}}),
+        )]);
+        assert_eq!(
+            filters::default_deep(
+                &json!({"replicas": null, "image": {"tag": "1.2"}, "name": "web"}),
+                &defaults
+            )
+            .unwrap(),
+            json!({"replicas": 1, "image": {"tag": "1.2", "pull": "Always"}, "name": "web"})
+        );
+        assert_eq!(
+            filters::default_deep(&json!(null), &defaults).unwrap(),
+            defaults["defaults"]
+        );
+        assert!(filters::default_deep(&json!({}), &map).is_err());
+    }
+
+    #[test]
+    fn coalesce() {
+        let data = json!({"spec": {"replicas": null}, "replicaCount": 3});
+        assert_eq!(
+            filters::coalesce(
+                &data,
+                &params(json!({"paths": ["$.spec.replicas", "$.replicaCount"]}))
+            )
+            .unwrap(),
+            json!(3)
+        );
+        assert_eq!(
+            filters::coalesce(
+                &data,
+                &params(json!({"paths": ["$.replicas"], "default": 1}))
+            )
+            .unwrap(),
+            json!(1)
+        );
+        assert_eq!(
+            filters::coalesce(&data, &params(json!({"paths": ["$.replicas"]}))).unwrap(),
+            json!(null)
+        );
+        assert!(filters::coalesce(&data, &params(json!({"paths": ["$.["]}))).is_err());
+        assert!(filters::coalesce(&data, &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(
+            filters::truncate_middle(&json!("0123456789abcdef"), &params(json!({"length": 9})))
+                .unwrap(),
+            json!("0123…cdef")
+        );
+        assert_eq!(
+            filters::truncate_middle(
+                &json!("src/templates/mod.rs"),
+                &params(json!({"length": 12, "separator": "..."}))
+            )
+            .unwrap(),
+            json!("src/t...d.rs")
+        );
+        assert_eq!(
+            filters::truncate_middle(&json!("short"), &HashMap::new()).unwrap(),
+            json!("short")
+        );
+
+        assert_eq!(
+            filters::wrap(
+                &json!("the quick brown fox\nabcdefghijkl jumps"),
+                &params(json!({"width": 5}))
+            )
+            .unwrap(),
+            json!("the\nquick\nbrown\nfox\nabcde\nfghij\nkl\njumps")
+        );
+        assert!(filters::wrap(&json!("text"), &params(json!({"width": 0}))).is_err());
+
+        assert_eq!(
+            filters::code_fence(&json!("echo `date`\n"), &params(json!({"lang": "sh"}))).unwrap(),
+            json!("```sh\necho `date`\n```")
+        );
+        assert_eq!(
+            filters::code_fence(&json!("```\nnested\n```"), &HashMap::new()).unwrap(),
+            json!("````\n```\nnested\n```\n````")
+        );
+    }
+
+    #[test]
+    fn escaping() {
+        let map: HashMap<String, serde_json::Value> = HashMap::new();
+        let text = json!("<a href=\"x?a=1&b='2'\">");
+
+        assert_eq!(
+            filters::xml_escape(&text, &map).unwrap(),
+            json!("&lt;a href=&quot;x?a=1&amp;b=&apos;2&apos;&quot;&gt;")
+        );
+        assert_eq!(
+            filters::html_escape(&text, &map).unwrap(),
+            json!("&lt;a href=&quot;x?a=1&amp;b=&#x27;2&#x27;&quot;&gt;")
+        );
+        assert_eq!(filters::xml_escape(&json!(3), &map).unwrap(), json!("3"));
+        assert!(filters::html_escape(&json!([]), &map).is_err());
+
+        let quoted = filters::yaml_quote(&json!("yes: \"no\"\\\n\u{7}"), &map).unwrap();
+        assert_eq!(quoted, json!("\"yes: \\\"no\\\"\\\\\\n\\u0007\""));
+        let parsed: String = serde_yaml::from_str(quoted.as_str().unwrap()).unwrap();
+        assert_eq!(parsed, "yes: \"no\"\\\n\u{7}");
+        assert_eq!(
+            filters::yaml_quote(&json!(true), &map).unwrap(),
+            json!("\"true\"")
+        );
+    }
+
+    #[test]
+    fn tables() {
+        let services = json!([
+            {"name": "web", "team": "platform", "env": "prod", "tags": ["a", "b"]},
+            {"name": "api", "team": "platform", "env": "dev", "tags": ["b"]},
+            {"name": "db", "team": "data", "env": "prod"},
+            {"name": "cache", "env": "prod"}
+        ]);
+        assert_eq!(
+            filters::count_by(&services, &params(json!({"path": "$.team"}))).unwrap(),
+            json!([{"value": "platform", "count": 2}, {"value": "data", "count": 1}])
+        );
+        assert_eq!(
+            filters::count_by(&services, &params(json!({"path": "$.tags[*]"}))).unwrap(),
+            json!([{"value": "b", "count": 2}, {"value": "a", "count": 1}])
+        );
+        assert!(filters::count_by(&json!({}), &params(json!({"path": "$.team"}))).is_err());
+
+        assert_eq!(
+            filters::pivot(
+                &services,
+                &params(json!({"rows": "$.team", "columns": "$.env"}))
+            )
+            .unwrap(),
+            json!({
+                "columns": ["dev", "prod"],
+                "rows": [
+                    {"value": "data", "counts": {"dev": 0, "prod": 1}, "total": 1},
+                    {"value": "platform", "counts": {"dev": 1, "prod": 1}, "total": 2}
+                ],
+                "totals": {"dev": 1, "prod": 2},
+                "total": 3
+            })
+        );
+        assert!(filters::pivot(&services, &params(json!({"rows": "$.team"}))).is_err());
+    }
+
+    #[test]
+    fn sorting() {
+        assert_eq!(
+            filters::sort_natural(&json!(["v10", "v9", "V1"]), &HashMap::new()).unwrap(),
+            json!(["V1", "v9", "v10"])
+        );
+        let files = json!([
+            {"file": {"path": "file10.md"}},
+            {"file": {"path": "file2.md"}},
+            {"file": {}}
+        ]);
+        assert_eq!(
+            filters::sort_natural(
+                &files,
+                &params(json!({"attribute": "file.path", "reverse": true}))
+            )
+            .unwrap(),
+            json!([{"file": {"path": "file10.md"}}, {"file": {"path": "file2.md"}}, {"file": {}}])
+        );
+
+        assert_eq!(
+            filters::sort_locale(
+                &json!(["öl", "zoo", "ost"]),
+                &params(json!({"locale": "sv"}))
+            )
+            .unwrap(),
+            json!(["ost", "zoo", "öl"])
+        );
+        assert_eq!(
+            filters::sort_locale(
+                &json!(["öl", "zoo", "ost"]),
+                &params(json!({"locale": "de"}))
+            )
+            .unwrap(),
+            json!(["öl", "ost", "zoo"])
+        );
+        assert!(filters::sort_locale(&json!([]), &HashMap::new()).is_err());
+    }
+
     #[test]
     fn json_path() {
         let data = serde_json::json!({
@@ -231,4 +1511,19 @@ mod tests {
 
         assert_eq!(filters::json_path(&data, &map).unwrap(), json!([[1, 2, 3]]));
     }
+
+    #[test]
+    fn template_dependencies() {
+        let tera = tera::Tera::new("test/templates/inheritance/*").unwrap();
+        let dependencies = dependencies::template_dependencies(&tera);
+
+        assert_eq!(
+            dependencies["page.tpl"].iter().collect_vec(),
+            vec!["layout.tpl", "macros.tpl", "row.tpl",]
+        );
+        assert_eq!(
+            dependencies::root_candidates(&dependencies),
+            vec!["page.tpl"]
+        );
+    }
 }
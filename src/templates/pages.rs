@@ -0,0 +1,203 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! The `page_for` template function, which returns the generated page for a source file.
+//!
+//! Pages are found with the `pattern` of the `[pages]` section of the configuration file,
+//! which should also be used to name split output files, or otherwise from the manifest
+//! written to the output directory by the previous run with `--manifest`:
+//!
+//! ```toml
+//! [pages]
+//! pattern = "services/{stem}.md"
+//! base_url = "https://docs.example.com/"
+//! ```
+
+use crate::config::PagesConfig;
+use crate::manifest::Manifest;
+use crate::paths;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Options for finding the generated page for a source file.
+#[derive(Debug, Clone, Default)]
+pub struct PageOptions {
+    /// The pattern of page paths, relative to the output directory.
+    pub pattern: Option<String>,
+    /// The URL prepended to page paths, if links are not relative.
+    pub base_url: Option<String>,
+    /// The manifest of the previous run, used if there is no pattern.
+    pub manifest: Option<Manifest>,
+}
+
+impl PageOptions {
+    /// Create page options from the configuration and the previous manifest, if any.
+    pub fn new(config: &PagesConfig, manifest: Option<Manifest>) -> PageOptions {
+        PageOptions {
+            pattern: config.pattern.to_owned(),
+            base_url: config.base_url.to_owned(),
+            manifest,
+        }
+    }
+}
+
+/// Return the generated page for the source file at the path in the `path` argument.
+///
+/// The page is a path relative to the directory of the page in the optional `from`
+/// argument, for links between pages, or otherwise the path of the page relative to the
+/// output directory, after the configured base URL, if any. An error is returned if no
+/// page can be found.
+pub fn page_for(args: &HashMap<String, Value>, options: &PageOptions) -> tera::Result<Value> {
+    let path = args
+        .get("path")
+        .ok_or("No path parameter.")?
+        .as_str()
+        .ok_or("Empty or non-string path parameter.")?;
+    let page = match (&options.pattern, &options.manifest) {
+        (Some(pattern), _) => apply_pattern(pattern, path),
+        (None, Some(manifest)) => from_manifest(manifest, path)
+            .ok_or_else(|| format!("No generated page in the manifest has source {}", path))?,
+        (None, None) => {
+            return Err(format!(
+                "Could not find the page for {}: set a [pages] pattern in the configuration file or write a manifest with --manifest",
+                path
+            )
+            .into())
+        }
+    };
+
+    match args.get("from") {
+        Some(from) => {
+            let from = from.as_str().ok_or("Non-string from parameter.")?;
+            let directory = paths::join(from, "..");
+            paths::relative_to(&page, &directory)
+                .map(Value::String)
+                .ok_or_else(|| format!("Could not link to {} from {}", page, from).into())
+        }
+        None => Ok(Value::String(match &options.base_url {
+            Some(base) => format!("{}/{}", base.trim_end_matches('/'), page),
+            None => page,
+        })),
+    }
+}
+
+/// Return the page path for a source file from a pattern, replacing `{path}`,
+/// `{directory}`, `{name}`, `{stem}` and `{extension}` with those of the source path.
+fn apply_pattern(pattern: &str, path: &str) -> String {
+    let path = paths::portable(path);
+    let source = Path::new(&path);
+    let part = |p: Option<&std::ffi::OsStr>| p.map(|p| p.to_string_lossy().to_string());
+    let directory = source
+        .parent()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default();
+    paths::portable(
+        &pattern
+            .replace("{path}", &path)
+            .replace("{directory}", &directory)
+            .replace("{name}", &part(source.file_name()).unwrap_or_default())
+            .replace("{stem}", &part(source.file_stem()).unwrap_or_default())
+            .replace("{extension}", &part(source.extension()).unwrap_or_default()),
+    )
+}
+
+/// Return the generated file with a source file among its sources, preferring the file
+/// with the fewest sources, as files without a `source` attribute list every source.
+fn from_manifest(manifest: &Manifest, path: &str) -> Option<String> {
+    let path = paths::portable(path);
+    manifest
+        .files
+        .iter()
+        .filter(|f| {
+            f.sources
+                .iter()
+                .any(|s| paths::portable(&s.to_string_lossy()) == path)
+        })
+        .min_by_key(|f| f.sources.len())
+        .map(|f| paths::portable(&f.path.to_string_lossy()))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::manifest::{GeneratedFile, Manifest};
+    use crate::templates::pages::{page_for, PageOptions};
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    fn args(values: serde_json::Value) -> HashMap<String, serde_json::Value> {
+        serde_json::from_value(values).unwrap()
+    }
+
+    #[test]
+    fn pattern() {
+        let options = PageOptions {
+            pattern: Some("services/{directory}/{stem}.md".to_string()),
+            base_url: Some("https://docs.example.com/".to_string()),
+            manifest: None,
+        };
+
+        assert_eq!(
+            page_for(&args(json!({"path": "./k8s/web.yaml"})), &options).unwrap(),
+            json!("https://docs.example.com/services/k8s/web.md")
+        );
+        assert_eq!(
+            page_for(
+                &args(json!({"path": "k8s/web.yaml", "from": "overview/index.md"})),
+                &options
+            )
+            .unwrap(),
+            json!("../services/k8s/web.md")
+        );
+        assert!(page_for(&args(json!({"path": "a.yaml"})), &PageOptions::default()).is_err());
+    }
+
+    #[test]
+    fn manifest() {
+        let file = |path: &str, sources: &[&str]| GeneratedFile {
+            path: PathBuf::from(path),
+            sha256: String::new(),
+            sources: sources.iter().map(PathBuf::from).collect(),
+        };
+        let options = PageOptions {
+            manifest: Some(Manifest {
+                kvasir_version: String::new(),
+                generated: String::new(),
+                engine: "tera".to_string(),
+                template: "docs.tpl".to_string(),
+                templates: Default::default(),
+                sources: vec![],
+                files: vec![
+                    file("index.md", &["k8s/web.yaml", "k8s/api.yaml"]),
+                    file("k8s/web.md", &["k8s/web.yaml"]),
+                ],
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            page_for(&args(json!({"path": "k8s/web.yaml"})), &options).unwrap(),
+            json!("k8s/web.md")
+        );
+        assert_eq!(
+            page_for(&args(json!({"path": "k8s/api.yaml"})), &options).unwrap(),
+            json!("index.md")
+        );
+        assert!(page_for(&args(json!({"path": "k8s/db.yaml"})), &options).is_err());
+    }
+}
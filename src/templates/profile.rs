@@ -0,0 +1,164 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Profiling of template rendering with `--profile-templates`.
+//!
+//! Each render of a root template and each call of a custom filter or function, such as
+//! `jsonpath` or `glob`, is timed and recorded by name, so that the authors of slow templates
+//! can find where the time goes. Timing is only recorded once profiling is enabled, so that
+//! ordinary runs only pay for checking whether it is.
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static TIMINGS: Mutex<BTreeMap<(Kind, String), Timing>> = Mutex::new(BTreeMap::new());
+
+/// The kind of thing timed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    Template,
+    Filter,
+    Function,
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Kind::Template => write!(f, "template"),
+            Kind::Filter => write!(f, "filter"),
+            Kind::Function => write!(f, "function"),
+        }
+    }
+}
+
+/// The time taken by all calls of a template, filter or function.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Timing {
+    /// The number of calls.
+    pub calls: usize,
+    /// The total time taken by the calls.
+    pub total: Duration,
+    /// The time taken by the slowest call.
+    pub max: Duration,
+}
+
+/// Start recording the time taken by templates, filters and functions.
+pub fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// Call a template, filter or function, recording the time taken if profiling is enabled.
+pub fn time<T>(kind: Kind, name: &str, call: impl FnOnce() -> T) -> T {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return call();
+    }
+    let start = Instant::now();
+    let result = call();
+    let elapsed = start.elapsed();
+    if let Ok(mut timings) = TIMINGS.lock() {
+        let timing = timings.entry((kind, name.to_string())).or_default();
+        timing.calls += 1;
+        timing.total += elapsed;
+        timing.max = timing.max.max(elapsed);
+    }
+    result
+}
+
+/// Return the recorded timings, slowest in total first.
+pub fn timings() -> Vec<(Kind, String, Timing)> {
+    let mut timings: Vec<_> = TIMINGS
+        .lock()
+        .map(|t| {
+            t.iter()
+                .map(|((kind, name), timing)| (*kind, name.to_owned(), *timing))
+                .collect()
+        })
+        .unwrap_or_default();
+    timings.sort_by_key(|t| std::cmp::Reverse(t.2.total));
+    timings
+}
+
+/// Return the recorded timings as the lines of a table.
+pub fn report() -> Vec<String> {
+    let timings = timings();
+    let width = timings
+        .iter()
+        .map(|(_, name, _)| name.len())
+        .max()
+        .unwrap_or_default()
+        .max(4);
+    let mut lines = vec![format!(
+        "{:<8}  {:<width$}  {:>8}  {:>12}  {:>12}  {:>12}",
+        "kind", "name", "calls", "total", "mean", "max"
+    )];
+    lines.extend(timings.iter().map(|(kind, name, t)| {
+        format!(
+            "{:<8}  {:<width$}  {:>8}  {:>12.3?}  {:>12.3?}  {:>12.3?}",
+            kind.to_string(),
+            name,
+            t.calls,
+            t.total,
+            t.total / t.calls.max(1) as u32,
+            t.max
+        )
+    }));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::templates::profile::{enable, report, time, timings, Kind};
+    use std::time::Duration;
+
+    #[test]
+    fn profile() {
+        assert_eq!(time(Kind::Filter, "profile_test_before", || 1), 1);
+        enable();
+        for _ in 0..2 {
+            time(Kind::Function, "profile_test_slow", || {
+                std::thread::sleep(Duration::from_millis(5))
+            });
+        }
+        time(Kind::Filter, "profile_test_fast", || ());
+
+        // Other tests may render templates concurrently, so only these names are checked
+        let ours: Vec<_> = timings()
+            .into_iter()
+            .filter(|(_, name, _)| name.starts_with("profile_test"))
+            .collect();
+        assert_eq!(ours.len(), 2);
+        let (kind, name, slow) = &ours[0];
+        assert_eq!(
+            (*kind, name.as_str(), slow.calls),
+            (Kind::Function, "profile_test_slow", 2)
+        );
+        assert!(slow.total >= Duration::from_millis(10));
+        assert!(slow.max >= Duration::from_millis(5) && slow.max <= slow.total);
+        assert_eq!(ours[1].2.calls, 1);
+
+        let report = report();
+        assert!(report[0].starts_with("kind"));
+        assert!(report
+            .iter()
+            .any(|l| l.starts_with("function") && l.contains("profile_test_slow")));
+    }
+}
@@ -0,0 +1,127 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! A local HTTP server for tests, which answers requests with canned responses and records
+//! the requests it receives.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+/// A canned HTTP response.
+pub struct Response {
+    status: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    /// Create an empty response with a status, e.g. `404 Not Found`.
+    pub fn new(status: &str) -> Response {
+        Response {
+            status: status.to_string(),
+            headers: vec![],
+            body: vec![],
+        }
+    }
+
+    /// Create a `200 OK` response with a body.
+    pub fn ok(body: impl Into<Vec<u8>>) -> Response {
+        Response {
+            body: body.into(),
+            ..Response::new("200 OK")
+        }
+    }
+
+    /// Add a header to the response.
+    pub fn header(mut self, name: &str, value: &str) -> Response {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    fn write(&self, stream: &mut TcpStream) -> std::io::Result<()> {
+        write!(stream, "HTTP/1.1 {}\r\n", self.status)?;
+        for (name, value) in &self.headers {
+            write!(stream, "{}: {}\r\n", name, value)?;
+        }
+        write!(
+            stream,
+            "Content-Length: {}\r\nConnection: close\r\n\r\n",
+            self.body.len()
+        )?;
+        stream.write_all(&self.body)
+    }
+}
+
+/// Read a request, returning its request line, e.g. `GET /a HTTP/1.1`.
+fn read_request(stream: &TcpStream) -> String {
+    let mut reader = BufReader::new(stream);
+    let mut request = String::new();
+    let _ = reader.read_line(&mut request);
+    let (mut line, mut length) = (String::new(), 0);
+    while reader.read_line(&mut line).is_ok_and(|n| n > 0) && line != "\r\n" {
+        if let Some(l) = line.to_lowercase().strip_prefix("content-length:") {
+            length = l.trim().parse().unwrap_or_default();
+        }
+        line.clear();
+    }
+    let _ = reader.read_exact(&mut vec![0; length]);
+    request.trim().to_string()
+}
+
+/// A local HTTP server, which runs until the tests finish.
+pub struct Server {
+    /// The base URL of the server, e.g. `http://127.0.0.1:8080`.
+    pub url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl Server {
+    /// Serve the response returned for each request line.
+    pub fn new(respond: impl Fn(&str) -> Response + Send + 'static) -> Server {
+        Server::start(usize::MAX, respond)
+    }
+
+    /// Serve the given responses to successive requests, then refuse connections.
+    pub fn sequence(responses: Vec<Response>) -> Server {
+        let count = responses.len();
+        let responses = Mutex::new(responses.into_iter());
+        Server::start(count, move |_| responses.lock().unwrap().next().unwrap())
+    }
+
+    fn start(limit: usize, respond: impl Fn(&str) -> Response + Send + 'static) -> Server {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let received = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(limit) {
+                let Ok(mut stream) = stream else { continue };
+                let request = read_request(&stream);
+                let response = respond(&request);
+                // Recorded before responding, so that clients see every request they made
+                received.lock().unwrap().push(request);
+                let _ = response.write(&mut stream);
+            }
+        });
+        Server { url, requests }
+    }
+
+    /// Return the request lines received so far.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().to_owned()
+    }
+}
@@ -0,0 +1,121 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Tracing of the parse pipeline with `--trace-output`.
+//!
+//! The pipeline is instrumented with `tracing` spans: one for listing the source files, one
+//! for parsing them, and within it one for each file and one for each parser that parses it.
+//! With `--trace-output`, the spans are written to a file in the Chrome trace event format,
+//! which can be opened in `chrome://tracing`, [Perfetto](https://ui.perfetto.dev) or
+//! [Speedscope](https://www.speedscope.app) to view the time taken as a flame graph.
+//!
+//! Spans with a `name` field are named after its value in the trace, so that the spans of
+//! each parser are named after the parser, e.g. `yaml`, rather than all being `parser`.
+
+use crate::errors::*;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::Subscriber;
+use tracing_chrome::{ChromeLayerBuilder, EventOrSpan, FlushGuard};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// The name of a span in the trace, from its `name` field.
+struct SpanName(String);
+
+impl Visit for SpanName {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "name" {
+            self.0 = value.to_string();
+        }
+    }
+
+    fn record_debug(&mut self, _: &Field, _: &dyn std::fmt::Debug) {}
+}
+
+/// A layer recording the name of each span with a `name` field.
+struct SpanNames;
+
+impl<S> Layer<S> for SpanNames
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut name = SpanName(String::new());
+        attrs.record(&mut name);
+        if let Some(span) = ctx.span(id).filter(|_| !name.0.is_empty()) {
+            span.extensions_mut().insert(name);
+        }
+    }
+}
+
+/// Start writing spans to a Chrome trace file, returning a guard which finishes writing the
+/// file when dropped.
+pub fn init(path: &Path) -> Result<FlushGuard> {
+    let file = File::create(path)
+        .chain_err(|| format!("Could not create trace file {}", path.display()))?;
+    let (chrome, guard) = ChromeLayerBuilder::new()
+        .writer(BufWriter::new(file))
+        .include_args(true)
+        .name_fn(Box::new(|event_or_span| match event_or_span {
+            EventOrSpan::Event(event) => event.metadata().name().to_string(),
+            EventOrSpan::Span(span) => match span.extensions().get::<SpanName>() {
+                Some(name) => name.0.to_owned(),
+                None => span.name().to_string(),
+            },
+        }))
+        .build();
+    tracing::subscriber::set_global_default(
+        tracing_subscriber::registry().with(SpanNames).with(chrome),
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(guard)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::trace::init;
+    use serde_json::Value;
+
+    #[test]
+    fn chrome_trace() {
+        let path = std::env::temp_dir().join(format!("kvasir-trace-{}.json", std::process::id()));
+        let guard = init(&path).unwrap();
+        tracing::info_span!("file", path = %"a.yaml").in_scope(|| {
+            tracing::info_span!("parser", name = "yaml", path = %"a.yaml").in_scope(|| {});
+        });
+        drop(guard);
+
+        // Other tests may parse files concurrently, so only these spans are checked
+        let trace: Vec<Value> =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let names: Vec<(&str, &str)> = trace
+            .iter()
+            .filter(|e| e["args"]["path"] == "a.yaml")
+            .map(|e| (e["ph"].as_str().unwrap(), e["name"].as_str().unwrap()))
+            .collect();
+        assert_eq!(
+            names,
+            [("B", "file"), ("B", "yaml"), ("E", "yaml"), ("E", "file")]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,512 @@
+/*
+   Copyright 2021 Credera
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Updating kvasir in place with `kvasir self-update`, and checking for a newer release with
+//! `--check-update`.
+//!
+//! The latest release is read from the GitHub releases API. Its build for the current
+//! platform is only installed if its SHA-256 hash matches the one listed in the release's
+//! `SHA256SUMS` file, and the `gpg` command has verified that the release's `SHA256SUMS.asc`
+//! signature of the checksums was made by the kvasir release key. As the checksums are
+//! published alongside the builds, they only protect against a tampered release when
+//! signed, so a release without a valid signature is only installed with
+//! `--insecure-skip-signature`. The new binary is written alongside the running one and
+//! renamed over it, so that an interrupted update leaves the running binary in place.
+
+use crate::errors::*;
+use crate::manifest::sha256;
+use crate::signing;
+use itertools::Itertools;
+use log::{debug, warn};
+use serde::Deserialize;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The URL of the latest release of kvasir in the GitHub releases API.
+pub const RELEASES_URL: &str = "https://api.github.com/repos/crederauk/kvasir/releases/latest";
+
+/// The name of the release file listing the SHA-256 hash of each build.
+pub const CHECKSUMS_FILE: &str = "SHA256SUMS";
+
+/// The name of the detached GPG signature of the checksums.
+pub const SIGNATURE_FILE: &str = "SHA256SUMS.asc";
+
+/// The fingerprint of the GPG key which signs kvasir releases, which is embedded from the
+/// `KVASIR_RELEASE_KEY` environment variable when kvasir is built for release.
+pub const RELEASE_KEY: Option<&str> = option_env!("KVASIR_RELEASE_KEY");
+
+/// The version of the running kvasir.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The time allowed for each request.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The maximum size of a downloaded file.
+const MAX_DOWNLOAD: u64 = 256 * 1024 * 1024;
+
+/// A release, as returned by the GitHub releases API.
+#[derive(Debug, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    pub assets: Vec<Asset>,
+}
+
+/// A file attached to a release.
+#[derive(Debug, Deserialize)]
+pub struct Asset {
+    pub name: String,
+    pub browser_download_url: String,
+}
+
+/// The result of updating kvasir.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// The running version is the latest release, or newer.
+    UpToDate(String),
+    /// A newer release is available, but was not installed.
+    Available(String),
+    /// The latest release was installed.
+    Updated(String),
+}
+
+impl Release {
+    /// Return the version of the release, from its tag.
+    pub fn version(&self) -> &str {
+        self.tag_name.trim_start_matches('v')
+    }
+
+    /// Return whether the release is newer than the given version.
+    pub fn is_newer_than(&self, version: &str) -> bool {
+        numbers(self.version()) > numbers(version)
+    }
+
+    /// Return the first asset with one of the given names.
+    fn asset(&self, names: &[String]) -> Option<&Asset> {
+        names
+            .iter()
+            .find_map(|name| self.assets.iter().find(|a| &a.name == name))
+    }
+}
+
+/// Return the numeric components of a version, e.g. `[0, 3, 6]` for `0.3.6`, ignoring any
+/// pre-release or build suffix.
+fn numbers(version: &str) -> Vec<u64> {
+    version
+        .split(['-', '+'])
+        .next()
+        .unwrap_or_default()
+        .split('.')
+        .map(|n| n.parse().unwrap_or_default())
+        .collect()
+}
+
+/// Return the names of the release files which may hold the build for the current platform,
+/// e.g. `kvasir_linux_amd64` or `kvasir_darwin_arm64`, in order of preference.
+pub fn asset_names() -> Vec<String> {
+    let os = match std::env::consts::OS {
+        "macos" => "darwin",
+        os => os,
+    };
+    let arch = match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        arch => arch,
+    };
+    let mut names = vec![format!(
+        "kvasir_{}_{}{}",
+        os,
+        arch,
+        std::env::consts::EXE_SUFFIX
+    )];
+    // Releases have only included this Linux build under this name
+    if os == "linux" && arch == "amd64" {
+        names.push("kvasir_amd64".to_string());
+    }
+    names
+}
+
+/// Request a URL, returning the response body.
+fn get(url: &str) -> Result<Vec<u8>> {
+    let mut request = ureq::get(url).timeout(TIMEOUT);
+    // Authenticate with GitHub, where available, to avoid its rate limits in CI jobs
+    if url.starts_with("https://api.github.com/") {
+        request = request.set("Accept", "application/vnd.github+json");
+        if let Ok(token) = std::env::var("GITHUB_TOKEN") {
+            request = request.set("Authorization", &format!("Bearer {}", token));
+        }
+    }
+    let response = request
+        .call()
+        .map_err(|e| format!("Could not fetch {}: {}", url, e))?;
+    let mut body = vec![];
+    response
+        .into_reader()
+        .take(MAX_DOWNLOAD)
+        .read_to_end(&mut body)
+        .chain_err(|| format!("Could not read {}", url))?;
+    Ok(body)
+}
+
+/// Return the latest release from the releases API.
+pub fn latest(url: &str) -> Result<Release> {
+    serde_json::from_slice(&get(url)?).chain_err(|| format!("Invalid release from {}", url))
+}
+
+/// Return the hash of a file listed in `SHA256SUMS`, in the format written by `sha256sum`.
+fn checksum<'a>(checksums: &'a str, name: &str) -> Option<&'a str> {
+    checksums.lines().find_map(|line| {
+        let (hash, file) = line.split_once(char::is_whitespace)?;
+        (file.trim_start().trim_start_matches('*') == name).then_some(hash)
+    })
+}
+
+/// How the signature of a release is verified.
+#[derive(Debug, Clone, Default)]
+pub struct SignatureOptions {
+    /// The fingerprint of the key which must have signed the release, instead of the
+    /// embedded release key.
+    pub key: Option<String>,
+    /// Install a release whose signature is missing or cannot be verified, verifying only
+    /// its checksum.
+    pub insecure_skip: bool,
+}
+
+impl SignatureOptions {
+    /// Return the fingerprint of the key which must have signed the release, if known.
+    fn key(&self) -> Option<&str> {
+        self.key.as_deref().or(RELEASE_KEY)
+    }
+}
+
+/// Download and verify the build for the current platform in a release, returning its
+/// contents.
+pub fn download(release: &Release, signature: &SignatureOptions) -> Result<Vec<u8>> {
+    let names = asset_names();
+    let asset = release.asset(&names).ok_or_else(|| {
+        format!(
+            "Release {} has no build for this platform ({})",
+            release.tag_name,
+            names.join(" or ")
+        )
+    })?;
+    let checksums = release
+        .asset(&[CHECKSUMS_FILE.to_string()])
+        .ok_or_else(|| {
+            format!(
+                "Release {} has no {} file, so its build cannot be verified",
+                release.tag_name, CHECKSUMS_FILE
+            )
+        })?;
+    let checksums = String::from_utf8(get(&checksums.browser_download_url)?)
+        .map_err(|_| format!("Invalid {} in release {}", CHECKSUMS_FILE, release.tag_name))?;
+    let verified = match (
+        release.asset(&[SIGNATURE_FILE.to_string()]),
+        signature.key(),
+    ) {
+        (Some(asc), Some(key)) => {
+            verify_signature(&checksums, &get(&asc.browser_download_url)?, key)
+        }
+        (None, _) => Err(format!(
+            "Release {} has no {} signature",
+            release.tag_name, SIGNATURE_FILE
+        )
+        .into()),
+        (Some(_), None) => Err(
+            "This build of kvasir has no release key: give its fingerprint with --release-key"
+                .into(),
+        ),
+    };
+    if let Err(e) = verified {
+        let reason = e.iter().map(|e| e.to_string()).join(": ");
+        match signature.insecure_skip {
+            true => warn!(
+                "{}, so only the checksum of release {} is verified",
+                reason, release.tag_name
+            ),
+            false => bail!(format!(
+                "{}. Use --insecure-skip-signature to verify only the checksum of release {}",
+                reason, release.tag_name
+            )),
+        }
+    }
+
+    let expected = checksum(&checksums, &asset.name).ok_or_else(|| {
+        format!(
+            "The {} of release {} have no checksum for {}",
+            CHECKSUMS_FILE, release.tag_name, asset.name
+        )
+    })?;
+    let binary = get(&asset.browser_download_url)?;
+    let actual = sha256(&binary);
+    if !actual.eq_ignore_ascii_case(expected) {
+        bail!(format!(
+            "The checksum of {} is {}, but {} lists {}",
+            asset.name, actual, CHECKSUMS_FILE, expected
+        ))
+    }
+    Ok(binary)
+}
+
+/// Return whether `gpg` status output reports a valid signature by the key with the given
+/// fingerprint, or by one of its subkeys.
+fn signed_by(status: &str, key: &str) -> bool {
+    let key = key.replace(' ', "");
+    status.lines().any(|line| {
+        let mut fields = line.split_whitespace();
+        match (fields.next(), fields.next()) {
+            (Some("[GNUPG:]"), Some("VALIDSIG")) => {
+                let fields = fields.collect::<Vec<_>>();
+                // The fingerprint of the signing key comes first, and of its primary key last
+                [fields.first(), fields.last()]
+                    .iter()
+                    .flatten()
+                    .any(|fingerprint| fingerprint.eq_ignore_ascii_case(&key))
+            }
+            _ => false,
+        }
+    })
+}
+
+/// Verify the GPG signature of the checksums with the `gpg` command, which must have been
+/// made by the key with the given fingerprint.
+fn verify_signature(checksums: &str, signature: &[u8], key: &str) -> Result<()> {
+    // A private directory, so that other users cannot replace the files being verified
+    let dir = tempfile::tempdir().chain_err(|| "Could not create a temporary directory")?;
+    let (file, signature_file) = (
+        dir.path().join(CHECKSUMS_FILE),
+        dir.path().join(SIGNATURE_FILE),
+    );
+    fs::write(&file, checksums)
+        .and_then(|_| fs::write(&signature_file, signature))
+        .map_err(Error::from)
+        .and_then(|_| {
+            signing::gpg(&[
+                "--status-fd",
+                "1",
+                "--verify",
+                &signature_file.to_string_lossy(),
+                &file.to_string_lossy(),
+            ])
+        })
+        .and_then(|status| match signed_by(&status, key) {
+            true => Ok(()),
+            false => Err(format!(
+                "{} is not signed by the release key {}",
+                CHECKSUMS_FILE, key
+            )
+            .into()),
+        })
+        .chain_err(|| format!("Could not verify the signature of {}", CHECKSUMS_FILE))
+}
+
+/// Replace the binary at a path with a new binary, keeping its permissions.
+pub fn install(binary: &[u8], target: &Path) -> Result<()> {
+    let name = target
+        .file_name()
+        .ok_or_else(|| format!("Invalid path {}", target.display()))?;
+    let temp = target.with_file_name(format!(".{}.update", name.to_string_lossy()));
+    fs::write(&temp, binary)
+        .and_then(|_| fs::set_permissions(&temp, fs::metadata(target)?.permissions()))
+        .chain_err(|| {
+            format!(
+                "Could not write the new binary alongside {}",
+                target.display()
+            )
+        })?;
+    // A running executable cannot be replaced on Windows, but it can be renamed
+    if cfg!(windows) {
+        let old = target.with_extension("old");
+        let _ = fs::remove_file(&old);
+        fs::rename(target, old)?;
+    }
+    fs::rename(&temp, target).chain_err(|| format!("Could not replace {}", target.display()))
+}
+
+/// Check for a newer release and, unless only checking, install it over the binary at the
+/// target path.
+pub fn update(
+    url: &str,
+    target: &Path,
+    check: bool,
+    signature: &SignatureOptions,
+) -> Result<Outcome> {
+    let release = latest(url)?;
+    if !release.is_newer_than(VERSION) {
+        return Ok(Outcome::UpToDate(VERSION.to_string()));
+    }
+    if check {
+        return Ok(Outcome::Available(release.version().to_string()));
+    }
+    install(&download(&release, signature)?, target)?;
+    Ok(Outcome::Updated(release.version().to_string()))
+}
+
+/// Return the path of the running binary.
+pub fn current_exe() -> Result<PathBuf> {
+    let path = std::env::current_exe().chain_err(|| "Could not find the running binary")?;
+    Ok(fs::canonicalize(&path).unwrap_or(path))
+}
+
+/// Warn if a newer release is available, without failing if the check fails.
+pub fn warn_if_outdated(url: &str) {
+    match latest(url) {
+        Ok(release) if release.is_newer_than(VERSION) => warn!(
+            "kvasir {} is available, and this is {}: run `kvasir self-update` to update",
+            release.version(),
+            VERSION
+        ),
+        Ok(_) => debug!("kvasir {} is the latest release", VERSION),
+        Err(e) => warn!("Could not check for a newer release of kvasir: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::manifest::sha256;
+    use crate::testing::{Response, Server};
+    use crate::update::{
+        asset_names, checksum, download, install, numbers, signed_by, Asset, Release,
+        SignatureOptions,
+    };
+
+    /// Serve the given files on a local port, returning the base URL.
+    fn serve(files: Vec<(&'static str, Vec<u8>)>) -> String {
+        Server::new(move |request| {
+            let path = request.split_whitespace().nth(1).unwrap_or_default();
+            let body = files
+                .iter()
+                .find(|(name, _)| path == format!("/{}", name))
+                .map(|(_, body)| body.to_owned())
+                .unwrap_or_default();
+            Response::ok(body)
+        })
+        .url
+    }
+
+    fn release(url: &str, names: &[&str]) -> Release {
+        Release {
+            tag_name: "v9.0.0".to_string(),
+            assets: names
+                .iter()
+                .map(|name| Asset {
+                    name: name.to_string(),
+                    browser_download_url: format!("{}/{}", url, name),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn versions() {
+        let release = release("", &[]);
+        assert_eq!(release.version(), "9.0.0");
+        assert!(release.is_newer_than("0.3.6"));
+        assert!(!release.is_newer_than("9.0.0"));
+        assert!(!release.is_newer_than("10.0.0"));
+        assert_eq!(numbers("0.10.1-rc.1"), [0, 10, 1]);
+        assert!(numbers("0.10.0") > numbers("0.9.12"));
+
+        let sums = "abc123  kvasir_amd64\ndef456 *kvasir_darwin_arm64\n";
+        assert_eq!(checksum(sums, "kvasir_amd64"), Some("abc123"));
+        assert_eq!(checksum(sums, "kvasir_darwin_arm64"), Some("def456"));
+        assert_eq!(checksum(sums, "kvasir"), None);
+    }
+
+    #[test]
+    fn signatures() {
+        let status = "[GNUPG:] NEWSIG\n\
+            [GNUPG:] GOODSIG 0123456789ABCDEF kvasir releases\n\
+            [GNUPG:] VALIDSIG AAAA1111BBBB2222CCCC3333DDDD44440123456789ABCDEF 2026-01-01 1767225600 0 4 0 1 10 00 FFFF1111BBBB2222CCCC3333DDDD44440123456789ABCDEF\n";
+        assert!(signed_by(
+            status,
+            "FFFF 1111 BBBB 2222 CCCC 3333 DDDD 4444 0123 4567 89AB CDEF"
+        ));
+        assert!(signed_by(
+            status,
+            "aaaa1111bbbb2222cccc3333dddd44440123456789abcdef"
+        ));
+        // A good signature by any other key in the keyring is not enough
+        assert!(!signed_by(
+            status,
+            "EEEE1111BBBB2222CCCC3333DDDD44440123456789ABCDEF"
+        ));
+        assert!(!signed_by(
+            "[GNUPG:] GOODSIG 0123456789ABCDEF kvasir releases\n",
+            "0123456789ABCDEF"
+        ));
+    }
+
+    #[test]
+    fn update() {
+        let name: &'static str = asset_names().remove(0).leak();
+        let binary = b"new kvasir".to_vec();
+        let sums = format!("{}  {}\n", sha256(&binary), name).into_bytes();
+        let url = serve(vec![
+            (name, binary.to_owned()),
+            ("SHA256SUMS", sums.to_owned()),
+        ]);
+        let insecure = SignatureOptions {
+            insecure_skip: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            download(&release(&url, &[name, "SHA256SUMS"]), &insecure).unwrap(),
+            binary
+        );
+
+        // Builds are not installed without a matching checksum or a required signature
+        let url = serve(vec![
+            (name, b"tampered".to_vec()),
+            ("SHA256SUMS", sums.to_owned()),
+        ]);
+        let error = download(&release(&url, &[name, "SHA256SUMS"]), &insecure).unwrap_err();
+        assert!(
+            error.to_string().contains("but SHA256SUMS lists"),
+            "{}",
+            error
+        );
+        assert!(download(&release(&url, &[name]), &insecure)
+            .unwrap_err()
+            .to_string()
+            .contains("has no SHA256SUMS file"));
+        assert!(download(&release(&url, &["kvasir_plan9_mips"]), &insecure).is_err());
+        let url = serve(vec![("SHA256SUMS", sums)]);
+        let error = download(
+            &release(&url, &[name, "SHA256SUMS"]),
+            &SignatureOptions::default(),
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(
+            error.contains("has no SHA256SUMS.asc signature"),
+            "{}",
+            error
+        );
+        assert!(error.contains("--insecure-skip-signature"), "{}", error);
+
+        let dir = std::env::temp_dir().join(format!("kvasir-update-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("kvasir");
+        std::fs::write(&target, "old kvasir").unwrap();
+        install(&binary, &target).unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), binary);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
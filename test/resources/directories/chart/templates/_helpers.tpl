@@ -0,0 +1 @@
+{{- define "web.name" -}}web{{- end -}}
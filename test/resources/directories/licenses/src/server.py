@@ -0,0 +1,6 @@
+# Copyright 2021 Example Ltd
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+
+print("serving")
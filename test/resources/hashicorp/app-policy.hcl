@@ -0,0 +1,20 @@
+# Read the application's secrets
+path "secret/data/orders/*" {
+  capabilities = ["read", "list"]
+}
+
+# Issue database credentials
+# for the orders role
+path "database/creds/orders" {
+  capabilities = ["read"]
+}
+
+path "transit/encrypt/+" {
+  capabilities         = ["update"]
+  allowed_parameters   = { "plaintext" = [] }
+  max_wrapping_ttl     = "1h"
+}
+
+path "sys/*" {
+  capabilities = ["deny"]
+}
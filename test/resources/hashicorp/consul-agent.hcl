@@ -0,0 +1,35 @@
+datacenter       = "eu-west-2"
+node_name        = "consul-server-1"
+data_dir         = "/opt/consul"
+server           = true
+bootstrap_expect = 3
+retry_join       = ["provider=aws tag_key=consul tag_value=server"]
+encrypt          = "cg8StVXbQJ0gPvMd9o7yrg=="
+
+acl {
+  enabled        = true
+  default_policy = "deny"
+  tokens {
+    initial_management = "b1gs33cr3t"
+  }
+}
+
+service {
+  name = "orders"
+  port = 8080
+  tags = ["api", "v1"]
+  check {
+    http     = "http://localhost:8080/health"
+    interval = "10s"
+  }
+  connect {
+    sidecar_service {
+      proxy {
+        upstreams {
+          destination_name = "payments"
+          local_bind_port  = 9191
+        }
+      }
+    }
+  }
+}
@@ -0,0 +1,16 @@
+{
+  // A deployment of an image with a number of replicas
+  deployment(name, image, replicas=1):: {
+    apiVersion: 'apps/v1',
+    kind: 'Deployment',
+    metadata: { name: name, labels: { app: name } },
+    spec: {
+      replicas: replicas,
+      template: {
+        spec: { containers: [{ name: name, image: image }] },
+      },
+    },
+  },
+
+  port(number):: { containerPort: number },
+}
@@ -0,0 +1,63 @@
+terraform {
+  required_version = ">= 1.5"
+
+  required_providers {
+    aws = {
+      source  = "hashicorp/aws"
+      version = "~> 5.0"
+    }
+    random = "~> 3.6"
+  }
+
+  backend "s3" {
+    bucket = "example-terraform-state"
+    key    = "site/terraform.tfstate"
+  }
+}
+
+provider "aws" {
+  region = var.region
+}
+
+provider "aws" {
+  alias  = "us"
+  region = "us-east-1"
+}
+
+locals {
+  name = "${var.prefix}-site"
+  tags = merge(var.tags, { Service = "site" })
+}
+
+resource "aws_s3_bucket" "site" {
+  bucket = local.name
+  tags   = local.tags
+
+  versioning {
+    enabled = true
+  }
+
+  lifecycle {
+    prevent_destroy = true
+  }
+}
+
+resource "aws_acm_certificate" "site" {
+  provider          = aws.us
+  count             = var.certificate ? 1 : 0
+  domain_name       = var.domain
+  validation_method = "DNS"
+  depends_on        = [aws_s3_bucket.site]
+}
+
+data "aws_route53_zone" "main" {
+  name = var.domain
+}
+
+module "dns" {
+  source  = "terraform-aws-modules/route53/aws//modules/records"
+  version = "2.10.2"
+
+  zone_id = data.aws_route53_zone.main.zone_id
+  records = [{ name = "www", type = "CNAME", records = [aws_s3_bucket.site.bucket_regional_domain_name] }]
+}
@@ -0,0 +1,40 @@
+variable "region" {
+  type        = string
+  default     = "eu-west-2"
+  description = "The AWS region to deploy the site to"
+}
+
+variable "prefix" {
+  type        = string
+  description = "Prefix of resource names"
+
+  validation {
+    condition     = length(var.prefix) <= 16
+    error_message = "The prefix must be at most 16 characters."
+  }
+}
+
+variable "domain" {
+  type = string
+}
+
+variable "certificate" {
+  type    = bool
+  default = true
+}
+
+variable "tags" {
+  type     = map(string)
+  default  = {}
+  nullable = false
+}
+
+output "bucket_arn" {
+  value       = aws_s3_bucket.site.arn
+  description = "The ARN of the site bucket"
+}
+
+output "api_key" {
+  value     = random_password.key.result
+  sensitive = true
+}
@@ -0,0 +1,29 @@
+include "env" {
+  path           = find_in_parent_folders("env.hcl")
+  merge_strategy = "deep"
+  expose         = true
+}
+
+locals {
+  name     = "orders"
+  replicas = 3
+}
+
+terraform {
+  source = "git::git@github.com:acme/modules.git//service?ref=v1.2.0"
+}
+
+dependency "vpc" {
+  config_path = "../vpc"
+}
+
+dependencies {
+  paths = ["../database"]
+}
+
+inputs = {
+  name     = "${local.name}-api"
+  replicas = local.replicas
+  vpc_id   = dependency.vpc.outputs.vpc_id
+  tags     = { service = "orders" }
+}
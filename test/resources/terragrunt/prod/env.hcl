@@ -0,0 +1,12 @@
+include "root" {
+  path = find_in_parent_folders("root.hcl")
+}
+
+locals {
+  account = "123456789012"
+}
+
+inputs = {
+  environment = "prod"
+  tags        = { cost_centre = "platform" }
+}
@@ -0,0 +1,13 @@
+remote_state {
+  backend = "s3"
+  config = {
+    bucket = "acme-tf-state"
+    key    = "${path_relative_to_include()}/terraform.tfstate"
+    region = "eu-west-2"
+  }
+}
+
+inputs = {
+  company     = "acme"
+  environment = "default"
+}
@@ -0,0 +1,3 @@
+# {% block title %}Configuration{% endblock title %}
+
+{% block content %}{% endblock content %}
@@ -0,0 +1 @@
+{% macro heading(text) %}## {{ text }}{% endmacro heading %}
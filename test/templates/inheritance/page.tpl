@@ -0,0 +1,9 @@
+{% extends "layout.tpl" %}
+{% import "macros.tpl" as macros %}
+{% block content %}
+{{ macros::heading(text="Files") }}
+
+| Path | Parser |
+| ---- | ------ |
+{% for file in files %}{% include "row.tpl" %}{% endfor %}
+{% endblock content %}
@@ -0,0 +1 @@
+| {{ file.path }} | {{ file.parser }} |